@@ -0,0 +1,390 @@
+use std::{fmt::Write, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+use clap::Subcommand;
+use overseer::{access::{WatcherActivity, WatcherBehaviour}, models::{DiffLine, Key, KeyFilter, Promotion, PromotionReport, Revision, Value}};
+use overseer_client::Client;
+
+use crate::error::CliError;
+
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Prints the value of a key.
+    Get { key: String },
+    /// Prints the value a key held at a revision, see [Command::Diff].
+    GetAt {
+        key: String,
+        #[arg(value_parser = parse_revision)]
+        at: Revision
+    },
+    /// Sets a key, values that parse as integers are stored as integers
+    /// unless they are quoted.
+    Set { key: String, value: String },
+    /// Deletes a key.
+    Delete { key: String },
+    /// Prints the value of a key, the durability class of its writes and
+    /// when and by whom it was written.
+    Meta { key: String },
+    /// Prints every change to a key until interrupted.
+    Watch { key: String },
+    /// Lists the keys under a prefix.
+    Scan { prefix: String },
+    /// Lists the keys matching a glob such as `app.*.port`, or a regular
+    /// expression with `--regex`. The server does the matching.
+    Find {
+        pattern: String,
+        /// Reads the pattern as a regular expression rather than a glob.
+        #[arg(long)]
+        regex: bool
+    },
+    /// Lists the keys whose map value holds the value at an indexed field path.
+    Query { field: String, value: String },
+    /// Shows what a key held at two revisions and what changed in between,
+    /// a revision is a store version or `@` and a unix time in milliseconds.
+    Diff {
+        key: String,
+        #[arg(value_parser = parse_revision)]
+        from: Revision,
+        #[arg(value_parser = parse_revision)]
+        to: Revision
+    },
+    /// Lists the recent changes to a key the server kept, oldest first.
+    History {
+        key: String,
+        /// Only lists the last changes.
+        #[arg(long)]
+        limit: Option<u32>
+    },
+    /// Promotes every key of a namespace, or under a prefix of it, into
+    /// another in a single step. Key spaces are written `namespace:prefix`.
+    Promote {
+        source: String,
+        target: String,
+        /// Deletes the target keys the source does not have.
+        #[arg(long)]
+        prune: bool,
+        /// Only prints what would change.
+        #[arg(long)]
+        dry_run: bool
+    },
+    /// Rolls back the latest promotion.
+    Rollback { id: u64 },
+    /// Prints how many keys the server holds and how much room they take,
+    /// the manifest drift and the recent compaction runs.
+    Stats,
+    /// Writes keys to a file in the seed format, so the file can seed
+    /// a fresh server.
+    Backup { file: PathBuf, keys: Vec<String> },
+    /// Writes every key neither read nor written since a unix time in
+    /// milliseconds to a file in the seed format.
+    Archive {
+        file: PathBuf,
+        since: u64,
+        /// Deletes the keys once the file is written.
+        #[arg(long)]
+        delete: bool
+    }
+}
+
+/// Parses a value the way seed files do.
+pub fn parse_value(value: &str) -> Value {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        Value::String(value[1..value.len() - 1].into())
+    } else if let Ok(integer) = value.parse() {
+        Value::Integer(integer)
+    } else {
+        Value::String(value.into())
+    }
+}
+
+/// Formats a value so [parse_value] reads it back, maps are written
+/// as `{field = value, ..}` which is only read back as a string.
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::Integer(integer) => integer.to_string(),
+        Value::String(string) => format!("\"{string}\""),
+        Value::Map(fields) => {
+            let fields: Vec<String> = fields.iter().map(|(name, value)| format!("{name} = {}", format_value(value))).collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        Value::Json(json) => json.clone()
+    }
+}
+
+/// Parses a store version, or a unix time in milliseconds after an `@`.
+pub fn parse_revision(revision: &str) -> Result<Revision, String> {
+    let parsed = match revision.strip_prefix('@') {
+        Some(at) => at.parse().map(Revision::Timestamp),
+        None => revision.parse().map(Revision::Version)
+    };
+    parsed.map_err(|_| format!("invalid revision {revision:?}, expected a version or @ and a unix time in milliseconds"))
+}
+
+/// Splits a key space into its namespace and prefix, a bare namespace
+/// stands for every key in it.
+pub fn parse_key_space(space: &str) -> (String, String) {
+    match space.split_once(':') {
+        Some((namespace, prefix)) => (namespace.to_string(), prefix.to_string()),
+        None => (space.to_string(), String::new())
+    }
+}
+
+fn print_report(report: &PromotionReport) {
+    for (sign, keys) in [("+", &report.added), ("~", &report.changed), ("-", &report.removed)] {
+        for key in keys {
+            println!("{sign} {}", key.as_str());
+        }
+    }
+    println!("{} added, {} changed, {} removed", report.added.len(), report.changed.len(), report.removed.len());
+}
+
+/// How long ago something happened, to the largest whole unit.
+pub fn format_age(elapsed: u64) -> String {
+    let seconds = elapsed / 1000;
+    match seconds {
+        0..=59 => format!("{seconds} seconds ago"),
+        60..=3599 => format!("{} minutes ago", seconds / 60),
+        3600..=86399 => format!("{} hours ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400)
+    }
+}
+
+fn print_value(value: Option<&Value>) {
+    match value {
+        Some(value) => println!("{}", format_value(value)),
+        None => println!("(nil)")
+    }
+}
+
+pub async fn run(client: &Client, command: Command) -> Result<(), CliError> {
+    match command {
+        Command::Get { key } => {
+            print_value(client.get(&Key::from_owned(key)).await?.as_ref());
+        }
+        Command::GetAt { key, at } => {
+            print_value(client.get_at(&Key::from_owned(key), at).await?.as_ref());
+        }
+        Command::Set { key, value } => {
+            client.insert(&Key::from_owned(key), parse_value(&value)).await?;
+            println!("OK");
+        }
+        Command::Delete { key } => {
+            client.delete(&Key::from_owned(key)).await?;
+            println!("OK");
+        }
+        Command::Meta { key } => {
+            let (value, meta) = client.get_with_meta(&Key::from_owned(key)).await?;
+            print_value(value.as_ref());
+            println!("exists: {}", meta.exists);
+            println!("durability: {}", meta.durability.as_str());
+            if let Some(record) = meta.record {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|f| f.as_millis() as u64).unwrap_or_default();
+                let writer = match record.writer {
+                    Some(id) => format!("client {id}"),
+                    None => "the server".to_string()
+                };
+                println!("created: {}", format_age(now.saturating_sub(record.created_at)));
+                println!("updated: {} by {writer}", format_age(now.saturating_sub(record.modified_at)));
+                println!("used: {}", format_age(now.saturating_sub(record.accessed_at)));
+            }
+        }
+        Command::Watch { key } => {
+            let live = client.subscribe(&Key::from_owned(key), WatcherActivity::Kickback, WatcherBehaviour::Ordered).await?;
+            loop {
+                print_value(live.wait_on_update().await.as_ref());
+            }
+        }
+        Command::Scan { prefix } => {
+            let mut keys = client.keys();
+            while let Some(key) = keys.next().await? {
+                if key.as_str().starts_with(&prefix) {
+                    println!("{}", key.as_str());
+                } else if key.as_str() > prefix.as_str() {
+                    // Keys are listed in order, so none further along match.
+                    break;
+                }
+            }
+        }
+        Command::Find { pattern, regex } => {
+            let filter = match regex {
+                true => KeyFilter::regex(pattern),
+                false => KeyFilter::glob(pattern)
+            };
+            let mut keys = client.find_keys(filter);
+            while let Some(key) = keys.next().await? {
+                println!("{}", key.as_str());
+            }
+        }
+        Command::Query { field, value } => {
+            match client.query_by_index(&field, &parse_value(&value)).await? {
+                Some(keys) => {
+                    for key in keys {
+                        println!("{}", key.as_str());
+                    }
+                }
+                None => println!("No index on {field}")
+            }
+        }
+        Command::Diff { key, from, to } => {
+            let diff = client.diff(&Key::from_owned(key), from, to).await?;
+            print!("from: ");
+            print_value(diff.from.as_ref());
+            print!("to: ");
+            print_value(diff.to.as_ref());
+            for line in &diff.lines {
+                match line {
+                    DiffLine::Kept(line) => println!("  {line}"),
+                    DiffLine::Removed(line) => println!("- {line}"),
+                    DiffLine::Added(line) => println!("+ {line}")
+                }
+            }
+        }
+        Command::History { key, limit } => {
+            let key = Key::from_owned(key);
+            let entries = match limit {
+                Some(limit) => client.recent_history(&key, limit).await?,
+                None => client.history(&key).await?
+            };
+            for entry in entries {
+                print!("version {} at {}ms: ", entry.version, entry.at);
+                print_value(entry.value.as_ref());
+            }
+        }
+        Command::Promote { source, target, prune, dry_run } => {
+            let (source, source_prefix) = parse_key_space(&source);
+            let (target, target_prefix) = parse_key_space(&target);
+            let promotion = Promotion { source, source_prefix, target, target_prefix, prune };
+            let report = client.promote(promotion, dry_run).await?;
+            print_report(&report);
+            match report.id {
+                Some(id) => println!("Promotion {id}, undo it with `rollback {id}`"),
+                None if dry_run => println!("Dry run, nothing was changed"),
+                None => println!("The target already matches")
+            }
+        }
+        Command::Rollback { id } => {
+            print_report(&client.rollback_promotion(id).await?);
+        }
+        Command::Stats => {
+            let stats = client.db_stats().await?;
+            println!("keys: {}", stats.keys);
+            for (prefix, count) in stats.prefixes {
+                println!("  {prefix}: {count}");
+            }
+            println!("value bytes: {}", stats.value_bytes);
+            println!("storage bytes: {}", stats.storage_bytes);
+            let drift = client.check_manifest().await?;
+            println!("manifest drift: {}", drift.len());
+            for entry in drift {
+                let found = entry.found.map(|f| f.type_name()).unwrap_or("nothing");
+                println!("  {}: expected {}, found {}", entry.key.as_str(), entry.expected.type_name(), found);
+            }
+            let runs = client.compaction_history().await?;
+            println!("compaction runs: {}", runs.len());
+            for run in runs {
+                println!("  at {}ms took {:?}, reclaimed {} bytes", run.finished_at, run.duration, run.bytes_reclaimed);
+            }
+        }
+        Command::Backup { file, keys } => {
+            let mut out = String::new();
+            for key in &keys {
+                if let Some(value) = client.get(&Key::from_str(key)).await? {
+                    let _ = writeln!(out, "{key} = {}", format_value(&value));
+                }
+            }
+            tokio::fs::write(&file, out).await?;
+            println!("Backed up {} keys to {}", keys.len(), file.display());
+        }
+        Command::Archive { file, since, delete } => {
+            // Nothing is deleted until the file holds the keys. A key that is
+            // unused now stays unused, so the delete only finds keys the file has.
+            let records = client.archive(since, false).await?;
+            let mut out = String::new();
+            for (key, value) in &records {
+                let _ = writeln!(out, "{} = {}", key.as_str(), format_value(value));
+            }
+            tokio::fs::write(&file, out).await?;
+            println!("Archived {} keys to {}", records.len(), file.display());
+            if delete {
+                println!("Deleted {} keys", client.archive(since, true).await?.len());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Splits a REPL line into words, double quotes group words together
+/// and are kept so values stay strings.
+pub fn split_line(line: &str) -> Result<Vec<String>, CliError> {
+    let mut words = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c)
+        }
+    }
+    if quoted {
+        return Err(CliError::UnbalancedQuotes);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    Ok(words)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use overseer::models::{Revision, Value};
+
+    use super::{format_age, format_value, parse_key_space, parse_revision, parse_value, split_line};
+
+    #[test]
+    pub fn test_parse_value() {
+        assert_eq!(parse_value("12"), Value::Integer(12));
+        assert_eq!(parse_value("\"12\""), Value::String("12".into()));
+        assert_eq!(parse_value("hello"), Value::String("hello".into()));
+        assert_eq!(parse_value(&format_value(&Value::String("7".into()))), Value::String("7".into()));
+        let map = Value::Map(BTreeMap::from([("port".to_string(), Value::Integer(80)), ("region".to_string(), Value::String("eu".into()))]));
+        assert_eq!(format_value(&map), "{port = 80, region = \"eu\"}");
+    }
+
+    #[test]
+    pub fn test_parse_revision() {
+        assert_eq!(parse_revision("12"), Ok(Revision::Version(12)));
+        assert_eq!(parse_revision("@1700000000000"), Ok(Revision::Timestamp(1_700_000_000_000)));
+        assert!(parse_revision("yesterday").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_key_space() {
+        assert_eq!(parse_key_space("staging:app."), ("staging".to_string(), "app.".to_string()));
+        assert_eq!(parse_key_space("staging"), ("staging".to_string(), String::new()));
+    }
+
+    #[test]
+    pub fn test_format_age() {
+        assert_eq!(format_age(12_500), "12 seconds ago");
+        assert_eq!(format_age(5 * 60_000 + 30_000), "5 minutes ago");
+        assert_eq!(format_age(3 * 3_600_000), "3 hours ago");
+        assert_eq!(format_age(2 * 86_400_000), "2 days ago");
+    }
+
+    #[test]
+    pub fn test_split_line() {
+        assert_eq!(split_line("set  greeting \"hello world\"").unwrap(), vec!["set", "greeting", "\"hello world\""]);
+        assert!(split_line("set key \"open").is_err());
+    }
+}
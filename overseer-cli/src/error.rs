@@ -0,0 +1,15 @@
+use overseer::error::NetworkError;
+use thiserror::Error;
+
+
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error("{0}")]
+    Network(#[from] NetworkError),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Readline(#[from] rustyline::error::ReadlineError),
+    #[error("Unbalanced quotes in the command")]
+    UnbalancedQuotes
+}
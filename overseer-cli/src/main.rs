@@ -0,0 +1,93 @@
+mod command;
+mod error;
+
+use clap::{Parser, Subcommand};
+use overseer_client::{Client, ClientConfig};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use crate::{command::{run, split_line, Command}, error::CliError};
+
+
+/// Administers an overseer server.
+#[derive(Parser, Debug)]
+#[command(name = "overseer-cli")]
+struct Cli {
+    /// The address of the server, further addresses separated by commas
+    /// are failed over to when the first one is down.
+    #[arg(short, long, default_value = "127.0.0.1:7878", value_delimiter = ',')]
+    address: Vec<String>,
+    /// The token to authenticate with.
+    #[arg(short, long)]
+    token: Option<String>,
+    /// Runs a single command, otherwise a REPL is started.
+    #[command(subcommand)]
+    command: Option<Command>
+}
+
+/// A command typed into the REPL.
+#[derive(Parser, Debug)]
+#[command(multicall = true)]
+struct ReplLine {
+    #[command(subcommand)]
+    command: ReplCommand
+}
+
+#[derive(Subcommand, Debug)]
+enum ReplCommand {
+    #[command(flatten)]
+    Command(Command),
+    /// Leaves the REPL.
+    Exit
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = start(cli).await {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+async fn start(cli: Cli) -> Result<(), CliError> {
+    let mut config = ClientConfig::default();
+    if let Some(token) = cli.token {
+        config = config.with_token(token);
+    }
+    let client = Client::with_endpoints(cli.address.iter().map(String::as_str), config).await?;
+    match cli.command {
+        Some(command) => run(&client, command).await,
+        None => repl(&client).await
+    }
+}
+
+async fn repl(client: &Client) -> Result<(), CliError> {
+    let mut editor = DefaultEditor::new()?;
+    loop {
+        let line = match editor.readline("overseer> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(()),
+            Err(e) => return Err(e.into())
+        };
+        let words = match split_line(&line) {
+            Ok(words) if words.is_empty() => continue,
+            Ok(words) => words,
+            Err(e) => {
+                eprintln!("error: {e}");
+                continue;
+            }
+        };
+        let _ = editor.add_history_entry(line.as_str());
+        match ReplLine::try_parse_from(words) {
+            Ok(ReplLine { command: ReplCommand::Exit }) => return Ok(()),
+            Ok(ReplLine { command: ReplCommand::Command(command) }) => {
+                if let Err(e) = run(client, command).await {
+                    eprintln!("error: {e}");
+                }
+            }
+            Err(e) => {
+                let _ = e.print();
+            }
+        }
+    }
+}
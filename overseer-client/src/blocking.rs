@@ -0,0 +1,145 @@
+//! A client for code that is not async, every call blocks until the server
+//! answers. It owns a runtime of its own, so it cannot be used from inside
+//! another one.
+
+use std::{future::Future, net::ToSocketAddrs, sync::Arc, time::Duration};
+
+use overseer::{access::{WatcherActivity, WatcherBehaviour}, error::NetworkError, models::{Key, Value}};
+use tokio::runtime::Runtime;
+
+use crate::{Client, ClientConfig, LiveValue};
+
+
+/// Mirrors the common requests of a [Client] with blocking calls.
+///
+/// # Panics
+/// Every call panics when it is made from inside an async runtime.
+pub struct BlockingClient {
+    runtime: Arc<Runtime>,
+    client: Client
+}
+
+impl BlockingClient {
+    pub fn new<A>(address: A) -> Result<Self, NetworkError>
+    where 
+        A: ToSocketAddrs
+    {
+        Self::with_config(address, ClientConfig::default())
+    }
+    /// Creates a client with a specific configuration.
+    pub fn with_config<A>(address: A, config: ClientConfig) -> Result<Self, NetworkError>
+    where 
+        A: ToSocketAddrs
+    {
+        // A worker keeps driving the connection between the calls, so the
+        // live values stay current while nothing blocks on the client.
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build()?;
+        let client = runtime.block_on(Client::with_config(address, config))?;
+        Ok(Self { runtime: Arc::new(runtime), client })
+    }
+    /// The async client underneath, for the requests not mirrored here,
+    /// see [BlockingClient::block_on].
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+    /// Runs a future on the runtime of the client until it completes.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+    pub fn get(&self, key: &Key) -> Result<Option<Value>, NetworkError> {
+        self.block_on(self.client.get(key))
+    }
+    /// Inserts a value, returning the value the key held before.
+    pub fn insert(&self, key: &Key, value: Value) -> Result<Option<Value>, NetworkError> {
+        self.block_on(self.client.insert(key, value))
+    }
+    pub fn delete(&self, key: &Key) -> Result<(), NetworkError> {
+        self.block_on(self.client.delete(key))
+    }
+    /// Subscribes to a key, see [Client::subscribe].
+    pub fn subscribe(&self, key: &Key, activity: WatcherActivity, behaviour: WatcherBehaviour) -> Result<BlockingLiveValue, NetworkError> {
+        let value = self.block_on(self.client.subscribe(key, activity, behaviour))?;
+        Ok(BlockingLiveValue { runtime: Arc::clone(&self.runtime), value })
+    }
+}
+
+/// A [LiveValue] read with blocking calls, it stays current as long as
+/// the [BlockingClient] it came from is open.
+pub struct BlockingLiveValue {
+    runtime: Arc<Runtime>,
+    value: LiveValue
+}
+
+impl BlockingLiveValue {
+    pub fn get(&self) -> Option<Value> {
+        self.runtime.block_on(self.value.get())
+    }
+    /// Blocks until the value changes and returns it.
+    pub fn wait_on_update(&self) -> Option<Value> {
+        self.runtime.block_on(self.value.wait_on_update())
+    }
+    /// Blocks until the value changes or the timeout passes, [None] if it
+    /// did not change.
+    pub fn wait_on_update_timeout(&self, timeout: Duration) -> Option<Option<Value>> {
+        self.runtime.block_on(async { tokio::time::timeout(timeout, self.value.wait_on_update()).await.ok() })
+    }
+    /// How many changes the server could not queue, see [LiveValue::missed].
+    pub fn missed(&self) -> u64 {
+        self.value.missed()
+    }
+    /// Whether the watch was killed, see [LiveValue::is_closed].
+    pub fn is_closed(&self) -> bool {
+        self.value.is_closed()
+    }
+    /// The async live value underneath.
+    pub fn live_value(&self) -> &LiveValue {
+        &self.value
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use overseer::{access::{WatcherActivity, WatcherBehaviour}, models::{Key, Value}, network::{OverseerSerde, Packet, PacketId, PacketPayload}};
+    use tokio::net::TcpListener;
+
+    use super::BlockingClient;
+
+    #[test]
+    pub fn test_blocking_client() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || runtime.block_on(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Watch { key, .. } = packet.payload() else {
+                panic!("Expected a watch packet.");
+            };
+            let key = (**key).clone();
+            Packet::get(packet.id(), &key).serialize(&mut socket).await.unwrap();
+
+            // The insert changes the watched key before it is answered.
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Insert { value, .. } = packet.payload() else {
+                panic!("Expected an insert packet.");
+            };
+            Packet::new(PacketId::zero(), PacketPayload::notify(&key, Some(value), false)).serialize(&mut socket).await.unwrap();
+            Packet::vreturn(packet.id(), &key, None, 1).serialize(&mut socket).await.unwrap();
+        }));
+
+        let client = BlockingClient::new(address).unwrap();
+        let key = Key::from_str("app.mode");
+        let live = client.subscribe(&key, WatcherActivity::Lazy, WatcherBehaviour::Ordered).unwrap();
+        assert_eq!(live.wait_on_update_timeout(Duration::from_millis(20)), None);
+
+        let waiter = std::thread::spawn(move || live.wait_on_update());
+        // Gives the waiter the time to start waiting.
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(client.insert(&key, Value::Integer(2)).unwrap(), None);
+        assert_eq!(waiter.join().unwrap(), Some(Value::Integer(2)));
+        server.join().unwrap();
+    }
+}
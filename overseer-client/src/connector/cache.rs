@@ -0,0 +1,105 @@
+use dashmap::DashMap;
+use overseer::{access::WatcherBehaviour, error::NetworkError, models::{Key, Value}};
+
+use super::{Client, Subscription};
+
+
+/// Reads of a namespace served from a local copy, made with [Client::cached].
+///
+/// The first read of a key fetches it and watches it, the server then
+/// keeps the copy current and later reads do not leave the process. A
+/// watch the server gave up on is made again on the next read. Keys are
+/// watched until they are evicted or the cache is dropped.
+pub struct CachedClient<'a> {
+    client: &'a Client,
+    namespace: String,
+    entries: DashMap<Key, Subscription>
+}
+
+impl<'a> CachedClient<'a> {
+    pub(crate) fn new(client: &'a Client, namespace: String) -> Self {
+        Self { client, namespace, entries: DashMap::new() }
+    }
+    /// Gets a value from the cache, fetching and watching the key if it is not cached.
+    pub async fn get(&self, key: &Key) -> Result<Option<Value>, NetworkError> {
+        let cached = self.entries.get(key).map(|f| f.value().value().clone());
+        match cached {
+            Some(live) if !live.is_closed() => return Ok(live.get().await),
+            Some(..) => {
+                self.entries.remove(key);
+            }
+            None => {}
+        }
+        let (subscription, _) = self.client.namespace(self.namespace.as_str()).watch_snapshot(key, WatcherBehaviour::Ordered).await?;
+        let value = subscription.value().get().await;
+        self.entries.insert(key.clone(), subscription);
+        Ok(value)
+    }
+    /// Drops a key from the cache and releases its watch, returning whether it was cached.
+    pub fn evict(&self, key: &Key) -> bool {
+        self.entries.remove(key).is_some()
+    }
+    /// Drops every key from the cache.
+    pub fn clear(&self) {
+        self.entries.clear();
+    }
+    /// How many keys are cached.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::{models::{Key, Value}, network::{OverseerSerde, Packet, PacketId, PacketPayload}};
+    use tokio::{net::TcpListener, sync::mpsc};
+
+    use crate::Client;
+
+    #[tokio::test]
+    pub async fn test_cached_reads() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (changed, mut changes) = mpsc::unbounded_channel::<Value>();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::WatchSnapshot { key, .. } = packet.payload() else {
+                panic!("Expected a snapshot watch.");
+            };
+            let key = (**key).clone();
+            Packet::snapshot(packet.id(), &key, Some(&Value::Integer(1)), 1).serialize(&mut socket).await.unwrap();
+
+            // Changes reach the cache through the watch.
+            let value = changes.recv().await.unwrap();
+            Packet::notify(PacketId::zero(), &key, Some(&value), false).serialize(&mut socket).await.unwrap();
+
+            // The only request left is the release of the evicted key.
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            assert!(matches!(packet.payload(), PacketPayload::Release { .. }));
+            Packet::get(packet.id(), &key).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let reads = async {
+            let cache = client.cached();
+            let key = Key::from_str("app.mode");
+            assert_eq!(cache.get(&key).await.unwrap(), Some(Value::Integer(1)));
+            assert_eq!(cache.get(&key).await.unwrap(), Some(Value::Integer(1)));
+            changed.send(Value::Integer(2)).unwrap();
+            while cache.get(&key).await.unwrap() != Some(Value::Integer(2)) {
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(cache.len(), 1);
+            assert!(cache.evict(&key));
+            assert!(cache.is_empty());
+        };
+        let _ = tokio::join!(server, reads);
+    }
+}
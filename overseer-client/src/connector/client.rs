@@ -1,10 +1,16 @@
-use std::{borrow::Borrow, net::{SocketAddr, ToSocketAddrs}, sync::{atomic::{AtomicBool, AtomicU32, Ordering}, Arc}};
+use std::{borrow::{Borrow, Cow}, future::{pending, Future}, io::ErrorKind, net::{SocketAddr, ToSocketAddrs}, path::{Path, PathBuf}, pin::pin, sync::{atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering}, Arc}, task::{Context, Poll, Waker}, time::Duration};
 
-use dashmap::DashMap;
-use overseer::{access::{WatcherActivity, WatcherBehaviour}, error::NetworkError, models::{Key, Value}, network::{OverseerSerde, Packet, PacketId, PacketPayload}};
-use tokio::{net::{tcp::{OwnedReadHalf, OwnedWriteHalf}, TcpStream}, sync::{oneshot::Sender, Mutex, Notify}};
+use dashmap::{mapref::entry::Entry, DashMap};
+use serde::{de::DeserializeOwned, Serialize};
+use overseer::{access::{WatcherActivity, WatcherBehaviour}, error::{ClientError, NetworkError, ProtocolError}, models::{parse_seed_line, Acknowledgement, CompactionRun, DatabaseStats, DeleteCondition, DumpReader, DumpWriter, ExportedRecord, HistoryEntry, Key, KeyChild, KeyDiff, KeyDrift, KeyFilter, KeyMeta, LeasedItem, Promotion, PromotionReport, Revision, Schema, Value, WatchedKey}, network::{queue_topic, OverseerSerde, OvrInteger, Packet, PacketId, PacketPayload, DEFAULT_MAX_PACKET_SIZE, DEFAULT_NAMESPACE, FIRST_REQUEST_ID, FRAMED_VERSION, LAST_REQUEST_ID, VARINT_FRAME_VERSION}};
+use tokio::{io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite}, net::TcpStream, sync::{broadcast, mpsc, oneshot::{self, Sender}, Mutex, Notify}, time::Instant};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::{offline::{OfflineQueue, QueuedWrite}, trace::current_trace, CachedClient, ClientConfig, Interceptor, ReplayConflict, HEALTH_CHECK_TIMEOUT};
 
-use tokio::io::AsyncWriteExt;
 #[derive(Clone)]
 pub struct LiveValue {
     value: Arc<LiveValueInternal>,
@@ -20,197 +26,3471 @@ impl LiveValue {
         self.value.notify.notified().await;
         self.get().await
     }
+    /// How many changes the server could not queue for this watch.
+    ///
+    /// The value is fetched again after an overflow, so it is current but
+    /// the changes in between were never seen.
+    pub fn missed(&self) -> u64 {
+        self.value.missed.load(Ordering::Acquire)
+    }
+    /// Whether the server killed the watch after an overflow, the value
+    /// will not change anymore.
+    pub fn is_closed(&self) -> bool {
+        self.value.closed.load(Ordering::Acquire)
+    }
+    /// The trace of the request whose write brought the value, [None] if
+    /// it was made outside of one or the value was fetched, see [super::traced].
+    pub fn trace(&self) -> Option<u64> {
+        Some(self.value.trace.load(Ordering::Acquire)).filter(|f| *f != 0)
+    }
+    /// When the server last showed the watch is alive, with a change or a
+    /// heartbeat, [None] if it did not yet.
+    ///
+    /// Servers that send heartbeats do so whenever a watch was quiet for
+    /// their interval, so a watch not seen for much longer is likely dead.
+    pub fn last_seen(&self) -> Option<Instant> {
+        *self.value.last_seen.lock().unwrap()
+    }
+}
+
+/// A live value decoded into a serde type, made with [Client::subscribe_typed].
+///
+/// The value a notification brings is decoded when it is read, one that
+/// does not decode fails with [NetworkError::ValueConversion] and leaves
+/// the last value that did in place.
+pub struct LiveTyped<T> {
+    value: LiveValue,
+    last: Option<T>
+}
+
+impl<T: DeserializeOwned> LiveTyped<T> {
+    /// Decodes the current value.
+    pub async fn get(&mut self) -> Result<Option<&T>, NetworkError> {
+        let value = self.value.get().await;
+        self.decode(value)
+    }
+    /// Waits for the next notification and decodes the value it brought.
+    pub async fn wait_on_update(&mut self) -> Result<Option<&T>, NetworkError> {
+        let value = self.value.wait_on_update().await;
+        self.decode(value)
+    }
+    /// The last value that decoded, [None] if none did yet or the key was deleted.
+    pub fn last(&self) -> Option<&T> {
+        self.last.as_ref()
+    }
+    /// The undecoded value.
+    pub fn live_value(&self) -> &LiveValue {
+        &self.value
+    }
+    fn decode(&mut self, value: Option<Value>) -> Result<Option<&T>, NetworkError> {
+        self.last = value.map(|f| f.to_serde()).transpose()?;
+        Ok(self.last.as_ref())
+    }
 }
 
 struct LiveValueInternal {
     value: Mutex<Option<Value>>,
-    notify: Notify
+    notify: Notify,
+    missed: AtomicU64,
+    closed: AtomicBool,
+    /// The trace the last notification carried, zero for none.
+    trace: AtomicU64,
+    /// When the server last sent something for the watch.
+    last_seen: std::sync::Mutex<Option<Instant>>,
+    /// How the watch was made, for subscribing again after a failover.
+    behaviour: WatcherBehaviour,
+    min_interval: Option<Duration>
+}
+
+/// A watch of a key that is released once it is no longer needed, made
+/// with [Client::watch].
+///
+/// Dropping it releases the watch in the background, [Subscription::unsubscribe]
+/// waits for the server to take it down. Watches of the same key share one
+/// watch on the server, so releasing one of them ends the others too.
+pub struct Subscription {
+    inner: Arc<Inner>,
+    namespace: String,
+    key: Key,
+    value: LiveValue,
+    released: bool
+}
+
+impl Subscription {
+    /// The value of the watched key.
+    pub fn value(&self) -> &LiveValue {
+        &self.value
+    }
+    /// When the server last showed the watch is alive, see [LiveValue::last_seen].
+    pub fn last_seen(&self) -> Option<Instant> {
+        self.value.last_seen()
+    }
+    /// Releases the watch, resolving once the server acknowledged it.
+    pub async fn unsubscribe(mut self) -> Result<(), NetworkError> {
+        self.released = true;
+        self.close();
+        let response = self.inner.release(&self.namespace, &self.key).await?;
+        match response.await.map_err(|_| NetworkError::Client(ClientError::ConnectionClosed))?.payload() {
+            PacketPayload::Get { .. } => Ok(()),
+            PacketPayload::Error { code, message } => Err(NetworkError::from_code(*code, message)),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Forgets the watch so it is not made again on a reconnect, and wakes
+    /// whoever waits on the value.
+    fn close(&self) {
+        let watched = (self.namespace.clone(), self.key.clone());
+        self.inner.watched.remove_if(&watched, |_, live| Arc::ptr_eq(&live.value, &self.value.value));
+        self.value.value.closed.store(true, Ordering::Release);
+        self.value.value.notify.notify_waiters();
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        self.close();
+        // The codec futures are not [Send], the release goes out from a
+        // thread of its own like the backend's.
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let (inner, namespace, key) = (Arc::clone(&self.inner), std::mem::take(&mut self.namespace), self.key.clone());
+            std::thread::spawn(move || {
+                let _ = handle.block_on(inner.release(&namespace, &key));
+            });
+        }
+    }
+}
+
+/// How many messages of a topic are held for a subscription that falls behind.
+pub const TOPIC_CAPACITY: usize = 256;
+
+/// The messages published to a topic since subscribing to it.
+pub struct TopicSubscription {
+    messages: broadcast::Receiver<Value>
+}
+
+impl TopicSubscription {
+    /// Waits for the next message, [None] once the client unsubscribed from
+    /// the topic. Messages the subscription fell too far behind on are skipped.
+    pub async fn recv(&mut self) -> Option<Value> {
+        loop {
+            match self.messages.recv().await {
+                Ok(value) => return Some(value),
+                Err(broadcast::error::RecvError::Lagged(..)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None
+            }
+        }
+    }
+}
+
+/// A change of a key watched with acknowledged delivery.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Delivered {
+    /// The value after the change, [None] if the key was deleted.
+    pub value: Option<Value>,
+    /// The version of the store the change produced, it only goes up.
+    pub sequence: u64,
+    /// Whether changes before this one were lost, the value is what the
+    /// key held rather than the next change. A fresh watch starts with one.
+    pub gap: bool
+}
+
+/// The changes of a key watched with [Client::subscribe_acknowledged].
+///
+/// Each change is handed out once and has to be acknowledged with
+/// [Deliveries::ack] once it is applied. The server sends what was not
+/// acknowledged again after a reconnect, and a change that arrives again
+/// is not handed out again.
+///
+/// A process that stops between applying a change and acknowledging it
+/// gets the change again when it watches the key anew from the sequence it
+/// last stored, so changes are processed exactly once if the sequence is
+/// stored along with what applying the change did, and the changes at or
+/// below the stored sequence are skipped.
+pub struct Deliveries {
+    inner: Arc<Inner>,
+    namespace: String,
+    key: Key,
+    state: Arc<DeliveryState>,
+    changes: mpsc::UnboundedReceiver<Delivered>
+}
+
+impl Deliveries {
+    /// Waits for the next change, [None] once the server gave up on the watch.
+    pub async fn next(&mut self) -> Option<Delivered> {
+        self.changes.recv().await
+    }
+    /// Acknowledges every change up to the sequence. Without a connection
+    /// the acknowledgement goes out when the key is watched again.
+    pub async fn ack(&self, sequence: u64) -> Result<(), NetworkError> {
+        self.state.acked.fetch_max(sequence, Ordering::AcqRel);
+        if let Some((stream, _)) = self.inner.write.lock().await.as_mut() {
+            // Nothing answers an acknowledgement, so it is sent as a notification.
+            let packet = Packet::new(PacketId::zero(), PacketPayload::AckDelivery { key: Cow::Borrowed(&self.key), sequence })
+                .with_namespace(self.namespace.as_str());
+            self.inner.intercept(packet).serialize(stream).await?;
+        }
+        Ok(())
+    }
+    /// The last sequence that was acknowledged.
+    pub fn acknowledged(&self) -> u64 {
+        self.state.acked.load(Ordering::Acquire)
+    }
+}
+
+struct DeliveryState {
+    changes: mpsc::UnboundedSender<Delivered>,
+    /// The last sequence handed out.
+    received: AtomicU64,
+    /// The last sequence acknowledged, the server sends what follows after a reconnect.
+    acked: AtomicU64
+}
+
+impl DeliveryState {
+    /// Hands out a change unless it was already.
+    fn deliver(&self, delivered: Delivered) {
+        if self.received.fetch_max(delivered.sequence, Ordering::AcqRel) < delivered.sequence {
+            let _ = self.changes.send(delivered);
+        }
+    }
+}
+
+/// How often a consumer waiting on an empty queue asks again, which picks
+/// up the items whose lease ran out as those are not announced.
+pub const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many keys are fetched per request when listing.
+pub const KEY_PAGE_SIZE: u32 = 256;
+
+/// How many records go in each request of an import.
+pub const IMPORT_CHUNK_SIZE: usize = 1024;
+
+/// How many records are fetched per request when exporting.
+pub const EXPORT_PAGE_SIZE: u32 = 256;
+
+/// Lists the keys of a namespace in order, fetching a page at a time.
+///
+/// Keys written after the listing passed them are not seen, every other key
+/// is listed exactly once.
+pub struct Keys<'a> {
+    namespace: Namespace<'a>,
+    /// Only the keys matching it are listed, see [Client::find_keys].
+    filter: Option<KeyFilter>,
+    /// The keys of the current page, in reverse so they pop off in order.
+    page: Vec<Key>,
+    /// Where the next page starts.
+    cursor: Option<Key>,
+    done: bool
+}
+
+impl Keys<'_> {
+    /// Fetches the next page of keys, or [None] once every key was listed.
+    pub async fn next_page(&mut self) -> Result<Option<Vec<Key>>, NetworkError> {
+        if !self.page.is_empty() {
+            let mut page = std::mem::take(&mut self.page);
+            page.reverse();
+            return Ok(Some(page));
+        }
+        while !self.done {
+            let (keys, cursor) = match &self.filter {
+                Some(filter) => self.namespace.find_keys_page(filter, self.cursor.as_ref(), KEY_PAGE_SIZE).await?,
+                None => self.namespace.list_keys(self.cursor.as_ref(), KEY_PAGE_SIZE).await?
+            };
+            self.done = cursor.is_none();
+            self.cursor = cursor;
+            // A filtered page may come back empty with more keys to look at.
+            if !keys.is_empty() {
+                return Ok(Some(keys));
+            }
+        }
+        Ok(None)
+    }
+    /// The next key, fetching another page when the current one runs out.
+    pub async fn next(&mut self) -> Result<Option<Key>, NetworkError> {
+        if self.page.is_empty() {
+            match self.next_page().await? {
+                Some(mut page) => {
+                    page.reverse();
+                    self.page = page;
+                }
+                None => return Ok(None)
+            }
+        }
+        Ok(self.page.pop())
+    }
+}
+
+/// The read side of a connection, either a plain socket or a TLS stream.
+type ReadHalf = Box<dyn AsyncRead + Send + Unpin>;
+/// The write side of a connection, either a plain socket or a TLS stream.
+type WriteHalf = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Where the server is listening.
+#[derive(Clone)]
+enum Endpoint {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf)
+}
+
+impl Endpoint {
+    fn describe(&self) -> String {
+        match self {
+            Self::Tcp(address) => address.to_string(),
+            #[cfg(unix)]
+            Self::Unix(path) => path.display().to_string()
+        }
+    }
+}
+
+/// How an address of the client answered a health check.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EndpointHealth {
+    pub address: String,
+    /// Whether this is the primary, which takes the writes.
+    pub primary: bool,
+    /// How long the server took to answer, [None] if it did not.
+    pub latency: Option<Duration>
+}
+
+/// Whether the request failed because the connection went away, in which
+/// case another server may still answer it.
+fn is_connection_lost(error: &NetworkError) -> bool {
+    matches!(error, NetworkError::Client(ClientError::ConnectionClosed) | NetworkError::IoError(..) | NetworkError::Client(ClientError::HealthCheckFailed))
 }
 
 pub struct Client {
-    address: SocketAddr,
-    inner: Arc<Inner>
+    /// The addresses of the servers, the first one is the primary.
+    endpoints: Vec<Endpoint>,
+    /// The endpoint of the current connection.
+    active: AtomicUsize,
+    inner: Arc<Inner>,
+    config: ClientConfig,
+    /// Serves the reads if they go to the replicas.
+    replica: Option<Box<Client>>,
+    /// Holds the writes made while the server cannot be reached.
+    offline: Option<OfflineQueue>,
+    #[cfg(feature = "tls")]
+    tls: Option<super::tls::TlsConnector>
 }
 
 struct Inner {
-    write: Mutex<Option<(OwnedWriteHalf, Arc<Notify>)>>,
+    write: Mutex<Option<(WriteHalf, Arc<Notify>)>>,
+    /// The next request id to try.
     counter: AtomicU32,
-    signal: Notify,
+    /// The in-flight requests keyed by request id.
     channels: DashMap<u32, Sender<Packet<'static>>>,
-    watched: DashMap<Key, LiveValue>
+    /// The live values by namespace and key.
+    watched: DashMap<(String, Key), LiveValue>,
+    /// The subscribed topics by namespace and topic.
+    topics: DashMap<(String, Key), broadcast::Sender<Value>>,
+    /// The keys watched with acknowledged delivery by namespace and key.
+    deliveries: DashMap<(String, Key), Arc<DeliveryState>>,
+    /// Requests the backend sent itself to refresh a live value.
+    refetches: DashMap<u32, (String, Key)>,
+    /// Announces the prefixes the server invalidated.
+    invalidations: broadcast::Sender<String>,
+    /// Resolves once the backend of the last connection has exited.
+    backend_exited: Mutex<Option<oneshot::Receiver<()>>>,
+    /// The endpoint and id of the last session the server issued.
+    session: Mutex<Option<(usize, u64)>>,
+    /// See [ClientConfig::interceptors].
+    interceptors: Vec<Arc<dyn Interceptor>>
     // channel: 
 }
 
+impl Inner {
+    /// Hands the packet to the interceptors before it is sent.
+    fn intercept<'p>(&self, mut packet: Packet<'p>) -> Packet<'p> {
+        for interceptor in &self.interceptors {
+            interceptor.before_send(&mut packet);
+        }
+        packet
+    }
+    /// Allocates a request id and registers the response channel under it.
+    ///
+    /// Ids are handed out in order and wrap around once [LAST_REQUEST_ID] is
+    /// reached. An id that still has a pending response is skipped, so we only
+    /// fail if every id in the range is in flight.
+    fn allocate(&self, channel: Sender<Packet<'static>>) -> Result<PacketId, NetworkError> {
+        let attempts = self.channels.len() + 1;
+        for _ in 0..attempts {
+            let id = self.next_candidate();
+            if let Entry::Vacant(slot) = self.channels.entry(id) {
+                slot.insert(channel);
+                return Ok(PacketId::new(id, 0));
+            }
+        }
+        Err(NetworkError::Client(ClientError::RequestIdsExhausted))
+    }
+    /// Sends the release of a watch, the receiver resolves with the answer.
+    async fn release(&self, namespace: &str, key: &Key) -> Result<oneshot::Receiver<Packet<'static>>, NetworkError> {
+        let (sender, response) = oneshot::channel();
+        let id = self.allocate(sender)?;
+        let mut handle = self.write.lock().await;
+        let Some((stream, _)) = handle.as_mut() else {
+            self.channels.remove(&id.id());
+            return Err(NetworkError::Client(ClientError::ConnectionClosed));
+        };
+        if let Err(e) = self.intercept(Packet::release(id, key).with_namespace(namespace)).serialize(stream).await {
+            self.channels.remove(&id.id());
+            return Err(e);
+        }
+        Ok(response)
+    }
+    /// Advances the counter, wrapping back to [FIRST_REQUEST_ID].
+    fn next_candidate(&self) -> u32 {
+        self.counter
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                Some(if current >= LAST_REQUEST_ID {
+                    FIRST_REQUEST_ID
+                } else {
+                    current + 1
+                })
+            })
+            .unwrap()
+    }
+}
 
 
 
-
-async fn run_client_backend(mut read: OwnedReadHalf, kill: Arc<Notify>, inner: Arc<Inner>) -> Result<(), NetworkError>
+async fn run_client_backend(mut read: ReadHalf, kill: Arc<Notify>, inner: Arc<Inner>, keepalive: Option<Duration>) -> Result<(), NetworkError>
 {
+    // The pinger runs beside the reader instead of racing it, cancelling a
+    // read part way through a packet would corrupt the stream.
+    let result = tokio::select! {
+        result = read_packets(&mut read, &inner) => result,
+        result = send_pings(&inner, keepalive) => result,
+        _ = kill.notified() => Ok(())
+    };
 
-    inner.signal.notify_waiters();
+    // Dropping the senders wakes every pending request with an error.
+    inner.channels.clear();
+    inner.refetches.clear();
 
-    loop {
+    // Forget the connection so the next request reconnects, unless it
+    // was already replaced.
+    let mut write = inner.write.lock().await;
+    if write.as_ref().is_some_and(|(_, current)| Arc::ptr_eq(current, &kill)) {
+        write.take();
+    }
+    drop(write);
 
-        let packet = tokio::select! {
-            e = Packet::deserialize(&mut read) => {
-                e
-            },
-            _ = kill.notified() => {
-                break;
-            }
-        }?;
+    result
+}
+
+/// Routes incoming packets to their requests and live values.
+async fn read_packets(read: &mut ReadHalf, inner: &Inner) -> Result<(), NetworkError>
+{
+    loop {
+        let packet = read_packet(read).await?;
+        for interceptor in &inner.interceptors {
+            interceptor.after_receive(&packet);
+        }
         let packet_id = packet.id();
 
  
-        if packet_id.id() == 0 {
-            if let PacketPayload::Notify { key, value, .. } = packet.payload() {
-                let live_value = &*inner.watched.get(&key).unwrap().value;
-                *live_value.value.lock().await = value.as_deref().cloned();
-                live_value.notify.notify_waiters();
+        if packet_id.is_notification() {
+            // A busy server closes the connection right after telling us.
+            if let PacketPayload::ServerClosing | PacketPayload::ServerBusy = packet.payload() {
+                // Forget the connection so the next request reconnects.
+                inner.write.lock().await.take();
+                return Ok(());
             }
-        } else {
-            let (_, channel) = inner.channels.remove(&packet_id.id()).unwrap();
-            channel.send(packet).unwrap();
+            if let PacketPayload::Invalidate { prefix } = packet.payload() {
+                let _ = inner.invalidations.send(prefix.to_string());
+                refetch_watched(inner, packet.namespace(), prefix).await?;
+            }
+            if let PacketPayload::WatchOverflow { key, missed, closed } = packet.payload() {
+                let watched = (packet.namespace().to_string(), (**key).clone());
+                let live_value = inner.watched.get(&watched).map(|f| Arc::clone(&f.value));
+                if let Some(live_value) = live_value {
+                    live_value.missed.fetch_add(*missed, Ordering::AcqRel);
+                    if *closed {
+                        live_value.closed.store(true, Ordering::Release);
+                        inner.watched.remove(&watched);
+                        live_value.notify.notify_waiters();
+                    } else {
+                        // The changes that were dropped may have been the latest.
+                        refetch(inner, watched.0, watched.1).await?;
+                    }
+                }
+            }
+            if let PacketPayload::WatchIsolated { key, .. } = packet.payload() {
+                // The server gave up on the watch, it will not be sent anything else.
+                let watched = (packet.namespace().to_string(), (**key).clone());
+                if let Some((_, live_value)) = inner.watched.remove(&watched) {
+                    live_value.value.closed.store(true, Ordering::Release);
+                    live_value.value.notify.notify_waiters();
+                }
+                // Dropping the sender ends the deliveries.
+                inner.deliveries.remove(&watched);
+            }
+            if let PacketPayload::Delivery { key, value, sequence, gap } = packet.payload() {
+                let state = inner.deliveries.get(&(packet.namespace().to_string(), (**key).clone())).map(|f| Arc::clone(&f));
+                if let Some(state) = state {
+                    state.deliver(Delivered { value: value.as_deref().cloned(), sequence: *sequence, gap: *gap });
+                }
+            }
+            if let PacketPayload::TopicMessage { topic, value } = packet.payload() {
+                if let Some(messages) = inner.topics.get(&(packet.namespace().to_string(), (**topic).clone())) {
+                    let _ = messages.send((**value).clone());
+                }
+            }
+            if let PacketPayload::Notify { key, value, heartbeat, .. } = packet.payload() {
+                let live_value = inner.watched.get(&(packet.namespace().to_string(), (**key).clone())).map(|f| Arc::clone(&f.value));
+                if let Some(live_value) = live_value {
+                    *live_value.last_seen.lock().unwrap() = Some(Instant::now());
+                    // A heartbeat brings no change to wake anyone for.
+                    if !*heartbeat {
+                        *live_value.value.lock().await = value.as_deref().cloned();
+                        live_value.trace.store(packet.trace().unwrap_or(0), Ordering::Release);
+                        live_value.notify.notify_waiters();
+                    }
+                }
+            }
+        } else if let Some((_, key)) = inner.refetches.remove(&packet_id.id()) {
+            inner.channels.remove(&packet_id.id());
+            if let PacketPayload::Return { value, .. } = packet.payload() {
+                let live_value = inner.watched.get(&key).map(|f| Arc::clone(&f.value));
+                if let Some(live_value) = live_value {
+                    *live_value.value.lock().await = value.as_deref().cloned();
+                    live_value.trace.store(0, Ordering::Release);
+                    live_value.notify.notify_waiters();
+                }
+            }
+        } else if let Some((_, channel)) = inner.channels.remove(&packet_id.id()) {
+            if let PacketPayload::Snapshot { key, value, version } = packet.payload() {
+                // Seed the live value here so no notification behind it can be overwritten.
+                let watched = (packet.namespace().to_string(), (**key).clone());
+                let live_value = inner.watched.get(&watched).map(|f| Arc::clone(&f.value));
+                if let Some(live_value) = live_value {
+                    *live_value.value.lock().await = value.as_deref().cloned();
+                    *live_value.last_seen.lock().unwrap() = Some(Instant::now());
+                }
+                // A fresh watch with acknowledged delivery starts from the record.
+                let state = inner.deliveries.get(&watched).map(|f| Arc::clone(&f));
+                if let Some(state) = state {
+                    state.deliver(Delivered { value: value.as_deref().cloned(), sequence: *version, gap: true });
+                }
+            }
+            // The requester may have given up, which is fine.
+            let _ = channel.send(packet);
         }
+    }
+}
 
+/// Fetches every watched key under the prefix of the namespace again.
+///
+/// The responses are applied by the reader in order with the notifications,
+/// so a refreshed value can never land on top of a newer one.
+async fn refetch_watched(inner: &Inner, namespace: &str, prefix: &str) -> Result<(), NetworkError>
+{
+    let keys: Vec<(String, Key)> = inner.watched.iter()
+        .map(|f| f.key().clone())
+        .filter(|(ns, key)| ns == namespace && key.as_str().starts_with(prefix))
+        .collect();
+    for (namespace, key) in keys {
+        refetch(inner, namespace, key).await?;
     }
-    println!("Hello broken");
+    Ok(())
+}
 
+/// Fetches a watched key again, the reader applies the response to its live value.
+async fn refetch(inner: &Inner, namespace: String, key: Key) -> Result<(), NetworkError>
+{
+    let (sdr, _) = tokio::sync::oneshot::channel();
+    let id = inner.allocate(sdr)?;
+    inner.refetches.insert(id.id(), (namespace.clone(), key.clone()));
+    if let Some((stream, _)) = inner.write.lock().await.as_mut() {
+        stream.write_all(&encode_packet(&inner.intercept(Packet::get(id, &key).with_namespace(namespace)))?).await?;
+    }
     Ok(())
 }
 
+/// Pings the server every period, forever if keepalives are off.
+///
+/// Pings carry the notification id since nothing waits on the pong.
+async fn send_pings(inner: &Inner, keepalive: Option<Duration>) -> Result<(), NetworkError>
+{
+    let Some(period) = keepalive else {
+        return pending().await;
+    };
+    let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+    loop {
+        interval.tick().await;
+        if let Some((stream, _)) = inner.write.lock().await.as_mut() {
+            stream.write_all(&encode_packet(&inner.intercept(Packet::new(PacketId::zero(), PacketPayload::Ping)))?).await?;
+        }
+    }
+}
+
+/// The bytes of a packet header, its version and the two halves of its id.
+const HEADER_SIZE: usize = 9;
+
+/// The most bytes a frame length is encoded in.
+const MAX_LENGTH_SIZE: usize = 10;
+
+/// Reads the next packet off the connection.
+///
+/// The codec futures are not [Send], so the backend could not be spawned if
+/// it awaited them on the socket. The frame is read with the socket's own
+/// reads instead and the packet is then decoded from memory.
+async fn read_packet(read: &mut ReadHalf) -> Result<Packet<'static>, NetworkError>
+{
+    let mut frame = vec![0u8; HEADER_SIZE];
+    read.read_exact(&mut frame).await?;
+    let version = frame[0];
+    if version < FRAMED_VERSION {
+        // The server answers in our version, which is always framed.
+        return Err(NetworkError::Protocol(ProtocolError::UnknownPacketSchema(version)));
+    }
+    let length = if version >= VARINT_FRAME_VERSION {
+        loop {
+            let byte = read.read_u8().await?;
+            frame.push(byte);
+            if byte & 0x80 == 0 || frame.len() - HEADER_SIZE == MAX_LENGTH_SIZE {
+                break;
+            }
+        }
+        OvrInteger::read_slice::<usize>(&frame[HEADER_SIZE..])?
+    } else {
+        let length = read.read_u32().await?;
+        frame.extend_from_slice(&length.to_be_bytes());
+        length as usize
+    };
+    if length > DEFAULT_MAX_PACKET_SIZE {
+        return Err(NetworkError::Protocol(ProtocolError::PacketTooLarge(length, DEFAULT_MAX_PACKET_SIZE)));
+    }
+    let start = frame.len();
+    frame.resize(start + length, 0);
+    read.read_exact(&mut frame[start..]).await?;
+    in_memory(Packet::deserialize(&mut frame.as_slice()))
+}
+
+/// Encodes a packet so the backend can write it with the socket's own writes, see [read_packet].
+fn encode_packet(packet: &Packet<'_>) -> Result<Vec<u8>, NetworkError>
+{
+    in_memory(async {
+        let mut encoded = vec![];
+        packet.serialize(&mut encoded).await?;
+        Ok(encoded)
+    })
+}
+
+/// Runs a codec future over a buffer in memory, which never has to wait.
+fn in_memory<T>(future: impl Future<Output = Result<T, NetworkError>>) -> Result<T, NetworkError>
+{
+    match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(result) => result,
+        Poll::Pending => Err(std::io::Error::from(ErrorKind::WouldBlock).into())
+    }
+}
+
 impl Client {
 
     pub async fn new<A>(address: A) -> Result<Self, NetworkError>
     where 
         A: ToSocketAddrs
     {
-        println!("HELLO");
-        let address = address.to_socket_addrs().map_err(|_| NetworkError::SocketError)?.nth(0).unwrap();
+        Self::with_config(address, ClientConfig::default()).await
+    }
+    /// Creates a client that speaks TLS to the server.
+    #[cfg(feature = "tls")]
+    pub async fn new_tls<A>(address: A, config: super::ClientTlsConfig) -> Result<Self, NetworkError>
+    where 
+        A: ToSocketAddrs
+    {
+        Self::with_config(address, ClientConfig::default().with_tls(config)).await
+    }
+    /// Creates a client with a specific configuration.
+    pub async fn with_config<A>(address: A, config: ClientConfig) -> Result<Self, NetworkError>
+    where 
+        A: ToSocketAddrs
+    {
+        Self::with_endpoints([address], config).await
+    }
+    /// Creates a client that fails over between several servers.
+    ///
+    /// The first address is the primary, it takes the writes whenever it is
+    /// healthy. If the connection is lost the client moves to the next server
+    /// that passes a health check and subscribes to its watches again there.
+    /// With [ClientConfig::with_replica_reads] the reads go to the other
+    /// servers instead.
+    pub async fn with_endpoints<I, A>(addresses: I, config: ClientConfig) -> Result<Self, NetworkError>
+    where 
+        I: IntoIterator<Item = A>,
+        A: ToSocketAddrs
+    {
+        let endpoints = addresses.into_iter()
+            .map(|f| f.to_socket_addrs().map_err(|_| NetworkError::Client(ClientError::SocketError))?.next().map(Endpoint::Tcp).ok_or(NetworkError::Client(ClientError::SocketError)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::with_endpoint(endpoints, config)
+    }
+    /// Creates a client that connects over a Unix domain socket.
+    #[cfg(unix)]
+    pub async fn new_unix<P>(path: P) -> Result<Self, NetworkError>
+    where 
+        P: AsRef<Path>
+    {
+        Self::with_endpoint(vec![Endpoint::Unix(path.as_ref().to_path_buf())], ClientConfig::default())
+    }
+    fn with_endpoint(endpoints: Vec<Endpoint>, config: ClientConfig) -> Result<Self, NetworkError> {
+        if endpoints.is_empty() {
+            return Err(NetworkError::Client(ClientError::NoEndpoints));
+        }
+        let replica = if config.replica_reads && endpoints.len() > 1 {
+            // The replicas are tried in order, the primary answers if none can.
+            let mut replicas = endpoints[1..].to_vec();
+            replicas.push(endpoints[0].clone());
+            let config = ClientConfig { replica_reads: false, offline_queue: None, ..config.clone() };
+            Some(Box::new(Self::with_endpoint(replicas, config)?))
+        } else {
+            None
+        };
         Ok(Self {
-            address,
+            endpoints,
+            active: AtomicUsize::new(0),
+            replica,
+            offline: config.offline_queue.clone().map(OfflineQueue::new),
             inner: Arc::new(Inner {
-                counter: AtomicU32::new(1),
+                counter: AtomicU32::new(FIRST_REQUEST_ID),
                 write: Mutex::new(None),
-                signal: Notify::new(),
                 channels: DashMap::new(),
-                watched: DashMap::new()
-            })
+                watched: DashMap::new(),
+                topics: DashMap::new(),
+                deliveries: DashMap::new(),
+                refetches: DashMap::new(),
+                invalidations: broadcast::channel(16).0,
+                backend_exited: Mutex::new(None),
+                session: Mutex::new(None),
+                interceptors: config.interceptors.clone()
+            }),
+            #[cfg(feature = "tls")]
+            tls: config.tls.as_ref().map(|tls| tls.connector()).transpose()?,
+            config
         })
     }
+    /// Receives the key prefixes the server invalidates, anything cached
+    /// for keys under them should be dropped and fetched again. Live values
+    /// under the prefix are refreshed automatically.
+    pub fn invalidations(&self) -> broadcast::Receiver<String> {
+        self.inner.invalidations.subscribe()
+    }
     pub async fn reset_connection(&self) -> Result<(), NetworkError> {
         if let Some((a, kill)) = &mut *self.inner.write.lock().await {
             a.shutdown().await?;
-            kill.notify_waiters();
+            kill.notify_one();
         }
         *self.inner.write.lock().await = None;
         Ok(())
 
     }
-    async fn connect(&self) -> Result<(), NetworkError> {
-        println!("CONNECT");
-        if self.inner.write.lock().await.is_none() {
-            let (read, write) = TcpStream::connect(self.address).await?.into_split();
+    /// Connects if there is no connection, trying the primary first and
+    /// failing over to the next server that passes the health check.
+    ///
+    /// The watches of a lost connection are subscribed to again on the new one.
+    pub(crate) async fn connect(&self) -> Result<(), NetworkError> {
+        if self.inner.write.lock().await.is_some() {
+            return Ok(());
+        }
+        // The backend of a lost connection fails every request in flight as
+        // it exits, it must not get to the requests of the new one.
+        if let Some(exited) = self.inner.backend_exited.lock().await.take() {
+            let _ = exited.await;
+        }
+        let mut failure = NetworkError::Client(ClientError::NoEndpoints);
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            match self.connect_to(endpoint).await {
+                Ok(()) => {
+                    self.active.store(index, Ordering::Release);
+                    if self.config.resume_sessions && self.resume_session(index).await? {
+                        // The server kept the watches of the lost connection.
+                        return self.replay_offline().await;
+                    }
+                    self.resubscribe().await?;
+                    return self.replay_offline().await;
+                }
+                // The servers share the token, the others will refuse it as well.
+                Err(NetworkError::Client(ClientError::Unauthorized)) => return Err(NetworkError::Client(ClientError::Unauthorized)),
+                Err(e) => failure = e
+            }
+        }
+        Err(failure)
+    }
+    async fn connect_to(&self, endpoint: &Endpoint) -> Result<(), NetworkError> {
+        let (read, write) = self.open_stream(endpoint).await?;
+
+        let notif = Arc::new(Notify::new());
+
+        let (done, exited) = oneshot::channel::<()>();
+        let backend = run_client_backend(read, notif.clone(), Arc::clone(&self.inner), self.config.keepalive);
+        tokio::spawn(async move {
+            let _ = backend.await;
+            drop(done);
+        });
+        *self.inner.backend_exited.lock().await = Some(exited);
+
+        *self.inner.write.lock().await = Some((write, notif));
+
+        let checked = async {
+            if let Some(token) = &self.config.token {
+                self.authenticate(token).await?;
+            }
+            // A lone server is used whatever state it is in.
+            if self.endpoints.len() > 1 {
+                self.ping().await?;
+            }
+            Ok(())
+        }.await;
+        if let Err(e) = checked {
+            self.reset_connection().await?;
+            return Err(e);
+        }
+        Ok(())
+    }
+    /// Forgets the connection without shutting it down, for one that is already lost.
+    async fn drop_connection(&self) {
+        if let Some((_, kill)) = self.inner.write.lock().await.take() {
+            // The backend may not be waiting on it yet, the permit is kept for it.
+            kill.notify_one();
+        }
+    }
+    /// Resumes the session the client had on the endpoint, or takes a new
+    /// one. Returns whether the session was resumed.
+    async fn resume_session(&self, endpoint: usize) -> Result<bool, NetworkError> {
+        let previous = self.inner.session.lock().await.filter(|(e, _)| *e == endpoint).map(|(_, id)| id);
+        match self.send(PacketPayload::Resume { session: previous }).await?.payload() {
+            PacketPayload::Session { id, resumed } => {
+                *self.inner.session.lock().await = Some((endpoint, *id));
+                Ok(*resumed)
+            }
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Watches the keys of the live values again after a reconnect, the
+    /// snapshots bring the values up to date with the new server. The topics
+    /// are subscribed to again as well, what was published in between is lost.
+    async fn resubscribe(&self) -> Result<(), NetworkError> {
+        let topics: Vec<(String, Key)> = self.inner.topics.iter().map(|f| f.key().clone()).collect();
+        for (namespace, topic) in topics {
+            match self.send_in(&namespace, PacketPayload::subscribe_topic(&topic)).await {
+                Ok(..) => {}
+                Err(e) if is_connection_lost(&e) => return Err(e),
+                // Dropping the sender ends the subscription.
+                Err(..) => drop(self.inner.topics.remove(&(namespace, topic)))
+            }
+        }
 
-            let notif = Arc::new(Notify::new());
+        let deliveries: Vec<((String, Key), u64)> = self.inner.deliveries.iter()
+            .map(|f| (f.key().clone(), f.acked.load(Ordering::Acquire)))
+            .collect();
+        for ((namespace, key), acked) in deliveries {
+            match self.send_in(&namespace, PacketPayload::WatchAcknowledged { key: Cow::Borrowed(&key), after: acked }).await {
+                Ok(response) if matches!(response.payload(), PacketPayload::Get { .. } | PacketPayload::Snapshot { .. }) => {}
+                Err(e) if is_connection_lost(&e) => return Err(e),
+                _ => drop(self.inner.deliveries.remove(&(namespace, key)))
+            }
+        }
 
-            // TODO: REINTRODUCE
-            // tokio::spawn(run_client_backend(read, notif.clone(), Arc::clone(&self.inner)));
-            
-            self.inner.signal.notified().await;
-            *self.inner.write.lock().await = Some((write, notif));
-            
+        let watched: Vec<((String, Key), LiveValue)> = self.inner.watched.iter()
+            .map(|f| (f.key().clone(), f.value().clone()))
+            .collect();
+        for ((namespace, key), live) in watched {
+            match self.send_in(&namespace, PacketPayload::watch_snapshot(&key, live.value.behaviour).with_min_interval(live.value.min_interval)).await {
+                Ok(response) if matches!(response.payload(), PacketPayload::Snapshot { .. }) => live.value.notify.notify_waiters(),
+                Err(e) if is_connection_lost(&e) => return Err(e),
+                _ => {
+                    // The key cannot be watched on this server.
+                    self.inner.watched.remove(&(namespace, key));
+                    live.value.closed.store(true, Ordering::Release);
+                    live.value.notify.notify_waiters();
+                }
+            }
         }
         Ok(())
     }
-    async fn send(&self, packet: Packet<'static>) -> Result<Packet, NetworkError> {
-        let mut handle = self.inner.write.lock().await;
-        let (stream, _) = handle.as_mut().unwrap();
+    /// Checks that the server answers within the health timeout.
+    pub(crate) async fn ping(&self) -> Result<Duration, NetworkError> {
+        let start = Instant::now();
+        let timeout = self.config.health_timeout.unwrap_or(HEALTH_CHECK_TIMEOUT);
+        match tokio::time::timeout(timeout, self.send(PacketPayload::Ping)).await {
+            Ok(Ok(response)) if matches!(response.payload(), PacketPayload::Pong) => Ok(start.elapsed()),
+            Ok(Err(e)) if !is_connection_lost(&e) => Err(e),
+            _ => Err(NetworkError::Client(ClientError::HealthCheckFailed))
+        }
+    }
+    /// Checks every server of the client over a fresh connection, the
+    /// connection in use is left alone.
+    pub async fn health_check(&self) -> Vec<EndpointHealth> {
+        let mut report = Vec::with_capacity(self.endpoints.len());
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            let config = ClientConfig { replica_reads: false, keepalive: None, resume_sessions: false, offline_queue: None, ..self.config.clone() };
+            let latency = match Self::with_endpoint(vec![endpoint.clone()], config) {
+                Ok(probe) => {
+                    let latency = match probe.connect().await {
+                        Ok(()) => probe.ping().await.ok(),
+                        Err(..) => None
+                    };
+                    probe.drop_connection().await;
+                    latency
+                }
+                Err(..) => None
+            };
+            report.push(EndpointHealth { address: endpoint.describe(), primary: index == 0, latency });
+        }
+        report
+    }
+    /// The address of the server the client is connected to, or last was.
+    pub fn active_address(&self) -> String {
+        self.endpoints[self.active.load(Ordering::Acquire)].describe()
+    }
+    /// Presents the token, this has to be the first packet on the connection.
+    async fn authenticate(&self, token: &str) -> Result<(), NetworkError> {
+        match self.send(PacketPayload::auth(token)).await?.payload() {
+            PacketPayload::AuthResult { accepted: true } => Ok(()),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Opens the transport to the server.
+    async fn open_stream(&self, endpoint: &Endpoint) -> Result<(ReadHalf, WriteHalf), NetworkError> {
+        let address = match endpoint {
+            Endpoint::Tcp(address) => address,
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                let (read, write) = UnixStream::connect(path).await?.into_split();
+                return Ok((Box::new(read), Box::new(write)));
+            }
+        };
+        let socket = TcpStream::connect(address).await?;
+
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            let (read, write) = tokio::io::split(tls.connect(socket).await?);
+            return Ok((Box::new(read), Box::new(write)));
+        }
 
+        let (read, write) = socket.into_split();
+        Ok((Box::new(read), Box::new(write)))
+    }
+    /// Sends a request to the default namespace and waits for the response to it.
+    async fn send(&self, payload: PacketPayload<'_>) -> Result<Packet<'static>, NetworkError> {
+        self.send_in(DEFAULT_NAMESPACE, payload).await
+    }
+    /// Sends a request to a namespace and waits for the response to it.
+    async fn send_in(&self, namespace: &str, payload: PacketPayload<'_>) -> Result<Packet<'static>, NetworkError> {
+        let (sdr, rcv) = tokio::sync::oneshot::channel::<Packet<'static>>();
+        let id = self.inner.allocate(sdr)?;
+        let packet = self.inner.intercept(Packet::new(id, payload).with_namespace(namespace).with_trace(current_trace()));
 
-        let (sdr, rcv) = tokio::sync::oneshot::channel::<Packet>();
-        
-        self.inner.channels.insert(packet.id().id(), sdr);
+        {
+            let mut handle = self.inner.write.lock().await;
+            let Some((stream, _)) = handle.as_mut() else {
+                self.inner.channels.remove(&id.id());
+                return Err(NetworkError::Client(ClientError::ConnectionClosed));
+            };
+            if let Err(e) = packet.serialize(stream).await {
+                self.inner.channels.remove(&id.id());
+                return Err(e);
+            }
+        }
 
-        packet.serialize(stream).await?;
-        Ok(rcv.await.unwrap())
+        let response = rcv.await.map_err(|_| NetworkError::Client(ClientError::ConnectionClosed))?;
+        match response.payload() {
+            PacketPayload::AuthResult { accepted: false } => return Err(NetworkError::Client(ClientError::Unauthorized)),
+            PacketPayload::UnknownNamespace => return Err(NetworkError::UnknownNamespace),
+            PacketPayload::Throttled { retry_after } => return Err(NetworkError::Client(ClientError::Throttled(*retry_after))),
+            PacketPayload::Error { code, message } => return Err(NetworkError::from_code(*code, message)),
+            _ => {}
+        }
+        Ok(response)
         // Ok(Packet::read(stream).await?)
     }
-    fn count(&self) -> u32 {
-        self.inner.counter.fetch_add(1, Ordering::AcqRel)
+    /// Sends a request to a namespace, connecting first. If the client has
+    /// several servers and the connection is lost before the response arrives,
+    /// the request is sent once more to the server the client fails over to,
+    /// so it may be applied twice.
+    async fn request_in<'p, F>(&self, namespace: &str, payload: F) -> Result<Packet<'static>, NetworkError>
+    where 
+        F: Fn() -> PacketPayload<'p>
+    {
+        self.connect().await?;
+        match self.send_in(namespace, payload()).await {
+            Err(e) if is_connection_lost(&e) && self.endpoints.len() > 1 => {
+                self.drop_connection().await;
+                self.connect().await?;
+                self.send_in(namespace, payload()).await
+            }
+            response => response
+        }
+    }
+    /// Sends an insert, or a delete if there is no value. A client with an
+    /// offline queue queues the write if the server cannot be reached and
+    /// returns [None], the writes queued before it are replayed first.
+    async fn write_in(&self, namespace: &str, key: &Key, value: Option<&Value>) -> Result<Option<Packet<'static>>, NetworkError> {
+        let payload = || match value {
+            Some(value) => PacketPayload::insert(key, value),
+            None => PacketPayload::delete(key)
+        };
+        let Some(queue) = &self.offline else {
+            return self.request_in(namespace, payload).await.map(Some);
+        };
+        let sent = async {
+            self.connect().await?;
+            self.replay_offline().await?;
+            self.request_in(namespace, payload).await
+        }.await;
+        match sent {
+            Err(e) if is_connection_lost(&e) => {
+                queue.writes().await?.push(QueuedWrite::new(namespace, key, value)).await?;
+                Ok(None)
+            }
+            sent => sent.map(Some)
+        }
     }
-    pub async fn get(&self, key: &Key) -> Result<Option<Value>, NetworkError>
+    /// Replays the queued writes oldest first. A write the server refuses is
+    /// dropped, the replay stops where it is if the connection is lost.
+    async fn replay_offline(&self) -> Result<(), NetworkError> {
+        let Some(queue) = &self.offline else {
+            return Ok(());
+        };
+        let mut writes = queue.writes().await?;
+        while let Some(write) = writes.front() {
+            if queue.conflict() == ReplayConflict::KeepServer && self.written_since(&write).await? {
+                writes.pop().await?;
+                continue;
+            }
+            let sent = match &write.value {
+                Some(value) => self.send_in(&write.namespace, PacketPayload::insert(&write.key, value)).await,
+                None => self.send_in(&write.namespace, PacketPayload::delete(&write.key)).await
+            };
+            match sent {
+                Err(e) if is_connection_lost(&e) => return Err(e),
+                _ => writes.pop().await?
+            }
+        }
+        Ok(())
+    }
+    /// Whether the key was written on the server after the write was queued.
+    async fn written_since(&self, write: &QueuedWrite) -> Result<bool, NetworkError> {
+        match self.send_in(&write.namespace, PacketPayload::GetMeta { key: Cow::Borrowed(&write.key) }).await {
+            Ok(response) => match response.payload() {
+                PacketPayload::KeyMeta { meta, .. } => Ok(meta.record.is_some_and(|f| f.modified_at > write.queued_at)),
+                _ => Ok(false)
+            },
+            Err(e) if is_connection_lost(&e) => Err(e),
+            Err(_) => Ok(false)
+        }
+    }
+    /// The writes waiting in the offline queue for the server.
+    pub async fn queued_writes(&self) -> Result<usize, NetworkError> {
+        match &self.offline {
+            Some(queue) => Ok(queue.writes().await?.len()),
+            None => Ok(0)
+        }
+    }
+    /// Sends a read to the replicas if the client reads from them, the
+    /// primary answers it if no replica can.
+    async fn read_in<'p, F>(&self, namespace: &str, payload: F) -> Result<Packet<'static>, NetworkError>
+    where 
+        F: Fn() -> PacketPayload<'p>
+    {
+        if let Some(replica) = &self.replica {
+            match replica.request_in(namespace, &payload).await {
+                Err(e) if is_connection_lost(&e) => {}
+                response => return response
+            }
+        }
+        self.request_in(namespace, payload).await
+    }
+    /// A handle scoped to a namespace of the server, every request made
+    /// through it reads and writes that namespace alone.
+    pub fn namespace<S>(&self, name: S) -> Namespace<'_>
+    where 
+        S: Into<String>
+    {
+        Namespace { client: self, name: name.into() }
+    }
+    /// Creates a namespace, returning false if it already existed.
+    pub async fn create_namespace(&self, name: &str) -> Result<bool, NetworkError>
     {
         self.connect().await?;
 
-        let packet = Packet::new(PacketId::new(self.count(), 0), PacketPayload::get(key)).to_owned();
-        if let PacketPayload::Return { value, .. } = self.send(packet).await?.payload() {
-            return Ok(value.as_deref().cloned());
+        if let PacketPayload::NamespaceResult { changed } = self.send(PacketPayload::create_namespace(name)).await?.payload() {
+            Ok(*changed)
         } else {
-            return Err(NetworkError::WrongResponseFromServer);
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
         }
     }
-    pub async fn delete(&self, key: &Key) -> Result<(), NetworkError>
+    /// Drops a namespace and everything in it, returning false if it did not exist.
+    pub async fn drop_namespace(&self, name: &str) -> Result<bool, NetworkError>
     {
         self.connect().await?;
 
-   
-        let packet = Packet::new(PacketId::new(self.count(), 0), PacketPayload::delete(key)).to_owned();
-        if let PacketPayload::Get { .. } = self.send(packet).await?.payload() {
-            return Ok(());
+        if let PacketPayload::NamespaceResult { changed } = self.send(PacketPayload::drop_namespace(name)).await?.payload() {
+            Ok(*changed)
         } else {
-            return Err(NetworkError::WrongResponseFromServer);
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
         }
     }
-    pub async fn insert(&self, key: &Key, value: Value) -> Result<Option<Value>, NetworkError>
+    /// Promotes the keys under a prefix of one namespace into another in a
+    /// single step, watchers of the target see every change at once.
+    ///
+    /// A dry run only reports what would change.
+    pub async fn promote(&self, promotion: Promotion, dry_run: bool) -> Result<PromotionReport, NetworkError>
     {
         self.connect().await?;
 
-        // if let Packet::Return { value, .. } = self.send(Packet::insert(key, value)).await? {
-        //     return Ok(value);
-        // } else {
-        //     return Err(NetworkError::WrongResponseFromServer);
-        // }
-        println!("Hello");
-        let packet = Packet::new(PacketId::new(self.count(), 0), PacketPayload::insert(key, &value)).to_owned();
-        if let PacketPayload::Return { value, .. } = self.send(packet).await?.payload() {
-            return Ok(value.as_deref().cloned());
+        if let PacketPayload::PromotionResult { report: Some(report) } = self.send(PacketPayload::Promote { promotion, dry_run }).await?.into_payload() {
+            Ok(report)
         } else {
-            return Err(NetworkError::WrongResponseFromServer);
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
         }
-        // Ok(None)
     }
-    pub async fn subscribe(&self, key: &Key, activity: WatcherActivity, behaviour: WatcherBehaviour) -> Result<LiveValue, NetworkError>
+    /// Restores what the target of the latest promotion held before it.
+    ///
+    /// Promotions are rolled back newest first, any other id fails with
+    /// [ClientError::UnknownPromotion].
+    pub async fn rollback_promotion(&self, id: u64) -> Result<PromotionReport, NetworkError>
     {
         self.connect().await?;
 
-        let inner = LiveValue {
-            value: Arc::new(LiveValueInternal {
-                value: Mutex::default(),
-                notify: Notify::new()
-            })
-        };
-
-        self.inner.watched.insert(key.borrow().clone(), inner.clone());
-        let packet = Packet::new(PacketId::new(self.count(), 0), PacketPayload::watch(key, activity, behaviour)).to_owned();
-        
-        if let PacketPayload::Get { .. } = self.send(packet).await?.payload() {
-            return Ok(inner);
+        if let PacketPayload::PromotionResult { report } = self.send(PacketPayload::RollbackPromotion { id }).await?.into_payload() {
+            report.ok_or(NetworkError::Client(ClientError::UnknownPromotion))
         } else {
-            return Err(NetworkError::WrongResponseFromServer);
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
         }
-        // self.send(Packet::watch(key, activity, behaviour)).await?;
-
-        // Ok(())
     }
-}
-
-
-#[cfg(test)]
-mod tests {
-    use crate::Client;
-
-
-   
-}
\ No newline at end of file
+    pub async fn get(&self, key: &Key) -> Result<Option<Value>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).get(key).await
+    }
+    /// Gets the value of a key along with the version of its record, which
+    /// is zero if the key holds nothing. Pass the version to
+    /// [Client::insert_if_version] to detect lost updates.
+    pub async fn get_versioned(&self, key: &Key) -> Result<(Option<Value>, u64), NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).get_versioned(key).await
+    }
+    /// Gets the values of the keys as they were at a single point in time,
+    /// in the order of the keys. Reading them one by one may see some from
+    /// before a batch of writes and some from after it.
+    pub async fn snapshot_get(&self, keys: &[Key]) -> Result<Vec<Option<Value>>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).snapshot_get(keys).await
+    }
+    /// The keys one segment below the key in order, see [Key::child]. The
+    /// root key lists the top of the tree.
+    pub async fn children(&self, key: &Key) -> Result<Vec<KeyChild>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).children(key).await
+    }
+    /// Lists every key in order.
+    pub fn keys(&self) -> Keys<'_> {
+        self.namespace(DEFAULT_NAMESPACE).keys()
+    }
+    /// Lists the keys matching the glob or regular expression in order. The
+    /// server does the matching, so only the keys that match are sent.
+    /// Servers before version 35 do not know it.
+    pub fn find_keys(&self, filter: KeyFilter) -> Keys<'_> {
+        self.namespace(DEFAULT_NAMESPACE).find_keys(filter)
+    }
+    /// The keys watched on the server in order, with how many clients
+    /// watch each.
+    pub async fn watcher_info(&self) -> Result<Vec<WatchedKey>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).watcher_info().await
+    }
+    /// How many clients watch the key.
+    pub async fn watcher_count(&self, key: &Key) -> Result<u64, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).watcher_count(key).await
+    }
+    /// What the key held at a version or a time, [None] if it had no value
+    /// then. See [Namespace::get_at].
+    pub async fn get_at(&self, key: &Key, at: Revision) -> Result<Option<Value>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).get_at(key, at).await
+    }
+    pub async fn diff(&self, key: &Key, from: Revision, to: Revision) -> Result<KeyDiff, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).diff(key, from, to).await
+    }
+    /// Every change to the key the server kept, oldest first. The server
+    /// keeps a bounded number of changes per key and none from before it
+    /// started.
+    pub async fn history(&self, key: &Key) -> Result<Vec<HistoryEntry>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).history(key).await
+    }
+    /// The last changes to the key the server kept, oldest first.
+    pub async fn recent_history(&self, key: &Key, limit: u32) -> Result<Vec<HistoryEntry>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).recent_history(key, limit).await
+    }
+    pub async fn meta(&self, key: &Key) -> Result<KeyMeta, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).meta(key).await
+    }
+    /// The keys neither read nor written since the time, in milliseconds
+    /// since the unix epoch, along with their values. The server deletes
+    /// them as well if `delete` is set, which needs write access.
+    pub async fn archive(&self, before: u64, delete: bool) -> Result<Vec<(Key, Value)>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).archive(before, delete).await
+    }
+    /// The value of the key along with its metadata, which says when the
+    /// record was written and by whom.
+    pub async fn get_with_meta(&self, key: &Key) -> Result<(Option<Value>, KeyMeta), NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).get_with_meta(key).await
+    }
+    pub async fn query_by_index(&self, field: &str, value: &Value) -> Result<Option<Vec<Key>>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).query_by_index(field, value).await
+    }
+    pub async fn delete(&self, key: &Key) -> Result<(), NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).delete(key).await
+    }
+    /// Deletes the key only if it still holds the value or its record is
+    /// still at the version, returning whether it did.
+    ///
+    /// The server checks and deletes in one step, so a cleanup job cannot
+    /// remove a key another client rewrote after it was read. A key holding
+    /// nothing is never deleted. Servers before version 34 do not know it.
+    pub async fn delete_if(&self, key: &Key, condition: DeleteCondition) -> Result<bool, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).delete_if(key, condition).await
+    }
+    /// Inserts a value, returning the value the key held before. A write
+    /// the offline queue took returns [None], see [ClientConfig::with_offline_queue].
+    pub async fn insert(&self, key: &Key, value: Value) -> Result<Option<Value>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).insert(key, value).await
+    }
+    /// Inserts the values under their keys all at once, returning the
+    /// version of each new record in order.
+    ///
+    /// Either every write lands or none does, and no reader or watcher sees
+    /// some of them without the others, which makes it fit for rolling out
+    /// a set of keys that belong together. On a sharded server every shard
+    /// counts its own versions. Servers before version 33 do not know it.
+    pub async fn insert_all(&self, entries: Vec<(Key, Value)>) -> Result<Vec<u64>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).insert_all(entries).await
+    }
+    /// Inserts any serde type as a [Value::Json], returning the value the
+    /// key held before.
+    pub async fn insert_json<T: Serialize + ?Sized>(&self, key: &Key, value: &T) -> Result<Option<Value>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).insert_json(key, value).await
+    }
+    /// Gets a value written by [Client::insert_json], a key holding any
+    /// other type fails with [NetworkError::ValueConversion].
+    pub async fn get_json<T: DeserializeOwned>(&self, key: &Key) -> Result<Option<T>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).get_json(key).await
+    }
+    pub async fn get_or_insert(&self, key: &Key, default: Value) -> Result<Value, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).get_or_insert(key, default).await
+    }
+    /// Adds the delta to the integer the key holds, a key holding nothing
+    /// counting as zero, and returns the sum.
+    ///
+    /// A key holding anything else is left alone and fails with
+    /// [NetworkError::ValueConversion]. The server may split hot counters
+    /// across sub-keys, their increments are then not ordered.
+    pub async fn increment(&self, key: &Key, delta: i64) -> Result<i64, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).increment(key, delta).await
+    }
+    /// Inserts the value only if the key is still at the version, zero for a
+    /// key that holds nothing, and returns the new version. A key someone
+    /// else wrote in the meantime fails with [NetworkError::VersionConflict].
+    pub async fn insert_if_version(&self, key: &Key, value: &Value, version: u64) -> Result<u64, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).insert_if_version(key, value, version).await
+    }
+    /// Moves the record of a key to another on the server in one step,
+    /// keeping its version and metadata, and returns that version. [None]
+    /// if the key holds nothing. A target holding a record fails with
+    /// [NetworkError::VersionConflict] unless `overwrite` is set.
+    ///
+    /// The watchers of the key see it deleted and those of the target the
+    /// value, without the race of a get, insert and delete.
+    pub async fn rename(&self, from: &Key, to: &Key, overwrite: bool) -> Result<Option<u64>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).rename(from, to, overwrite).await
+    }
+    /// Registers the schema the values written under the prefix must
+    /// conform to, or removes it, returning the schema the prefix had.
+    ///
+    /// The server refuses the writes that do not conform with
+    /// [NetworkError::ValidationFailed], the values already held are kept.
+    pub async fn register_schema(&self, prefix: &str, schema: Option<Schema>) -> Result<Option<Schema>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).register_schema(prefix, schema).await
+    }
+    /// The schemas registered by their prefix, in order.
+    pub async fn schemas(&self) -> Result<Vec<(String, Schema)>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).schemas().await
+    }
+    /// How many keys the server holds, how many bytes their values take,
+    /// how many keys are under each first segment and how large the files
+    /// of the storage are. Servers before version 36 do not know it.
+    pub async fn db_stats(&self) -> Result<DatabaseStats, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).db_stats().await
+    }
+    /// Takes the next number of a named sequence. Unlike [Client::increment]
+    /// on a key, the numbers are unique and increasing across server restarts
+    /// and crashes, though a crash can leave a gap. Servers before version 37
+    /// do not know it.
+    pub async fn next_sequence(&self, name: &str) -> Result<u64, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).next_sequence(name).await
+    }
+    /// Inserts a value and waits until the write reached the acknowledgement
+    /// level, returning the version of the new record.
+    ///
+    /// If the followers do not acknowledge the write in time this fails with
+    /// [ClientError::AcknowledgementTimeout], the write is applied anyway.
+    /// Waiting on followers is experimental, see [Acknowledgement::Replicated].
+    pub async fn insert_acknowledged(&self, key: &Key, value: &Value, acknowledgement: Acknowledgement) -> Result<u64, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).insert_acknowledged(key, value, acknowledgement).await
+    }
+    /// Inserts a value the server deletes once this connection closes,
+    /// returning the version of the new record. Watchers see the delete like
+    /// any other. A key another client wrote over in the meantime is kept.
+    ///
+    /// A client that reconnects has to insert its ephemeral keys again.
+    pub async fn insert_ephemeral(&self, key: &Key, value: &Value) -> Result<u64, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).insert_ephemeral(key, value).await
+    }
+    /// Lists the required keys that are missing or hold the wrong type
+    /// according to the manifest of the server.
+    pub async fn check_manifest(&self) -> Result<Vec<KeyDrift>, NetworkError>
+    {
+        self.connect().await?;
+
+        if let PacketPayload::ManifestReport { drift } = self.send(PacketPayload::CheckManifest).await?.into_payload() {
+            Ok(drift)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Lists the recent compaction runs of the store, oldest first.
+    pub async fn compaction_history(&self) -> Result<Vec<CompactionRun>, NetworkError>
+    {
+        self.connect().await?;
+
+        if let PacketPayload::CompactionReport { runs } = self.send(PacketPayload::CompactionHistory).await?.into_payload() {
+            Ok(runs)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    pub async fn subscribe(&self, key: &Key, activity: WatcherActivity, behaviour: WatcherBehaviour) -> Result<LiveValue, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).subscribe(key, activity, behaviour).await
+    }
+    /// Subscribes to a key written by [Client::insert_json], decoding its
+    /// value into `T`, see [LiveTyped].
+    pub async fn subscribe_typed<T: DeserializeOwned>(&self, key: &Key) -> Result<LiveTyped<T>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).subscribe_typed(key).await
+    }
+    /// Subscribes to a key like [Client::subscribe], the watch is released
+    /// when the [Subscription] is unsubscribed or dropped.
+    pub async fn watch(&self, key: &Key, activity: WatcherActivity, behaviour: WatcherBehaviour) -> Result<Subscription, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).watch(key, activity, behaviour).await
+    }
+    /// Watches a key with at most one notification per interval, for keys
+    /// written far more often than the client needs to hear of them.
+    ///
+    /// The server coalesces the changes made within the interval, an eager
+    /// watch gets the latest value and an ordered one every change in one
+    /// batch. Servers before protocol version 31 notify of every change.
+    pub async fn watch_debounced(&self, key: &Key, activity: WatcherActivity, behaviour: WatcherBehaviour, min_interval: Duration) -> Result<Subscription, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).watch_debounced(key, activity, behaviour, min_interval).await
+    }
+    /// Publishes a message to the subscribers of a topic without storing it,
+    /// returning how many subscribers it was queued for.
+    ///
+    /// Topics are the pub/sub channels of the server, so this is also how
+    /// a message is broadcast on a channel of [Client::subscribe_channel].
+    pub async fn publish(&self, topic: &Key, value: &Value) -> Result<u64, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).publish(topic, value).await
+    }
+    /// Subscribes to the messages published to a topic from now on.
+    pub async fn subscribe_topic(&self, topic: &Key) -> Result<TopicSubscription, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).subscribe_topic(topic).await
+    }
+    /// Subscribes to the messages broadcast on a channel by [Client::publish],
+    /// the same as [Client::subscribe_topic] with the channel as the topic.
+    /// Nothing published on a channel is stored.
+    pub async fn subscribe_channel(&self, channel: &Key) -> Result<TopicSubscription, NetworkError>
+    {
+        self.subscribe_topic(channel).await
+    }
+    /// Ends every subscription of the client to a topic.
+    pub async fn unsubscribe_topic(&self, topic: &Key) -> Result<(), NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).unsubscribe_topic(topic).await
+    }
+    /// Appends an item to a work queue, returning the key it is stored under.
+    ///
+    /// The items are ordinary records under the queue, handed out oldest
+    /// first by [Client::dequeue].
+    pub async fn enqueue(&self, queue: &Key, value: &Value) -> Result<Key, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).enqueue(queue, value).await
+    }
+    /// Leases the oldest item of the queue no other consumer holds, [None]
+    /// if there is none. The item is hidden from the other consumers until
+    /// it is acknowledged or the visibility timeout runs out, then it is
+    /// handed out again.
+    pub async fn dequeue(&self, queue: &Key, visibility: Duration) -> Result<Option<LeasedItem>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).dequeue(queue, visibility).await
+    }
+    /// Waits for an item of the queue and leases it, see [Client::dequeue].
+    ///
+    /// The client stays subscribed to the [queue_topic] of the queue, which
+    /// the server publishes to on every enqueue.
+    pub async fn dequeue_wait(&self, queue: &Key, visibility: Duration) -> Result<LeasedItem, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).dequeue_wait(queue, visibility).await
+    }
+    /// Removes a leased item from its queue once it is processed, returning
+    /// false if the lease ran out and the item went to another consumer.
+    pub async fn ack(&self, item: &LeasedItem) -> Result<bool, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).ack(item).await
+    }
+    /// Gives up the lease on an item so another consumer gets it right away,
+    /// returning false if the item already went to another consumer.
+    pub async fn nack(&self, item: &LeasedItem) -> Result<bool, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).nack(item).await
+    }
+    /// Takes the lock on the key for the time to live, or extends it if this
+    /// client holds it already, returning its token. The token is [None] if
+    /// another client holds the lock.
+    ///
+    /// The server releases the lock once the time to live runs out or the
+    /// connection closes, so a holder renews it well within the time to live.
+    /// Watchers of `$sys.lock.` followed by the key see the id of each new
+    /// holder and nothing once the lock is free, the watchers of the key
+    /// itself are not told. Tokens grow with every acquisition, so they can
+    /// fence off a holder that lost the lock without noticing.
+    pub async fn acquire(&self, key: &Key, ttl: Duration) -> Result<Option<u64>, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).acquire(key, ttl).await
+    }
+    /// Releases a lock this client holds, returning false if it did not hold it.
+    pub async fn release_lock(&self, key: &Key) -> Result<bool, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).release_lock(key).await
+    }
+    /// Sends a packet of a protocol extension, returning the opcode and
+    /// payload of the answer. See [PacketPayload::Extension].
+    ///
+    /// Fails with [ClientError::UnsupportedExtension] if the server has no
+    /// handler for the extension.
+    ///
+    /// # Panics
+    /// If the opcode is not one of the discriminators reserved for extensions.
+    pub async fn extension(&self, opcode: u8, extension: u32, payload: &[u8]) -> Result<(u8, Vec<u8>), NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).extension(opcode, extension, payload).await
+    }
+    /// Watches a key with acknowledged delivery, see [Deliveries]. A fresh
+    /// watch passes zero and starts with the current record, one picking
+    /// up where an earlier process left off passes the last sequence it
+    /// applied and gets every change after it the server still keeps.
+    pub async fn subscribe_acknowledged(&self, key: &Key, after: u64) -> Result<Deliveries, NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).subscribe_acknowledged(key, after).await
+    }
+    /// Loads records in bulk to seed the server, returning how many were
+    /// loaded. The records are sent [IMPORT_CHUNK_SIZE] at a time and each
+    /// chunk is written in one go, the watchers of the keys are not told.
+    pub async fn import<I>(&self, records: I) -> Result<u64, NetworkError>
+    where
+        I: IntoIterator<Item = (Key, Value)>
+    {
+        self.namespace(DEFAULT_NAMESPACE).import(records).await
+    }
+    /// Loads the `key = value` lines of a seed file in bulk, see [Client::import].
+    pub async fn import_from_reader<R>(&self, reader: R) -> Result<u64, NetworkError>
+    where
+        R: AsyncBufRead + Unpin
+    {
+        self.namespace(DEFAULT_NAMESPACE).import_from_reader(reader).await
+    }
+    /// Writes every record of the default namespace to the writer as a
+    /// dump, see [DumpWriter], returning how many records it holds.
+    ///
+    /// The records are fetched [EXPORT_PAGE_SIZE] at a time in the order of
+    /// their keys, the ones written while the export runs may or may not
+    /// make it in.
+    pub async fn export<W>(&self, writer: &mut W) -> Result<u64, NetworkError>
+    where
+        W: AsyncWrite + Unpin
+    {
+        self.namespace(DEFAULT_NAMESPACE).export(writer).await
+    }
+    /// Loads the records of a dump written by [Client::export], see
+    /// [Client::import]. The importing server stamps the records afresh,
+    /// the metadata in the dump is not carried over.
+    pub async fn import_dump<R>(&self, reader: &mut R) -> Result<u64, NetworkError>
+    where
+        R: AsyncRead + Unpin
+    {
+        self.namespace(DEFAULT_NAMESPACE).import_dump(reader).await
+    }
+    /// A cache of the default namespace that keeps the keys read through it
+    /// current by watching them, see [CachedClient].
+    pub fn cached(&self) -> CachedClient<'_>
+    {
+        CachedClient::new(self, DEFAULT_NAMESPACE.to_string())
+    }
+    /// Subscribes to a key and fetches its current value atomically.
+    ///
+    /// The live value starts out holding the value at the returned version, and
+    /// every update after it comes from a later change, so there is no window
+    /// between reading and watching. Use an ordered watcher to see every change.
+    pub async fn subscribe_snapshot(&self, key: &Key, behaviour: WatcherBehaviour) -> Result<(LiveValue, u64), NetworkError>
+    {
+        self.namespace(DEFAULT_NAMESPACE).subscribe_snapshot(key, behaviour).await
+    }
+}
+
+/// A view of the client scoped to one namespace of the server.
+pub struct Namespace<'a> {
+    client: &'a Client,
+    name: String
+}
+
+impl<'a> Namespace<'a> {
+    /// The name of the namespace.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub async fn get(&self, key: &Key) -> Result<Option<Value>, NetworkError>
+    {
+        if let PacketPayload::Return { value, .. } = self.client.read_in(&self.name, || PacketPayload::get(key)).await?.payload() {
+            return Ok(value.as_deref().cloned());
+        } else {
+            return Err(NetworkError::Client(ClientError::WrongResponseFromServer));
+        }
+    }
+    /// Gets a value and the version of its record, see [Client::get_versioned].
+    ///
+    /// Reads go to the primary, a replica counts versions of its own.
+    pub async fn get_versioned(&self, key: &Key) -> Result<(Option<Value>, u64), NetworkError>
+    {
+        if let PacketPayload::Return { value, version, .. } = self.client.request_in(&self.name, || PacketPayload::get(key)).await?.into_payload() {
+            Ok((value.map(|f| f.into_owned()), version))
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Lists every key of the namespace in order.
+    pub fn keys(&self) -> Keys<'a> {
+        Keys {
+            namespace: Namespace { client: self.client, name: self.name.clone() },
+            filter: None,
+            page: vec![],
+            cursor: None,
+            done: false
+        }
+    }
+    /// Lists the keys of the namespace matching the filter, see [Client::find_keys].
+    pub fn find_keys(&self, filter: KeyFilter) -> Keys<'a> {
+        Keys { filter: Some(filter), ..self.keys() }
+    }
+    /// Fetches a page of the keys matching the filter, like [Namespace::list_keys].
+    /// The page can be empty and still have a cursor, as the server looks
+    /// at a bounded number of keys for each.
+    pub async fn find_keys_page(&self, filter: &KeyFilter, cursor: Option<&Key>, limit: u32) -> Result<(Vec<Key>, Option<Key>), NetworkError>
+    {
+        match self.client.read_in(&self.name, || PacketPayload::find_keys(filter.clone(), cursor, limit)).await?.into_payload() {
+            PacketPayload::KeyPage { keys, cursor } => Ok((keys, cursor)),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Fetches up to `limit` keys after the cursor, along with the cursor
+    /// that continues after them.
+    pub async fn list_keys(&self, cursor: Option<&Key>, limit: u32) -> Result<(Vec<Key>, Option<Key>), NetworkError>
+    {
+        if let PacketPayload::KeyPage { keys, cursor } = self.client.read_in(&self.name, || PacketPayload::list_keys(cursor, limit)).await?.into_payload() {
+            Ok((keys, cursor))
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Gets the values of keys of the namespace at a single point in time,
+    /// see [Client::snapshot_get].
+    pub async fn snapshot_get(&self, keys: &[Key]) -> Result<Vec<Option<Value>>, NetworkError>
+    {
+        Ok(self.snapshot_get_versioned(keys).await?.into_iter().map(|(value, _)| value).collect())
+    }
+    /// Gets the values of the keys at a single point in time along with the
+    /// version of each record, zero if the key holds nothing.
+    pub async fn snapshot_get_versioned(&self, keys: &[Key]) -> Result<Vec<(Option<Value>, u64)>, NetworkError>
+    {
+        if let PacketPayload::SnapshotValues { values } = self.client.read_in(&self.name, || PacketPayload::snapshot_get(keys)).await?.into_payload() {
+            Ok(values)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// The children of a key of the namespace, see [Client::children].
+    pub async fn children(&self, key: &Key) -> Result<Vec<KeyChild>, NetworkError>
+    {
+        let (mut children, mut cursor) = self.list_children(key, None, KEY_PAGE_SIZE).await?;
+        while let Some(after) = cursor {
+            let (page, next) = self.list_children(key, Some(&after), KEY_PAGE_SIZE).await?;
+            children.extend(page);
+            cursor = next;
+        }
+        Ok(children)
+    }
+    /// Fetches up to `limit` children of the key after the cursor, along
+    /// with the cursor that continues after them.
+    pub async fn list_children(&self, key: &Key, cursor: Option<&Key>, limit: u32) -> Result<(Vec<KeyChild>, Option<Key>), NetworkError>
+    {
+        if let PacketPayload::Children { children, cursor } = self.client.read_in(&self.name, || PacketPayload::list_children(key, cursor, limit)).await?.into_payload() {
+            Ok((children, cursor))
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// The watched keys of the namespace, see [Client::watcher_info].
+    pub async fn watcher_info(&self) -> Result<Vec<WatchedKey>, NetworkError>
+    {
+        self.list_watchers(None).await
+    }
+    /// How many clients watch the key of the namespace.
+    pub async fn watcher_count(&self, key: &Key) -> Result<u64, NetworkError>
+    {
+        Ok(self.list_watchers(Some(key)).await?.first().map(|f| f.clients).unwrap_or_default())
+    }
+    async fn list_watchers(&self, key: Option<&Key>) -> Result<Vec<WatchedKey>, NetworkError>
+    {
+        if let PacketPayload::WatcherReport { watched } = self.client.read_in(&self.name, || PacketPayload::list_watchers(key)).await?.into_payload() {
+            Ok(watched)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Fetches up to `limit` records after the cursor with their metadata,
+    /// along with the cursor that continues after them.
+    pub async fn export_page(&self, cursor: Option<&Key>, limit: u32) -> Result<(Vec<ExportedRecord>, Option<Key>), NetworkError>
+    {
+        if let PacketPayload::ExportPage { records, cursor } = self.client.read_in(&self.name, || PacketPayload::export(cursor, limit)).await?.into_payload() {
+            Ok((records, cursor))
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Writes every record of the namespace to the writer, see [Client::export].
+    pub async fn export<W>(&self, writer: &mut W) -> Result<u64, NetworkError>
+    where
+        W: AsyncWrite + Unpin
+    {
+        let mut dump = DumpWriter::new(writer).await?;
+        let mut cursor = None;
+        loop {
+            let (records, next) = self.export_page(cursor.as_ref(), EXPORT_PAGE_SIZE).await?;
+            for record in &records {
+                dump.write(record).await?;
+            }
+            cursor = match next {
+                Some(next) => Some(next),
+                None => break
+            };
+        }
+        let count = dump.finish().await?;
+        writer.flush().await?;
+        Ok(count)
+    }
+    /// What the key held at a revision, [None] if it had no value then.
+    ///
+    /// The server only keeps the recent changes of each key and those made
+    /// since it started, older revisions fail with [ClientError::RevisionUnavailable].
+    pub async fn get_at(&self, key: &Key, at: Revision) -> Result<Option<Value>, NetworkError>
+    {
+        match self.client.read_in(&self.name, || PacketPayload::GetAt { key: Cow::Borrowed(key), at }).await?.into_payload() {
+            PacketPayload::ValueAt { value, available: true } => Ok(value),
+            PacketPayload::ValueAt { available: false, .. } => Err(NetworkError::Client(ClientError::RevisionUnavailable)),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// What the key held at two revisions and how it changed in between.
+    ///
+    /// The server only keeps the recent changes of each key, older
+    /// revisions fail with [ClientError::RevisionUnavailable].
+    pub async fn diff(&self, key: &Key, from: Revision, to: Revision) -> Result<KeyDiff, NetworkError>
+    {
+        let request = || PacketPayload::KeyHistory { key: Cow::Borrowed(key), from, to };
+        if let PacketPayload::KeyDiff { diff } = self.client.read_in(&self.name, request).await?.into_payload() {
+            diff.ok_or(NetworkError::Client(ClientError::RevisionUnavailable))
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Every kept change to a key of the namespace, see [Client::history].
+    pub async fn history(&self, key: &Key) -> Result<Vec<HistoryEntry>, NetworkError>
+    {
+        self.recent_history(key, 0).await
+    }
+    /// The last kept changes to a key of the namespace, see [Client::recent_history].
+    pub async fn recent_history(&self, key: &Key, limit: u32) -> Result<Vec<HistoryEntry>, NetworkError>
+    {
+        if let PacketPayload::HistoryReport { entries } = self.client.read_in(&self.name, || PacketPayload::history(key, limit)).await?.into_payload() {
+            Ok(entries)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Whether the key exists and the durability class its writes get.
+    pub async fn meta(&self, key: &Key) -> Result<KeyMeta, NetworkError>
+    {
+        let request = || PacketPayload::GetMeta { key: Cow::Borrowed(key) };
+        if let PacketPayload::KeyMeta { meta, .. } = self.client.read_in(&self.name, request).await?.into_payload() {
+            Ok(meta)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Loads records into the namespace in bulk, see [Client::import].
+    pub async fn import<I>(&self, records: I) -> Result<u64, NetworkError>
+    where
+        I: IntoIterator<Item = (Key, Value)>
+    {
+        let mut records = records.into_iter().peekable();
+        let mut loaded = 0;
+        while records.peek().is_some() {
+            let chunk: Vec<(Key, Value)> = records.by_ref().take(IMPORT_CHUNK_SIZE).collect();
+            loaded += self.import_chunk(chunk).await?;
+        }
+        Ok(loaded)
+    }
+    /// Loads the records of a seed file into the namespace, see [Client::import_from_reader].
+    pub async fn import_from_reader<R>(&self, reader: R) -> Result<u64, NetworkError>
+    where
+        R: AsyncBufRead + Unpin
+    {
+        let mut lines = reader.lines();
+        let (mut chunk, mut loaded, mut number) = (vec![], 0, 0);
+        while let Some(line) = lines.next_line().await? {
+            number += 1;
+            chunk.extend(parse_seed_line(number, &line)?);
+            if chunk.len() == IMPORT_CHUNK_SIZE {
+                loaded += self.import_chunk(std::mem::take(&mut chunk)).await?;
+            }
+        }
+        if !chunk.is_empty() {
+            loaded += self.import_chunk(chunk).await?;
+        }
+        Ok(loaded)
+    }
+    /// Loads the records of a dump into the namespace, see [Client::import_dump].
+    pub async fn import_dump<R>(&self, reader: &mut R) -> Result<u64, NetworkError>
+    where
+        R: AsyncRead + Unpin
+    {
+        let mut dump = DumpReader::new(reader).await?;
+        let (mut chunk, mut loaded) = (vec![], 0);
+        while let Some(record) = dump.next().await? {
+            chunk.push((record.key, record.value));
+            if chunk.len() == IMPORT_CHUNK_SIZE {
+                loaded += self.import_chunk(std::mem::take(&mut chunk)).await?;
+            }
+        }
+        if !chunk.is_empty() {
+            loaded += self.import_chunk(chunk).await?;
+        }
+        Ok(loaded)
+    }
+    async fn import_chunk(&self, records: Vec<(Key, Value)>) -> Result<u64, NetworkError> {
+        match self.client.request_in(&self.name, || PacketPayload::Import { records: records.clone() }).await?.payload() {
+            PacketPayload::Imported { count } => Ok(*count),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// The keys unused since the time, see [Client::archive].
+    pub async fn archive(&self, before: u64, delete: bool) -> Result<Vec<(Key, Value)>, NetworkError>
+    {
+        match self.client.request_in(&self.name, || PacketPayload::Archive { before, delete }).await?.into_payload() {
+            PacketPayload::Archived { records } => Ok(records),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// The value of the key along with its metadata, see [Client::get_with_meta].
+    pub async fn get_with_meta(&self, key: &Key) -> Result<(Option<Value>, KeyMeta), NetworkError>
+    {
+        let request = || PacketPayload::GetMeta { key: Cow::Borrowed(key) };
+        if let PacketPayload::KeyMeta { value, meta, .. } = self.client.read_in(&self.name, request).await?.into_payload() {
+            Ok((value.map(|f| f.into_owned()), meta))
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// The keys whose map value holds the value at the field path, in order.
+    /// This is [None] if the server keeps no index on the field.
+    pub async fn query_by_index(&self, field: &str, value: &Value) -> Result<Option<Vec<Key>>, NetworkError>
+    {
+        let request = || PacketPayload::query_by_index(field, value);
+        if let PacketPayload::IndexMatches { keys } = self.client.read_in(&self.name, request).await?.into_payload() {
+            Ok(keys)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    pub async fn delete(&self, key: &Key) -> Result<(), NetworkError>
+    {
+        let Some(response) = self.client.write_in(&self.name, key, None).await? else {
+            // Queued until the server can be reached.
+            return Ok(());
+        };
+        if let PacketPayload::Get { .. } = response.payload() {
+            return Ok(());
+        } else {
+            return Err(NetworkError::Client(ClientError::WrongResponseFromServer));
+        }
+    }
+    /// Deletes the key of the namespace if it holds what the condition
+    /// expects, see [Client::delete_if].
+    pub async fn delete_if(&self, key: &Key, condition: DeleteCondition) -> Result<bool, NetworkError>
+    {
+        match self.client.request_in(&self.name, || PacketPayload::delete_if(key, condition.clone())).await?.payload() {
+            PacketPayload::DeleteIfResult { deleted } => Ok(*deleted),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Adds to the integer of a key in the namespace, see [Client::increment].
+    pub async fn increment(&self, key: &Key, delta: i64) -> Result<i64, NetworkError>
+    {
+        match self.client.request_in(&self.name, || PacketPayload::increment(key, delta)).await?.payload() {
+            PacketPayload::Return { value: Some(value), .. } => Ok(value.as_integer()?),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Inserts a serde type into the namespace, see [Client::insert_json].
+    pub async fn insert_json<T: Serialize + ?Sized>(&self, key: &Key, value: &T) -> Result<Option<Value>, NetworkError>
+    {
+        self.insert(key, Value::from_serde(value)?).await
+    }
+    /// Gets a serde type from the namespace, see [Client::get_json].
+    pub async fn get_json<T: DeserializeOwned>(&self, key: &Key) -> Result<Option<T>, NetworkError>
+    {
+        Ok(self.get(key).await?.map(|f| f.to_serde()).transpose()?)
+    }
+    /// Inserts the values under their keys of the namespace all at once,
+    /// see [Client::insert_all].
+    pub async fn insert_all(&self, entries: Vec<(Key, Value)>) -> Result<Vec<u64>, NetworkError>
+    {
+        match self.client.request_in(&self.name, || PacketPayload::MultiInsert { entries: entries.clone() }).await?.into_payload() {
+            PacketPayload::MultiInserted { versions } => Ok(versions),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    pub async fn insert(&self, key: &Key, value: Value) -> Result<Option<Value>, NetworkError>
+    {
+        let Some(response) = self.client.write_in(&self.name, key, Some(&value)).await? else {
+            return Ok(None);
+        };
+        if let PacketPayload::Return { value, .. } = response.payload() {
+            return Ok(value.as_deref().cloned());
+        } else {
+            return Err(NetworkError::Client(ClientError::WrongResponseFromServer));
+        }
+    }
+    /// Returns the value of the key, the default is inserted first if the key
+    /// holds nothing. Concurrent callers all get the value of whichever
+    /// inserted first.
+    pub async fn get_or_insert(&self, key: &Key, default: Value) -> Result<Value, NetworkError>
+    {
+        let request = || PacketPayload::get_or_insert(key, &default);
+        if let PacketPayload::Return { value: Some(value), .. } = self.client.request_in(&self.name, request).await?.into_payload() {
+            Ok(value.into_owned())
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Publishes a message to a topic of the namespace, see [Client::publish].
+    pub async fn publish(&self, topic: &Key, value: &Value) -> Result<u64, NetworkError>
+    {
+        if let PacketPayload::Published { receivers } = self.client.request_in(&self.name, || PacketPayload::publish(topic, value)).await?.payload() {
+            Ok(*receivers)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Subscribes to a topic of the namespace, see [Client::subscribe_topic].
+    pub async fn subscribe_topic(&self, topic: &Key) -> Result<TopicSubscription, NetworkError>
+    {
+        self.client.connect().await?;
+
+        let subscribed = (self.name.clone(), topic.clone());
+        let messages = self.client.inner.topics
+            .entry(subscribed.clone())
+            .or_insert_with(|| broadcast::channel(TOPIC_CAPACITY).0)
+            .subscribe();
+        if let PacketPayload::Get { .. } = self.client.send_in(&self.name, PacketPayload::subscribe_topic(topic)).await?.payload() {
+            Ok(TopicSubscription { messages })
+        } else {
+            self.client.inner.topics.remove(&subscribed);
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Subscribes to a channel of the namespace, see [Client::subscribe_channel].
+    pub async fn subscribe_channel(&self, channel: &Key) -> Result<TopicSubscription, NetworkError>
+    {
+        self.subscribe_topic(channel).await
+    }
+    /// Unsubscribes from a topic of the namespace, see [Client::unsubscribe_topic].
+    pub async fn unsubscribe_topic(&self, topic: &Key) -> Result<(), NetworkError>
+    {
+        self.client.inner.topics.remove(&(self.name.clone(), topic.clone()));
+        if let PacketPayload::Get { .. } = self.client.request_in(&self.name, || PacketPayload::unsubscribe_topic(topic)).await?.payload() {
+            Ok(())
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Inserts a value at an acknowledgement level, see [Client::insert_acknowledged].
+    pub async fn insert_acknowledged(&self, key: &Key, value: &Value, acknowledgement: Acknowledgement) -> Result<u64, NetworkError>
+    {
+        match self.client.request_in(&self.name, || PacketPayload::insert_acknowledged(key, value, acknowledgement)).await?.payload() {
+            PacketPayload::Return { version, .. } => Ok(*version),
+            PacketPayload::AcknowledgementTimeout { version, .. } => Err(NetworkError::Client(ClientError::AcknowledgementTimeout(*version))),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Inserts a value bound to the connection, see [Client::insert_ephemeral].
+    pub async fn insert_ephemeral(&self, key: &Key, value: &Value) -> Result<u64, NetworkError>
+    {
+        match self.client.request_in(&self.name, || PacketPayload::insert_ephemeral(key, value)).await?.payload() {
+            PacketPayload::Return { version, .. } => Ok(*version),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Inserts a value if the key is still at the version, see [Client::insert_if_version].
+    pub async fn insert_if_version(&self, key: &Key, value: &Value, version: u64) -> Result<u64, NetworkError>
+    {
+        match self.client.request_in(&self.name, || PacketPayload::insert_if_version(key, value, version)).await?.payload() {
+            PacketPayload::Return { version, .. } => Ok(*version),
+            PacketPayload::VersionConflict { version, .. } => Err(NetworkError::VersionConflict(*version)),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Moves the record of a key of the namespace, see [Client::rename].
+    pub async fn rename(&self, from: &Key, to: &Key, overwrite: bool) -> Result<Option<u64>, NetworkError>
+    {
+        match self.client.request_in(&self.name, || PacketPayload::rename(from, to, overwrite)).await?.payload() {
+            PacketPayload::Return { version: 0, .. } => Ok(None),
+            PacketPayload::Return { version, .. } => Ok(Some(*version)),
+            PacketPayload::VersionConflict { version, .. } => Err(NetworkError::VersionConflict(*version)),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Registers a schema with the namespace, see [Client::register_schema].
+    pub async fn register_schema(&self, prefix: &str, schema: Option<Schema>) -> Result<Option<Schema>, NetworkError>
+    {
+        match self.client.request_in(&self.name, || PacketPayload::register_schema(prefix, schema.clone())).await?.into_payload() {
+            PacketPayload::SchemaRegistered { previous } => Ok(previous),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// The schemas registered with the namespace, see [Client::schemas].
+    pub async fn schemas(&self) -> Result<Vec<(String, Schema)>, NetworkError>
+    {
+        match self.client.request_in(&self.name, || PacketPayload::ListSchemas).await?.into_payload() {
+            PacketPayload::Schemas { schemas } => Ok(schemas),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// The figures of the database serving the namespace, see [Client::db_stats].
+    pub async fn db_stats(&self) -> Result<DatabaseStats, NetworkError>
+    {
+        match self.client.read_in(&self.name, || PacketPayload::GetStats).await?.into_payload() {
+            PacketPayload::Stats { stats } => Ok(stats),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Takes the next number of a sequence of the namespace, see [Client::next_sequence].
+    pub async fn next_sequence(&self, name: &str) -> Result<u64, NetworkError>
+    {
+        match self.client.request_in(&self.name, || PacketPayload::NextSequence { name: Cow::Owned(name.to_string()) }).await?.into_payload() {
+            PacketPayload::Sequence { value } => Ok(value),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Appends an item to a work queue of the namespace, see [Client::enqueue].
+    pub async fn enqueue(&self, queue: &Key, value: &Value) -> Result<Key, NetworkError>
+    {
+        if let PacketPayload::Return { key, .. } = self.client.request_in(&self.name, || PacketPayload::enqueue(queue, value)).await?.into_payload() {
+            Ok(key.into_owned())
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Leases an item of a work queue of the namespace, see [Client::dequeue].
+    pub async fn dequeue(&self, queue: &Key, visibility: Duration) -> Result<Option<LeasedItem>, NetworkError>
+    {
+        if let PacketPayload::Leased { item } = self.client.request_in(&self.name, || PacketPayload::dequeue(queue, visibility)).await?.into_payload() {
+            Ok(item)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Waits for an item of a work queue of the namespace, see [Client::dequeue_wait].
+    pub async fn dequeue_wait(&self, queue: &Key, visibility: Duration) -> Result<LeasedItem, NetworkError>
+    {
+        // Subscribing first, so an item enqueued after an empty dequeue is announced.
+        let topic = queue_topic(queue);
+        let mut enqueued = self.subscribe_topic(&topic).await?;
+        loop {
+            if let Some(item) = self.dequeue(queue, visibility).await? {
+                return Ok(item);
+            }
+            tokio::select! {
+                message = enqueued.recv() => if message.is_none() {
+                    enqueued = self.subscribe_topic(&topic).await?;
+                },
+                _ = tokio::time::sleep(QUEUE_POLL_INTERVAL) => {}
+            }
+        }
+    }
+    /// Acknowledges an item of a work queue of the namespace, see [Client::ack].
+    pub async fn ack(&self, item: &LeasedItem) -> Result<bool, NetworkError>
+    {
+        if let PacketPayload::LeaseResult { accepted } = self.client.request_in(&self.name, || PacketPayload::ack(&item.key, item.lease)).await?.payload() {
+            Ok(*accepted)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Gives up the lease on an item of a work queue of the namespace, see [Client::nack].
+    pub async fn nack(&self, item: &LeasedItem) -> Result<bool, NetworkError>
+    {
+        if let PacketPayload::LeaseResult { accepted } = self.client.request_in(&self.name, || PacketPayload::nack(&item.key, item.lease)).await?.payload() {
+            Ok(*accepted)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Sends a packet of a protocol extension to the namespace, see [Client::extension].
+    pub async fn extension(&self, opcode: u8, extension: u32, payload: &[u8]) -> Result<(u8, Vec<u8>), NetworkError>
+    {
+        match self.client.request_in(&self.name, || PacketPayload::extension(opcode, extension, payload.to_vec())).await?.into_payload() {
+            PacketPayload::Extension { opcode, payload, .. } => Ok((opcode, payload.into_owned())),
+            PacketPayload::UnsupportedExtension { extension } => Err(NetworkError::Client(ClientError::UnsupportedExtension(extension))),
+            _ => Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Takes or extends a lock in the namespace, see [Client::acquire].
+    pub async fn acquire(&self, key: &Key, ttl: Duration) -> Result<Option<u64>, NetworkError>
+    {
+        if let PacketPayload::LockResult { token } = self.client.request_in(&self.name, || PacketPayload::acquire(key, ttl)).await?.payload() {
+            Ok(*token)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    /// Releases a lock in the namespace, see [Client::release_lock].
+    pub async fn release_lock(&self, key: &Key) -> Result<bool, NetworkError>
+    {
+        if let PacketPayload::LeaseResult { accepted } = self.client.request_in(&self.name, || PacketPayload::release_lock(key)).await?.payload() {
+            Ok(*accepted)
+        } else {
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+    pub async fn subscribe(&self, key: &Key, activity: WatcherActivity, behaviour: WatcherBehaviour) -> Result<LiveValue, NetworkError>
+    {
+        self.subscribe_with(key, activity, behaviour, None).await
+    }
+    async fn subscribe_with(&self, key: &Key, activity: WatcherActivity, behaviour: WatcherBehaviour, min_interval: Option<Duration>) -> Result<LiveValue, NetworkError>
+    {
+        self.client.connect().await?;
+
+        let inner = LiveValue {
+            value: Arc::new(LiveValueInternal {
+                value: Mutex::default(),
+                notify: Notify::new(),
+                missed: AtomicU64::new(0),
+                closed: AtomicBool::new(false),
+                trace: AtomicU64::new(0),
+                last_seen: std::sync::Mutex::default(),
+                behaviour,
+                min_interval
+            })
+        };
+
+        self.client.inner.watched.insert((self.name.clone(), key.borrow().clone()), inner.clone());
+        if let PacketPayload::Get { .. } = self.client.send_in(&self.name, PacketPayload::watch(key, activity, behaviour).with_min_interval(min_interval)).await?.payload() {
+            return Ok(inner);
+        } else {
+            return Err(NetworkError::Client(ClientError::WrongResponseFromServer));
+        }
+    }
+    /// Subscribes to a key of the namespace decoding its value, see [Client::subscribe_typed].
+    pub async fn subscribe_typed<T: DeserializeOwned>(&self, key: &Key) -> Result<LiveTyped<T>, NetworkError>
+    {
+        let value = self.subscribe(key, WatcherActivity::Kickback, WatcherBehaviour::Ordered).await?;
+        Ok(LiveTyped { value, last: None })
+    }
+    /// A cache of the namespace, see [Client::cached].
+    pub fn cached(&self) -> CachedClient<'a>
+    {
+        CachedClient::new(self.client, self.name.clone())
+    }
+    /// Watches a key and fetches its current value atomically, the watch is
+    /// released with the handle.
+    pub(crate) async fn watch_snapshot(&self, key: &Key, behaviour: WatcherBehaviour) -> Result<(Subscription, u64), NetworkError>
+    {
+        let (value, version) = self.subscribe_snapshot(key, behaviour).await?;
+        let subscription = Subscription {
+            inner: Arc::clone(&self.client.inner),
+            namespace: self.name.clone(),
+            key: key.clone(),
+            value,
+            released: false
+        };
+        Ok((subscription, version))
+    }
+    /// Watches a key of the namespace until the handle is released, see [Client::watch].
+    pub async fn watch(&self, key: &Key, activity: WatcherActivity, behaviour: WatcherBehaviour) -> Result<Subscription, NetworkError>
+    {
+        let value = self.subscribe(key, activity, behaviour).await?;
+        Ok(Subscription {
+            inner: Arc::clone(&self.client.inner),
+            namespace: self.name.clone(),
+            key: key.clone(),
+            value,
+            released: false
+        })
+    }
+    /// Watches a key of the namespace with at most one notification per
+    /// interval, see [Client::watch_debounced].
+    pub async fn watch_debounced(&self, key: &Key, activity: WatcherActivity, behaviour: WatcherBehaviour, min_interval: Duration) -> Result<Subscription, NetworkError>
+    {
+        let value = self.subscribe_with(key, activity, behaviour, Some(min_interval)).await?;
+        Ok(Subscription {
+            inner: Arc::clone(&self.client.inner),
+            namespace: self.name.clone(),
+            key: key.clone(),
+            value,
+            released: false
+        })
+    }
+    /// Watches a key of the namespace with acknowledged delivery, see
+    /// [Client::subscribe_acknowledged].
+    pub async fn subscribe_acknowledged(&self, key: &Key, after: u64) -> Result<Deliveries, NetworkError>
+    {
+        self.client.connect().await?;
+
+        let (sender, changes) = mpsc::unbounded_channel();
+        let state = Arc::new(DeliveryState { changes: sender, received: AtomicU64::new(after), acked: AtomicU64::new(after) });
+        let watched = (self.name.clone(), key.clone());
+        self.client.inner.deliveries.insert(watched.clone(), Arc::clone(&state));
+        match self.client.send_in(&self.name, PacketPayload::WatchAcknowledged { key: Cow::Borrowed(key), after }).await?.payload() {
+            PacketPayload::Get { .. } | PacketPayload::Snapshot { .. } => Ok(Deliveries {
+                inner: Arc::clone(&self.client.inner),
+                namespace: self.name.clone(),
+                key: key.clone(),
+                state,
+                changes
+            }),
+            _ => {
+                self.client.inner.deliveries.remove(&watched);
+                Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+            }
+        }
+    }
+    /// Subscribes to a key and fetches its current value atomically, see
+    /// [Client::subscribe_snapshot].
+    pub async fn subscribe_snapshot(&self, key: &Key, behaviour: WatcherBehaviour) -> Result<(LiveValue, u64), NetworkError>
+    {
+        self.client.connect().await?;
+
+        let inner = LiveValue {
+            value: Arc::new(LiveValueInternal {
+                value: Mutex::default(),
+                notify: Notify::new(),
+                missed: AtomicU64::new(0),
+                closed: AtomicBool::new(false),
+                trace: AtomicU64::new(0),
+                last_seen: std::sync::Mutex::default(),
+                behaviour,
+                min_interval: None
+            })
+        };
+
+        let watched = (self.name.clone(), key.clone());
+        self.client.inner.watched.insert(watched.clone(), inner.clone());
+        if let PacketPayload::Snapshot { version, .. } = self.client.send_in(&self.name, PacketPayload::watch_snapshot(key, behaviour)).await?.payload() {
+            Ok((inner, *version))
+        } else {
+            self.client.inner.watched.remove(&watched);
+            Err(NetworkError::Client(ClientError::WrongResponseFromServer))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex}, time::Duration};
+
+    use overseer::{access::{IsolationReason, WatcherActivity, WatcherBehaviour}, error::{ClientError, ErrorClass, ErrorCode, NetworkError}, models::{Acknowledgement, Durability, HistoryEntry, Key, KeyMeta, LeasedItem, RecordMeta, Value}, network::{queue_topic, OverseerSerde, Packet, PacketId, PacketPayload, FIRST_REQUEST_ID, LAST_REQUEST_ID}};
+    use serde::{Deserialize, Serialize};
+    use tokio::net::{TcpListener, UnixListener};
+
+    use crate::{Client, ClientConfig, Interceptor, OfflineQueueConfig};
+
+
+    #[tokio::test]
+    pub async fn test_request_id_wraparound() {
+        let client = Client::new("127.0.0.1:0").await.unwrap();
+        client.inner.counter.store(LAST_REQUEST_ID, Ordering::Release);
+
+        let (sdr, _rcv) = tokio::sync::oneshot::channel();
+        assert_eq!(client.inner.allocate(sdr).unwrap().id(), LAST_REQUEST_ID);
+        let (sdr, _rcv) = tokio::sync::oneshot::channel();
+        assert_eq!(client.inner.allocate(sdr).unwrap().id(), FIRST_REQUEST_ID);
+    }
+
+    #[tokio::test]
+    pub async fn test_unresolved_address() {
+        let nowhere: &[std::net::SocketAddr] = &[];
+        assert!(matches!(Client::new(nowhere).await, Err(NetworkError::Client(ClientError::SocketError))));
+    }
+
+    #[tokio::test]
+    pub async fn test_request_id_skips_in_flight() {
+        let client = Client::new("127.0.0.1:0").await.unwrap();
+
+        let (sdr, _rcv_a) = tokio::sync::oneshot::channel();
+        let first = client.inner.allocate(sdr).unwrap();
+
+        // Rewind so the next candidate collides with the in-flight request.
+        client.inner.counter.store(first.id(), Ordering::Release);
+        let (sdr, _rcv_b) = tokio::sync::oneshot::channel();
+        let second = client.inner.allocate(sdr).unwrap();
+        assert_ne!(first.id(), second.id());
+        assert_eq!(second.id(), first.id() + 1);
+
+        // Once a response is consumed the id becomes available again.
+        client.inner.channels.remove(&first.id());
+        client.inner.counter.store(first.id(), Ordering::Release);
+        let (sdr, _rcv_c) = tokio::sync::oneshot::channel();
+        assert_eq!(client.inner.allocate(sdr).unwrap().id(), first.id());
+    }
+
+    #[tokio::test]
+    pub async fn test_rejected_token() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Auth { token } = packet.payload() else {
+                panic!("The first packet was not an authentication packet.");
+            };
+            assert_eq!(token, "secret");
+            Packet::auth_result(packet.id(), false).serialize(&mut socket).await.unwrap();
+        };
+
+        let client = Client::with_config(address, ClientConfig::default().with_token("secret")).await.unwrap();
+        let key = Key::from_str("hello");
+        let (_, result) = tokio::join!(server, client.get(&key));
+        assert!(matches!(result, Err(NetworkError::Client(ClientError::Unauthorized))));
+    }
+
+    #[tokio::test]
+    pub async fn test_server_closing() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            Packet::deserialize(&mut socket).await.unwrap();
+            Packet::new(PacketId::zero(), PacketPayload::ServerClosing).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("hello");
+        let (_socket, result) = tokio::join!(server, client.get(&key));
+        assert!(matches!(result, Err(NetworkError::Client(ClientError::ConnectionClosed))));
+        assert!(client.inner.write.lock().await.is_none());
+    }
+
+    #[tokio::test]
+    pub async fn test_server_busy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            Packet::new(PacketId::zero(), PacketPayload::ServerBusy).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("hello");
+        let (_socket, result) = tokio::join!(server, client.get(&key));
+        // The request fails whether it went out before the notice or after,
+        // when reconnecting is refused.
+        assert!(result.is_err());
+        assert!(client.inner.write.lock().await.is_none());
+    }
+
+    #[tokio::test]
+    pub async fn test_throttled() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            assert!(matches!(packet.payload(), PacketPayload::Insert { .. }));
+            let retry_after = Duration::from_millis(250);
+            Packet::new(packet.id(), PacketPayload::Throttled { retry_after }).serialize(&mut socket).await.unwrap();
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("stats.hits");
+        let (_, result) = tokio::join!(server, client.insert(&key, Value::Integer(1)));
+        assert!(matches!(result, Err(NetworkError::Client(ClientError::Throttled(retry_after))) if retry_after == Duration::from_millis(250)));
+    }
+
+    #[tokio::test]
+    pub async fn test_server_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let failed = PacketPayload::Error { code: ErrorCode(302), message: Cow::Borrowed("The key is at version 4, not the one the write expected") };
+            Packet::new(packet.id(), failed).serialize(&mut socket).await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let denied = NetworkError::from(ClientError::Unauthorized);
+            Packet::error(packet.id(), &denied).serialize(&mut socket).await.unwrap();
+
+            // The connection is still served after the error.
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let value = Value::Integer(3);
+            Packet::vreturn(packet.id(), &Key::from_str("app.size"), Some(&value), 1).serialize(&mut socket).await.unwrap();
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("app.size");
+        let requests = async {
+            let failed = client.insert(&key, Value::Integer(3)).await;
+            let denied = client.delete(&key).await;
+            (failed, denied, client.get(&key).await)
+        };
+        let (_, (failed, denied, value)) = tokio::join!(server, requests);
+        assert!(matches!(failed, Err(NetworkError::Client(ClientError::Server(code, _))) if code.class() == ErrorClass::Request));
+        // A code that names its error alone comes back as that error.
+        assert!(matches!(denied, Err(NetworkError::Client(ClientError::Unauthorized))));
+        assert_eq!(value.unwrap(), Some(Value::Integer(3)));
+    }
+
+    #[tokio::test]
+    pub async fn test_acknowledged_delivery() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::WatchAcknowledged { key, after: 0 } = packet.payload() else {
+                panic!("Expected a fresh acknowledged watch.");
+            };
+            let key = (**key).clone();
+            let value = Value::Integer(5);
+            Packet::snapshot(packet.id(), &key, Some(&value), 5).serialize(&mut socket).await.unwrap();
+
+            // A change sent again after a rewind is handed out once.
+            for sequence in [6, 6, 7] {
+                let value = Value::Integer(sequence as i64);
+                let delivery = PacketPayload::Delivery { key: Cow::Borrowed(&key), value: Some(Cow::Borrowed(&value)), sequence, gap: false };
+                Packet::new(PacketId::zero(), delivery).serialize(&mut socket).await.unwrap();
+            }
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            assert!(packet.id().is_notification());
+            assert!(matches!(packet.payload(), PacketPayload::AckDelivery { sequence: 7, .. }));
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let consumer = async {
+            let mut deliveries = client.subscribe_acknowledged(&Key::from_str("orders.last"), 0).await.unwrap();
+            let mut received = vec![];
+            for _ in 0..3 {
+                let delivered = deliveries.next().await.unwrap();
+                received.push((delivered.sequence, delivered.gap));
+            }
+            deliveries.ack(7).await.unwrap();
+            assert_eq!(deliveries.acknowledged(), 7);
+            received
+        };
+        let (_, received) = tokio::join!(server, consumer);
+        assert_eq!(received, vec![(5, true), (6, false), (7, false)]);
+    }
+
+    #[tokio::test]
+    pub async fn test_subscription_release() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            for _ in 0..2 {
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::Watch { key, .. } = packet.payload() else {
+                    panic!("Expected a watch packet.");
+                };
+                Packet::get(packet.id(), key).serialize(&mut socket).await.unwrap();
+            }
+            // Unsubscribing and dropping both release the watch.
+            let mut released = vec![];
+            for _ in 0..2 {
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::Release { key } = packet.payload() else {
+                    panic!("Expected a release packet.");
+                };
+                released.push(key.as_str().to_string());
+                Packet::get(packet.id(), key).serialize(&mut socket).await.unwrap();
+            }
+            released
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let consumer = async {
+            let (size, mode) = (Key::from_str("app.size"), Key::from_str("app.mode"));
+            let first = client.watch(&size, WatcherActivity::Kickback, WatcherBehaviour::Ordered).await.unwrap();
+            let second = client.watch(&mode, WatcherActivity::Kickback, WatcherBehaviour::Ordered).await.unwrap();
+            let value = first.value().clone();
+            first.unsubscribe().await.unwrap();
+            assert!(value.is_closed());
+            assert!(!client.inner.watched.contains_key(&("default".to_string(), size)));
+            drop(second);
+            assert!(!client.inner.watched.contains_key(&("default".to_string(), mode)));
+        };
+        let (released, _) = tokio::join!(server, consumer);
+        assert_eq!(released, vec!["app.size", "app.mode"]);
+    }
+
+    #[tokio::test]
+    pub async fn test_offline_queue() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+
+        let config = ClientConfig::default().with_offline_queue(OfflineQueueConfig::new(2));
+        let client = Client::with_config(address, config).await.unwrap();
+        let (mode, size) = (Key::from_str("app.mode"), Key::from_str("app.size"));
+        assert_eq!(client.insert(&mode, Value::Integer(1)).await.unwrap(), None);
+        client.delete(&size).await.unwrap();
+        assert!(matches!(client.insert(&mode, Value::Integer(2)).await, Err(NetworkError::Client(ClientError::OfflineQueueFull(2)))));
+        assert_eq!(client.queued_writes().await.unwrap(), 2);
+
+        // The queued writes go out in order ahead of the next one.
+        let listener = TcpListener::bind(address).await.unwrap();
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut written = vec![];
+            for _ in 0..3 {
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                match packet.payload() {
+                    PacketPayload::Insert { key, value } => {
+                        written.push((key.as_str().to_string(), Some(value.clone().into_owned())));
+                        Packet::vreturn(packet.id(), key, None, 1).serialize(&mut socket).await.unwrap();
+                    }
+                    PacketPayload::Delete { key } => {
+                        written.push((key.as_str().to_string(), None));
+                        Packet::get(packet.id(), key).serialize(&mut socket).await.unwrap();
+                    }
+                    _ => panic!("Expected a write.")
+                }
+            }
+            (socket, written)
+        };
+        let ((_socket, written), inserted) = tokio::join!(server, client.insert(&mode, Value::Integer(3)));
+        inserted.unwrap();
+        assert_eq!(written, vec![
+            ("app.mode".to_string(), Some(Value::Integer(1))),
+            ("app.size".to_string(), None),
+            ("app.mode".to_string(), Some(Value::Integer(3)))
+        ]);
+        assert_eq!(client.queued_writes().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    pub async fn test_keepalive_pings() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            assert!(matches!(packet.payload(), PacketPayload::Ping));
+            assert!(packet.id().is_notification());
+        };
+
+        let config = ClientConfig::default().with_keepalive(Duration::from_millis(10));
+        let client = Client::with_config(address, config).await.unwrap();
+        let (_, connected) = tokio::join!(server, client.connect());
+        connected.unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_invalidate_refetches() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let value = Value::Integer(1);
+            Packet::snapshot(packet.id(), &Key::from_str("app.size"), Some(&value), 1).serialize(&mut socket).await.unwrap();
+            Packet::new(PacketId::zero(), PacketPayload::invalidate("app.")).serialize(&mut socket).await.unwrap();
+
+            // The client fetches the watched key again.
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Get { key } = packet.payload() else {
+                panic!("Expected a get packet.");
+            };
+            assert_eq!(key.as_str(), "app.size");
+            let value = Value::Integer(9);
+            Packet::vreturn(packet.id(), key, Some(&value), 1).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let mut invalidations = client.invalidations();
+        let key = Key::from_str("app.size");
+        let (_socket, result) = tokio::join!(server, client.subscribe_snapshot(&key, WatcherBehaviour::Ordered));
+        let (live, _) = result.unwrap();
+
+        assert_eq!(invalidations.recv().await.unwrap(), "app.");
+        while live.get().await != Some(Value::Integer(9)) {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_watch_overflow() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let key = Key::from_str("app.size");
+            let value = Value::Integer(1);
+            Packet::snapshot(packet.id(), &key, Some(&value), 1).serialize(&mut socket).await.unwrap();
+            let overflow = PacketPayload::WatchOverflow { key: Cow::Borrowed(&key), missed: 5, closed: false };
+            Packet::new(PacketId::zero(), overflow).serialize(&mut socket).await.unwrap();
+
+            // The client fetches the key again to catch up.
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Get { key } = packet.payload() else {
+                panic!("Expected a get packet.");
+            };
+            let value = Value::Integer(9);
+            Packet::vreturn(packet.id(), key, Some(&value), 1).serialize(&mut socket).await.unwrap();
+
+            let overflow = PacketPayload::WatchOverflow { key: Cow::Borrowed(key), missed: 2, closed: true };
+            Packet::new(PacketId::zero(), overflow).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("app.size");
+        let (_socket, result) = tokio::join!(server, client.subscribe_snapshot(&key, WatcherBehaviour::Ordered));
+        let (live, _) = result.unwrap();
+
+        while !live.is_closed() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(live.get().await, Some(Value::Integer(9)));
+        assert_eq!(live.missed(), 7);
+    }
+
+    #[tokio::test]
+    pub async fn test_watch_isolated() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let key = Key::from_str("app.feed");
+            Packet::snapshot(packet.id(), &key, None, 1).serialize(&mut socket).await.unwrap();
+            let isolated = PacketPayload::WatchIsolated { key: Cow::Borrowed(&key), reason: IsolationReason::DeliveryFailed };
+            Packet::new(PacketId::zero(), isolated).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("app.feed");
+        let (_socket, result) = tokio::join!(server, client.subscribe_snapshot(&key, WatcherBehaviour::Ordered));
+        let (live, _) = result.unwrap();
+
+        while !live.is_closed() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(live.missed(), 0);
+    }
+
+    #[tokio::test]
+    pub async fn test_failover() {
+        // Nothing listens on the primary.
+        let primary = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dead = primary.local_addr().unwrap();
+        drop(primary);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            assert!(matches!(packet.payload(), PacketPayload::Ping));
+            Packet::new(packet.id(), PacketPayload::Pong).serialize(&mut socket).await.unwrap();
+
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Get { key } = packet.payload() else {
+                panic!("Expected a get packet.");
+            };
+            let value = Value::Integer(4);
+            Packet::vreturn(packet.id(), key, Some(&value), 1).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::with_endpoints([dead, address], ClientConfig::default()).await.unwrap();
+        let key = Key::from_str("app.size");
+        let (_socket, value) = tokio::join!(server, client.get(&key));
+        assert_eq!(value.unwrap(), Some(Value::Integer(4)));
+        assert_eq!(client.active_address(), address.to_string());
+
+        let health = client.health_check().await;
+        assert!(health[0].primary && health[0].latency.is_none());
+    }
+
+    #[tokio::test]
+    pub async fn test_failover_resubscribes() {
+        let first = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addresses = [first.local_addr().unwrap(), second.local_addr().unwrap()];
+
+        /// Answers the health check and the watch with the value.
+        async fn accept_watch(listener: &TcpListener, value: i64) -> tokio::net::TcpStream {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            Packet::new(packet.id(), PacketPayload::Pong).serialize(&mut socket).await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::WatchSnapshot { key, .. } = packet.payload() else {
+                panic!("Expected a watch snapshot packet.");
+            };
+            let value = Value::Integer(value);
+            Packet::snapshot(packet.id(), key, Some(&value), 1).serialize(&mut socket).await.unwrap();
+            socket
+        }
+
+        let client = Client::with_endpoints(addresses, ClientConfig::default()).await.unwrap();
+        let key = Key::from_str("app.size");
+        let (socket, result) = tokio::join!(accept_watch(&first, 1), client.subscribe_snapshot(&key, WatcherBehaviour::Ordered));
+        let (live, _) = result.unwrap();
+        assert_eq!(live.get().await, Some(Value::Integer(1)));
+
+        // The primary goes away.
+        drop(socket);
+        drop(first);
+
+        let server = async {
+            let mut socket = accept_watch(&second, 2).await;
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Delete { .. } = packet.payload() else {
+                panic!("Expected a delete packet.");
+            };
+            Packet::get(packet.id(), &key).serialize(&mut socket).await.unwrap();
+            socket
+        };
+        let other = Key::from_str("app.other");
+        let (_socket, result) = tokio::join!(server, client.delete(&other));
+        result.unwrap();
+        assert_eq!(live.get().await, Some(Value::Integer(2)));
+        assert!(!live.is_closed());
+    }
+
+    #[tokio::test]
+    pub async fn test_session_resumption() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = Client::with_config(address, ClientConfig::default().with_session_resumption()).await.unwrap();
+        let key = Key::from_str("app.size");
+
+        let server = async {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Resume { session: None } = packet.payload() else {
+                panic!("Expected a resume packet.");
+            };
+            Packet::session(packet.id(), 7, false).serialize(&mut socket).await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::WatchSnapshot { key, .. } = packet.payload() else {
+                panic!("Expected a watch snapshot packet.");
+            };
+            Packet::snapshot(packet.id(), key, Some(&Value::Integer(1)), 1).serialize(&mut socket).await.unwrap();
+            socket
+        };
+        let (_socket, result) = tokio::join!(server, client.subscribe_snapshot(&key, WatcherBehaviour::Ordered));
+        let (live, _) = result.unwrap();
+        client.reset_connection().await.unwrap();
+
+        let server = async {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Resume { session: Some(7) } = packet.payload() else {
+                panic!("Expected the session to be resumed.");
+            };
+            Packet::session(packet.id(), 7, true).serialize(&mut socket).await.unwrap();
+            // The watch is not sent again, the server still holds it.
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Delete { .. } = packet.payload() else {
+                panic!("Expected a delete packet.");
+            };
+            // What changed while the client was away.
+            let value = Value::Integer(2);
+            Packet::new(PacketId::zero(), PacketPayload::notify(&key, Some(&value), false)).serialize(&mut socket).await.unwrap();
+            Packet::get(packet.id(), &key).serialize(&mut socket).await.unwrap();
+            socket
+        };
+        let other = Key::from_str("app.other");
+        let (_socket, result) = tokio::join!(server, client.delete(&other));
+        result.unwrap();
+        assert_eq!(live.get().await, Some(Value::Integer(2)));
+        assert!(!live.is_closed());
+    }
+
+    #[tokio::test]
+    pub async fn test_subscribe_snapshot() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::WatchSnapshot { key, .. } = packet.payload() else {
+                panic!("Expected a watch snapshot packet.");
+            };
+            let (old, new) = (Value::Integer(1), Value::Integer(2));
+            Packet::snapshot(packet.id(), key, Some(&old), 5).serialize(&mut socket).await.unwrap();
+            Packet::notify(PacketId::zero(), key, Some(&new), false).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("hello");
+        let (_socket, result) = tokio::join!(server, client.subscribe_snapshot(&key, WatcherBehaviour::Ordered));
+        let (live, version) = result.unwrap();
+        assert_eq!(version, 5);
+
+        // The notification must win over the snapshot it follows.
+        while live.get().await != Some(Value::Integer(2)) {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_namespace_scoping() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            assert_eq!(packet.namespace(), "tenant-a");
+            let PacketPayload::Get { key } = packet.payload() else {
+                panic!("Expected a get packet.");
+            };
+            let value = Value::Integer(3);
+            Packet::vreturn(packet.id(), key, Some(&value), 1).with_namespace("tenant-a").serialize(&mut socket).await.unwrap();
+
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            assert_eq!(packet.namespace(), "tenant-b");
+            Packet::new(packet.id(), PacketPayload::UnknownNamespace).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("hello");
+        let requests = async {
+            let found = client.namespace("tenant-a").get(&key).await;
+            let missing = client.namespace("tenant-b").get(&key).await;
+            (found, missing)
+        };
+        let (_socket, (found, missing)) = tokio::join!(server, requests);
+        assert_eq!(found.unwrap(), Some(Value::Integer(3)));
+        assert!(matches!(missing, Err(NetworkError::UnknownNamespace)));
+    }
+
+    #[tokio::test]
+    pub async fn test_keys_pagination() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            assert!(matches!(packet.payload(), PacketPayload::ListKeys { cursor: None, .. }));
+            let keys = vec![Key::from_str("a"), Key::from_str("b")];
+            let page = PacketPayload::KeyPage { keys, cursor: Some(Key::from_str("b")) };
+            Packet::new(packet.id(), page).serialize(&mut socket).await.unwrap();
+
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::ListKeys { cursor: Some(cursor), .. } = packet.payload() else {
+                panic!("Expected a list keys packet with a cursor.");
+            };
+            assert_eq!(cursor.as_str(), "b");
+            let page = PacketPayload::KeyPage { keys: vec![Key::from_str("c")], cursor: None };
+            Packet::new(packet.id(), page).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let listing = async {
+            let mut keys = client.keys();
+            let mut listed = vec![];
+            while let Some(key) = keys.next().await.unwrap() {
+                listed.push(key.as_str().to_string());
+            }
+            listed
+        };
+        let (_socket, listed) = tokio::join!(server, listing);
+        assert_eq!(listed, vec!["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    pub async fn test_query_by_index() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            for keys in [Some(vec![Key::from_str("host.a")]), None] {
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::QueryByIndex { field, value } = packet.payload() else {
+                    panic!("Expected a query by index packet.");
+                };
+                assert_eq!(field, "region");
+                assert_eq!(**value, Value::String("eu".into()));
+                Packet::new(packet.id(), PacketPayload::IndexMatches { keys }).serialize(&mut socket).await.unwrap();
+            }
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let region = Value::String("eu".into());
+        let queries = async {
+            let found = client.query_by_index("region", &region).await.unwrap();
+            let unindexed = client.query_by_index("region", &region).await.unwrap();
+            (found, unindexed)
+        };
+        let (_socket, (found, unindexed)) = tokio::join!(server, queries);
+        assert_eq!(found, Some(vec![Key::from_str("host.a")]));
+        assert_eq!(unindexed, None);
+    }
+
+    #[tokio::test]
+    pub async fn test_get_or_insert() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::GetOrInsert { key, default } = packet.payload() else {
+                panic!("Expected a get or insert packet.");
+            };
+            assert_eq!(**default, Value::Integer(1));
+            // Another client inserted first.
+            Packet::vreturn(packet.id(), key, Some(&Value::Integer(7)), 1).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("app.pool");
+        let (_socket, value) = tokio::join!(server, client.get_or_insert(&key, Value::Integer(1)));
+        assert_eq!(value.unwrap(), Value::Integer(7));
+    }
+
+    #[tokio::test]
+    pub async fn test_insert_if_version() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Get { key } = packet.payload() else {
+                panic!("Expected a get packet.");
+            };
+            Packet::vreturn(packet.id(), key, Some(&Value::Integer(1)), 5).serialize(&mut socket).await.unwrap();
+            for current in [5, 9] {
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::InsertIfVersion { key, value, version } = packet.payload() else {
+                    panic!("Expected an insert if version packet.");
+                };
+                assert_eq!(*version, 5);
+                if current == *version {
+                    Packet::vreturn(packet.id(), key, Some(value), 9).serialize(&mut socket).await.unwrap();
+                } else {
+                    // Another agent wrote the key first.
+                    Packet::new(packet.id(), PacketPayload::VersionConflict { key: key.clone(), version: current }).serialize(&mut socket).await.unwrap();
+                }
+            }
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("app.pool");
+        let writes = async {
+            let (value, version) = client.get_versioned(&key).await.unwrap();
+            assert_eq!(value, Some(Value::Integer(1)));
+            let first = client.insert_if_version(&key, &Value::Integer(2), version).await;
+            let second = client.insert_if_version(&key, &Value::Integer(3), version).await;
+            (first, second)
+        };
+        let (_socket, (first, second)) = tokio::join!(server, writes);
+        assert_eq!(first.unwrap(), 9);
+        assert!(matches!(second, Err(NetworkError::VersionConflict(9))));
+    }
+
+    #[tokio::test]
+    pub async fn test_topics() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let topic = Key::from_str("deploy.started");
+
+        let server = {
+            let topic = topic.clone();
+            async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::SubscribeTopic { topic: subscribed } = packet.payload() else {
+                    panic!("Expected a subscribe topic packet.");
+                };
+                assert_eq!(**subscribed, topic);
+                Packet::get(packet.id(), &topic).serialize(&mut socket).await.unwrap();
+
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::Publish { value, .. } = packet.payload() else {
+                    panic!("Expected a publish packet.");
+                };
+                let message = Packet::new(PacketId::zero(), PacketPayload::topic_message(&topic, value));
+                message.serialize(&mut socket).await.unwrap();
+                Packet::new(packet.id(), PacketPayload::Published { receivers: 1 }).serialize(&mut socket).await.unwrap();
+
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                assert!(matches!(packet.payload(), PacketPayload::UnsubscribeTopic { .. }));
+                Packet::get(packet.id(), &topic).serialize(&mut socket).await.unwrap();
+                socket
+            }
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let messages = async {
+            let mut subscription = client.subscribe_topic(&topic).await.unwrap();
+            let receivers = client.publish(&topic, &Value::String("api".into())).await.unwrap();
+            let message = subscription.recv().await;
+            client.unsubscribe_topic(&topic).await.unwrap();
+            (receivers, message, subscription.recv().await)
+        };
+        let (_socket, (receivers, message, after)) = tokio::join!(server, messages);
+        assert_eq!(receivers, 1);
+        assert_eq!(message, Some(Value::String("api".into())));
+        // Unsubscribing ends the subscription.
+        assert_eq!(after, None);
+    }
+
+    #[tokio::test]
+    pub async fn test_archive() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let records = vec![(Key::from_str("legacy.mode"), Value::Integer(1))];
+
+        let server = {
+            let records = records.clone();
+            async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::Archive { before: 1_700_000_000_000, delete: true } = packet.payload() else {
+                    panic!("Expected an archive packet.");
+                };
+                Packet::new(packet.id(), PacketPayload::Archived { records }).serialize(&mut socket).await.unwrap();
+                socket
+            }
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let (_socket, archived) = tokio::join!(server, client.archive(1_700_000_000_000, true));
+        assert_eq!(archived.unwrap(), records);
+    }
+
+    #[tokio::test]
+    pub async fn test_insert_ephemeral() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::InsertEphemeral { key, value } = packet.payload() else {
+                panic!("Expected an insert ephemeral packet.");
+            };
+            Packet::vreturn(packet.id(), key, Some(value), 3).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("presence.agent-1");
+        let value = Value::String("10.0.0.7:4000".into());
+        let (_socket, version) = tokio::join!(server, client.insert_ephemeral(&key, &value));
+        assert_eq!(version.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    pub async fn test_increment() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut total = 0;
+            for _ in 0..2 {
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::Increment { key, delta } = packet.payload() else {
+                    panic!("Expected an increment packet.");
+                };
+                total += delta;
+                Packet::vreturn(packet.id(), key, Some(&Value::Integer(total)), 1).serialize(&mut socket).await.unwrap();
+            }
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            Packet::vreturn(packet.id(), &Key::from_str("app.mode"), Some(&Value::String("on".into())), 1).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("stats.hits");
+        let requests = async {
+            let first = client.increment(&key, 5).await.unwrap();
+            let second = client.increment(&key, -2).await.unwrap();
+            (first, second, client.increment(&Key::from_str("app.mode"), 1).await)
+        };
+        let (_socket, (first, second, mismatched)) = tokio::join!(server, requests);
+        assert_eq!((first, second), (5, 3));
+        assert!(matches!(mismatched, Err(NetworkError::ValueConversion(..))));
+    }
+
+    #[tokio::test]
+    pub async fn test_subscribe_typed() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Endpoint {
+            host: String,
+            port: u16
+        }
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (step, mut steps) = tokio::sync::mpsc::unbounded_channel::<Value>();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Watch { key, .. } = packet.payload() else {
+                panic!("Expected a watch packet.");
+            };
+            let key = (**key).clone();
+            Packet::get(packet.id(), &key).serialize(&mut socket).await.unwrap();
+            while let Some(value) = steps.recv().await {
+                Packet::notify(PacketId::zero(), &key, Some(&value), false).serialize(&mut socket).await.unwrap();
+            }
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let consumer = async {
+            let mut typed = client.subscribe_typed::<Endpoint>(&Key::from_str("app.endpoint")).await.unwrap();
+            assert!(matches!(typed.get().await, Ok(None)));
+
+            step.send(Value::from_serde(&Endpoint { host: "edge".into(), port: 80 }).unwrap()).unwrap();
+            while typed.get().await.unwrap().is_none() {
+                tokio::task::yield_now().await;
+            }
+            // A value that does not decode is an error, the last good one stays.
+            step.send(Value::Integer(80)).unwrap();
+            while typed.get().await.is_ok() {
+                tokio::task::yield_now().await;
+            }
+            assert!(matches!(typed.get().await, Err(NetworkError::ValueConversion(..))));
+            assert_eq!(typed.last(), Some(&Endpoint { host: "edge".into(), port: 80 }));
+            drop(step);
+        };
+        let _ = tokio::join!(server, consumer);
+    }
+
+    #[tokio::test]
+    pub async fn test_json_values() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Endpoint {
+            host: String,
+            port: u16
+        }
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Insert { key, value } = packet.payload() else {
+                panic!("Expected an insert packet.");
+            };
+            let stored = value.clone().into_owned();
+            assert_eq!(stored, Value::Json(r#"{"host":"edge","port":80}"#.into()));
+            Packet::vreturn(packet.id(), key, None, 1).serialize(&mut socket).await.unwrap();
+
+            for value in [stored, Value::Integer(80)] {
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::Get { key } = packet.payload() else {
+                    panic!("Expected a get packet.");
+                };
+                Packet::vreturn(packet.id(), key, Some(&value), 1).serialize(&mut socket).await.unwrap();
+            }
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("app.endpoint");
+        let endpoint = Endpoint { host: "edge".into(), port: 80 };
+        let requests = async {
+            client.insert_json(&key, &endpoint).await.unwrap();
+            let decoded = client.get_json::<Endpoint>(&key).await.unwrap();
+            let mismatched = client.get_json::<Endpoint>(&key).await;
+            (decoded, mismatched)
+        };
+        let (_socket, (decoded, mismatched)) = tokio::join!(server, requests);
+        assert_eq!(decoded, Some(endpoint));
+        assert!(matches!(mismatched, Err(NetworkError::ValueConversion(..))));
+    }
+
+    #[tokio::test]
+    pub async fn test_extension() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Extension { opcode: 230, extension: 41, payload } = packet.payload() else {
+                panic!("Expected an extension packet.");
+            };
+            let reversed = payload.iter().rev().copied().collect();
+            Packet::new(packet.id(), PacketPayload::extension(231, 41, reversed)).serialize(&mut socket).await.unwrap();
+
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            Packet::new(packet.id(), PacketPayload::UnsupportedExtension { extension: 42 }).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let requests = async {
+            let answer = client.extension(230, 41, &[1, 2, 3]).await.unwrap();
+            let unsupported = client.extension(230, 42, &[]).await;
+            (answer, unsupported)
+        };
+        let (_socket, (answer, unsupported)) = tokio::join!(server, requests);
+        assert_eq!(answer, (231, vec![3, 2, 1]));
+        assert!(matches!(unsupported, Err(NetworkError::Client(ClientError::UnsupportedExtension(42)))));
+    }
+
+    #[tokio::test]
+    pub async fn test_acquire_and_release_lock() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Acquire { ttl, .. } = packet.payload() else {
+                panic!("Expected an acquire packet.");
+            };
+            assert_eq!(*ttl, Duration::from_secs(10));
+            Packet::new(packet.id(), PacketPayload::LockResult { token: Some(4) }).serialize(&mut socket).await.unwrap();
+
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::ReleaseLock { key } = packet.payload() else {
+                panic!("Expected a release lock packet.");
+            };
+            assert_eq!(key.as_str(), "leader");
+            Packet::new(packet.id(), PacketPayload::LeaseResult { accepted: true }).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("leader");
+        let (_socket, released) = tokio::join!(server, async {
+            assert_eq!(client.acquire(&key, Duration::from_secs(10)).await.unwrap(), Some(4));
+            client.release_lock(&key).await
+        });
+        assert!(released.unwrap());
+    }
+
+    #[tokio::test]
+    pub async fn test_get_with_meta() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let record = RecordMeta { created_at: 1_700_000_000_000, modified_at: 1_700_000_300_000, accessed_at: 1_700_000_400_000, writer: Some(7) };
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::GetMeta { key } = packet.payload() else {
+                panic!("Expected a get meta packet.");
+            };
+            let meta = KeyMeta { exists: true, durability: Durability::Periodic, record: Some(record) };
+            let value = Value::String("on".into());
+            Packet::new(packet.id(), PacketPayload::KeyMeta { key: key.clone(), value: Some(Cow::Owned(value)), meta })
+                .serialize(&mut socket)
+                .await
+                .unwrap();
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("app.mode");
+        let (_socket, described) = tokio::join!(server, client.get_with_meta(&key));
+        let (value, meta) = described.unwrap();
+        assert_eq!(value, Some(Value::String("on".into())));
+        assert_eq!(meta.record, Some(record));
+    }
+
+    #[tokio::test]
+    pub async fn test_insert_acknowledged() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            for version in [4, 5] {
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::InsertAcknowledged { key, value, acknowledgement } = packet.payload() else {
+                    panic!("Expected an acknowledged insert packet.");
+                };
+                let response = match acknowledgement {
+                    Acknowledgement::Synced => Packet::vreturn(packet.id(), key, Some(value), version),
+                    _ => Packet::new(packet.id(), PacketPayload::AcknowledgementTimeout { key: key.clone(), version })
+                };
+                response.serialize(&mut socket).await.unwrap();
+            }
+            socket
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("app.mode");
+        let writes = async {
+            let synced = client.insert_acknowledged(&key, &Value::Integer(1), Acknowledgement::Synced).await;
+            let replicated = client.insert_acknowledged(&key, &Value::Integer(2), Acknowledgement::Replicated(2)).await;
+            (synced, replicated)
+        };
+        let (_socket, (synced, replicated)) = tokio::join!(server, writes);
+        assert_eq!(synced.unwrap(), 4);
+        assert!(matches!(replicated, Err(NetworkError::Client(ClientError::AcknowledgementTimeout(5)))));
+    }
+
+    #[tokio::test]
+    pub async fn test_history() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let entries = vec![
+            HistoryEntry { value: Some(Value::Integer(1)), version: 2, at: 1_700_000_000_000 },
+            HistoryEntry { value: None, version: 6, at: 1_700_000_001_000 }
+        ];
+
+        let server = {
+            let entries = entries.clone();
+            async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                for limit in [0, 1] {
+                    let packet = Packet::deserialize(&mut socket).await.unwrap();
+                    let PacketPayload::History { key, limit: asked } = packet.payload() else {
+                        panic!("Expected a history packet.");
+                    };
+                    assert_eq!((key.as_str(), *asked), ("app.mode", limit));
+                    let entries = match limit {
+                        0 => entries.clone(),
+                        limit => entries[entries.len() - limit as usize..].to_vec()
+                    };
+                    Packet::new(packet.id(), PacketPayload::HistoryReport { entries }).serialize(&mut socket).await.unwrap();
+                }
+                socket
+            }
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let key = Key::from_str("app.mode");
+        let requests = async {
+            (client.history(&key).await.unwrap(), client.recent_history(&key, 1).await.unwrap())
+        };
+        let (_socket, (all, recent)) = tokio::join!(server, requests);
+        assert_eq!(all, entries);
+        assert_eq!(recent, entries[1..]);
+    }
+
+    #[tokio::test]
+    pub async fn test_work_queue() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let queue = Key::from_str("jobs");
+        let item = LeasedItem { key: Key::from_str("jobs.00000000000000000000"), value: Value::Integer(7), lease: 3 };
+
+        let server = {
+            let (queue, item) = (queue.clone(), item.clone());
+            async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let topic = queue_topic(&queue);
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::SubscribeTopic { topic: subscribed } = packet.payload() else {
+                    panic!("Expected a subscribe topic packet.");
+                };
+                assert_eq!(**subscribed, Key::from_str("$sys.queue.jobs"));
+                Packet::get(packet.id(), &topic).serialize(&mut socket).await.unwrap();
+
+                // The queue is empty until a producer enqueues.
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::Dequeue { visibility, .. } = packet.payload() else {
+                    panic!("Expected a dequeue packet.");
+                };
+                assert_eq!(*visibility, Duration::from_secs(30));
+                Packet::new(packet.id(), PacketPayload::Leased { item: None }).serialize(&mut socket).await.unwrap();
+                let announcement = Value::String(item.key.as_str().into());
+                Packet::new(PacketId::zero(), PacketPayload::topic_message(&topic, &announcement)).serialize(&mut socket).await.unwrap();
+
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                assert!(matches!(packet.payload(), PacketPayload::Dequeue { .. }));
+                Packet::new(packet.id(), PacketPayload::Leased { item: Some(item.clone()) }).serialize(&mut socket).await.unwrap();
+
+                let packet = Packet::deserialize(&mut socket).await.unwrap();
+                let PacketPayload::Ack { key, lease } = packet.payload() else {
+                    panic!("Expected an ack packet.");
+                };
+                assert_eq!((&**key, *lease), (&item.key, item.lease));
+                Packet::new(packet.id(), PacketPayload::LeaseResult { accepted: true }).serialize(&mut socket).await.unwrap();
+                socket
+            }
+        };
+
+        let client = Client::new(address).await.unwrap();
+        let consumer = async {
+            let leased = client.dequeue_wait(&queue, Duration::from_secs(30)).await.unwrap();
+            (client.ack(&leased).await.unwrap(), leased)
+        };
+        let (_socket, (accepted, leased)) = tokio::join!(server, consumer);
+        assert_eq!(leased, item);
+        assert!(accepted);
+    }
+
+    struct Tagging(u64);
+
+    impl Interceptor for Tagging {
+        fn before_send(&self, packet: &mut Packet<'_>) {
+            packet.set_trace(Some(self.0));
+        }
+    }
+
+    #[derive(Default)]
+    struct Recording {
+        sent: Mutex<Vec<Option<u64>>>,
+        received: AtomicUsize
+    }
+
+    impl Interceptor for Arc<Recording> {
+        fn before_send(&self, packet: &mut Packet<'_>) {
+            self.sent.lock().unwrap().push(packet.trace());
+        }
+        fn after_receive(&self, _: &Packet<'_>) {
+            self.received.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_interceptors() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            assert_eq!(packet.trace(), Some(7));
+            let PacketPayload::Get { key } = packet.payload() else {
+                panic!("Expected a get packet.");
+            };
+            Packet::vreturn(packet.id(), key, Some(&Value::Integer(1)), 1).serialize(&mut socket).await.unwrap();
+            socket
+        };
+
+        // The interceptors run in the order they were added.
+        let recording = Arc::new(Recording::default());
+        let config = ClientConfig::default().with_interceptor(Tagging(7)).with_interceptor(Arc::clone(&recording));
+        let client = Client::with_config(address, config).await.unwrap();
+        let key = Key::from_str("hello");
+        let (_socket, value) = tokio::join!(server, client.get(&key));
+        assert_eq!(value.unwrap(), Some(Value::Integer(1)));
+        assert_eq!(*recording.sent.lock().unwrap(), [Some(7)]);
+        assert_eq!(recording.received.load(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    pub async fn test_unix_socket_transport() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("overseer.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let packet = Packet::deserialize(&mut socket).await.unwrap();
+            let PacketPayload::Get { key } = packet.payload() else {
+                panic!("Expected a get packet.");
+            };
+            let value = Value::Integer(7);
+            Packet::vreturn(packet.id(), key, Some(&value), 1).serialize(&mut socket).await.unwrap();
+        };
+
+        let client = Client::new_unix(&path).await.unwrap();
+        let key = Key::from_str("hello");
+        let (_, result) = tokio::join!(server, client.get(&key));
+        assert_eq!(result.unwrap(), Some(Value::Integer(7)));
+    }
+}
@@ -0,0 +1,82 @@
+use std::{sync::Arc, time::Duration};
+
+
+/// How long a server has to answer the health check when the client picks
+/// between several addresses.
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The options a [super::Client] is created with.
+#[derive(Default, Clone)]
+pub struct ClientConfig {
+    /// The token sent to authenticate every new connection.
+    pub token: Option<String>,
+    /// How often to ping the server so it does not reap the connection.
+    pub keepalive: Option<Duration>,
+    /// Sends reads to the other addresses of the client, writes stay on the primary.
+    pub replica_reads: bool,
+    /// How long a server has to answer the health check, [HEALTH_CHECK_TIMEOUT] if this is not set.
+    pub health_timeout: Option<Duration>,
+    /// Resumes the session of a lost connection when reconnecting to the same server.
+    pub resume_sessions: bool,
+    /// Queues the inserts and deletes made while the server cannot be reached.
+    pub offline_queue: Option<super::OfflineQueueConfig>,
+    /// Called with every packet sent and received, in order.
+    pub interceptors: Vec<Arc<dyn super::Interceptor>>,
+    /// Speaks TLS to the server when set.
+    #[cfg(feature = "tls")]
+    pub tls: Option<super::ClientTlsConfig>
+}
+
+impl ClientConfig {
+    /// Authenticates with the token whenever the client connects.
+    pub fn with_token<S>(mut self, token: S) -> Self
+    where 
+        S: Into<String>
+    {
+        self.token = Some(token.into());
+        self
+    }
+    /// Pings the server at this period while connected.
+    pub fn with_keepalive(mut self, period: Duration) -> Self {
+        self.keepalive = Some(period);
+        self
+    }
+    /// Reads from the first healthy replica when the client was created with
+    /// several addresses, falling back to the primary.
+    pub fn with_replica_reads(mut self) -> Self {
+        self.replica_reads = true;
+        self
+    }
+    pub fn with_health_timeout(mut self, timeout: Duration) -> Self {
+        self.health_timeout = Some(timeout);
+        self
+    }
+    /// Asks the server for a session on every connection and resumes it
+    /// after reconnecting, so the watches, locks and ephemeral keys survive
+    /// a brief disconnect. The server has to speak protocol version 13.
+    pub fn with_session_resumption(mut self) -> Self {
+        self.resume_sessions = true;
+        self
+    }
+    /// Queues the inserts and deletes that fail for want of a connection and
+    /// replays them in order once the client connects again, see
+    /// [super::OfflineQueueConfig].
+    pub fn with_offline_queue(mut self, queue: super::OfflineQueueConfig) -> Self {
+        self.offline_queue = Some(queue);
+        self
+    }
+    /// Adds an interceptor after those already registered, see [super::Interceptor].
+    pub fn with_interceptor<I>(mut self, interceptor: I) -> Self
+    where 
+        I: super::Interceptor + 'static
+    {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+    /// Connects to the server over TLS.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, tls: super::ClientTlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+}
@@ -0,0 +1,18 @@
+use overseer::network::Packet;
+
+
+/// Sees every packet a [super::Client] sends and receives, to add headers,
+/// count requests or break them on purpose without forking the client.
+///
+/// Interceptors are registered with [super::ClientConfig::with_interceptor]
+/// and called in the order they were added. They run on the path of every
+/// packet, so they must not block.
+pub trait Interceptor: Send + Sync {
+    /// Called with every packet before it is sent, the pings and the
+    /// requests the client makes itself included. Changes to the packet
+    /// are sent along with it.
+    fn before_send(&self, _packet: &mut Packet<'_>) {}
+    /// Called with every packet the server sends, the notifications
+    /// included, before the client acts on it.
+    fn after_receive(&self, _packet: &Packet<'_>) {}
+}
@@ -1,3 +1,19 @@
+mod cache;
 mod client;
+mod config;
+mod interceptor;
+mod offline;
+mod pool;
+mod trace;
+#[cfg(feature = "tls")]
+mod tls;
 
-pub use crate::connector::client::*;
\ No newline at end of file
+pub use crate::connector::cache::CachedClient;
+pub use crate::connector::client::*;
+pub use crate::connector::config::{ClientConfig, HEALTH_CHECK_TIMEOUT};
+pub use crate::connector::interceptor::Interceptor;
+pub use crate::connector::offline::{OfflineQueueConfig, ReplayConflict};
+pub use crate::connector::pool::ClientPool;
+pub use crate::connector::trace::traced;
+#[cfg(feature = "tls")]
+pub use crate::connector::tls::ClientTlsConfig;
\ No newline at end of file
@@ -0,0 +1,199 @@
+use std::{collections::VecDeque, io::ErrorKind, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+use overseer::{error::{ClientError, NetworkError}, models::{Key, Value}, network::OverseerSerde};
+use tokio::sync::{Mutex, MutexGuard};
+
+
+/// What a queued write does when the key was written on the server after
+/// the write was queued.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ReplayConflict {
+    /// The queued write is applied over the newer record.
+    #[default]
+    Overwrite,
+    /// The newer record is kept and the queued write is dropped.
+    KeepServer
+}
+
+/// Buffers the inserts and deletes made while the server cannot be
+/// reached, they are replayed in order once the client connects again.
+#[derive(Clone, PartialEq, Debug)]
+pub struct OfflineQueueConfig {
+    /// The most writes held, the ones past it fail.
+    pub capacity: usize,
+    pub conflict: ReplayConflict,
+    /// Keeps the queue in this file so it survives a restart, the queue is
+    /// held in memory alone if this is not set.
+    pub path: Option<PathBuf>
+}
+
+impl OfflineQueueConfig {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, conflict: ReplayConflict::default(), path: None }
+    }
+    pub fn with_conflict(mut self, conflict: ReplayConflict) -> Self {
+        self.conflict = conflict;
+        self
+    }
+    /// Keeps the queue in a file.
+    pub fn with_path<P>(mut self, path: P) -> Self
+    where
+        P: Into<PathBuf>
+    {
+        self.path = Some(path.into());
+        self
+    }
+}
+
+/// A write that waits for the server.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct QueuedWrite {
+    pub namespace: String,
+    pub key: Key,
+    /// The value inserted, [None] for a delete.
+    pub value: Option<Value>,
+    /// When the write was made, in milliseconds since the unix epoch.
+    pub queued_at: u64
+}
+
+impl QueuedWrite {
+    pub fn new(namespace: &str, key: &Key, value: Option<&Value>) -> Self {
+        let queued_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|f| f.as_millis() as u64).unwrap_or_default();
+        Self { namespace: namespace.to_string(), key: key.clone(), value: value.cloned(), queued_at }
+    }
+}
+
+pub(crate) struct OfflineQueue {
+    config: OfflineQueueConfig,
+    /// The writes oldest first, read from the file on first use.
+    writes: Mutex<Option<VecDeque<QueuedWrite>>>
+}
+
+impl OfflineQueue {
+    pub fn new(config: OfflineQueueConfig) -> Self {
+        Self { config, writes: Mutex::new(None) }
+    }
+    pub fn conflict(&self) -> ReplayConflict {
+        self.config.conflict
+    }
+    /// The queued writes, the replay holds them for as long as it runs so
+    /// the writes made meanwhile line up behind it.
+    pub async fn writes(&self) -> Result<QueueGuard<'_>, NetworkError> {
+        let mut writes = self.writes.lock().await;
+        if writes.is_none() {
+            *writes = Some(self.load().await?);
+        }
+        Ok(QueueGuard { queue: self, writes })
+    }
+    async fn load(&self) -> Result<VecDeque<QueuedWrite>, NetworkError> {
+        let Some(path) = &self.config.path else {
+            return Ok(VecDeque::new());
+        };
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(VecDeque::new()),
+            Err(e) => return Err(e.into())
+        };
+        let mut reader = bytes.as_slice();
+        let mut writes = VecDeque::new();
+        while !reader.is_empty() {
+            let namespace = String::deserialize(&mut reader).await?;
+            let key = Key::deserialize(&mut reader).await?;
+            let value = match bool::deserialize(&mut reader).await? {
+                true => Some(Value::deserialize(&mut reader).await?),
+                false => None
+            };
+            let queued_at = u64::deserialize(&mut reader).await?;
+            writes.push_back(QueuedWrite { namespace, key, value, queued_at });
+        }
+        Ok(writes)
+    }
+    async fn store(&self, writes: &VecDeque<QueuedWrite>) -> Result<(), NetworkError> {
+        let Some(path) = &self.config.path else {
+            return Ok(());
+        };
+        let mut bytes = vec![];
+        for write in writes {
+            write.namespace.serialize(&mut bytes).await?;
+            write.key.serialize(&mut bytes).await?;
+            write.value.is_some().serialize(&mut bytes).await?;
+            if let Some(value) = &write.value {
+                value.serialize(&mut bytes).await?;
+            }
+            write.queued_at.serialize(&mut bytes).await?;
+        }
+        // Written aside and moved over, a crash leaves the old queue or the new one.
+        let staged = path.with_extension("tmp");
+        std::fs::write(&staged, bytes)?;
+        std::fs::rename(staged, path)?;
+        Ok(())
+    }
+}
+
+pub(crate) struct QueueGuard<'a> {
+    queue: &'a OfflineQueue,
+    writes: MutexGuard<'a, Option<VecDeque<QueuedWrite>>>
+}
+
+impl QueueGuard<'_> {
+    fn writes(&mut self) -> &mut VecDeque<QueuedWrite> {
+        self.writes.get_or_insert_with(VecDeque::new)
+    }
+    pub fn len(&mut self) -> usize {
+        self.writes().len()
+    }
+    pub fn front(&mut self) -> Option<QueuedWrite> {
+        self.writes().front().cloned()
+    }
+    /// Queues a write behind the others, failing if the queue is full.
+    pub async fn push(&mut self, write: QueuedWrite) -> Result<(), NetworkError> {
+        let capacity = self.queue.config.capacity;
+        if self.writes().len() >= capacity {
+            return Err(NetworkError::Client(ClientError::OfflineQueueFull(capacity)));
+        }
+        self.writes().push_back(write);
+        let queue = self.queue;
+        queue.store(self.writes()).await
+    }
+    /// Drops the oldest write once it was replayed.
+    pub async fn pop(&mut self) -> Result<(), NetworkError> {
+        self.writes().pop_front();
+        let queue = self.queue;
+        queue.store(self.writes()).await
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::models::{Key, Value};
+
+    use super::{OfflineQueue, OfflineQueueConfig, QueuedWrite};
+
+    #[tokio::test]
+    pub async fn test_durable_queue() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = OfflineQueueConfig::new(2).with_path(directory.path().join("offline.queue"));
+        let (first, second) = (
+            QueuedWrite::new("default", &Key::from_str("app.mode"), Some(&Value::String("dark".into()))),
+            QueuedWrite::new("tenant-a", &Key::from_str("app.size"), None)
+        );
+
+        let queue = OfflineQueue::new(config.clone());
+        let mut writes = queue.writes().await.unwrap();
+        writes.push(first.clone()).await.unwrap();
+        writes.push(second.clone()).await.unwrap();
+        assert!(writes.push(first.clone()).await.is_err());
+        drop(writes);
+
+        // A queue opened on the same file picks up where the last one left off.
+        let reopened = OfflineQueue::new(config.clone());
+        let mut writes = reopened.writes().await.unwrap();
+        assert_eq!(writes.len(), 2);
+        assert_eq!(writes.front(), Some(first));
+        writes.pop().await.unwrap();
+        drop(writes);
+        let queue = OfflineQueue::new(config);
+        assert_eq!(queue.writes().await.unwrap().front(), Some(second));
+    }
+}
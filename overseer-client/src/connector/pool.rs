@@ -0,0 +1,182 @@
+use std::{collections::hash_map::DefaultHasher, hash::{Hash, Hasher}, net::{SocketAddr, ToSocketAddrs}, sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Arc, RwLock, Weak}, time::Duration};
+
+use overseer::{error::{ClientError, NetworkError}, models::Key};
+
+use super::{Client, ClientConfig};
+
+
+/// Several connections to one server, so the requests of a busy process are
+/// not all queued behind each other on a single socket.
+///
+/// Every connection is a [Client] of its own. [ClientPool::get] hands them
+/// out in turn, [ClientPool::for_key] always picks the same one for a key so
+/// its requests stay in order. A connection that fails its health check is
+/// replaced, and the watches made on it are lost with it, so keep watches
+/// on a [Client] of their own.
+pub struct ClientPool {
+    inner: Arc<PoolInner>
+}
+
+struct PoolInner {
+    address: SocketAddr,
+    config: ClientConfig,
+    clients: Vec<RwLock<Arc<Client>>>,
+    /// The next connection [ClientPool::get] hands out.
+    next: AtomicUsize,
+    /// How many connections were replaced.
+    replaced: AtomicU64
+}
+
+impl ClientPool {
+    /// Opens the connections to the server.
+    ///
+    /// # Panics
+    /// If the size is zero.
+    pub async fn new<A>(address: A, size: usize) -> Result<Self, NetworkError>
+    where 
+        A: ToSocketAddrs
+    {
+        Self::with_config(address, size, ClientConfig::default()).await
+    }
+    /// Opens the connections to the server with a specific configuration.
+    ///
+    /// # Panics
+    /// If the size is zero.
+    pub async fn with_config<A>(address: A, size: usize, config: ClientConfig) -> Result<Self, NetworkError>
+    where 
+        A: ToSocketAddrs
+    {
+        assert!(size > 0, "A pool needs at least one connection.");
+        let address = address.to_socket_addrs()
+            .map_err(|_| NetworkError::Client(ClientError::SocketError))?
+            .next()
+            .ok_or(NetworkError::Client(ClientError::SocketError))?;
+        let mut clients = Vec::with_capacity(size);
+        for _ in 0..size {
+            clients.push(RwLock::new(Arc::new(open(address, &config).await?)));
+        }
+        Ok(Self {
+            inner: Arc::new(PoolInner { address, config, clients, next: AtomicUsize::new(0), replaced: AtomicU64::new(0) })
+        })
+    }
+    /// Checks the connections at this period in the background, see [ClientPool::check].
+    ///
+    /// The checks run on a thread of their own like the backends of the
+    /// clients, the connections they open belong to the caller's runtime.
+    pub fn with_health_checks(self, period: Duration) -> Self {
+        let pool = Arc::downgrade(&self.inner);
+        let runtime = tokio::runtime::Handle::current();
+        std::thread::spawn(move || runtime.block_on(check_periodically(pool, period)));
+        self
+    }
+    /// How many connections the pool holds.
+    pub fn size(&self) -> usize {
+        self.inner.clients.len()
+    }
+    /// The next connection in turn, for a request that may go anywhere.
+    pub fn get(&self) -> Arc<Client> {
+        let index = self.inner.next.fetch_add(1, Ordering::Relaxed) % self.size();
+        self.inner.slot(index)
+    }
+    /// The connection the requests of the key go to, it stays the same
+    /// until the connection is replaced.
+    pub fn for_key(&self, key: &Key) -> Arc<Client> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.inner.slot(hasher.finish() as usize % self.size())
+    }
+    /// Pings every connection and replaces the ones that do not answer.
+    /// A replacement that cannot connect leaves the old connection in place
+    /// for the next check. Returns how many were replaced.
+    pub async fn check(&self) -> usize {
+        self.inner.check().await
+    }
+    /// How many connections were replaced since the pool was opened.
+    pub fn replaced(&self) -> u64 {
+        self.inner.replaced.load(Ordering::Acquire)
+    }
+}
+
+impl PoolInner {
+    fn slot(&self, index: usize) -> Arc<Client> {
+        Arc::clone(&self.clients[index].read().unwrap_or_else(|f| f.into_inner()))
+    }
+    async fn check(&self) -> usize {
+        let mut replaced = 0;
+        for (index, slot) in self.clients.iter().enumerate() {
+            let client = self.slot(index);
+            if client.ping().await.is_ok() {
+                continue;
+            }
+            let Ok(fresh) = open(self.address, &self.config).await else {
+                continue;
+            };
+            *slot.write().unwrap_or_else(|f| f.into_inner()) = Arc::new(fresh);
+            // Requests already on the old connection finish or fail on their own.
+            let _ = client.reset_connection().await;
+            replaced += 1;
+        }
+        self.replaced.fetch_add(replaced as u64, Ordering::AcqRel);
+        replaced
+    }
+}
+
+/// Opens a connection of the pool.
+async fn open(address: SocketAddr, config: &ClientConfig) -> Result<Client, NetworkError> {
+    let client = Client::with_config(address, config.clone()).await?;
+    client.connect().await?;
+    Ok(client)
+}
+
+async fn check_periodically(pool: Weak<PoolInner>, period: Duration) {
+    loop {
+        tokio::time::sleep(period).await;
+        // The checks end with the pool.
+        let Some(pool) = pool.upgrade() else {
+            return;
+        };
+        pool.check().await;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use overseer::{models::Key, network::{OverseerSerde, Packet, PacketPayload}};
+    use tokio::net::TcpListener;
+
+    use super::ClientPool;
+
+    #[tokio::test]
+    pub async fn test_client_pool() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let (pool, accepted) = tokio::join!(ClientPool::new(address, 2), async {
+            let first = listener.accept().await.unwrap().0;
+            let second = listener.accept().await.unwrap().0;
+            (first, second)
+        });
+        let pool = pool.unwrap();
+        let (mut healthy, broken) = accepted;
+        assert_eq!(pool.size(), 2);
+
+        // Keys stick to a connection, the rest goes around.
+        let key = Key::from_str("orders.last");
+        assert!(Arc::ptr_eq(&pool.for_key(&key), &pool.for_key(&key)));
+        assert!(!Arc::ptr_eq(&pool.get(), &pool.get()));
+
+        // The connection the server dropped is replaced, the other stays.
+        drop(broken);
+        let server = async {
+            let ping = Packet::deserialize(&mut healthy).await.unwrap();
+            assert!(matches!(ping.payload(), PacketPayload::Ping));
+            Packet::new(ping.id(), PacketPayload::Pong).serialize(&mut healthy).await.unwrap();
+            listener.accept().await.unwrap().0
+        };
+        let (replaced, _replacement) = tokio::join!(pool.check(), server);
+        assert_eq!((replaced, pool.replaced()), (1, 1));
+    }
+}
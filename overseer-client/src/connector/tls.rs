@@ -0,0 +1,89 @@
+use std::{path::PathBuf, sync::Arc};
+
+use overseer::error::NetworkError;
+use tokio::net::TcpStream;
+use tokio_rustls::{client::TlsStream, rustls::{pki_types::{pem::PemObject, CertificateDer, ServerName}, ClientConfig, RootCertStore}};
+
+/// How the client should establish TLS with the server.
+#[derive(Clone, Debug)]
+pub struct ClientTlsConfig {
+    /// The name the server certificate must be valid for.
+    pub server_name: String,
+    /// PEM files holding the certificate authorities we trust.
+    pub root_certificates: Vec<PathBuf>
+}
+
+impl ClientTlsConfig {
+    /// Creates a configuration trusting a single certificate authority.
+    pub fn new<S, P>(server_name: S, root_certificate: P) -> Self
+    where 
+        S: AsRef<str>,
+        P: Into<PathBuf>
+    {
+        Self {
+            server_name: server_name.as_ref().to_string(),
+            root_certificates: vec![root_certificate.into()]
+        }
+    }
+    pub(crate) fn connector(&self) -> Result<TlsConnector, NetworkError> {
+        let mut roots = RootCertStore::empty();
+        for path in &self.root_certificates {
+            let certs = CertificateDer::pem_file_iter(path)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .map_err(|e| NetworkError::TlsConfiguration(format!("Could not read {}: {e}", path.display())))?;
+            for cert in certs {
+                roots.add(cert).map_err(|e| NetworkError::TlsConfiguration(e.to_string()))?;
+            }
+        }
+
+        let server_name = ServerName::try_from(self.server_name.clone())
+            .map_err(|e| NetworkError::TlsConfiguration(e.to_string()))?;
+
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        Ok(TlsConnector {
+            inner: tokio_rustls::TlsConnector::from(Arc::new(config)),
+            server_name
+        })
+    }
+}
+
+/// A ready to use connector built from a [ClientTlsConfig].
+pub(crate) struct TlsConnector {
+    inner: tokio_rustls::TlsConnector,
+    server_name: ServerName<'static>
+}
+
+impl TlsConnector {
+    pub async fn connect(&self, socket: TcpStream) -> Result<TlsStream<TcpStream>, NetworkError> {
+        Ok(self.inner.connect(self.server_name.clone(), socket).await?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::error::NetworkError;
+
+    use super::ClientTlsConfig;
+
+
+    #[test]
+    pub fn test_missing_root_certificate() {
+        let config = ClientTlsConfig::new("localhost", "/does/not/exist.pem");
+        assert!(matches!(config.connector().err().unwrap(), NetworkError::TlsConfiguration(..)));
+    }
+
+    #[test]
+    pub fn test_load_root_certificate() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ca.pem");
+        std::fs::write(&path, cert.cert.pem()).unwrap();
+
+        assert!(ClientTlsConfig::new("localhost", &path).connector().is_ok());
+        assert!(ClientTlsConfig::new("not a hostname!", &path).connector().is_err());
+    }
+}
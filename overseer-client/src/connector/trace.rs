@@ -0,0 +1,21 @@
+use std::future::Future;
+
+
+tokio::task_local! {
+    /// The trace the requests being made belong to.
+    static TRACE: u64;
+}
+
+/// Makes the requests of the future as part of a trace.
+///
+/// Every packet sent while the future runs carries the id in its header,
+/// and the server tags the notifications the writes among them cause with
+/// it, see [super::LiveValue::trace]. Zero is read as no trace.
+pub async fn traced<F: Future>(trace: u64, future: F) -> F::Output {
+    TRACE.scope(trace, future).await
+}
+
+/// The trace of the requests being made, if they belong to one.
+pub(crate) fn current_trace() -> Option<u64> {
+    TRACE.try_with(|f| *f).ok().filter(|f| *f != 0)
+}
@@ -1,3 +1,4 @@
+pub mod blocking;
 mod connector;
 
 pub use crate::connector::*;
\ No newline at end of file
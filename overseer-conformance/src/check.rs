@@ -0,0 +1,70 @@
+use overseer::error::NetworkError;
+use thiserror::Error;
+
+
+/// Why a check failed.
+#[derive(Error, Debug)]
+pub enum CheckError {
+    #[error("{0}")]
+    Network(#[from] NetworkError),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("the server did not answer in time")]
+    Timeout(#[from] tokio::time::error::Elapsed),
+    #[error("{0}")]
+    Failed(String)
+}
+
+/// Fails the check with a message unless the condition holds.
+pub fn ensure(condition: bool, message: impl FnOnce() -> String) -> Result<(), CheckError> {
+    if condition {
+        Ok(())
+    } else {
+        Err(CheckError::Failed(message()))
+    }
+}
+
+/// The outcome of every check that was run.
+#[derive(Default)]
+pub struct Report {
+    results: Vec<(&'static str, Result<(), CheckError>)>
+}
+
+impl Report {
+    pub fn record(&mut self, name: &'static str, result: Result<(), CheckError>) {
+        self.results.push((name, result));
+    }
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|(_, f)| f.is_ok()).count()
+    }
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+    /// Renders a line per check followed by a summary.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, result) in &self.results {
+            match result {
+                Ok(()) => out.push_str(&format!("PASS {name}\n")),
+                Err(e) => out.push_str(&format!("FAIL {name}: {e}\n"))
+            }
+        }
+        out.push_str(&format!("{} passed, {} failed\n", self.passed(), self.failed()));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckError, Report};
+
+    #[test]
+    pub fn test_render_report() {
+        let mut report = Report::default();
+        report.record("ping", Ok(()));
+        report.record("round_trip", Err(CheckError::Failed("wrong value".to_string())));
+        assert_eq!(report.passed(), 1);
+        assert_eq!(report.failed(), 1);
+        assert_eq!(report.render(), "PASS ping\nFAIL round_trip: wrong value\n1 passed, 1 failed\n");
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use overseer::network::{OverseerSerde, Packet, PacketId, PacketPayload, FIRST_REQUEST_ID};
+use tokio::{io::AsyncWriteExt, net::TcpStream};
+
+use crate::check::CheckError;
+
+
+/// How long to wait on the server before failing a check.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the suite connects and how it authenticates.
+#[derive(Clone, Debug)]
+pub struct Target {
+    pub address: String,
+    pub token: Option<String>
+}
+
+/// A raw protocol connection, with none of the conveniences of the client
+/// so the checks see exactly what the server sends.
+pub struct Connection {
+    stream: TcpStream,
+    next_id: u32
+}
+
+impl Connection {
+    /// Connects and authenticates if the target has a token.
+    pub async fn open(target: &Target) -> Result<Self, CheckError> {
+        let stream = tokio::time::timeout(TIMEOUT, TcpStream::connect(&target.address)).await??;
+        let mut connection = Self { stream, next_id: FIRST_REQUEST_ID };
+        if let Some(token) = &target.token {
+            match connection.request(PacketPayload::auth(token)).await?.payload() {
+                PacketPayload::AuthResult { accepted: true } => {}
+                _ => return Err(CheckError::Failed("the server refused the token".to_string()))
+            }
+        }
+        Ok(connection)
+    }
+    /// The id of the next request.
+    pub fn allocate(&mut self) -> PacketId {
+        let id = PacketId::new(self.next_id, 0);
+        self.next_id += 1;
+        id
+    }
+    pub async fn send(&mut self, packet: &Packet<'_>) -> Result<(), CheckError> {
+        packet.serialize(&mut self.stream).await?;
+        Ok(())
+    }
+    /// Writes bytes as they are, for packets the codec refuses to build.
+    pub async fn send_raw(&mut self, bytes: &[u8]) -> Result<(), CheckError> {
+        self.stream.write_all(bytes).await?;
+        Ok(())
+    }
+    pub async fn receive(&mut self) -> Result<Packet<'static>, CheckError> {
+        Ok(tokio::time::timeout(TIMEOUT, Packet::deserialize(&mut self.stream)).await??)
+    }
+    /// Sends a request and reads the response, which must carry its id.
+    pub async fn request(&mut self, payload: PacketPayload<'_>) -> Result<Packet<'static>, CheckError> {
+        let packet = Packet::new(self.allocate(), payload);
+        self.send(&packet).await?;
+        let response = self.receive().await?;
+        if response.id() != packet.id() {
+            return Err(CheckError::Failed(format!(
+                "expected a response to request {}, got {}",
+                packet.id().id(),
+                response.id().id()
+            )));
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,53 @@
+mod check;
+mod connection;
+mod suite;
+
+use clap::Parser;
+
+use crate::{connection::Target, suite::{run_suite, Check}};
+
+
+/// Runs the protocol conformance suite against an overseer server.
+#[derive(Parser, Debug)]
+#[command(name = "overseer-conformance")]
+struct Cli {
+    /// The address of the server.
+    #[arg(short, long, default_value = "127.0.0.1:7878")]
+    address: String,
+    /// The token to authenticate with.
+    #[arg(short, long)]
+    token: Option<String>,
+    /// Only runs the checks with these names.
+    #[arg(short, long)]
+    only: Vec<String>,
+    /// Lists the checks instead of running them.
+    #[arg(long)]
+    list: bool
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let cli = Cli::parse();
+    if cli.list {
+        for check in Check::ALL {
+            println!("{}", check.name());
+        }
+        return;
+    }
+
+    let checks: Vec<Check> = Check::ALL
+        .into_iter()
+        .filter(|f| cli.only.is_empty() || cli.only.iter().any(|name| name == f.name()))
+        .collect();
+    if checks.is_empty() {
+        eprintln!("error: no check matches {:?}", cli.only);
+        std::process::exit(2);
+    }
+
+    let target = Target { address: cli.address, token: cli.token };
+    let report = run_suite(&target, &checks).await;
+    print!("{}", report.render());
+    if report.failed() > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,296 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use overseer::{
+    access::{WatcherActivity, WatcherBehaviour},
+    models::{Key, Value},
+    network::{OverseerSerde, Packet, PacketPayload}
+};
+
+use crate::{check::{ensure, CheckError, Report}, connection::{Connection, Target}};
+
+
+/// A protocol check, each one runs on connections of its own.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Check {
+    Ping,
+    RoundTrip,
+    ZeroLength,
+    ExtremeIntegers,
+    Delete,
+    ResponseOrder,
+    NotifyOrder,
+    Snapshot,
+    ListKeysOrder,
+    UnknownNamespace,
+    VersionDowngrade,
+    UnknownDiscriminator
+}
+
+impl Check {
+    pub const ALL: [Check; 12] = [
+        Check::Ping,
+        Check::RoundTrip,
+        Check::ZeroLength,
+        Check::ExtremeIntegers,
+        Check::Delete,
+        Check::ResponseOrder,
+        Check::NotifyOrder,
+        Check::Snapshot,
+        Check::ListKeysOrder,
+        Check::UnknownNamespace,
+        Check::VersionDowngrade,
+        Check::UnknownDiscriminator
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ping => "ping",
+            Self::RoundTrip => "round_trip",
+            Self::ZeroLength => "zero_length",
+            Self::ExtremeIntegers => "extreme_integers",
+            Self::Delete => "delete",
+            Self::ResponseOrder => "response_order",
+            Self::NotifyOrder => "notify_order",
+            Self::Snapshot => "snapshot",
+            Self::ListKeysOrder => "list_keys_order",
+            Self::UnknownNamespace => "unknown_namespace",
+            Self::VersionDowngrade => "version_downgrade",
+            Self::UnknownDiscriminator => "unknown_discriminator"
+        }
+    }
+    pub async fn run(&self, target: &Target, prefix: &str) -> Result<(), CheckError> {
+        match self {
+            Self::Ping => ping(target).await,
+            Self::RoundTrip => round_trip(target, prefix).await,
+            Self::ZeroLength => zero_length(target).await,
+            Self::ExtremeIntegers => extreme_integers(target, prefix).await,
+            Self::Delete => delete(target, prefix).await,
+            Self::ResponseOrder => response_order(target, prefix).await,
+            Self::NotifyOrder => notify_order(target, prefix).await,
+            Self::Snapshot => snapshot(target, prefix).await,
+            Self::ListKeysOrder => list_keys_order(target, prefix).await,
+            Self::UnknownNamespace => unknown_namespace(target, prefix).await,
+            Self::VersionDowngrade => version_downgrade(target, prefix).await,
+            Self::UnknownDiscriminator => unknown_discriminator(target).await
+        }
+    }
+}
+
+/// Runs the checks one after another, the keys they write all live under
+/// a prefix unique to the run.
+pub async fn run_suite(target: &Target, checks: &[Check]) -> Report {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|f| f.as_nanos()).unwrap_or_default();
+    let prefix = format!("conformance.{nanos}.");
+    let mut report = Report::default();
+    for check in checks {
+        report.record(check.name(), check.run(target, &prefix).await);
+    }
+    report
+}
+
+fn key(prefix: &str, name: &str) -> Key {
+    Key::from_owned(format!("{prefix}{name}"))
+}
+
+async fn insert(connection: &mut Connection, key: &Key, value: &Value) -> Result<(), CheckError> {
+    match connection.request(PacketPayload::insert(key, value)).await?.payload() {
+        PacketPayload::Return { .. } => Ok(()),
+        other => Err(CheckError::Failed(format!("expected a return to an insert, got {}", other.name())))
+    }
+}
+
+async fn get(connection: &mut Connection, key: &Key) -> Result<Option<Value>, CheckError> {
+    match connection.request(PacketPayload::get(key)).await?.into_payload() {
+        PacketPayload::Return { key: found, value, .. } => {
+            ensure(*found == *key, || format!("the return was for {:?} instead of {:?}", found.as_str(), key.as_str()))?;
+            Ok(value.map(|f| f.into_owned()))
+        }
+        other => Err(CheckError::Failed(format!("expected a return to a get, got {}", other.name())))
+    }
+}
+
+/// Writes a value and reads it back.
+async fn expect_round_trip(connection: &mut Connection, key: &Key, value: Value) -> Result<(), CheckError> {
+    insert(connection, key, &value).await?;
+    let found = get(connection, key).await?;
+    ensure(found.as_ref() == Some(&value), || format!("wrote {value:?} but read {found:?}"))
+}
+
+async fn ping(target: &Target) -> Result<(), CheckError> {
+    let mut connection = Connection::open(target).await?;
+    let pong = connection.request(PacketPayload::Ping).await?;
+    ensure(matches!(pong.payload(), PacketPayload::Pong), || format!("expected a pong, got {}", pong.payload().name()))
+}
+
+async fn round_trip(target: &Target, prefix: &str) -> Result<(), CheckError> {
+    let mut connection = Connection::open(target).await?;
+    expect_round_trip(&mut connection, &key(prefix, "string"), Value::String("hello".into())).await?;
+    expect_round_trip(&mut connection, &key(prefix, "integer"), Value::Integer(42)).await
+}
+
+/// Empty keys and strings are legal and must not be confused with absence.
+async fn zero_length(target: &Target) -> Result<(), CheckError> {
+    let mut connection = Connection::open(target).await?;
+    let empty = Key::from_str("");
+    let previous = get(&mut connection, &empty).await?;
+    expect_round_trip(&mut connection, &empty, Value::String("".into())).await?;
+    match previous {
+        Some(value) => insert(&mut connection, &empty, &value).await,
+        None => Ok(())
+    }
+}
+
+/// The integers at the edges of the variable length encoding.
+async fn extreme_integers(target: &Target, prefix: &str) -> Result<(), CheckError> {
+    let mut connection = Connection::open(target).await?;
+    for integer in [i64::MAX, i64::MIN, 0, -1, 1 << 32] {
+        expect_round_trip(&mut connection, &key(prefix, "extreme"), Value::Integer(integer)).await?;
+    }
+    Ok(())
+}
+
+async fn delete(target: &Target, prefix: &str) -> Result<(), CheckError> {
+    let mut connection = Connection::open(target).await?;
+    let key = key(prefix, "deleted");
+    insert(&mut connection, &key, &Value::Integer(1)).await?;
+    connection.request(PacketPayload::delete(&key)).await?;
+    let found = get(&mut connection, &key).await?;
+    ensure(found.is_none(), || format!("the key still held {found:?} after being deleted"))
+}
+
+/// Pipelined requests are answered in the order they were sent.
+async fn response_order(target: &Target, prefix: &str) -> Result<(), CheckError> {
+    let mut connection = Connection::open(target).await?;
+    let key = key(prefix, "ordered");
+    let mut sent = vec![];
+    for i in 0..32 {
+        let value = Value::Integer(i);
+        let packet = Packet::new(connection.allocate(), PacketPayload::insert(&key, &value));
+        connection.send(&packet).await?;
+        sent.push(packet.id());
+    }
+    for id in sent {
+        let response = connection.receive().await?;
+        ensure(response.id() == id, || format!("expected the response to {}, got {}", id.id(), response.id().id()))?;
+    }
+    let found = get(&mut connection, &key).await?;
+    ensure(found == Some(Value::Integer(31)), || format!("the last write should win, read {found:?}"))
+}
+
+/// An ordered watcher sees every change in the order it was made.
+async fn notify_order(target: &Target, prefix: &str) -> Result<(), CheckError> {
+    let mut watcher = Connection::open(target).await?;
+    let mut writer = Connection::open(target).await?;
+    let key = key(prefix, "watched");
+
+    watcher.request(PacketPayload::watch(&key, WatcherActivity::Lazy, WatcherBehaviour::Ordered)).await?;
+    for i in 0..5 {
+        insert(&mut writer, &key, &Value::Integer(i)).await?;
+    }
+    for i in 0..5 {
+        let packet = watcher.receive().await?;
+        ensure(packet.id().is_notification(), || format!("expected a notification, got a response to {}", packet.id().id()))?;
+        let PacketPayload::Notify { value, .. } = packet.payload() else {
+            return Err(CheckError::Failed(format!("expected a notify, got {}", packet.payload().name())));
+        };
+        let value = value.as_deref();
+        ensure(value == Some(&Value::Integer(i)), || format!("expected change {i}, got {value:?}"))?;
+    }
+    Ok(())
+}
+
+async fn snapshot(target: &Target, prefix: &str) -> Result<(), CheckError> {
+    let mut connection = Connection::open(target).await?;
+    let key = key(prefix, "snapshot");
+    insert(&mut connection, &key, &Value::Integer(7)).await?;
+    match connection.request(PacketPayload::watch_snapshot(&key, WatcherBehaviour::Ordered)).await?.payload() {
+        PacketPayload::Snapshot { value, .. } => {
+            let value = value.as_deref();
+            ensure(value == Some(&Value::Integer(7)), || format!("the snapshot held {value:?}"))
+        }
+        other => Err(CheckError::Failed(format!("expected a snapshot, got {}", other.name())))
+    }
+}
+
+/// Keys are listed in order and each page continues where the last ended.
+async fn list_keys_order(target: &Target, prefix: &str) -> Result<(), CheckError> {
+    let mut connection = Connection::open(target).await?;
+    let names = ["list.c", "list.a", "list.b", "list.e", "list.d"];
+    for name in names {
+        insert(&mut connection, &key(prefix, name), &Value::Integer(0)).await?;
+    }
+
+    let mut listed = vec![];
+    let mut cursor = None;
+    loop {
+        let (keys, next) = match connection.request(PacketPayload::list_keys(cursor.as_ref(), 2)).await?.into_payload() {
+            PacketPayload::KeyPage { keys, cursor } => (keys, cursor),
+            other => return Err(CheckError::Failed(format!("expected a key page, got {}", other.name())))
+        };
+        ensure(keys.len() <= 2, || format!("asked for 2 keys, got {}", keys.len()))?;
+        listed.extend(keys);
+        match next {
+            Some(next) => cursor = Some(next),
+            None => break
+        }
+    }
+    ensure(listed.windows(2).all(|f| f[0] < f[1]), || "the keys were not listed in order".to_string())?;
+
+    let mut expected: Vec<Key> = names.iter().map(|f| key(prefix, f)).collect();
+    expected.sort();
+    let found: Vec<Key> = listed.into_iter().filter(|f| f.as_str().starts_with(&format!("{prefix}list."))).collect();
+    ensure(found == expected, || format!("expected {expected:?}, listed {found:?}"))
+}
+
+async fn unknown_namespace(target: &Target, prefix: &str) -> Result<(), CheckError> {
+    let mut connection = Connection::open(target).await?;
+    let key = key(prefix, "missing");
+    let packet = Packet::new(connection.allocate(), PacketPayload::get(&key)).with_namespace("conformance-missing");
+    connection.send(&packet).await?;
+    let response = connection.receive().await?;
+    ensure(matches!(response.payload(), PacketPayload::UnknownNamespace), || {
+        format!("expected an unknown namespace, got {}", response.payload().name())
+    })
+}
+
+/// A server answers an older client in the version the client speaks.
+async fn version_downgrade(target: &Target, prefix: &str) -> Result<(), CheckError> {
+    let mut connection = Connection::open(target).await?;
+    let key = key(prefix, "downgraded");
+    let Some(packet) = Packet::new(connection.allocate(), PacketPayload::get(&key)).downgrade(0)? else {
+        return Err(CheckError::Failed("a get could not be encoded for version 0".to_string()));
+    };
+    connection.send(&packet).await?;
+    let response = connection.receive().await?;
+    ensure(response.version() == 0, || format!("answered a version 0 request with version {}", response.version()))
+}
+
+/// A packet the server does not know ends the connection instead of
+/// being skipped, since the length of its body is unknown.
+async fn unknown_discriminator(target: &Target) -> Result<(), CheckError> {
+    let mut connection = Connection::open(target).await?;
+    let mut bytes = vec![];
+    Packet::new(connection.allocate(), PacketPayload::Ping).serialize(&mut bytes).await?;
+    // A ping has no body, so the discriminator is the final byte.
+    if let Some(last) = bytes.last_mut() {
+        *last = u8::MAX;
+    }
+    connection.send_raw(&bytes).await?;
+    match connection.receive().await {
+        Err(CheckError::Network(..) | CheckError::Io(..)) => Ok(()),
+        Err(e) => Err(e),
+        Ok(packet) => Err(CheckError::Failed(format!("the server answered with {}", packet.payload().name())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Check;
+
+    #[test]
+    pub fn test_check_names_are_unique() {
+        let mut names: Vec<&str> = Check::ALL.iter().map(|f| f.name()).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), Check::ALL.len());
+    }
+}
@@ -0,0 +1,147 @@
+//! The derive for `OverseerSerde`, use it through `overseer::network::OverseerSerde`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Expr, ExprLit, Fields, GenericArgument, Lit, PathArguments, Type};
+
+
+/// Encodes the fields in the order they are declared, each the way its own
+/// `OverseerSerde` encodes it, so integers and lengths are varints.
+///
+/// An enum leads with a byte holding the discriminant of the variant,
+/// which is its explicit discriminant or else one more than the last.
+/// `Option` fields are encoded like `Option<&T>`, a byte for whether
+/// the value is there and then the value.
+#[proc_macro_derive(OverseerSerde)]
+pub fn derive_overseer_serde(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into()
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let (serialize, deserialize) = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, writes, reads) = expand_fields(&data.fields);
+            (quote! { let Self #pattern = self; #(#writes)* }, quote! { Ok(Self #reads) })
+        }
+        Data::Enum(data) => {
+            let (mut writes, mut reads) = (vec![], vec![]);
+            let mut next: Option<u8> = Some(0);
+            for variant in &data.variants {
+                let discriminant = match &variant.discriminant {
+                    Some((_, expr)) => literal_discriminant(expr)?,
+                    None => next.ok_or_else(|| syn::Error::new(variant.span(), "Only 256 variants fit in the discriminant byte."))?
+                };
+                next = discriminant.checked_add(1);
+                let ident = &variant.ident;
+                let (pattern, fields, values) = expand_fields(&variant.fields);
+                writes.push(quote! {
+                    Self::#ident #pattern => {
+                        ::overseer::models::LocalWriteAsync::write_u8(writer, #discriminant).await?;
+                        #(#fields)*
+                    }
+                });
+                reads.push(quote! { #discriminant => Self::#ident #values, });
+            }
+            let serialize = quote! {
+                match self {
+                    #(#writes)*
+                }
+            };
+            let deserialize = quote! {
+                Ok(match ::overseer::models::LocalReadAsync::read_u8(reader).await? {
+                    #(#reads)*
+                    other => return Err(::overseer::error::ProtocolError::UnrecognizedDiscriminator(stringify!(#name), other).into())
+                })
+            };
+            (serialize, deserialize)
+        }
+        Data::Union(..) => return Err(syn::Error::new(input.span(), "OverseerSerde cannot be derived for unions."))
+    };
+    Ok(quote! {
+        impl #impl_generics ::overseer::network::OverseerSerde<#name #ty_generics> for #name #ty_generics #where_clause {
+            type E = ::overseer::error::NetworkError;
+            #[allow(unused_variables)]
+            async fn serialize<W: ::overseer::models::LocalWriteAsync>(&self, writer: &mut W) -> ::core::result::Result<(), Self::E> {
+                #serialize
+                Ok(())
+            }
+            #[allow(unused_variables)]
+            async fn deserialize<R: ::overseer::models::LocalReadAsync>(reader: &mut R) -> ::core::result::Result<Self, Self::E> {
+                #deserialize
+            }
+        }
+    })
+}
+
+/// The pattern binding the fields, the statements writing them and the
+/// constructor reading them back.
+fn expand_fields(fields: &Fields) -> (TokenStream2, Vec<TokenStream2>, TokenStream2) {
+    let bindings: Vec<_> = fields.iter().enumerate()
+        .map(|(index, field)| field.ident.clone().unwrap_or_else(|| format_ident!("field_{index}")))
+        .collect();
+    let writes = fields.iter().zip(&bindings).map(|(field, binding)| write_field(&field.ty, binding)).collect();
+    let reads: Vec<_> = fields.iter().map(|field| read_field(&field.ty)).collect();
+    match fields {
+        Fields::Named(..) => (
+            quote! { { #(#bindings),* } },
+            writes,
+            quote! { { #(#bindings: #reads),* } }
+        ),
+        Fields::Unnamed(..) => (quote! { ( #(#bindings),* ) }, writes, quote! { ( #(#reads),* ) }),
+        Fields::Unit => (quote! {}, writes, quote! {})
+    }
+}
+
+fn write_field(ty: &Type, binding: &syn::Ident) -> TokenStream2 {
+    match option_inner(ty) {
+        Some(inner) => quote! {
+            <::core::option::Option<&#inner> as ::overseer::network::OverseerSerde<::core::option::Option<#inner>>>::serialize(&#binding.as_ref(), writer).await?;
+        },
+        None => quote! {
+            <#ty as ::overseer::network::OverseerSerde<#ty>>::serialize(#binding, writer).await?;
+        }
+    }
+}
+
+fn read_field(ty: &Type) -> TokenStream2 {
+    match option_inner(ty) {
+        Some(inner) => quote! {
+            <::core::option::Option<&#inner> as ::overseer::network::OverseerSerde<::core::option::Option<#inner>>>::deserialize(reader).await?
+        },
+        None => quote! {
+            <#ty as ::overseer::network::OverseerSerde<#ty>>::deserialize(reader).await?
+        }
+    }
+}
+
+/// The `T` of an `Option<T>` field.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+    match arguments.args.first() {
+        Some(GenericArgument::Type(inner)) if arguments.args.len() == 1 => Some(inner),
+        _ => None
+    }
+}
+
+fn literal_discriminant(expr: &Expr) -> syn::Result<u8> {
+    match expr {
+        Expr::Lit(ExprLit { lit: Lit::Int(int), .. }) => int.base10_parse::<u8>(),
+        _ => Err(syn::Error::new(expr.span(), "OverseerSerde needs discriminants that are integer literals from 0 to 255."))
+    }
+}
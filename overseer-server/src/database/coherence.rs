@@ -0,0 +1,228 @@
+use std::{cell::{Cell, RefCell}, collections::HashMap, future::Future, rc::Rc};
+
+use overseer::models::{Key, Value};
+use tokio::sync::mpsc::{error::TryRecvError, UnboundedReceiver};
+
+use super::{Change, SequencedChange, SUB_KEY_SEPARATOR};
+
+
+/// A record as the owning server held it, [None] if the key was not set.
+pub type CachedRecord = Option<(Rc<Value>, u64)>;
+
+/// What a [PeerCache] knows about a key.
+#[derive(Clone, PartialEq, Debug)]
+pub enum CachedRead {
+    /// The record is current as of the last change the cache was sent.
+    Hit(CachedRecord),
+    /// The record has to be fetched from the owning server.
+    Miss,
+    /// The key is not under a prefix the cache subscribed to.
+    Uncovered
+}
+
+/// How a [PeerCache] served its reads.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct PeerCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped because the owning server changed their key.
+    pub invalidations: u64
+}
+
+/// Serves reads on a peer server from a local copy of the records under
+/// the prefixes it subscribed to on the server that owns them.
+///
+/// The owner streams the changes under the prefixes, see
+/// [super::ReplicationLog::follow_prefixes], and each one drops the entry
+/// of its key, so the cache holds only what was read since and writes
+/// still go to the owner. The stream is drained before every read, so a
+/// read never misses a change the owner recorded before it.
+///
+/// A record fetched from the owner is only cached if it is at least as
+/// new as the changes the cache applied, one fetched before a change it
+/// crossed would otherwise linger. Once the stream closes every read
+/// misses, nothing tells the cache its entries went stale any more.
+///
+/// This is experimental. The stream only runs between databases in the
+/// same process, servers do not connect to each other's feeds and the
+/// driver does not route writes to an owner yet.
+pub struct PeerCache {
+    prefixes: Vec<String>,
+    changes: RefCell<UnboundedReceiver<SequencedChange>>,
+    entries: RefCell<HashMap<Key, CachedRecord>>,
+    /// The last change of the owner the entries account for.
+    applied: Cell<u64>,
+    connected: Cell<bool>,
+    stats: Cell<PeerCacheStats>
+}
+
+impl PeerCache {
+    /// A cache of the prefixes, which the changes after the sequence of the
+    /// owner keep coherent.
+    pub fn new(prefixes: Vec<String>, changes: UnboundedReceiver<SequencedChange>, sequence: u64) -> Self {
+        Self {
+            prefixes,
+            changes: RefCell::new(changes),
+            entries: RefCell::default(),
+            applied: Cell::new(sequence),
+            connected: Cell::new(true),
+            stats: Cell::default()
+        }
+    }
+    /// Whether the key is under one of the prefixes.
+    pub fn covers(&self, key: &Key) -> bool {
+        self.prefixes.iter().any(|f| key.as_str().starts_with(f.as_str()))
+    }
+    /// The last change of the owner the cache applied.
+    pub fn applied(&self) -> u64 {
+        self.applied.get()
+    }
+    /// Whether the owner is still streaming its changes.
+    pub fn is_connected(&self) -> bool {
+        self.sync();
+        self.connected.get()
+    }
+    /// How many records are cached.
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+    pub fn stats(&self) -> PeerCacheStats {
+        self.stats.get()
+    }
+    /// Reads the key from the cache.
+    pub fn get(&self, key: &Key) -> CachedRead {
+        if !self.covers(key) {
+            return CachedRead::Uncovered;
+        }
+        self.sync();
+        let mut stats = self.stats.get();
+        let read = match self.entries.borrow().get(key) {
+            Some(record) if self.connected.get() => {
+                stats.hits += 1;
+                CachedRead::Hit(record.clone())
+            }
+            _ => {
+                stats.misses += 1;
+                CachedRead::Miss
+            }
+        };
+        self.stats.set(stats);
+        read
+    }
+    /// Caches a record fetched from the owner as of its sequence, returning
+    /// false if it was not cached for being older than the cache.
+    pub fn fill(&self, key: Key, record: CachedRecord, sequence: u64) -> bool {
+        self.sync();
+        if !self.connected.get() || !self.covers(&key) || sequence < self.applied.get() {
+            return false;
+        }
+        self.entries.borrow_mut().insert(key, record);
+        true
+    }
+    /// Reads the key from the cache, fetching it with the fetch on a miss,
+    /// which returns the record along with the sequence of the owner it was
+    /// read at. Uncovered keys are always fetched.
+    pub async fn read_through<F, Fut>(&self, key: &Key, fetch: F) -> CachedRecord
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = (CachedRecord, u64)>
+    {
+        if let CachedRead::Hit(record) = self.get(key) {
+            return record;
+        }
+        let (record, sequence) = fetch().await;
+        self.fill(key.clone(), record.clone(), sequence);
+        record
+    }
+    /// Drops the entry of a key, for instance after forwarding a write of it
+    /// to the owner.
+    pub fn invalidate(&self, key: &Key) {
+        if self.entries.borrow_mut().remove(key).is_some() {
+            let mut stats = self.stats.get();
+            stats.invalidations += 1;
+            self.stats.set(stats);
+        }
+    }
+    /// Applies the changes the owner streamed so far.
+    fn sync(&self) {
+        let mut changes = self.changes.borrow_mut();
+        loop {
+            match changes.try_recv() {
+                Ok(change) => self.apply(change),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.connected.set(false);
+                    self.entries.borrow_mut().clear();
+                    return;
+                }
+            }
+        }
+    }
+    fn apply(&self, change: SequencedChange) {
+        if change.sequence <= self.applied.get() {
+            return;
+        }
+        self.applied.set(change.sequence);
+        let key = match &change.change {
+            Change::Insert(key, _) | Change::Delete(key) => key
+        };
+        self.invalidate(key);
+        // The sub-key of a split counter changes what its key reads.
+        if let Some((parent, _)) = key.as_str().rsplit_once(SUB_KEY_SEPARATOR) {
+            self.invalidate(&Key::from_str(parent));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::models::{Key, Value};
+
+    use crate::database::Database;
+
+    use super::CachedRead;
+
+    #[monoio::test]
+    pub async fn test_peer_cache_coherence() {
+        let dir = tempfile::tempdir().unwrap();
+        let owner = Database::new(dir.path(), "owner.db").await.unwrap();
+        owner.insert(Key::from_str("eu.mode"), Value::String("on".into()), None).await.unwrap();
+        let cache = owner.cache_for_peer(vec!["eu.".into()]);
+
+        let (mode, other) = (Key::from_str("eu.mode"), Key::from_str("us.mode"));
+        let fetch = |key: Key| { let owner = &owner; async move { (owner.get_versioned(&key), owner.version()) } };
+        assert_eq!(cache.get(&other), CachedRead::Uncovered);
+        assert_eq!(cache.get(&mode), CachedRead::Miss);
+        let record = cache.read_through(&mode, || fetch(mode.clone())).await;
+        assert_eq!(record.map(|(value, _)| (*value).clone()), Some(Value::String("on".into())));
+        assert!(matches!(cache.get(&mode), CachedRead::Hit(Some(..))));
+        assert_eq!(cache.get(&Key::from_str("eu.missing")), CachedRead::Miss);
+
+        // A write on the owner drops the entry before the next read.
+        owner.insert(&mode, Value::String("off".into()), None).await.unwrap();
+        assert_eq!(cache.get(&mode), CachedRead::Miss);
+        assert_eq!(cache.stats().invalidations, 1);
+
+        // A fetch that a change overtook is not cached, a current one is.
+        let stale = (owner.get_versioned(&mode), owner.version());
+        owner.delete(&mode).await.unwrap();
+        assert!(!cache.fill(mode.clone(), stale.0, stale.1));
+        assert!(cache.fill(mode.clone(), None, owner.version()));
+        assert_eq!(cache.get(&mode), CachedRead::Hit(None));
+
+        // Changes outside the prefixes are not streamed.
+        owner.insert(&other, Value::Integer(1), None).await.unwrap();
+        assert_eq!(cache.get(&mode), CachedRead::Hit(None));
+        assert_eq!(cache.applied(), owner.version() - 1);
+
+        // Without the owner nothing is served from the cache.
+        drop(owner);
+        assert!(!cache.is_connected());
+        assert_eq!(cache.get(&mode), CachedRead::Miss);
+        assert_eq!(cache.len(), 0);
+    }
+}
@@ -0,0 +1,70 @@
+use std::{cell::RefCell, collections::VecDeque, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use overseer::models::CompactionRun;
+
+
+/// How many runs are kept before the oldest is forgotten.
+pub const COMPACTION_HISTORY_SIZE: usize = 64;
+
+/// A bounded record of the recent compaction runs.
+///
+/// The page store reclaims space by defragmenting pages, every
+/// run should be recorded here so operators can see maintenance happening.
+pub struct CompactionHistory {
+    runs: RefCell<VecDeque<CompactionRun>>,
+    capacity: usize
+}
+
+impl Default for CompactionHistory {
+    fn default() -> Self {
+        Self::with_capacity(COMPACTION_HISTORY_SIZE)
+    }
+}
+
+impl CompactionHistory {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            runs: RefCell::new(VecDeque::with_capacity(capacity)),
+            capacity
+        }
+    }
+    /// Records a run that just finished, evicting the oldest if full.
+    pub fn record(&self, duration: Duration, bytes_reclaimed: u64, pages_moved: u64) {
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|f| f.as_millis() as u64)
+            .unwrap_or_default();
+
+        let mut runs = self.runs.borrow_mut();
+        if runs.len() == self.capacity {
+            runs.pop_front();
+        }
+        runs.push_back(CompactionRun { finished_at, duration, bytes_reclaimed, pages_moved });
+    }
+    /// The recorded runs, oldest first.
+    pub fn runs(&self) -> Vec<CompactionRun> {
+        self.runs.borrow().iter().copied().collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::CompactionHistory;
+
+
+    #[test]
+    pub fn test_compaction_history_bounded() {
+        let history = CompactionHistory::with_capacity(2);
+        history.record(Duration::from_millis(1), 10, 1);
+        history.record(Duration::from_millis(2), 20, 2);
+        history.record(Duration::from_millis(3), 30, 3);
+
+        let runs = history.runs();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].bytes_reclaimed, 20);
+        assert_eq!(runs[1].pages_moved, 3);
+    }
+}
@@ -1,23 +1,68 @@
-use std::{borrow::Borrow, path::Path, rc::Rc, sync::Arc};
+use std::{borrow::Borrow, cell::{Cell, RefCell}, collections::HashMap, path::Path, rc::Rc, sync::Arc, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use tokio::sync::mpsc::UnboundedReceiver;
 
 use overseer::{
     access::{WatcherActivity, WatcherBehaviour},
-    error::NetworkError,
-    models::{Key, Value},
+    error::{NetworkError, StorageError},
+    models::{Acknowledgement, DatabaseStats, DeleteCondition, Durability, ExportedRecord, Key, KeyChild, KeyDrift, KeyMatcher, KeyMeta, LeasedItem, RecordMeta, Revision, Value, WatchedKey},
+    network::CURRENT_VERSION,
 };
 
 use crate::net::ClientId;
 
-use super::{DatabaseStorage, MemoryDatabase, WatchClient, Watcher};
+use super::{AccessSampler, Change, ChangeFeed, CompactionHistory, DeltaSource, DurabilityPolicy, HistoryRetention, KeyHistory, SecondaryIndexes, MigrationReport, PlacementPolicy, Quota, QuotaUsage, ReadSnapshot, SchemaRegistry, SequenceRegistry, MAX_CATCH_UP_ROUNDS, DatabaseStorage, Manifest, PeerCache, ReplicationLog, ReplicationSnapshot, Seed, MemoryDatabase, SequencedChange, WatchClient, Watcher, WatcherLimit, WorkQueues, Locks, WriteOrder, SplitPolicy, StorageSnapshot, SystemKeys, SystemStats, SYSTEM_KEYS, SYSTEM_PREFIX, lock_key, locked_key, queue_of, queue_prefix, aggregate_split, split_keys, sub_key};
+
+
+/// The most keys returned by a single listing.
+pub const MAX_KEY_PAGE: usize = 1024;
 
+/// The most keys a filtered listing looks at for a single page.
+pub const MAX_KEYS_SCANNED: usize = 16 * MAX_KEY_PAGE;
 
 /// The [Database] structure which controls the API to the
 /// underlying key-value store.
+///
+/// Writes are ordered per key and reads are served without waiting on
+/// them, see [WriteOrder] for what clients can rely on.
 pub struct Database {
     /// The memory backend.
     memory: MemoryDatabase,
-    /// The storage backend.
-    storage: DatabaseStorage,
+    /// The storage backend, swapped out when the store is migrated.
+    storage: RefCell<Rc<DatabaseStorage>>,
+    /// The writes made while a migration is copying the store.
+    changes: ChangeFeed,
+    /// The recent compaction runs of the storage backend.
+    compactions: CompactionHistory,
+    /// The sampled access frequency of the keys.
+    sampler: AccessSampler,
+    /// Serializes the writes to each key.
+    order: WriteOrder,
+    /// The recent changes to every key.
+    history: KeyHistory,
+    /// How durable the writes to each key are made.
+    durability: DurabilityPolicy,
+    /// Streams the changes to the followers.
+    replication: ReplicationLog,
+    /// The keys by the fields of their map values.
+    indexes: SecondaryIndexes,
+    /// The sequences and leases of the work queues.
+    queues: WorkQueues,
+    /// The locks held on the keys by the connections.
+    locks: Locks,
+    /// The counters split across sub-keys.
+    split: SplitPolicy,
+    /// The increment of a split key that comes next, which picks its sub-key.
+    next_split: Cell<usize>,
+    /// Answers the keys under [SYSTEM_PREFIX], which read as nothing if
+    /// this is not set.
+    system: RefCell<Option<SystemKeys>>,
+    /// The limits on what the writes of clients make the database hold.
+    quota: Cell<Quota>,
+    /// The schemas the values written under key prefixes conform to.
+    schemas: SchemaRegistry,
+    /// The durable counters handing out unique numbers.
+    sequences: SequenceRegistry
 }
 
 impl Database {
@@ -30,40 +75,966 @@ impl Database {
         P: AsRef<Path>,
         S: AsRef<str>,
     {
+        let schemas = SchemaRegistry::open(&path, &name).await?;
+        let sequences = SequenceRegistry::open(&path, &name).await?;
         let storage = DatabaseStorage::new(path, name).await?;
         let memory = MemoryDatabase::new();
 
+        let metas = storage.record_metas();
         for (key, value) in storage.records().await {
-            memory.insert(key, value).await;
+            let meta = metas.get(&key).copied();
+            memory.insert(key, value, meta).await;
         }
 
-        Ok(Self { memory, storage })
+        let history = KeyHistory::default().starting_at(memory.version());
+        Ok(Self {
+            memory,
+            storage: RefCell::new(Rc::new(storage)),
+            changes: ChangeFeed::default(),
+            compactions: CompactionHistory::default(),
+            sampler: AccessSampler::default(),
+            order: WriteOrder::default(),
+            history,
+            durability: DurabilityPolicy::default(),
+            replication: ReplicationLog::default(),
+            indexes: SecondaryIndexes::default(),
+            queues: WorkQueues::default(),
+            locks: Locks::default(),
+            split: SplitPolicy::default(),
+            next_split: Cell::new(0),
+            system: RefCell::new(None),
+            quota: Cell::new(Quota::default()),
+            schemas,
+            sequences
+        })
+    }
+    /// The current storage backend.
+    fn storage(&self) -> Rc<DatabaseStorage> {
+        Rc::clone(&self.storage.borrow())
     }
     /// Gets a value for a key.
     pub async fn get<K>(&self, key: K) -> Option<Rc<Value>>
     where
         K: Borrow<Key>,
     {
+        if let Some(value) = self.system_value(key.borrow()) {
+            return value;
+        }
+        if let Some(ways) = self.split.ways_of(key.borrow()) {
+            return self.get_split(key.borrow(), ways).map(|(value, _)| value);
+        }
+        self.sampler.record(key.borrow());
+        self.touch(key.borrow());
         self.memory.get(key.borrow()).await
     }
-    /// Inserts a value under a key.
-    pub async fn insert<K>(&self, key: K, value: Value) -> Result<(), NetworkError>
+    /// Gets a value for a key along with the version of its record.
+    ///
+    /// Every write gives the record the version of the store it produced,
+    /// so the version of a key only ever goes up while the store runs.
+    pub fn get_versioned(&self, key: &Key) -> Option<(Rc<Value>, u64)> {
+        if let Some(value) = self.system_value(key) {
+            return value.map(|f| (f, self.version()));
+        }
+        if let Some(ways) = self.split.ways_of(key) {
+            return self.get_split(key, ways);
+        }
+        self.sampler.record(key);
+        self.touch(key);
+        self.memory.get_versioned(key)
+    }
+    /// Gets the values of the keys at a single point in time, along with
+    /// the version of each record, zero if the key holds nothing.
+    ///
+    /// The keys are read without yielding, so no write, not even one half
+    /// through a batch, lands between two of them.
+    pub fn snapshot_get(&self, keys: &[Key]) -> Vec<(Option<Rc<Value>>, u64)> {
+        keys.iter()
+            .map(|key| match self.get_versioned(key) {
+                Some((value, version)) => (Some(value), version),
+                None => (None, 0)
+            })
+            .collect()
+    }
+    /// Gets a value for a key along with the version and metadata of its record.
+    pub fn get_with_meta(&self, key: &Key) -> Option<(Rc<Value>, u64, Option<RecordMeta>)> {
+        if let Some(value) = self.system_value(key) {
+            return value.map(|f| (f, self.version(), None));
+        }
+        if let Some(ways) = self.split.ways_of(key) {
+            return self.get_split(key, ways).map(|(value, version)| (value, version, None));
+        }
+        self.sampler.record(key);
+        self.touch(key);
+        self.memory.get_with_meta(key)
+    }
+    /// The value of a split key added up from its sub-keys, see [SplitPolicy].
+    fn get_split(&self, key: &Key, ways: usize) -> Option<(Rc<Value>, u64)> {
+        self.sampler.record(key);
+        let records = split_keys(key, ways).map(|f| self.memory.get_versioned(&f));
+        aggregate_split(records).map(|(value, version)| (Rc::new(value), version))
+    }
+    /// Adds the delta to the integer the key holds, a key holding nothing
+    /// counting as zero, and returns the value after with the version of
+    /// the write. The sum saturates rather than overflows.
+    ///
+    /// A key holding anything else is left alone and returned as it is
+    /// with the version of its record. The increments of a split key go
+    /// to its sub-keys in turn, see [SplitPolicy].
+    pub async fn increment(&self, key: &Key, delta: i64, writer: Option<ClientId>) -> Result<(Rc<Value>, u64), NetworkError> {
+        let ways = self.split.ways_of(key);
+        let target = match ways {
+            Some(ways) => {
+                if let Some((base, version)) = self.memory.get_versioned(key).filter(|(f, _)| !matches!(**f, Value::Integer(..))) {
+                    return Ok((base, version));
+                }
+                let next = self.next_split.get();
+                self.next_split.set(next.wrapping_add(1));
+                sub_key(key, next % ways)
+            }
+            None => key.clone()
+        };
+        let (sum, version) = {
+            let _order = self.order.lock(&target).await;
+            let sum = match self.memory.get_versioned(&target) {
+                None => delta,
+                Some((value, version)) => match *value {
+                    Value::Integer(integer) => integer.saturating_add(delta),
+                    _ => return Ok((value, version))
+                }
+            };
+            (sum, self.write_ordered(&target, Value::Integer(sum), self.durability.class_of(&target), writer).await?)
+        };
+        match ways {
+            Some(ways) => Ok((self.get_split(key, ways).map(|(value, _)| value).unwrap_or_else(|| Rc::new(Value::Integer(sum))), version)),
+            None => Ok((Rc::new(Value::Integer(sum)), version))
+        }
+    }
+    /// Deletes the sub-keys of a split key, the caller holds no lock in the
+    /// [WriteOrder] as a sub-key may share it.
+    async fn clear_split(&self, key: &Key) -> Result<(), NetworkError> {
+        let Some(ways) = self.split.ways_of(key) else {
+            return Ok(());
+        };
+        for sub_key in split_keys(key, ways).skip(1) {
+            let _order = self.order.lock(&sub_key).await;
+            if self.memory.get_versioned(&sub_key).is_some() {
+                self.delete_ordered(&sub_key).await?;
+            }
+        }
+        Ok(())
+    }
+    /// How many records the database holds and about how many bytes they take.
+    pub fn usage(&self) -> QuotaUsage {
+        QuotaUsage { keys: self.memory.len(), bytes: self.memory.bytes() }
+    }
+    /// How many records the database holds, how many bytes their values
+    /// take and how many the files of the storage take.
+    pub fn stats(&self) -> DatabaseStats {
+        let storage = self.storage();
+        let flushed = storage.stats();
+        DatabaseStats {
+            storage_bytes: storage.file_size(),
+            dirty: flushed.dirty,
+            flushes: flushed.flushes,
+            flush_time: flushed.flush_time,
+            ..self.memory.stats()
+        }
+    }
+    pub fn quota(&self) -> Quota {
+        self.quota.get()
+    }
+    /// Replaces the limits on what the database holds, the records held
+    /// beyond them are kept.
+    pub fn set_quota(&self, quota: Quota) {
+        self.quota.set(quota);
+    }
+    /// The schemas the values written under key prefixes conform to.
+    pub fn schemas(&self) -> &SchemaRegistry {
+        &self.schemas
+    }
+    pub fn sequences(&self) -> &SequenceRegistry {
+        &self.sequences
+    }
+    /// Checks the writes against the schemas and the quota, the caller
+    /// holds the order of their keys.
+    fn check_quota<'a>(&self, writes: impl IntoIterator<Item = (&'a Key, &'a Value)> + Clone) -> Result<(), NetworkError> {
+        for (key, value) in writes.clone() {
+            self.schemas.validate(key, value)?;
+        }
+        let quota = self.quota.get();
+        if quota.is_unlimited() {
+            return Ok(());
+        }
+        let before = self.usage();
+        let (mut keys, mut bytes, mut largest) = (before.keys, before.bytes, 0);
+        for (key, value) in writes {
+            let size = value.size();
+            match self.memory.get_versioned(key) {
+                Some((old, _)) => bytes = (bytes + size).saturating_sub(old.size()),
+                None => {
+                    keys += 1;
+                    bytes += key.as_str().len() + size;
+                }
+            }
+            largest = largest.max(size);
+        }
+        quota.check(before, QuotaUsage { keys, bytes }, largest)
+    }
+    /// Marks the key as read now, see [Database::archive].
+    fn touch(&self, key: &Key) {
+        let at = unix_millis();
+        self.memory.touch(key, at);
+        self.storage().touch(key, at);
+    }
+    /// Inserts a value under a key, returning the version of the new record.
+    ///
+    /// The writer is recorded in the metadata of the record, [None] standing
+    /// for the server itself.
+    pub async fn insert<K>(&self, key: K, value: Value, writer: Option<ClientId>) -> Result<u64, NetworkError>
     where
         K: Borrow<Key>,
     {
-        self.storage.write(key.borrow(), &value).await?;
-        self.memory.insert(key.borrow(), value).await;
-        Ok(())
+        let version = {
+            let _order = self.order.lock(key.borrow()).await;
+            self.write_ordered(key.borrow(), value, self.durability.class_of(key.borrow()), writer).await?
+        };
+        self.clear_split(key.borrow()).await?;
+        Ok(version)
+    }
+    /// Inserts a value at least as durable as the acknowledgement level asks
+    /// for, returning the version of the new record. A level waiting on the
+    /// followers is reached once [Database::wait_replicated] resolves.
+    pub async fn insert_acknowledged<K>(&self, key: K, value: Value, acknowledgement: Acknowledgement, writer: Option<ClientId>) -> Result<u64, NetworkError>
+    where
+        K: Borrow<Key>,
+    {
+        let _order = self.order.lock(key.borrow()).await;
+        let durability = acknowledgement.durability(self.durability.class_of(key.borrow()));
+        self.write_ordered(key.borrow(), value, durability, writer).await
+    }
+    /// Waits until the followers acknowledging what they apply hold the change.
+    pub async fn wait_replicated(&self, sequence: u64, followers: usize) {
+        self.replication.wait_replicated(sequence, followers).await
+    }
+    /// Inserts a value only if the record of the key is still at the version,
+    /// zero standing for a key that holds nothing. Returns the version of the
+    /// new record, or [NetworkError::VersionConflict] with the current one.
+    pub async fn insert_if_version<K>(&self, key: K, value: Value, version: u64, writer: Option<ClientId>) -> Result<u64, NetworkError>
+    where
+        K: Borrow<Key>,
+    {
+        let _order = self.order.lock(key.borrow()).await;
+        let current = self.memory.record_version(key.borrow());
+        if current != version {
+            return Err(NetworkError::VersionConflict(current));
+        }
+        self.write_ordered(key.borrow(), value, self.durability.class_of(key.borrow()), writer).await
+    }
+    /// Writes a value once the writer holds the order of the key.
+    async fn write_ordered(&self, key: &Key, value: Value, durability: Durability, writer: Option<ClientId>) -> Result<u64, NetworkError> {
+        self.write_with_meta(key, value, durability, self.stamp(key, writer)).await
+    }
+    /// Writes a value with the metadata given, the caller holds the order of the key.
+    async fn write_with_meta(&self, key: &Key, value: Value, durability: Durability, meta: RecordMeta) -> Result<u64, NetworkError> {
+        self.check_quota([(key, &value)])?;
+        let storage = self.storage();
+        self.sampler.record(key);
+        self.changes.record(Change::Insert(key.clone(), value.clone()));
+        storage.write(key, &value, meta, durability).await?;
+        let old = self.memory.get(key).await;
+        self.indexes.update(key, old.as_deref(), Some(&value));
+        let version = self.memory.insert(key, value.clone(), Some(meta)).await;
+        self.replication.record(self.memory.version(), || Change::Insert(key.clone(), value));
+        self.history.record(key, self.memory.version(), self.memory.get(key).await);
+        Ok(version)
+    }
+    /// Returns the value of the key, inserting the default if it has none.
+    ///
+    /// Concurrent callers are ordered by the key, so exactly one of them
+    /// inserts and the others get its value. Returns the value with the
+    /// version of its record and whether the default was inserted.
+    pub async fn get_or_insert<K>(&self, key: K, default: Value, writer: Option<ClientId>) -> Result<(Rc<Value>, u64, bool), NetworkError>
+    where
+        K: Borrow<Key>,
+    {
+        let _order = self.order.lock(key.borrow()).await;
+        self.sampler.record(key.borrow());
+        if let Some((existing, version)) = self.memory.get_versioned(key.borrow()) {
+            return Ok((existing, version, false));
+        }
+        self.check_quota([(key.borrow(), &default)])?;
+        let storage = self.storage();
+        self.changes.record(Change::Insert(key.borrow().clone(), default.clone()));
+        let meta = self.stamp(key.borrow(), writer);
+        storage.write(key.borrow(), &default, meta, self.durability.class_of(key.borrow())).await?;
+        self.indexes.update(key.borrow(), None, Some(&default));
+        let (value, version, inserted) = self.memory.get_or_insert(key.borrow(), default, Some(meta)).await;
+        if inserted {
+            self.replication.record(self.memory.version(), || Change::Insert(key.borrow().clone(), (*value).clone()));
+            self.history.record(key.borrow(), self.memory.version(), Some(Rc::clone(&value)));
+        }
+        Ok((value, version, inserted))
     }
     /// Deletes a value under a key.
     pub async fn delete<K>(&self, key: K) -> Result<(), NetworkError>
     where
         K: Borrow<Key>,
     {
-        self.storage.delete(key.borrow()).await?;
-        self.memory.delete(key.borrow()).await;
+        {
+            let _order = self.order.lock(key.borrow()).await;
+            self.delete_ordered(key.borrow()).await?;
+        }
+        self.clear_split(key.borrow()).await
+    }
+    /// Deletes the key if the client was the last to write it, returning
+    /// whether it did. This ends an ephemeral key, which is left alone once
+    /// another client wrote over it.
+    pub async fn delete_written_by(&self, key: &Key, writer: ClientId) -> Result<bool, NetworkError> {
+        let _order = self.order.lock(key).await;
+        if !self.memory.record_meta(key).is_some_and(|f| f.writer == Some(writer.id())) {
+            return Ok(false);
+        }
+        self.delete_ordered(key).await?;
+        Ok(true)
+    }
+    /// Deletes the key only if it still holds the value or its record is
+    /// still at the version, returning whether it did. A key that holds
+    /// nothing is never deleted.
+    pub async fn delete_if(&self, key: &Key, condition: &DeleteCondition) -> Result<bool, NetworkError> {
+        {
+            let _order = self.order.lock(key).await;
+            let holds = match condition {
+                DeleteCondition::Value(expected) => self.memory.get_versioned(key).is_some_and(|(value, _)| *value == *expected),
+                DeleteCondition::Version(expected) => *expected != 0 && self.memory.record_version(key) == *expected
+            };
+            if !holds {
+                return Ok(false);
+            }
+            self.delete_ordered(key).await?;
+        }
+        self.clear_split(key).await?;
+        Ok(true)
+    }
+    /// Deletes the key, the caller holds its lock in the [WriteOrder].
+    async fn delete_ordered(&self, key: &Key) -> Result<(), NetworkError> {
+        let storage = self.storage();
+        self.sampler.forget(key);
+        self.changes.record(Change::Delete(key.clone()));
+        storage.delete(key, self.durability.class_of(key)).await?;
+        let old = self.memory.get(key).await;
+        self.indexes.update(key, old.as_deref(), None);
+        if self.memory.delete(key).await {
+            self.replication.record(self.memory.version(), || Change::Delete(key.clone()));
+            self.history.record(key, self.memory.version(), None);
+        }
         Ok(())
     }
+    /// Moves the record of a key to another in one step, the record keeping
+    /// its value, version and metadata. The watchers of the key are told it
+    /// was deleted and those of the other key the value.
+    ///
+    /// Returns the version of the moved record, [None] if the key holds
+    /// nothing. A target holding a record is only replaced with `overwrite`,
+    /// otherwise this fails with [NetworkError::VersionConflict] and the
+    /// version of that record.
+    pub async fn rename(&self, from: &Key, to: &Key, overwrite: bool) -> Result<Option<u64>, NetworkError> {
+        let version = {
+            let _order = self.order.lock_all([from, to]).await;
+            let Some((value, version, meta)) = self.memory.get_with_meta(from) else {
+                return Ok(None);
+            };
+            if from == to {
+                return Ok(Some(version));
+            }
+            let current = self.memory.record_version(to);
+            if current != 0 && !overwrite {
+                return Err(NetworkError::VersionConflict(current));
+            }
+            let moved = [Change::Delete(from.clone()), Change::Insert(to.clone(), (*value).clone())];
+            for change in &moved {
+                self.changes.record(change.clone());
+            }
+            // A record without metadata does not take on that of the record it replaces.
+            let mut stored = vec![Change::Delete(from.clone())];
+            if meta.is_none() && current != 0 {
+                stored.push(Change::Delete(to.clone()));
+            }
+            stored.push(Change::Insert(to.clone(), (*value).clone()));
+            let metas: HashMap<Key, RecordMeta> = meta.map(|f| (to.clone(), f)).into_iter().collect();
+            self.storage().apply(stored, &metas, self.durability.class_of_all([from, to])).await?;
+
+            self.sampler.forget(from);
+            self.sampler.record(to);
+            let old = self.memory.get(to).await;
+            self.indexes.update(from, Some(&value), None);
+            self.indexes.update(to, old.as_deref(), Some(&value));
+            let Some((value, version)) = self.memory.rename(from, to).await else {
+                return Ok(None);
+            };
+            let sequence = self.memory.version();
+            let [delete, insert] = moved;
+            self.replication.record(sequence - 1, || delete);
+            self.replication.record(sequence, || insert);
+            self.history.record(from, sequence - 1, None);
+            self.history.record(to, sequence, Some(value));
+            version
+        };
+        self.clear_split(from).await?;
+        self.clear_split(to).await?;
+        Ok(Some(version))
+    }
+    /// Deletes the key and returns the value and metadata of the record it
+    /// held, for moving the record to another database, see [Database::place].
+    pub async fn take(&self, key: &Key) -> Result<Option<(Value, Option<RecordMeta>)>, NetworkError> {
+        let taken = {
+            let _order = self.order.lock(key).await;
+            let Some((value, _, meta)) = self.memory.get_with_meta(key) else {
+                return Ok(None);
+            };
+            self.delete_ordered(key).await?;
+            ((*value).clone(), meta)
+        };
+        self.clear_split(key).await?;
+        Ok(Some(taken))
+    }
+    /// Writes a record taken from another database, keeping its metadata.
+    /// A key holding a record is only replaced with `overwrite`, see
+    /// [Database::rename].
+    pub async fn place(&self, key: &Key, value: Value, meta: Option<RecordMeta>, overwrite: bool, writer: Option<ClientId>) -> Result<u64, NetworkError> {
+        let version = {
+            let _order = self.order.lock(key).await;
+            let current = self.memory.record_version(key);
+            if current != 0 && !overwrite {
+                return Err(NetworkError::VersionConflict(current));
+            }
+            let meta = meta.unwrap_or_else(|| self.stamp(key, writer));
+            self.write_with_meta(key, value, self.durability.class_of(key), meta).await?
+        };
+        self.clear_split(key).await?;
+        Ok(version)
+    }
+    /// Applies a batch of changes so that no reader or watcher sees part of it.
+    ///
+    /// The batch is written to the store first and then swapped into the memory
+    /// backend without yielding, so reads see the records from before or after
+    /// the whole batch and the watchers are notified together.
+    pub async fn apply_batch(&self, changes: Vec<Change>) -> Result<(), NetworkError> {
+        let _order = self.order.lock_all(changes.iter().map(|f| match f {
+            Change::Insert(key, _) | Change::Delete(key) => key
+        })).await;
+        self.apply_ordered(changes, None).await.map(|_| ())
+    }
+    /// Inserts the values under their keys all at once, returning the
+    /// version of each new record in order.
+    ///
+    /// Every write is checked before any is made, so either all of them land
+    /// or none does, and they land the way [Database::apply_batch] does, no
+    /// reader or watcher seeing part of them. A key given twice takes the
+    /// later value.
+    pub async fn insert_all(&self, records: Vec<(Key, Value)>, writer: Option<ClientId>) -> Result<Vec<u64>, NetworkError> {
+        let versions = {
+            let _order = self.order.lock_all(records.iter().map(|(key, _)| key)).await;
+            self.check_writes(&records)?;
+            self.apply_ordered(records.iter().map(|(key, value)| Change::Insert(key.clone(), value.clone())).collect(), writer).await?
+        };
+        for (key, _) in &records {
+            self.clear_split(key).await?;
+        }
+        Ok(versions)
+    }
+    /// Checks the writes against the schemas and the quota without making
+    /// them, see [Database::insert_all].
+    pub fn check_writes(&self, records: &[(Key, Value)]) -> Result<(), NetworkError> {
+        self.check_quota(records.iter().map(|(key, value)| (key, value)))
+    }
+    /// Applies a batch once the writer holds the order of every key in it,
+    /// returning the version of the store after each change.
+    async fn apply_ordered(&self, changes: Vec<Change>, writer: Option<ClientId>) -> Result<Vec<u64>, NetworkError> {
+        let keys = || changes.iter().map(|f| match f {
+            Change::Insert(key, _) | Change::Delete(key) => key
+        });
+        for change in &changes {
+            self.changes.record(change.clone());
+        }
+        // The whole batch is written by the same writer at the same time.
+        let metas: HashMap<Key, RecordMeta> = changes.iter()
+            .filter_map(|f| match f {
+                Change::Insert(key, _) => Some((key.clone(), self.stamp(key, writer))),
+                Change::Delete(_) => None
+            })
+            .collect();
+        self.storage().apply(changes.clone(), &metas, self.durability.class_of_all(keys())).await?;
+        let mut versions = Vec::with_capacity(changes.len());
+        for change in changes {
+            match change {
+                Change::Insert(key, value) => {
+                    self.sampler.record(&key);
+                    let old = self.memory.get(&key).await;
+                    self.indexes.update(&key, old.as_deref(), Some(&value));
+                    self.memory.insert(&key, value.clone(), metas.get(&key).copied()).await;
+                    self.replication.record(self.memory.version(), || Change::Insert(key.clone(), value));
+                    self.history.record(&key, self.memory.version(), self.memory.get(&key).await);
+                }
+                Change::Delete(key) => {
+                    self.sampler.forget(&key);
+                    let old = self.memory.get(&key).await;
+                    self.indexes.update(&key, old.as_deref(), None);
+                    if self.memory.delete(&key).await {
+                        self.replication.record(self.memory.version(), || Change::Delete(key.clone()));
+                        self.history.record(&key, self.memory.version(), None);
+                    }
+                }
+            }
+            versions.push(self.memory.version());
+        }
+        Ok(versions)
+    }
+    /// Loads records in bulk to seed the store, returning how many were loaded.
+    ///
+    /// The records go to the storage backend in one write rather than one
+    /// per record, and the watchers of the keys are not told, a watcher
+    /// sees the loaded value the next time it fetches the key. Loading a key
+    /// that holds a value replaces it.
+    pub async fn bulk_load<I>(&self, records: I, writer: Option<ClientId>) -> Result<usize, NetworkError>
+    where
+        I: IntoIterator<Item = (Key, Value)>
+    {
+        let records: Vec<(Key, Value)> = records.into_iter().collect();
+        let _order = self.order.lock_all(records.iter().map(|(key, _)| key)).await;
+        self.check_quota(records.iter().map(|(key, value)| (key, value)))?;
+        let metas: HashMap<Key, RecordMeta> = records.iter().map(|(key, _)| (key.clone(), self.stamp(key, writer))).collect();
+        let changes: Vec<Change> = records.iter().map(|(key, value)| Change::Insert(key.clone(), value.clone())).collect();
+        for change in &changes {
+            self.changes.record(change.clone());
+        }
+        self.storage().apply(changes, &metas, Durability::Periodic).await?;
+
+        let start = self.memory.version();
+        for (key, value) in &records {
+            let old = self.memory.get_versioned(key).map(|(value, _)| value);
+            self.indexes.update(key, old.as_deref(), Some(value));
+        }
+        let count = records.len();
+        self.memory.load(records.iter().map(|(key, value)| (key.clone(), value.clone(), metas[key])).collect());
+        for (sequence, (key, value)) in (start + 1..).zip(records) {
+            self.replication.record(sequence, || Change::Insert(key, value));
+        }
+        Ok(count)
+    }
+    /// Every record neither read nor written since the time, in milliseconds
+    /// since the unix epoch, deleting them in a single batch if asked to.
+    ///
+    /// Records written before the store kept metadata are never archived,
+    /// nothing says when they were last used.
+    pub async fn archive(&self, before: u64, delete: bool) -> Result<Vec<(Key, Value)>, NetworkError> {
+        let mut stale = self.memory.accessed_before(before);
+        if delete {
+            let _order = self.order.lock_all(stale.iter().map(|(key, _)| key)).await;
+            // A key written while the locks were taken is in use again.
+            stale.retain(|(key, _)| self.memory.record_meta(key).is_some_and(|f| f.accessed_at < before));
+            self.apply_ordered(stale.iter().map(|(key, _)| Change::Delete(key.clone())).collect(), None).await?;
+        }
+        Ok(stale.into_iter().map(|(key, value)| (key, (*value).clone())).collect())
+    }
+    /// Appends an item to the work queue, returning the key it is stored
+    /// under and the version of its record.
+    pub async fn enqueue(&self, queue: &str, value: Value) -> Result<(Key, u64), NetworkError> {
+        let item = self.queues.next_item(queue, || {
+            self.memory.scan(&queue_prefix(queue)).into_iter()
+                .map(|(key, _)| key)
+                .filter(|key| queue_of(key) == Some(queue))
+                .next_back()
+        });
+        let version = self.insert(&item, value, None).await?;
+        Ok((item, version))
+    }
+    /// Leases the oldest item of the queue no consumer holds, hiding it from
+    /// the others until the visibility timeout runs out, see [WorkQueues].
+    pub fn dequeue(&self, queue: &str, visibility: Duration) -> Option<LeasedItem> {
+        let now = Instant::now();
+        let (key, value) = self.memory.scan(&queue_prefix(queue)).into_iter()
+            .filter(|(key, _)| queue_of(key) == Some(queue))
+            .find(|(key, _)| !self.queues.is_leased(key, now))?;
+        let lease = self.queues.lease(&key, now + visibility);
+        Some(LeasedItem { key, value: (*value).clone(), lease })
+    }
+    /// Removes a leased item from its queue, returning false if the lease
+    /// was handed on to another consumer.
+    pub async fn ack(&self, item: &Key, lease: u64) -> Result<bool, NetworkError> {
+        if !self.queues.holds(item, lease) {
+            return Ok(false);
+        }
+        self.delete(item).await?;
+        self.queues.release(item, lease);
+        Ok(true)
+    }
+    /// Gives up a lease so the item is visible again, returning false if
+    /// the lease was handed on to another consumer.
+    pub fn nack(&self, item: &Key, lease: u64) -> bool {
+        self.queues.release(item, lease)
+    }
+    /// Takes the lock on the key for the client or extends the one it holds,
+    /// returning its token, or [None] if another client holds it, see [Locks].
+    ///
+    /// The watchers of its [lock_key] are told the id of the new holder when
+    /// the lock changes hands and nothing once it is free again, the watchers
+    /// of the key itself only see its record.
+    pub async fn acquire(&self, key: &Key, owner: ClientId, ttl: Duration) -> Option<u64> {
+        let now = Instant::now();
+        let (token, changed) = self.locks.acquire(key, owner, now + ttl, now)?;
+        if changed {
+            self.memory.notify(&lock_key(key), Some(Rc::new(Value::Integer(owner.id() as i64)))).await;
+        }
+        Some(token)
+    }
+    /// Releases the lock, returning false if the client does not hold it.
+    pub async fn release_lock(&self, key: &Key, owner: ClientId) -> bool {
+        let released = self.locks.release(key, owner);
+        if released {
+            self.memory.notify(&lock_key(key), None).await;
+        }
+        released
+    }
+    /// Releases every lock of a client that went away, returning how many it held.
+    pub async fn release_locks(&self, owner: ClientId) -> usize {
+        let keys = self.locks.release_all(owner);
+        for key in &keys {
+            self.memory.notify(&lock_key(key), None).await;
+        }
+        keys.len()
+    }
+    /// Answers the keys under [SYSTEM_PREFIX] with the figures of the server
+    /// and of this database.
+    pub fn serve_system(&self, stats: Arc<SystemStats>) {
+        *self.system.borrow_mut() = Some(SystemKeys::new(stats));
+    }
+    /// What a system key holds, [None] if the key is not one this database
+    /// answers. The names not in [SYSTEM_KEYS] hold nothing.
+    fn system_value(&self, key: &Key) -> Option<Option<Rc<Value>>> {
+        let name = key.as_str().strip_prefix(SYSTEM_PREFIX)?;
+        // Every namespace answers the holders of its locks.
+        if let Some(locked) = locked_key(key) {
+            return Some(self.locks.holder(&locked, Instant::now()).map(|f| Rc::new(Value::Integer(f.id() as i64))));
+        }
+        let system = self.system.borrow();
+        let stats = &system.as_ref()?.stats;
+        let storage = self.storage().stats();
+        let value = match name {
+            "version" => Value::String(env!("CARGO_PKG_VERSION").into()),
+            "protocol" => Value::Integer(CURRENT_VERSION as i64),
+            "uptime_ms" => Value::Integer(stats.uptime().as_millis() as i64),
+            "connections" => Value::Integer(stats.connections() as i64),
+            "watchers" => Value::Integer(self.watcher_count() as i64),
+            "storage.records" => Value::Integer(self.memory.len() as i64),
+            "storage.version" => Value::Integer(self.version() as i64),
+            "storage.dirty" => Value::Integer(storage.dirty as i64),
+            "storage.flushes" => Value::Integer(storage.flushes as i64),
+            "storage.flush_latency_us" => Value::Integer(storage.mean_flush_latency().as_micros() as i64),
+            _ => return Some(None)
+        };
+        Some(Some(Rc::new(value)))
+    }
+    /// Tells the watchers of the system keys whose values changed since
+    /// the last refresh, see [super::SYSTEM_REFRESH_INTERVAL].
+    pub async fn refresh_system(&self) {
+        for name in SYSTEM_KEYS {
+            let key = Key::from_str(format!("{SYSTEM_PREFIX}{name}"));
+            let Some(Some(value)) = self.system_value(&key) else {
+                continue;
+            };
+            let changed = self.system.borrow().as_ref().is_some_and(|f| f.publish(name, &value));
+            if changed {
+                self.memory.notify(&key, Some(value)).await;
+            }
+        }
+    }
+    /// Releases the locks whose time to live ran out, returning how many there were.
+    pub async fn expire_locks(&self) -> usize {
+        let keys = self.locks.expire(Instant::now());
+        for key in &keys {
+            self.memory.notify(&lock_key(key), None).await;
+        }
+        keys.len()
+    }
+    /// Every record with a key under the prefix, in order.
+    pub fn scan(&self, prefix: &str) -> Vec<(Key, Rc<Value>)> {
+        self.memory.scan(prefix)
+    }
+    /// Pins the current version of the store for a long read, the records
+    /// read through the snapshot stay as they were however they are
+    /// written meanwhile. The old records are dropped with the snapshot.
+    pub fn snapshot(&self) -> ReadSnapshot {
+        self.memory.snapshot()
+    }
+    /// The value of the key and the version of its record as of the
+    /// snapshot. Split keys and system keys are read as they are stored.
+    pub fn get_at(&self, snapshot: &ReadSnapshot, key: &Key) -> Option<(Rc<Value>, u64)> {
+        self.memory.record_at(snapshot, key).map(|f| (f.value, f.version))
+    }
+    /// Lists the keys as of the snapshot, paged like [Database::keys].
+    pub fn keys_at(&self, snapshot: &ReadSnapshot, cursor: Option<&Key>, limit: usize) -> (Vec<Key>, Option<Key>) {
+        self.memory.keys_at(snapshot, cursor, limit.clamp(1, MAX_KEY_PAGE))
+    }
+    /// Exports the records as of the snapshot, paged like [Database::export].
+    pub fn export_at(&self, snapshot: &ReadSnapshot, cursor: Option<&Key>, limit: usize) -> (Vec<ExportedRecord>, Option<Key>) {
+        let (keys, cursor) = self.keys_at(snapshot, cursor, limit);
+        let records = keys.into_iter()
+            .filter_map(|key| {
+                let record = self.memory.record_at(snapshot, &key)?;
+                Some(ExportedRecord { key, value: (*record.value).clone(), meta: record.meta })
+            })
+            .collect();
+        (records, cursor)
+    }
+    /// Lists the keys in order, a page at a time. Pass the returned cursor
+    /// back to continue after the page, it is [None] once every key was listed.
+    pub fn keys(&self, cursor: Option<&Key>, limit: usize) -> (Vec<Key>, Option<Key>) {
+        self.memory.keys(cursor, limit.clamp(1, MAX_KEY_PAGE))
+    }
+    /// Lists the keys the matcher accepts, paged like [Database::keys]. At
+    /// most [MAX_KEYS_SCANNED] keys are looked at per page, see
+    /// [MemoryDatabase::find_keys].
+    pub fn find_keys(&self, matcher: &KeyMatcher, cursor: Option<&Key>, limit: usize) -> Result<(Vec<Key>, Option<Key>), NetworkError> {
+        self.memory.find_keys(matcher, cursor, limit.clamp(1, MAX_KEY_PAGE), MAX_KEYS_SCANNED)
+    }
+    /// Up to `limit` of the keys one segment below the key in order,
+    /// starting after the cursor, see [MemoryDatabase::children].
+    pub fn children(&self, key: &Key, cursor: Option<&Key>, limit: usize) -> (Vec<KeyChild>, Option<Key>) {
+        self.memory.children(key, cursor, limit.clamp(1, MAX_KEY_PAGE))
+    }
+    /// Up to `limit` records in order with their metadata, starting after
+    /// the cursor, paged like [Database::keys]. Exporting does not count
+    /// as reading the records.
+    pub fn export(&self, cursor: Option<&Key>, limit: usize) -> (Vec<ExportedRecord>, Option<Key>) {
+        let (keys, cursor) = self.keys(cursor, limit);
+        let records = keys.into_iter()
+            .filter_map(|key| {
+                let (value, _, meta) = self.memory.get_with_meta(&key)?;
+                Some(ExportedRecord { key, value: (*value).clone(), meta })
+            })
+            .collect();
+        (records, cursor)
+    }
+    /// Declares a secondary index on a field of the map values, see [SecondaryIndexes].
+    pub fn declare_index(&self, field: &str) {
+        let records = self.memory.scan("");
+        self.indexes.declare(field, records.iter().map(|(key, value)| (key, &**value)));
+    }
+    /// The indexed fields, in order.
+    pub fn indexes(&self) -> Vec<String> {
+        self.indexes.fields()
+    }
+    /// The keys whose map value holds the value at the field, in order.
+    /// This is [None] if no index is declared on the field.
+    pub fn query_index(&self, field: &str, value: &Value) -> Option<Vec<Key>> {
+        self.indexes.query(field, value)
+    }
+    /// The version of the store, this increases with every change.
+    pub fn version(&self) -> u64 {
+        self.memory.version()
+    }
+    /// Takes a snapshot for a follower joining while the database is written,
+    /// along with the stream of every change after it.
+    ///
+    /// Nothing yields between reading the records and joining the stream, and
+    /// every change is recorded in the same step it is applied, so the stream
+    /// starts right after the sequence of the snapshot.
+    pub fn replicate(&self) -> (ReplicationSnapshot, UnboundedReceiver<SequencedChange>) {
+        let snapshot = ReplicationSnapshot {
+            sequence: self.memory.version(),
+            records: self.memory.scan("").into_iter().map(|(key, value)| (key, (*value).clone())).collect()
+        };
+        (snapshot, self.replication.follow())
+    }
+    /// Like [Database::replicate], but for a follower that already holds a
+    /// stale copy of the records and syncs only what diverged, see [super::Replica::sync].
+    pub fn replicate_delta(&self) -> (DeltaSource, UnboundedReceiver<SequencedChange>) {
+        (DeltaSource::new(self.memory.version(), self.memory.scan("")), self.replication.follow())
+    }
+    /// A cache of the records under the prefixes for a peer server that
+    /// serves reads of them locally and writes them here. Experimental,
+    /// the peer has to run in this process, see [PeerCache].
+    pub fn cache_for_peer(&self, prefixes: Vec<String>) -> PeerCache {
+        let changes = self.replication.follow_prefixes(prefixes.clone());
+        PeerCache::new(prefixes, changes, self.memory.version())
+    }
+    /// The followers streaming the changes.
+    pub fn replication(&self) -> &ReplicationLog {
+        &self.replication
+    }
+    /// Checks if the database holds no records.
+    pub fn is_empty(&self) -> bool {
+        self.memory.len() == 0
+    }
+    /// Writes the seed into a fresh database, returning how many
+    /// records were written. Nothing happens if the database has records.
+    pub async fn seed(&self, seed: &Seed) -> Result<usize, NetworkError> {
+        if !self.is_empty() {
+            return Ok(0);
+        }
+        let records = seed.resolve().await?;
+        for (key, value) in &records {
+            self.insert(key, value.clone(), None).await?;
+        }
+        Ok(records.len())
+    }
+    /// Lists the required keys that are missing or hold the wrong type.
+    pub async fn check_manifest(&self, manifest: &Manifest) -> Vec<KeyDrift> {
+        let mut drift = vec![];
+        for required in manifest.keys() {
+            let found = self.get(&required.key).await;
+            drift.extend(required.check(found.as_deref()));
+        }
+        drift
+    }
+    /// Creates the defaults of the required keys that are missing, returning
+    /// how many were created. Keys that hold the wrong type are left alone.
+    pub async fn apply_manifest_defaults(&self, manifest: &Manifest) -> Result<usize, NetworkError> {
+        let mut created = 0;
+        for required in manifest.keys() {
+            if let Some(default) = &required.default {
+                if self.get(&required.key).await.is_none() {
+                    self.insert(&required.key, default.clone(), None).await?;
+                    created += 1;
+                }
+            }
+        }
+        Ok(created)
+    }
+    /// The recent compaction runs.
+    pub fn compactions(&self) -> &CompactionHistory {
+        &self.compactions
+    }
+    /// The recent changes to every key.
+    pub fn history(&self) -> &KeyHistory {
+        &self.history
+    }
+    /// What the key held at the revision, or [None] if the revision is older
+    /// than the history kept of the key. A key no change was recorded of
+    /// since the revision still holds its value.
+    pub async fn value_at(&self, key: &Key, revision: Revision) -> Option<Option<Rc<Value>>> {
+        match self.history.at(key, revision) {
+            Some(value) => Some(value),
+            None if self.history.unchanged_since(key, revision) => Some(self.memory.get(key).await),
+            None => None
+        }
+    }
+    /// Replaces the placement policy, forgetting what was sampled so far.
+    pub fn set_placement_policy(&mut self, policy: PlacementPolicy) {
+        self.sampler = AccessSampler::new(policy);
+    }
+    /// Replaces the classes that decide how durable the writes to each key are made.
+    pub fn set_durability_policy(&mut self, policy: DurabilityPolicy) {
+        self.storage().set_group_window(policy.group_window);
+        self.durability = policy;
+    }
+    /// Replaces how many changes of each key the history keeps, forgetting
+    /// the changes recorded so far.
+    pub fn set_history_retention(&mut self, retention: HistoryRetention) {
+        self.history = KeyHistory::with_retention(retention).starting_at(self.memory.version());
+    }
+    /// Replaces the prefixes whose counters are split across sub-keys.
+    pub fn set_split_policy(&mut self, policy: SplitPolicy) {
+        self.split = policy;
+    }
+    pub fn durability(&self) -> &DurabilityPolicy {
+        &self.durability
+    }
+    /// What is known about a key besides its value.
+    pub async fn meta(&self, key: &Key) -> KeyMeta {
+        KeyMeta {
+            exists: self.memory.get(key).await.is_some(),
+            durability: self.durability.class_of(key),
+            record: self.memory.record_meta(key)
+        }
+    }
+    /// The metadata of a write to the key made now, keeping when the key was created.
+    fn stamp(&self, key: &Key, writer: Option<ClientId>) -> RecordMeta {
+        RecordMeta::stamp(self.memory.record_meta(key).as_ref(), unix_millis(), writer.map(|f| f.id()))
+    }
+    /// Makes the writes that were not synced right away durable, see [Durability].
+    pub async fn sync_pending(&self) -> Result<bool, NetworkError> {
+        self.storage().sync_pending().await
+    }
+    /// The sampled access frequency of the keys, which the storage tiers
+    /// consult to decide where a key lives.
+    /// Gets the flush counters of the store.
+    pub fn storage_stats(&self) -> StorageSnapshot {
+        self.storage().stats()
+    }
+    pub fn placement(&self) -> &AccessSampler {
+        &self.sampler
+    }
+    /// Persists the store to disk and syncs it.
+    pub async fn flush(&self) -> Result<(), NetworkError> {
+        let storage = self.storage();
+        storage.save().await?;
+        storage.sync().await
+    }
+    /// Migrates the store to a new location while it keeps serving.
+    ///
+    /// The records are copied across, then the writes that landed during
+    /// the copy are replayed until the new store has caught up. The switch
+    /// happens without yielding after the last check, so no write is lost.
+    pub async fn migrate<P, S>(&self, path: P, name: S) -> Result<MigrationReport, NetworkError>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        if path.as_ref().join(name.as_ref()).exists() {
+            return Err(NetworkError::Storage(StorageError::MigrationFailed("the target already exists".to_string())));
+        }
+        if !self.changes.start() {
+            return Err(NetworkError::Storage(StorageError::MigrationFailed("a migration is already running".to_string())));
+        }
+        let result = self.copy_to(path, name).await;
+        self.changes.stop();
+        result
+    }
+    async fn copy_to<P, S>(&self, path: P, name: S) -> Result<MigrationReport, NetworkError>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let target = DatabaseStorage::new(path, name).await?;
+        target.set_group_window(self.durability.group_window);
+        let records = self.storage().records().await;
+        let copied = records.len();
+        // The copy is synced regardless of the classes, the old store is dropped after it.
+        target.apply(records.into_iter().map(|(key, value)| Change::Insert(key, value)).collect(), &HashMap::new(), Durability::Sync).await?;
+
+        let mut replayed = 0;
+        for rounds in 0..MAX_CATCH_UP_ROUNDS {
+            let changes = self.changes.drain();
+            if changes.is_empty() {
+                // The metadata is taken over as of the switch, it is written out with the next change.
+                target.restore_metas(self.storage().record_metas());
+                *self.storage.borrow_mut() = Rc::new(target);
+                return Ok(MigrationReport { copied, replayed, rounds });
+            }
+            replayed += changes.len();
+            target.apply(changes, &HashMap::new(), Durability::Sync).await?;
+        }
+        Err(NetworkError::Storage(StorageError::MigrationFailed("writes outpaced the catch up".to_string())))
+    }
+    /// Counts the active subscriptions.
+    pub fn watcher_count(&self) -> usize {
+        self.memory.watcher_count()
+    }
+    /// The watched keys with how many clients watch each, see
+    /// [MemoryDatabase::watched_keys].
+    pub fn watched_keys(&self, key: Option<&Key>) -> Vec<WatchedKey> {
+        self.memory.watched_keys(key)
+    }
+    /// The trace of the request whose change handed the watchers of the
+    /// key the value, see [MemoryDatabase::trace_of].
+    pub fn trace_of(&self, key: &Key, value: Option<&Rc<Value>>) -> Option<u64> {
+        self.memory.trace_of(key, value)
+    }
+    /// Kills every subscription.
+    pub fn kill_watchers(&self) {
+        self.memory.kill_watchers();
+    }
     /// Releases a subscription.
     pub async fn release<K>(&self, key: K, id: ClientId) -> Result<(), NetworkError>
     where 
@@ -79,22 +1050,618 @@ impl Database {
         client: ClientId,
         behaviour: WatcherBehaviour,
         activity: WatcherActivity,
+        limit: WatcherLimit,
     ) -> Result<Watcher<WatchClient>, NetworkError>
     where
         K: Borrow<Key>,
     {
+        if let Some(value) = self.system_value(key.borrow()) {
+            let kickback = matches!(activity, WatcherActivity::Kickback).then_some(value);
+            return Ok(self.memory.subscribe_from(key.borrow(), client, behaviour, kickback, limit));
+        }
         Ok(self
             .memory
-            .subscribe(key, client, behaviour, activity)
+            .subscribe(key, client, behaviour, activity, limit)
             .await)
     }
+    /// Subscribes to a key and returns its current value along with the
+    /// version of the store it was read at.
+    pub async fn subscribe_snapshot<K>(
+        &self,
+        key: K,
+        client: ClientId,
+        behaviour: WatcherBehaviour,
+        limit: WatcherLimit,
+    ) -> (Watcher<WatchClient>, Option<Rc<Value>>, u64)
+    where
+        K: Borrow<Key>,
+    {
+        if let Some(value) = self.system_value(key.borrow()) {
+            return (self.memory.subscribe_from(key.borrow(), client, behaviour, None, limit), value, self.version());
+        }
+        self.memory.subscribe_snapshot(key, client, behaviour, limit).await
+    }
+}
+
+/// The time now in milliseconds since the unix epoch.
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|f| f.as_millis() as u64)
+        .unwrap_or_default()
 }
 
 #[cfg(test)]
 mod tests {
-    use overseer::models::{Key, Value};
+    use std::{collections::BTreeMap, time::Duration};
+
+    use overseer::{access::{WatcherActivity, WatcherBehaviour}, error::NetworkError, models::{DeleteCondition, DiffLine, Durability, Key, KeyFilter, RecordMeta, Revision, Schema, Value}};
+
+    use crate::{database::{lock_key, Change, Database, DurabilityPolicy, Quota, QuotaUsage, SplitPolicy, SEQUENCE_BLOCK, WatcherLimit}, net::ClientId};
+
+    use super::unix_millis;
+
+    #[monoio::test]
+    pub async fn test_migrate() {
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir().unwrap();
+        let da = Database::new(from.path(), "test.db").await.unwrap();
+        da.insert(Key::from_str("hello"), Value::Integer(21), None).await.unwrap();
+
+        let report = da.migrate(to.path(), "test.db").await.unwrap();
+        assert_eq!(report.copied, 1);
+
+        // Writes after the switch land in the new store.
+        da.insert(Key::from_str("world"), Value::Integer(22), None).await.unwrap();
+        let moved = Database::new(to.path(), "test.db").await.unwrap();
+        assert_eq!(*moved.get(Key::from_str("hello")).await.unwrap(), Value::Integer(21));
+        assert_eq!(*moved.get(Key::from_str("world")).await.unwrap(), Value::Integer(22));
+
+        // The target must not already exist.
+        assert!(da.migrate(to.path(), "test.db").await.is_err());
+    }
+
+    #[monoio::test]
+    pub async fn test_list_keys() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        for key in ["c", "a", "b"] {
+            da.insert(Key::from_str(key), Value::Integer(1), None).await.unwrap();
+        }
+
+        let (keys, cursor) = da.keys(None, 2);
+        assert_eq!(keys, vec![Key::from_str("a"), Key::from_str("b")]);
+        assert_eq!(cursor, Some(Key::from_str("b")));
+
+        let (keys, cursor) = da.keys(cursor.as_ref(), 2);
+        assert_eq!(keys, vec![Key::from_str("c")]);
+        assert_eq!(cursor, None);
+    }
+
+    #[monoio::test]
+    pub async fn test_key_history_diff() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let key = Key::from_str("app.config");
+        da.insert(&key, Value::String("mode = a\nport = 80".into()), None).await.unwrap();
+        let first = da.memory.version();
+        da.insert(&key, Value::String("mode = b\nport = 80".into()), None).await.unwrap();
+        let second = da.memory.version();
+
+        let diff = da.history().diff(&key, Revision::Version(first), Revision::Version(second)).unwrap();
+        assert_eq!(diff.lines, vec![
+            DiffLine::Removed("mode = a".to_string()),
+            DiffLine::Added("mode = b".to_string()),
+            DiffLine::Kept("port = 80".to_string())
+        ]);
+    }
+
+    #[monoio::test]
+    pub async fn test_value_at() {
+        let tf = tempfile::tempdir().unwrap();
+        let (mode, port) = (Key::from_str("app.mode"), Key::from_str("app.port"));
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        da.insert(&mode, Value::String("a".into()), None).await.unwrap();
+        da.insert(&port, Value::Integer(80), None).await.unwrap();
+        da.storage().save().await.unwrap();
+        drop(da);
+
+        // The keys loaded at startup hold their value from then on.
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let started = da.memory.version();
+        assert_eq!(da.value_at(&mode, Revision::Version(started)).await.unwrap().as_deref(), Some(&Value::String("a".into())));
+        assert_eq!(da.value_at(&Key::from_str("app.host"), Revision::Version(started)).await, Some(None));
+
+        da.insert(&mode, Value::String("b".into()), None).await.unwrap();
+        let changed = da.memory.version();
+        da.delete(&mode).await.unwrap();
+        assert_eq!(da.value_at(&mode, Revision::Version(changed)).await.unwrap().as_deref(), Some(&Value::String("b".into())));
+        assert_eq!(da.value_at(&mode, Revision::Version(changed + 1)).await, Some(None));
+        assert_eq!(da.value_at(&port, Revision::Version(changed)).await.unwrap().as_deref(), Some(&Value::Integer(80)));
+
+        // What the key held before its first recorded change is not known.
+        assert!(da.value_at(&mode, Revision::Version(started)).await.is_none());
+    }
+
+    #[monoio::test]
+    pub async fn test_writes_apply_in_order() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let key = Key::from_str("hello");
+
+        let (first, second) = monoio::join!(
+            da.insert(&key, Value::Integer(1), None),
+            da.insert(&key, Value::Integer(2), None)
+        );
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(*da.get(&key).await.unwrap(), Value::Integer(2));
+
+        // The store saw the writes in the same order as the memory backend.
+        da.flush().await.unwrap();
+        let reopened = Database::new(tf.path(), "test.db").await.unwrap();
+        assert_eq!(*reopened.get(&key).await.unwrap(), Value::Integer(2));
+    }
+
+    #[monoio::test]
+    pub async fn test_get_or_insert() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let key = Key::from_str("app.mode");
+
+        // Concurrent initializers agree on the first default.
+        let (first, second) = monoio::join!(
+            da.get_or_insert(&key, Value::Integer(1), None),
+            da.get_or_insert(&key, Value::Integer(2), None)
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert_eq!((*first.0).clone(), Value::Integer(1));
+        assert_eq!((*second.0).clone(), Value::Integer(1));
+        assert!(first.2 && !second.2);
+        assert_eq!((first.1, second.1), (1, 1));
+        assert_eq!(da.version(), 1);
+
+        da.flush().await.unwrap();
+        let reopened = Database::new(tf.path(), "test.db").await.unwrap();
+        assert_eq!(*reopened.get(&key).await.unwrap(), Value::Integer(1));
+    }
+
+    #[monoio::test]
+    pub async fn test_insert_if_version() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let key = Key::from_str("app.mode");
+
+        // Zero expects the key to hold nothing.
+        let created = da.insert_if_version(&key, Value::Integer(1), 0, None).await.unwrap();
+        assert!(matches!(da.insert_if_version(&key, Value::Integer(2), 0, None).await, Err(NetworkError::VersionConflict(v)) if v == created));
+
+        // Two agents read the same version, only the first write lands.
+        let (first, second) = monoio::join!(
+            da.insert_if_version(&key, Value::Integer(2), created, None),
+            da.insert_if_version(&key, Value::Integer(3), created, None)
+        );
+        let updated = first.unwrap();
+        assert!(updated > created);
+        assert!(matches!(second, Err(NetworkError::VersionConflict(v)) if v == updated));
+        assert_eq!(da.get_versioned(&key).map(|(value, version)| ((*value).clone(), version)), Some((Value::Integer(2), updated)));
+
+        // Versions keep going up across a delete.
+        da.delete(&key).await.unwrap();
+        assert!(da.insert(&key, Value::Integer(4), None).await.unwrap() > updated);
+    }
+
+    #[monoio::test]
+    pub async fn test_split_counter() {
+        let tf = tempfile::tempdir().unwrap();
+        let mut da = Database::new(tf.path(), "test.db").await.unwrap();
+        da.set_split_policy(SplitPolicy::default().with_prefix("stats.", 4));
+        let (hits, mode) = (Key::from_str("stats.hits"), Key::from_str("app.mode"));
+
+        for _ in 0..10 {
+            da.increment(&hits, 3, None).await.unwrap();
+        }
+        assert_eq!(da.get(&hits).await.as_deref(), Some(&Value::Integer(30)));
+        assert_eq!(da.get(&Key::from_str("stats.hits#1")).await.as_deref(), Some(&Value::Integer(9)));
+
+        // An insert resets the counter, a key that is not split is bumped in place.
+        da.insert(&hits, Value::Integer(5), None).await.unwrap();
+        let (value, _) = da.increment(&hits, 1, None).await.unwrap();
+        assert_eq!(*value, Value::Integer(6));
+        assert_eq!(*da.increment(&mode, -2, None).await.unwrap().0, Value::Integer(-2));
+
+        // Anything but an integer is left alone.
+        da.insert(&mode, Value::String("on".into()), None).await.unwrap();
+        assert_eq!(*da.increment(&mode, 1, None).await.unwrap().0, Value::String("on".into()));
+    }
+
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_record_meta() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let key = Key::from_str("app.mode");
+        da.insert(&key, Value::Integer(1), Some(ClientId::from_id(7))).await.unwrap();
+        let created = da.meta(&key).await.record.unwrap();
+        assert_eq!(created.writer, Some(7));
+        assert_eq!(created.created_at, created.modified_at);
 
-    use crate::database::Database;
+        // A later write keeps when the key was created.
+        monoio::time::sleep(Duration::from_millis(5)).await;
+        da.insert(&key, Value::Integer(2), Some(ClientId::from_id(8))).await.unwrap();
+        let (_, _, modified) = da.get_with_meta(&key).unwrap();
+        let modified = modified.unwrap();
+        assert_eq!((modified.created_at, modified.writer), (created.created_at, Some(8)));
+        assert!(modified.modified_at > created.modified_at);
+
+        // The server writes batches itself, a delete forgets the record.
+        da.apply_batch(vec![Change::Insert(key.clone(), Value::Integer(3))]).await.unwrap();
+        assert_eq!(da.meta(&key).await.record.unwrap().writer, None);
+        da.insert(Key::from_str("app.port"), Value::Integer(80), Some(ClientId::from_id(9))).await.unwrap();
+        da.delete(&key).await.unwrap();
+        assert!(da.meta(&key).await.record.is_none());
+
+        // The metadata survives a restart.
+        da.flush().await.unwrap();
+        let reopened = Database::new(tf.path(), "test.db").await.unwrap();
+        assert_eq!(reopened.meta(&Key::from_str("app.port")).await.record.unwrap().writer, Some(9));
+    }
+
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_archive() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        for key in ["app.mode", "legacy.mode", "legacy.port"] {
+            da.insert(Key::from_str(key), Value::Integer(1), None).await.unwrap();
+        }
+        monoio::time::sleep(Duration::from_millis(5)).await;
+        let cutoff = unix_millis();
+        monoio::time::sleep(Duration::from_millis(5)).await;
+
+        // A read keeps a key in use as much as a write does.
+        da.get(Key::from_str("app.mode")).await.unwrap();
+        da.insert(Key::from_str("legacy.port"), Value::Integer(2), None).await.unwrap();
+        let archived = da.archive(cutoff, false).await.unwrap();
+        assert_eq!(archived, vec![(Key::from_str("legacy.mode"), Value::Integer(1))]);
+        assert_eq!(da.keys(None, 10).0.len(), 3);
+
+        // An export is not a read, so the key can still be deleted.
+        assert_eq!(da.archive(cutoff, true).await.unwrap(), archived);
+        assert!(da.get(Key::from_str("legacy.mode")).await.is_none());
+        assert!(da.archive(cutoff, true).await.unwrap().is_empty());
+        assert_eq!(da.keys(None, 10).0.len(), 2);
+    }
+
+    #[monoio::test]
+    pub async fn test_work_queue() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let visibility = Duration::from_secs(30);
+        let (first, _) = da.enqueue("jobs", Value::Integer(1)).await.unwrap();
+        let (second, _) = da.enqueue("jobs", Value::Integer(2)).await.unwrap();
+        // A key under the queue that is not an item is left alone.
+        da.insert(Key::from_str("jobs.config"), Value::Integer(0), None).await.unwrap();
+
+        let leased = da.dequeue("jobs", visibility).unwrap();
+        assert_eq!((&leased.key, &leased.value), (&first, &Value::Integer(1)));
+        assert_eq!(da.dequeue("jobs", visibility).unwrap().key, second);
+        assert!(da.dequeue("jobs", visibility).is_none());
+
+        // A nack hands the item to the next consumer, the old lease is void.
+        assert!(da.nack(&first, leased.lease));
+        let retried = da.dequeue("jobs", visibility).unwrap();
+        assert_eq!(retried.key, first);
+        assert!(!da.ack(&first, leased.lease).await.unwrap());
+        assert!(da.ack(&first, retried.lease).await.unwrap());
+        assert!(da.get(&first).await.is_none());
+
+        // The leases are gone after a restart, the items are not.
+        da.flush().await.unwrap();
+        let reopened = Database::new(tf.path(), "test.db").await.unwrap();
+        assert_eq!(reopened.dequeue("jobs", visibility).unwrap().key, second);
+        assert!(reopened.enqueue("jobs", Value::Integer(3)).await.unwrap().0 > second);
+    }
+
+    #[monoio::test]
+    pub async fn test_delete_written_by() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let (first, second) = (ClientId::from_id(1), ClientId::from_id(2));
+        let (mine, taken) = (Key::from_str("presence.a"), Key::from_str("presence.b"));
+        da.insert(&mine, Value::Integer(1), Some(first)).await.unwrap();
+        da.insert(&taken, Value::Integer(1), Some(first)).await.unwrap();
+        da.insert(&taken, Value::Integer(2), Some(second)).await.unwrap();
+
+        assert!(da.delete_written_by(&mine, first).await.unwrap());
+        assert!(da.get(&mine).await.is_none());
+        // Another client wrote over the key, so it is theirs now.
+        assert!(!da.delete_written_by(&taken, first).await.unwrap());
+        assert_eq!(*da.get(&taken).await.unwrap(), Value::Integer(2));
+        assert!(!da.delete_written_by(&mine, first).await.unwrap());
+    }
+
+    #[monoio::test]
+    pub async fn test_quota() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let (a, b, c) = (Key::from_str("a"), Key::from_str("b"), Key::from_str("c"));
+        da.set_quota(Quota::default().with_max_keys(2).with_max_value_size(16).with_max_bytes(20));
+        da.insert(&a, Value::Integer(1), None).await.unwrap();
+        da.insert(&b, Value::String("four".into()), None).await.unwrap();
+        assert_eq!(da.usage(), QuotaUsage { keys: 2, bytes: 1 + 8 + 1 + 4 });
+
+        assert!(matches!(da.insert(&c, Value::Integer(1), None).await, Err(NetworkError::QuotaExceeded(..))));
+        assert!(matches!(da.get_or_insert(&c, Value::Integer(1), None).await, Err(NetworkError::QuotaExceeded(..))));
+        assert!(da.insert(&a, Value::String("x".repeat(17).into()), None).await.is_err());
+        assert!(da.insert(&a, Value::String("x".repeat(16).into()), None).await.is_err());
+        // Overwriting a key adds no key and a smaller value fits again.
+        da.insert(&a, Value::String("x".repeat(8).into()), None).await.unwrap();
+        assert!(da.bulk_load([(c.clone(), Value::Integer(1))], None).await.is_err());
+        assert!(da.get(&c).await.is_none());
+
+        da.delete(&b).await.unwrap();
+        da.rename(&a, &c, false).await.unwrap();
+        assert_eq!(da.usage(), QuotaUsage { keys: 1, bytes: 1 + 8 });
+        da.insert(&a, Value::Integer(2), None).await.unwrap();
+    }
+
+    #[monoio::test]
+    pub async fn test_snapshot_reads() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let keys: Vec<Key> = (0..4).map(|i| Key::from_owned(format!("key-{i}"))).collect();
+        for (i, key) in keys.iter().enumerate() {
+            da.insert(key, Value::Integer(i as i64), None).await.unwrap();
+        }
+        let snapshot = da.snapshot();
+        let (first, cursor) = da.keys_at(&snapshot, None, 2);
+
+        // The writes in the middle of the scan are not seen by it.
+        da.delete(&keys[2]).await.unwrap();
+        da.insert(&keys[3], Value::Integer(30), None).await.unwrap();
+        da.insert(&Key::from_str("key-9"), Value::Integer(9), None).await.unwrap();
+        da.rename(&keys[0], &Key::from_str("key-5"), false).await.unwrap();
+        let (rest, _) = da.export_at(&snapshot, cursor.as_ref(), 10);
+        let rest: Vec<_> = rest.into_iter().map(|f| (f.key, f.value)).collect();
+        assert_eq!(first, keys[..2]);
+        assert_eq!(rest, [(keys[2].clone(), Value::Integer(2)), (keys[3].clone(), Value::Integer(3))]);
+        assert_eq!(*da.get_at(&snapshot, &keys[0]).unwrap().0, Value::Integer(0));
+        assert!(da.get(&keys[0]).await.is_none());
+
+        // The old records go with the last snapshot reading them.
+        assert!(da.memory.retained_versions() > 0);
+        drop(snapshot);
+        assert_eq!(da.memory.retained_versions(), 0);
+        da.insert(&keys[3], Value::Integer(31), None).await.unwrap();
+        assert_eq!(da.memory.retained_versions(), 0);
+    }
+
+    #[monoio::test]
+    pub async fn test_rename() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let (from, to, taken) = (Key::from_str("app.mode"), Key::from_str("app.theme"), Key::from_str("app.size"));
+        let version = da.insert(&from, Value::Integer(1), Some(ClientId::from_id(1))).await.unwrap();
+        da.insert(&taken, Value::Integer(2), None).await.unwrap();
+        // Reads move the access time on, the rest of the metadata stays.
+        let written = |meta: Option<RecordMeta>| meta.map(|f| (f.created_at, f.modified_at, f.writer));
+        let meta = written(da.get_with_meta(&from).unwrap().2);
+        let from_watcher = da.subscribe(&from, ClientId::from_id(3), WatcherBehaviour::Ordered, WatcherActivity::Lazy, WatcherLimit::default()).await.unwrap();
+        let to_watcher = da.subscribe(&to, ClientId::from_id(3), WatcherBehaviour::Ordered, WatcherActivity::Lazy, WatcherLimit::default()).await.unwrap();
+
+        // The record moves with its version and metadata.
+        assert_eq!(da.rename(&from, &to, false).await.unwrap(), Some(version));
+        assert!(da.get(&from).await.is_none());
+        let (value, moved, moved_meta) = da.get_with_meta(&to).unwrap();
+        assert_eq!((&*value, moved, written(moved_meta)), (&Value::Integer(1), version, meta));
+        assert_eq!(from_watcher.drain().into_iter().map(|f| f.is_some()).collect::<Vec<_>>(), [false]);
+        assert_eq!(to_watcher.drain().into_iter().map(|f| f.map(|f| (*f).clone())).collect::<Vec<_>>(), [Some(Value::Integer(1))]);
+
+        // A key holding nothing moves nothing, a taken target needs overwrite.
+        assert_eq!(da.rename(&from, &to, false).await.unwrap(), None);
+        let current = da.get_versioned(&taken).unwrap().1;
+        assert!(matches!(da.rename(&to, &taken, false).await, Err(NetworkError::VersionConflict(v)) if v == current));
+        assert_eq!(da.rename(&to, &taken, true).await.unwrap(), Some(version));
+        assert_eq!(*da.get(&taken).await.unwrap(), Value::Integer(1));
+        assert!(da.get(&to).await.is_none());
+    }
+
+    #[monoio::test]
+    pub async fn test_locks_notify_watchers() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let key = Key::from_str("leader");
+        let (first, second) = (ClientId::from_id(1), ClientId::from_id(2));
+        let watcher = da.subscribe(lock_key(&key), ClientId::from_id(3), WatcherBehaviour::Ordered, WatcherActivity::Lazy, WatcherLimit::default()).await.unwrap();
+        let record = da.subscribe(&key, ClientId::from_id(3), WatcherBehaviour::Ordered, WatcherActivity::Lazy, WatcherLimit::default()).await.unwrap();
+
+        let token = da.acquire(&key, first, Duration::from_secs(30)).await.unwrap();
+        assert_eq!(da.get(lock_key(&key)).await.as_deref(), Some(&Value::Integer(1)));
+        assert!(da.acquire(&key, second, Duration::from_secs(30)).await.is_none());
+        // A renewal is not a transition.
+        assert_eq!(da.acquire(&key, first, Duration::from_secs(30)).await, Some(token));
+        assert!(!da.release_lock(&key, second).await);
+        assert_eq!(da.release_locks(first).await, 1);
+        assert!(da.acquire(&key, second, Duration::ZERO).await.unwrap() > token);
+        assert_eq!(da.expire_locks().await, 1);
+
+        let transitions: Vec<Option<Value>> = watcher.drain().into_iter().map(|f| f.map(|f| (*f).clone())).collect();
+        assert_eq!(transitions, vec![Some(Value::Integer(1)), None, Some(Value::Integer(2)), None]);
+        // A lock never reaches the store, nor the watchers of the key.
+        assert!(da.get(&key).await.is_none());
+        assert!(da.get(lock_key(&key)).await.is_none());
+        assert!(record.drain().is_empty());
+    }
+
+    #[monoio::test]
+    pub async fn test_bulk_load() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let watched = Key::from_str("seed.0");
+        let watcher = da.subscribe(&watched, ClientId::from_id(1), WatcherBehaviour::Ordered, WatcherActivity::Lazy, WatcherLimit::default()).await.unwrap();
+
+        let records = (0..1000).map(|f| (Key::from_str(format!("seed.{f}")), Value::Integer(f)));
+        assert_eq!(da.bulk_load(records, None).await.unwrap(), 1000);
+        assert_eq!(*da.get(&watched).await.unwrap(), Value::Integer(0));
+        assert_eq!(da.get_versioned(&Key::from_str("seed.999")).unwrap().1, 1000);
+        // The watchers are not told.
+        assert!(watcher.drain().is_empty());
+
+        // The records are on disk after the one write.
+        drop(da);
+        let reopened = Database::new(tf.path(), "test.db").await.unwrap();
+        assert_eq!(reopened.keys(None, 2000).0.len(), 1000);
+        assert!(reopened.get_with_meta(&watched).unwrap().2.is_some());
+    }
+
+    #[monoio::test]
+    pub async fn test_insert_all() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let (host, port) = (Key::from_str("app.host"), Key::from_str("app.port"));
+        let watcher = da.subscribe(&port, ClientId::from_id(2), WatcherBehaviour::Ordered, WatcherActivity::Lazy, WatcherLimit::default()).await.unwrap();
+        da.schemas().register("app.port", Some(Schema::integer(Some(1), None))).await.unwrap();
+
+        // One write that does not pass leaves every key alone.
+        let refused = vec![(host.clone(), Value::String("a".into())), (port.clone(), Value::Integer(0))];
+        assert!(matches!(da.insert_all(refused, None).await, Err(NetworkError::ValidationFailed(..))));
+        assert!(da.get(&host).await.is_none());
+
+        let writer = ClientId::from_id(1);
+        let records = vec![(host.clone(), Value::String("b".into())), (port.clone(), Value::Integer(80))];
+        assert_eq!(da.insert_all(records, Some(writer)).await.unwrap(), vec![1, 2]);
+        assert_eq!(*da.get(&host).await.unwrap(), Value::String("b".into()));
+        assert_eq!(da.get_with_meta(&port).unwrap().2.unwrap().writer, Some(writer.id()));
+        let notified: Vec<_> = watcher.drain().into_iter().map(|f| f.map(|f| (*f).clone())).collect();
+        assert_eq!(notified, vec![Some(Value::Integer(80))]);
+    }
+
+    #[monoio::test]
+    pub async fn test_delete_if() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let key = Key::from_str("jobs.lease");
+        let owner = |name: &str| DeleteCondition::Value(Value::String(name.into()));
+        assert!(!da.delete_if(&key, &DeleteCondition::Version(0)).await.unwrap());
+
+        let version = da.insert(&key, Value::String("worker-1".into()), None).await.unwrap();
+        assert!(!da.delete_if(&key, &owner("worker-2")).await.unwrap());
+        assert!(da.delete_if(&key, &owner("worker-1")).await.unwrap());
+        assert!(da.get(&key).await.is_none());
+
+        // A key someone rewrote in the meantime stays.
+        da.insert(&key, Value::String("worker-1".into()), None).await.unwrap();
+        let rewritten = da.insert(&key, Value::String("worker-1".into()), None).await.unwrap();
+        assert!(!da.delete_if(&key, &DeleteCondition::Version(version)).await.unwrap());
+        assert!(da.delete_if(&key, &DeleteCondition::Version(rewritten)).await.unwrap());
+        assert!(da.get(&key).await.is_none());
+    }
+
+    #[monoio::test]
+    pub async fn test_find_keys() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        for key in ["app.db.port", "app.mode", "app.web.port", "apps.web.port", "web.port"] {
+            da.insert(Key::from_str(key), Value::Integer(1), None).await.unwrap();
+        }
+        let listed = |keys: Vec<Key>| keys.iter().map(|f| f.as_str().to_string()).collect::<Vec<_>>();
+
+        let glob = KeyFilter::glob("app.*.port").compile().unwrap();
+        let (keys, cursor) = da.find_keys(&glob, None, 1).unwrap();
+        assert_eq!((listed(keys), cursor.clone()), (vec!["app.db.port".to_string()], Some(Key::from_str("app.db.port"))));
+        let (keys, cursor) = da.find_keys(&glob, cursor.as_ref(), 10).unwrap();
+        assert_eq!((listed(keys), cursor), (vec!["app.web.port".to_string()], None));
+
+        let regex = KeyFilter::regex("web\\.port$").compile().unwrap();
+        assert_eq!(listed(da.find_keys(&regex, None, 10).unwrap().0), vec!["app.web.port", "apps.web.port", "web.port"]);
+
+        // A page stops after the keys it may look at.
+        let (keys, cursor) = da.memory.find_keys(&regex, None, 10, 2).unwrap();
+        assert_eq!((keys, cursor), (vec![], Some(Key::from_str("app.mode"))));
+    }
+
+    #[monoio::test]
+    pub async fn test_stats() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        da.insert(Key::from_str("app.mode"), Value::String("dark".into()), None).await.unwrap();
+        da.insert(Key::from_str("app.port"), Value::Integer(80), None).await.unwrap();
+        da.storage().save().await.unwrap();
+
+        let stats = da.stats();
+        assert_eq!((stats.keys, stats.value_bytes), (2, 12));
+        assert_eq!(stats.prefixes.get("app"), Some(&2));
+        assert!(stats.storage_bytes > 0);
+    }
+
+    #[monoio::test]
+    pub async fn test_sequences_survive_reopen() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        assert_eq!(da.sequences().next("jobs").await.unwrap(), 1);
+        assert_eq!(da.sequences().next("jobs").await.unwrap(), 2);
+        drop(da);
+
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        assert_eq!(da.sequences().next("jobs").await.unwrap(), SEQUENCE_BLOCK + 1);
+    }
+
+    #[monoio::test]
+    pub async fn test_secondary_index_follows_writes() {
+        let tf = tempfile::tempdir().unwrap();
+        let da = Database::new(tf.path(), "test.db").await.unwrap();
+        let host = |region: &str| Value::Map(BTreeMap::from([("region".to_string(), Value::String(region.into()))]));
+        let (eu, us) = (Value::String("eu".into()), Value::String("us".into()));
+        let (a, b, c) = (Key::from_str("host.a"), Key::from_str("host.b"), Key::from_str("host.c"));
+
+        // Records written before the index is declared are indexed too.
+        da.insert(&a, host("eu"), None).await.unwrap();
+        da.declare_index("region");
+        assert_eq!(da.indexes(), vec!["region".to_string()]);
+        da.insert(&b, host("eu"), None).await.unwrap();
+        assert_eq!(da.query_index("region", &eu), Some(vec![a.clone(), b.clone()]));
+
+        da.apply_batch(vec![
+            Change::Insert(a.clone(), host("us")),
+            Change::Insert(c.clone(), host("us")),
+            Change::Delete(b.clone())
+        ]).await.unwrap();
+        assert_eq!(da.query_index("region", &eu), Some(vec![]));
+        assert_eq!(da.query_index("region", &us), Some(vec![a.clone(), c.clone()]));
+
+        da.delete(&c).await.unwrap();
+        assert_eq!(da.query_index("region", &us), Some(vec![a]));
+        assert_eq!(da.query_index("zone", &us), None);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_group_commit() {
+        let tf = tempfile::tempdir().unwrap();
+        let mut da = Database::new(tf.path(), "test.db").await.unwrap();
+        da.set_durability_policy(DurabilityPolicy::default()
+            .with_default(Durability::Sync)
+            .with_group_window(Duration::from_millis(20)));
+        let keys: Vec<_> = (0..4).map(|f| Key::from_str(format!("app.{f}"))).collect();
+
+        // The writes arriving within the window share one checkpoint.
+        let written = monoio::join!(
+            da.insert(&keys[0], Value::Integer(0), None),
+            da.insert(&keys[1], Value::Integer(1), None),
+            da.insert(&keys[2], Value::Integer(2), None),
+            da.insert(&keys[3], Value::Integer(3), None)
+        );
+        assert!([written.0, written.1, written.2, written.3].iter().all(Result::is_ok));
+        assert_eq!(da.storage().checkpoints(), 1);
+
+        // Every write was synced before it was answered.
+        let reopened = Database::new(tf.path(), "test.db").await.unwrap();
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(*reopened.get(key).await.unwrap(), Value::Integer(i as i64));
+        }
+
+        // A lone write still checkpoints on its own.
+        da.insert(&keys[0], Value::Integer(4), None).await.unwrap();
+        assert_eq!(da.storage().checkpoints(), 2);
+    }
 
     // #[tokio::test]
     // pub async fn test_database_persistence() {
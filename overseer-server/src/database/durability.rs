@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use overseer::models::{Durability, Key};
+
+
+/// How often the writes that were not synced right away are made durable.
+pub const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a commit waits for more writes by default, none, so only the
+/// writes waiting on a running checkpoint are grouped.
+pub const DEFAULT_GROUP_WINDOW: Duration = Duration::ZERO;
+
+/// Assigns every key a [Durability] class by its prefix.
+///
+/// Critical keys can be synced on every write while high churn keys, such as
+/// telemetry, are only written out periodically. The longest prefix a key
+/// starts with decides its class, a key matching none gets the default.
+#[derive(Clone, PartialEq, Debug)]
+pub struct DurabilityPolicy {
+    /// The class of the keys no prefix matches.
+    pub default: Durability,
+    /// The classes of the keys under each prefix.
+    pub classes: Vec<(String, Durability)>,
+    /// How often the periodic and best effort writes are made durable.
+    pub interval: Duration,
+    /// How long the synced and periodic writes wait for more writes to
+    /// share their checkpoint.
+    pub group_window: Duration
+}
+
+impl Default for DurabilityPolicy {
+    fn default() -> Self {
+        Self {
+            default: Durability::default(),
+            classes: vec![],
+            interval: DEFAULT_SYNC_INTERVAL,
+            group_window: DEFAULT_GROUP_WINDOW
+        }
+    }
+}
+
+impl DurabilityPolicy {
+    /// Assigns a class to the keys under a prefix, replacing the class
+    /// the prefix had.
+    pub fn with_class<S>(mut self, prefix: S, durability: Durability) -> Self
+    where
+        S: Into<String>
+    {
+        let prefix = prefix.into();
+        self.classes.retain(|(existing, _)| *existing != prefix);
+        self.classes.push((prefix, durability));
+        self
+    }
+    pub fn with_default(mut self, durability: Durability) -> Self {
+        self.default = durability;
+        self
+    }
+    /// # Panics
+    /// If the interval is zero.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "The sync interval must be positive.");
+        self.interval = interval;
+        self
+    }
+    /// Lets the writes arriving within the window share one checkpoint,
+    /// each write waiting for it at most once.
+    pub fn with_group_window(mut self, window: Duration) -> Self {
+        self.group_window = window;
+        self
+    }
+    /// The class of the key.
+    pub fn class_of(&self, key: &Key) -> Durability {
+        self.classes.iter()
+            .filter(|(prefix, _)| key.as_str().starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, durability)| *durability)
+            .unwrap_or(self.default)
+    }
+    /// The strongest class among the keys, which a batch touching all of them gets.
+    pub fn class_of_all<'a, I>(&self, keys: I) -> Durability
+    where
+        I: IntoIterator<Item = &'a Key>
+    {
+        keys.into_iter().map(|f| self.class_of(f)).min().unwrap_or(self.default)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::models::{Durability, Key};
+
+    use super::DurabilityPolicy;
+
+    #[test]
+    pub fn test_durability_classes() {
+        let policy = DurabilityPolicy::default()
+            .with_class("billing.", Durability::Sync)
+            .with_class("billing.cache.", Durability::BestEffort)
+            .with_class("telemetry.", Durability::BestEffort);
+
+        assert_eq!(policy.class_of(&Key::from_str("billing.plan")), Durability::Sync);
+        // The longest prefix wins.
+        assert_eq!(policy.class_of(&Key::from_str("billing.cache.hits")), Durability::BestEffort);
+        assert_eq!(policy.class_of(&Key::from_str("app.mode")), Durability::Periodic);
+
+        let keys = [Key::from_str("telemetry.cpu"), Key::from_str("billing.plan")];
+        assert_eq!(policy.class_of_all(&keys), Durability::Sync);
+    }
+}
@@ -0,0 +1,254 @@
+use std::{cell::RefCell, collections::{HashMap, VecDeque}, rc::Rc, time::{SystemTime, UNIX_EPOCH}};
+
+use overseer::models::{HistoryEntry, Key, KeyDiff, Revision, Value};
+
+
+/// How many changes of each key are kept before the oldest is forgotten.
+pub const KEY_HISTORY_DEPTH: usize = 32;
+
+/// How many changes the history keeps of every key, by prefix.
+///
+/// The longest prefix a key starts with decides its depth, a key matching
+/// none gets the default. A depth of zero keeps no history of the key.
+#[derive(Clone, PartialEq, Debug)]
+pub struct HistoryRetention {
+    /// The depth of the keys no prefix matches.
+    pub default: usize,
+    /// The depths of the keys under each prefix.
+    pub depths: Vec<(String, usize)>
+}
+
+impl Default for HistoryRetention {
+    fn default() -> Self {
+        Self {
+            default: KEY_HISTORY_DEPTH,
+            depths: vec![]
+        }
+    }
+}
+
+impl HistoryRetention {
+    /// Keeps the last changes of the keys under a prefix, replacing the
+    /// depth the prefix had.
+    pub fn with_depth<S>(mut self, prefix: S, depth: usize) -> Self
+    where
+        S: Into<String>
+    {
+        let prefix = prefix.into();
+        self.depths.retain(|(existing, _)| *existing != prefix);
+        self.depths.push((prefix, depth));
+        self
+    }
+    pub fn with_default(mut self, depth: usize) -> Self {
+        self.default = depth;
+        self
+    }
+    /// How many changes of the key are kept.
+    pub fn depth_of(&self, key: &Key) -> usize {
+        self.depths.iter()
+            .filter(|(prefix, _)| key.as_str().starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, depth)| *depth)
+            .unwrap_or(self.default)
+    }
+}
+
+/// A change to a key.
+#[derive(Clone, Debug)]
+pub struct KeyChange {
+    /// The version of the store the change produced.
+    pub version: u64,
+    /// When the change was made, in milliseconds since the unix epoch.
+    pub at: u64,
+    /// The value after the change, [None] if the key was deleted.
+    pub value: Option<Rc<Value>>
+}
+
+/// A bounded record of the recent changes to every key.
+///
+/// The keys loaded at startup have no recorded change, so a key can be
+/// looked up from its first change made while the server was running.
+pub struct KeyHistory {
+    changes: RefCell<HashMap<Key, VecDeque<KeyChange>>>,
+    /// The version of the last change evicted from each key.
+    evicted: RefCell<HashMap<Key, u64>>,
+    retention: HistoryRetention,
+    /// The version and time the recording began at, every change since
+    /// then is recorded.
+    started: (u64, u64)
+}
+
+impl Default for KeyHistory {
+    fn default() -> Self {
+        Self::with_retention(HistoryRetention::default())
+    }
+}
+
+impl KeyHistory {
+    /// Keeps the same number of changes of every key.
+    pub fn with_depth(depth: usize) -> Self {
+        Self::with_retention(HistoryRetention::default().with_default(depth))
+    }
+    pub fn with_retention(retention: HistoryRetention) -> Self {
+        Self {
+            changes: RefCell::new(HashMap::new()),
+            evicted: RefCell::default(),
+            retention,
+            started: (0, now())
+        }
+    }
+    /// Begins the recording at the version, the last one of the records
+    /// the database was loaded with.
+    pub fn starting_at(mut self, version: u64) -> Self {
+        self.started = (version, now());
+        self
+    }
+    /// Records a change that was just made, evicting the oldest of the key if full.
+    pub fn record(&self, key: &Key, version: u64, value: Option<Rc<Value>>) {
+        let depth = self.retention.depth_of(key);
+        if depth == 0 {
+            return;
+        }
+        let at = now();
+
+        let mut changes = self.changes.borrow_mut();
+        let changes = changes.entry(key.clone()).or_default();
+        while changes.len() >= depth {
+            if let Some(evicted) = changes.pop_front() {
+                self.evicted.borrow_mut().insert(key.clone(), evicted.version);
+            }
+        }
+        changes.push_back(KeyChange { version, at, value });
+    }
+    /// The recorded changes to a key, oldest first.
+    pub fn changes(&self, key: &Key) -> Vec<KeyChange> {
+        self.changes.borrow().get(key).map(|f| f.iter().cloned().collect()).unwrap_or_default()
+    }
+    /// The changes to a key after the version, oldest first, or [None] if
+    /// some of them are no longer kept.
+    pub fn since(&self, key: &Key, after: u64) -> Option<Vec<KeyChange>> {
+        if self.retention.depth_of(key) == 0 || self.evicted.borrow().get(key).is_some_and(|f| *f > after) {
+            return None;
+        }
+        let changes = self.changes.borrow();
+        let Some(changes) = changes.get(key) else {
+            return Some(vec![]);
+        };
+        Some(changes.iter().filter(|f| f.version > after).cloned().collect())
+    }
+    /// The last recorded changes to a key, oldest first. A limit of zero
+    /// returns every change that is kept.
+    pub fn entries(&self, key: &Key, limit: usize) -> Vec<HistoryEntry> {
+        let changes = self.changes.borrow();
+        let Some(changes) = changes.get(key) else {
+            return vec![];
+        };
+        let skip = match limit {
+            0 => 0,
+            limit => changes.len().saturating_sub(limit)
+        };
+        changes.iter().skip(skip).map(|f| HistoryEntry {
+            value: f.value.as_deref().cloned(),
+            version: f.version,
+            at: f.at
+        }).collect()
+    }
+    /// What the key held at the revision, or [None] if the revision is
+    /// older than the recorded changes.
+    pub fn at(&self, key: &Key, revision: Revision) -> Option<Option<Rc<Value>>> {
+        let changes = self.changes.borrow();
+        let change = changes.get(key)?.iter().rev().find(|f| match revision {
+            Revision::Version(version) => f.version <= version,
+            Revision::Timestamp(at) => f.at <= at
+        })?;
+        Some(change.value.clone())
+    }
+    /// Whether the key is known to be unchanged from the revision until now,
+    /// that is no change to it was recorded and the revision is not older
+    /// than the recording.
+    pub fn unchanged_since(&self, key: &Key, revision: Revision) -> bool {
+        let started = match revision {
+            Revision::Version(version) => version >= self.started.0,
+            Revision::Timestamp(at) => at >= self.started.1
+        };
+        started && self.retention.depth_of(key) != 0
+            && !self.changes.borrow().contains_key(key)
+            && !self.evicted.borrow().contains_key(key)
+    }
+    /// Diffs what the key held at two revisions, or [None] if either
+    /// is older than the recorded changes.
+    pub fn diff(&self, key: &Key, from: Revision, to: Revision) -> Option<KeyDiff> {
+        let from = self.at(key, from)?;
+        let to = self.at(key, to)?;
+        Some(KeyDiff::between(from.as_deref().cloned(), to.as_deref().cloned()))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|f| f.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use overseer::models::{DiffLine, Key, Revision, Value};
+
+    use super::{HistoryRetention, KeyHistory};
+
+    #[test]
+    pub fn test_key_history() {
+        let history = KeyHistory::with_depth(2);
+        let key = Key::from_str("app.mode");
+        history.record(&key, 1, Some(Rc::new(Value::String("a".into()))));
+        history.record(&key, 4, Some(Rc::new(Value::String("b".into()))));
+        history.record(&key, 7, None);
+
+        // The first change was evicted.
+        assert_eq!(history.changes(&key).len(), 2);
+        assert!(history.at(&key, Revision::Version(3)).is_none());
+        assert_eq!(history.at(&key, Revision::Version(5)).unwrap().as_deref(), Some(&Value::String("b".into())));
+        assert_eq!(history.at(&key, Revision::Version(9)).unwrap(), None);
+
+        let diff = history.diff(&key, Revision::Version(4), Revision::Version(7)).unwrap();
+        assert_eq!(diff.lines, vec![DiffLine::Removed("b".to_string())]);
+        assert!(history.diff(&Key::from_str("other"), Revision::Version(4), Revision::Version(7)).is_none());
+
+        // The changes after a version are only returned while all of them are kept.
+        assert_eq!(history.since(&key, 4).unwrap().iter().map(|f| f.version).collect::<Vec<_>>(), vec![7]);
+        assert_eq!(history.since(&key, 7).unwrap().len(), 0);
+        assert!(history.since(&key, 0).is_none());
+        assert!(history.since(&Key::from_str("other"), 0).unwrap().is_empty());
+
+        // Only a key without recorded changes is unchanged since the recording began.
+        let history = KeyHistory::default().starting_at(5);
+        assert!(history.unchanged_since(&key, Revision::Version(5)));
+        assert!(!history.unchanged_since(&key, Revision::Version(4)));
+        history.record(&key, 6, None);
+        assert!(!history.unchanged_since(&key, Revision::Version(9)));
+    }
+
+    #[test]
+    pub fn test_history_retention() {
+        let retention = HistoryRetention::default()
+            .with_depth("config.", 3)
+            .with_depth("telemetry.", 0);
+        let history = KeyHistory::with_retention(retention);
+        let (config, telemetry) = (Key::from_str("config.mode"), Key::from_str("telemetry.cpu"));
+        for version in 1..=5 {
+            history.record(&config, version, Some(Rc::new(Value::Integer(version as i64))));
+            history.record(&telemetry, version, Some(Rc::new(Value::Integer(0))));
+        }
+
+        // Only the last changes are kept, and none of the keys without history.
+        let versions = |limit| history.entries(&config, limit).into_iter().map(|f| f.version).collect::<Vec<_>>();
+        assert_eq!(versions(0), vec![3, 4, 5]);
+        assert_eq!(versions(2), vec![4, 5]);
+        assert_eq!(history.entries(&config, 1)[0].value, Some(Value::Integer(5)));
+        assert!(history.entries(&telemetry, 0).is_empty());
+    }
+}
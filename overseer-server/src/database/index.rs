@@ -0,0 +1,107 @@
+use std::{cell::RefCell, collections::{BTreeMap, BTreeSet, HashMap}};
+
+use overseer::models::{Key, Value};
+
+
+/// The keys holding each value of a field.
+type FieldIndex = HashMap<Value, BTreeSet<Key>>;
+
+/// Indexes the map values of a store by the fields declared on it.
+///
+/// A field is named by its path, see [Value::field]. Keys whose value is not
+/// a map, or a map without the field, are left out of its index. The store
+/// updates the indexes in the same step it changes a record, so a query never
+/// sees a write the records do not.
+#[derive(Default)]
+pub struct SecondaryIndexes {
+    fields: RefCell<BTreeMap<String, FieldIndex>>
+}
+
+impl SecondaryIndexes {
+    /// Declares an index on a field, built from the records the store holds.
+    /// Declaring a field twice rebuilds its index.
+    pub fn declare<'a, I>(&self, field: &str, records: I)
+    where
+        I: IntoIterator<Item = (&'a Key, &'a Value)>
+    {
+        let mut index = FieldIndex::new();
+        for (key, value) in records {
+            if let Some(found) = value.field(field) {
+                index.entry(found.clone()).or_default().insert(key.clone());
+            }
+        }
+        self.fields.borrow_mut().insert(field.to_string(), index);
+    }
+    /// The fields that are indexed, in order.
+    pub fn fields(&self) -> Vec<String> {
+        self.fields.borrow().keys().cloned().collect()
+    }
+    /// Moves the key from what it held to what it holds now in every index,
+    /// [None] stands for a key without a value.
+    pub fn update(&self, key: &Key, old: Option<&Value>, new: Option<&Value>) {
+        let mut fields = self.fields.borrow_mut();
+        for (field, index) in fields.iter_mut() {
+            let (old, new) = (old.and_then(|f| f.field(field)), new.and_then(|f| f.field(field)));
+            if old == new {
+                continue;
+            }
+            if let Some(old) = old {
+                if let Some(keys) = index.get_mut(old) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        index.remove(old);
+                    }
+                }
+            }
+            if let Some(new) = new {
+                index.entry(new.clone()).or_default().insert(key.clone());
+            }
+        }
+    }
+    /// The keys holding the value at the field in order, [None] if the
+    /// field is not indexed.
+    pub fn query(&self, field: &str, value: &Value) -> Option<Vec<Key>> {
+        let fields = self.fields.borrow();
+        let index = fields.get(field)?;
+        Some(index.get(value).map(|f| f.iter().cloned().collect()).unwrap_or_default())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use overseer::models::{Key, Value};
+
+    use super::SecondaryIndexes;
+
+    fn host(region: &str) -> Value {
+        Value::Map(BTreeMap::from([("region".to_string(), Value::String(region.into()))]))
+    }
+
+    #[test]
+    pub fn test_secondary_indexes() {
+        let (a, b, c) = (Key::from_str("host.a"), Key::from_str("host.b"), Key::from_str("host.c"));
+        let (eu, us) = (Value::String("eu".into()), Value::String("us".into()));
+
+        let indexes = SecondaryIndexes::default();
+        let (first, plain) = (host("eu"), Value::Integer(3));
+        indexes.declare("region", [(&a, &first), (&c, &plain)]);
+        assert_eq!(indexes.query("region", &eu), Some(vec![a.clone()]));
+        assert_eq!(indexes.query("zone", &eu), None);
+
+        indexes.update(&b, None, Some(&host("eu")));
+        assert_eq!(indexes.query("region", &eu), Some(vec![a.clone(), b.clone()]));
+
+        // Moving a key takes it out of the old value.
+        indexes.update(&a, Some(&host("eu")), Some(&host("us")));
+        assert_eq!(indexes.query("region", &eu), Some(vec![b.clone()]));
+        assert_eq!(indexes.query("region", &us), Some(vec![a.clone()]));
+
+        indexes.update(&b, Some(&host("eu")), None);
+        indexes.update(&a, Some(&host("us")), Some(&plain));
+        assert_eq!(indexes.query("region", &eu), Some(vec![]));
+        assert_eq!(indexes.query("region", &us), Some(vec![]));
+    }
+}
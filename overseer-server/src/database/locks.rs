@@ -0,0 +1,124 @@
+use std::{cell::{Cell, RefCell}, collections::HashMap, time::Instant};
+
+use overseer::models::Key;
+
+use crate::net::ClientId;
+
+
+/// A connection holding a lock until it releases it, disconnects or the
+/// time to live runs out.
+struct Lock {
+    owner: ClientId,
+    token: u64,
+    until: Instant
+}
+
+/// The locks held on the keys of a store.
+///
+/// A lock is not a record, taking one never touches the store and they
+/// only live in memory, after a restart every lock is free again. Every
+/// acquisition gets a larger token than the last, so a holder that lost
+/// its lock without noticing can be fenced off by the token.
+#[derive(Default)]
+pub struct Locks {
+    held: RefCell<HashMap<Key, Lock>>,
+    next_token: Cell<u64>
+}
+
+impl Locks {
+    /// Takes the lock for the client until the deadline, or extends it if
+    /// the client already holds it. Returns the token and whether the lock
+    /// changed hands, [None] if another client holds it.
+    pub fn acquire(&self, key: &Key, owner: ClientId, until: Instant, now: Instant) -> Option<(u64, bool)> {
+        let mut held = self.held.borrow_mut();
+        match held.get_mut(key) {
+            Some(lock) if lock.owner == owner => {
+                lock.until = until;
+                Some((lock.token, false))
+            }
+            Some(lock) if lock.until > now => None,
+            _ => {
+                let token = self.next_token.get() + 1;
+                self.next_token.set(token);
+                held.insert(key.clone(), Lock { owner, token, until });
+                Some((token, true))
+            }
+        }
+    }
+    /// Releases the lock, returning false if the client does not hold it.
+    pub fn release(&self, key: &Key, owner: ClientId) -> bool {
+        let mut held = self.held.borrow_mut();
+        match held.get(key) {
+            Some(lock) if lock.owner == owner => {
+                held.remove(key);
+                true
+            }
+            _ => false
+        }
+    }
+    /// Releases every lock of the client, returning their keys.
+    pub fn release_all(&self, owner: ClientId) -> Vec<Key> {
+        self.take(|lock| lock.owner == owner)
+    }
+    /// Releases every lock whose time to live ran out, returning their keys.
+    pub fn expire(&self, now: Instant) -> Vec<Key> {
+        self.take(|lock| lock.until <= now)
+    }
+    /// The holder of the lock, if it is held at the moment.
+    pub fn holder(&self, key: &Key, now: Instant) -> Option<ClientId> {
+        self.held.borrow().get(key).filter(|f| f.until > now).map(|f| f.owner)
+    }
+    fn take<F>(&self, released: F) -> Vec<Key>
+    where
+        F: Fn(&Lock) -> bool
+    {
+        let mut held = self.held.borrow_mut();
+        let keys: Vec<Key> = held.iter().filter(|(_, lock)| released(lock)).map(|(key, _)| key.clone()).collect();
+        for key in &keys {
+            held.remove(key);
+        }
+        keys
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use overseer::models::Key;
+
+    use crate::net::ClientId;
+
+    use super::Locks;
+
+    #[test]
+    pub fn test_locks() {
+        let locks = Locks::default();
+        let leader = Key::from_str("leader");
+        let (first, second) = (ClientId::from_id(0), ClientId::from_id(1));
+        let now = Instant::now();
+
+        let (token, changed) = locks.acquire(&leader, first, now + Duration::from_secs(10), now).unwrap();
+        assert!(changed);
+        assert!(locks.acquire(&leader, second, now + Duration::from_secs(10), now).is_none());
+        // The holder renews the lock under the same token.
+        assert_eq!(locks.acquire(&leader, first, now + Duration::from_secs(20), now), Some((token, false)));
+        assert_eq!(locks.holder(&leader, now + Duration::from_secs(15)), Some(first));
+
+        // A lapsed lock goes to the next client under a larger token.
+        let later = now + Duration::from_secs(21);
+        let (next, changed) = locks.acquire(&leader, second, later + Duration::from_secs(10), later).unwrap();
+        assert!(changed && next > token);
+        assert!(!locks.release(&leader, first));
+        assert!(locks.release(&leader, second));
+        assert_eq!(locks.holder(&leader, later), None);
+
+        let other = Key::from_str("compactor");
+        locks.acquire(&leader, first, now + Duration::from_secs(5), now);
+        locks.acquire(&other, first, now + Duration::from_secs(50), now);
+        assert_eq!(locks.expire(now + Duration::from_secs(6)), vec![leader.clone()]);
+        assert_eq!(locks.release_all(first), vec![other]);
+        assert_eq!(locks.release_all(first), vec![]);
+    }
+}
@@ -0,0 +1,85 @@
+use overseer::models::{Key, KeyDrift, Value, ValueType};
+
+
+/// A key the deployment expects to exist.
+#[derive(Clone, Debug)]
+pub struct RequiredKey {
+    pub key: Key,
+    /// The type the value must have.
+    pub expected: ValueType,
+    /// Created at startup if the key is missing and defaults are enabled.
+    pub default: Option<Value>
+}
+
+impl RequiredKey {
+    /// Compares the required key against the stored value.
+    pub fn check(&self, found: Option<&Value>) -> Option<KeyDrift> {
+        let found = found.map(Value::value_type);
+        if found == Some(self.expected) {
+            None
+        } else {
+            Some(KeyDrift {
+                key: self.key.clone(),
+                expected: self.expected,
+                found
+            })
+        }
+    }
+}
+
+/// The manifest of keys that must be present in the store.
+#[derive(Default, Clone, Debug)]
+pub struct Manifest {
+    keys: Vec<RequiredKey>
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Requires a key to exist with a certain type.
+    pub fn require<K>(mut self, key: K, expected: ValueType) -> Self
+    where 
+        K: Into<Key>
+    {
+        self.keys.push(RequiredKey { key: key.into(), expected, default: None });
+        self
+    }
+    /// Requires a key to exist with the type of the default.
+    pub fn require_with_default<K>(mut self, key: K, default: Value) -> Self
+    where 
+        K: Into<Key>
+    {
+        self.keys.push(RequiredKey { key: key.into(), expected: default.value_type(), default: Some(default) });
+        self
+    }
+    pub fn keys(&self) -> &[RequiredKey] {
+        &self.keys
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::models::{Key, Value, ValueType};
+
+    use super::Manifest;
+
+
+    #[test]
+    pub fn test_required_key_check() {
+        let manifest = Manifest::new()
+            .require("db.url", ValueType::String)
+            .require_with_default("db.pool", Value::Integer(8));
+
+        let url = &manifest.keys()[0];
+        assert!(url.check(Some(&Value::String("postgres://".into()))).is_none());
+        assert!(url.check(None).unwrap().is_missing());
+
+        let pool = &manifest.keys()[1];
+        assert_eq!(pool.expected, ValueType::Integer);
+        let drift = pool.check(Some(&Value::String("eight".into()))).unwrap();
+        assert_eq!(drift.key, Key::from_str("db.pool"));
+        assert_eq!(drift.found, Some(ValueType::String));
+    }
+}
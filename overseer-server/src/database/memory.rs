@@ -1,32 +1,45 @@
-use std::{borrow::Borrow, cell::RefCell, collections::HashMap, iter::Map, marker::PhantomData, rc::Rc, sync::Arc};
+use std::{borrow::Borrow, cell::{Cell, RefCell}, collections::BTreeMap, ops::Bound, rc::Rc};
 
 use dashmap::DashMap;
-use monoio::io::{as_fd::AsWriteFd, AsyncWriteRent, AsyncWriteRentExt};
-use overseer::{access::{WatcherActivity, WatcherBehaviour}, error::NetworkError, models::{Key, LocalReadAsync, Value}};
+use overseer::{access::{WatcherActivity, WatcherBehaviour}, error::NetworkError, models::{DatabaseStats, Key, KeyChild, KeyMatcher, LocalReadAsync, RecordMeta, Value, WatchedKey, KEY_SEPARATOR}};
 
 use overseer::network::OverseerSerde;
 use crate::net::ClientId;
 
-use super::watcher::{WatchClient, WatchServer, Watcher};
+use super::{watcher::{WatchClient, WatchServer, Watcher, WatcherLimit}, ReadSnapshot, RecordVersion, Traces, Versions};
 
 
 
 pub struct MemoryDatabase {
-    /// The database list of records.
-    records: RefCell<HashMap<Key, Record>>,
+    /// The database list of records, ordered by key.
+    records: RefCell<BTreeMap<Key, Record>>,
     /// The list of watchers.
-    watchers: DashMap<Key, DashMap<ClientId, Watcher<WatchServer>>>
+    watchers: DashMap<Key, DashMap<ClientId, Watcher<WatchServer>>>,
+    /// Bumped on every change to the records.
+    version: Cell<u64>,
+    /// The superseded records the pinned snapshots read.
+    versions: Rc<Versions>,
+    /// The traces of the changes the watchers were told of.
+    traces: Traces,
+    /// About how many bytes the keys and values of the records take.
+    bytes: Cell<usize>
 }
 
 pub struct Record {
-    value: Rc<Value>
+    value: Rc<Value>,
+    /// The version of the store that wrote the record.
+    version: u64,
+    /// Who wrote the record and when, if that is known.
+    meta: Option<RecordMeta>
 }
 
 
 impl Record {
-    pub fn new(value: Value) -> Self {
+    pub fn new(value: Value, version: u64, meta: Option<RecordMeta>) -> Self {
         Self {
-            value: Rc::new(value)
+            value: Rc::new(value),
+            version,
+            meta
         }
     }
     pub async fn write<W>(&self, writer: &mut W) -> Result<(), NetworkError>
@@ -40,24 +53,55 @@ impl Record {
     where 
         R: LocalReadAsync
     {
+        // The version is not stored, it belongs to the running store.
         Ok(Self {
-            value: Rc::new(Value::deserialize(reader).await?)
+            value: Rc::new(Value::deserialize(reader).await?),
+            version: 0,
+            meta: None
         })
     }
     pub fn value(&self) -> &Rc<Value> {
         &self.value
     }
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+    pub fn meta(&self) -> Option<RecordMeta> {
+        self.meta
+    }
+    fn as_version(&self) -> RecordVersion {
+        RecordVersion { value: Rc::clone(&self.value), version: self.version, meta: self.meta }
+    }
 }
 
 impl MemoryDatabase {
     pub fn new() -> Self {
         Self {
-            records: RefCell::new(HashMap::new()),
+            records: RefCell::new(BTreeMap::new()),
             watchers: DashMap::new(),
+            version: Cell::new(0),
+            versions: Rc::default(),
+            traces: Traces::default(),
+            bytes: Cell::new(0)
         }
     }
+    /// Keeps the record the key holds before the change at the version for
+    /// the snapshots that read it.
+    fn supersede(&self, key: &Key, until: u64, current: Option<&Record>) {
+        if self.versions.pinned() {
+            self.versions.retain(key, until, current.map(Record::as_version));
+        }
+    }
+    /// Counts the bytes of the record the key holds after a change in
+    /// place of the one it held before.
+    fn resize(&self, key: &Key, old: Option<&Record>, new: Option<&Value>) {
+        let size = |value: &Value| key.as_str().len() + value.size();
+        let added = self.bytes.get() + new.map(size).unwrap_or(0);
+        self.bytes.set(added - old.map(|f| size(&f.value)).unwrap_or(0));
+    }
     
-    pub async fn insert<K, V>(&self, key: K, value: V)
+    /// Inserts a value, returning the version of the new record.
+    pub async fn insert<K, V>(&self, key: K, value: V, meta: Option<RecordMeta>) -> u64
     where 
         K: Borrow<Key>,
         V: Into<Value>
@@ -65,25 +109,98 @@ impl MemoryDatabase {
         // let wow = *self.records.get(&key).unwrap();
         let key = key.borrow();
         let value = Rc::new(value.into());
-        self.records.borrow_mut().insert(key.clone(), Record {
-            value: Rc::clone(&value)
-        });
+        let version = self.version.get() + 1;
+        {
+            let mut records = self.records.borrow_mut();
+            self.supersede(key, version, records.get(key));
+            let old = records.insert(key.clone(), Record {
+                value: Rc::clone(&value),
+                version,
+                meta
+            });
+            self.resize(key, old.as_ref(), Some(&value));
+        }
+        self.version.set(version);
         self.notify(key, Some(value)).await;
+        version
     }
 
+    /// Inserts the records without telling the watchers, for loading them in
+    /// bulk. Every record gets a version of its own, in order.
+    pub fn load(&self, records: Vec<(Key, Value, RecordMeta)>) {
+        let mut stored = self.records.borrow_mut();
+        for (key, value, meta) in records {
+            let version = self.version.get() + 1;
+            self.supersede(&key, version, stored.get(&key));
+            let record = Record::new(value, version, Some(meta));
+            self.resize(&key, stored.get(&key), Some(record.value()));
+            stored.insert(key, record);
+            self.version.set(version);
+        }
+    }
+
+    /// Returns the value of the key, inserting the default first if there is none.
+    ///
+    /// The records are borrowed once for the check and the insert, so nothing
+    /// can insert the key in between. Returns the value with the version of
+    /// its record and whether the default was inserted.
+    pub async fn get_or_insert(&self, key: &Key, default: Value, meta: Option<RecordMeta>) -> (Rc<Value>, u64, bool) {
+        let version = self.version.get() + 1;
+        let value = {
+            let mut records = self.records.borrow_mut();
+            if let Some(existing) = records.get(key) {
+                return (Rc::clone(existing.value()), existing.version(), false);
+            }
+            self.supersede(key, version, None);
+            let record = Record::new(default, version, meta);
+            let value = Rc::clone(record.value());
+            self.resize(key, None, Some(&value));
+            records.insert(key.clone(), record);
+            value
+        };
+        self.version.set(version);
+        self.notify(key, Some(Rc::clone(&value))).await;
+        (value, version, true)
+    }
+    /// The version of the store, this increases with every change.
+    pub fn version(&self) -> u64 {
+        self.version.get()
+    }
     pub fn len(&self) -> usize {
         self.records.borrow().len()
     }
-    pub async fn subscribe<K>(&self, key: K, client_id: ClientId, behaviour: WatcherBehaviour, activity: WatcherActivity) -> Watcher<WatchClient>
+    /// Counts the records and the bytes of their values, the size of the
+    /// storage is left to the caller.
+    pub fn stats(&self) -> DatabaseStats {
+        let mut stats = DatabaseStats::default();
+        for (key, record) in self.records.borrow().iter() {
+            stats.record(key, record.value().size());
+        }
+        stats
+    }
+    /// About how many bytes the keys and values of the records take, see [Value::size].
+    pub fn bytes(&self) -> usize {
+        self.bytes.get()
+    }
+    pub async fn subscribe<K>(&self, key: K, client_id: ClientId, behaviour: WatcherBehaviour, activity: WatcherActivity, limit: WatcherLimit) -> Watcher<WatchClient>
         where 
             K: Borrow<Key>
     {
         let key= key.borrow();
-        let (client, server) = Watcher::new(behaviour);
+        let kickback = match activity {
+            WatcherActivity::Kickback => Some(self.get(key).await),
+            WatcherActivity::Lazy => None
+        };
+        self.subscribe_from(key, client_id, behaviour, kickback, limit)
+    }
+    /// Subscribes to a key, kicking the value back first if there is one
+    /// to kick back. The value need not be the record of the key.
+    pub fn subscribe_from(&self, key: &Key, client_id: ClientId, behaviour: WatcherBehaviour, kickback: Option<Option<Rc<Value>>>, limit: WatcherLimit) -> Watcher<WatchClient> {
+        let (client, server) = Watcher::new(behaviour, limit);
         
-        if let WatcherActivity::Kickback = activity {
+        if let Some(value) = kickback {
             // Kick the value back immediately.
-            server.wake(self.get(&key).await);
+            server.wake(value);
         }
         
         match self.watchers.get(&key) {
@@ -102,6 +219,19 @@ impl MemoryDatabase {
 
         client
     }
+    /// Subscribes to a key and takes a snapshot of it in the same step.
+    ///
+    /// Nothing can change the store between installing the watcher and reading
+    /// the value, so the watcher only sees changes made after the returned version.
+    pub async fn subscribe_snapshot<K>(&self, key: K, client_id: ClientId, behaviour: WatcherBehaviour, limit: WatcherLimit) -> (Watcher<WatchClient>, Option<Rc<Value>>, u64)
+        where 
+            K: Borrow<Key>
+    {
+        let key = key.borrow();
+        let client = self.subscribe(key, client_id, behaviour, WatcherActivity::Lazy, limit).await;
+        let value = self.records.borrow().get(key).map(|f| Rc::clone(f.value()));
+        (client, value, self.version())
+    }
     pub async fn release<K>(&self, key: K, id: ClientId) -> bool
     where 
         K: Borrow<Key>
@@ -120,23 +250,67 @@ impl MemoryDatabase {
             false
         }
     }
+    /// Counts the active watchers across every key.
+    pub fn watcher_count(&self) -> usize {
+        self.watchers.iter().map(|f| f.len()).sum()
+    }
+    /// The watched keys in order with how many clients watch each, the
+    /// key alone if one is named. A key whose watchers were all released
+    /// is left out.
+    pub fn watched_keys(&self, key: Option<&Key>) -> Vec<WatchedKey> {
+        let mut watched: Vec<WatchedKey> = match key {
+            Some(key) => self.watchers.get(key)
+                .map(|map| WatchedKey { key: key.clone(), clients: map.len() as u64 })
+                .into_iter()
+                .collect(),
+            None => self.watchers.iter()
+                .map(|map| WatchedKey { key: map.key().clone(), clients: map.len() as u64 })
+                .collect()
+        };
+        watched.retain(|f| f.clients > 0);
+        watched.sort_by(|a, b| a.key.cmp(&b.key));
+        watched
+    }
+    /// Kills every watcher, waking their subscribers so they exit.
+    pub fn kill_watchers(&self) {
+        for map in self.watchers.iter() {
+            for watcher in map.iter() {
+                watcher.kill();
+            }
+        }
+        self.watchers.clear();
+    }
     pub async fn notify<K>(&self, key: K, value: Option<Rc<Value>>) -> bool
     where 
         K: Borrow<Key>
     {
         match self.watchers.get(key.borrow()) {
             Some(map) => {
+                self.traces.record(key.borrow(), value.as_ref());
                 Watcher::notify_coordinated(map.iter(), value);
                 true
             },
             None => false
         }
     }
+    /// The trace of the request whose change handed the watchers of the
+    /// key the value, see [super::traced].
+    pub fn trace_of(&self, key: &Key, value: Option<&Rc<Value>>) -> Option<u64> {
+        self.traces.of(key, value)
+    }
     pub async fn delete(&self, key: &Key) -> bool {
         if self.len() == 0 {
             return false;
         } else {
-            if self.records.borrow_mut().remove(key).is_some() {
+            let removed = {
+                let mut records = self.records.borrow_mut();
+                self.supersede(key, self.version.get() + 1, records.get(key));
+                let removed = records.remove(key);
+                self.resize(key, removed.as_ref(), None);
+                removed.is_some()
+            };
+            if removed {
+                self.version.set(self.version.get() + 1);
                 self.notify(key, None).await;
                 true
             } else {
@@ -144,9 +318,233 @@ impl MemoryDatabase {
             }
         }
     }
+    /// Moves the record of a key to another, keeping its version and
+    /// metadata, and tells the watchers of the key it was deleted and those
+    /// of the other the value. Returns the moved value with the version of
+    /// its record, [None] if the key holds nothing.
+    ///
+    /// The version of the store moves on once for each of the keys.
+    pub async fn rename(&self, from: &Key, to: &Key) -> Option<(Rc<Value>, u64)> {
+        let (value, version) = {
+            let mut records = self.records.borrow_mut();
+            let record = records.remove(from)?;
+            let next = self.version.get();
+            self.supersede(from, next + 1, Some(&record));
+            self.supersede(to, next + 2, records.get(to));
+            let moved = (Rc::clone(record.value()), record.version());
+            self.resize(from, Some(&record), None);
+            let replaced = records.insert(to.clone(), record);
+            self.resize(to, replaced.as_ref(), Some(&moved.0));
+            moved
+        };
+        self.version.set(self.version.get() + 2);
+        self.notify(from, None).await;
+        self.notify(to, Some(Rc::clone(&value))).await;
+        Some((value, version))
+    }
     pub async fn get(&self, key: &Key) -> Option<Rc<Value>> {
         Some(Rc::clone(self.records.borrow().get(key)?.value()))
     }
+    /// The value of the key along with the version of its record.
+    pub fn get_versioned(&self, key: &Key) -> Option<(Rc<Value>, u64)> {
+        let records = self.records.borrow();
+        let record = records.get(key)?;
+        Some((Rc::clone(record.value()), record.version()))
+    }
+    /// The value of the key along with the version and metadata of its record.
+    pub fn get_with_meta(&self, key: &Key) -> Option<(Rc<Value>, u64, Option<RecordMeta>)> {
+        let records = self.records.borrow();
+        let record = records.get(key)?;
+        Some((Rc::clone(record.value()), record.version(), record.meta()))
+    }
+    /// Pins the current version of the store, the records are read as of
+    /// it through the snapshot however they change until it is dropped.
+    pub fn snapshot(&self) -> ReadSnapshot {
+        self.versions.pin(self.version())
+    }
+    /// How many superseded records the pinned snapshots hold on to.
+    pub fn retained_versions(&self) -> usize {
+        self.versions.retained()
+    }
+    /// The record of the key as of the snapshot.
+    pub fn record_at(&self, snapshot: &ReadSnapshot, key: &Key) -> Option<RecordVersion> {
+        match self.versions.state_at(key, snapshot.version()) {
+            Some(state) => state,
+            None => self.records.borrow().get(key).map(Record::as_version)
+        }
+    }
+    /// Up to `limit` of the keys holding a record as of the snapshot, in
+    /// order after the cursor, paged like [MemoryDatabase::keys].
+    pub fn keys_at(&self, snapshot: &ReadSnapshot, cursor: Option<&Key>, limit: usize) -> (Vec<Key>, Option<Key>) {
+        let start = match cursor {
+            Some(cursor) => Bound::Excluded(cursor),
+            None => Bound::Unbounded
+        };
+        // The keys deleted since the snapshot are among the superseded ones.
+        let mut superseded = self.versions.keys_after(cursor).into_iter().peekable();
+        let records = self.records.borrow();
+        let mut current = records.range::<Key, _>((start, Bound::Unbounded)).map(|(key, _)| key).peekable();
+        let mut keys = vec![];
+        let mut more = false;
+        loop {
+            let key = match (current.peek(), superseded.peek()) {
+                (None, None) => break,
+                (Some(a), Some(b)) if *a == b => {
+                    superseded.next();
+                    current.next().cloned()
+                }
+                (Some(a), Some(b)) if *a > b => superseded.next(),
+                (Some(_), _) => current.next().cloned(),
+                (None, Some(_)) => superseded.next()
+            };
+            let Some(key) = key else {
+                break;
+            };
+            let held = match self.versions.state_at(&key, snapshot.version()) {
+                Some(state) => state.is_some(),
+                None => records.contains_key(&key)
+            };
+            if !held {
+                continue;
+            }
+            if keys.len() == limit {
+                more = true;
+                break;
+            }
+            keys.push(key);
+        }
+        let cursor = if more { keys.last().cloned() } else { None };
+        (keys, cursor)
+    }
+    /// The metadata of the record of the key, if it holds one and that is known.
+    pub fn record_meta(&self, key: &Key) -> Option<RecordMeta> {
+        self.records.borrow().get(key)?.meta()
+    }
+    /// Marks the record of the key as read at the time.
+    pub fn touch(&self, key: &Key, at: u64) {
+        if let Some(meta) = self.records.borrow_mut().get_mut(key).and_then(|f| f.meta.as_mut()) {
+            meta.accessed_at = meta.accessed_at.max(at);
+        }
+    }
+    /// Every record last read or written before the time, in order. Records
+    /// without metadata are left out, nothing says when they were used.
+    pub fn accessed_before(&self, before: u64) -> Vec<(Key, Rc<Value>)> {
+        self.records.borrow().iter()
+            .filter(|(_, record)| record.meta.is_some_and(|f| f.accessed_at < before))
+            .map(|(key, record)| (key.clone(), Rc::clone(record.value())))
+            .collect()
+    }
+    /// The version of the record of the key, zero if it holds nothing.
+    pub fn record_version(&self, key: &Key) -> u64 {
+        self.records.borrow().get(key).map(|f| f.version()).unwrap_or(0)
+    }
+    /// Every record with a key under the prefix, in order.
+    pub fn scan(&self, prefix: &str) -> Vec<(Key, Rc<Value>)> {
+        let start = Key::from_str(prefix);
+        self.records.borrow()
+            .range::<Key, _>((Bound::Included(&start), Bound::Unbounded))
+            .take_while(|(key, _)| key.as_str().starts_with(prefix))
+            .map(|(key, record)| (key.clone(), Rc::clone(record.value())))
+            .collect()
+    }
+    /// Lists up to `limit` of the keys one segment below the key in order,
+    /// starting after the cursor, paged like [MemoryDatabase::keys].
+    ///
+    /// The keys below a child are skipped over rather than walked, so a
+    /// page costs about as much as the children under the key.
+    pub fn children(&self, key: &Key, cursor: Option<&Key>, limit: usize) -> (Vec<KeyChild>, Option<Key>) {
+        let prefix = key.child_prefix();
+        // The keys of a child sort after the child, so nothing before the cursor is needed.
+        let mut start = match cursor {
+            Some(cursor) if cursor.as_str() > prefix.as_str() => Key::from_str(cursor.as_str()),
+            _ => Key::from_str(&prefix)
+        };
+        let records = self.records.borrow();
+        let mut children: BTreeMap<Key, KeyChild> = BTreeMap::new();
+        loop {
+            let Some(stored) = records.range::<Key, _>((Bound::Included(&start), Bound::Unbounded)).map(|(key, _)| key).next() else {
+                break;
+            };
+            let Some(rest) = stored.as_str().strip_prefix(prefix.as_str()) else {
+                break;
+            };
+            let (name, below) = match rest.split_once(KEY_SEPARATOR) {
+                Some((name, _)) => (name, true),
+                None => (rest, false)
+            };
+            let child = Key::from_str(format!("{prefix}{name}"));
+            start = match below {
+                // Past every key below the child, the character after the separator sorts next.
+                true => Key::from_str(format!("{}{}", child.as_str(), (KEY_SEPARATOR as u8 + 1) as char)),
+                false => Key::from_str(format!("{}\0", child.as_str()))
+            };
+            if rest.is_empty() || cursor.is_some_and(|f| &child <= f) {
+                continue;
+            }
+            let entry = children.entry(child.clone()).or_insert(KeyChild { key: child, has_value: false, has_children: false });
+            entry.has_value |= !below;
+            entry.has_children |= below;
+        }
+        let mut children: Vec<KeyChild> = children.into_values().collect();
+        let cursor = match children.len() > limit {
+            true => {
+                children.truncate(limit);
+                children.last().map(|f| f.key.clone())
+            }
+            false => None
+        };
+        (children, cursor)
+    }
+    /// Lists up to `limit` keys in order, starting after the cursor.
+    ///
+    /// The returned cursor is the last key of the page, or [None] if
+    /// the listing reached the end.
+    pub fn keys(&self, cursor: Option<&Key>, limit: usize) -> (Vec<Key>, Option<Key>) {
+        let start = match cursor {
+            Some(cursor) => Bound::Excluded(cursor),
+            None => Bound::Unbounded
+        };
+        let records = self.records.borrow();
+        let mut range = records.range::<Key, _>((start, Bound::Unbounded)).map(|(key, _)| key);
+        let keys: Vec<Key> = range.by_ref().take(limit).cloned().collect();
+        let cursor = match range.next() {
+            Some(..) => keys.last().cloned(),
+            None => None
+        };
+        (keys, cursor)
+    }
+    /// Up to `limit` of the keys the matcher accepts in order, starting
+    /// after the cursor and looking at no more than `scan` keys. The
+    /// cursor continues after the last key looked at, so a page cut short
+    /// by the scan may hold fewer keys than asked for.
+    pub fn find_keys(&self, matcher: &KeyMatcher, cursor: Option<&Key>, limit: usize, scan: usize) -> Result<(Vec<Key>, Option<Key>), NetworkError> {
+        let prefix = Key::from_str(matcher.prefix());
+        let start = match cursor {
+            Some(cursor) if *cursor >= prefix => Bound::Excluded(cursor),
+            _ => Bound::Included(&prefix)
+        };
+        let records = self.records.borrow();
+        let mut range = records.range::<Key, _>((start, Bound::Unbounded))
+            .map(|(key, _)| key)
+            .take_while(|key| key.as_str().starts_with(prefix.as_str()))
+            .peekable();
+        let mut keys = vec![];
+        let mut last = None;
+        for key in range.by_ref().take(scan) {
+            if matcher.is_match(key)? {
+                keys.push(key.clone());
+            }
+            last = Some(key);
+            if keys.len() == limit {
+                break;
+            }
+        }
+        let cursor = match range.peek() {
+            Some(..) => last.cloned(),
+            None => None
+        };
+        Ok((keys, cursor))
+    }
 }
 
 
@@ -154,8 +552,6 @@ impl MemoryDatabase {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
-
     // use overseer::{access::{WatcherActivity, WatcherBehaviour}, models::{Key, Value}};
     // use tokio::sync::Notify;
 
@@ -0,0 +1,99 @@
+use std::cell::RefCell;
+
+use overseer::models::{Key, Value};
+
+
+/// How many times a migration tries to catch up with writes before giving up.
+pub const MAX_CATCH_UP_ROUNDS: usize = 16;
+
+/// A single mutation of the store.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Change {
+    Insert(Key, Value),
+    Delete(Key)
+}
+
+/// Captures the mutations made while a migration copies the store.
+///
+/// Recording is a no-op unless a capture is running, so the feed
+/// costs nothing outside of a migration.
+#[derive(Default)]
+pub struct ChangeFeed {
+    changes: RefCell<Option<Vec<Change>>>
+}
+
+impl ChangeFeed {
+    /// Starts capturing changes, returning false if a capture is already running.
+    pub fn start(&self) -> bool {
+        let mut changes = self.changes.borrow_mut();
+        if changes.is_some() {
+            return false;
+        }
+        *changes = Some(vec![]);
+        true
+    }
+    pub fn is_capturing(&self) -> bool {
+        self.changes.borrow().is_some()
+    }
+    pub fn record(&self, change: Change) {
+        if let Some(changes) = &mut *self.changes.borrow_mut() {
+            changes.push(change);
+        }
+    }
+    /// Takes the changes captured so far.
+    pub fn drain(&self) -> Vec<Change> {
+        match &mut *self.changes.borrow_mut() {
+            Some(changes) => std::mem::take(changes),
+            None => vec![]
+        }
+    }
+    pub fn stop(&self) {
+        self.changes.borrow_mut().take();
+    }
+}
+
+/// The outcome of a migration.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MigrationReport {
+    /// How many records were in the initial copy.
+    pub copied: usize,
+    /// How many changes were replayed while catching up.
+    pub replayed: usize,
+    /// How many catch up rounds it took before the switch.
+    pub rounds: usize
+}
+
+impl MigrationReport {
+    /// Adds up the records of another migration, as for the shards of a
+    /// namespace, which catch up on their own.
+    pub fn merge(&mut self, other: MigrationReport) {
+        self.copied += other.copied;
+        self.replayed += other.replayed;
+        self.rounds = self.rounds.max(other.rounds);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::models::{Key, Value};
+
+    use super::{Change, ChangeFeed};
+
+    #[test]
+    pub fn test_change_feed() {
+        let feed = ChangeFeed::default();
+        feed.record(Change::Delete(Key::from_str("ignored")));
+        assert!(feed.drain().is_empty());
+
+        assert!(feed.start());
+        assert!(!feed.start());
+        feed.record(Change::Insert(Key::from_str("hello"), Value::Integer(1)));
+        feed.record(Change::Delete(Key::from_str("hello")));
+        assert_eq!(feed.drain().len(), 2);
+        assert!(feed.drain().is_empty());
+
+        feed.stop();
+        assert!(!feed.is_capturing());
+    }
+}
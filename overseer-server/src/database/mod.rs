@@ -3,8 +3,59 @@ mod storage;
 mod watcher;
 mod database;
 mod store;
+mod manifest;
+mod compaction;
+mod seed;
+mod migration;
+mod sampling;
+mod namespaces;
+mod ordering;
+mod history;
+mod promotion;
+mod shards;
+mod durability;
+mod replication;
+mod index;
+mod topics;
+mod queue;
+mod locks;
+mod split;
+mod coherence;
+mod system;
+mod mvcc;
+mod traces;
+mod quota;
+mod schemas;
+mod sequences;
+mod records;
 
 pub use crate::database::memory::*;
 pub use crate::database::storage::*;
 pub use crate::database::watcher::*;
 pub use crate::database::database::*;
+pub use crate::database::manifest::*;
+pub use crate::database::compaction::*;
+pub use crate::database::seed::*;
+pub use crate::database::migration::*;
+pub use crate::database::sampling::*;
+pub use crate::database::namespaces::*;
+pub use crate::database::ordering::*;
+pub use crate::database::history::*;
+pub use crate::database::promotion::*;
+pub use crate::database::shards::*;
+pub use crate::database::durability::*;
+pub use crate::database::replication::*;
+pub use crate::database::index::*;
+pub use crate::database::topics::*;
+pub use crate::database::queue::*;
+pub use crate::database::locks::*;
+pub use crate::database::split::*;
+pub use crate::database::coherence::*;
+pub use crate::database::system::*;
+pub use crate::database::mvcc::*;
+pub use crate::database::traces::*;
+pub use crate::database::quota::*;
+pub use crate::database::schemas::*;
+pub use crate::database::sequences::*;
+pub use crate::database::records::*;
+pub use crate::database::store::{StorageSnapshot, StorageStats, PAGE_SIZE};
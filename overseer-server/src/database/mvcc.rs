@@ -0,0 +1,176 @@
+use std::{cell::RefCell, collections::BTreeMap, ops::Bound, rc::Rc};
+
+use overseer::models::{Key, RecordMeta, Value};
+
+
+/// A record as a snapshot reads it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RecordVersion {
+    pub value: Rc<Value>,
+    /// The version of the store that wrote the record.
+    pub version: u64,
+    pub meta: Option<RecordMeta>
+}
+
+/// What a key held until the change at a version of the store replaced
+/// it, [None] if it held nothing.
+struct Superseded {
+    until: u64,
+    state: Option<RecordVersion>
+}
+
+/// The states of the records that snapshots pinned by readers may still
+/// read, so a long scan sees the records as of the version it started at
+/// however many writes land while it runs.
+///
+/// A state is only kept while a snapshot from before the change that
+/// replaced it is pinned, and dropped once the last such snapshot is.
+#[derive(Default)]
+pub struct Versions {
+    /// The pinned versions with how many snapshots pin each.
+    pins: RefCell<BTreeMap<u64, usize>>,
+    /// The superseded states of each key, oldest first.
+    chains: RefCell<BTreeMap<Key, Vec<Superseded>>>
+}
+
+impl Versions {
+    /// Pins the version for a reader until the snapshot is dropped.
+    pub fn pin(self: &Rc<Self>, version: u64) -> ReadSnapshot {
+        *self.pins.borrow_mut().entry(version).or_default() += 1;
+        ReadSnapshot { version, versions: Rc::clone(self) }
+    }
+    /// Whether a reader pins a snapshot, nothing is kept otherwise.
+    pub fn pinned(&self) -> bool {
+        !self.pins.borrow().is_empty()
+    }
+    /// How many superseded states are kept.
+    pub fn retained(&self) -> usize {
+        self.chains.borrow().values().map(Vec::len).sum()
+    }
+    /// Keeps what the key held before the change at the version, if a
+    /// pinned snapshot reads it.
+    pub fn retain(&self, key: &Key, until: u64, state: Option<RecordVersion>) {
+        let mut chains = self.chains.borrow_mut();
+        let from = chains.get(key).and_then(|f| f.last()).map(|f| f.until).unwrap_or(0);
+        if self.pins.borrow().range(from..until).next().is_none() {
+            return;
+        }
+        chains.entry(key.clone()).or_default().push(Superseded { until, state });
+    }
+    /// What the key held as of the version, [None] if nothing replaced the
+    /// record since, which is then read as it is.
+    pub fn state_at(&self, key: &Key, version: u64) -> Option<Option<RecordVersion>> {
+        let chains = self.chains.borrow();
+        let superseded = chains.get(key)?.iter().find(|f| f.until > version)?;
+        Some(superseded.state.clone())
+    }
+    /// The keys with superseded states after the cursor, in order.
+    pub fn keys_after(&self, cursor: Option<&Key>) -> Vec<Key> {
+        let start = match cursor {
+            Some(cursor) => Bound::Excluded(cursor),
+            None => Bound::Unbounded
+        };
+        self.chains.borrow().range::<Key, _>((start, Bound::Unbounded)).map(|(key, _)| key.clone()).collect()
+    }
+    fn unpin(&self, version: u64) {
+        {
+            let mut pins = self.pins.borrow_mut();
+            if let Some(count) = pins.get_mut(&version) {
+                *count -= 1;
+                if *count == 0 {
+                    pins.remove(&version);
+                }
+            }
+        }
+        self.collect();
+    }
+    /// Drops the states no pinned snapshot reads, a state being read by
+    /// the snapshots from the change before it up to the one replacing it.
+    fn collect(&self) {
+        let pins = self.pins.borrow();
+        let mut chains = self.chains.borrow_mut();
+        if pins.is_empty() {
+            chains.clear();
+            return;
+        }
+        chains.retain(|_, chain| {
+            let mut from = 0;
+            chain.retain(|f| {
+                let read = pins.range(from..f.until).next().is_some();
+                from = f.until;
+                read
+            });
+            !chain.is_empty()
+        });
+    }
+}
+
+/// A version of the store pinned by a reader, see [Versions].
+pub struct ReadSnapshot {
+    version: u64,
+    versions: Rc<Versions>
+}
+
+impl ReadSnapshot {
+    /// The version of the store the snapshot reads.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl Clone for ReadSnapshot {
+    fn clone(&self) -> Self {
+        self.versions.pin(self.version)
+    }
+}
+
+impl Drop for ReadSnapshot {
+    fn drop(&mut self) {
+        self.versions.unpin(self.version);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use overseer::models::{Key, Value};
+
+    use super::{RecordVersion, Versions};
+
+    fn state(value: i64, version: u64) -> Option<RecordVersion> {
+        Some(RecordVersion { value: Rc::new(Value::Integer(value)), version, meta: None })
+    }
+
+    #[test]
+    pub fn test_versions_collected() {
+        let versions = Rc::new(Versions::default());
+        let key = Key::from_str("app.mode");
+        // Nothing is kept while no snapshot is pinned.
+        versions.retain(&key, 2, state(1, 1));
+        assert_eq!(versions.retained(), 0);
+
+        let first = versions.pin(2);
+        versions.retain(&key, 3, state(1, 2));
+        let second = versions.pin(3);
+        versions.retain(&key, 4, state(2, 3));
+        // Both snapshots are older than the record the change at 5
+        // replaces, so it is not kept.
+        versions.retain(&key, 5, state(3, 4));
+        assert_eq!(versions.retained(), 2);
+        assert_eq!(versions.state_at(&key, 2), Some(state(1, 2)));
+        assert_eq!(versions.state_at(&key, 3), Some(state(2, 3)));
+        assert_eq!(versions.state_at(&key, 5), None);
+
+        drop(first);
+        assert_eq!(versions.retained(), 1);
+        assert_eq!(versions.keys_after(None), [key]);
+        let copy = second.clone();
+        drop(second);
+        assert_eq!(versions.retained(), 1);
+        drop(copy);
+        assert_eq!(versions.retained(), 0);
+        assert!(!versions.pinned());
+    }
+}
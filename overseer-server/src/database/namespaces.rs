@@ -0,0 +1,198 @@
+use std::{cell::RefCell, collections::HashMap, path::{Path, PathBuf}, rc::Rc};
+
+use overseer::{error::NetworkError, models::{Promotion, PromotionReport}, network::DEFAULT_NAMESPACE};
+
+use super::{Database, DurabilityPolicy, HistoryRetention, PlacementPolicy, PromotionLog, QuotaPolicy};
+
+
+/// The longest name a namespace may have.
+pub const MAX_NAMESPACE_LENGTH: usize = 64;
+/// The names that would clash with the files kept next to the database,
+/// `db.meta` holds the metadata of the default namespace and not a namespace.
+pub const RESERVED_NAMESPACES: [&str; 3] = ["meta", "schemas", "sequences"];
+
+/// Checks that a namespace name is usable as part of a file name.
+pub fn validate_namespace(name: &str) -> Result<(), NetworkError> {
+    if name.is_empty() || name.len() > MAX_NAMESPACE_LENGTH {
+        return Err(NetworkError::InvalidNamespace(format!("Namespaces must be 1 to {MAX_NAMESPACE_LENGTH} characters")));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(NetworkError::InvalidNamespace(format!("{name} may only contain letters, digits, '-' and '_'")));
+    }
+    if RESERVED_NAMESPACES.contains(&name) {
+        return Err(NetworkError::InvalidNamespace(format!("{name} is reserved for the files of the database")));
+    }
+    Ok(())
+}
+
+/// The isolated key spaces hosted by one server.
+///
+/// Every namespace is its own [Database]. The default namespace lives in the
+/// database file itself and every other one in a file named after it, so
+/// `db` hosts `tenant-a` in `db.tenant-a`.
+pub struct Namespaces {
+    path: PathBuf,
+    name: String,
+    placement: PlacementPolicy,
+    durability: DurabilityPolicy,
+    history: HistoryRetention,
+    /// The fields every namespace indexes.
+    indexes: Vec<String>,
+    quotas: QuotaPolicy,
+    databases: RefCell<HashMap<String, Rc<Database>>>,
+    /// The promotions between namespaces that can be rolled back.
+    promotions: PromotionLog
+}
+
+impl Namespaces {
+    /// Opens the namespaces left in the directory by a previous run.
+    pub async fn open<P, S>(path: P, name: S, default: Rc<Database>, placement: PlacementPolicy, durability: DurabilityPolicy, history: HistoryRetention, indexes: Vec<String>) -> Result<Self, NetworkError>
+    where 
+        P: AsRef<Path>,
+        S: AsRef<str>
+    {
+        let namespaces = Self {
+            path: path.as_ref().to_path_buf(),
+            name: name.as_ref().to_string(),
+            placement,
+            durability,
+            history,
+            indexes,
+            quotas: QuotaPolicy::default(),
+            databases: RefCell::new(HashMap::from([(DEFAULT_NAMESPACE.to_string(), default)])),
+            promotions: PromotionLog::default()
+        };
+
+        let prefix = format!("{}.", namespaces.name);
+        let mut found = vec![];
+        if let Ok(entries) = std::fs::read_dir(&namespaces.path) {
+            for entry in entries.flatten() {
+                let file = entry.file_name();
+                let Some(namespace) = file.to_str().and_then(|f| f.strip_prefix(&prefix)) else {
+                    continue;
+                };
+                if validate_namespace(namespace).is_ok() && namespace != DEFAULT_NAMESPACE {
+                    found.push(namespace.to_string());
+                }
+            }
+        }
+        for namespace in found {
+            let database = namespaces.open_database(&namespace).await?;
+            namespaces.databases.borrow_mut().insert(namespace, Rc::new(database));
+        }
+        Ok(namespaces)
+    }
+    fn file_name(&self, namespace: &str) -> String {
+        format!("{}.{namespace}", self.name)
+    }
+    async fn open_database(&self, namespace: &str) -> Result<Database, NetworkError> {
+        let mut database = Database::new(&self.path, self.file_name(namespace)).await?;
+        database.set_placement_policy(self.placement);
+        database.set_durability_policy(self.durability.clone());
+        database.set_history_retention(self.history.clone());
+        for field in &self.indexes {
+            database.declare_index(field);
+        }
+        database.set_quota(self.quotas.quota_of(namespace));
+        Ok(database)
+    }
+    /// Replaces the quotas of the namespaces, both those open and those
+    /// created later.
+    pub fn set_quota_policy(&mut self, quotas: QuotaPolicy) {
+        for (namespace, database) in self.databases.get_mut() {
+            database.set_quota(quotas.quota_of(namespace));
+        }
+        self.quotas = quotas;
+    }
+    pub fn get(&self, namespace: &str) -> Option<Rc<Database>> {
+        self.databases.borrow().get(namespace).cloned()
+    }
+    /// Every namespace and its database.
+    pub fn all(&self) -> Vec<(String, Rc<Database>)> {
+        self.databases.borrow().iter().map(|(k, v)| (k.clone(), Rc::clone(v))).collect()
+    }
+    /// Creates an empty namespace, returning false if it already exists.
+    pub async fn create(&self, namespace: &str) -> Result<bool, NetworkError> {
+        validate_namespace(namespace)?;
+        if self.databases.borrow().contains_key(namespace) {
+            return Ok(false);
+        }
+        let database = self.open_database(namespace).await?;
+        // Another request may have created it while the file was opened.
+        let mut databases = self.databases.borrow_mut();
+        if databases.contains_key(namespace) {
+            return Ok(false);
+        }
+        databases.insert(namespace.to_string(), Rc::new(database));
+        Ok(true)
+    }
+    /// Promotes a key space into another, see [PromotionLog::promote].
+    ///
+    /// Fails with [NetworkError::UnknownNamespace] if either namespace does not exist.
+    pub async fn promote(&self, promotion: &Promotion, dry_run: bool) -> Result<PromotionReport, NetworkError> {
+        let (Some(source), Some(target)) = (self.get(&promotion.source), self.get(&promotion.target)) else {
+            return Err(NetworkError::UnknownNamespace);
+        };
+        self.promotions.promote(&source, &target, promotion, dry_run).await
+    }
+    /// Rolls back the latest promotion, returning [None] if it does not have the id.
+    pub async fn rollback_promotion(&self, id: u64) -> Result<Option<PromotionReport>, NetworkError> {
+        let Some(target) = self.promotions.target_of(id).and_then(|f| self.get(&f)) else {
+            return Ok(None);
+        };
+        self.promotions.rollback(&target, id).await
+    }
+    /// Drops a namespace and deletes its records, killing its watchers.
+    /// The default namespace cannot be dropped.
+    pub async fn drop_namespace(&self, namespace: &str) -> Result<bool, NetworkError> {
+        if namespace == DEFAULT_NAMESPACE {
+            return Ok(false);
+        }
+        let Some(database) = self.databases.borrow_mut().remove(namespace) else {
+            return Ok(false);
+        };
+        database.kill_watchers();
+        self.promotions.forget(namespace);
+        let file = self.path.join(self.file_name(namespace));
+        if file.exists() {
+            std::fs::remove_file(file)?;
+        }
+        Ok(true)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use overseer::{models::{Key, Value}, network::DEFAULT_NAMESPACE};
+
+    use crate::database::Database;
+
+    use super::{validate_namespace, Namespaces};
+
+    #[test]
+    pub fn test_validate_namespace() {
+        assert!(validate_namespace("tenant-a").is_ok());
+        assert!(validate_namespace("tenant_2").is_ok());
+        assert!(validate_namespace("").is_err());
+        assert!(validate_namespace("../escape").is_err());
+        assert!(validate_namespace(&"a".repeat(65)).is_err());
+        assert!(validate_namespace("meta").is_err());
+    }
+
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_reopen_skips_database_files() {
+        let tf = tempfile::tempdir().unwrap();
+        let database = Database::new(tf.path(), "db").await.unwrap();
+        database.insert(&Key::from_str("app.mode"), Value::Integer(1), None).await.unwrap();
+        database.flush().await.unwrap();
+        assert!(tf.path().join("db.meta").exists());
+
+        // The metadata of the default namespace is not opened as a namespace.
+        let default = Rc::new(Database::new(tf.path(), "db").await.unwrap());
+        let namespaces = Namespaces::open(tf.path(), "db", default, Default::default(), Default::default(), Default::default(), vec![]).await.unwrap();
+        assert_eq!(namespaces.all().into_iter().map(|(f, _)| f).collect::<Vec<_>>(), vec![DEFAULT_NAMESPACE.to_string()]);
+    }
+}
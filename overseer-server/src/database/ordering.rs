@@ -0,0 +1,94 @@
+use std::{collections::BTreeSet, hash::{DefaultHasher, Hash, Hasher}};
+
+use overseer::models::Key;
+use tokio::sync::{Mutex, MutexGuard};
+
+
+/// How many shards the keys are spread across.
+pub const WRITE_SHARDS: usize = 64;
+
+/// Orders the writes of the database per shard of keys.
+///
+/// A write holds the lock of its shard from the moment it is recorded until
+/// the memory backend has the new value, the lock is fair so writes to a key
+/// are applied in the order they arrived. Reads never take the lock, they are
+/// served from the memory backend which swaps a record in a single step, so a
+/// read sees the last write that finished and never one that is in progress.
+///
+/// The guarantees this gives clients are:
+/// - Monotonic writes per key: writes to a key land in the store, the memory
+///   backend and the notifications in the same order.
+/// - Read your writes per connection: a connection is served one request at
+///   a time and the response to a write is sent once it is applied, so every
+///   later read on that connection sees it or something newer.
+///
+/// There is no ordering between keys that live on different shards, nor
+/// between connections beyond what the responses tell them.
+pub struct WriteOrder {
+    shards: Vec<Mutex<()>>
+}
+
+impl Default for WriteOrder {
+    fn default() -> Self {
+        Self::with_shards(WRITE_SHARDS)
+    }
+}
+
+impl WriteOrder {
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| Mutex::new(())).collect()
+        }
+    }
+    /// The shard a key belongs to.
+    pub fn shard(&self, key: &Key) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+    /// Waits until the key may be written, the write ends when the guard drops.
+    pub async fn lock(&self, key: &Key) -> MutexGuard<'_, ()> {
+        self.shards[self.shard(key)].lock().await
+    }
+    /// Waits until every key may be written, for a batch that has to land at once.
+    ///
+    /// The shards are taken in order so two batches never wait on each other.
+    pub async fn lock_all<'a, I>(&self, keys: I) -> Vec<MutexGuard<'_, ()>>
+    where
+        I: IntoIterator<Item = &'a Key>
+    {
+        let shards: BTreeSet<usize> = keys.into_iter().map(|f| self.shard(f)).collect();
+        let mut guards = Vec::with_capacity(shards.len());
+        for shard in shards {
+            guards.push(self.shards[shard].lock().await);
+        }
+        guards
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use overseer::models::Key;
+
+    use super::WriteOrder;
+
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_writes_queue_per_shard() {
+        let order = WriteOrder::with_shards(2);
+        let key = Key::from_str("hello");
+        let other = (0..).map(|i| Key::from_str(&format!("key-{i}")))
+            .find(|f| order.shard(f) != order.shard(&key))
+            .unwrap();
+
+        let guard = order.lock(&key).await;
+        // Another shard is free while this one is held.
+        drop(order.lock(&other).await);
+        // The same shard has to wait for the guard.
+        let waiting = monoio::time::timeout(Duration::from_millis(10), order.lock(&key)).await;
+        assert!(waiting.is_err());
+        drop(guard);
+        drop(order.lock(&key).await);
+    }
+}
@@ -0,0 +1,189 @@
+use std::{cell::{Cell, RefCell}, collections::{HashMap, VecDeque}};
+
+use overseer::{error::NetworkError, models::{Key, Promotion, PromotionReport, Value}};
+
+use super::{Change, Database};
+
+
+/// How many applied promotions can be rolled back.
+pub const PROMOTION_HISTORY_SIZE: usize = 16;
+
+/// The changes that make the target match the source, along with what they
+/// would change. Keys that already match are left out.
+pub fn plan_promotion(source: &Database, target: &Database, promotion: &Promotion) -> (Vec<Change>, PromotionReport) {
+    let mut current: HashMap<Key, Value> = target.scan(&promotion.target_prefix)
+        .into_iter()
+        .map(|(key, value)| (key, (*value).clone()))
+        .collect();
+
+    let mut changes = vec![];
+    let mut report = PromotionReport::default();
+    for (key, value) in source.scan(&promotion.source_prefix) {
+        let Some(key) = promotion.target_key(&key) else {
+            continue;
+        };
+        match current.remove(&key) {
+            Some(existing) if existing == *value => continue,
+            Some(..) => report.changed.push(key.clone()),
+            None => report.added.push(key.clone())
+        }
+        changes.push(Change::Insert(key, (*value).clone()));
+    }
+    if promotion.prune {
+        let mut leftover: Vec<Key> = current.into_keys().collect();
+        leftover.sort();
+        for key in leftover {
+            report.removed.push(key.clone());
+            changes.push(Change::Delete(key));
+        }
+    }
+    (changes, report)
+}
+
+/// A promotion that was applied, with what it replaced.
+struct Applied {
+    id: u64,
+    /// The namespace the promotion wrote to.
+    target: String,
+    /// Restores every key the promotion touched.
+    undo: Vec<Change>,
+    report: PromotionReport
+}
+
+/// The recent promotions, newest last, so they can be rolled back in the
+/// reverse order they were applied.
+///
+/// The log is kept in memory, a restart forgets what can be rolled back.
+pub struct PromotionLog {
+    applied: RefCell<VecDeque<Applied>>,
+    next_id: Cell<u64>,
+    capacity: usize
+}
+
+impl Default for PromotionLog {
+    fn default() -> Self {
+        Self::with_capacity(PROMOTION_HISTORY_SIZE)
+    }
+}
+
+impl PromotionLog {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            applied: RefCell::new(VecDeque::with_capacity(capacity)),
+            next_id: Cell::new(1),
+            capacity
+        }
+    }
+    /// Promotes the source into the target in a single batch, see
+    /// [Database::apply_batch], or only plans it if it is a dry run.
+    pub async fn promote(&self, source: &Database, target: &Database, promotion: &Promotion, dry_run: bool) -> Result<PromotionReport, NetworkError> {
+        let (changes, mut report) = plan_promotion(source, target, promotion);
+        if dry_run || changes.is_empty() {
+            return Ok(report);
+        }
+
+        let mut undo = vec![];
+        for change in &changes {
+            let key = match change {
+                Change::Insert(key, _) | Change::Delete(key) => key
+            };
+            undo.push(match target.get(key).await {
+                Some(value) => Change::Insert(key.clone(), (*value).clone()),
+                None => Change::Delete(key.clone())
+            });
+        }
+        target.apply_batch(changes).await?;
+
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        report.id = Some(id);
+
+        let mut applied = self.applied.borrow_mut();
+        if applied.len() == self.capacity {
+            applied.pop_front();
+        }
+        applied.push_back(Applied { id, target: promotion.target.clone(), undo, report: report.clone() });
+        Ok(report)
+    }
+    /// The namespace the latest promotion wrote to, if it is the one with the id.
+    pub fn target_of(&self, id: u64) -> Option<String> {
+        self.applied.borrow().back().filter(|f| f.id == id).map(|f| f.target.clone())
+    }
+    /// Restores what the target of the latest promotion held before it,
+    /// returning [None] unless the promotion with the id is the latest.
+    ///
+    /// Writes made to the promoted keys since the promotion are overwritten.
+    pub async fn rollback(&self, target: &Database, id: u64) -> Result<Option<PromotionReport>, NetworkError> {
+        let latest = self.applied.borrow().back().is_some_and(|f| f.id == id);
+        let Some(applied) = latest.then(|| self.applied.borrow_mut().pop_back()).flatten() else {
+            return Ok(None);
+        };
+        if let Err(e) = target.apply_batch(applied.undo.clone()).await {
+            self.applied.borrow_mut().push_back(applied);
+            return Err(e);
+        }
+        // The rollback adds what the promotion removed and the other way around.
+        Ok(Some(PromotionReport {
+            id: Some(applied.id),
+            added: applied.report.removed,
+            changed: applied.report.changed,
+            removed: applied.report.added
+        }))
+    }
+    /// Forgets the promotions into a namespace that was dropped.
+    pub fn forget(&self, target: &str) {
+        self.applied.borrow_mut().retain(|f| f.target != target);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::models::{Key, Promotion, Value};
+
+    use crate::database::Database;
+
+    use super::PromotionLog;
+
+    #[monoio::test]
+    pub async fn test_promote_and_rollback() {
+        let tf = tempfile::tempdir().unwrap();
+        let staging = Database::new(tf.path(), "db.staging").await.unwrap();
+        let production = Database::new(tf.path(), "db").await.unwrap();
+        staging.insert(Key::from_str("app.mode"), Value::String("b".into()), None).await.unwrap();
+        staging.insert(Key::from_str("app.port"), Value::Integer(80), None).await.unwrap();
+        production.insert(Key::from_str("app.mode"), Value::String("a".into()), None).await.unwrap();
+        production.insert(Key::from_str("app.port"), Value::Integer(80), None).await.unwrap();
+        production.insert(Key::from_str("app.legacy"), Value::Integer(1), None).await.unwrap();
+
+        let promotion = Promotion {
+            source: "staging".to_string(),
+            source_prefix: "app.".to_string(),
+            target: "production".to_string(),
+            target_prefix: "app.".to_string(),
+            prune: true
+        };
+        let log = PromotionLog::default();
+
+        // A dry run changes nothing.
+        let report = log.promote(&staging, &production, &promotion, true).await.unwrap();
+        assert_eq!(report.id, None);
+        assert_eq!(report.changed, vec![Key::from_str("app.mode")]);
+        assert_eq!(report.removed, vec![Key::from_str("app.legacy")]);
+        assert!(report.added.is_empty());
+        assert_eq!(*production.get(&Key::from_str("app.mode")).await.unwrap(), Value::String("a".into()));
+
+        let report = log.promote(&staging, &production, &promotion, false).await.unwrap();
+        let id = report.id.unwrap();
+        assert_eq!(*production.get(&Key::from_str("app.mode")).await.unwrap(), Value::String("b".into()));
+        assert!(production.get(&Key::from_str("app.legacy")).await.is_none());
+
+        let report = log.rollback(&production, id).await.unwrap().unwrap();
+        assert_eq!(report.added, vec![Key::from_str("app.legacy")]);
+        assert_eq!(*production.get(&Key::from_str("app.mode")).await.unwrap(), Value::String("a".into()));
+        assert_eq!(*production.get(&Key::from_str("app.legacy")).await.unwrap(), Value::Integer(1));
+
+        // It cannot be rolled back twice.
+        assert!(log.rollback(&production, id).await.unwrap().is_none());
+    }
+}
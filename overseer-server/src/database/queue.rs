@@ -0,0 +1,128 @@
+use std::{cell::{Cell, RefCell}, collections::HashMap, time::Instant};
+
+use overseer::models::Key;
+
+
+/// The digits of the sequence in the key of a queue item, so the keys sort
+/// in the order the items were enqueued.
+const SEQUENCE_DIGITS: usize = 20;
+
+/// A consumer holding an item until it acknowledges it or the lease runs out.
+struct Lease {
+    id: u64,
+    until: Instant
+}
+
+/// The sequences and leases of the work queues of a store.
+///
+/// A queue is a prefix, every item is an ordinary record under it named by
+/// its sequence, so the items are as durable as any other record. The leases
+/// only live in memory, after a restart every item is visible again and is
+/// handed out once more, the delivery is at least once.
+#[derive(Default)]
+pub struct WorkQueues {
+    /// The next sequence of each queue, read from its last item when first used.
+    sequences: RefCell<HashMap<String, u64>>,
+    leases: RefCell<HashMap<Key, Lease>>,
+    next_lease: Cell<u64>
+}
+
+impl WorkQueues {
+    /// The key of the next item of the queue. The last key under the queue
+    /// is only asked for the first time the queue is used.
+    pub fn next_item<F>(&self, queue: &str, last: F) -> Key
+    where
+        F: FnOnce() -> Option<Key>
+    {
+        let mut sequences = self.sequences.borrow_mut();
+        let sequence = sequences.entry(queue.to_string()).or_insert_with(|| {
+            last().and_then(|f| sequence_of(&f)).map(|f| f + 1).unwrap_or(0)
+        });
+        let key = Key::from_str(&format!("{}{:0width$}", queue_prefix(queue), sequence, width = SEQUENCE_DIGITS));
+        *sequence += 1;
+        key
+    }
+    /// Whether the item is leased to a consumer at the moment.
+    pub fn is_leased(&self, item: &Key, now: Instant) -> bool {
+        self.leases.borrow().get(item).is_some_and(|f| f.until > now)
+    }
+    /// Leases the item until the deadline, replacing a lease that ran out.
+    pub fn lease(&self, item: &Key, until: Instant) -> u64 {
+        let id = self.next_lease.get() + 1;
+        self.next_lease.set(id);
+        self.leases.borrow_mut().insert(item.clone(), Lease { id, until });
+        id
+    }
+    /// Whether the lease is the last one handed out for the item. A lease
+    /// that ran out still holds until another consumer takes the item.
+    pub fn holds(&self, item: &Key, lease: u64) -> bool {
+        self.leases.borrow().get(item).is_some_and(|f| f.id == lease)
+    }
+    /// Ends the lease, returning false if it is not the one held on the item.
+    pub fn release(&self, item: &Key, lease: u64) -> bool {
+        let mut leases = self.leases.borrow_mut();
+        match leases.get(item) {
+            Some(held) if held.id == lease => {
+                leases.remove(item);
+                true
+            }
+            _ => false
+        }
+    }
+}
+
+/// The prefix the items of the queue are stored under.
+pub fn queue_prefix(queue: &str) -> String {
+    format!("{queue}.")
+}
+
+/// The queue an item belongs to, [None] if the key is not named like an item.
+pub fn queue_of(item: &Key) -> Option<&str> {
+    let (queue, _) = item.as_str().rsplit_once('.')?;
+    sequence_of(item)?;
+    Some(queue)
+}
+
+fn sequence_of(item: &Key) -> Option<u64> {
+    let (_, sequence) = item.as_str().rsplit_once('.')?;
+    match sequence.len() {
+        SEQUENCE_DIGITS => sequence.parse().ok(),
+        _ => None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use overseer::models::Key;
+
+    use super::{queue_of, WorkQueues};
+
+    #[test]
+    pub fn test_work_queue_leases() {
+        let queues = WorkQueues::default();
+        let first = queues.next_item("jobs", || None);
+        assert_eq!(first.as_str(), "jobs.00000000000000000000");
+        assert_eq!(queue_of(&first), Some("jobs"));
+        assert_eq!(queue_of(&Key::from_str("jobs.a")), None);
+
+        // A queue used for the first time continues after its last item.
+        let resumed = queues.next_item("builds", || Some(Key::from_str("builds.00000000000000000041")));
+        assert_eq!(resumed.as_str(), "builds.00000000000000000042");
+        assert!(queues.next_item("jobs", || unreachable!()) > first);
+
+        let now = Instant::now();
+        let lease = queues.lease(&first, now + Duration::from_secs(30));
+        assert!(queues.is_leased(&first, now));
+        assert!(!queues.is_leased(&first, now + Duration::from_secs(31)));
+
+        // Once the lease runs out, the item goes to the next consumer.
+        let next = queues.lease(&first, now + Duration::from_secs(60));
+        assert!(!queues.holds(&first, lease));
+        assert!(!queues.release(&first, lease));
+        assert!(queues.release(&first, next));
+        assert!(!queues.is_leased(&first, now));
+    }
+}
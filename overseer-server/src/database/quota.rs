@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use overseer::error::NetworkError;
+
+
+/// Limits on what a database holds, so one misbehaving client cannot fill
+/// the disk. Every limit is off unless it is set.
+///
+/// The writes that break a limit are refused with
+/// [NetworkError::QuotaExceeded], the ones that free up room always go
+/// through, so a database over its quota can still be cleaned up.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Quota {
+    /// The most records the database holds.
+    pub max_keys: Option<usize>,
+    /// The largest value written, in bytes, see [overseer::models::Value::size].
+    pub max_value_size: Option<usize>,
+    /// The most bytes the keys and values of the records take.
+    pub max_bytes: Option<usize>
+}
+
+/// How many records a database holds and about how many bytes they take.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct QuotaUsage {
+    pub keys: usize,
+    pub bytes: usize
+}
+
+impl Quota {
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = Some(max_keys);
+        self
+    }
+    pub fn with_max_value_size(mut self, max_value_size: usize) -> Self {
+        self.max_value_size = Some(max_value_size);
+        self
+    }
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+    /// Whether no limit is set.
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+    /// The share of the limits each of the shards a database is split
+    /// across keeps, the largest value is the same for every shard.
+    pub fn share(&self, shards: usize) -> Self {
+        let shards = shards.max(1);
+        Self {
+            max_keys: self.max_keys.map(|f| f.div_ceil(shards)),
+            max_value_size: self.max_value_size,
+            max_bytes: self.max_bytes.map(|f| f.div_ceil(shards))
+        }
+    }
+    /// Checks a write taking the usage of the database from `before` to
+    /// `after`, whose largest value takes `largest` bytes.
+    pub fn check(&self, before: QuotaUsage, after: QuotaUsage, largest: usize) -> Result<(), NetworkError> {
+        if let Some(max) = self.max_value_size.filter(|f| largest > *f) {
+            return Err(NetworkError::QuotaExceeded(format!("The value is {largest} bytes, the most allowed is {max}")));
+        }
+        if let Some(max) = self.max_keys.filter(|f| after.keys > before.keys && after.keys > *f) {
+            return Err(NetworkError::QuotaExceeded(format!("The namespace holds the most keys allowed, {max}")));
+        }
+        if let Some(max) = self.max_bytes.filter(|f| after.bytes > before.bytes && after.bytes > *f) {
+            return Err(NetworkError::QuotaExceeded(format!("The namespace would hold {} bytes, the most allowed is {max}", after.bytes)));
+        }
+        Ok(())
+    }
+}
+
+/// The quotas of the namespaces, those without one of their own take the
+/// default.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct QuotaPolicy {
+    pub default: Quota,
+    pub namespaces: HashMap<String, Quota>
+}
+
+impl QuotaPolicy {
+    /// The quota every namespace without one of its own takes.
+    pub fn with_default(mut self, quota: Quota) -> Self {
+        self.default = quota;
+        self
+    }
+    pub fn with_namespace<S: Into<String>>(mut self, namespace: S, quota: Quota) -> Self {
+        self.namespaces.insert(namespace.into(), quota);
+        self
+    }
+    pub fn quota_of(&self, namespace: &str) -> Quota {
+        self.namespaces.get(namespace).copied().unwrap_or(self.default)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::error::NetworkError;
+
+    use super::{Quota, QuotaPolicy, QuotaUsage};
+
+    #[test]
+    pub fn test_quota() {
+        let quota = Quota::default().with_max_keys(2).with_max_value_size(8).with_max_bytes(100);
+        let usage = |keys, bytes| QuotaUsage { keys, bytes };
+        assert!(quota.check(usage(1, 10), usage(2, 20), 8).is_ok());
+        assert!(matches!(quota.check(usage(1, 10), usage(2, 20), 9), Err(NetworkError::QuotaExceeded(..))));
+        assert!(quota.check(usage(2, 10), usage(3, 20), 1).is_err());
+        assert!(quota.check(usage(1, 90), usage(1, 101), 1).is_err());
+        // A database over its quota can still shrink.
+        assert!(quota.check(usage(5, 500), usage(5, 400), 1).is_ok());
+
+        assert_eq!(quota.share(3), Quota { max_keys: Some(1), max_value_size: Some(8), max_bytes: Some(34) });
+        let policy = QuotaPolicy::default().with_namespace("tenant-a", quota);
+        assert_eq!(policy.quota_of("tenant-a"), quota);
+        assert!(policy.quota_of("tenant-b").is_unlimited());
+    }
+}
@@ -0,0 +1,247 @@
+use std::time::Duration;
+
+use overseer::{error::NetworkError, models::{Acknowledgement, CompactionRun, DatabaseStats, DeleteCondition, ExportedRecord, HistoryEntry, Key, KeyChild, KeyDiff, KeyDrift, KeyMatcher, LeasedItem, RecordMeta, Revision, Schema, Value, WatchedKey}};
+
+use crate::net::ClientId;
+
+use super::{Database, Manifest, PlacementStats, ShardPool, StorageSnapshot};
+
+
+/// The records a request is served from, the database of a namespace or
+/// the shards the default namespace is split across.
+///
+/// The calls take and return the same types for either, so a request is
+/// served the same way whichever holds its records.
+#[derive(Clone, Copy)]
+pub enum Records<'a> {
+    Local(&'a Database),
+    Sharded(&'a ShardPool)
+}
+
+impl Records<'_> {
+    pub async fn get_with_meta(&self, key: &Key) -> Result<Option<(Value, u64, Option<RecordMeta>)>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.get_with_meta(key).map(|(value, version, meta)| ((*value).clone(), version, meta))),
+            Self::Sharded(shards) => shards.get_with_meta(key).await
+        }
+    }
+    pub async fn insert(&self, key: &Key, value: Value, writer: Option<ClientId>) -> Result<u64, NetworkError> {
+        match self {
+            Self::Local(database) => database.insert(key, value, writer).await,
+            Self::Sharded(shards) => shards.insert(key, value, writer).await
+        }
+    }
+    pub async fn insert_acknowledged(&self, key: &Key, value: Value, acknowledgement: Acknowledgement, writer: Option<ClientId>) -> Result<u64, NetworkError> {
+        match self {
+            Self::Local(database) => database.insert_acknowledged(key, value, acknowledgement, writer).await,
+            Self::Sharded(shards) => shards.insert_acknowledged(key, value, acknowledgement, writer).await
+        }
+    }
+    pub async fn insert_if_version(&self, key: &Key, value: Value, version: u64, writer: Option<ClientId>) -> Result<u64, NetworkError> {
+        match self {
+            Self::Local(database) => database.insert_if_version(key, value, version, writer).await,
+            Self::Sharded(shards) => shards.insert_if_version(key, value, version, writer).await
+        }
+    }
+    pub async fn get_or_insert(&self, key: &Key, default: Value, writer: Option<ClientId>) -> Result<(Value, u64), NetworkError> {
+        match self {
+            Self::Local(database) => {
+                let (value, version, _) = database.get_or_insert(key, default, writer).await?;
+                Ok(((*value).clone(), version))
+            }
+            Self::Sharded(shards) => shards.get_or_insert(key, default, writer).await
+        }
+    }
+    pub async fn increment(&self, key: &Key, delta: i64, writer: Option<ClientId>) -> Result<(Value, u64), NetworkError> {
+        match self {
+            Self::Local(database) => database.increment(key, delta, writer).await.map(|(value, version)| ((*value).clone(), version)),
+            Self::Sharded(shards) => shards.increment(key, delta, writer).await
+        }
+    }
+    pub async fn delete(&self, key: &Key) -> Result<(), NetworkError> {
+        match self {
+            Self::Local(database) => database.delete(key).await,
+            Self::Sharded(shards) => shards.delete(key).await
+        }
+    }
+    pub async fn delete_if(&self, key: &Key, condition: DeleteCondition) -> Result<bool, NetworkError> {
+        match self {
+            Self::Local(database) => database.delete_if(key, &condition).await,
+            Self::Sharded(shards) => shards.delete_if(key, condition).await
+        }
+    }
+    pub async fn rename(&self, from: &Key, to: &Key, overwrite: bool, writer: Option<ClientId>) -> Result<Option<u64>, NetworkError> {
+        match self {
+            Self::Local(database) => database.rename(from, to, overwrite).await,
+            Self::Sharded(shards) => shards.rename(from, to, overwrite, writer).await
+        }
+    }
+    pub async fn snapshot_get(&self, keys: &[Key]) -> Result<Vec<(Option<Value>, u64)>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.snapshot_get(keys).into_iter().map(|(value, version)| (value.map(|f| (*f).clone()), version)).collect()),
+            Self::Sharded(shards) => shards.snapshot_get(keys).await
+        }
+    }
+    pub async fn insert_all(&self, records: Vec<(Key, Value)>, writer: Option<ClientId>) -> Result<Vec<u64>, NetworkError> {
+        match self {
+            Self::Local(database) => database.insert_all(records, writer).await,
+            Self::Sharded(shards) => shards.insert_all(records, writer).await
+        }
+    }
+    pub async fn bulk_load(&self, records: Vec<(Key, Value)>, writer: Option<ClientId>) -> Result<usize, NetworkError> {
+        match self {
+            Self::Local(database) => database.bulk_load(records, writer).await,
+            Self::Sharded(shards) => shards.bulk_load(records, writer).await
+        }
+    }
+    pub async fn archive(&self, before: u64, delete: bool) -> Result<Vec<(Key, Value)>, NetworkError> {
+        match self {
+            Self::Local(database) => database.archive(before, delete).await,
+            Self::Sharded(shards) => shards.archive(before, delete).await
+        }
+    }
+    pub async fn history(&self, key: &Key, limit: usize) -> Result<Vec<HistoryEntry>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.history().entries(key, limit)),
+            Self::Sharded(shards) => shards.history(key, limit).await
+        }
+    }
+    pub async fn value_at(&self, key: &Key, revision: Revision) -> Result<Option<Option<Value>>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.value_at(key, revision).await.map(|value| value.map(|f| (*f).clone()))),
+            Self::Sharded(shards) => shards.value_at(key, revision).await
+        }
+    }
+    pub async fn key_diff(&self, key: &Key, from: Revision, to: Revision) -> Result<Option<KeyDiff>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.history().diff(key, from, to)),
+            Self::Sharded(shards) => shards.key_diff(key, from, to).await
+        }
+    }
+    pub async fn enqueue(&self, queue: &str, value: Value) -> Result<(Key, u64), NetworkError> {
+        match self {
+            Self::Local(database) => database.enqueue(queue, value).await,
+            Self::Sharded(shards) => shards.enqueue(queue, value).await
+        }
+    }
+    pub async fn dequeue(&self, queue: &str, visibility: Duration) -> Result<Option<LeasedItem>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.dequeue(queue, visibility)),
+            Self::Sharded(shards) => shards.dequeue(queue, visibility).await
+        }
+    }
+    pub async fn ack(&self, item: &Key, lease: u64) -> Result<bool, NetworkError> {
+        match self {
+            Self::Local(database) => database.ack(item, lease).await,
+            Self::Sharded(shards) => shards.ack(item, lease).await
+        }
+    }
+    pub async fn nack(&self, item: &Key, lease: u64) -> Result<bool, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.nack(item, lease)),
+            Self::Sharded(shards) => shards.nack(item, lease).await
+        }
+    }
+    pub async fn acquire(&self, key: &Key, client: ClientId, ttl: Duration) -> Result<Option<u64>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.acquire(key, client, ttl).await),
+            Self::Sharded(shards) => shards.acquire(key, client, ttl).await
+        }
+    }
+    pub async fn release_lock(&self, key: &Key, client: ClientId) -> Result<bool, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.release_lock(key, client).await),
+            Self::Sharded(shards) => shards.release_lock(key, client).await
+        }
+    }
+    pub async fn keys(&self, cursor: Option<&Key>, limit: usize) -> Result<(Vec<Key>, Option<Key>), NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.keys(cursor, limit)),
+            Self::Sharded(shards) => shards.keys(cursor, limit).await
+        }
+    }
+    pub async fn find_keys(&self, matcher: &KeyMatcher, cursor: Option<&Key>, limit: usize) -> Result<(Vec<Key>, Option<Key>), NetworkError> {
+        match self {
+            Self::Local(database) => database.find_keys(matcher, cursor, limit),
+            Self::Sharded(shards) => shards.find_keys(matcher, cursor, limit).await
+        }
+    }
+    pub async fn children(&self, key: &Key, cursor: Option<&Key>, limit: usize) -> Result<(Vec<KeyChild>, Option<Key>), NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.children(key, cursor, limit)),
+            Self::Sharded(shards) => shards.children(key, cursor, limit).await
+        }
+    }
+    pub async fn export(&self, cursor: Option<&Key>, limit: usize) -> Result<(Vec<ExportedRecord>, Option<Key>), NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.export(cursor, limit)),
+            Self::Sharded(shards) => shards.export(cursor, limit).await
+        }
+    }
+    pub async fn watched_keys(&self, key: Option<&Key>) -> Result<Vec<WatchedKey>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.watched_keys(key)),
+            Self::Sharded(shards) => shards.watched_keys(key).await
+        }
+    }
+    pub async fn query_index(&self, field: &str, value: &Value) -> Result<Option<Vec<Key>>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.query_index(field, value)),
+            Self::Sharded(shards) => shards.query_index(field, value).await
+        }
+    }
+    pub async fn register_schema(&self, prefix: &str, schema: Option<Schema>) -> Result<Option<Schema>, NetworkError> {
+        match self {
+            Self::Local(database) => database.schemas().register(prefix, schema).await,
+            Self::Sharded(shards) => shards.register_schema(prefix, schema).await
+        }
+    }
+    pub async fn schemas(&self) -> Result<Vec<(String, Schema)>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.schemas().schemas()),
+            Self::Sharded(shards) => shards.schemas().await
+        }
+    }
+    pub async fn stats(&self) -> Result<DatabaseStats, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.stats()),
+            Self::Sharded(shards) => shards.stats().await
+        }
+    }
+    pub async fn storage_stats(&self) -> Result<StorageSnapshot, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.storage_stats()),
+            Self::Sharded(shards) => shards.storage_stats().await
+        }
+    }
+    pub async fn placement_stats(&self) -> Result<PlacementStats, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.placement().stats()),
+            Self::Sharded(shards) => shards.placement_stats().await
+        }
+    }
+    pub async fn hottest(&self, limit: usize) -> Result<Vec<(Key, u64)>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.placement().hottest(limit)),
+            Self::Sharded(shards) => shards.hottest(limit).await
+        }
+    }
+    pub async fn compactions(&self) -> Result<Vec<CompactionRun>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.compactions().runs()),
+            Self::Sharded(shards) => shards.compactions().await
+        }
+    }
+    pub async fn check_manifest(&self, manifest: &Manifest) -> Result<Vec<KeyDrift>, NetworkError> {
+        match self {
+            Self::Local(database) => Ok(database.check_manifest(manifest).await),
+            Self::Sharded(shards) => shards.check_manifest(manifest).await
+        }
+    }
+    pub async fn next_sequence(&self, name: &str) -> Result<u64, NetworkError> {
+        match self {
+            Self::Local(database) => database.sequences().next(name).await,
+            Self::Sharded(shards) => shards.next_sequence(name).await
+        }
+    }
+}
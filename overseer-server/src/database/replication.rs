@@ -0,0 +1,500 @@
+use std::{cell::{Cell, RefCell}, collections::{BTreeMap, BTreeSet}, rc::Rc};
+
+use overseer::{error::{NetworkError, StorageError}, models::{Key, Value}};
+use tokio::sync::{mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender}, Notify};
+
+use super::{Change, Database};
+
+
+/// A change of the leader stamped with the version of the store it produced.
+///
+/// Every change bumps the version by one, so the changes a follower receives
+/// are numbered without gaps.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SequencedChange {
+    pub sequence: u64,
+    pub change: Change
+}
+
+/// Every record of the leader as of a version of the store.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ReplicationSnapshot {
+    /// The last change the records include.
+    pub sequence: u64,
+    /// The records in key order.
+    pub records: Vec<(Key, Value)>
+}
+
+/// A prefix whose records are transferred whole once it holds at most this
+/// many on the leader, rather than comparing its children.
+pub const DELTA_BATCH_RECORDS: usize = 32;
+
+/// The checksum of the records under a prefix of the key space.
+///
+/// The prefixes split the keys at their dots, the children of `app.` are the
+/// keys and prefixes one segment deeper such as `app.mode` and `app.db.`.
+/// A child naming a single key is a leaf.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PrefixDigest {
+    pub prefix: String,
+    /// The wrapping sum of the fingerprints of the records, so the checksum
+    /// of a prefix is the sum of those of its children.
+    pub checksum: u64,
+    pub records: usize,
+    pub leaf: bool
+}
+
+/// What a delta sync exchanged to bring a follower in step.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct DeltaReport {
+    /// How many digests were compared.
+    pub compared: usize,
+    /// How many records of the leader were transferred.
+    pub transferred: usize,
+    /// How many records only the follower held.
+    pub deleted: usize
+}
+
+/// The records of the leader as of a version of the store, which a follower
+/// compares itself against to fetch only the prefixes that diverged.
+///
+/// The records are shared with the store rather than copied, so holding the
+/// source is cheap while the follower syncs.
+pub struct DeltaSource {
+    sequence: u64,
+    /// The records in key order.
+    records: Vec<(Key, Rc<Value>)>
+}
+
+impl DeltaSource {
+    pub fn new(sequence: u64, records: Vec<(Key, Rc<Value>)>) -> Self {
+        Self { sequence, records }
+    }
+    /// The last change the records include.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+    /// The digests of the children of the prefix.
+    pub fn digests(&self, prefix: &str) -> Vec<PrefixDigest> {
+        digests(self.under(prefix), prefix)
+    }
+    /// The records under the prefix, for a follower to replace its own with.
+    pub fn records(&self, prefix: &str) -> Vec<(Key, Value)> {
+        self.under(prefix).map(|(key, value)| (key.clone(), value.clone())).collect()
+    }
+    fn under<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a Key, &'a Value)> + 'a {
+        let start = self.records.partition_point(|(key, _)| key.as_str() < prefix);
+        self.records[start..].iter()
+            .take_while(move |(key, _)| key.as_str().starts_with(prefix))
+            .map(|(key, value)| (key, &**value))
+    }
+}
+
+/// Groups the records under the prefix into the digests of its children, in order.
+pub fn digests<'a, I>(records: I, prefix: &str) -> Vec<PrefixDigest>
+where
+    I: IntoIterator<Item = (&'a Key, &'a Value)>
+{
+    let mut digests: Vec<PrefixDigest> = vec![];
+    for (key, value) in records {
+        let rest = &key.as_str()[prefix.len()..];
+        let (child, leaf) = match rest.find('.') {
+            Some(at) => (&key.as_str()[..prefix.len() + at + 1], false),
+            None => (key.as_str(), true)
+        };
+        let fingerprint = fingerprint(key, value);
+        match digests.last_mut() {
+            // Keys sharing a prefix are adjacent in key order.
+            Some(last) if !leaf && last.prefix == child => {
+                last.checksum = last.checksum.wrapping_add(fingerprint);
+                last.records += 1;
+            }
+            _ => digests.push(PrefixDigest { prefix: child.to_string(), checksum: fingerprint, records: 1, leaf })
+        }
+    }
+    digests
+}
+
+/// A stable 64 bit FNV-1a hash of a record, the same on every platform and build.
+pub fn fingerprint(key: &Key, value: &Value) -> u64 {
+    let mut hash = FNV_OFFSET;
+    write_bytes(&mut hash, key.as_str().as_bytes());
+    write_value(&mut hash, value);
+    hash
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn write_bytes(hash: &mut u64, bytes: &[u8]) {
+    // The length first, so the boundaries of the fields are part of the hash.
+    for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+        *hash ^= *byte as u64;
+        *hash = hash.wrapping_mul(FNV_PRIME);
+    }
+}
+
+fn write_value(hash: &mut u64, value: &Value) {
+    match value {
+        Value::String(string) => {
+            write_bytes(hash, &[0]);
+            write_bytes(hash, string.as_bytes());
+        }
+        Value::Integer(integer) => {
+            write_bytes(hash, &[1]);
+            write_bytes(hash, &integer.to_le_bytes());
+        }
+        Value::Map(fields) => {
+            write_bytes(hash, &[2]);
+            write_bytes(hash, &(fields.len() as u64).to_le_bytes());
+            for (name, value) in fields {
+                write_bytes(hash, name.as_bytes());
+                write_value(hash, value);
+            }
+        }
+        Value::Json(json) => {
+            write_bytes(hash, &[3]);
+            write_bytes(hash, json.as_bytes());
+        }
+    }
+}
+
+/// Lets a follower tell the leader how far it applied the stream, which the
+/// writes waiting on [overseer::models::Acknowledgement::Replicated] count.
+pub struct ReplicationAck {
+    applied: Rc<Cell<u64>>,
+    acknowledged: Rc<Notify>
+}
+
+impl ReplicationAck {
+    /// Acknowledges every change up to the sequence.
+    pub fn acknowledge(&self, sequence: u64) {
+        self.applied.set(self.applied.get().max(sequence));
+        self.acknowledged.notify_waiters();
+    }
+}
+
+struct Follower {
+    changes: UnboundedSender<SequencedChange>,
+    /// How far the follower acknowledged applying, if it acknowledges at all.
+    applied: Option<Rc<Cell<u64>>>,
+    /// The prefixes of the keys whose changes it is sent, every change if [None].
+    prefixes: Option<Vec<String>>
+}
+
+impl Follower {
+    fn wants(&self, change: &Change) -> bool {
+        let key = match change {
+            Change::Insert(key, _) | Change::Delete(key) => key
+        };
+        self.prefixes.as_ref().is_none_or(|f| f.iter().any(|f| key.as_str().starts_with(f.as_str())))
+    }
+}
+
+/// Hands the changes of the leader to the followers that joined it.
+///
+/// Recording is a no-op while no follower is connected.
+#[derive(Default)]
+pub struct ReplicationLog {
+    followers: RefCell<Vec<Follower>>,
+    acknowledged: Rc<Notify>
+}
+
+impl ReplicationLog {
+    /// Streams every change recorded from now on.
+    pub fn follow(&self) -> UnboundedReceiver<SequencedChange> {
+        let (sender, changes) = unbounded_channel();
+        self.followers.borrow_mut().push(Follower { changes: sender, applied: None, prefixes: None });
+        changes
+    }
+    /// Streams the changes under the prefixes recorded from now on, which
+    /// leaves gaps in their sequences, see [super::PeerCache].
+    pub fn follow_prefixes(&self, prefixes: Vec<String>) -> UnboundedReceiver<SequencedChange> {
+        let (sender, changes) = unbounded_channel();
+        self.followers.borrow_mut().push(Follower { changes: sender, applied: None, prefixes: Some(prefixes) });
+        changes
+    }
+    /// Streams every change recorded from now on to a follower that
+    /// acknowledges what it applied. Experimental, the follower has to run
+    /// in this process, nothing serves the stream over the network yet.
+    pub fn follow_acknowledged(&self) -> (UnboundedReceiver<SequencedChange>, ReplicationAck) {
+        let (sender, changes) = unbounded_channel();
+        let applied = Rc::new(Cell::new(0));
+        self.followers.borrow_mut().push(Follower { changes: sender, applied: Some(Rc::clone(&applied)), prefixes: None });
+        (changes, ReplicationAck { applied, acknowledged: Rc::clone(&self.acknowledged) })
+    }
+    /// How many of the followers acknowledged the change.
+    pub fn replicated(&self, sequence: u64) -> usize {
+        self.followers.borrow().iter()
+            .filter(|f| f.applied.as_ref().is_some_and(|f| f.get() >= sequence))
+            .count()
+    }
+    /// Waits until enough followers acknowledged the change. This only
+    /// resolves once that many followers joined.
+    pub async fn wait_replicated(&self, sequence: u64, followers: usize) {
+        loop {
+            // Listening before checking, so an acknowledgement in between is not missed.
+            let acknowledged = self.acknowledged.notified();
+            if self.replicated(sequence) >= followers {
+                return;
+            }
+            acknowledged.await;
+        }
+    }
+    /// Records a change, the followers that went away are dropped.
+    pub fn record<F>(&self, sequence: u64, change: F)
+    where
+        F: FnOnce() -> Change
+    {
+        let mut followers = self.followers.borrow_mut();
+        if followers.is_empty() {
+            return;
+        }
+        let change = SequencedChange { sequence, change: change() };
+        followers.retain(|f| !f.wants(&change.change) || f.changes.send(change.clone()).is_ok());
+    }
+    pub fn followers(&self) -> usize {
+        self.followers.borrow().len()
+    }
+}
+
+/// Keeps a follower in step with the leader it replicates.
+///
+/// The follower starts from a [ReplicationSnapshot] and applies the stream
+/// behind it in order. A change it already holds is skipped, so replaying
+/// part of the stream is harmless, and a change after a missing one is
+/// refused with [StorageError::ReplicationGap] so the follower never diverges
+/// silently. It has to restore a new snapshot then, or sync against a
+/// [DeltaSource] to only fetch what changed while it was behind.
+pub struct Replica {
+    /// The last change of the leader the follower holds.
+    applied: u64
+}
+
+impl Replica {
+    /// Makes the follower hold exactly the records of the snapshot.
+    pub async fn restore(database: &Database, snapshot: ReplicationSnapshot) -> Result<Self, NetworkError> {
+        let mut changes: Vec<Change> = database.scan("")
+            .into_iter()
+            .filter(|(key, _)| snapshot.records.binary_search_by(|(f, _)| f.cmp(key)).is_err())
+            .map(|(key, _)| Change::Delete(key))
+            .collect();
+        changes.extend(snapshot.records.into_iter().map(|(key, value)| Change::Insert(key, value)));
+        database.apply_batch(changes).await?;
+        Ok(Self { applied: snapshot.sequence })
+    }
+    /// Brings a follower that already holds records in step with the leader
+    /// by comparing the checksums of the prefixes, descending only into the
+    /// ones that differ. The divergent records are applied in one batch.
+    pub async fn sync(database: &Database, source: &DeltaSource) -> Result<(Self, DeltaReport), NetworkError> {
+        let mut report = DeltaReport::default();
+        let mut changes = vec![];
+        let mut pending = vec![String::new()];
+        while let Some(prefix) = pending.pop() {
+            let local = database.scan(&prefix);
+            let ours = digests(local.iter().map(|(key, value)| (key, &**value)), &prefix);
+            let theirs = source.digests(&prefix);
+            report.compared += theirs.len();
+
+            // A leaf and a prefix never share a name, as a prefix ends with a dot.
+            let ours: BTreeMap<&str, &PrefixDigest> = ours.iter().map(|f| (f.prefix.as_str(), f)).collect();
+            let theirs: BTreeMap<&str, &PrefixDigest> = theirs.iter().map(|f| (f.prefix.as_str(), f)).collect();
+            let children: BTreeSet<&str> = ours.keys().chain(theirs.keys()).copied().collect();
+            for child in children {
+                let (mine, leader) = (ours.get(child), theirs.get(child));
+                if mine.map(|f| (f.checksum, f.records)) == leader.map(|f| (f.checksum, f.records)) {
+                    continue;
+                }
+                // A large prefix both hold is compared child by child, anything else is sent whole.
+                if let (Some(_), Some(leader)) = (mine, leader) {
+                    if !leader.leaf && leader.records > DELTA_BATCH_RECORDS {
+                        pending.push(child.to_string());
+                        continue;
+                    }
+                }
+                let leaf = mine.or(leader).is_some_and(|f| f.leaf);
+                let within = |key: &Key| match leaf {
+                    true => key.as_str() == child,
+                    false => key.as_str().starts_with(child)
+                };
+                let fetched: Vec<(Key, Value)> = source.records(child).into_iter().filter(|(key, _)| within(key)).collect();
+                for (key, _) in local.iter().filter(|(key, _)| within(key)) {
+                    if fetched.binary_search_by(|(f, _)| f.cmp(key)).is_err() {
+                        changes.push(Change::Delete(key.clone()));
+                        report.deleted += 1;
+                    }
+                }
+                report.transferred += fetched.len();
+                changes.extend(fetched.into_iter().map(|(key, value)| Change::Insert(key, value)));
+            }
+        }
+        database.apply_batch(changes).await?;
+        Ok((Self { applied: source.sequence() }, report))
+    }
+    /// The last change of the leader the follower holds.
+    pub fn applied(&self) -> u64 {
+        self.applied
+    }
+    /// Applies the next change of the stream, returning false if the follower
+    /// already held it.
+    pub async fn apply(&mut self, database: &Database, change: SequencedChange) -> Result<bool, NetworkError> {
+        if change.sequence <= self.applied {
+            return Ok(false);
+        }
+        if change.sequence != self.applied + 1 {
+            return Err(NetworkError::Storage(StorageError::ReplicationGap(self.applied + 1, change.sequence)));
+        }
+        match change.change {
+            Change::Insert(key, value) => {
+                database.insert(key, value, None).await?;
+            }
+            Change::Delete(key) => database.delete(key).await?
+        }
+        self.applied = change.sequence;
+        Ok(true)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, time::Duration};
+
+    use overseer::{error::{NetworkError, StorageError}, models::{Acknowledgement, Key, Value}};
+
+    use crate::database::{Change, Database};
+
+    use super::{digests, fingerprint, Replica, SequencedChange, DELTA_BATCH_RECORDS};
+
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_replica_joins_mid_write() {
+        let leader_dir = tempfile::tempdir().unwrap();
+        let leader = Rc::new(Database::new(leader_dir.path(), "leader.db").await.unwrap());
+
+        // Writers churn a handful of keys while the followers join.
+        let writers: Vec<_> = (0..4).map(|w| {
+            let leader = Rc::clone(&leader);
+            monoio::spawn(async move {
+                for i in 0..200i64 {
+                    let key = Key::from_str(&format!("key.{}", (i + w) % 7));
+                    if i % 5 == 0 {
+                        leader.delete(&key).await.unwrap();
+                    } else {
+                        leader.insert(&key, Value::Integer(i * 10 + w), None).await.unwrap();
+                    }
+                }
+            })
+        }).collect();
+
+        // Followers join at different points of the churn.
+        let mut followers = vec![];
+        for threshold in [50, 250, 450] {
+            while leader.version() < threshold {
+                monoio::time::sleep(Duration::from_millis(1)).await;
+            }
+            let dir = tempfile::tempdir().unwrap();
+            let follower = Database::new(dir.path(), "follower.db").await.unwrap();
+            follower.insert(Key::from_str("stale"), Value::Integer(0), None).await.unwrap();
+            let (snapshot, changes) = leader.replicate();
+            let replica = Replica::restore(&follower, snapshot).await.unwrap();
+            followers.push((dir, follower, replica, changes));
+        }
+        for writer in writers {
+            writer.await;
+        }
+
+        let values = |db: &Database| db.scan("").into_iter().map(|(k, v)| (k, (*v).clone())).collect::<Vec<_>>();
+        for (_dir, follower, mut replica, mut changes) in followers {
+            while let Ok(change) = changes.try_recv() {
+                // Every change is handed over twice, the second copy is skipped.
+                assert!(replica.apply(&follower, change.clone()).await.unwrap());
+                assert!(!replica.apply(&follower, change).await.unwrap());
+            }
+            assert_eq!(replica.applied(), leader.version());
+            assert_eq!(values(&follower), values(&leader));
+        }
+    }
+
+    #[monoio::test]
+    pub async fn test_delta_sync() {
+        let leader_dir = tempfile::tempdir().unwrap();
+        let leader = Database::new(leader_dir.path(), "leader.db").await.unwrap();
+        for service in 0..20 {
+            for field in 0..50 {
+                leader.insert(Key::from_str(&format!("svc.{service}.{field}")), Value::Integer(field), None).await.unwrap();
+            }
+        }
+        leader.insert(Key::from_str("mode"), Value::String("on".into()), None).await.unwrap();
+
+        // The follower went down and missed a few changes.
+        let dir = tempfile::tempdir().unwrap();
+        let follower = Database::new(dir.path(), "follower.db").await.unwrap();
+        let (snapshot, _) = leader.replicate();
+        Replica::restore(&follower, snapshot).await.unwrap();
+        leader.insert(Key::from_str("svc.3.7"), Value::Integer(-1), None).await.unwrap();
+        leader.delete(Key::from_str("svc.11.0")).await.unwrap();
+        leader.insert(Key::from_str("svc.20.0"), Value::Integer(0), None).await.unwrap();
+        follower.insert(Key::from_str("stale"), Value::Integer(0), None).await.unwrap();
+
+        let (source, mut changes) = leader.replicate_delta();
+        let (mut replica, report) = Replica::sync(&follower, &source).await.unwrap();
+        let values = |db: &Database| db.scan("").into_iter().map(|(k, v)| (k, (*v).clone())).collect::<Vec<_>>();
+        assert_eq!(values(&follower), values(&leader));
+        assert_eq!(replica.applied(), leader.version());
+
+        // Only the diverged prefixes were sent, not the thousand records.
+        assert!(report.transferred <= 2 * DELTA_BATCH_RECORDS, "{report:?}");
+        assert_eq!(report.deleted, 2);
+
+        // The stream continues right after the sync.
+        leader.insert(Key::from_str("mode"), Value::String("off".into()), None).await.unwrap();
+        assert!(replica.apply(&follower, changes.try_recv().unwrap()).await.unwrap());
+        assert_eq!(values(&follower), values(&leader));
+    }
+
+    #[test]
+    pub fn test_prefix_digests() {
+        let (a, b, c) = (Key::from_str("app.mode"), Key::from_str("app.db.host"), Key::from_str("app.db.port"));
+        let value = Value::Integer(1);
+        let found = digests([(&b, &value), (&c, &value), (&a, &value)], "app.");
+        assert_eq!(found.iter().map(|f| (f.prefix.as_str(), f.records, f.leaf)).collect::<Vec<_>>(), vec![("app.db.", 2, false), ("app.mode", 1, true)]);
+        assert_eq!(found[0].checksum, fingerprint(&b, &value).wrapping_add(fingerprint(&c, &value)));
+        assert_ne!(fingerprint(&a, &Value::Integer(1)), fingerprint(&a, &Value::String("1".into())));
+    }
+
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_replicated_acknowledgement() {
+        let leader_dir = tempfile::tempdir().unwrap();
+        let leader = Database::new(leader_dir.path(), "leader.db").await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let follower = Database::new(dir.path(), "follower.db").await.unwrap();
+        let (snapshot, _) = leader.replicate();
+        let mut replica = Replica::restore(&follower, snapshot).await.unwrap();
+        let (mut changes, ack) = leader.replication().follow_acknowledged();
+        // A follower that never acknowledges is not counted.
+        let _silent = leader.replication().follow();
+
+        let version = leader.insert_acknowledged(Key::from_str("a"), Value::Integer(1), Acknowledgement::Replicated(1), None).await.unwrap();
+        assert_eq!(leader.replication().replicated(version), 0);
+        assert!(monoio::time::timeout(Duration::from_millis(10), leader.wait_replicated(version, 1)).await.is_err());
+
+        let waiting = leader.wait_replicated(version, 1);
+        replica.apply(&follower, changes.try_recv().unwrap()).await.unwrap();
+        ack.acknowledge(replica.applied());
+        monoio::time::timeout(Duration::from_millis(100), waiting).await.unwrap();
+        assert_eq!(leader.replication().replicated(version), 1);
+    }
+
+    #[monoio::test]
+    pub async fn test_replica_refuses_gap() {
+        let tf = tempfile::tempdir().unwrap();
+        let follower = Database::new(tf.path(), "follower.db").await.unwrap();
+        let snapshot = super::ReplicationSnapshot { sequence: 4, records: vec![(Key::from_str("a"), Value::Integer(1))] };
+        let mut replica = Replica::restore(&follower, snapshot).await.unwrap();
+
+        let change = |sequence| SequencedChange { sequence, change: Change::Delete(Key::from_str("a")) };
+        assert!(matches!(replica.apply(&follower, change(6)).await, Err(NetworkError::Storage(StorageError::ReplicationGap(5, 6)))));
+        assert!(replica.apply(&follower, change(5)).await.unwrap());
+        assert!(follower.get(Key::from_str("a")).await.is_none());
+    }
+}
@@ -0,0 +1,196 @@
+use std::{cell::{Cell, RefCell}, cmp::Reverse, collections::HashMap};
+
+use overseer::models::Key;
+
+
+/// Decides which tier a key belongs in from its sampled access frequency.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PlacementPolicy {
+    /// One in this many accesses is sampled.
+    pub sample_rate: u64,
+    /// How many samples are taken before every count is halved, so
+    /// keys that stop being used cool down over time.
+    pub decay_interval: u64,
+    /// The estimated accesses at which a key is hot.
+    pub hot_threshold: u64,
+    /// The estimated accesses under which a key is cold.
+    pub cold_threshold: u64
+}
+
+impl Default for PlacementPolicy {
+    fn default() -> Self {
+        Self {
+            sample_rate: 16,
+            decay_interval: 4096,
+            hot_threshold: 1024,
+            cold_threshold: 16
+        }
+    }
+}
+
+/// Where a key should live.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Tier {
+    /// Accessed often, worth pinning in memory.
+    Hot,
+    Warm,
+    /// Rarely accessed, a candidate for compressed storage.
+    Cold
+}
+
+/// A point in time summary of the placement decisions.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct PlacementStats {
+    pub sampled: u64,
+    pub hot: usize,
+    pub warm: usize,
+    pub cold: usize
+}
+
+impl PlacementStats {
+    /// Adds the figures of another sampler, as for the shards of a namespace.
+    pub fn merge(&mut self, other: PlacementStats) {
+        self.sampled += other.sampled;
+        self.hot += other.hot;
+        self.warm += other.warm;
+        self.cold += other.cold;
+    }
+}
+
+/// Samples key accesses to estimate how hot every key is.
+///
+/// Only one in `sample_rate` accesses touches the map, the rest
+/// only bump a counter, which keeps the read path cheap.
+pub struct AccessSampler {
+    policy: PlacementPolicy,
+    accesses: Cell<u64>,
+    samples: Cell<u64>,
+    counts: RefCell<HashMap<Key, u64>>
+}
+
+impl AccessSampler {
+    pub fn new(policy: PlacementPolicy) -> Self {
+        assert!(policy.sample_rate > 0, "The sample rate must be positive.");
+        Self {
+            policy,
+            accesses: Cell::new(0),
+            samples: Cell::new(0),
+            counts: RefCell::default()
+        }
+    }
+    pub fn policy(&self) -> PlacementPolicy {
+        self.policy
+    }
+    /// Records an access to a key, sampling it if it is the key's turn.
+    pub fn record(&self, key: &Key) {
+        let accesses = self.accesses.get().wrapping_add(1);
+        self.accesses.set(accesses);
+        if !accesses.is_multiple_of(self.policy.sample_rate) {
+            return;
+        }
+        let mut counts = self.counts.borrow_mut();
+        *counts.entry(key.clone()).or_default() += self.policy.sample_rate;
+
+        let samples = self.samples.get() + 1;
+        self.samples.set(samples);
+        if self.policy.decay_interval != 0 && samples.is_multiple_of(self.policy.decay_interval) {
+            counts.retain(|_, count| {
+                *count /= 2;
+                *count > 0
+            });
+        }
+    }
+    /// Forgets a key, for instance once it is deleted.
+    pub fn forget(&self, key: &Key) {
+        self.counts.borrow_mut().remove(key);
+    }
+    /// The estimated number of accesses to a key.
+    pub fn estimate(&self, key: &Key) -> u64 {
+        self.counts.borrow().get(key).copied().unwrap_or_default()
+    }
+    pub fn tier(&self, key: &Key) -> Tier {
+        self.classify(self.estimate(key))
+    }
+    fn classify(&self, estimate: u64) -> Tier {
+        if estimate >= self.policy.hot_threshold {
+            Tier::Hot
+        } else if estimate < self.policy.cold_threshold {
+            Tier::Cold
+        } else {
+            Tier::Warm
+        }
+    }
+    /// The hottest keys, hottest first.
+    pub fn hottest(&self, limit: usize) -> Vec<(Key, u64)> {
+        let mut keys: Vec<_> = self.counts.borrow().iter().map(|(k, v)| (k.clone(), *v)).collect();
+        keys.sort_by_key(|f| Reverse(f.1));
+        keys.truncate(limit);
+        keys
+    }
+    /// Summarises the tiers of the sampled keys. Keys that were never
+    /// sampled are not counted, even though they are cold.
+    pub fn stats(&self) -> PlacementStats {
+        let mut stats = PlacementStats { sampled: self.samples.get(), ..Default::default() };
+        for count in self.counts.borrow().values() {
+            match self.classify(*count) {
+                Tier::Hot => stats.hot += 1,
+                Tier::Warm => stats.warm += 1,
+                Tier::Cold => stats.cold += 1
+            }
+        }
+        stats
+    }
+}
+
+impl Default for AccessSampler {
+    fn default() -> Self {
+        Self::new(PlacementPolicy::default())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::models::Key;
+
+    use super::{AccessSampler, PlacementPolicy, Tier};
+
+    #[test]
+    pub fn test_access_sampling() {
+        let sampler = AccessSampler::new(PlacementPolicy {
+            sample_rate: 2,
+            decay_interval: 0,
+            hot_threshold: 10,
+            cold_threshold: 4
+        });
+        let hot = Key::from_str("hot");
+        let cold = Key::from_str("cold");
+        for _ in 0..20 {
+            sampler.record(&hot);
+        }
+        sampler.record(&cold);
+        sampler.record(&cold);
+
+        assert_eq!(sampler.estimate(&hot), 20);
+        assert_eq!(sampler.tier(&hot), Tier::Hot);
+        assert_eq!(sampler.tier(&cold), Tier::Cold);
+        assert_eq!(sampler.hottest(1)[0].0, hot);
+
+        let stats = sampler.stats();
+        assert_eq!((stats.hot, stats.cold), (1, 1));
+    }
+
+    #[test]
+    pub fn test_access_decay() {
+        let sampler = AccessSampler::new(PlacementPolicy {
+            sample_rate: 1,
+            decay_interval: 4,
+            ..Default::default()
+        });
+        let key = Key::from_str("key");
+        for _ in 0..4 {
+            sampler.record(&key);
+        }
+        assert_eq!(sampler.estimate(&key), 2);
+    }
+}
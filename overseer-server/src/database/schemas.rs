@@ -0,0 +1,133 @@
+use std::{cell::RefCell, collections::BTreeMap, path::{Path, PathBuf}};
+
+use overseer::{error::{NetworkError, StorageError}, models::{Key, Schema, SchemaValidator, Value}};
+
+use super::{sync_directory, temporary};
+
+
+/// The schemas the values written under key prefixes must conform to,
+/// kept in a file beside the records of the database.
+///
+/// A key is checked against the schema of the longest prefix registered
+/// for it, the keys under no prefix take any value. The schemas are
+/// compiled once, as they are registered or opened.
+pub struct SchemaRegistry {
+    location: PathBuf,
+    schemas: RefCell<BTreeMap<String, SchemaValidator>>
+}
+
+impl SchemaRegistry {
+    /// Opens the schemas of the database with the name at the path.
+    pub async fn open<P, S>(path: P, name: S) -> Result<Self, NetworkError>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>
+    {
+        let location = path.as_ref().join(format!("{}.schemas", name.as_ref()));
+        let temporary = temporary(&location);
+        if temporary.exists() {
+            std::fs::remove_file(temporary)?;
+        }
+        let schemas: BTreeMap<String, Schema> = if location.exists() {
+            bincode::deserialize(&monoio::fs::read(&location).await?)
+                .map_err(|e| StorageError::Corrupt(format!("{}: {e}", location.display())))?
+        } else {
+            BTreeMap::new()
+        };
+        let schemas = schemas.into_iter()
+            .map(|(prefix, schema)| Ok((prefix, schema.compile()?)))
+            .collect::<Result<_, NetworkError>>()?;
+        Ok(Self { location, schemas: RefCell::new(schemas) })
+    }
+    /// The schema of the longest prefix of the key that has one.
+    pub fn schema_of(&self, key: &Key) -> Option<Schema> {
+        validator_of(&self.schemas.borrow(), key).map(|f| f.schema().clone())
+    }
+    /// Checks the value written under the key against its schema.
+    pub fn validate(&self, key: &Key, value: &Value) -> Result<(), NetworkError> {
+        let schemas = self.schemas.borrow();
+        if schemas.is_empty() {
+            return Ok(());
+        }
+        match validator_of(&schemas, key) {
+            Some(validator) => validator.validate(value).map_err(|e| match e {
+                NetworkError::ValidationFailed(reason) => NetworkError::ValidationFailed(format!("{}: {reason}", key.as_str())),
+                e => e
+            }),
+            None => Ok(())
+        }
+    }
+    /// Registers the schema of the prefix, or removes it, returning the one
+    /// it had. The schemas are on disk once this returns, the values
+    /// already held are not checked.
+    pub async fn register(&self, prefix: &str, schema: Option<Schema>) -> Result<Option<Schema>, NetworkError> {
+        let validator = schema.as_ref().map(Schema::compile).transpose()?;
+        let mut schemas = self.schemas.borrow().clone();
+        let previous = match validator {
+            Some(validator) => schemas.insert(prefix.to_string(), validator),
+            None => schemas.remove(prefix)
+        };
+        let stored: BTreeMap<&String, &Schema> = schemas.iter().map(|(prefix, validator)| (prefix, validator.schema())).collect();
+        let temporary = temporary(&self.location);
+        monoio::fs::write(&temporary, bincode::serialize(&stored).unwrap()).await.0?;
+        monoio::fs::File::open(&temporary).await?.sync_all().await?;
+        std::fs::rename(&temporary, &self.location)?;
+        sync_directory(&self.location)?;
+        *self.schemas.borrow_mut() = schemas;
+        Ok(previous.map(|f| f.schema().clone()))
+    }
+    /// The registered schemas by their prefix.
+    pub fn schemas(&self) -> Vec<(String, Schema)> {
+        self.schemas.borrow().iter().map(|(prefix, validator)| (prefix.clone(), validator.schema().clone())).collect()
+    }
+}
+
+/// The schema of the longest prefix of the key that has one.
+fn validator_of<'s>(schemas: &'s BTreeMap<String, SchemaValidator>, key: &Key) -> Option<&'s SchemaValidator> {
+    schemas.iter()
+        .filter(|(prefix, _)| key.as_str().starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, validator)| validator)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::{error::{NetworkError, StorageError}, models::{Key, Schema, Value}};
+
+    use super::SchemaRegistry;
+
+    #[monoio::test]
+    pub async fn test_schema_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = SchemaRegistry::open(dir.path(), "db").await.unwrap();
+        assert!(registry.register("app.", Some(Schema::integer(Some(0), None))).await.unwrap().is_none());
+        registry.register("app.name", Some(Schema::string("^[a-z]+$"))).await.unwrap();
+        assert!(registry.register("app.bad", Some(Schema::string("("))).await.is_err());
+
+        let (port, name) = (Key::from_str("app.port"), Key::from_str("app.name"));
+        assert!(registry.validate(&port, &Value::Integer(80)).is_ok());
+        assert!(matches!(registry.validate(&port, &Value::Integer(-1)), Err(NetworkError::ValidationFailed(..))));
+        // The longest prefix wins.
+        assert!(registry.validate(&name, &Value::String("jim".into())).is_ok());
+        assert!(registry.validate(&Key::from_str("other"), &Value::Integer(-1)).is_ok());
+
+        let reopened = SchemaRegistry::open(dir.path(), "db").await.unwrap();
+        assert_eq!(reopened.schemas(), registry.schemas());
+        assert_eq!(reopened.register("app.", None).await.unwrap(), Some(Schema::integer(Some(0), None)));
+        assert!(reopened.validate(&port, &Value::Integer(-1)).is_ok());
+    }
+
+    #[monoio::test]
+    pub async fn test_truncated_schemas() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = SchemaRegistry::open(dir.path(), "db").await.unwrap();
+        registry.register("app.", Some(Schema::integer(Some(0), None))).await.unwrap();
+        let location = dir.path().join("db.schemas");
+        let contents = std::fs::read(&location).unwrap();
+        std::fs::write(&location, &contents[..contents.len() - 3]).unwrap();
+
+        let opened = SchemaRegistry::open(dir.path(), "db").await;
+        assert!(matches!(opened, Err(NetworkError::Storage(StorageError::Corrupt(..)))));
+    }
+}
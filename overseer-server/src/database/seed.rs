@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use overseer::{error::{NetworkError, StorageError}, models::{parse_seed_file, Key, Value, ValueType}};
+
+
+/// Where a seeded value comes from.
+#[derive(Clone, Debug)]
+pub enum SeedSource {
+    /// Reads a single key from an environment variable.
+    Env {
+        key: Key,
+        variable: String,
+        value_type: ValueType
+    },
+    /// Reads `key = value` lines from a file.
+    File(PathBuf)
+}
+
+/// The keys written into a fresh database on first startup.
+///
+/// Seeding only happens when the database is empty, so restarting
+/// a server never overwrites what has been changed since.
+#[derive(Default, Clone, Debug)]
+pub struct Seed {
+    sources: Vec<SeedSource>
+}
+
+impl Seed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Seeds a key from an environment variable, unset variables are skipped.
+    pub fn from_env<K, S>(mut self, key: K, variable: S, value_type: ValueType) -> Self
+    where 
+        K: Into<Key>,
+        S: Into<String>
+    {
+        self.sources.push(SeedSource::Env { key: key.into(), variable: variable.into(), value_type });
+        self
+    }
+    /// Seeds every key listed in a file.
+    pub fn from_file<P>(mut self, path: P) -> Self
+    where 
+        P: Into<PathBuf>
+    {
+        self.sources.push(SeedSource::File(path.into()));
+        self
+    }
+    /// Collects the records from every source, later sources win.
+    pub async fn resolve(&self) -> Result<Vec<(Key, Value)>, NetworkError> {
+        let mut records = vec![];
+        for source in &self.sources {
+            match source {
+                SeedSource::Env { key, variable, value_type } => {
+                    if let Ok(raw) = std::env::var(variable) {
+                        records.push((key.clone(), parse_typed(&raw, *value_type)?));
+                    }
+                }
+                SeedSource::File(path) => {
+                    let bytes = monoio::fs::read(path).await?;
+                    let text = std::str::from_utf8(&bytes)?;
+                    records.extend(parse_seed_file(text)?);
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+fn parse_typed(raw: &str, value_type: ValueType) -> Result<Value, NetworkError> {
+    match value_type {
+        ValueType::String => Ok(Value::String(raw.into())),
+        ValueType::Integer => raw
+            .trim()
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| NetworkError::Storage(StorageError::InvalidSeed(format!("{raw:?} is not an integer")))),
+        ValueType::Map => Err(NetworkError::Storage(StorageError::InvalidSeed(format!("{raw:?} cannot be read as a map")))),
+        ValueType::Json => Err(NetworkError::Storage(StorageError::InvalidSeed(format!("{raw:?} cannot be read as json"))))
+    }
+}
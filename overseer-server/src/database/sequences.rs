@@ -0,0 +1,136 @@
+use std::{cell::RefCell, collections::{BTreeMap, HashMap}, path::{Path, PathBuf}};
+
+use overseer::error::{NetworkError, StorageError};
+use tokio::sync::Mutex;
+
+use super::{sync_directory, temporary};
+
+
+/// How many numbers of a sequence are reserved on disk at a time.
+pub const SEQUENCE_BLOCK: u64 = 64;
+
+/// Named counters handing out unique increasing numbers, kept in a file
+/// beside the records of the database.
+///
+/// The numbers are reserved a block at a time, and the file has a block
+/// before any number of it is handed out. A crash skips the rest of the
+/// block, so a sequence can have gaps but never repeats a number.
+pub struct SequenceRegistry {
+    location: PathBuf,
+    /// The end of the block reserved for each sequence.
+    reserved: RefCell<BTreeMap<String, u64>>,
+    /// The last number handed out of each sequence since it was opened.
+    issued: RefCell<HashMap<String, u64>>,
+    /// Held while a block is reserved, so two reservations do not write
+    /// the file at once.
+    reserving: Mutex<()>
+}
+
+impl SequenceRegistry {
+    /// Opens the sequences of the database with the name at the path.
+    pub async fn open<P, S>(path: P, name: S) -> Result<Self, NetworkError>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>
+    {
+        let location = path.as_ref().join(format!("{}.sequences", name.as_ref()));
+        let temporary = temporary(&location);
+        if temporary.exists() {
+            std::fs::remove_file(temporary)?;
+        }
+        let reserved: BTreeMap<String, u64> = if location.exists() {
+            bincode::deserialize(&monoio::fs::read(&location).await?)
+                .map_err(|e| StorageError::Corrupt(format!("{}: {e}", location.display())))?
+        } else {
+            BTreeMap::new()
+        };
+        // The numbers up to the end of each block on disk may have been
+        // handed out before a crash or a restart.
+        let issued = reserved.iter().map(|(name, end)| (name.clone(), *end)).collect();
+        Ok(Self { location, reserved: RefCell::new(reserved), issued: RefCell::new(issued), reserving: Mutex::new(()) })
+    }
+    /// The next number of the sequence, the first being one.
+    pub async fn next(&self, name: &str) -> Result<u64, NetworkError> {
+        loop {
+            if let Some(next) = self.take(name) {
+                return Ok(next);
+            }
+            let _reserving = self.reserving.lock().await;
+            if self.has_left(name) {
+                continue;
+            }
+            let mut reserved = self.reserved.borrow().clone();
+            let end = reserved.entry(name.to_string()).or_default();
+            *end += SEQUENCE_BLOCK;
+            self.write(&reserved).await?;
+            *self.reserved.borrow_mut() = reserved;
+        }
+    }
+    /// The last number reserved on disk for each sequence, in order.
+    pub fn sequences(&self) -> Vec<(String, u64)> {
+        self.reserved.borrow().iter().map(|(name, end)| (name.clone(), *end)).collect()
+    }
+    /// Hands out the next number if the block reserved has one left.
+    fn take(&self, name: &str) -> Option<u64> {
+        if !self.has_left(name) {
+            return None;
+        }
+        let mut issued = self.issued.borrow_mut();
+        let last = issued.entry(name.to_string()).or_default();
+        *last += 1;
+        Some(*last)
+    }
+    /// Whether the block reserved for the sequence has numbers left, which
+    /// another caller may have reserved while this one waited to.
+    fn has_left(&self, name: &str) -> bool {
+        let end = self.reserved.borrow().get(name).copied().unwrap_or_default();
+        self.issued.borrow().get(name).copied().unwrap_or_default() < end
+    }
+    async fn write(&self, reserved: &BTreeMap<String, u64>) -> Result<(), NetworkError> {
+        let temporary = temporary(&self.location);
+        monoio::fs::write(&temporary, bincode::serialize(reserved).unwrap()).await.0?;
+        monoio::fs::File::open(&temporary).await?.sync_all().await?;
+        std::fs::rename(&temporary, &self.location)?;
+        sync_directory(&self.location)?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::error::{NetworkError, StorageError};
+
+    use super::{SequenceRegistry, SEQUENCE_BLOCK};
+
+    #[monoio::test]
+    pub async fn test_sequences() {
+        let dir = tempfile::tempdir().unwrap();
+        let sequences = SequenceRegistry::open(dir.path(), "db").await.unwrap();
+        let mut issued = vec![];
+        for _ in 0..SEQUENCE_BLOCK + 2 {
+            issued.push(sequences.next("jobs").await.unwrap());
+        }
+        assert_eq!(issued, (1..=SEQUENCE_BLOCK + 2).collect::<Vec<_>>());
+        assert_eq!(sequences.next("other").await.unwrap(), 1);
+        assert_eq!(sequences.sequences(), vec![("jobs".to_string(), 2 * SEQUENCE_BLOCK), ("other".to_string(), SEQUENCE_BLOCK)]);
+
+        // Reopened, the sequence goes on after every number it may have
+        // handed out.
+        let reopened = SequenceRegistry::open(dir.path(), "db").await.unwrap();
+        assert_eq!(reopened.next("jobs").await.unwrap(), 2 * SEQUENCE_BLOCK + 1);
+        assert_eq!(reopened.next("other").await.unwrap(), SEQUENCE_BLOCK + 1);
+    }
+
+    #[monoio::test]
+    pub async fn test_truncated_sequences() {
+        let dir = tempfile::tempdir().unwrap();
+        SequenceRegistry::open(dir.path(), "db").await.unwrap().next("jobs").await.unwrap();
+        let location = dir.path().join("db.sequences");
+        let contents = std::fs::read(&location).unwrap();
+        std::fs::write(&location, &contents[..contents.len() - 3]).unwrap();
+
+        let opened = SequenceRegistry::open(dir.path(), "db").await;
+        assert!(matches!(opened, Err(NetworkError::Storage(StorageError::Corrupt(..)))));
+    }
+}
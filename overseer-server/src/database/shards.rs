@@ -0,0 +1,1452 @@
+use std::{cell::{Cell, RefCell}, cmp::Reverse, collections::{hash_map::DefaultHasher, BTreeMap}, hash::{Hash, Hasher}, path::{Path, PathBuf}, rc::Rc, sync::Arc, thread::JoinHandle, time::Duration};
+
+use overseer::{access::{WatcherActivity, WatcherBehaviour}, error::{NetworkError, StorageError}, models::{Acknowledgement, CompactionRun, DatabaseStats, DeleteCondition, ExportedRecord, HistoryEntry, Key, KeyChild, KeyDiff, KeyDrift, KeyMatcher, LeasedItem, RecordMeta, Revision, Schema, Value, WatchedKey}};
+use tokio::sync::{mpsc::{self, UnboundedReceiver, UnboundedSender}, oneshot};
+
+use crate::net::ClientId;
+
+use super::{aggregate_split, current_trace, locked_key, queue_of, split_keys, sub_key, Database, DurabilityPolicy, HistoryRetention, Manifest, MigrationReport, PlacementPolicy, PlacementStats, Quota, Seed, SplitPolicy, StorageSnapshot, SystemStats, WatcherLimit, MAX_KEY_PAGE, traced};
+
+
+/// The record taken off a key, with its metadata.
+type TakenRecord = Option<(Value, Option<RecordMeta>)>;
+
+/// The channel the requests of a shard are sent down, with their traces.
+type ShardSender = UnboundedSender<(ShardRequest, Option<u64>)>;
+
+/// A batch of changes to a watched key, in the order they were made.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ShardChanges {
+    pub values: Vec<Option<Value>>,
+    /// The traces of the requests that made the changes, one per value.
+    pub traces: Vec<Option<u64>>,
+    /// How many changes were lost to a full queue before these.
+    pub missed: u64,
+    /// Whether the overflow killed the watcher, no batch follows this one.
+    pub closed: bool
+}
+
+impl ShardChanges {
+    /// Folds a later batch into this one, an eager watcher keeping only the
+    /// latest value.
+    pub fn absorb(&mut self, later: ShardChanges, behaviour: WatcherBehaviour) {
+        match behaviour {
+            WatcherBehaviour::Eager if !later.values.is_empty() => {
+                self.values = later.values;
+                self.traces = later.traces;
+            }
+            WatcherBehaviour::Eager => {}
+            WatcherBehaviour::Ordered => {
+                self.values.extend(later.values);
+                self.traces.extend(later.traces);
+            }
+        }
+        self.missed += later.missed;
+        self.closed |= later.closed;
+    }
+}
+
+/// A page of keys and the cursor continuing after it.
+type KeyPage = (Vec<Key>, Option<Key>);
+
+/// A request handed to the thread that owns a shard.
+enum ShardRequest {
+    Get {
+        key: Key,
+        reply: oneshot::Sender<Option<(Value, u64, Option<RecordMeta>)>>
+    },
+    Insert {
+        key: Key,
+        value: Value,
+        writer: Option<ClientId>,
+        reply: oneshot::Sender<Result<u64, NetworkError>>
+    },
+    InsertAcknowledged {
+        key: Key,
+        value: Value,
+        acknowledgement: Acknowledgement,
+        writer: Option<ClientId>,
+        reply: oneshot::Sender<Result<u64, NetworkError>>
+    },
+    InsertIfVersion {
+        key: Key,
+        value: Value,
+        version: u64,
+        writer: Option<ClientId>,
+        reply: oneshot::Sender<Result<u64, NetworkError>>
+    },
+    /// Reads the keys and holds off every other request of the shard
+    /// until released, see [ShardPool::snapshot_get].
+    SnapshotGet {
+        keys: Vec<Key>,
+        reply: oneshot::Sender<Vec<(Option<Value>, u64)>>,
+        release: oneshot::Receiver<()>
+    },
+    Rename {
+        from: Key,
+        to: Key,
+        overwrite: bool,
+        reply: oneshot::Sender<Result<Option<u64>, NetworkError>>
+    },
+    Take {
+        key: Key,
+        reply: oneshot::Sender<Result<TakenRecord, NetworkError>>
+    },
+    Place {
+        key: Key,
+        value: Value,
+        meta: Option<RecordMeta>,
+        overwrite: bool,
+        writer: Option<ClientId>,
+        reply: oneshot::Sender<Result<u64, NetworkError>>
+    },
+    /// Serves nothing but the requests sent on its own channel until that
+    /// is dropped, see [ShardPool::rename].
+    Hold {
+        held: oneshot::Sender<()>,
+        requests: UnboundedReceiver<(ShardRequest, Option<u64>)>
+    },
+    BulkLoad {
+        records: Vec<(Key, Value)>,
+        writer: Option<ClientId>,
+        reply: oneshot::Sender<Result<usize, NetworkError>>
+    },
+    /// Checks the writes and holds off every other request of the shard
+    /// until told to make them, and then until released, see
+    /// [ShardPool::insert_all].
+    InsertAll {
+        records: Vec<(Key, Value)>,
+        writer: Option<ClientId>,
+        checked: oneshot::Sender<Result<(), NetworkError>>,
+        commit: oneshot::Receiver<()>,
+        reply: oneshot::Sender<Result<Vec<u64>, NetworkError>>,
+        release: oneshot::Receiver<()>
+    },
+    GetOrInsert {
+        key: Key,
+        default: Value,
+        writer: Option<ClientId>,
+        reply: oneshot::Sender<Result<(Value, u64), NetworkError>>
+    },
+    Increment {
+        key: Key,
+        delta: i64,
+        writer: Option<ClientId>,
+        reply: oneshot::Sender<Result<(Value, u64), NetworkError>>
+    },
+    Delete {
+        key: Key,
+        reply: oneshot::Sender<Result<(), NetworkError>>
+    },
+    DeleteWrittenBy {
+        key: Key,
+        writer: ClientId,
+        reply: oneshot::Sender<Result<bool, NetworkError>>
+    },
+    DeleteIf {
+        key: Key,
+        condition: DeleteCondition,
+        reply: oneshot::Sender<Result<bool, NetworkError>>
+    },
+    History {
+        key: Key,
+        limit: usize,
+        reply: oneshot::Sender<Vec<HistoryEntry>>
+    },
+    ValueAt {
+        key: Key,
+        revision: Revision,
+        reply: oneshot::Sender<Option<Option<Value>>>
+    },
+    KeyDiff {
+        key: Key,
+        from: Revision,
+        to: Revision,
+        reply: oneshot::Sender<Option<KeyDiff>>
+    },
+    Enqueue {
+        queue: String,
+        value: Value,
+        reply: oneshot::Sender<Result<(Key, u64), NetworkError>>
+    },
+    Dequeue {
+        queue: String,
+        visibility: Duration,
+        reply: oneshot::Sender<Option<LeasedItem>>
+    },
+    /// Acknowledges the item if `ack` is set, gives up the lease otherwise.
+    Settle {
+        item: Key,
+        lease: u64,
+        ack: bool,
+        reply: oneshot::Sender<Result<bool, NetworkError>>
+    },
+    Keys {
+        cursor: Option<Key>,
+        limit: usize,
+        reply: oneshot::Sender<(Vec<Key>, Option<Key>)>
+    },
+    FindKeys {
+        matcher: KeyMatcher,
+        cursor: Option<Key>,
+        limit: usize,
+        reply: oneshot::Sender<Result<KeyPage, NetworkError>>
+    },
+    Export {
+        cursor: Option<Key>,
+        limit: usize,
+        reply: oneshot::Sender<(Vec<ExportedRecord>, Option<Key>)>
+    },
+    Children {
+        key: Key,
+        cursor: Option<Key>,
+        limit: usize,
+        reply: oneshot::Sender<(Vec<KeyChild>, Option<Key>)>
+    },
+    WatchedKeys {
+        key: Option<Key>,
+        reply: oneshot::Sender<Vec<WatchedKey>>
+    },
+    QueryIndex {
+        field: String,
+        value: Value,
+        reply: oneshot::Sender<Option<Vec<Key>>>
+    },
+    /// Subscribes to a key, the changes are sent over the channel until it
+    /// is released. The reply holds the value and version at subscription.
+    Watch {
+        key: Key,
+        client: ClientId,
+        behaviour: WatcherBehaviour,
+        activity: WatcherActivity,
+        limit: WatcherLimit,
+        changes: UnboundedSender<ShardChanges>,
+        reply: oneshot::Sender<(Option<Value>, u64)>
+    },
+    Release {
+        key: Key,
+        client: ClientId,
+        reply: oneshot::Sender<Result<(), NetworkError>>
+    },
+    Archive {
+        before: u64,
+        delete: bool,
+        reply: oneshot::Sender<Result<Vec<(Key, Value)>, NetworkError>>
+    },
+    Acquire {
+        key: Key,
+        client: ClientId,
+        ttl: Duration,
+        reply: oneshot::Sender<Option<u64>>
+    },
+    ReleaseLock {
+        key: Key,
+        client: ClientId,
+        reply: oneshot::Sender<bool>
+    },
+    /// Releases the locks of the client if it is set, the lapsed locks otherwise.
+    ReleaseLocks {
+        client: Option<ClientId>,
+        reply: oneshot::Sender<usize>
+    },
+    /// Answers the system keys, see [Database::serve_system].
+    ServeSystem {
+        stats: Arc<SystemStats>
+    },
+    RefreshSystem {
+        reply: oneshot::Sender<()>
+    },
+    SetQuota {
+        quota: Quota
+    },
+    RegisterSchema {
+        prefix: String,
+        schema: Option<Schema>,
+        reply: oneshot::Sender<Result<Option<Schema>, NetworkError>>
+    },
+    ListSchemas {
+        reply: oneshot::Sender<Vec<(String, Schema)>>
+    },
+    Stats {
+        reply: oneshot::Sender<DatabaseStats>
+    },
+    StorageStats {
+        reply: oneshot::Sender<StorageSnapshot>
+    },
+    Placement {
+        reply: oneshot::Sender<PlacementStats>
+    },
+    Hottest {
+        limit: usize,
+        reply: oneshot::Sender<Vec<(Key, u64)>>
+    },
+    Compactions {
+        reply: oneshot::Sender<Vec<CompactionRun>>
+    },
+    /// Moves the shard to the store of that name, serving the other
+    /// requests while it copies, see [Database::migrate].
+    Migrate {
+        path: PathBuf,
+        name: String,
+        reply: oneshot::Sender<Result<MigrationReport, NetworkError>>
+    },
+    NextSequence {
+        name: String,
+        reply: oneshot::Sender<Result<u64, NetworkError>>
+    },
+    /// Makes the writes that were not synced right away durable.
+    Sync {
+        reply: oneshot::Sender<Result<bool, NetworkError>>
+    },
+    /// Kills the watchers and flushes the shard, which then stops.
+    Shutdown {
+        reply: oneshot::Sender<Result<(), NetworkError>>
+    }
+}
+
+/// The key space of the default namespace split across worker threads.
+///
+/// Every shard is a [Database] of its own, living on a thread with its own
+/// runtime so the shards use as many cores as there are of them. A key always
+/// lives on the shard its hash picks, so the writes to a key are still ordered
+/// by that shard, see [super::WriteOrder]. Listings ask every shard and merge
+/// the pages.
+///
+/// A work queue lives whole on the shard its name picks, so its items are
+/// not on the shards their own keys pick and are only reached through the
+/// queue.
+///
+/// The shards are stored next to the database, `db` keeps its shards in
+/// `db.shard.0`, `db.shard.1` and so on. Changing the number of shards does
+/// not move the keys between them.
+///
+/// The sub-keys of a split counter live on the shards they pick, so its
+/// increments are spread across the threads, see [SplitPolicy].
+pub struct ShardPool {
+    /// The requests for each shard along with the trace they are made in.
+    shards: Vec<ShardSender>,
+    threads: RefCell<Vec<JoinHandle<()>>>,
+    split: SplitPolicy,
+    next_split: Cell<usize>
+}
+
+/// How the database of every shard is set up.
+#[derive(Clone, Debug, Default)]
+pub struct ShardSettings {
+    pub placement: PlacementPolicy,
+    pub durability: DurabilityPolicy,
+    pub history: HistoryRetention,
+    /// The fields every shard keeps an index of.
+    pub indexes: Vec<String>
+}
+
+impl ShardPool {
+    /// Starts a thread per shard and opens its database.
+    ///
+    /// # Panics
+    /// If there are no shards.
+    pub async fn open<P, S>(path: P, name: S, count: usize, settings: ShardSettings) -> Result<Self, NetworkError>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>
+    {
+        assert!(count > 0, "There has to be at least one shard.");
+        let mut pool = Self { shards: vec![], threads: RefCell::default(), split: SplitPolicy::default(), next_split: Cell::new(0) };
+        for index in 0..count {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let (ready, opened) = oneshot::channel();
+            let path = path.as_ref().to_path_buf();
+            let name = format!("{}.shard.{index}", name.as_ref());
+            let settings = settings.clone();
+            let thread = std::thread::Builder::new()
+                .name(format!("overseer-shard-{index}"))
+                .spawn(move || {
+                    let served = crate::runtime::block_on(|| serve_shard(path, name, settings, receiver, ready));
+                    if let Err(e) = served {
+                        tracing::error!("Could not start the runtime of shard {index}: {e}");
+                    }
+                })?;
+            pool.shards.push(sender);
+            pool.threads.get_mut().push(thread);
+            opened.await.map_err(|_| NetworkError::Storage(StorageError::ShardStopped))??;
+        }
+        Ok(pool)
+    }
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+    /// Replaces the prefixes whose counters are split across sub-keys.
+    pub fn set_split_policy(&mut self, policy: SplitPolicy) {
+        self.split = policy;
+    }
+    /// The shard the key lives on. The transitions of a lock are published
+    /// on the shard of the key locked, see [super::lock_key].
+    pub fn shard_of(&self, key: &Key) -> usize {
+        let locked = locked_key(key);
+        let mut hasher = DefaultHasher::new();
+        locked.as_ref().unwrap_or(key).hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+    /// Sends a request to a shard and waits for the reply.
+    async fn request<T>(&self, shard: usize, request: impl FnOnce(oneshot::Sender<T>) -> ShardRequest) -> Result<T, NetworkError> {
+        ask(&self.shards[shard], request).await
+    }
+    /// Holds a shard, it serves nothing but the requests sent on the
+    /// channel until the channel is dropped.
+    async fn hold(&self, shard: usize) -> Result<ShardSender, NetworkError> {
+        let (sender, requests) = mpsc::unbounded_channel();
+        self.request(shard, |held| ShardRequest::Hold { held, requests }).await?;
+        Ok(sender)
+    }
+    pub async fn get(&self, key: &Key) -> Result<Option<Value>, NetworkError> {
+        Ok(self.get_versioned(key).await?.map(|(value, _)| value))
+    }
+    /// The value of the key and the version of its record, see [Database::get_versioned].
+    ///
+    /// Every shard counts its own versions.
+    pub async fn get_versioned(&self, key: &Key) -> Result<Option<(Value, u64)>, NetworkError> {
+        Ok(self.get_with_meta(key).await?.map(|(value, version, _)| (value, version)))
+    }
+    /// The value of the key with the version and metadata of its record, see [Database::get_with_meta].
+    pub async fn get_with_meta(&self, key: &Key) -> Result<Option<(Value, u64, Option<RecordMeta>)>, NetworkError> {
+        if let Some(ways) = self.split.ways_of(key) {
+            return Ok(self.get_split(key, ways).await?.map(|(value, version)| (value, version, None)));
+        }
+        self.request(self.shard_of(key), |reply| ShardRequest::Get { key: key.clone(), reply }).await
+    }
+    /// The value of a split key added up from the shards of its sub-keys.
+    async fn get_split(&self, key: &Key, ways: usize) -> Result<Option<(Value, u64)>, NetworkError> {
+        let mut records = vec![];
+        for key in split_keys(key, ways) {
+            let record = self.request(self.shard_of(&key), |reply| ShardRequest::Get { key: key.clone(), reply }).await?;
+            records.push(record.map(|(value, version, _)| (value, version)));
+        }
+        Ok(aggregate_split(records))
+    }
+    /// Adds the delta to the integer of the key, see [Database::increment].
+    pub async fn increment(&self, key: &Key, delta: i64, writer: Option<ClientId>) -> Result<(Value, u64), NetworkError> {
+        let Some(ways) = self.split.ways_of(key) else {
+            return self.request(self.shard_of(key), |reply| ShardRequest::Increment { key: key.clone(), delta, writer, reply }).await?;
+        };
+        let base = self.request(self.shard_of(key), |reply| ShardRequest::Get { key: key.clone(), reply }).await?;
+        if let Some((base, version, _)) = base.filter(|(f, ..)| !matches!(f, Value::Integer(..))) {
+            return Ok((base, version));
+        }
+        let next = self.next_split.get();
+        self.next_split.set(next.wrapping_add(1));
+        let target = sub_key(key, next % ways);
+        let (sum, version) = self.request(self.shard_of(&target), |reply| ShardRequest::Increment { key: target.clone(), delta, writer, reply }).await??;
+        let total = self.get_split(key, ways).await?.map(|(value, _)| value);
+        Ok((total.unwrap_or(sum), version))
+    }
+    /// Deletes the sub-keys of a split key from their shards.
+    async fn clear_split(&self, key: &Key) -> Result<(), NetworkError> {
+        let Some(ways) = self.split.ways_of(key) else {
+            return Ok(());
+        };
+        for key in split_keys(key, ways).skip(1) {
+            self.request(self.shard_of(&key), |reply| ShardRequest::Delete { key: key.clone(), reply }).await??;
+        }
+        Ok(())
+    }
+    pub async fn insert(&self, key: &Key, value: Value, writer: Option<ClientId>) -> Result<u64, NetworkError> {
+        let version = self.request(self.shard_of(key), |reply| ShardRequest::Insert { key: key.clone(), value, writer, reply }).await??;
+        self.clear_split(key).await?;
+        Ok(version)
+    }
+    /// Inserts the value as durable as the level asks for, see [Database::insert_acknowledged].
+    ///
+    /// The shards have no followers, a level waiting on them is never reached.
+    pub async fn insert_acknowledged(&self, key: &Key, value: Value, acknowledgement: Acknowledgement, writer: Option<ClientId>) -> Result<u64, NetworkError> {
+        self.request(self.shard_of(key), |reply| ShardRequest::InsertAcknowledged { key: key.clone(), value, acknowledgement, writer, reply }).await?
+    }
+    /// Inserts the value if the key is still at the version, see [Database::insert_if_version].
+    pub async fn insert_if_version(&self, key: &Key, value: Value, version: u64, writer: Option<ClientId>) -> Result<u64, NetworkError> {
+        self.request(self.shard_of(key), |reply| ShardRequest::InsertIfVersion { key: key.clone(), value, version, writer, reply }).await?
+    }
+    /// Gets the values of the keys at a single point in time, see
+    /// [Database::snapshot_get].
+    ///
+    /// Every shard holding one of the keys reads its own and then serves
+    /// nothing else until all of them read theirs. They are asked in order
+    /// one after the other, so two snapshots never wait on each other.
+    pub async fn snapshot_get(&self, keys: &[Key]) -> Result<Vec<(Option<Value>, u64)>, NetworkError> {
+        let mut shards: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (i, key) in keys.iter().enumerate() {
+            shards.entry(self.shard_of(key)).or_default().push(i);
+        }
+        let mut values = vec![(None, 0); keys.len()];
+        let mut held = vec![];
+        for (shard, indices) in shards {
+            let (release, released) = oneshot::channel();
+            let keys = indices.iter().map(|i| keys[*i].clone()).collect();
+            let read = self.request(shard, |reply| ShardRequest::SnapshotGet { keys, reply, release: released }).await?;
+            for (i, value) in indices.into_iter().zip(read) {
+                values[i] = value;
+            }
+            held.push(release);
+        }
+        // Dropping the senders releases the shards.
+        drop(held);
+        Ok(values)
+    }
+    /// Moves the record of a key to another, see [Database::rename].
+    ///
+    /// Keys on different shards are moved by taking the record from one and
+    /// writing it to the other. Both shards are held while it moves, in
+    /// order like [ShardPool::snapshot_get] asks them, so no other request
+    /// finds the record under neither key or both. The record gets a
+    /// version of the target shard, and goes back if it cannot be written.
+    pub async fn rename(&self, from: &Key, to: &Key, overwrite: bool, writer: Option<ClientId>) -> Result<Option<u64>, NetworkError> {
+        let (source, target) = (self.shard_of(from), self.shard_of(to));
+        if source == target {
+            return self.request(source, |reply| ShardRequest::Rename { from: from.clone(), to: to.clone(), overwrite, reply }).await?;
+        }
+        let (first, second) = (self.hold(source.min(target)).await?, self.hold(source.max(target)).await?);
+        let (source, target) = if source < target { (&first, &second) } else { (&second, &first) };
+        if !overwrite {
+            if let Some((_, version, _)) = ask(target, |reply| ShardRequest::Get { key: to.clone(), reply }).await? {
+                return Err(NetworkError::VersionConflict(version));
+            }
+        }
+        let Some((value, meta)) = ask(source, |reply| ShardRequest::Take { key: from.clone(), reply }).await?? else {
+            return Ok(None);
+        };
+        let placed = ask(target, |reply| ShardRequest::Place { key: to.clone(), value: value.clone(), meta, overwrite: true, writer, reply }).await?;
+        if let Err(e) = placed {
+            ask(source, |reply| ShardRequest::Place { key: from.clone(), value, meta, overwrite: true, writer, reply }).await??;
+            return Err(e);
+        }
+        // Dropping the channels releases the shards.
+        placed.map(Some)
+    }
+    /// Inserts the values under their keys all at once, see [Database::insert_all].
+    ///
+    /// Every shard holding one of the keys checks its writes and then
+    /// serves nothing else until all of them did, so none makes its writes
+    /// unless all of them can. The shards are asked in order like they are
+    /// by [ShardPool::snapshot_get], and released once every one of them
+    /// made its writes. Every shard counts its own versions.
+    pub async fn insert_all(&self, records: Vec<(Key, Value)>, writer: Option<ClientId>) -> Result<Vec<u64>, NetworkError> {
+        let stopped = || NetworkError::Storage(StorageError::ShardStopped);
+        let mut shards: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (i, (key, _)) in records.iter().enumerate() {
+            shards.entry(self.shard_of(key)).or_default().push(i);
+        }
+        let mut held = vec![];
+        for (shard, indices) in shards {
+            let (checked, check) = oneshot::channel();
+            let (commit, committed) = oneshot::channel();
+            let (reply, response) = oneshot::channel();
+            let (release, released) = oneshot::channel();
+            let part = indices.iter().map(|i| records[*i].clone()).collect();
+            let request = ShardRequest::InsertAll { records: part, writer, checked, commit: committed, reply, release: released };
+            self.shards[shard].send((request, current_trace())).map_err(|_| stopped())?;
+            // Returning drops the commits, the shards asked so far write nothing.
+            check.await.map_err(|_| stopped())??;
+            held.push((indices, commit, response, release));
+        }
+        let mut releases = vec![];
+        let mut responses = vec![];
+        for (indices, commit, response, release) in held {
+            let _ = commit.send(());
+            responses.push((indices, response));
+            releases.push(release);
+        }
+        let mut versions = vec![0; records.len()];
+        for (indices, response) in responses {
+            for (i, version) in indices.into_iter().zip(response.await.map_err(|_| stopped())??) {
+                versions[i] = version;
+            }
+        }
+        // Dropping the senders releases the shards.
+        drop(releases);
+        Ok(versions)
+    }
+    /// Loads records in bulk, each shard takes its own in one write, see
+    /// [Database::bulk_load].
+    pub async fn bulk_load(&self, records: Vec<(Key, Value)>, writer: Option<ClientId>) -> Result<usize, NetworkError> {
+        let mut shards: Vec<Vec<(Key, Value)>> = vec![vec![]; self.shards.len()];
+        for (key, value) in records {
+            shards[self.shard_of(&key)].push((key, value));
+        }
+        let mut replies = vec![];
+        for (shard, records) in self.shards.iter().zip(shards).filter(|(_, records)| !records.is_empty()) {
+            let (reply, response) = oneshot::channel();
+            shard.send((ShardRequest::BulkLoad { records, writer, reply }, current_trace())).map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+            replies.push(response);
+        }
+        let mut loaded = 0;
+        for response in replies {
+            loaded += response.await.map_err(|_| NetworkError::Storage(StorageError::ShardStopped))??;
+        }
+        Ok(loaded)
+    }
+    /// Returns the value of the key and the version of its record, inserting
+    /// the default if it has none, see [Database::get_or_insert].
+    pub async fn get_or_insert(&self, key: &Key, default: Value, writer: Option<ClientId>) -> Result<(Value, u64), NetworkError> {
+        self.request(self.shard_of(key), |reply| ShardRequest::GetOrInsert { key: key.clone(), default, writer, reply }).await?
+    }
+    pub async fn delete(&self, key: &Key) -> Result<(), NetworkError> {
+        self.request(self.shard_of(key), |reply| ShardRequest::Delete { key: key.clone(), reply }).await??;
+        self.clear_split(key).await
+    }
+    /// Deletes the key if the client was the last to write it, see [Database::delete_written_by].
+    pub async fn delete_written_by(&self, key: &Key, writer: ClientId) -> Result<bool, NetworkError> {
+        self.request(self.shard_of(key), |reply| ShardRequest::DeleteWrittenBy { key: key.clone(), writer, reply }).await?
+    }
+    /// Deletes the key if it holds what the condition expects, see [Database::delete_if].
+    pub async fn delete_if(&self, key: &Key, condition: DeleteCondition) -> Result<bool, NetworkError> {
+        let deleted = self.request(self.shard_of(key), |reply| ShardRequest::DeleteIf { key: key.clone(), condition, reply }).await??;
+        if deleted {
+            self.clear_split(key).await?;
+        }
+        Ok(deleted)
+    }
+    /// The last changes to the key, see [super::KeyHistory::entries].
+    pub async fn history(&self, key: &Key, limit: usize) -> Result<Vec<HistoryEntry>, NetworkError> {
+        self.request(self.shard_of(key), |reply| ShardRequest::History { key: key.clone(), limit, reply }).await
+    }
+    /// What the key held at the revision, see [Database::value_at].
+    pub async fn value_at(&self, key: &Key, revision: Revision) -> Result<Option<Option<Value>>, NetworkError> {
+        self.request(self.shard_of(key), |reply| ShardRequest::ValueAt { key: key.clone(), revision, reply }).await
+    }
+    /// The shard a work queue lives on.
+    fn shard_of_queue(&self, queue: &str) -> usize {
+        self.shard_of(&Key::from_str(queue))
+    }
+    /// Appends an item to the work queue, see [Database::enqueue].
+    pub async fn enqueue(&self, queue: &str, value: Value) -> Result<(Key, u64), NetworkError> {
+        self.request(self.shard_of_queue(queue), |reply| ShardRequest::Enqueue { queue: queue.to_string(), value, reply }).await?
+    }
+    /// Leases the oldest visible item of the queue, see [Database::dequeue].
+    pub async fn dequeue(&self, queue: &str, visibility: Duration) -> Result<Option<LeasedItem>, NetworkError> {
+        self.request(self.shard_of_queue(queue), |reply| ShardRequest::Dequeue { queue: queue.to_string(), visibility, reply }).await
+    }
+    /// Removes a leased item from its queue, see [Database::ack].
+    pub async fn ack(&self, item: &Key, lease: u64) -> Result<bool, NetworkError> {
+        self.settle(item, lease, true).await
+    }
+    /// Gives up a lease on an item, see [Database::nack].
+    pub async fn nack(&self, item: &Key, lease: u64) -> Result<bool, NetworkError> {
+        self.settle(item, lease, false).await
+    }
+    async fn settle(&self, item: &Key, lease: u64, ack: bool) -> Result<bool, NetworkError> {
+        // A key not named like an item was never leased, any shard says so.
+        let shard = queue_of(item).map(|f| self.shard_of_queue(f)).unwrap_or(0);
+        self.request(shard, |reply| ShardRequest::Settle { item: item.clone(), lease, ack, reply }).await?
+    }
+    /// Lists the keys of every shard in order, a page at a time, see [Database::keys].
+    ///
+    /// Each shard lists its first keys after the cursor, the page is the
+    /// first of those across the shards.
+    pub async fn keys(&self, cursor: Option<&Key>, limit: usize) -> Result<(Vec<Key>, Option<Key>), NetworkError> {
+        let limit = limit.clamp(1, MAX_KEY_PAGE);
+        let mut replies = vec![];
+        for shard in &self.shards {
+            let (reply, response) = oneshot::channel();
+            shard.send((ShardRequest::Keys { cursor: cursor.cloned(), limit, reply }, current_trace())).map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+            replies.push(response);
+        }
+
+        let mut keys = vec![];
+        let mut more = false;
+        for response in replies {
+            let (page, cursor) = response.await.map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+            more |= cursor.is_some();
+            keys.extend(page);
+        }
+        keys.sort();
+        more |= keys.len() > limit;
+        keys.truncate(limit);
+        let cursor = if more { keys.last().cloned() } else { None };
+        Ok((keys, cursor))
+    }
+    /// The keys on every shard the matcher accepts in order, see
+    /// [Database::find_keys]. A page ends at the first key a shard stopped
+    /// looking at, so every key is listed once across the pages.
+    pub async fn find_keys(&self, matcher: &KeyMatcher, cursor: Option<&Key>, limit: usize) -> Result<(Vec<Key>, Option<Key>), NetworkError> {
+        let limit = limit.clamp(1, MAX_KEY_PAGE);
+        let mut replies = vec![];
+        for shard in &self.shards {
+            let (reply, response) = oneshot::channel();
+            let request = ShardRequest::FindKeys { matcher: matcher.clone(), cursor: cursor.cloned(), limit, reply };
+            shard.send((request, current_trace())).map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+            replies.push(response);
+        }
+
+        let mut keys = vec![];
+        let mut end: Option<Key> = None;
+        for response in replies {
+            let (page, cursor) = response.await.map_err(|_| NetworkError::Storage(StorageError::ShardStopped))??;
+            keys.extend(page);
+            if let Some(cursor) = cursor {
+                end = Some(match end {
+                    Some(end) => end.min(cursor),
+                    None => cursor
+                });
+            }
+        }
+        keys.sort();
+        if let Some(end) = &end {
+            keys.retain(|key| key <= end);
+        }
+        if keys.len() > limit {
+            keys.truncate(limit);
+            end = keys.last().cloned();
+        }
+        Ok((keys, end))
+    }
+    /// The watched keys on every shard in order, a named key is asked of
+    /// the shard that holds it. See [Database::watched_keys].
+    pub async fn watched_keys(&self, key: Option<&Key>) -> Result<Vec<WatchedKey>, NetworkError> {
+        if let Some(key) = key {
+            return self.request(self.shard_of(key), |reply| ShardRequest::WatchedKeys { key: Some(key.clone()), reply }).await;
+        }
+        let mut replies = vec![];
+        for shard in &self.shards {
+            let (reply, response) = oneshot::channel();
+            shard.send((ShardRequest::WatchedKeys { key: None, reply }, current_trace())).map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+            replies.push(response);
+        }
+        let mut watched = vec![];
+        for response in replies {
+            watched.extend(response.await.map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?);
+        }
+        watched.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(watched)
+    }
+    /// Lists the children on every shard and merges them in order, a child
+    /// with keys on several shards is listed once. See [Database::children].
+    pub async fn children(&self, key: &Key, cursor: Option<&Key>, limit: usize) -> Result<(Vec<KeyChild>, Option<Key>), NetworkError> {
+        let limit = limit.clamp(1, MAX_KEY_PAGE);
+        let mut replies = vec![];
+        for shard in &self.shards {
+            let (reply, response) = oneshot::channel();
+            shard.send((ShardRequest::Children { key: key.clone(), cursor: cursor.cloned(), limit, reply }, current_trace())).map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+            replies.push(response);
+        }
+
+        let mut children: Vec<KeyChild> = vec![];
+        let mut more = false;
+        for response in replies {
+            let (page, cursor) = response.await.map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+            more |= cursor.is_some();
+            children.extend(page);
+        }
+        children.sort_by(|a, b| a.key.cmp(&b.key));
+        children.dedup_by(|next, kept| {
+            if next.key != kept.key {
+                return false;
+            }
+            kept.has_value |= next.has_value;
+            kept.has_children |= next.has_children;
+            true
+        });
+        more |= children.len() > limit;
+        children.truncate(limit);
+        let cursor = if more { children.last().map(|f| f.key.clone()) } else { None };
+        Ok((children, cursor))
+    }
+    /// Exports a page of every shard and merges the records in order, see
+    /// [Database::export].
+    pub async fn export(&self, cursor: Option<&Key>, limit: usize) -> Result<(Vec<ExportedRecord>, Option<Key>), NetworkError> {
+        let limit = limit.clamp(1, MAX_KEY_PAGE);
+        let mut replies = vec![];
+        for shard in &self.shards {
+            let (reply, response) = oneshot::channel();
+            shard.send((ShardRequest::Export { cursor: cursor.cloned(), limit, reply }, current_trace())).map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+            replies.push(response);
+        }
+
+        let mut records = vec![];
+        let mut more = false;
+        for response in replies {
+            let (page, cursor) = response.await.map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+            more |= cursor.is_some();
+            records.extend(page);
+        }
+        records.sort_by(|a, b| a.key.cmp(&b.key));
+        more |= records.len() > limit;
+        records.truncate(limit);
+        let cursor = if more { records.last().map(|f| f.key.clone()) } else { None };
+        Ok((records, cursor))
+    }
+    /// Queries the index of every shard and merges the keys in order, see
+    /// [Database::query_index].
+    pub async fn query_index(&self, field: &str, value: &Value) -> Result<Option<Vec<Key>>, NetworkError> {
+        let mut replies = vec![];
+        for shard in &self.shards {
+            let (reply, response) = oneshot::channel();
+            shard.send((ShardRequest::QueryIndex { field: field.to_string(), value: value.clone(), reply }, current_trace())).map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+            replies.push(response);
+        }
+
+        let mut keys = vec![];
+        for response in replies {
+            // Every shard declares the same indexes.
+            let Some(matches) = response.await.map_err(|_| NetworkError::Storage(StorageError::ShardStopped))? else {
+                return Ok(None);
+            };
+            keys.extend(matches);
+        }
+        keys.sort();
+        Ok(Some(keys))
+    }
+    /// Archives the unused keys of every shard and merges them in order,
+    /// see [Database::archive].
+    pub async fn archive(&self, before: u64, delete: bool) -> Result<Vec<(Key, Value)>, NetworkError> {
+        let mut replies = vec![];
+        for shard in &self.shards {
+            let (reply, response) = oneshot::channel();
+            shard.send((ShardRequest::Archive { before, delete, reply }, current_trace())).map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+            replies.push(response);
+        }
+
+        let mut records = vec![];
+        for response in replies {
+            records.extend(response.await.map_err(|_| NetworkError::Storage(StorageError::ShardStopped))??);
+        }
+        records.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(records)
+    }
+    /// Takes or extends the lock on the key, see [Database::acquire].
+    pub async fn acquire(&self, key: &Key, client: ClientId, ttl: Duration) -> Result<Option<u64>, NetworkError> {
+        self.request(self.shard_of(key), |reply| ShardRequest::Acquire { key: key.clone(), client, ttl, reply }).await
+    }
+    /// Releases the lock on the key, see [Database::release_lock].
+    pub async fn release_lock(&self, key: &Key, client: ClientId) -> Result<bool, NetworkError> {
+        self.request(self.shard_of(key), |reply| ShardRequest::ReleaseLock { key: key.clone(), client, reply }).await
+    }
+    /// Releases the locks of the client on every shard, see [Database::release_locks].
+    pub async fn release_locks(&self, client: ClientId) -> Result<usize, NetworkError> {
+        self.release_locks_of(Some(client)).await
+    }
+    /// Releases the lapsed locks on every shard, see [Database::expire_locks].
+    pub async fn expire_locks(&self) -> Result<usize, NetworkError> {
+        self.release_locks_of(None).await
+    }
+    async fn release_locks_of(&self, client: Option<ClientId>) -> Result<usize, NetworkError> {
+        let mut released = 0;
+        for shard in 0..self.shards.len() {
+            released += self.request(shard, |reply| ShardRequest::ReleaseLocks { client, reply }).await?;
+        }
+        Ok(released)
+    }
+    /// Has every shard answer the system keys, see [Database::serve_system].
+    pub fn serve_system(&self, stats: Arc<SystemStats>) -> Result<(), NetworkError> {
+        for shard in &self.shards {
+            shard.send((ShardRequest::ServeSystem { stats: Arc::clone(&stats) }, current_trace())).map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+        }
+        Ok(())
+    }
+    /// Limits what the shards hold, each keeps an even share of the quota
+    /// of the default namespace, see [Quota::share].
+    pub fn set_quota(&self, quota: Quota) -> Result<(), NetworkError> {
+        let quota = quota.share(self.shards.len());
+        for shard in &self.shards {
+            shard.send((ShardRequest::SetQuota { quota }, current_trace())).map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+        }
+        Ok(())
+    }
+    /// Registers the schema of the prefix with every shard, see
+    /// [super::SchemaRegistry::register].
+    pub async fn register_schema(&self, prefix: &str, schema: Option<Schema>) -> Result<Option<Schema>, NetworkError> {
+        if let Some(schema) = &schema {
+            schema.check()?;
+        }
+        let mut previous = None;
+        for shard in 0..self.shards.len() {
+            let (prefix, schema) = (prefix.to_string(), schema.clone());
+            previous = self.request(shard, |reply| ShardRequest::RegisterSchema { prefix, schema, reply }).await??;
+        }
+        Ok(previous)
+    }
+    /// The schemas registered with the shards, which all hold the same.
+    pub async fn schemas(&self) -> Result<Vec<(String, Schema)>, NetworkError> {
+        self.request(0, |reply| ShardRequest::ListSchemas { reply }).await
+    }
+    /// The figures of every shard added together, see [Database::stats].
+    pub async fn stats(&self) -> Result<DatabaseStats, NetworkError> {
+        let mut stats = DatabaseStats::default();
+        for shard in 0..self.shards.len() {
+            stats.merge(self.request(shard, |reply| ShardRequest::Stats { reply }).await?);
+        }
+        Ok(stats)
+    }
+    /// The flush counters of every shard added together, see [Database::storage_stats].
+    pub async fn storage_stats(&self) -> Result<StorageSnapshot, NetworkError> {
+        let mut stats = StorageSnapshot::default();
+        for shard in 0..self.shards.len() {
+            stats.merge(self.request(shard, |reply| ShardRequest::StorageStats { reply }).await?);
+        }
+        Ok(stats)
+    }
+    /// How many sampled keys of every shard sit in each storage tier.
+    pub async fn placement_stats(&self) -> Result<PlacementStats, NetworkError> {
+        let mut stats = PlacementStats::default();
+        for shard in 0..self.shards.len() {
+            stats.merge(self.request(shard, |reply| ShardRequest::Placement { reply }).await?);
+        }
+        Ok(stats)
+    }
+    /// The most accessed keys across the shards, every shard samples the
+    /// keys it holds. See [super::AccessSampler::hottest].
+    pub async fn hottest(&self, limit: usize) -> Result<Vec<(Key, u64)>, NetworkError> {
+        let mut keys = vec![];
+        for shard in 0..self.shards.len() {
+            keys.extend(self.request(shard, |reply| ShardRequest::Hottest { limit, reply }).await?);
+        }
+        keys.sort_by_key(|f| Reverse(f.1));
+        keys.truncate(limit);
+        Ok(keys)
+    }
+    /// The recent compaction runs of every shard, oldest first.
+    pub async fn compactions(&self) -> Result<Vec<CompactionRun>, NetworkError> {
+        let mut runs = vec![];
+        for shard in 0..self.shards.len() {
+            runs.extend(self.request(shard, |reply| ShardRequest::Compactions { reply }).await?);
+        }
+        runs.sort_by_key(|f| f.finished_at);
+        Ok(runs)
+    }
+    /// Diffs what the key held at two revisions on its shard, see
+    /// [super::KeyHistory::diff].
+    pub async fn key_diff(&self, key: &Key, from: Revision, to: Revision) -> Result<Option<KeyDiff>, NetworkError> {
+        self.request(self.shard_of(key), |reply| ShardRequest::KeyDiff { key: key.clone(), from, to, reply }).await
+    }
+    /// Lists the required keys that are missing or hold the wrong type, see
+    /// [Database::check_manifest].
+    pub async fn check_manifest(&self, manifest: &Manifest) -> Result<Vec<KeyDrift>, NetworkError> {
+        let mut drift = vec![];
+        for required in manifest.keys() {
+            let found = self.get(&required.key).await?;
+            drift.extend(required.check(found.as_ref()));
+        }
+        Ok(drift)
+    }
+    /// Creates the defaults of the required keys that are missing on their
+    /// shards, see [Database::apply_manifest_defaults].
+    pub async fn apply_manifest_defaults(&self, manifest: &Manifest) -> Result<usize, NetworkError> {
+        let mut created = 0;
+        for required in manifest.keys() {
+            if let Some(default) = &required.default {
+                if self.get(&required.key).await?.is_none() {
+                    self.insert(&required.key, default.clone(), None).await?;
+                    created += 1;
+                }
+            }
+        }
+        Ok(created)
+    }
+    /// Moves every shard next to the store of that name, `db` moving its
+    /// shards to `db.shard.0`, `db.shard.1` and so on. See [Database::migrate].
+    ///
+    /// The shards are moved one after the other, a failure leaves the ones
+    /// before it moved.
+    pub async fn migrate<P, S>(&self, path: P, name: S) -> Result<MigrationReport, NetworkError>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>
+    {
+        let mut report = MigrationReport { copied: 0, replayed: 0, rounds: 0 };
+        for shard in 0..self.shards.len() {
+            let (path, name) = (path.as_ref().to_path_buf(), format!("{}.shard.{shard}", name.as_ref()));
+            report.merge(self.request(shard, |reply| ShardRequest::Migrate { path, name, reply }).await??);
+        }
+        Ok(report)
+    }
+    /// The next number of the sequence, which lives on the shard its name
+    /// would as a key. See [super::SequenceRegistry::next].
+    pub async fn next_sequence(&self, name: &str) -> Result<u64, NetworkError> {
+        self.request(self.shard_of(&Key::from_str(name)), |reply| ShardRequest::NextSequence { name: name.to_string(), reply }).await?
+    }
+    /// Refreshes the system keys of every shard, see [Database::refresh_system].
+    pub async fn refresh_system(&self) -> Result<(), NetworkError> {
+        for shard in 0..self.shards.len() {
+            self.request(shard, |reply| ShardRequest::RefreshSystem { reply }).await?;
+        }
+        Ok(())
+    }
+    /// Writes the seed if every shard is empty, see [Database::seed].
+    pub async fn seed(&self, seed: &Seed) -> Result<usize, NetworkError> {
+        if !self.keys(None, 1).await?.0.is_empty() {
+            return Ok(0);
+        }
+        let records = seed.resolve().await?;
+        for (key, value) in &records {
+            self.insert(key, value.clone(), None).await?;
+        }
+        Ok(records.len())
+    }
+    /// Subscribes to a key on its shard, returning the value and version of
+    /// the shard at the time along with the changes made after it.
+    pub async fn subscribe(
+        &self,
+        key: &Key,
+        client: ClientId,
+        behaviour: WatcherBehaviour,
+        activity: WatcherActivity,
+        limit: WatcherLimit
+    ) -> Result<(Option<Value>, u64, UnboundedReceiver<ShardChanges>), NetworkError> {
+        let (changes, receiver) = mpsc::unbounded_channel();
+        let (value, version) = self.request(self.shard_of(key), |reply| ShardRequest::Watch {
+            key: key.clone(),
+            client,
+            behaviour,
+            activity,
+            limit,
+            changes,
+            reply
+        }).await?;
+        Ok((value, version, receiver))
+    }
+    pub async fn release(&self, key: &Key, client: ClientId) -> Result<(), NetworkError> {
+        self.request(self.shard_of(key), |reply| ShardRequest::Release { key: key.clone(), client, reply }).await?
+    }
+    /// Makes the pending writes of every shard durable, see [Database::sync_pending].
+    pub async fn sync_pending(&self) -> Result<bool, NetworkError> {
+        let mut pending = false;
+        for shard in 0..self.shards.len() {
+            pending |= self.request(shard, |reply| ShardRequest::Sync { reply }).await??;
+        }
+        Ok(pending)
+    }
+    /// Kills the watchers of every shard and flushes them, then waits for
+    /// the threads to stop. The shards refuse any request made after this.
+    pub async fn shutdown(&self) -> Result<(), NetworkError> {
+        let mut result = Ok(());
+        for shard in 0..self.shards.len() {
+            if let Err(e) = self.request(shard, |reply| ShardRequest::Shutdown { reply }).await.and_then(|f| f) {
+                result = Err(e);
+            }
+        }
+        let threads: Vec<_> = self.threads.borrow_mut().drain(..).collect();
+        for thread in threads {
+            // The shard already replied, so it is only tearing down its runtime.
+            let _ = thread.join();
+        }
+        result
+    }
+}
+
+/// Sends a request down the channel of a shard and waits for the reply.
+async fn ask<T>(shard: &ShardSender, request: impl FnOnce(oneshot::Sender<T>) -> ShardRequest) -> Result<T, NetworkError> {
+    let (reply, response) = oneshot::channel();
+    shard.send((request(reply), current_trace())).map_err(|_| NetworkError::Storage(StorageError::ShardStopped))?;
+    response.await.map_err(|_| NetworkError::Storage(StorageError::ShardStopped))
+}
+
+/// Runs a shard until the pool shuts it down or goes away.
+async fn serve_shard(
+    path: PathBuf,
+    name: String,
+    settings: ShardSettings,
+    mut requests: UnboundedReceiver<(ShardRequest, Option<u64>)>,
+    ready: oneshot::Sender<Result<(), NetworkError>>
+) {
+    let database = match Database::new(&path, &name).await {
+        Ok(mut database) => {
+            database.set_placement_policy(settings.placement);
+            database.set_durability_policy(settings.durability);
+            database.set_history_retention(settings.history);
+            for field in &settings.indexes {
+                database.declare_index(field);
+            }
+            Rc::new(database)
+        }
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+    let _ = ready.send(Ok(()));
+
+    while let Some((request, trace)) = requests.recv().await {
+        if !traced(trace, serve_request(&database, request)).await {
+            return;
+        }
+    }
+}
+
+/// Serves a request on the database of a shard in the trace it was made
+/// in, returning false once the shard is shut down.
+async fn serve_request(database: &Rc<Database>, request: ShardRequest) -> bool {
+    match request {
+        ShardRequest::Get { key, reply } => {
+            let _ = reply.send(database.get_with_meta(&key).map(|(value, version, meta)| ((*value).clone(), version, meta)));
+        }
+        ShardRequest::Insert { key, value, writer, reply } => {
+            let _ = reply.send(database.insert(&key, value, writer).await);
+        }
+        ShardRequest::BulkLoad { records, writer, reply } => {
+            let _ = reply.send(database.bulk_load(records, writer).await);
+        }
+        ShardRequest::InsertAll { records, writer, checked, commit, reply, release } => {
+            let check = database.check_writes(&records);
+            let passed = check.is_ok();
+            // The writes are called off by dropping the commit.
+            if checked.send(check).is_ok() && passed && commit.await.is_ok() {
+                let _ = reply.send(database.insert_all(records, writer).await);
+                let _ = release.await;
+            }
+        }
+        ShardRequest::InsertAcknowledged { key, value, acknowledgement, writer, reply } => {
+            let _ = reply.send(database.insert_acknowledged(&key, value, acknowledgement, writer).await);
+        }
+        ShardRequest::InsertIfVersion { key, value, version, writer, reply } => {
+            let _ = reply.send(database.insert_if_version(&key, value, version, writer).await);
+        }
+        ShardRequest::SnapshotGet { keys, reply, release } => {
+            let values = database.snapshot_get(&keys).into_iter().map(|(value, version)| (value.map(|f| (*f).clone()), version)).collect();
+            let _ = reply.send(values);
+            let _ = release.await;
+        }
+        ShardRequest::Rename { from, to, overwrite, reply } => {
+            let _ = reply.send(database.rename(&from, &to, overwrite).await);
+        }
+        ShardRequest::Hold { held, mut requests } => {
+            let _ = held.send(());
+            while let Some((request, trace)) = requests.recv().await {
+                if !Box::pin(traced(trace, serve_request(database, request))).await {
+                    return false;
+                }
+            }
+        }
+        ShardRequest::Take { key, reply } => {
+            let _ = reply.send(database.take(&key).await);
+        }
+        ShardRequest::Place { key, value, meta, overwrite, writer, reply } => {
+            let _ = reply.send(database.place(&key, value, meta, overwrite, writer).await);
+        }
+        ShardRequest::GetOrInsert { key, default, writer, reply } => {
+            let _ = reply.send(database.get_or_insert(&key, default, writer).await.map(|(value, version, _)| ((*value).clone(), version)));
+        }
+        ShardRequest::Increment { key, delta, writer, reply } => {
+            let _ = reply.send(database.increment(&key, delta, writer).await.map(|(value, version)| ((*value).clone(), version)));
+        }
+        ShardRequest::Delete { key, reply } => {
+            let _ = reply.send(database.delete(&key).await);
+        }
+        ShardRequest::DeleteWrittenBy { key, writer, reply } => {
+            let _ = reply.send(database.delete_written_by(&key, writer).await);
+        }
+        ShardRequest::DeleteIf { key, condition, reply } => {
+            let _ = reply.send(database.delete_if(&key, &condition).await);
+        }
+        ShardRequest::History { key, limit, reply } => {
+            let _ = reply.send(database.history().entries(&key, limit));
+        }
+        ShardRequest::ValueAt { key, revision, reply } => {
+            let _ = reply.send(database.value_at(&key, revision).await.map(|f| f.as_deref().cloned()));
+        }
+        ShardRequest::KeyDiff { key, from, to, reply } => {
+            let _ = reply.send(database.history().diff(&key, from, to));
+        }
+        ShardRequest::Enqueue { queue, value, reply } => {
+            let _ = reply.send(database.enqueue(&queue, value).await);
+        }
+        ShardRequest::Dequeue { queue, visibility, reply } => {
+            let _ = reply.send(database.dequeue(&queue, visibility));
+        }
+        ShardRequest::Settle { item, lease, ack, reply } => {
+            let released = match ack {
+                true => database.ack(&item, lease).await,
+                false => Ok(database.nack(&item, lease))
+            };
+            let _ = reply.send(released);
+        }
+        ShardRequest::Keys { cursor, limit, reply } => {
+            let _ = reply.send(database.keys(cursor.as_ref(), limit));
+        }
+        ShardRequest::FindKeys { matcher, cursor, limit, reply } => {
+            let _ = reply.send(database.find_keys(&matcher, cursor.as_ref(), limit));
+        }
+        ShardRequest::Export { cursor, limit, reply } => {
+            let _ = reply.send(database.export(cursor.as_ref(), limit));
+        }
+        ShardRequest::Children { key, cursor, limit, reply } => {
+            let _ = reply.send(database.children(&key, cursor.as_ref(), limit));
+        }
+        ShardRequest::WatchedKeys { key, reply } => {
+            let _ = reply.send(database.watched_keys(key.as_ref()));
+        }
+        ShardRequest::QueryIndex { field, value, reply } => {
+            let _ = reply.send(database.query_index(&field, &value));
+        }
+        ShardRequest::Watch { key, client, behaviour, activity, limit, changes, reply } => {
+            let (watcher, value, version) = match activity {
+                WatcherActivity::Lazy => database.subscribe_snapshot(&key, client, behaviour, limit).await,
+                // A kickback is queued on the watcher, so it is not part of the snapshot.
+                WatcherActivity::Kickback => match database.subscribe(&key, client, behaviour, activity, limit).await {
+                    Ok(watcher) => (watcher, None, 0),
+                    Err(e) => {
+                        tracing::warn!("Could not subscribe on the shard: {e}");
+                        return true;
+                    }
+                }
+            };
+            let _ = reply.send((value.map(|f| (*f).clone()), version));
+            monoio::spawn({
+                let database = Rc::clone(database);
+                async move {
+                    loop {
+                        let mut batch = vec![watcher.wait().await];
+                        batch.extend(watcher.drain());
+                        let missed = watcher.take_missed();
+                        let closed = watcher.is_killed();
+                        if closed && missed == 0 {
+                            // Released or shut down.
+                            break;
+                        }
+                        // Values cannot leave the thread as they are shared within it.
+                        let (values, traces) = match closed {
+                            true => (vec![], vec![]),
+                            false => (
+                                batch.iter().map(|f| f.as_deref().cloned()).collect(),
+                                batch.iter().map(|f| database.trace_of(&key, f.as_ref())).collect()
+                            )
+                        };
+                        if changes.send(ShardChanges { values, traces, missed, closed }).is_err() {
+                            // The connection went away without releasing the key.
+                            let _ = database.release(&key, client).await;
+                            break;
+                        }
+                        if closed {
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+        ShardRequest::Release { key, client, reply } => {
+            let _ = reply.send(database.release(&key, client).await);
+        }
+        ShardRequest::Archive { before, delete, reply } => {
+            let _ = reply.send(database.archive(before, delete).await);
+        }
+        ShardRequest::Acquire { key, client, ttl, reply } => {
+            let _ = reply.send(database.acquire(&key, client, ttl).await);
+        }
+        ShardRequest::ReleaseLock { key, client, reply } => {
+            let _ = reply.send(database.release_lock(&key, client).await);
+        }
+        ShardRequest::ReleaseLocks { client, reply } => {
+            let released = match client {
+                Some(client) => database.release_locks(client).await,
+                None => database.expire_locks().await
+            };
+            let _ = reply.send(released);
+        }
+        ShardRequest::ServeSystem { stats } => database.serve_system(stats),
+        ShardRequest::SetQuota { quota } => database.set_quota(quota),
+        ShardRequest::RegisterSchema { prefix, schema, reply } => {
+            let _ = reply.send(database.schemas().register(&prefix, schema).await);
+        }
+        ShardRequest::ListSchemas { reply } => {
+            let _ = reply.send(database.schemas().schemas());
+        }
+        ShardRequest::Stats { reply } => {
+            let _ = reply.send(database.stats());
+        }
+        ShardRequest::StorageStats { reply } => {
+            let _ = reply.send(database.storage_stats());
+        }
+        ShardRequest::Placement { reply } => {
+            let _ = reply.send(database.placement().stats());
+        }
+        ShardRequest::Hottest { limit, reply } => {
+            let _ = reply.send(database.placement().hottest(limit));
+        }
+        ShardRequest::Compactions { reply } => {
+            let _ = reply.send(database.compactions().runs());
+        }
+        ShardRequest::Migrate { path, name, reply } => {
+            monoio::spawn({
+                let database = Rc::clone(database);
+                async move {
+                    let _ = reply.send(database.migrate(&path, &name).await);
+                }
+            });
+        }
+        ShardRequest::NextSequence { name, reply } => {
+            let _ = reply.send(database.sequences().next(&name).await);
+        }
+        ShardRequest::RefreshSystem { reply } => {
+            database.refresh_system().await;
+            let _ = reply.send(());
+        }
+        ShardRequest::Sync { reply } => {
+            let _ = reply.send(database.sync_pending().await);
+        }
+        ShardRequest::Shutdown { reply } => {
+            database.kill_watchers();
+            let _ = reply.send(database.flush().await);
+            return false;
+        }
+    }
+    true
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use overseer::{access::{WatcherActivity, WatcherBehaviour}, error::NetworkError, models::{Key, RecordMeta, Schema, Value}};
+
+    use crate::{database::{PlacementPolicy, WatcherLimit}, net::ClientId};
+
+    use super::{ShardPool, ShardSettings};
+
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_sharded_keys_and_watch() {
+        let tf = tempfile::tempdir().unwrap();
+        let pool = ShardPool::open(tf.path(), "db", 3, ShardSettings::default()).await.unwrap();
+        for i in 0..20 {
+            pool.insert(&Key::from_owned(format!("key-{i:02}")), Value::Integer(i), None).await.unwrap();
+        }
+        assert_eq!(pool.get(&Key::from_str("key-07")).await.unwrap(), Some(Value::Integer(7)));
+
+        // The pages merge the shards in order.
+        let mut listed = vec![];
+        let mut cursor = None;
+        loop {
+            let (keys, next) = pool.keys(cursor.as_ref(), 6).await.unwrap();
+            listed.extend(keys);
+            cursor = next;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        let expected: Vec<Key> = (0..20).map(|i| Key::from_owned(format!("key-{i:02}"))).collect();
+        assert_eq!(listed, expected);
+
+        let key = Key::from_str("key-03");
+        let (value, _, mut changes) = pool.subscribe(&key, ClientId::from_id(1), WatcherBehaviour::Ordered, WatcherActivity::Lazy, WatcherLimit::default()).await.unwrap();
+        assert_eq!(value, Some(Value::Integer(3)));
+        pool.insert(&key, Value::Integer(30), None).await.unwrap();
+        assert_eq!(changes.recv().await.unwrap().values, vec![Some(Value::Integer(30))]);
+
+        pool.release(&key, ClientId::from_id(1)).await.unwrap();
+        assert!(changes.recv().await.is_none());
+        pool.shutdown().await.unwrap();
+    }
+
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_snapshot_get_across_shards() {
+        let tf = tempfile::tempdir().unwrap();
+        let pool = ShardPool::open(tf.path(), "db", 3, ShardSettings::default()).await.unwrap();
+        let keys: Vec<Key> = (0..8).map(|i| Key::from_owned(format!("key-{i}"))).collect();
+        for (i, key) in keys.iter().enumerate().skip(1) {
+            pool.insert(key, Value::Integer(i as i64), None).await.unwrap();
+        }
+        let values = pool.snapshot_get(&keys).await.unwrap();
+        assert_eq!(values[0], (None, 0));
+        for (i, (value, version)) in values.into_iter().enumerate().skip(1) {
+            assert_eq!(value, Some(Value::Integer(i as i64)));
+            assert!(version > 0);
+        }
+        // The shards serve again once released.
+        pool.insert(&keys[0], Value::Integer(0), None).await.unwrap();
+        assert_eq!(pool.snapshot_get(&keys[..1]).await.unwrap()[0].0, Some(Value::Integer(0)));
+        pool.shutdown().await.unwrap();
+    }
+
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_insert_all_across_shards() {
+        let tf = tempfile::tempdir().unwrap();
+        let pool = ShardPool::open(tf.path(), "db", 3, ShardSettings::default()).await.unwrap();
+        let keys: Vec<Key> = (0..8).map(|i| Key::from_owned(format!("app.{i}"))).collect();
+        pool.register_schema("app.7", Some(Schema::integer(Some(1), None))).await.unwrap();
+
+        // The write one shard refuses calls off those of the others.
+        let refused = keys.iter().enumerate().map(|(i, key)| (key.clone(), Value::Integer(i as i64 - 7))).collect();
+        assert!(matches!(pool.insert_all(refused, None).await, Err(NetworkError::ValidationFailed(..))));
+        assert!(pool.snapshot_get(&keys).await.unwrap().iter().all(|(value, _)| value.is_none()));
+
+        let records = keys.iter().enumerate().map(|(i, key)| (key.clone(), Value::Integer(i as i64))).collect();
+        let versions = pool.insert_all(records, None).await.unwrap();
+        assert_eq!(versions.len(), keys.len());
+        for (i, (value, version)) in pool.snapshot_get(&keys).await.unwrap().into_iter().enumerate() {
+            assert_eq!(value, Some(Value::Integer(i as i64)));
+            assert_eq!(version, versions[i]);
+        }
+        pool.shutdown().await.unwrap();
+    }
+
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_rename_across_shards() {
+        let tf = tempfile::tempdir().unwrap();
+        let pool = ShardPool::open(tf.path(), "db", 3, ShardSettings::default()).await.unwrap();
+        let from = Key::from_str("key-00");
+        let to = (1..).map(|i| Key::from_owned(format!("key-{i:02}"))).find(|f| pool.shard_of(f) != pool.shard_of(&from)).unwrap();
+        let taken = (1..).map(|i| Key::from_owned(format!("taken-{i:02}"))).find(|f| pool.shard_of(f) != pool.shard_of(&to)).unwrap();
+        pool.insert(&from, Value::Integer(1), Some(ClientId::from_id(1))).await.unwrap();
+        pool.insert(&taken, Value::Integer(2), None).await.unwrap();
+        let written = |meta: Option<RecordMeta>| meta.map(|f| (f.created_at, f.modified_at, f.writer));
+        let meta = written(pool.get_with_meta(&from).await.unwrap().unwrap().2);
+
+        assert!(pool.rename(&from, &to, false, None).await.unwrap().is_some());
+        assert!(pool.get(&from).await.unwrap().is_none());
+        let (value, _, moved) = pool.get_with_meta(&to).await.unwrap().unwrap();
+        assert_eq!((value, written(moved)), (Value::Integer(1), meta));
+
+        // A taken target is left alone, and so is the record.
+        assert!(pool.rename(&to, &taken, false, None).await.is_err());
+        assert_eq!(pool.get(&to).await.unwrap(), Some(Value::Integer(1)));
+        assert!(pool.rename(&to, &taken, true, None).await.unwrap().is_some());
+        assert_eq!(pool.get(&taken).await.unwrap(), Some(Value::Integer(1)));
+
+        // The shards are held while the record moves, nothing else is served meanwhile.
+        let held = pool.hold(pool.shard_of(&taken)).await.unwrap();
+        assert!(monoio::time::timeout(Duration::from_millis(50), pool.get(&taken)).await.is_err());
+        drop(held);
+        assert_eq!(pool.get(&taken).await.unwrap(), Some(Value::Integer(1)));
+        pool.shutdown().await.unwrap();
+    }
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_placement_across_shards() {
+        let tf = tempfile::tempdir().unwrap();
+        let placement = PlacementPolicy { sample_rate: 1, ..Default::default() };
+        let pool = ShardPool::open(tf.path(), "db", 3, ShardSettings { placement, ..Default::default() }).await.unwrap();
+        let keys: Vec<Key> = (0..6).map(|i| Key::from_owned(format!("key-{i}"))).collect();
+        for (i, key) in keys.iter().enumerate() {
+            pool.insert(key, Value::Integer(i as i64), None).await.unwrap();
+            for _ in 0..i {
+                pool.get(key).await.unwrap();
+            }
+        }
+
+        // Every shard samples its own keys, the figures add up.
+        let stats = pool.placement_stats().await.unwrap();
+        assert_eq!(stats.hot + stats.warm + stats.cold, keys.len());
+        let hottest: Vec<Key> = pool.hottest(2).await.unwrap().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(hottest, vec![keys[5].clone(), keys[4].clone()]);
+        pool.shutdown().await.unwrap();
+    }
+
+    #[monoio::test(enable_timer = true)]
+    pub async fn test_migrate_shards() {
+        let (tf, target) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let pool = ShardPool::open(tf.path(), "db", 3, ShardSettings::default()).await.unwrap();
+        for i in 0..20 {
+            pool.insert(&Key::from_owned(format!("key-{i:02}")), Value::Integer(i), None).await.unwrap();
+        }
+
+        let report = pool.migrate(target.path(), "moved").await.unwrap();
+        assert_eq!(report.copied, 20);
+        for shard in 0..3 {
+            assert!(target.path().join(format!("moved.shard.{shard}")).exists());
+        }
+        // The shards keep serving from where they moved to.
+        pool.insert(&Key::from_str("key-20"), Value::Integer(20), None).await.unwrap();
+        assert_eq!(pool.get(&Key::from_str("key-07")).await.unwrap(), Some(Value::Integer(7)));
+        assert!(pool.migrate(target.path(), "moved").await.is_err());
+        pool.shutdown().await.unwrap();
+
+        let reopened = ShardPool::open(target.path(), "moved", 3, ShardSettings::default()).await.unwrap();
+        assert_eq!(reopened.stats().await.unwrap().keys, 21);
+        reopened.shutdown().await.unwrap();
+    }
+}
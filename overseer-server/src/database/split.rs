@@ -0,0 +1,130 @@
+use std::borrow::Borrow;
+
+use overseer::models::{Key, Value};
+
+
+/// Joins a split key and the index of one of its sub-keys.
+pub const SUB_KEY_SEPARATOR: char = '#';
+
+/// Splits the counters under some prefixes across sub-keys.
+///
+/// The writes to a key are ordered, so a counter that every client bumps
+/// waits on itself. Increments of a split key go to its sub-keys in turn,
+/// `hits` to `hits#0`, `hits#1` and so on, which are ordered apart and
+/// on a [super::ShardPool] land on different threads. Reads add the key
+/// and its sub-keys back up, so the increments of a split key are not
+/// ordered against each other and its watchers only see inserts.
+///
+/// An insert or delete of a split key clears its sub-keys, so it resets
+/// the counter. The longest prefix a key starts with decides how many
+/// ways it is split.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct SplitPolicy {
+    /// How many sub-keys the keys under each prefix are split across.
+    pub prefixes: Vec<(String, usize)>
+}
+
+impl SplitPolicy {
+    /// Splits the keys under a prefix, replacing how the prefix was split.
+    ///
+    /// # Panics
+    /// If the keys are split fewer than two ways.
+    pub fn with_prefix<S>(mut self, prefix: S, ways: usize) -> Self
+    where
+        S: Into<String>
+    {
+        assert!(ways > 1, "A key has to be split at least two ways.");
+        let prefix = prefix.into();
+        self.prefixes.retain(|(existing, _)| *existing != prefix);
+        self.prefixes.push((prefix, ways));
+        self
+    }
+    /// How many sub-keys the key is split across, [None] if it is not
+    /// split or is itself the sub-key of a split key.
+    pub fn ways_of(&self, key: &Key) -> Option<usize> {
+        if let Some((parent, index)) = key.as_str().rsplit_once(SUB_KEY_SEPARATOR) {
+            let split = self.ways_of_prefix(parent);
+            if split.is_some_and(|ways| index.parse::<usize>().is_ok_and(|f| f < ways)) {
+                return None;
+            }
+        }
+        self.ways_of_prefix(key.as_str())
+    }
+    fn ways_of_prefix(&self, key: &str) -> Option<usize> {
+        self.prefixes.iter()
+            .filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, ways)| *ways)
+    }
+}
+
+/// The sub-key of a split key at the index.
+pub fn sub_key(key: &Key, index: usize) -> Key {
+    Key::from_str(&format!("{}{SUB_KEY_SEPARATOR}{index}", key.as_str()))
+}
+
+/// The key and its sub-keys, in the order [aggregate_split] reads them.
+pub fn split_keys(key: &Key, ways: usize) -> impl Iterator<Item = Key> + '_ {
+    std::iter::once(key.clone()).chain((0..ways).map(|f| sub_key(key, f)))
+}
+
+/// Adds up the records of the key and its sub-keys, see [split_keys],
+/// with the newest of their versions. [None] if none of them is set.
+///
+/// A key holding something other than an integer is read as that value,
+/// it does not take increments.
+pub fn aggregate_split<V, I>(records: I) -> Option<(Value, u64)>
+where
+    V: Borrow<Value>,
+    I: IntoIterator<Item = Option<(V, u64)>>
+{
+    let mut records = records.into_iter();
+    let base = records.next().flatten();
+    if let Some((value, version)) = &base {
+        if !matches!(value.borrow(), Value::Integer(..)) {
+            return Some((value.borrow().clone(), *version));
+        }
+    }
+    let mut total: Option<(i64, u64)> = None;
+    for (value, version) in base.into_iter().chain(records.flatten()) {
+        if let Value::Integer(integer) = value.borrow() {
+            let (sum, newest) = total.unwrap_or_default();
+            total = Some((sum.saturating_add(*integer), newest.max(version)));
+        }
+    }
+    total.map(|(sum, version)| (Value::Integer(sum), version))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::models::{Key, Value};
+
+    use super::{aggregate_split, split_keys, SplitPolicy};
+
+
+    #[test]
+    pub fn test_split_policy() {
+        let policy = SplitPolicy::default().with_prefix("stats.", 4).with_prefix("stats.hits", 2);
+        assert_eq!(policy.ways_of(&Key::from_str("stats.hits")), Some(2));
+        assert_eq!(policy.ways_of(&Key::from_str("stats.views")), Some(4));
+        assert_eq!(policy.ways_of(&Key::from_str("app.mode")), None);
+
+        // The sub-keys are not split again, a key that only looks like one is.
+        let keys: Vec<Key> = split_keys(&Key::from_str("stats.views"), 4).collect();
+        assert_eq!(keys[1], Key::from_str("stats.views#0"));
+        assert!(keys[1..].iter().all(|f| policy.ways_of(f).is_none()));
+        assert_eq!(policy.ways_of(&Key::from_str("stats.views#4")), Some(4));
+    }
+
+    #[test]
+    pub fn test_aggregate_split() {
+        let integer = |f| Some((Value::Integer(f), f as u64));
+        assert_eq!(aggregate_split([None, integer(2), None, integer(5)]), Some((Value::Integer(7), 5)));
+        assert_eq!(aggregate_split([integer(1), integer(2)]), Some((Value::Integer(3), 2)));
+        assert_eq!(aggregate_split::<Value, _>([None, None]), None);
+
+        let text = Some((Value::String("off".into()), 9));
+        assert_eq!(aggregate_split([text, integer(2)]), Some((Value::String("off".into()), 9)));
+    }
+}
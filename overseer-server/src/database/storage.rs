@@ -1,16 +1,59 @@
-use std::{collections::HashMap, path::{Path, PathBuf}, sync::RwLock};
+use std::{cell::Cell, collections::HashMap, path::{Path, PathBuf}, sync::RwLock, time::{Duration, Instant}};
 
-use overseer::{error::NetworkError, models::{Key, Value}};
+use overseer::{error::{NetworkError, StorageError}, models::{Durability, Key, RecordMeta, Value}};
+
+use tokio::sync::Mutex;
+
+use super::{Change, StorageSnapshot, StorageStats};
 
 
 /// The storage driver for the database. Without this we cannot store things.
 pub struct DatabaseStorage
 {
     location: PathBuf,
-    hashmap: RwLock<HashMap<Key, Value>>
+    hashmap: RwLock<HashMap<Key, Value>>,
+    /// Where the metadata of the records is kept, next to the records so
+    /// that stores written before it existed still open.
+    meta_location: PathBuf,
+    meta: RwLock<HashMap<Key, RecordMeta>>,
+    /// Whether there are best effort writes the file does not have.
+    unsaved: Cell<bool>,
+    /// Whether the file was written since it was last synced to disk.
+    unsynced: Cell<bool>,
+    /// Held through a checkpoint, two of them would write the same
+    /// temporary files and the second rename would find them gone.
+    checkpointing: Mutex<()>,
+    /// How many changes were made to the records, the checkpoints note
+    /// how many of them they hold.
+    written: Cell<u64>,
+    /// How many changes the files hold.
+    saved_through: Cell<u64>,
+    /// How many changes the files hold that were synced to disk.
+    synced_through: Cell<u64>,
+    /// How long a commit waits for more writes to join it, see [DatabaseStorage::set_group_window].
+    group_window: Cell<Duration>,
+    /// The checkpoints that completed and how long they took.
+    stats: StorageStats
     // pool: Pool<Sqlite>
 }
 
+/// The steps of a checkpoint in the order they run, see [DatabaseStorage::save].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum CheckpointStep {
+    /// The records and their metadata were written to temporary files.
+    WriteTemporary,
+    /// The temporary files were synced, only for a synced checkpoint.
+    SyncTemporary,
+    /// The metadata replaced the last metadata.
+    RenameMeta,
+    /// The records replaced the last records, a crash from here on
+    /// reopens the new checkpoint.
+    RenameRecords,
+    /// The directory was synced so the renames survive a power loss, only
+    /// for a synced checkpoint.
+    SyncDirectory
+}
+
 pub struct StoredRecord {
     pub key: Key,
     pub value: Value
@@ -24,9 +67,24 @@ impl DatabaseStorage {
     {
 
         let path = path.as_ref().join(name.as_ref());
+        let meta_location = path.with_file_name(format!("{}.meta", name.as_ref()));
+        for temporary in [temporary(&path), temporary(&meta_location)] {
+            // A checkpoint crashed before replacing the files, which still
+            // hold the last complete one.
+            if temporary.exists() {
+                std::fs::remove_file(temporary)?;
+            }
+        }
  
         let inner = if path.exists() {
-            bincode::deserialize(&monoio::fs::read(&path).await?).unwrap()
+            bincode::deserialize(&monoio::fs::read(&path).await?)
+                .map_err(|e| StorageError::Corrupt(format!("{}: {e}", path.display())))?
+        } else {
+            HashMap::new()
+        };
+        let meta = if meta_location.exists() {
+            bincode::deserialize(&monoio::fs::read(&meta_location).await?)
+                .map_err(|e| StorageError::Corrupt(format!("{}: {e}", meta_location.display())))?
         } else {
             HashMap::new()
         };
@@ -34,29 +92,193 @@ impl DatabaseStorage {
         
         Ok(Self {
             location: path,
-            hashmap: RwLock::new(inner)
+            hashmap: RwLock::new(inner),
+            meta_location,
+            meta: RwLock::new(meta),
+            unsaved: Cell::new(false),
+            unsynced: Cell::new(false),
+            checkpointing: Mutex::new(()),
+            written: Cell::new(0),
+            saved_through: Cell::new(0),
+            synced_through: Cell::new(0),
+            group_window: Cell::new(Duration::ZERO),
+            stats: StorageStats::default()
         })
     }
-    pub async fn write(&self, key: &Key, value: &Value) -> Result<(), NetworkError> {
+    pub async fn write(&self, key: &Key, value: &Value, meta: RecordMeta, durability: Durability) -> Result<(), NetworkError> {
         self.hashmap.write().unwrap().insert(key.clone(), value.to_owned());
+        self.meta.write().unwrap().insert(key.clone(), meta);
+        self.written.set(self.written.get() + 1);
         // sqlx::query("INSERT INTO kv_table(key, type, data) VALUES ($1, $2, $3)")
         //     .bind(key.as_str())
         //     .bind(value.discriminator())
         //     .bind(value.as_bytes())
         //     .execute(&self.pool)
         //     .await?;
-        self.save().await?;
+        self.persist(durability).await
+    }
+    /// Makes a write as durable as its class asks for.
+    async fn persist(&self, durability: Durability) -> Result<(), NetworkError> {
+        match durability {
+            Durability::Sync => self.commit(true).await,
+            Durability::Periodic => self.commit(false).await,
+            Durability::BestEffort => {
+                self.unsaved.set(true);
+                Ok(())
+            }
+        }
+    }
+    /// Writes out the changes made so far as part of a group commit.
+    ///
+    /// The writes waiting on a running checkpoint do not each write the
+    /// store again, the first of them to get its turn checkpoints for all
+    /// of them and the others find their changes already in the files.
+    /// With a group window the checkpoint first waits for it to pass, so
+    /// the writes arriving in the meantime join it as well.
+    async fn commit(&self, synced: bool) -> Result<(), NetworkError> {
+        let through = self.written.get();
+        if self.committed(synced, through) {
+            return Ok(());
+        }
+        let _checkpointing = self.checkpointing.lock().await;
+        if self.committed(synced, through) {
+            return Ok(());
+        }
+        let window = self.group_window.get();
+        if !window.is_zero() {
+            monoio::time::sleep(window).await;
+        }
+        self.checkpoint_locked(synced, |_| Ok(())).await
+    }
+    /// Whether the files hold the changes up to the count, synced if asked.
+    fn committed(&self, synced: bool, through: u64) -> bool {
+        if synced {
+            self.synced_through.get() >= through
+        } else {
+            self.saved_through.get() >= through
+        }
+    }
+    /// Sets how long a commit waits for more writes to join it before it
+    /// writes the store out, zero to only group the writes that wait on a
+    /// running checkpoint.
+    ///
+    /// Every synced or periodic write waits for the window at most once,
+    /// trading that much latency for fewer checkpoints under many writers.
+    pub fn set_group_window(&self, window: Duration) {
+        self.group_window.set(window);
+    }
+    /// How many checkpoints completed since the store was opened.
+    pub fn checkpoints(&self) -> u64 {
+        self.stats().flushes
+    }
+    /// How many changes the files do not hold yet and how long the
+    /// checkpoints writing them out took.
+    pub fn stats(&self) -> StorageSnapshot {
+        self.stats.snapshot(self.written.get() - self.saved_through.get())
+    }
+    /// How many bytes the files of the records and their metadata take on
+    /// disk, as of the last checkpoint.
+    pub fn file_size(&self) -> u64 {
+        [&self.location, &self.meta_location].into_iter()
+            .filter_map(|f| std::fs::metadata(f).ok())
+            .map(|f| f.len())
+            .sum()
+    }
+    /// Writes the records out to disk, without waiting for them to be synced.
+    ///
+    /// The files are never overwritten in place. The records and metadata go
+    /// to temporary files that are renamed over the old ones, the metadata
+    /// first, so a crash at any step reopens either the last checkpoint or
+    /// this one and never a mix of their records. A crash between the two
+    /// renames pairs the old records with the new metadata, which only
+    /// describes keys the records may not have yet.
+    pub async fn save(&self) -> Result<(), NetworkError> {
+        self.checkpoint(false, |_| Ok(())).await
+    }
+    /// Writes the records out and syncs them, the temporary files before
+    /// they are renamed and the directory after, so neither rename can
+    /// reach the disk ahead of the bytes it points to.
+    ///
+    /// `reached` is called after every [CheckpointStep], an error from it
+    /// stops the checkpoint there as a crash would.
+    pub async fn checkpoint<F>(&self, synced: bool, reached: F) -> Result<(), NetworkError>
+    where
+        F: Fn(CheckpointStep) -> Result<(), NetworkError>
+    {
+        let _checkpointing = self.checkpointing.lock().await;
+        self.checkpoint_locked(synced, reached).await
+    }
+    /// Runs a checkpoint, the caller holding the checkpointing lock.
+    async fn checkpoint_locked<F>(&self, synced: bool, reached: F) -> Result<(), NetworkError>
+    where
+        F: Fn(CheckpointStep) -> Result<(), NetworkError>
+    {
+        let through = self.written.get();
+        let started = Instant::now();
+        self.unsaved.set(true);
+        let records = bincode::serialize(&*self.hashmap.read().unwrap()).unwrap();
+        let meta = bincode::serialize(&*self.meta.read().unwrap()).unwrap();
+        let (records_temporary, meta_temporary) = (temporary(&self.location), temporary(&self.meta_location));
+        monoio::fs::write(&records_temporary, records).await.0?;
+        monoio::fs::write(&meta_temporary, meta).await.0?;
+        reached(CheckpointStep::WriteTemporary)?;
+        if synced {
+            for temporary in [&records_temporary, &meta_temporary] {
+                monoio::fs::File::open(temporary).await?.sync_all().await?;
+            }
+            reached(CheckpointStep::SyncTemporary)?;
+        }
+        std::fs::rename(&meta_temporary, &self.meta_location)?;
+        reached(CheckpointStep::RenameMeta)?;
+        std::fs::rename(&records_temporary, &self.location)?;
+        // The best effort writes made while the files were written wait for the next checkpoint.
+        self.unsaved.set(self.written.get() != through);
+        self.unsynced.set(true);
+        self.saved_through.set(through);
+        reached(CheckpointStep::RenameRecords)?;
+        if synced {
+            sync_directory(&self.location)?;
+            self.unsynced.set(false);
+            self.synced_through.set(through);
+            reached(CheckpointStep::SyncDirectory)?;
+        }
+        self.stats.record_flush(started.elapsed());
         Ok(())
     }
-    async fn save(&self) -> Result<(), NetworkError> {
-        let s= bincode::serialize(&*self.hashmap.read().unwrap()).unwrap();
-        let (r, a) = monoio::fs::write(&self.location, s).await;
-        r?;
+    /// Syncs what was written to the files to disk, along with the renames
+    /// that put them in place.
+    pub async fn sync(&self) -> Result<(), NetworkError> {
+        let through = self.saved_through.get();
+        self.unsynced.set(false);
+        for location in [&self.location, &self.meta_location] {
+            let file = monoio::fs::File::open(location).await?;
+            if let Err(e) = file.sync_all().await {
+                self.unsynced.set(true);
+                return Err(e.into());
+            }
+        }
+        if let Err(e) = sync_directory(&self.location) {
+            self.unsynced.set(true);
+            return Err(e.into());
+        }
+        self.synced_through.set(self.synced_through.get().max(through));
         Ok(())
     }
+    /// Makes the periodic and best effort writes durable, returning
+    /// whether there were any.
+    pub async fn sync_pending(&self) -> Result<bool, NetworkError> {
+        let pending = self.unsaved.get() || self.unsynced.get();
+        if self.unsaved.get() {
+            self.save().await?;
+        }
+        if self.unsynced.get() {
+            self.sync().await?;
+        }
+        Ok(pending)
+    }
     
-    pub async fn update(&self, key: &Key, value: Value) -> Result<(), NetworkError> {
-        self.write(key, &value).await?;
+    pub async fn update(&self, key: &Key, value: Value, meta: RecordMeta, durability: Durability) -> Result<(), NetworkError> {
+        self.write(key, &value, meta, durability).await?;
         // sqlx::query("UPDATE kv_table SET type = $1, data = $2  WHERE key = $3")
         //     .bind(value.discriminator())
         //     .bind(value.as_bytes())
@@ -66,18 +288,60 @@ impl DatabaseStorage {
         Ok(())
 
     }
-    pub async fn delete(&self, key: &Key) -> Result<(), NetworkError> {
+    pub async fn delete(&self, key: &Key, durability: Durability) -> Result<(), NetworkError> {
         // sqlx::query("DELETE FROM kv_table WHERE key = $1")
         //     .bind(key.as_str())
         //     .execute(&self.pool)
         //     .await?;
         self.hashmap.write().unwrap().remove(key);
-        self.save().await?;
-        Ok(())
+        self.meta.write().unwrap().remove(key);
+        self.written.set(self.written.get() + 1);
+        self.persist(durability).await
+    }
+    /// Applies a batch of changes and writes them out once.
+    ///
+    /// An insert takes its metadata from the map, a key missing from it
+    /// keeps the metadata it had.
+    pub async fn apply(&self, changes: Vec<Change>, metas: &HashMap<Key, RecordMeta>, durability: Durability) -> Result<(), NetworkError> {
+        {
+            let mut map = self.hashmap.write().unwrap();
+            let mut meta = self.meta.write().unwrap();
+            for change in changes {
+                match change {
+                    Change::Insert(key, value) => {
+                        if let Some(stamped) = metas.get(&key) {
+                            meta.insert(key.clone(), *stamped);
+                        }
+                        map.insert(key, value);
+                    }
+                    Change::Delete(key) => {
+                        meta.remove(&key);
+                        map.remove(&key);
+                    }
+                }
+            }
+        }
+        self.written.set(self.written.get() + 1);
+        self.persist(durability).await
     }
     pub async fn records(&self) -> Vec<(Key, Value)> {
         self.hashmap.read().unwrap().iter().map(|f| (f.0.clone(), f.1.clone())).collect()
     }
+    /// Marks the record of the key as read at the time. A read changes no
+    /// record, so this is written out with the next change.
+    pub fn touch(&self, key: &Key, at: u64) {
+        if let Some(meta) = self.meta.write().unwrap().get_mut(key) {
+            meta.accessed_at = meta.accessed_at.max(at);
+        }
+    }
+    /// The metadata of every record that has it.
+    pub fn record_metas(&self) -> HashMap<Key, RecordMeta> {
+        self.meta.read().unwrap().clone()
+    }
+    /// Replaces the metadata of the records, used when a store is copied.
+    pub fn restore_metas(&self, metas: HashMap<Key, RecordMeta>) {
+        *self.meta.write().unwrap() = metas;
+    }
     // pub async fn read(&self) -> Result<Vec<StoredRecord>, NetworkError> {
     //     let rows = sqlx::query("SELECT * FROM kv_table;")
     //         .fetch_all(&self.pool).await?
@@ -88,6 +352,26 @@ impl DatabaseStorage {
 }
 
 
+/// Where a checkpoint writes the file before it is renamed over it.
+pub(crate) fn temporary(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Syncs the directory of the file, which is where its renames are recorded.
+pub(crate) fn sync_directory(path: &Path) -> std::io::Result<()> {
+    // Directories cannot be opened for syncing everywhere.
+    if !cfg!(unix) {
+        return Ok(());
+    }
+    match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => std::fs::File::open(directory)?.sync_all(),
+        _ => std::fs::File::open(".")?.sync_all()
+    }
+}
+
+
 // fn read_sqliterow(row: SqliteRow) -> Result<StoredRecord, NetworkError> {
 //     let r = row.get::<String, _>(1);
 //     let v_type = row.get::<i64, _>(2);
@@ -118,9 +402,99 @@ impl DatabaseStorage {
 #[cfg(test)]
 mod tests {
 
-    use overseer::models::{Key, Value};
+    use overseer::{error::{NetworkError, StorageError}, models::{Durability, Key, RecordMeta, Value}};
 
-    use crate::database::DatabaseStorage;
+    use crate::database::{CheckpointStep, DatabaseStorage};
+
+    #[monoio::test]
+    pub async fn test_durability_classes() {
+        let tf = tempfile::tempdir().unwrap();
+        let storage = DatabaseStorage::new(tf.path(), "db").await.unwrap();
+        let meta = RecordMeta::stamp(None, 1_700_000_000_000, Some(3));
+        storage.write(&Key::from_str("telemetry.cpu"), &Value::Integer(3), meta, Durability::BestEffort).await.unwrap();
+
+        // A best effort write waits for the next periodic flush.
+        assert!(DatabaseStorage::new(tf.path(), "db").await.unwrap().records().await.is_empty());
+        assert!(storage.sync_pending().await.unwrap());
+        assert_eq!(DatabaseStorage::new(tf.path(), "db").await.unwrap().records().await.len(), 1);
+        assert!(!storage.sync_pending().await.unwrap());
+
+        // The metadata is kept next to the records.
+        let reopened = DatabaseStorage::new(tf.path(), "db").await.unwrap();
+        assert_eq!(reopened.record_metas().get(&Key::from_str("telemetry.cpu")), Some(&meta));
+
+        storage.write(&Key::from_str("billing.plan"), &Value::Integer(1), meta, Durability::Sync).await.unwrap();
+        assert_eq!(DatabaseStorage::new(tf.path(), "db").await.unwrap().records().await.len(), 2);
+        assert!(!storage.sync_pending().await.unwrap());
+
+        storage.delete(&Key::from_str("billing.plan"), Durability::Periodic).await.unwrap();
+        assert_eq!(DatabaseStorage::new(tf.path(), "db").await.unwrap().records().await.len(), 1);
+        assert!(storage.sync_pending().await.unwrap());
+    }
+
+    #[monoio::test]
+    pub async fn test_checkpoint_recovery() {
+        let steps = [
+            CheckpointStep::WriteTemporary,
+            CheckpointStep::SyncTemporary,
+            CheckpointStep::RenameMeta,
+            CheckpointStep::RenameRecords,
+            CheckpointStep::SyncDirectory
+        ];
+        let (old, new) = (Key::from_str("app.old"), Key::from_str("app.new"));
+        let meta = RecordMeta::stamp(None, 1_700_000_000_000, Some(1));
+        for step in steps {
+            let tf = tempfile::tempdir().unwrap();
+            let storage = DatabaseStorage::new(tf.path(), "db").await.unwrap();
+            storage.write(&old, &Value::Integer(1), meta, Durability::Sync).await.unwrap();
+            storage.write(&new, &Value::Integer(2), meta, Durability::BestEffort).await.unwrap();
+
+            // The checkpoint crashes right after the step.
+            let crashed = storage.checkpoint(true, |reached| match reached == step {
+                true => Err(std::io::Error::other("simulated crash").into()),
+                false => Ok(())
+            }).await;
+            assert!(crashed.is_err());
+
+            let reopened = DatabaseStorage::new(tf.path(), "db").await.unwrap();
+            let mut keys: Vec<String> = reopened.records().await.into_iter().map(|(key, _)| key.as_str().to_string()).collect();
+            keys.sort();
+            let expected = match step >= CheckpointStep::RenameRecords {
+                true => vec!["app.new", "app.old"],
+                false => vec!["app.old"]
+            };
+            assert_eq!(keys, expected, "Crashed after {step:?}.");
+            assert_eq!(reopened.record_metas().contains_key(&new), step >= CheckpointStep::RenameMeta, "Crashed after {step:?}.");
+            // Nothing of the crashed checkpoint is left behind.
+            let names: Vec<_> = std::fs::read_dir(tf.path()).unwrap().map(|f| f.unwrap().file_name()).collect();
+            assert_eq!(names.len(), 2, "Crashed after {step:?}: {names:?}.");
+        }
+
+        // A temporary file torn part way through its write is thrown away.
+        let tf = tempfile::tempdir().unwrap();
+        let storage = DatabaseStorage::new(tf.path(), "db").await.unwrap();
+        storage.write(&old, &Value::Integer(1), meta, Durability::Sync).await.unwrap();
+        std::fs::write(tf.path().join("db.tmp"), [0xff, 0x01]).unwrap();
+        let reopened = DatabaseStorage::new(tf.path(), "db").await.unwrap();
+        assert_eq!(reopened.records().await.len(), 1);
+        assert!(!tf.path().join("db.tmp").exists());
+    }
+
+    #[monoio::test]
+    pub async fn test_corrupt_files() {
+        for file in ["db", "db.meta"] {
+            let tf = tempfile::tempdir().unwrap();
+            let storage = DatabaseStorage::new(tf.path(), "db").await.unwrap();
+            let meta = RecordMeta::stamp(None, 1_700_000_000_000, Some(1));
+            storage.write(&Key::from_str("app.mode"), &Value::Integer(1), meta, Durability::Sync).await.unwrap();
+            let location = tf.path().join(file);
+            let contents = std::fs::read(&location).unwrap();
+            std::fs::write(&location, &contents[..contents.len() - 3]).unwrap();
+
+            let opened = DatabaseStorage::new(tf.path(), "db").await;
+            assert!(matches!(opened, Err(NetworkError::Storage(StorageError::Corrupt(..)))), "Truncated {file}.");
+        }
+    }
 
 
     // #[tokio::test]
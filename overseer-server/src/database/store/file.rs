@@ -1,9 +1,9 @@
-use std::{fmt::UpperHex, io, path::Path};
+use std::{path::Path, time::Instant};
 
 use monoio::fs::{File, OpenOptions};
-use overseer::{error::NetworkError, models::{asynctrait, IoBufferMut, LocalReadAsync}};
+use overseer::{error::{NetworkError, ProtocolError, StorageError}, models::{compress_block, decompress_block, LocalReadAsync}};
 
-use super::paging::{meta::{PageType, RawPageAddress}, page::{Page, PageReference}};
+use super::{paging::{meta::RawPageAddress, page::{Page, PageReference}}, stats::{StorageSnapshot, StorageStats}};
 
 
 
@@ -15,11 +15,19 @@ pub const RESERVED_HEADER_SIZE: u32 = 4096;
 pub const PAGE_HEADER_RESERVED_BYTES: u32 = 1 + 4 + 4 + 1; // Is free + Previous
 // pub const PAGE_FOOTER_RESERVED_BYTES: u32 = 4;
 
+/// Payloads larger than this are compressed as LZ4 blocks.
+const BLOCK_PAYLOAD_THRESHOLD: usize = 512;
+/// Marks a payload that is stored as is.
+const PAYLOAD_RAW: u8 = 0;
+/// Marks a payload stored as an LZ4 block, preceded by its length as a little endian `u32`.
+const PAYLOAD_BLOCK: u8 = 1;
+
 pub struct PagedFile {
     underlying: File,
     file_size: u64,
     is_initialized: bool,
-    free_list: Vec<RawPageAddress>
+    free_list: Vec<RawPageAddress>,
+    stats: StorageStats
 }
 
 
@@ -54,7 +62,8 @@ impl PagedFile {
             underlying: file,
             file_size: size,
             is_initialized: size != 0,
-            free_list: Vec::new()
+            free_list: Vec::new(),
+            stats: StorageStats::default()
         };
         
         
@@ -116,7 +125,7 @@ impl PagedFile {
     }
     pub async fn acquire(&self, page: u32) -> Result<Page, NetworkError> {
         if page >= self.pages() {
-            Err(NetworkError::PageOutOfBounds)?;
+            Err(NetworkError::Storage(StorageError::PageOutOfBounds))?;
         }
         let addr = RawPageAddress::new((RESERVED_HEADER_SIZE) + ((page) * PAGE_SIZE as u32));
         let reference = PageReference::new(addr, PAGE_SIZE as u32);
@@ -124,19 +133,20 @@ impl PagedFile {
 
         let acked = reference.load(self).await?;
         if acked.metadata.free {
-            return Err(NetworkError::PageFreedError);
+            return Err(NetworkError::Storage(StorageError::PageFreedError));
         }
         Ok(acked)
     }
+    #[tracing::instrument(level = "trace", skip(self), fields(free = self.free_list.len()))]
     pub async fn new_page(&mut self) -> Result<Page, NetworkError>
     {
         if self.free_list.is_empty() {
             // If the free list is empty we have to make a new page from scratch.
-            // println!("FOCA");
+            tracing::trace!("Growing the file by a page.");
             self.reserve(RawPageAddress::new((RESERVED_HEADER_SIZE + (self.pages() * (PAGE_SIZE as u32))) as u32), PAGE_SIZE as u32, None).await
         } else {
             // Let us reuse a page.
-            // println!("FOCB");
+            tracing::trace!("Reusing a free page.");
             let to_use = self.free_list.pop().unwrap();
             self.reserve(to_use, PAGE_SIZE as u32, None).await
         }
@@ -163,9 +173,44 @@ impl PagedFile {
         Ok(page)
     }
     pub async fn sync(&self) -> Result<(), NetworkError> {
+        let start = Instant::now();
         self.underlying.sync_all().await?;
+        self.stats.record_flush(start.elapsed());
         Ok(())
     }
+    /// Encodes a record payload, compressing it as a block if it is large
+    /// and that makes it smaller.
+    pub fn compress_payload(&self, payload: &[u8]) -> Vec<u8> {
+        if payload.len() > BLOCK_PAYLOAD_THRESHOLD {
+            let block = compress_block(payload);
+            if block.len() + 4 < payload.len() {
+                let mut encoded = Vec::with_capacity(block.len() + 5);
+                encoded.push(PAYLOAD_BLOCK);
+                encoded.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+                encoded.extend(block);
+                return encoded;
+            }
+        }
+        let mut encoded = Vec::with_capacity(payload.len() + 1);
+        encoded.push(PAYLOAD_RAW);
+        encoded.extend_from_slice(payload);
+        encoded
+    }
+    /// Decodes a payload written by [PagedFile::compress_payload].
+    pub fn decompress_payload(&self, encoded: &[u8]) -> Result<Vec<u8>, NetworkError> {
+        match encoded.split_first() {
+            Some((&PAYLOAD_RAW, payload)) => Ok(payload.to_vec()),
+            Some((&PAYLOAD_BLOCK, payload)) if payload.len() >= 4 => {
+                let (length, block) = payload.split_at(4);
+                decompress_block(block, u32::from_le_bytes(length.try_into()?) as usize)
+            }
+            _ => Err(NetworkError::Protocol(ProtocolError::DecompressionFailed))
+        }
+    }
+    /// Gets the flush counters, the pages are written through so none are dirty.
+    pub fn stats(&self) -> StorageSnapshot {
+        self.stats.snapshot(0)
+    }
 }
 
 async fn format_pagefile_header(file: &PagedFile, page: Page) -> Result<(), NetworkError>
@@ -182,14 +227,12 @@ async fn format_pagefile_header(file: &PagedFile, page: Page) -> Result<(), Netw
     Ok(())
 }
 
-
-
 #[cfg(test)]
 mod tests {
-    use overseer::error::NetworkError;
+    use overseer::error::{NetworkError, StorageError};
     use tempfile::tempdir;
 
-    use crate::database::store::file::{PageType, RawPageAddress, PAGE_SIZE, RESERVED_HEADER_SIZE};
+    use crate::database::store::{file::{PAGE_SIZE, RESERVED_HEADER_SIZE}, paging::meta::PageType};
 
     use super::PagedFile;
 
@@ -270,7 +313,7 @@ mod tests {
 
         // Error if we forcibly acquire a freedpage
         page.free(&mut paged).await.unwrap();
-        assert!(matches!(paged.acquire(0).await.err().unwrap(), NetworkError::PageFreedError));
+        assert!(matches!(paged.acquire(0).await.err().unwrap(), NetworkError::Storage(StorageError::PageFreedError)));
     }
 
     #[monoio::test]
@@ -281,7 +324,6 @@ mod tests {
 
         assert_eq!(page.start().page_number(), 0);
         assert!(!page.has_next());
-        println!("Page previous: {:?}", page.metadata.previous);
         assert!(page.metadata.previous.is_zero());
 
         let chain = page.get_next(&mut paged).await.unwrap();
@@ -299,6 +341,36 @@ mod tests {
         
     }
 
+    #[monoio::test]
+    pub async fn flush_stats() {
+        let dir = tempdir().unwrap();
+        let mut paged = PagedFile::open(dir.path().join("hello.txt")).await.unwrap();
+        paged.new_page().await.unwrap();
+
+        paged.sync().await.unwrap();
+        assert_eq!(paged.stats().flushes, 1);
+        assert_eq!(paged.stats().dirty, 0);
+    }
+
+    #[monoio::test]
+    pub async fn test_payload_encoding() {
+        let dir = tempdir().unwrap();
+        let paged = PagedFile::open(dir.path().join("hello.txt")).await.unwrap();
+
+        // Small payloads are stored as they are.
+        let encoded = paged.compress_payload(b"xyz");
+        assert_eq!(encoded, b"\0xyz");
+        assert_eq!(paged.decompress_payload(&encoded).unwrap(), b"xyz");
+
+        // Large payloads are compressed on their own.
+        let large: Vec<u8> = (0..64).flat_map(|i| format!("{{\"name\":\"worker-{i}\",\"enabled\":true}}").into_bytes()).collect();
+        let encoded = paged.compress_payload(&large);
+        assert!(encoded.len() * 2 < large.len());
+        assert_eq!(paged.decompress_payload(&encoded).unwrap(), large);
+        assert!(paged.decompress_payload(&encoded[..3]).is_err());
+        assert!(paged.decompress_payload(&[7, 1, 2]).is_err());
+    }
+
     #[monoio::test]
     pub async fn test_header_consistency() {
 
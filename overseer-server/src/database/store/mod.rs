@@ -1,3 +1,7 @@
 mod file;
 mod paging;
-mod alloc;
\ No newline at end of file
+mod alloc;
+mod stats;
+
+pub use crate::database::store::stats::{StorageStats, StorageSnapshot};
+pub use crate::database::store::file::PAGE_SIZE;
@@ -2,7 +2,7 @@
 
 use std::array::TryFromSliceError;
 
-use overseer::error::NetworkError;
+use overseer::error::{NetworkError, StorageError};
 use thiserror::Error;
 
 
@@ -36,14 +36,14 @@ impl PageError {
     }
 }
 
-// impl Into<NetworkError> for PageError {
-//     fn into(self) -> NetworkError {
-//         NetworkError::PagingError(format!("{self:?}"))
-//     }
-// }
+impl From<PageError> for StorageError {
+    fn from(value: PageError) -> Self {
+        StorageError::Page(value.to_string())
+    }
+}
 
 impl From<PageError> for NetworkError {
     fn from(value: PageError) -> Self {
-        value.into()
+        StorageError::from(value).into()
     }
-}
\ No newline at end of file
+}
@@ -5,9 +5,9 @@
 //! The fragmented bytes counts not only genuinely fragmented memory but also "detractive" memory which is that
 //! which may be considered unnecessary, such as free list allocations.
 
-use std::{io::Cursor, process::exit};
+use std::io::Cursor;
 
-use overseer::{error::NetworkError, models::{Key, Value}, network::{OverseerSerde, OvrInteger}};
+use overseer::{models::{Key, Value}, network::{OverseerSerde, OvrInteger}};
 
 
 use crate::database::store::file::{PagedFile, PAGE_HEADER_RESERVED_BYTES, PAGE_SIZE};
@@ -25,6 +25,7 @@ pub struct Leaf;
 //     inner: Page
 // }
 
+#[derive(OverseerSerde)]
 pub struct Record {
     key: Key,
     value: Option<Value>
@@ -43,12 +44,18 @@ impl Record {
             value
         }
     }
-    pub async fn produce(self) -> SerializedRecord {
+    /// Serializes the record, the key as it is so the page can be searched
+    /// and the value as a payload encoded by the file, see [PagedFile::compress_payload].
+    pub async fn produce(self, file: &PagedFile) -> SerializedRecord {
         let mut cursor = Cursor::new(vec![]);
-        self.serialize(&mut cursor).await.unwrap();
+        self.key.serialize(&mut cursor).await.unwrap();
+        let mut value = Cursor::new(vec![]);
+        self.value.as_ref().serialize(&mut value).await.unwrap();
+        let mut data = cursor.into_inner();
+        data.extend(file.compress_payload(&value.into_inner()));
         SerializedRecord {
             value: self,
-            data: cursor.into_inner()
+            data
         }
     }
 }
@@ -63,25 +70,6 @@ impl SerializedRecord {
 }
 
 
-impl OverseerSerde<Record> for Record {
-    type E = NetworkError;
-    async fn deserialize<R: overseer::models::LocalReadAsync>(reader: &mut R) -> Result<Record, Self::E> {
-        let key = Key::deserialize(reader).await?;
-        let val = Option::<&Value>::deserialize(reader).await?;
-        Ok(Self {
-            key: key,
-            value: val
-        })
-    }
-    async fn serialize<W: overseer::models::LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
-        self.key.serialize(writer).await?;
-        self.value.as_ref().serialize(writer).await?;
-        Ok(())
-    }
-}
-
-
-
 struct Allocation {
     location: usize,
     /// The free block bytes in case we need to zero them.
@@ -161,8 +149,6 @@ impl Projection<Leaf> {
     pub fn get_offset(&self, index: usize) -> usize {
         let pos = Self::calculate_offset_index(index);
 
-        // let unitary = u16::from_le_bytes(self[pos .. pos + 2].try_into().unwrap()) as usize;
-        // if index == 2 { println!("2 {unitary}"); exit(1) };
         u16::from_le_bytes(self[pos .. pos + 2].try_into().unwrap()) as usize
     }
     /// Checks if a record will fit into the database.
@@ -240,14 +226,14 @@ impl Projection<Leaf> {
         } else {
             let offset = self.get_offset(index);
             let mut reader = self.reader(offset as usize);
-            OvrInteger::read::<usize, _>(&mut reader).await?;
+            OvrInteger::read::<usize, _>(&mut reader).await.map_err(|_| PageError::RecordDeserializationFailure)?;
             Ok(Key::deserialize(&mut reader).await.map_err(|_| PageError::RecordDeserializationFailure)?)
         }
     }
 
     /// Find suitable free block.
     fn find_free_slot(&self, size: usize) -> Option<Allocation> {
-        println!("Finding a free block... for size {}", size);
+        tracing::trace!("Finding a free block... for size {}", size);
         let star = self.get_free_block_ptr();
         if star == 0 {
             // We do not even have a running free block so how
@@ -282,7 +268,7 @@ impl Projection<Leaf> {
     }
 
     /// Reads a record if it exists.
-    pub async fn read_record(&self, index: usize) -> Result<Option<Record>, PageError>
+    pub async fn read_record(&self, file: &PagedFile, index: usize) -> Result<Option<Record>, PageError>
     {
         if index >= self.get_cell_count() {
             return Ok(None);
@@ -290,13 +276,18 @@ impl Projection<Leaf> {
             // Calculate the record offset.
             let offset = self.get_offset(index);
 
-            let mut reader = self.reader(offset as usize);
             // read the size first.
-            OvrInteger::read::<usize, _>(&mut reader).await?;
-            // now deserialize the record.
-            let record = Record::deserialize(&mut reader).await.map_err(|_| PageError::RecordDeserializationFailure)?;
+            let size = OvrInteger::read_slice::<usize>(&self[offset..]).map_err(|_| PageError::RecordDeserializationFailure)?;
+            let start = offset + OvrInteger::required_space(size);
+            let data = self[start..].get(..size).ok_or(PageError::RecordDeserializationFailure)?;
+
+            // now deserialize the key and then decode the value after it.
+            let mut reader = Cursor::new(data);
+            let key = Key::deserialize(&mut reader).await.map_err(|_| PageError::RecordDeserializationFailure)?;
+            let payload = file.decompress_payload(&data[reader.position() as usize..]).map_err(|_| PageError::RecordDeserializationFailure)?;
+            let value = Option::<&Value>::deserialize(&mut Cursor::new(payload)).await.map_err(|_| PageError::RecordDeserializationFailure)?;
 
-            Ok(Some(record))
+            Ok(Some(Record { key, value }))
         }
     }
 
@@ -384,8 +375,8 @@ impl Transact<Leaf> {
         let offset = Projection::<Leaf>::header_size() + offset_index * 2;
         self[offset..offset + 2].copy_from_slice(&(record_ptr as u16).to_le_bytes());
     }
-    pub async fn write_record(&mut self, record: Record) -> Result<(), PageError> {
-        self.write_serialized_record(record.produce().await).await
+    pub async fn write_record(&mut self, file: &PagedFile, record: Record) -> Result<(), PageError> {
+        self.write_serialized_record(record.produce(file).await).await
     }
 
     pub async fn find_record_offset_position(&mut self, record: &SerializedRecord) -> Result<usize, PageError> {
@@ -395,18 +386,18 @@ impl Transact<Leaf> {
             return Ok(0);
         } 
 
-        println!("INSERTING: {:?}", record.value.key);
+        tracing::trace!("INSERTING: {:?}", record.value.key);
         //https://stackoverflow.com/questions/75692519/how-to-implement-binary-search-in-rust-with-usize-indexes
         let mut right = cells;
         
         let mut left = 0;
-        println!("yu");
+        tracing::trace!("yu");
         while left < right {
             let midpoint = left + (right - left) / 2;
-            println!("There are {cells} cells which gives us a midpoint at {midpoint}");
+            tracing::trace!("There are {cells} cells which gives us a midpoint at {midpoint}");
             let mid_key = self.read_key(midpoint).await?;
 
-            println!("The key at the midpoint is {midpoint} the comparison result is {:?}", record.value.key.cmp(&mid_key));
+            tracing::trace!("The key at the midpoint is {midpoint} the comparison result is {:?}", record.value.key.cmp(&mid_key));
 
             if record.value.key == mid_key {
 
@@ -420,7 +411,7 @@ impl Transact<Leaf> {
             
         }
 
-        println!("FAILED {left}");
+        tracing::trace!("FAILED {left}");
         
         
 
@@ -438,11 +429,11 @@ impl Transact<Leaf> {
         let start = Projection::<Leaf>::calculate_offset_index(position);
         let end = Projection::<Leaf>::calculate_offset_index(cells);
 
-        println!("View: {:?}", &self[..40]);
+        tracing::trace!("View: {:?}", &self[..40]);
         self[start..end + 2 + 2].rotate_right(2);
-        println!("View (1): {:?}", &self[..40]);
+        tracing::trace!("View (1): {:?}", &self[..40]);
         self[start..start + 2].copy_from_slice(&(pointer as u16).to_le_bytes());
-        println!("View (2): {:?}", &self[..40]);
+        tracing::trace!("View (2): {:?}", &self[..40]);
 
         Ok(position)
     }
@@ -457,17 +448,15 @@ impl Transact<Leaf> {
         // Find a new record pointer.
         let record_allocation = self.find_new_record_ptr(&record).ok_or_else(|| PageError::LeafPageFull)?;
         let record_ptr = record_allocation.location;
-        println!("Record Ptr: {}", record_ptr);
+        tracing::trace!("Record Ptr: {}", record_ptr);
 
         // let total_usage = 2 + record.total_serialized_size();
         
-        let size = OvrInteger::to_bytes(record.data.len()).await;
+        let size = OvrInteger::to_bytes(record.data.len());
 
         // Update the free space.
         
-        // println!("Writing new space {}", self.get_used_space() + (2 + record.total_serialized_size()));
         // self.set_used_space(self.get_used_space() + (2 + record.total_serialized_size()));
-        // println!("Writing new space {}", self.get_used_space() + (2 + record.total_serialized_size()));
 
         // Write the record.
         self[record_ptr..record_ptr + size.len()].copy_from_slice(&size);
@@ -524,7 +513,7 @@ impl Transact<Leaf> {
                 // We have used it up, so this is empty.
                 FreeBlock::write(self, fb.position, fb.next as usize, 0, 0);
             } else {
-                println!("Allocation size: {}, Total: {}", allocation.size, fb.size);
+                tracing::trace!("Allocation size: {}, Total: {}", allocation.size, fb.size);
                 // We have not used this up, so it is not empty.
                 self.adjust_free_block(fb.offset as usize + allocation.size, fb.size as usize - allocation.size, fb);
                 // FreeBlock::write(self, fb.position, fb.next as usize, , fb.size as usize - allocation.size);
@@ -561,7 +550,7 @@ impl Transact<Leaf> {
             self.set_free_ptr(new_ptr);
         } else {
             // We do have a free chain.
-            println!("Descending down chain");
+            tracing::trace!("Descending down chain");
             self.update_free_chain(None, free_list_pointer, start, size)?;
         }
 
@@ -579,7 +568,7 @@ impl Transact<Leaf> {
         self.solve_allocate(allocate)?;
 
         // increase the fragmented size
-        println!("Before updating the fragmentation it was {}", self.get_fragmented());
+        tracing::trace!("Before updating the fragmentation it was {}", self.get_fragmented());
         self.set_fragmented(self.get_fragmented() + FreeBlock::size());
         Ok(location)
     }
@@ -593,8 +582,6 @@ impl Transact<Leaf> {
             Ok(())
         }
         else if (start + size) == current.offset as usize {
-            // println!("LEFT EDGE...");
-            // exit(1);
             // Solves a left edge.
             //
             // Essentially, if there are two open spaces next to eachoher it is more efficient to combine them.
@@ -607,21 +594,20 @@ impl Transact<Leaf> {
             // open space.
             // We just extend this block.
             FreeBlock::write(self, current.position, current.next as usize, current.offset as usize, current.size as usize + size);
-            // println!("SOLVED EGDE");
             Ok(())
         } else if current.next == 0 {
-            println!("Descending allocating new blocck w/ {}", size);
+            tracing::trace!("Descending allocating new blocck w/ {}", size);
             // End of the chain, allocate a new block
 
             if size <= FRAGMENTATION_SIZE {
                 // Not worth it.
-                println!("NOT WORTH IT1");
+                tracing::trace!("NOT WORTH IT1");
                 self.set_fragmented(self.get_fragmented() + size);
                 return Ok(());
             }
             
             let new_block = self.allocate_free_block(start, size)?;
-            println!("New pointer for {:?} is {new_block} fragged, {}", previous, self.get_fragmented());
+            tracing::trace!("New pointer for {:?} is {new_block} fragged, {}", previous, self.get_fragmented());
             
             // Update the previous block.
             FreeBlock::write(self, current.position, new_block, current.offset as usize, current.size as usize);
@@ -629,7 +615,7 @@ impl Transact<Leaf> {
             
             Ok(())
         } else {
-            println!("Descending with size {}", size);
+            tracing::trace!("Descending with size {}", size);
             // Continue down the chain recursively.
             self.update_free_chain(Some(current), next_ptr as usize, start, size)
         }
@@ -638,7 +624,7 @@ impl Transact<Leaf> {
     
    
 
-    pub async fn defragment(&mut self) -> Result<(), PageError> {
+    pub async fn defragment(&mut self, file: &PagedFile) -> Result<(), PageError> {
         let copy = self.virtualize();
 
         // We format ourself.
@@ -647,8 +633,8 @@ impl Transact<Leaf> {
 
         for i in 0..copy.get_cell_count() {
             // Write over all the record data.
-            let read_record = copy.read_record(i).await?.unwrap();
-            self.write_record(read_record).await?;
+            let read_record = copy.read_record(file, i).await?.unwrap();
+            self.write_record(file, read_record).await?;
         }
         
 
@@ -664,8 +650,6 @@ impl Transact<Leaf> {
     fn simple_delete(&mut self, record: usize) -> Result<(), PageError> {
         
         if !self.check_record_exists(record) {
-            // println!("DELETING RECORD E: {record}");
-            // if record == 2 { exit(1) };
             return Err(PageError::NoRecordFound)?;
         }
         
@@ -707,7 +691,7 @@ impl Transact<Leaf> {
         }
         
         // Update the free list. ONLY if the size is
-        println!("Making call to UFL {} {}", size, self.get_fragmented());
+        tracing::trace!("Making call to UFL {} {}", size, self.get_fragmented());
         self.update_free_list(offset, size)?;
         
 
@@ -733,11 +717,11 @@ mod tests {
 
     // TODO: Add unit tests to test mid-write failure.
 
-    async fn make_test_record<K: Into<Key>>(key: K, val: Option<Value>) -> (usize, SerializedRecord) {
+    async fn make_test_record<K: Into<Key>>(file: &PagedFile, key: K, val: Option<Value>) -> (usize, SerializedRecord) {
         let rec = Record {
             key: key.into(),
             value: val
-        }.produce().await;
+        }.produce(file).await;
         let size = rec.total_serialized_size();
         (size, rec)
     }
@@ -748,11 +732,11 @@ mod tests {
         let mut paged = PagedFile::open(dir.path().join("hello.txt")).await?;
         paged.new_page().await?.leaf().open(&paged, async |leaf: &mut Transact<Leaf>| {
             
-            let (_, rec_a) = make_test_record("a", Some(Value::Integer(339939393))).await;
-            let (_, rec_b) = make_test_record("b", Some(Value::Integer(332))).await;
-            let (_, rec_c) = make_test_record("c", Some(Value::Integer(83920320039092))).await;
-            let (_, rec_d) = make_test_record("d", Some(Value::Integer(83920320039092))).await;
-            let (_, rec_e) = make_test_record("e", Some(Value::Integer(83920320039092))).await;
+            let (_, rec_a) = make_test_record(&paged, "a", Some(Value::Integer(339939393))).await;
+            let (_, rec_b) = make_test_record(&paged, "b", Some(Value::Integer(332))).await;
+            let (_, rec_c) = make_test_record(&paged, "c", Some(Value::Integer(83920320039092))).await;
+            let (_, rec_d) = make_test_record(&paged, "d", Some(Value::Integer(83920320039092))).await;
+            let (_, rec_e) = make_test_record(&paged, "e", Some(Value::Integer(83920320039092))).await;
             
             
             
@@ -783,11 +767,11 @@ mod tests {
         let mut paged = PagedFile::open(dir.path().join("hello.txt")).await?;
         paged.new_page().await?.leaf().open(&paged, async |leaf: &mut Transact<Leaf>| {
             
-            let (_, rec_a) = make_test_record("a", Some(Value::Integer(339939393))).await;
-            let (_, rec_b) = make_test_record("b", Some(Value::Integer(332))).await;
-            let (_, rec_c) = make_test_record("c", Some(Value::Integer(83920320039092))).await;
-            let (_, rec_d) = make_test_record("d", Some(Value::Integer(83920320039092))).await;
-            let (_, rec_e) = make_test_record("e", Some(Value::Integer(83920320039092))).await;
+            let (_, rec_a) = make_test_record(&paged, "a", Some(Value::Integer(339939393))).await;
+            let (_, rec_b) = make_test_record(&paged, "b", Some(Value::Integer(332))).await;
+            let (_, rec_c) = make_test_record(&paged, "c", Some(Value::Integer(83920320039092))).await;
+            let (_, rec_d) = make_test_record(&paged, "d", Some(Value::Integer(83920320039092))).await;
+            let (_, rec_e) = make_test_record(&paged, "e", Some(Value::Integer(83920320039092))).await;
             
             leaf.write_serialized_record(rec_a).await?;
             leaf.write_serialized_record(rec_c).await?;
@@ -824,23 +808,22 @@ mod tests {
         paged.new_page().await?.leaf().open(&paged, async |leaf: &mut Transact<Leaf>| {
 
             
-            let (z_size, z_rec) = make_test_record("a", Some(Value::Integer(1))).await;
-            let (a_size, a_rec) = make_test_record("b", Some(Value::Integer(339939393))).await;
-            let (b_size, b_rec) = make_test_record("c", Some(Value::Integer(332))).await;
-            let (c_size, c_rec) = make_test_record("d", Some(Value::Integer(83920320039092))).await;
+            let (z_size, z_rec) = make_test_record(&paged, "a", Some(Value::Integer(1))).await;
+            let (a_size, a_rec) = make_test_record(&paged, "b", Some(Value::Integer(339939393))).await;
+            let (b_size, b_rec) = make_test_record(&paged, "c", Some(Value::Integer(332))).await;
+            let (c_size, c_rec) = make_test_record(&paged, "d", Some(Value::Integer(83920320039092))).await;
             
             leaf.write_serialized_record(z_rec).await?;
             leaf.write_serialized_record(a_rec).await?;
             leaf.write_serialized_record(b_rec).await?;
             leaf.write_serialized_record(c_rec).await?;
             
-            // println!("hello: {:?}", leaf.view());
 
         
             leaf.simple_delete(2)?;
 
-            assert_eq!(leaf.read_record(1).await?.unwrap().value, Some(Value::Integer(339939393)));
-            assert_eq!(leaf.read_record(2).await?.unwrap().value, Some(Value::Integer(83920320039092)));
+            assert_eq!(leaf.read_record(&paged, 1).await?.unwrap().value, Some(Value::Integer(339939393)));
+            assert_eq!(leaf.read_record(&paged, 2).await?.unwrap().value, Some(Value::Integer(83920320039092)));
             assert_eq!(leaf.get_fragmented(), FreeBlock::size()); // make sure we have some fragmentation + a freeblock
             
             // This should be far too small to put in the free list.
@@ -849,17 +832,17 @@ mod tests {
             
 
             // We may now defrag.
-            leaf.defragment().await?;
+            leaf.defragment(&paged).await?;
 
             assert_eq!(leaf.get_fragmented(), 0);
-            assert_eq!(leaf.read_record(0).await?.unwrap().value, Some(Value::Integer(339939393)));
-            assert_eq!(leaf.read_record(1).await?.unwrap().value, Some(Value::Integer(83920320039092)));
+            assert_eq!(leaf.read_record(&paged, 0).await?.unwrap().value, Some(Value::Integer(339939393)));
+            assert_eq!(leaf.read_record(&paged, 1).await?.unwrap().value, Some(Value::Integer(83920320039092)));
             
 
             
             // assert_eq!(leaf.get_cell_count(), 2);
-            // assert_eq!(leaf.read_record(0).await?.unwrap().value, Some(Value::Integer(339939393)));
-            // assert_eq!(leaf.read_record(1).await?.unwrap().value, Some(Value::Integer(83920320039092)));
+            // assert_eq!(leaf.read_record(&paged, 0).await?.unwrap().value, Some(Value::Integer(339939393)));
+            // assert_eq!(leaf.read_record(&paged, 1).await?.unwrap().value, Some(Value::Integer(83920320039092)));
 
     
 
@@ -881,10 +864,10 @@ mod tests {
         paged.new_page().await?.leaf().open(&paged, async |leaf: &mut Transact<Leaf>| {
             
             // Get the records.
-            let (total_a, rec_a) = make_test_record("a", Some(Value::Integer(339939393))).await;
-            let (total_b, rec_b) = make_test_record("b", Some(Value::Integer(332))).await;
-            let (total_c, rec_c) = make_test_record("c", Some(Value::Integer(83920320039092))).await;
-            let (total_d, rec_d) = make_test_record("d", Some(Value::Integer(83920320039092333))).await;
+            let (total_a, rec_a) = make_test_record(&paged, "a", Some(Value::Integer(339939393))).await;
+            let (total_b, rec_b) = make_test_record(&paged, "b", Some(Value::Integer(332))).await;
+            let (total_c, rec_c) = make_test_record(&paged, "c", Some(Value::Integer(83920320039092))).await;
+            let (total_d, rec_d) = make_test_record(&paged, "d", Some(Value::Integer(83920320039092333))).await;
 
 
 
@@ -926,15 +909,9 @@ mod tests {
             assert_eq!(fc.len(), 1);
             assert_eq!(fc.first().unwrap().size as usize, (total_a + total_b + total_c) - total_d);
 
-            // println!("leaf: {:?}", &leaf[..40]);
-            // println!("leaf: {:?}", &leaf[4020..]);
-            // println!("wow: {:?}", leaf.read_free_chain().unwrap());
 
-            // leaf.write_record(Record { value: Some(Value::Integer(3)) }).await?;
+            // leaf.write_record(&paged, Record { value: Some(Value::Integer(3)) }).await?;
 
-            // println!("wow: {:?}", leaf.read_free_chain().unwrap());
-            // println!("leaf: {:?}", &leaf[..40]);
-            // println!("leaf: {:?}", &leaf[4020..]);
             
 
             Ok(())
@@ -951,22 +928,21 @@ mod tests {
         let mut paged = PagedFile::open(dir.path().join("hello.txt")).await?;
         paged.new_page().await?.leaf().open(&paged, async |leaf: &mut Transact<Leaf>| {
             
-            let (_, rec_a) = make_test_record("a", Some(Value::Integer(339939393))).await;
-            let (_, rec_b) = make_test_record("b", Some(Value::Integer(332))).await;
-            let (_, rec_c) = make_test_record("c", Some(Value::Integer(83920320039092))).await;
+            let (_, rec_a) = make_test_record(&paged, "a", Some(Value::Integer(339939393))).await;
+            let (_, rec_b) = make_test_record(&paged, "b", Some(Value::Integer(332))).await;
+            let (_, rec_c) = make_test_record(&paged, "c", Some(Value::Integer(83920320039092))).await;
             
             leaf.write_serialized_record(rec_a).await?;
             leaf.write_serialized_record(rec_b).await?;
             leaf.write_serialized_record(rec_c).await?;
-            // println!("hello: {:?}", leaf.view());
 
         
             leaf.simple_delete(1)?;
 
             
             assert_eq!(leaf.get_cell_count(), 2);
-            assert_eq!(leaf.read_record(0).await?.unwrap().value, Some(Value::Integer(339939393)));
-            assert_eq!(leaf.read_record(1).await?.unwrap().value, Some(Value::Integer(83920320039092)));
+            assert_eq!(leaf.read_record(&paged, 0).await?.unwrap().value, Some(Value::Integer(339939393)));
+            assert_eq!(leaf.read_record(&paged, 1).await?.unwrap().value, Some(Value::Integer(83920320039092)));
 
     
 
@@ -1008,7 +984,7 @@ mod tests {
 
             let base = PAGE_SIZE as usize - PAGE_HEADER_RESERVED_BYTES as usize - Projection::<Leaf>::header_size();
             assert_eq!(leaf.get_free_space(), base);
-            let (_, record) = make_test_record("a", Some(Value::Integer(32))).await;
+            let (_, record) = make_test_record(&paged, "a", Some(Value::Integer(32))).await;
             let record_space = record.total_serialized_size();
             leaf.write_serialized_record(record).await?;
             assert_eq!(leaf.get_free_space(), base - record_space - 2);
@@ -1027,11 +1003,18 @@ mod tests {
         let dir = tempdir().unwrap();
         let mut paged = PagedFile::open(dir.path().join("hello.txt")).await.unwrap();
 
-        let massive_string = String::from_utf8(vec![23u8; PAGE_SIZE + 2]).unwrap();
+        // Varied enough that compressing it does not make it fit.
+        let mut state = 0x2545f491u32;
+        let massive_string: String = (0..PAGE_SIZE + 2).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (b'a' + (state % 26) as u8) as char
+        }).collect();
 
         paged.new_page().await.unwrap().leaf().open(&paged, async |leaf| {
             
-            let result: Result<(), PageError> = leaf.write_record(Record { key: Key::from_str("a"), value: Some(Value::String(massive_string)) }).await;
+            let result: Result<(), PageError> = leaf.write_record(&paged, Record { key: Key::from_str("a"), value: Some(Value::String(massive_string.into())) }).await;
             if let Err(result) = result {
                 
                 assert_eq!(result.variant(), PageError::LeafPageFull.variant());
@@ -1056,18 +1039,18 @@ mod tests {
         let mut paged = PagedFile::open(dir.path().join("hello.txt")).await.unwrap();
 
         paged.new_page().await.unwrap().leaf().open(&paged, async |leaf| {
-            leaf.write_record(Record::new(Key::from_str("a"), Some(Value::Integer(32)))).await.unwrap();
-            leaf.write_record(Record::new(Key::from_str("b"), Some(Value::Integer(21)))).await.unwrap();
-            leaf.write_record(Record::new(Key::from_str("c"), Some(Value::String("hello andrew".to_string())))).await.unwrap();
+            leaf.write_record(&paged, Record::new(Key::from_str("a"), Some(Value::Integer(32)))).await.unwrap();
+            leaf.write_record(&paged, Record::new(Key::from_str("b"), Some(Value::Integer(21)))).await.unwrap();
+            leaf.write_record(&paged, Record::new(Key::from_str("c"), Some(Value::String("hello andrew".into())))).await.unwrap();
  
 
             assert_eq!(leaf.get_cell_count(), 3);
 
 
 
-            assert_eq!(leaf.read_record(0).await.unwrap().unwrap().value, Some(Value::Integer(32)));
-            assert_eq!(leaf.read_record(1).await.unwrap().unwrap().value, Some(Value::Integer(21)));
-            assert_eq!(leaf.read_record(2).await.unwrap().unwrap().value, Some(Value::String("hello andrew".to_string())));
+            assert_eq!(leaf.read_record(&paged, 0).await.unwrap().unwrap().value, Some(Value::Integer(32)));
+            assert_eq!(leaf.read_record(&paged, 1).await.unwrap().unwrap().value, Some(Value::Integer(21)));
+            assert_eq!(leaf.read_record(&paged, 2).await.unwrap().unwrap().value, Some(Value::String("hello andrew".into())));
 
             Ok(())
         }).await.unwrap();
@@ -1078,4 +1061,37 @@ mod tests {
 
         
     }
-}
\ No newline at end of file
+
+    #[monoio::test]
+    pub async fn test_leaf_page_compressed_records() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("hello.txt");
+        let mut paged = PagedFile::open(&path).await.unwrap();
+
+        let config = |i: usize| format!(r#"{{"service":"api-{i}","replicas":{},"region":"eu-west-1","logging":{{"level":"info"}}}}"#, i % 5);
+        let samples: Vec<String> = (0..64).map(config).collect();
+
+        // Small values are stored as they are, large ones as a block.
+        let large = samples.concat();
+        let (size, small) = make_test_record(&paged, "a", Some(Value::String(config(100).into()))).await;
+        assert!(size > config(100).len());
+        let (size, block) = make_test_record(&paged, "b", Some(Value::String(large.clone().into()))).await;
+        assert!(size * 2 < large.len());
+        let (_, empty) = make_test_record(&paged, "c", None).await;
+
+        paged.new_page().await.unwrap().leaf().open(&paged, async |leaf: &mut Transact<Leaf>| {
+            leaf.write_serialized_record(small).await?;
+            leaf.write_serialized_record(block).await?;
+            leaf.write_serialized_record(empty).await?;
+            Ok(())
+        }).await.unwrap();
+
+        // They read back once the file is opened again.
+        let paged = PagedFile::open(&path).await.unwrap();
+        let leaf = paged.acquire(0).await.unwrap().leaf();
+        assert_eq!(leaf.read_key(1).await.unwrap().as_str(), "b");
+        assert_eq!(leaf.read_record(&paged, 0).await.unwrap().unwrap().value, Some(Value::String(config(100).into())));
+        assert_eq!(leaf.read_record(&paged, 1).await.unwrap().unwrap().value, Some(Value::String(large.into())));
+        assert_eq!(leaf.read_record(&paged, 2).await.unwrap().unwrap().value, None);
+    }
+}
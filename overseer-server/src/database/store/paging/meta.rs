@@ -1,4 +1,4 @@
-use overseer::error::NetworkError;
+use overseer::error::{NetworkError, ProtocolError};
 
 use crate::database::store::file::{PAGE_SIZE, RESERVED_HEADER_SIZE};
 
@@ -54,7 +54,7 @@ impl PageType {
         Ok(match discrim {
             0 => Self::Normal,
             1 => Self::Dummy,
-            _ => Err(NetworkError::ErrorDecodingBoolean)?
+            _ => Err(NetworkError::Protocol(ProtocolError::ErrorDecodingBoolean))?
         })
     }
 }
\ No newline at end of file
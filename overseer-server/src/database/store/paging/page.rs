@@ -1,6 +1,6 @@
 use std::{future::Future, marker::PhantomData, ops::{Deref, DerefMut, Index, IndexMut}, slice::SliceIndex};
 
-use overseer::{error::NetworkError, models::LocalReadAsync};
+use overseer::{error::{NetworkError, StorageError}, models::LocalReadAsync};
 
 use crate::database::store::file::{PagedFile, MAGIC_BYTE, PAGE_HEADER_RESERVED_BYTES, PAGE_SIZE, RESERVED_HEADER_SIZE};
 
@@ -50,7 +50,7 @@ impl PageReference {
     /// Formats and loads the address.
     pub async fn load_formatted(self, page_file: &PagedFile) -> Result<Page, NetworkError> {
         let (error, buffer) = page_file.handle().write_all_at(vec![0u8; self.size as usize], self.pointer.as_u64()).await;
-        println!("Wrote a whole buffer at {} @ {}", self.size, self.pointer.as_u64());
+        tracing::trace!("Wrote a whole buffer at {} @ {}", self.size, self.pointer.as_u64());
         error?;
         Ok(Page {
             reference: self,
@@ -71,7 +71,7 @@ impl PageReference {
 
 async fn load_page(PageReference { pointer, size }: PageReference, page_file: &PagedFile) -> Result<Page, NetworkError>
 {
-    println!("Loading a page of size {size} that starts at {pointer:?}");
+    tracing::trace!("Loading a page of size {size} that starts at {pointer:?}");
     let (error, backing) = page_file.handle().read_exact_at(vec![0u8; size as usize], pointer.as_u64()).await;
     error?; // propagate.
     let backing: Box<[u8]> = backing.into_boxed_slice();
@@ -80,7 +80,7 @@ async fn load_page(PageReference { pointer, size }: PageReference, page_file: &P
     let previous_page = RawPageAddress::new(u32::from_le_bytes(backing[1..5].try_into().unwrap()) * (PAGE_SIZE as u32) + (RESERVED_HEADER_SIZE as u32));
     let next_page = RawPageAddress::new(u32::from_le_bytes(backing[5..9].try_into().unwrap()) * (PAGE_SIZE as u32) + (RESERVED_HEADER_SIZE as u32));
     let page_type = PageType::from_u8(backing[9])?;
-    // println!("loaded {:?}", backing);
+    // tracing::trace!("loaded {:?}", backing);
 
 
     Ok(Page {
@@ -144,7 +144,7 @@ impl Page {
     }
     fn bound_check(&self, position: u32, size: u32) -> Result<(), NetworkError> {
         if (position + size) > self.size() {
-            return Err(NetworkError::IllegalRead)?;
+            return Err(NetworkError::Storage(StorageError::IllegalRead))?;
         }
         Ok(())
     }
@@ -248,7 +248,7 @@ impl Page {
                 
     //             print!("{:02x} ", view[y * WIDTH + x]);
     //         }
-    //         println!("");
+    //         tracing::trace!("");
     //     }
     //     Ok(String::new())
     // }
@@ -0,0 +1,75 @@
+use std::{cell::Cell, time::Duration};
+
+
+/// Counters describing how a store writes its pages or records out.
+///
+/// There is no buffer pool in front of the files, the records are all
+/// held in memory, so there are no hits, misses or evictions to count.
+#[derive(Default, Debug)]
+pub struct StorageStats {
+    flushes: Cell<u64>,
+    flush_time: Cell<Duration>
+}
+
+/// A point in time copy of the [StorageStats].
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct StorageSnapshot {
+    /// How many changes were made that the files do not hold yet.
+    pub dirty: u64,
+    pub flushes: u64,
+    /// The total time spent flushing to disk.
+    pub flush_time: Duration
+}
+
+impl StorageStats {
+    pub fn record_flush(&self, elapsed: Duration) {
+        self.flushes.set(self.flushes.get() + 1);
+        self.flush_time.set(self.flush_time.get() + elapsed);
+    }
+    /// The counters along with how many changes are dirty.
+    pub fn snapshot(&self, dirty: u64) -> StorageSnapshot {
+        StorageSnapshot {
+            dirty,
+            flushes: self.flushes.get(),
+            flush_time: self.flush_time.get()
+        }
+    }
+}
+
+impl StorageSnapshot {
+    /// Adds the counters of another store, as for the shards of a namespace.
+    pub fn merge(&mut self, other: StorageSnapshot) {
+        self.dirty += other.dirty;
+        self.flushes += other.flushes;
+        self.flush_time += other.flush_time;
+    }
+    /// The mean time a flush takes.
+    pub fn mean_flush_latency(&self) -> Duration {
+        if self.flushes == 0 {
+            Duration::ZERO
+        } else {
+            self.flush_time / self.flushes as u32
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::StorageStats;
+
+
+    #[test]
+    pub fn test_flush_latency() {
+        let stats = StorageStats::default();
+        assert_eq!(stats.snapshot(0).mean_flush_latency(), Duration::ZERO);
+
+        stats.record_flush(Duration::from_millis(2));
+        stats.record_flush(Duration::from_millis(4));
+        let snapshot = stats.snapshot(5);
+        assert_eq!((snapshot.dirty, snapshot.flushes), (5, 2));
+        assert_eq!(snapshot.mean_flush_latency(), Duration::from_millis(3));
+    }
+}
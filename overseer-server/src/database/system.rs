@@ -0,0 +1,120 @@
+use std::{cell::RefCell, collections::HashMap, sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::{Duration, Instant}};
+
+use overseer::models::{Key, Value};
+
+
+/// The prefix of the keys the server answers itself rather than from its
+/// records, see [SYSTEM_KEYS]. Clients cannot write under it.
+pub const SYSTEM_PREFIX: &str = "$sys.";
+
+/// The names of the system keys, each is read as the prefix followed by
+/// the name, such as `$sys.uptime_ms`.
+///
+/// The storage figures are those of the database serving the key, which
+/// is one of the shards when the default namespace is split across threads.
+pub const SYSTEM_KEYS: [&str; 10] = [
+    "version",
+    "protocol",
+    "uptime_ms",
+    "connections",
+    "watchers",
+    "storage.records",
+    "storage.version",
+    "storage.dirty",
+    "storage.flushes",
+    "storage.flush_latency_us"
+];
+
+/// The prefix of the keys the transitions of the locks are published on,
+/// `$sys.lock.leader` holds the id of the client holding the lock on
+/// `leader` and nothing while it is free.
+pub const LOCK_PREFIX: &str = "$sys.lock.";
+
+/// How often the watchers of the system keys are told what changed.
+pub const SYSTEM_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether the key is under [SYSTEM_PREFIX].
+pub fn is_system_key(key: &Key) -> bool {
+    key.as_str().starts_with(SYSTEM_PREFIX)
+}
+
+/// The key the transitions of the lock on the key are published on, see [LOCK_PREFIX].
+pub fn lock_key(key: &Key) -> Key {
+    Key::from_owned(format!("{LOCK_PREFIX}{}", key.as_str()))
+}
+
+/// The key locked, if the key is one the transitions of a lock are published on.
+pub fn locked_key(key: &Key) -> Option<Key> {
+    key.as_str().strip_prefix(LOCK_PREFIX).map(Key::from_str)
+}
+
+/// The figures of the server the system keys expose that no database
+/// knows of, the driver shares them with the database of every shard.
+pub struct SystemStats {
+    started: Instant,
+    connections: AtomicUsize
+}
+
+impl Default for SystemStats {
+    fn default() -> Self {
+        Self { started: Instant::now(), connections: AtomicUsize::new(0) }
+    }
+}
+
+impl SystemStats {
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+    pub fn set_connections(&self, connections: usize) {
+        self.connections.store(connections, Ordering::Relaxed);
+    }
+}
+
+/// The system keys served by a database, along with the values their
+/// watchers were last told.
+pub struct SystemKeys {
+    pub stats: Arc<SystemStats>,
+    published: RefCell<HashMap<&'static str, Value>>
+}
+
+impl SystemKeys {
+    pub fn new(stats: Arc<SystemStats>) -> Self {
+        Self { stats, published: RefCell::default() }
+    }
+    /// Records the value of the key, returning whether it changed since
+    /// it was last published.
+    pub fn publish(&self, name: &'static str, value: &Value) -> bool {
+        let mut published = self.published.borrow_mut();
+        if published.get(name) == Some(value) {
+            return false;
+        }
+        published.insert(name, value.clone());
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use overseer::models::{Key, Value};
+
+    use super::{is_system_key, SystemKeys, SystemStats};
+
+    #[test]
+    pub fn test_system_keys() {
+        assert!(is_system_key(&Key::from_str("$sys.uptime_ms")));
+        assert!(!is_system_key(&Key::from_str("sys.uptime_ms")));
+
+        let system = SystemKeys::new(Arc::new(SystemStats::default()));
+        system.stats.set_connections(3);
+        assert_eq!(system.stats.connections(), 3);
+        assert!(system.publish("connections", &Value::Integer(3)));
+        assert!(!system.publish("connections", &Value::Integer(3)));
+        assert!(system.publish("connections", &Value::Integer(4)));
+    }
+}
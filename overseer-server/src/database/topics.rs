@@ -0,0 +1,105 @@
+use std::rc::Rc;
+
+use dashmap::DashMap;
+use overseer::{access::WatcherBehaviour, models::{Key, Value}};
+
+use crate::net::ClientId;
+
+use super::watcher::{WatchClient, WatchServer, Watcher, WatcherLimit};
+
+
+/// The subscribers of every topic by namespace and topic.
+///
+/// A topic is not a key, publishing to it never touches the store. The
+/// message goes to the watchers subscribed at that moment and is gone, a
+/// subscriber that joins later does not see it.
+#[derive(Default)]
+pub struct Topics {
+    subscribers: DashMap<(String, Key), DashMap<ClientId, Watcher<WatchServer>>>
+}
+
+impl Topics {
+    /// Subscribes the client to a topic, replacing a subscription it already had.
+    ///
+    /// Messages are queued in order up to the limit.
+    pub fn subscribe(&self, namespace: &str, topic: &Key, client_id: ClientId, limit: WatcherLimit) -> Watcher<WatchClient> {
+        let (client, server) = Watcher::new(WatcherBehaviour::Ordered, limit);
+        let previous = self.subscribers
+            .entry((namespace.to_string(), topic.clone()))
+            .or_default()
+            .insert(client_id, server);
+        if let Some(previous) = previous {
+            previous.kill();
+        }
+        client
+    }
+    /// Unsubscribes the client from a topic, returning false if it was not subscribed.
+    pub fn unsubscribe(&self, namespace: &str, topic: &Key, client_id: ClientId) -> bool {
+        let entry = (namespace.to_string(), topic.clone());
+        let removed = match self.subscribers.get(&entry) {
+            Some(map) => map.remove(&client_id),
+            None => None
+        };
+        // A topic without subscribers is forgotten.
+        self.subscribers.remove_if(&entry, |_, map| map.is_empty());
+        match removed {
+            Some((_, watcher)) => {
+                watcher.kill();
+                true
+            }
+            None => false
+        }
+    }
+    /// Hands the message to the subscribers of the topic, returning how many there were.
+    pub fn publish(&self, namespace: &str, topic: &Key, value: Value) -> usize {
+        match self.subscribers.get(&(namespace.to_string(), topic.clone())) {
+            Some(map) => {
+                Watcher::notify_coordinated(map.iter(), Some(Rc::new(value)));
+                map.len()
+            }
+            None => 0
+        }
+    }
+    /// Counts the subscriptions across every topic.
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.iter().map(|f| f.len()).sum()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::models::{Key, Value};
+
+    use crate::{database::WatcherLimit, net::ClientId};
+
+    use super::Topics;
+
+    #[monoio::test]
+    pub async fn test_topics() {
+        let topics = Topics::default();
+        let topic = Key::from_str("deploy.started");
+        assert_eq!(topics.publish("default", &topic, Value::Integer(0)), 0);
+
+        let first = topics.subscribe("default", &topic, ClientId::from_id(0), WatcherLimit::default());
+        let second = topics.subscribe("default", &topic, ClientId::from_id(1), WatcherLimit::default());
+        // The same topic in another namespace is another topic.
+        let other = topics.subscribe("staging", &topic, ClientId::from_id(2), WatcherLimit::default());
+
+        assert_eq!(topics.publish("default", &topic, Value::Integer(1)), 2);
+        assert_eq!(topics.publish("default", &topic, Value::Integer(2)), 2);
+        for watcher in [&first, &second] {
+            // Every message arrives, in order.
+            assert_eq!(*watcher.wait().await.unwrap(), Value::Integer(1));
+            assert_eq!(watcher.drain().into_iter().map(|f| (*f.unwrap()).clone()).collect::<Vec<_>>(), vec![Value::Integer(2)]);
+        }
+        assert!(other.drain().is_empty());
+
+        assert!(topics.unsubscribe("default", &topic, ClientId::from_id(0)));
+        assert!(!topics.unsubscribe("default", &topic, ClientId::from_id(0)));
+        assert!(first.is_killed());
+        assert_eq!(topics.publish("default", &topic, Value::Integer(3)), 1);
+        assert!(topics.unsubscribe("default", &topic, ClientId::from_id(1)));
+        assert_eq!(topics.subscriber_count(), 1);
+    }
+}
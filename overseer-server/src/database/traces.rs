@@ -0,0 +1,98 @@
+use std::{cell::RefCell, collections::HashMap, future::Future, rc::{Rc, Weak}};
+
+use overseer::models::{Key, Value};
+
+
+tokio::task_local! {
+    /// The trace of the request being served.
+    static TRACE: Option<u64>;
+}
+
+/// Serves the future as part of the trace, the changes it makes tag the
+/// notifications they cause with it.
+pub async fn traced<F: Future>(trace: Option<u64>, future: F) -> F::Output {
+    TRACE.scope(trace, future).await
+}
+
+/// The trace of the request being served, if it has one.
+pub fn current_trace() -> Option<u64> {
+    TRACE.try_with(|f| *f).ok().flatten()
+}
+
+/// The traces of the changes to the watched keys whose notifications may
+/// still be waiting to go out.
+///
+/// A change is told apart by the value it wrote, which every watcher of the
+/// key is handed the same [Rc] of, so a trace is forgotten once no watcher
+/// holds its value anymore. A delete is kept until the next change.
+#[derive(Default)]
+pub struct Traces {
+    changes: RefCell<HashMap<Key, Vec<TracedChange>>>
+}
+
+/// A change made in a trace, [None] for a delete.
+struct TracedChange {
+    value: Option<Weak<Value>>,
+    trace: u64
+}
+
+impl Traces {
+    /// Notes the change to the key made by the request being served.
+    pub fn record(&self, key: &Key, value: Option<&Rc<Value>>) {
+        let mut changes = self.changes.borrow_mut();
+        let trace = current_trace();
+        if trace.is_none() && !changes.contains_key(key) {
+            return;
+        }
+        let traced = changes.entry(key.clone()).or_default();
+        traced.retain(|f| f.value.as_ref().is_some_and(|f| f.strong_count() > 0));
+        if let Some(trace) = trace {
+            traced.push(TracedChange { value: value.map(Rc::downgrade), trace });
+        }
+        if traced.is_empty() {
+            changes.remove(key);
+        }
+    }
+    /// The trace of the change that left the key with the value.
+    pub fn of(&self, key: &Key, value: Option<&Rc<Value>>) -> Option<u64> {
+        let changes = self.changes.borrow();
+        changes.get(key)?.iter().rev().find(|f| match (&f.value, value) {
+            (Some(written), Some(value)) => Weak::ptr_eq(written, &Rc::downgrade(value)),
+            (None, None) => true,
+            _ => false
+        }).map(|f| f.trace)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use overseer::models::{Key, Value};
+
+    use super::{current_trace, traced, Traces};
+
+    #[monoio::test]
+    pub async fn test_traces() {
+        let traces = Traces::default();
+        let key = Key::from_str("app.mode");
+        let (first, second) = (Rc::new(Value::Integer(1)), Rc::new(Value::Integer(2)));
+        traced(Some(7), async { traces.record(&key, Some(&first)) }).await;
+        traced(Some(8), async { traces.record(&key, Some(&second)) }).await;
+        assert_eq!(current_trace(), None);
+
+        // Each queued value keeps the trace of the change that wrote it.
+        assert_eq!(traces.of(&key, Some(&first)), Some(7));
+        assert_eq!(traces.of(&key, Some(&second)), Some(8));
+        assert_eq!(traces.of(&key, Some(&Rc::new(Value::Integer(2)))), None);
+
+        // A value nothing holds anymore is forgotten with the next change.
+        drop(first);
+        traces.record(&key, None);
+        assert_eq!(traces.of(&key, None), None);
+        assert_eq!(traces.of(&key, Some(&second)), Some(8));
+        traced(Some(9), async { traces.record(&key, None) }).await;
+        assert_eq!(traces.of(&key, None), Some(9));
+    }
+}
@@ -1,4 +1,4 @@
-use std::{cell::{Cell, RefCell, UnsafeCell}, collections::VecDeque, future::Future, marker::PhantomData, ops::Deref, rc::Rc, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, task::{LocalWaker, Poll, RawWaker, RawWakerVTable, Waker}};
+use std::{cell::{Cell, RefCell, UnsafeCell}, collections::VecDeque, future::Future, marker::PhantomData, ops::Deref, rc::Rc, task::{LocalWaker, Poll}};
 use overseer::{access::WatcherBehaviour, models::Value};
 
 
@@ -9,6 +9,35 @@ pub struct WatchClient;
 
 type IValue = Option<Rc<Value>>;
 
+/// What an ordered watcher does with a change once its queue is full.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OverflowPolicy {
+    /// Forget the oldest queued change to make room.
+    #[default]
+    DropOldest,
+    /// Forget the change that did not fit.
+    DropNewest,
+    /// Kill the watcher, the client has to subscribe again.
+    KillWatcher
+}
+
+/// Bounds the queue of an ordered watcher.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WatcherLimit {
+    /// The most changes queued for the subscriber.
+    pub capacity: usize,
+    pub overflow: OverflowPolicy
+}
+
+impl Default for WatcherLimit {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            overflow: OverflowPolicy::default()
+        }
+    }
+}
+
 enum HoldingInner {
     /// An ordered watcher returns things in the order of
     /// which they came.
@@ -22,6 +51,10 @@ enum HoldingInner {
 
 struct WatcherInner {
     inner: HoldingInner,
+    /// Bounds the queue of an ordered watcher.
+    limit: WatcherLimit,
+    /// The changes lost to a full queue since the subscriber last checked.
+    missed: Cell<u64>,
     wakeup: UnsafeCell<Option<LocalWaker>>,
     /// If the watcher is dead.
     killed: Cell<bool>,
@@ -97,14 +130,19 @@ impl Watcher<WatchServer> {
 impl Watcher<()> {
     /// Returns a split watcher. One of these is for
     /// the client and there other is for the server.
-    pub fn new(class: WatcherBehaviour) -> (Watcher<WatchClient>, Watcher<WatchServer>) {
-
+    ///
+    /// # Panics
+    /// If the capacity of the limit is zero.
+    pub fn new(class: WatcherBehaviour, limit: WatcherLimit) -> (Watcher<WatchClient>, Watcher<WatchServer>) {
+        assert!(limit.capacity > 0, "The watcher capacity must be positive.");
 
         let inner = Rc::new(WatcherInner {
             inner: match class {
                 WatcherBehaviour::Eager => HoldingInner::Eager(RefCell::default()),
                 WatcherBehaviour::Ordered => HoldingInner::Ordered(RefCell::default()),
             },
+            limit,
+            missed: Cell::new(0),
             killed: Cell::new(false),
             wakeup: UnsafeCell::new(None),
             ready: Cell::new(false)
@@ -141,26 +179,40 @@ impl Watcher<WatchClient> {
         match &self.inner.inner {
             HoldingInner::Eager(value) => {
                 if value.borrow_mut().is_some() {
+                    // The value was taken before its wakeup was seen, which
+                    // would otherwise surface as an empty value next time.
+                    self.inner.ready.set(false);
                     value.borrow_mut().take()
                 } else {
                     (&*self.inner).await;
                     value.borrow_mut().take()
                 }
             },
-            HoldingInner::Ordered(value) => {
-                if !value.borrow().is_empty() {
-                    value.borrow_mut().pop_front()?
-                } else {
-                    (&*self.inner).await;
-                    value.borrow_mut().pop_front()?
+            HoldingInner::Ordered(value) => loop {
+                // A wakeup can outlive the value it announced if that value was
+                // already taken, so only an actual value ends the wait.
+                if let Some(next) = value.borrow_mut().pop_front() {
+                    break next;
                 }
+                (&*self.inner).await;
             }
         }
     }
+    /// Takes every value queued behind the one last received, an eager
+    /// watcher only ever holds the latest so nothing is queued behind it.
+    pub fn drain(&self) -> Vec<IValue> {
+        match &self.inner.inner {
+            HoldingInner::Eager(..) => vec![],
+            HoldingInner::Ordered(value) => value.borrow_mut().drain(..).collect()
+        }
+    }
     pub fn is_killed(&self) -> bool {
         self.inner.killed.get()
     }
-    
+    /// How many changes were lost to a full queue since the last call.
+    pub fn take_missed(&self) -> u64 {
+        self.inner.missed.replace(0)
+    }
 }
 
 
@@ -173,8 +225,29 @@ impl Watcher<WatchServer> {
                 
             },
             HoldingInner::Ordered(value) => {
-                
-                value.borrow_mut().push_back(nvalue);
+                let mut value = value.borrow_mut();
+                if self.inner.killed.get() {
+                    // A dead watcher only needs something to wake up to.
+                    if value.is_empty() {
+                        value.push_back(nvalue);
+                    }
+                    return;
+                }
+                if value.len() >= self.inner.limit.capacity {
+                    self.inner.missed.set(self.inner.missed.get() + 1);
+                    match self.inner.limit.overflow {
+                        OverflowPolicy::DropOldest => {
+                            value.pop_front();
+                        }
+                        OverflowPolicy::DropNewest => return,
+                        OverflowPolicy::KillWatcher => {
+                            // The subscriber wakes to the kill and exits.
+                            self.inner.killed.set(true);
+                            value.clear();
+                        }
+                    }
+                }
+                value.push_back(nvalue);
             }
         }
     }
@@ -191,16 +264,16 @@ impl Watcher<WatchServer> {
 
 #[cfg(test)]
 mod tests {
-    use std::{rc::Rc, sync::Arc, time::Duration};
+    use std::{rc::Rc, time::Duration};
 
     use overseer::models::Value;
 
-    use crate::database::watcher::{Watcher, WatcherBehaviour};
+    use crate::database::watcher::{OverflowPolicy, Watcher, WatcherBehaviour, WatcherLimit};
 
 
     #[monoio::test]
     pub async fn check_watcher_correctness_ordered() {
-        let (client, server) = Watcher::new(WatcherBehaviour::Ordered);
+        let (client, server) = Watcher::new(WatcherBehaviour::Ordered, WatcherLimit::default());
         server.wake(None);
         server.wake(Some(Value::Integer(0).into()));
         assert!(client.wait().await.is_none());
@@ -209,7 +282,7 @@ mod tests {
     #[monoio::test(enable_timer = true)]
     pub async fn test_wakeup_mechanism_basic() {
         // Configure an eager watcher. We will do a basic two-shot receive.
-        let (client, server) = Watcher::new(WatcherBehaviour::Ordered);
+        let (client, server) = Watcher::new(WatcherBehaviour::Ordered, WatcherLimit::default());
         monoio::spawn(async move {
             server.wake(Some(Rc::new(Value::Integer(2))));
             server.wake(Some(Rc::new(Value::Integer(4))));
@@ -221,8 +294,8 @@ mod tests {
     #[monoio::test(enable_timer = true)]
     pub async fn test_wakeup_mechanism_twotailed() {
         // Configure an eager watcher. We will do a basic two-shot receive.
-        let (client_a, server_a) = Watcher::new(WatcherBehaviour::Ordered);
-        let (client_b, server_b) = Watcher::new(WatcherBehaviour::Ordered);
+        let (client_a, server_a) = Watcher::new(WatcherBehaviour::Ordered, WatcherLimit::default());
+        let (client_b, server_b) = Watcher::new(WatcherBehaviour::Ordered, WatcherLimit::default());
         monoio::spawn(async move {
             server_a.wake(Some(Rc::new(Value::Integer(2))));
             assert_eq!(*client_b.wait().await.unwrap(), Value::Integer(3));
@@ -233,9 +306,65 @@ mod tests {
         assert_eq!(*client_a.wait().await.unwrap(), Value::Integer(5));
     }
 
+    #[monoio::test(enable_timer = true)]
+    pub async fn check_watcher_eager_wakeup() {
+        let (client, server) = Watcher::new(WatcherBehaviour::Eager, WatcherLimit::default());
+        server.wake(Some(Rc::new(Value::Integer(1))));
+        assert_eq!(*client.wait().await.unwrap(), Value::Integer(1));
+        // The wakeup went with the value.
+        assert!(monoio::time::timeout(Duration::from_millis(50), client.wait()).await.is_err());
+        server.wake(None);
+        assert!(client.wait().await.is_none());
+    }
+
+    #[monoio::test]
+    pub async fn check_watcher_drain_ordered() {
+        let (client, server) = Watcher::new(WatcherBehaviour::Ordered, WatcherLimit::default());
+        for i in 0..3 {
+            server.wake(Some(Rc::new(Value::Integer(i))));
+        }
+        assert_eq!(*client.wait().await.unwrap(), Value::Integer(0));
+        let rest: Vec<_> = client.drain().into_iter().map(|f| f.unwrap().as_integer().unwrap()).collect();
+        assert_eq!(rest, vec![1, 2]);
+
+        // The wakeups of the drained values do not surface as empty values.
+        server.wake(Some(Rc::new(Value::Integer(3))));
+        assert_eq!(*client.wait().await.unwrap(), Value::Integer(3));
+    }
+
+    #[monoio::test]
+    pub async fn check_watcher_overflow() {
+        let limit = |overflow| WatcherLimit { capacity: 2, overflow };
+        let values = |client: &Watcher<_>| -> Vec<i64> {
+            client.drain().into_iter().map(|f| f.unwrap().as_integer().unwrap()).collect()
+        };
+
+        let (client, server) = Watcher::new(WatcherBehaviour::Ordered, limit(OverflowPolicy::DropOldest));
+        for i in 0..4 {
+            server.wake(Some(Rc::new(Value::Integer(i))));
+        }
+        assert_eq!(values(&client), vec![2, 3]);
+        assert_eq!(client.take_missed(), 2);
+        assert_eq!(client.take_missed(), 0);
+
+        let (client, server) = Watcher::new(WatcherBehaviour::Ordered, limit(OverflowPolicy::DropNewest));
+        for i in 0..4 {
+            server.wake(Some(Rc::new(Value::Integer(i))));
+        }
+        assert_eq!(values(&client), vec![0, 1]);
+        assert_eq!(client.take_missed(), 2);
+
+        let (client, server) = Watcher::new(WatcherBehaviour::Ordered, limit(OverflowPolicy::KillWatcher));
+        for i in 0..3 {
+            server.wake(Some(Rc::new(Value::Integer(i))));
+        }
+        assert!(client.is_killed());
+        assert_eq!(client.take_missed(), 1);
+    }
+
     #[monoio::test]
     pub async fn check_watcher_correctness_eager() {
-        let (client, server) = Watcher::new(WatcherBehaviour::Eager);
+        let (client, server) = Watcher::new(WatcherBehaviour::Eager, WatcherLimit::default());
         server.wake(None);
         server.wake(Some(Value::Integer(0).into()));
         assert_eq!(client.wait().await.unwrap().as_integer().unwrap(), 0);
@@ -243,8 +372,8 @@ mod tests {
 
     #[monoio::test]
     pub async fn check_watcher_notify_synchronize() {
-        let (client_1, server_1) = Watcher::new(WatcherBehaviour::Eager);
-        let (client_2, server_2) = Watcher::new(WatcherBehaviour::Eager);
+        let (client_1, server_1) = Watcher::new(WatcherBehaviour::Eager, WatcherLimit::default());
+        let (client_2, server_2) = Watcher::new(WatcherBehaviour::Eager, WatcherLimit::default());
         
 
         Watcher::notify_coordinated([server_1, server_2].iter(), Some(Rc::new(Value::Integer(45))));
@@ -257,7 +386,7 @@ mod tests {
     /// This test checks if notifications actually work.
     #[monoio::test]
     pub async fn check_watcher_notify_integrity() {
-        let (client_1, server_1) = Watcher::new(WatcherBehaviour::Eager);
+        let (client_1, server_1) = Watcher::new(WatcherBehaviour::Eager, WatcherLimit::default());
         server_1.wake_without_notify(Some(Rc::new(Value::Integer(2))));
         assert_eq!(client_1.wait().await.unwrap().as_integer().unwrap(), 2);
 
@@ -1,4 +1,8 @@
 #![feature(local_waker)]
 
 pub mod database;
-pub mod net;
\ No newline at end of file
+pub mod net;
+pub mod runtime;
+pub mod settings;
+#[cfg(feature = "test-support")]
+pub mod testing;
@@ -1,12 +1,100 @@
+use overseer::error::NetworkError;
+use overseer_server::{net::Driver, runtime, settings::ServerSettings};
 
 
+/// The settings file used when none is given.
+const DEFAULT_SETTINGS: &str = "overseer.toml";
+
 fn main() {
+    let path = match std::env::args().nth(1).as_deref() {
+        Some("--config" | "-c") => std::env::args().nth(2),
+        Some(path) => Some(path.to_string()),
+        None => None
+    };
+    let settings = match path {
+        Some(path) => ServerSettings::load(path),
+        None if std::path::Path::new(DEFAULT_SETTINGS).exists() => ServerSettings::load(DEFAULT_SETTINGS),
+        None => Ok(ServerSettings::default())
+    };
+    let settings = match settings {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+    };
 
     tracing_subscriber::fmt()
-    .with_max_level(tracing::Level::DEBUG)
-    .with_target(false)
-    .init();
-    tracing::info!("Hello");
-    
-    println!("Hello, world!");
+        .with_max_level(settings.log_level().unwrap_or(tracing::Level::INFO))
+        .with_target(false)
+        .init();
+
+    match runtime::block_on(|| run(settings)) {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            tracing::error!("The server stopped with an error: {e}");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            tracing::error!("Could not start the runtime: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run(settings: ServerSettings) -> Result<(), NetworkError> {
+    tracing::info!(
+        version = env!("CARGO_PKG_VERSION"),
+        data_dir = %settings.data_dir.display(),
+        database = %settings.database,
+        page_size = settings.page_size,
+        "Starting overseer."
+    );
+    std::fs::create_dir_all(&settings.data_dir)?;
+    let config = settings.driver_config();
+
+    let driver = match (&settings.unix_socket, &settings.tls) {
+        #[cfg(unix)]
+        (Some(socket), _) => {
+            tracing::info!("Listening on {}.", socket.display());
+            Driver::start_unix(socket, &settings.data_dir, &settings.database, config).await?
+        }
+        #[cfg(feature = "tls")]
+        (_, Some(tls)) => {
+            let driver = Driver::start_tls(&settings.bind, &tls.cert, &tls.key, &settings.data_dir, &settings.database, config).await?;
+            tracing::info!("Listening with TLS on port {}.", driver.port());
+            driver
+        }
+        _ => {
+            let driver = Driver::start_with_config(&settings.bind, &settings.data_dir, &settings.database, config).await?;
+            tracing::info!("Listening on port {}.", driver.port());
+            driver
+        }
+    };
+
+    #[cfg(feature = "metrics")]
+    if let Some(address) = &settings.metrics {
+        let port = driver.serve_metrics(address.as_str()).await?;
+        tracing::info!("Serving metrics on port {port}.");
+    }
+
+    shutdown_signal().await?;
+    tracing::info!("Shutting down gracefully.");
+    driver.shutdown().await?;
+    tracing::info!("Shut down.");
+    Ok(())
+}
+
+/// Resolves on an interrupt, or on a termination request on Unix.
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(())
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
 }
@@ -0,0 +1,52 @@
+
+
+/// What an authenticated connection is permitted to do.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    /// The connection may get and watch keys.
+    ReadOnly,
+    /// The connection may also insert and delete keys.
+    ReadWrite
+}
+
+impl Access {
+    /// Checks if this access level permits modifying keys.
+    pub fn can_write(&self) -> bool {
+        matches!(self, Self::ReadWrite)
+    }
+}
+
+/// Validates the token a connection presents in its first packet.
+///
+/// Returning [None] rejects the connection.
+pub trait Authenticator {
+    fn authenticate(&self, token: &str) -> Option<Access>;
+}
+
+impl<F> Authenticator for F
+where 
+    F: Fn(&str) -> Option<Access>
+{
+    fn authenticate(&self, token: &str) -> Option<Access> {
+        (self)(token)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Access, Authenticator};
+
+
+    #[test]
+    pub fn test_closure_authenticator() {
+        let auth = |token: &str| match token {
+            "admin" => Some(Access::ReadWrite),
+            "reader" => Some(Access::ReadOnly),
+            _ => None
+        };
+        assert!(auth.authenticate("admin").unwrap().can_write());
+        assert!(!auth.authenticate("reader").unwrap().can_write());
+        assert!(auth.authenticate("intruder").is_none());
+    }
+}
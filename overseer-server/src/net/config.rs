@@ -0,0 +1,405 @@
+use std::{collections::HashMap, rc::Rc, time::Duration};
+
+use overseer::{access::IsolationReason, models::KeyPolicy, network::DEFAULT_MAX_PACKET_SIZE};
+
+use crate::database::{DurabilityPolicy, HistoryRetention, Manifest, PlacementPolicy, QuotaPolicy, Seed, SplitPolicy, WatcherLimit};
+
+use super::{Authenticator, ClientId, ExtensionHandler, PoisonPolicy, SlowRequest, SlowRequestHook, SlowRequestPolicy, ThrottlePolicy, WritePolicy, DEFAULT_SESSION_GRACE};
+
+
+/// How long a write waits on its followers before it is answered with a timeout.
+pub const DEFAULT_ACKNOWLEDGEMENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+
+/// What happens to a client that cannot keep up with its notifications.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SlowConsumerPolicy {
+    /// Close the connection once the queue is full.
+    #[default]
+    Disconnect,
+    /// Drop notifications that do not fit in the queue.
+    DropNotify
+}
+
+/// What happens to a connection accepted while the driver serves
+/// [DriverConfig::max_connections] already.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ConnectionLimitPolicy {
+    /// Close it right away.
+    #[default]
+    Close,
+    /// Tell it with a [overseer::network::PacketPayload::ServerBusy] and
+    /// close it. Connections that would speak TLS are closed without it.
+    Reject,
+    /// Hold up to this many connections until others close, the ones
+    /// beyond are rejected.
+    Queue(usize)
+}
+
+/// What happens to a connection that sends a packet the server does not
+/// serve, such as a [overseer::network::PacketPayload::Notify] or a
+/// [overseer::network::PacketPayload::Return] only a server sends.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ProtocolViolationPolicy {
+    /// Answer it with an error and close the connection.
+    #[default]
+    Close,
+    /// Answer it with an error and keep serving the connection.
+    Reply,
+    /// Drop the packet and keep serving the connection.
+    Ignore
+}
+
+/// Reported to the event hook when the driver sheds load.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DriverEvent {
+    /// A connection was refused because the driver was at capacity.
+    ConnectionRejected,
+    /// A notification did not fit in the queue of the client.
+    NotificationDropped(ClientId),
+    /// A client was disconnected for not reading its notifications.
+    SlowConsumerDisconnected(ClientId),
+    /// A client was disconnected for being idle too long.
+    IdleDisconnected(ClientId),
+    /// A subscription of a client was killed for being broken, see [PoisonPolicy].
+    WatcherIsolated(ClientId, IsolationReason),
+    /// A client sent a packet the server does not serve, see [ProtocolViolationPolicy].
+    ProtocolViolation(ClientId)
+}
+
+/// The options the [super::Driver] is started with.
+#[derive(Clone)]
+pub struct DriverConfig {
+    /// Validates connection tokens, if this is not set every
+    /// connection is permitted to read and write.
+    pub authenticator: Option<Rc<dyn Authenticator>>,
+    /// The keys the store is expected to contain.
+    pub manifest: Option<Rc<Manifest>>,
+    /// Creates the defaults of missing required keys at startup.
+    pub create_defaults: bool,
+    /// Written into the database if it is empty at startup.
+    pub seed: Option<Seed>,
+    /// The most connections served at once, see [DriverConfig::connection_limit]
+    /// for what happens to further connections.
+    pub max_connections: Option<usize>,
+    /// Applied to the connections over [DriverConfig::max_connections].
+    pub connection_limit: ConnectionLimitPolicy,
+    /// How many outgoing packets may be queued for each client.
+    pub queue_capacity: usize,
+    /// Applied to a client whose queue is full when a notification arrives.
+    pub slow_consumer: SlowConsumerPolicy,
+    /// Bounds the changes queued by each ordered watcher.
+    pub watcher_limit: WatcherLimit,
+    /// Kills the subscriptions that stay broken, they are never killed if this is not set.
+    pub poison: Option<PoisonPolicy>,
+    /// Called whenever a connection or a notification is dropped.
+    pub on_event: Option<Rc<dyn Fn(DriverEvent)>>,
+    /// Reports the slow and large requests, they are not reported if this is not set.
+    pub slow_requests: Option<SlowRequestPolicy>,
+    /// Called with every slow or large request, besides the warning logged for it.
+    pub on_slow_request: Option<SlowRequestHook>,
+    /// Connections that send nothing for this long are closed.
+    pub idle_timeout: Option<Duration>,
+    /// The watches that were sent nothing for this long are sent a
+    /// heartbeat, they never are if this is not set.
+    pub watch_heartbeat: Option<Duration>,
+    /// The longest packet a client may send, a connection that sends a
+    /// longer one is closed before it is read.
+    pub max_packet_size: usize,
+    /// How long the session of a lost connection can be resumed, sessions
+    /// are never kept if this is not set.
+    pub session_grace: Option<Duration>,
+    /// The handlers of the protocol extensions by their id, clients are told
+    /// an extension is unsupported if it has none.
+    pub extensions: HashMap<u32, Rc<dyn ExtensionHandler>>,
+    /// How long a write waits to be replicated to the followers it asked for.
+    pub acknowledgement_timeout: Duration,
+    /// How key accesses are sampled and sorted into tiers.
+    pub placement: PlacementPolicy,
+    /// How durable the writes to each key are made.
+    pub durability: DurabilityPolicy,
+    /// How many changes of each key the history keeps.
+    pub history: HistoryRetention,
+    /// The counters of the default namespace split across sub-keys.
+    pub split: SplitPolicy,
+    /// How often the keys of every namespace may be written.
+    pub throttle: ThrottlePolicy,
+    /// What the writes may make each namespace hold.
+    pub quotas: QuotaPolicy,
+    /// What happens to the connections that send packets the server does not serve.
+    pub protocol_violation: ProtocolViolationPolicy,
+    /// What the keys of the writes have to look like.
+    pub key_policy: KeyPolicy,
+    /// Checks and rewrites the values of the writes, see [WritePolicy].
+    pub write_policy: Option<Rc<dyn WritePolicy>>,
+    /// The fields of map values every namespace keeps a secondary index on.
+    pub indexes: Vec<String>,
+    /// How many threads the default namespace is split across, see [crate::database::ShardPool].
+    pub shards: usize,
+    /// Installs a default log subscriber at this level when started.
+    pub log_level: Option<tracing::Level>
+}
+
+impl Default for DriverConfig {
+    fn default() -> Self {
+        Self {
+            authenticator: None,
+            manifest: None,
+            create_defaults: false,
+            seed: None,
+            max_connections: None,
+            connection_limit: ConnectionLimitPolicy::default(),
+            queue_capacity: 250,
+            slow_consumer: SlowConsumerPolicy::default(),
+            watcher_limit: WatcherLimit::default(),
+            poison: Some(PoisonPolicy::default()),
+            on_event: None,
+            slow_requests: Some(SlowRequestPolicy::default()),
+            on_slow_request: None,
+            idle_timeout: None,
+            watch_heartbeat: None,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            session_grace: Some(DEFAULT_SESSION_GRACE),
+            extensions: HashMap::new(),
+            acknowledgement_timeout: DEFAULT_ACKNOWLEDGEMENT_TIMEOUT,
+            placement: PlacementPolicy::default(),
+            durability: DurabilityPolicy::default(),
+            history: HistoryRetention::default(),
+            split: SplitPolicy::default(),
+            throttle: ThrottlePolicy::default(),
+            quotas: QuotaPolicy::default(),
+            protocol_violation: ProtocolViolationPolicy::default(),
+            key_policy: KeyPolicy::default(),
+            write_policy: None,
+            indexes: vec![],
+            shards: 1,
+            log_level: None
+        }
+    }
+}
+
+impl DriverConfig {
+    /// Requires every connection to authenticate with the authenticator.
+    pub fn with_authenticator<A>(mut self, authenticator: A) -> Self
+    where 
+        A: Authenticator + 'static
+    {
+        self.authenticator = Some(Rc::new(authenticator));
+        self
+    }
+    /// Checks the store against the manifest.
+    pub fn with_manifest(mut self, manifest: Manifest) -> Self {
+        self.manifest = Some(Rc::new(manifest));
+        self
+    }
+    /// Creates the defaults of missing required keys at startup.
+    pub fn with_default_creation(mut self) -> Self {
+        self.create_defaults = true;
+        self
+    }
+    /// Seeds a fresh database at startup.
+    pub fn with_seed(mut self, seed: Seed) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+    /// Limits how many connections are served at once.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = Some(max_connections);
+        self
+    }
+    /// Picks what happens to the connections over the limit.
+    pub fn with_connection_limit_policy(mut self, policy: ConnectionLimitPolicy) -> Self {
+        self.connection_limit = policy;
+        self
+    }
+    /// Bounds the outgoing queue of each client.
+    ///
+    /// # Panics
+    /// If the capacity is zero.
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "The queue capacity must be positive.");
+        self.queue_capacity = capacity;
+        self
+    }
+    pub fn with_slow_consumer_policy(mut self, policy: SlowConsumerPolicy) -> Self {
+        self.slow_consumer = policy;
+        self
+    }
+    /// Bounds the changes an ordered watcher queues for a client that
+    /// is behind, and picks what happens to the changes that do not fit.
+    ///
+    /// # Panics
+    /// If the capacity is zero.
+    pub fn with_watcher_limit(mut self, limit: WatcherLimit) -> Self {
+        assert!(limit.capacity > 0, "The watcher capacity must be positive.");
+        self.watcher_limit = limit;
+        self
+    }
+    /// Picks when a subscription whose deliveries keep failing, or whose
+    /// queue stays full, is killed. The client is told with a
+    /// [overseer::network::PacketPayload::WatchIsolated].
+    pub fn with_poison_policy(mut self, policy: Option<PoisonPolicy>) -> Self {
+        self.poison = policy;
+        self
+    }
+    /// Registers a hook for metrics or logging of dropped work.
+    pub fn with_event_hook<F>(mut self, hook: F) -> Self
+    where 
+        F: Fn(DriverEvent) + 'static
+    {
+        self.on_event = Some(Rc::new(hook));
+        self
+    }
+    /// Picks when a request is slow or large enough to be reported, with a
+    /// warning naming its client, operation, key and duration.
+    pub fn with_slow_request_policy(mut self, policy: Option<SlowRequestPolicy>) -> Self {
+        self.slow_requests = policy;
+        self
+    }
+    /// Registers a hook that is handed the slow and large requests, to ship
+    /// them somewhere besides the log.
+    pub fn with_slow_request_hook<F>(mut self, hook: F) -> Self
+    where 
+        F: Fn(&SlowRequest) + 'static
+    {
+        self.on_slow_request = Some(Rc::new(hook));
+        self
+    }
+    /// Closes connections that stay silent for longer than the timeout,
+    /// clients should ping more often than this.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+    /// Sends a heartbeat to every watch that was sent nothing for the
+    /// interval, so the clients can tell a quiet key from a dead watch.
+    /// Clients before protocol version 32 are not sent heartbeats.
+    ///
+    /// # Panics
+    /// If the interval is zero.
+    pub fn with_watch_heartbeat(mut self, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "The heartbeat interval must be positive.");
+        self.watch_heartbeat = Some(interval);
+        self
+    }
+    /// Bounds the packets clients may send, see [DriverConfig::max_packet_size].
+    ///
+    /// # Panics
+    /// If the size is zero.
+    pub fn with_max_packet_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "The packet size must be positive.");
+        self.max_packet_size = size;
+        self
+    }
+    /// Keeps the watches, locks and ephemeral keys of a client that asked
+    /// for a session this long after its connection is lost, so it can
+    /// resume them from a new one. [None] closes them right away.
+    pub fn with_session_grace(mut self, grace: Option<Duration>) -> Self {
+        self.session_grace = grace;
+        self
+    }
+    /// Serves the packets of the protocol extension with the handler,
+    /// replacing any handler registered for it before.
+    pub fn with_extension<H>(mut self, extension: u32, handler: H) -> Self
+    where 
+        H: ExtensionHandler + 'static
+    {
+        self.extensions.insert(extension, Rc::new(handler));
+        self
+    }
+    /// Sets how long a write waits to be replicated before the client is told
+    /// it timed out, the write itself is applied either way.
+    pub fn with_acknowledgement_timeout(mut self, timeout: Duration) -> Self {
+        self.acknowledgement_timeout = timeout;
+        self
+    }
+    pub fn with_placement_policy(mut self, policy: PlacementPolicy) -> Self {
+        self.placement = policy;
+        self
+    }
+    /// Assigns the keys durability classes, the driver makes the writes
+    /// that were not synced right away durable at the interval of the policy.
+    pub fn with_durability_policy(mut self, policy: DurabilityPolicy) -> Self {
+        self.durability = policy;
+        self
+    }
+    /// Sets how many changes of each key the history keeps, which clients
+    /// can list and diff.
+    pub fn with_history_retention(mut self, retention: HistoryRetention) -> Self {
+        self.history = retention;
+        self
+    }
+    /// Splits the increments of the hot counters under the prefixes of the
+    /// policy across sub-keys, giving up their order for throughput.
+    pub fn with_split_policy(mut self, policy: SplitPolicy) -> Self {
+        self.split = policy;
+        self
+    }
+    /// Drops the writes of keys under the prefixes of the policy that come
+    /// faster than it allows, answering them with a
+    /// [overseer::network::PacketPayload::Throttled].
+    pub fn with_throttle_policy(mut self, policy: ThrottlePolicy) -> Self {
+        self.throttle = policy;
+        self
+    }
+    /// Limits the keys and bytes every namespace holds, refusing the writes
+    /// beyond them with [overseer::error::NetworkError::QuotaExceeded]. A
+    /// default namespace split across shards gives each an even share.
+    pub fn with_quota_policy(mut self, policy: QuotaPolicy) -> Self {
+        self.quotas = policy;
+        self
+    }
+    /// Picks what happens to the connections that send packets the
+    /// server does not serve.
+    pub fn with_protocol_violation_policy(mut self, policy: ProtocolViolationPolicy) -> Self {
+        self.protocol_violation = policy;
+        self
+    }
+    /// Refuses the writes to keys the policy does not accept, see [KeyPolicy].
+    pub fn with_key_policy(mut self, policy: KeyPolicy) -> Self {
+        self.key_policy = policy;
+        self
+    }
+    /// Runs the writes of the clients through the policy before they are
+    /// applied, the ones it rejects are answered with
+    /// [overseer::error::NetworkError::ValidationFailed].
+    pub fn with_write_policy<P>(mut self, policy: P) -> Self
+    where 
+        P: WritePolicy + 'static
+    {
+        self.write_policy = Some(Rc::new(policy));
+        self
+    }
+    /// Keeps a secondary index on a field path of the map values, so clients
+    /// can look up the keys holding a value there.
+    pub fn with_index<S>(mut self, field: S) -> Self
+    where
+        S: Into<String>
+    {
+        let field = field.into();
+        if !self.indexes.contains(&field) {
+            self.indexes.push(field);
+        }
+        self
+    }
+    /// Splits the keys of the default namespace across this many threads,
+    /// a single shard serves them from the driver thread.
+    ///
+    /// Reads, writes, watches and listings of the default namespace go to
+    /// the shards. The manifest, key history and promotions still work on
+    /// the database of the driver thread.
+    ///
+    /// # Panics
+    /// If there are no shards.
+    pub fn with_shards(mut self, shards: usize) -> Self {
+        assert!(shards > 0, "There has to be at least one shard.");
+        self.shards = shards;
+        self
+    }
+    /// Logs to stdout at the level, for deployments that do not
+    /// install their own `tracing` subscriber.
+    pub fn with_default_logging(mut self, level: tracing::Level) -> Self {
+        self.log_level = Some(level);
+        self
+    }
+}
@@ -0,0 +1,115 @@
+use std::cell::Cell;
+
+use overseer::models::Key;
+use tokio::sync::Notify;
+
+use crate::database::{KeyChange, KeyHistory};
+
+
+/// What a watch with acknowledged delivery sends next.
+pub(crate) enum Pending {
+    /// The changes after the last one sent, oldest first.
+    Changes(Vec<KeyChange>),
+    /// The history no longer holds some of them, the current record goes
+    /// out in their place.
+    Gap
+}
+
+/// Tracks what the client of a watch with acknowledged delivery was sent
+/// and what it acknowledged.
+///
+/// The changes are read from the history of the key rather than queued,
+/// so the ones a lost connection swallowed can be sent again once the
+/// session is resumed, from the one after the last acknowledged.
+pub(crate) struct AcknowledgedWatch {
+    /// The last sequence the client acknowledged.
+    acked: Cell<u64>,
+    /// The last sequence queued for the client.
+    sent: Cell<u64>,
+    /// Wakes the delivery to send what was not acknowledged again.
+    rewound: Notify
+}
+
+impl AcknowledgedWatch {
+    /// A watch whose client already holds every change up to the sequence.
+    pub fn new(after: u64) -> Self {
+        Self { acked: Cell::new(after), sent: Cell::new(after), rewound: Notify::new() }
+    }
+    pub fn sent(&self) -> u64 {
+        self.sent.get()
+    }
+    /// What is left to send of the changes in the history.
+    pub fn pending(&self, history: &KeyHistory, key: &Key) -> Pending {
+        match history.since(key, self.sent.get()) {
+            Some(changes) => Pending::Changes(changes),
+            None => Pending::Gap
+        }
+    }
+    /// Records that the change at the sequence was queued.
+    pub fn mark_sent(&self, sequence: u64) {
+        self.sent.set(self.sent.get().max(sequence));
+    }
+    /// Acknowledges every change up to the sequence, a client cannot
+    /// acknowledge what it was not sent.
+    pub fn acknowledge(&self, sequence: u64) {
+        self.acked.set(self.acked.get().max(sequence.min(self.sent.get())));
+    }
+    /// Sends everything after the last acknowledged change again.
+    pub fn rewind(&self) {
+        self.sent.set(self.acked.get());
+        self.rewound.notify_one();
+    }
+    /// Resolves once the watch was rewound.
+    pub async fn rewound(&self) {
+        self.rewound.notified().await
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use overseer::models::{Key, Value};
+
+    use crate::database::KeyHistory;
+
+    use super::{AcknowledgedWatch, Pending};
+
+    fn versions(pending: Pending) -> Option<Vec<u64>> {
+        match pending {
+            Pending::Changes(changes) => Some(changes.into_iter().map(|f| f.version).collect()),
+            Pending::Gap => None
+        }
+    }
+
+    #[test]
+    pub fn test_acknowledged_watch() {
+        let history = KeyHistory::with_depth(3);
+        let key = Key::from_str("orders.last");
+        let watch = AcknowledgedWatch::new(2);
+        for version in [1, 3, 5] {
+            history.record(&key, version, Some(Rc::new(Value::Integer(version as i64))));
+        }
+        assert_eq!(versions(watch.pending(&history, &key)), Some(vec![3, 5]));
+        watch.mark_sent(5);
+        assert_eq!(versions(watch.pending(&history, &key)), Some(vec![]));
+
+        // Only what was sent can be acknowledged, a rewind sends the rest again.
+        watch.acknowledge(3);
+        watch.acknowledge(1);
+        watch.rewind();
+        assert_eq!((watch.sent(), versions(watch.pending(&history, &key))), (3, Some(vec![5])));
+        watch.acknowledge(99);
+        watch.rewind();
+        assert_eq!(watch.sent(), 3);
+
+        // Changes the history dropped before they were sent make a gap.
+        for version in [6, 7, 8] {
+            history.record(&key, version, None);
+        }
+        assert_eq!(versions(watch.pending(&history, &key)), None);
+        watch.mark_sent(8);
+        assert_eq!(versions(watch.pending(&history, &key)), Some(vec![]));
+    }
+}
@@ -1,35 +1,183 @@
-use std::{net::ToSocketAddrs, path::Path, rc::Rc, sync::Arc};
+use std::{borrow::Cow, cell::Cell, future::pending, path::Path, rc::Rc, sync::Arc, time::{Duration, Instant}};
 
-use dashmap::DashMap;
-use overseer::{error::NetworkError, models::Key, network::{OverseerSerde, Packet, PacketId, PacketPayload}};
-use tokio::{net::{tcp::{OwnedReadHalf, OwnedWriteHalf}, TcpListener, TcpStream}, sync::mpsc::{Receiver, Sender}};
+use dashmap::{DashMap, DashSet};
+use overseer::{access::{IsolationReason, WatcherActivity, WatcherBehaviour}, error::{NetworkError, ProtocolError}, models::{Acknowledgement, Key, KeyMeta, KeyPolicy, LocalReadAsync, LocalWriteAsync, Value}, network::{OverseerSerde, Packet, PacketId, PacketPayload, PooledReader, queue_topic, CURRENT_VERSION, DEFAULT_NAMESPACE, FIRST_EXTENSION_DISCRIMINATOR, FRAMED_VERSION}};
+use tracing::Instrument;
+use tokio::{io::{AsyncRead, AsyncWrite}, sync::{mpsc::{error::TrySendError, Receiver, Sender, UnboundedReceiver}, Notify}};
 
 
-use crate::database::{Database, WatchClient, Watcher};
+use crate::database::{is_system_key, traced, Database, MigrationReport, Namespaces, PlacementStats, Records, ShardChanges, ShardPool, ShardSettings, SystemStats, Topics, WatchClient, Watcher, SYSTEM_PREFIX, SYSTEM_REFRESH_INTERVAL};
+
+use super::{delivery::{AcknowledgedWatch, Pending}, fanout::{FanoutStats, NotifyEncoder, Outgoing}, listener::Listener, metrics::{ConnectionStats, DriverMetrics}, poison::SubscriptionHealth, session::Sessions, shutdown::Shutdown, throttle::WriteMeter, validation::police_writes, Access, ConnectionLimitPolicy, DriverConfig, DriverEvent, ExtensionRequest, ProtocolViolationPolicy, SlowConsumerPolicy, SlowRequest};
+
+/// How many bytes of queued packets the writer of a client coalesces
+/// into a single write.
+const MAX_WRITE_BATCH: usize = 64 * 1024;
+/// How often the locks whose time to live ran out are released.
+const LOCK_EXPIRY_INTERVAL: Duration = Duration::from_millis(100);
+/// How long the metrics listener waits after failing to accept, so an
+/// error that persists such as running out of descriptors does not spin.
+#[cfg(feature = "metrics")]
+const METRICS_ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
 
 pub struct Driver {
     internal: Rc<DriverInternal>
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct ClientId(u64);
 
 impl ClientId {
     pub fn from_id(i: u64) -> Self {
         Self(i)
     }
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+}
+
+/// What became of a notification handed to [DriverInternal::notify].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Delivery {
+    Queued,
+    /// The queue of the client was full and the notification was dropped.
+    Dropped,
+    /// The client is gone.
+    Closed
 }
 
 struct DriverInternal {
-    database: Database,
-    stream: TcpListener,
-    write_queue: DashMap<ClientId, Sender<Packet<'static>>>
+    /// The database of the default namespace.
+    database: Rc<Database>,
+    namespaces: Namespaces,
+    /// Serves the keys of the default namespace when it is split across threads.
+    shards: Option<ShardPool>,
+    listener: Listener,
+    write_queue: DashMap<ClientId, Sender<Outgoing>>,
+    config: DriverConfig,
+    shutdown: Rc<Shutdown>,
+    metrics: DriverMetrics,
+    /// Shares the encoding of large notifications between subscribers.
+    encoder: NotifyEncoder,
+    /// The subscribers of the topics, which live outside the databases.
+    topics: Topics,
+    /// The clients whose connection was lost, with the notifications
+    /// queued for them since.
+    sessions: Sessions<(Rc<ClientContext>, Receiver<Outgoing>)>,
+    /// Wakes a queued connection when a client closes.
+    slots: Notify,
+    /// Counts the writes of the throttled keys.
+    throttle: WriteMeter,
+    /// The figures behind the system keys of the default namespace.
+    system: Arc<SystemStats>,
+    /// Wraps accepted sockets when the driver was started with TLS.
+    #[cfg(feature = "tls")]
+    tls: Option<tokio_rustls::TlsAcceptor>
 }
 
 impl DriverInternal {
+    fn new(listener: Listener, namespaces: Namespaces, shards: Option<ShardPool>, config: DriverConfig) -> Self {
+        let database = namespaces.get(DEFAULT_NAMESPACE).expect("The default namespace always exists.");
+        let system = Arc::new(SystemStats::default());
+        database.serve_system(Arc::clone(&system));
+        if let Some(Err(e)) = shards.as_ref().map(|f| f.serve_system(Arc::clone(&system))) {
+            tracing::warn!("The shards cannot serve the system keys: {e}");
+        }
+        Self {
+            database,
+            namespaces,
+            shards,
+            listener,
+            write_queue: DashMap::new(),
+            throttle: WriteMeter::new(config.throttle.clone()),
+            system,
+            config,
+            shutdown: Rc::default(),
+            metrics: DriverMetrics::default(),
+            encoder: NotifyEncoder::default(),
+            topics: Topics::default(),
+            sessions: Sessions::default(),
+            slots: Notify::new(),
+            #[cfg(feature = "tls")]
+            tls: None
+        }
+    }
+    /// The records of the default namespace, on the shards if it is split.
+    fn default_records(&self) -> Records<'_> {
+        match &self.shards {
+            Some(shards) => Records::Sharded(shards),
+            None => Records::Local(&self.database)
+        }
+    }
     pub async fn send(&self, id: ClientId, packet: Packet<'static>) {
-        let queue = self.write_queue.get(&id).unwrap().value().clone();
-        queue.send(packet).await.unwrap();
+        // The client may already be closed, in which case there is no one to tell.
+        let Some(queue) = self.write_queue.get(&id).map(|f| f.value().clone()) else {
+            return;
+        };
+        let _ = queue.send(packet.into()).await;
+    }
+    /// Queues a notification without waiting, applying the slow consumer
+    /// policy if the client has fallen behind.
+    ///
+    /// Returns [Delivery::Closed] if the client was disconnected.
+    async fn notify(&self, ctx: &ClientContext, packet: Outgoing) -> Delivery {
+        let Some(queue) = self.write_queue.get(&ctx.id).map(|f| f.value().clone()) else {
+            return Delivery::Closed;
+        };
+        match queue.try_send(packet) {
+            Ok(()) => Delivery::Queued,
+            Err(TrySendError::Closed(..)) => Delivery::Closed,
+            Err(TrySendError::Full(..)) => match self.config.slow_consumer {
+                SlowConsumerPolicy::DropNotify => {
+                    self.emit(DriverEvent::NotificationDropped(ctx.id));
+                    Delivery::Dropped
+                }
+                SlowConsumerPolicy::Disconnect => {
+                    self.emit(DriverEvent::SlowConsumerDisconnected(ctx.id));
+                    close_client(self, ctx).await;
+                    Delivery::Closed
+                }
+            }
+        }
+    }
+    /// Records that a subscription of the client was killed for being broken
+    /// and tells the client, which may be too far behind to get the notice.
+    fn isolate(&self, ctx: &ClientContext, namespace: &str, key: &Key, reason: IsolationReason) {
+        tracing::warn!(client = ctx.id.0, key = key.as_str(), ?reason, "Isolated a broken subscription.");
+        self.metrics.record_isolation();
+        self.emit(DriverEvent::WatcherIsolated(ctx.id, reason));
+        if let Some(queue) = self.write_queue.get(&ctx.id) {
+            let packet = Packet::new(PacketId::zero(), PacketPayload::WatchIsolated { key: Cow::Owned(key.clone()), reason })
+                .with_namespace(namespace.to_string());
+            let _ = queue.try_send(packet.into());
+        }
+    }
+    fn emit(&self, event: DriverEvent) {
+        if let Some(hook) = &self.config.on_event {
+            hook(event);
+        }
+    }
+    /// Logs the request and hands it to the hook if the policy finds it
+    /// slow or large.
+    fn report_slow(&self, request: SlowRequest) {
+        let Some(policy) = &self.config.slow_requests else {
+            return;
+        };
+        if !policy.exceeded(request.duration, request.size) {
+            return;
+        }
+        tracing::warn!(
+            client = request.client.0,
+            operation = request.operation,
+            namespace = request.namespace,
+            key = request.key.as_ref().map(|f| f.as_str()),
+            duration_ms = request.duration.as_millis() as u64,
+            size = request.size,
+            "Served a slow or large request."
+        );
+        if let Some(hook) = &self.config.on_slow_request {
+            hook(&request);
+        }
     }
 }
 
@@ -40,316 +188,1654 @@ impl Driver {
         P: AsRef<Path>,
         S: AsRef<str>
     {
-        let internal = Rc::new(DriverInternal {
-            database: Database::new(path, name).await?,
-            stream: TcpListener::bind(addr).await?,
-            write_queue: DashMap::new(),
-        });
+        Self::start_with_config(addr, path, name, DriverConfig::default()).await
+    }
+    /// Starts the driver with a specific configuration.
+    pub async fn start_with_config<A, P, S>(addr: A, path: P, name: S, config: DriverConfig) -> Result<Self, NetworkError>
+    where 
+        A: tokio::net::ToSocketAddrs,
+        P: AsRef<Path>,
+        S: AsRef<str>
+    {
+        let namespaces = open_namespaces(&path, &name, &config).await?;
+        let shards = open_shards(path, name, &config).await?;
+        Ok(Self::launch(DriverInternal::new(Listener::tcp(addr).await?, namespaces, shards, config)))
+    }
+    /// Starts the driver on a Unix domain socket, which avoids the overhead
+    /// of TCP for agents on the same machine.
+    #[cfg(unix)]
+    pub async fn start_unix<U, P, S>(socket: U, path: P, name: S, config: DriverConfig) -> Result<Self, NetworkError>
+    where 
+        U: AsRef<Path>,
+        P: AsRef<Path>,
+        S: AsRef<str>
+    {
+        let namespaces = open_namespaces(&path, &name, &config).await?;
+        let shards = open_shards(path, name, &config).await?;
+        Ok(Self::launch(DriverInternal::new(Listener::unix(socket)?, namespaces, shards, config)))
+    }
+    /// Starts the driver with every accepted connection wrapped in TLS.
+    ///
+    /// The certificate chain and the private key are read from PEM files.
+    #[cfg(feature = "tls")]
+    pub async fn start_tls<A, C, K, P, S>(addr: A, cert: C, key: K, path: P, name: S, config: DriverConfig) -> Result<Self, NetworkError>
+    where 
+        A: tokio::net::ToSocketAddrs,
+        C: AsRef<Path>,
+        K: AsRef<Path>,
+        P: AsRef<Path>,
+        S: AsRef<str>
+    {
+        let acceptor = super::tls::acceptor(cert, key)?;
+        let namespaces = open_namespaces(&path, &name, &config).await?;
+        let shards = open_shards(path, name, &config).await?;
+        let mut internal = DriverInternal::new(Listener::tcp(addr).await?, namespaces, shards, config);
+        internal.tls = Some(acceptor);
+        Ok(Self::launch(internal))
+    }
+    /// Starts accepting connections on the listener.
+    fn launch(internal: DriverInternal) -> Self {
+        if let Some(level) = internal.config.log_level {
+            // Another subscriber may already be installed, it wins.
+            let _ = tracing_subscriber::fmt()
+                .with_max_level(level)
+                .with_target(false)
+                .try_init();
+        }
+        let internal = Rc::new(internal);
 
         monoio::spawn(accept_connection_loop(Rc::clone(&internal)));
+        monoio::spawn(sync_loop(Rc::clone(&internal)));
+        monoio::spawn(lock_expiry_loop(Rc::clone(&internal)));
+        monoio::spawn(system_refresh_loop(Rc::clone(&internal)));
 
-        Ok(Self {
-            internal: Rc::clone(&internal),
-        })
+        Self {
+            internal
+        }
     }
+    /// Shuts the driver down gracefully.
+    ///
+    /// This stops accepting connections, tells every client the server is closing,
+    /// kills all the watchers and flushes the database. It resolves once every
+    /// client task has exited.
+    pub async fn shutdown(&self) -> Result<(), NetworkError> {
+        let internal = &self.internal;
+        internal.shutdown.trigger();
+
+        let queues: Vec<_> = internal.write_queue.iter().map(|f| f.value().clone()).collect();
+        internal.write_queue.clear();
+        for queue in queues {
+            // A client that is not reading will simply miss the notice.
+            let _ = queue.try_send(Packet::new(PacketId::zero(), PacketPayload::ServerClosing).into());
+        }
+
+        for (_, database) in internal.namespaces.all() {
+            database.kill_watchers();
+            database.flush().await?;
+        }
+        if let Some(shards) = &internal.shards {
+            shards.shutdown().await?;
+        }
+        internal.shutdown.drained().await;
+        Ok(())
+    }
+    /// Tells every client to drop what it cached for keys under the prefix,
+    /// for use after changes made behind the back of the watchers.
+    pub fn invalidate(&self, prefix: &str) {
+        let internal = &self.internal;
+        for entry in internal.write_queue.iter() {
+            let packet = Packet::new(PacketId::zero(), PacketPayload::invalidate(prefix)).to_owned();
+            if entry.value().try_send(packet.into()).is_err() {
+                internal.emit(DriverEvent::NotificationDropped(*entry.key()));
+            }
+        }
+    }
+    /// Moves the store to a new location without stopping the server.
+    ///
+    /// If the default namespace is split, its shards move along with it,
+    /// see [ShardPool::migrate], and the report adds them up.
+    pub async fn migrate<P, S>(&self, path: P, name: S) -> Result<MigrationReport, NetworkError>
+    where 
+        P: AsRef<Path>,
+        S: AsRef<str>
+    {
+        let internal = &self.internal;
+        let mut report = internal.database.migrate(&path, &name).await?;
+        if let Some(shards) = &internal.shards {
+            report.merge(shards.migrate(&path, &name).await?);
+        }
+        Ok(report)
+    }
+    /// How many sampled keys of the default namespace sit in each storage tier.
+    pub async fn placement_stats(&self) -> Result<PlacementStats, NetworkError> {
+        self.internal.default_records().placement_stats().await
+    }
+    /// The most accessed keys of the default namespace with their estimated access counts.
+    pub async fn hottest_keys(&self, limit: usize) -> Result<Vec<(Key, u64)>, NetworkError> {
+        self.internal.default_records().hottest(limit).await
+    }
+    /// How often large notifications were encoded once and shared.
+    pub fn fanout_stats(&self) -> FanoutStats {
+        self.internal.encoder.stats()
+    }
+    /// The writes a second a throttled key took lately, [None] if it is not
+    /// throttled or was not written.
+    pub fn write_rate(&self, namespace: &str, key: &Key) -> Option<u32> {
+        self.internal.throttle.rate(namespace, key, Instant::now())
+    }
+    /// How many clients are served, have been at most and were turned away.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.internal.metrics.connections(self.internal.write_queue.len())
+    }
+    /// The counters collected while serving requests.
+    pub fn metrics(&self) -> &DriverMetrics {
+        &self.internal.metrics
+    }
+    /// Serves the metrics in the Prometheus text format at `/metrics`,
+    /// returning the port of the HTTP listener.
+    #[cfg(feature = "metrics")]
+    pub async fn serve_metrics<A>(&self, addr: A) -> Result<u16, NetworkError>
+    where 
+        A: tokio::net::ToSocketAddrs
+    {
+        let listener = super::metrics::http::bind(addr).await?;
+        let port = listener.local_addr()?.port();
+        let internal = Rc::clone(&self.internal);
+        monoio::spawn(async move {
+            loop {
+                let socket = tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok((socket, _)) => socket,
+                        Err(e) => {
+                            tracing::warn!("Could not accept a metrics connection: {e}");
+                            tokio::time::sleep(METRICS_ACCEPT_BACKOFF).await;
+                            continue;
+                        }
+                    },
+                    _ = internal.shutdown.triggered() => break
+                };
+                let internal = Rc::clone(&internal);
+                monoio::spawn(async move {
+                    let _ = super::metrics::http::serve(socket, async {
+                        let records = internal.default_records();
+                        let (storage, placement) = match (records.storage_stats().await, records.placement_stats().await) {
+                            (Ok(storage), Ok(placement)) => (storage, placement),
+                            (Err(e), _) | (_, Err(e)) => {
+                                tracing::warn!("Could not collect the figures of the shards: {e}");
+                                Default::default()
+                            }
+                        };
+                        internal.metrics.render(
+                            internal.namespaces.all().iter().map(|(_, f)| f.watcher_count()).sum(),
+                            internal.metrics.connections(internal.write_queue.len()),
+                            storage,
+                            placement,
+                            internal.encoder.stats()
+                        )
+                    }).await;
+                });
+            }
+        });
+        Ok(port)
+    }
+    /// The port the driver listens on.
+    ///
+    /// # Panics
+    /// If the driver was started on a Unix domain socket.
     pub fn port(&self) -> u16 {
-        self.internal.stream.local_addr().unwrap().port()
+        self.internal.listener.port().expect("The driver is not listening on TCP.")
+    }
+}
+
+/// Opens the namespaces, seeding the default one if it is fresh and creating any
+/// missing defaults from the manifest.
+async fn open_namespaces<P, S>(path: P, name: S, config: &DriverConfig) -> Result<Namespaces, NetworkError>
+where 
+    P: AsRef<Path>,
+    S: AsRef<str>
+{
+    let mut database = Database::new(&path, &name).await?;
+    database.set_placement_policy(config.placement);
+    database.set_durability_policy(config.durability.clone());
+    database.set_history_retention(config.history.clone());
+    database.set_split_policy(config.split.clone());
+    for field in &config.indexes {
+        database.declare_index(field);
+    }
+    if let Some(seed) = &config.seed {
+        database.seed(seed).await?;
+    }
+    if let Some(manifest) = &config.manifest {
+        if config.create_defaults {
+            database.apply_manifest_defaults(manifest).await?;
+        }
+    }
+    let mut namespaces = Namespaces::open(path, name, Rc::new(database), config.placement, config.durability.clone(), config.history.clone(), config.indexes.clone()).await?;
+    namespaces.set_quota_policy(config.quotas.clone());
+    Ok(namespaces)
+}
+
+/// Starts the threads of the default namespace if it is split into more
+/// than one shard, seeding them if they are fresh and creating any missing
+/// defaults from the manifest.
+async fn open_shards<P, S>(path: P, name: S, config: &DriverConfig) -> Result<Option<ShardPool>, NetworkError>
+where 
+    P: AsRef<Path>,
+    S: AsRef<str>
+{
+    if config.shards <= 1 {
+        return Ok(None);
+    }
+    tracing::info!("Splitting the default namespace across {} shards.", config.shards);
+    let mut shards = ShardPool::open(path, name, config.shards, ShardSettings {
+        placement: config.placement,
+        durability: config.durability.clone(),
+        history: config.history.clone(),
+        indexes: config.indexes.clone()
+    }).await?;
+    shards.set_split_policy(config.split.clone());
+    shards.set_quota(config.quotas.quota_of(DEFAULT_NAMESPACE))?;
+    if let Some(seed) = &config.seed {
+        shards.seed(seed).await?;
+    }
+    if let Some(manifest) = &config.manifest {
+        if config.create_defaults {
+            shards.apply_manifest_defaults(manifest).await?;
+        }
+    }
+    Ok(Some(shards))
+}
+
+/// Makes the writes that were not synced right away durable at the
+/// interval of the durability policy, until the driver shuts down.
+async fn sync_loop(internal: Rc<DriverInternal>) {
+    let mut interval = tokio::time::interval(internal.config.durability.interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = internal.shutdown.triggered() => return
+        }
+        for (namespace, database) in internal.namespaces.all() {
+            if let Err(e) = database.sync_pending().await {
+                tracing::warn!(namespace, "Could not sync the pending writes: {e}");
+            }
+        }
+        if let Some(shards) = &internal.shards {
+            if let Err(e) = shards.sync_pending().await {
+                tracing::warn!("Could not sync the pending writes of the shards: {e}");
+            }
+        }
+    }
+}
+
+/// Releases the locks whose time to live ran out, until the driver shuts down.
+async fn lock_expiry_loop(internal: Rc<DriverInternal>) {
+    let mut interval = tokio::time::interval(LOCK_EXPIRY_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = internal.shutdown.triggered() => return
+        }
+        for (_, database) in internal.namespaces.all() {
+            database.expire_locks().await;
+        }
+        if let Some(shards) = &internal.shards {
+            if let Err(e) = shards.expire_locks().await {
+                tracing::warn!("Could not expire the locks of the shards: {e}");
+            }
+        }
+    }
+}
+
+/// Tells the watchers of the system keys what changed, see [Database::refresh_system].
+async fn system_refresh_loop(internal: Rc<DriverInternal>) {
+    let mut interval = tokio::time::interval(SYSTEM_REFRESH_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = internal.shutdown.triggered() => return
+        }
+        internal.database.refresh_system().await;
+        if let Some(shards) = &internal.shards {
+            if let Err(e) = shards.refresh_system().await {
+                tracing::warn!("Could not refresh the system keys of the shards: {e}");
+            }
+        }
     }
 }
 
 async fn accept_connection_loop(internal: Rc<DriverInternal>) -> Result<(), NetworkError> {
     let mut counter = 0;
     loop {
-        let (sock, _) = internal.stream.accept().await?;
-        handle_client(sock, ClientId(counter), Rc::clone(&internal)).await;
+        tokio::select! {
+            accepted = accept_next(ClientId(counter), &internal) => accepted?,
+            _ = internal.shutdown.triggered() => return Ok(())
+        }
         counter += 1;
     }
 }
 
-async fn handle_client(
-    socket: TcpStream,
+/// Accepts the next connection on the listener.
+async fn accept_next(id: ClientId, internal: &Rc<DriverInternal>) -> Result<(), NetworkError> {
+    match &internal.listener {
+        Listener::Tcp(listener) => {
+            let (sock, _) = listener.accept().await?;
+            accept_client(sock, id, Rc::clone(internal)).await;
+        }
+        #[cfg(unix)]
+        Listener::Unix(listener) => {
+            let (sock, _) = listener.accept().await?;
+            accept_client(sock, id, Rc::clone(internal)).await;
+        }
+    }
+    Ok(())
+}
+
+/// Sets up the transport for a freshly accepted socket.
+async fn accept_client<S>(
+    socket: S,
     id: ClientId,
     internal: Rc<DriverInternal>,
-) {
-    println!("Spawning new client...");
-    let (read, write) = socket.into_split();
-    let (sender, receiver) = tokio::sync::mpsc::channel(250);
+)
+where 
+    S: AsyncRead + AsyncWrite + Unpin + 'static
+{
+    if let Some(max) = internal.config.max_connections {
+        if internal.write_queue.len() >= max {
+            match internal.config.connection_limit {
+                ConnectionLimitPolicy::Queue(bound) if internal.metrics.connections(max).queued < bound => {
+                    // The wait happens off the accept loop, which keeps turning away the rest.
+                    internal.metrics.record_queued(true);
+                    monoio::spawn(async move {
+                        let admitted = wait_for_slot(&internal, max).await;
+                        internal.metrics.record_queued(false);
+                        if admitted {
+                            admit_client(socket, id, internal).await;
+                        }
+                    });
+                }
+                ConnectionLimitPolicy::Close => reject_client(socket, &internal, false),
+                _ => reject_client(socket, &internal, true)
+            }
+            return;
+        }
+    }
+    admit_client(socket, id, internal).await;
+}
+
+/// Waits until fewer than the max connections are served, false if the
+/// driver shuts down first.
+async fn wait_for_slot(internal: &DriverInternal, max: usize) -> bool {
+    loop {
+        // Listening before checking, so a client closing in between is not missed.
+        let freed = internal.slots.notified();
+        if internal.write_queue.len() < max {
+            return true;
+        }
+        tokio::select! {
+            _ = freed => {}
+            _ = internal.shutdown.triggered() => return false
+        }
+    }
+}
+
+/// Turns away a connection over the limit, telling it why if asked to.
+fn reject_client<S>(mut socket: S, internal: &DriverInternal, tell: bool)
+where 
+    S: AsyncRead + AsyncWrite + Unpin + 'static
+{
+    internal.emit(DriverEvent::ConnectionRejected);
+    internal.metrics.record_rejection();
+    #[cfg(feature = "tls")]
+    let tell = tell && internal.tls.is_none();
+    if !tell {
+        // Dropping the socket closes it.
+        return;
+    }
+    monoio::spawn(async move {
+        let _ = Packet::new(PacketId::zero(), PacketPayload::ServerBusy).serialize(&mut socket).await;
+    });
+}
+
+/// Serves an accepted socket under the limit.
+async fn admit_client<S>(
+    socket: S,
+    id: ClientId,
+    internal: Rc<DriverInternal>,
+)
+where 
+    S: AsyncRead + AsyncWrite + Unpin + 'static
+{
+    #[cfg(feature = "tls")]
+    if let Some(acceptor) = internal.tls.clone() {
+        // Perform the handshake off the accept loop so a slow
+        // client cannot hold up everyone else.
+        monoio::spawn(async move {
+            match acceptor.accept(socket).await {
+                Ok(stream) => {
+                    let (read, write) = tokio::io::split(stream);
+                    handle_client(read, write, id, internal).await;
+                }
+                Err(e) => tracing::warn!(client = id.0, "TLS handshake failed: {e}")
+            }
+        });
+        return;
+    }
+
+    let (read, write) = tokio::io::split(socket);
+    handle_client(read, write, id, internal).await;
+}
+
+async fn handle_client<R, W>(
+    read: R,
+    write: W,
+    id: ClientId,
+    internal: Rc<DriverInternal>,
+)
+where 
+    R: LocalReadAsync + 'static,
+    W: LocalWriteAsync + 'static
+{
+    tracing::debug!(client = id.0, "Accepted a new client.");
+    let (sender, receiver) = tokio::sync::mpsc::channel(internal.config.queue_capacity);
     internal.write_queue.insert(id, sender);
+    internal.metrics.record_connections(internal.write_queue.len());
+    internal.system.set_connections(internal.write_queue.len());
     let ctx = Rc::new(ClientContext {
         id,
         watches: DashMap::new(),
+        deliveries: DashMap::new(),
+        shard_watches: DashSet::new(),
+        topics: DashMap::new(),
+        ephemeral: DashSet::new(),
+        // Without an authenticator every connection may do anything.
+        access: Cell::new(match internal.config.authenticator {
+            Some(..) => None,
+            None => Some(Access::ReadWrite)
+        }),
+        closing: Shutdown::default(),
+        last_active: Cell::new(Instant::now()),
+        version: Cell::new(CURRENT_VERSION),
+        session: Cell::new(None),
     });
-    monoio::spawn(handle_client_write(write, receiver));
-    monoio::spawn(handle_client_read(read, internal, ctx));
+    let span = tracing::info_span!("client", client = id.0);
+    let guard = internal.shutdown.track();
+    monoio::spawn({
+        let ctx = Rc::clone(&ctx);
+        async move {
+            let _guard = guard;
+            handle_client_write(write, receiver, ctx).await
+        }
+    }.instrument(span.clone()));
+    let guard = internal.shutdown.track();
+    monoio::spawn(async move {
+        let _guard = guard;
+        let mut ctx = ctx;
+        let result = handle_client_read(read, Rc::clone(&internal), &mut ctx).await;
+        if let Err(e) = &result {
+            tracing::warn!("Client connection failed: {e}");
+        }
+        // Only a lost connection is kept for the client to come back to.
+        if result.is_err() && detach_client(&internal, &ctx) {
+            tracing::debug!(detached = internal.sessions.len(), "Client disconnected, its session is kept.");
+            return result;
+        }
+        close_client(&internal, &ctx).await;
+        tracing::debug!("Client disconnected.");
+        result
+    }.instrument(span));
 }
 
 struct ClientContext {
     id: ClientId,
-    watches: DashMap<Key, Rc<Watcher<WatchClient>>>,
+    /// The subscriptions of the client by namespace and key.
+    watches: DashMap<(String, Key), Rc<Watcher<WatchClient>>>,
+    /// The watches of the client with acknowledged delivery by namespace
+    /// and key, their subscriptions are among the others.
+    deliveries: DashMap<(String, Key), Rc<AcknowledgedWatch>>,
+    /// The subscriptions of the client held by the shards.
+    shard_watches: DashSet<Key>,
+    /// The topics the client subscribed to by namespace and topic.
+    topics: DashMap<(String, Key), Rc<Watcher<WatchClient>>>,
+    /// The ephemeral keys the client inserted by namespace and key, deleted
+    /// once it disconnects.
+    ephemeral: DashSet<(String, Key)>,
+    /// What the connection may do, this is [None] until it authenticates.
+    access: Cell<Option<Access>>,
+    /// Triggered once the server closes the connection.
+    closing: Shutdown,
+    /// When the last packet arrived from the client.
+    last_active: Cell<Instant>,
+    /// The protocol version negotiated with the client, which is the
+    /// version of its packets capped at the version of the server.
+    version: Cell<u8>,
+    /// The session the client can resume the context with from another
+    /// connection, once it asked for one.
+    session: Cell<Option<u64>>,
+}
+
+impl ClientContext {
+    /// Checks if the connection may make this request.
+    fn permits(&self, payload: &PacketPayload<'_>) -> bool {
+        match self.access.get() {
+            None => false,
+            Some(access) => match payload {
+                PacketPayload::Insert { .. }
+                | PacketPayload::InsertAcknowledged { .. }
+                | PacketPayload::InsertEphemeral { .. }
+                | PacketPayload::GetOrInsert { .. }
+                | PacketPayload::InsertIfVersion { .. }
+                | PacketPayload::Increment { .. }
+                | PacketPayload::Delete { .. }
+                | PacketPayload::DeleteIf { .. }
+                | PacketPayload::Rename { .. }
+                | PacketPayload::Enqueue { .. }
+                | PacketPayload::Dequeue { .. }
+                | PacketPayload::Ack { .. }
+                | PacketPayload::Nack { .. }
+                | PacketPayload::Acquire { .. }
+                | PacketPayload::ReleaseLock { .. }
+                | PacketPayload::CreateNamespace { .. }
+                | PacketPayload::DropNamespace { .. }
+                | PacketPayload::Promote { .. }
+                | PacketPayload::RollbackPromotion { .. }
+                | PacketPayload::Publish { .. }
+                | PacketPayload::RegisterSchema { .. }
+                | PacketPayload::Archive { delete: true, .. }
+                | PacketPayload::Import { .. }
+                | PacketPayload::MultiInsert { .. }
+                | PacketPayload::NextSequence { .. } => access.can_write(),
+                _ => true
+            }
+        }
+    }
 }
 
-async fn handle_client_write(
-    mut socket: OwnedWriteHalf,
-    mut receiver: Receiver<Packet<'static>>,
+async fn handle_client_write<W: LocalWriteAsync>(
+    mut socket: W,
+    mut receiver: Receiver<Outgoing>,
+    ctx: Rc<ClientContext>,
 ) -> Result<(), NetworkError> {
-    loop {
-        let packet = receiver.recv().await.unwrap();
-        packet.serialize(&mut socket).await?;
+    while let Some(outgoing) = receiver.recv().await {
+        let mut buffer = vec![];
+        encode_outgoing(outgoing, &ctx, &mut buffer).await?;
+        // Whatever else is already queued goes out in the same write.
+        while buffer.len() < MAX_WRITE_BATCH {
+            match receiver.try_recv() {
+                Ok(outgoing) => encode_outgoing(outgoing, &ctx, &mut buffer).await?,
+                Err(..) => break
+            }
+        }
+        if !buffer.is_empty() {
+            socket.write_all(buffer).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Appends a queued packet to the write buffer of the client.
+async fn encode_outgoing(outgoing: Outgoing, ctx: &ClientContext, buffer: &mut Vec<u8>) -> Result<(), NetworkError> {
+    let packet = match outgoing {
+        Outgoing::Packet(packet) => packet,
+        Outgoing::Encoded(bytes) => {
+            // This was already encoded for the version of the client.
+            buffer.extend_from_slice(&bytes);
+            return Ok(());
+        }
+    };
+    // Every packet goes through here, so older clients never see
+    // anything encoded beyond the version they speak.
+    match packet.downgrade(ctx.version.get()) {
+        Ok(Some(packet)) => packet.serialize(buffer).await?,
+        Ok(None) => tracing::trace!("Suppressed a packet the client cannot decode."),
+        Err(e) => tracing::warn!("Could not send a packet to the client: {e}")
     }
+    Ok(())
 }
 
-async fn handle_client_read(
-    mut socket: OwnedReadHalf,
+/// Serves the requests of a connection, the context is replaced by the
+/// one of the session it resumes.
+async fn handle_client_read<R: LocalReadAsync>(
+    socket: R,
     internal: Rc<DriverInternal>,
-    ctx: Rc<ClientContext>,
+    ctx: &mut Rc<ClientContext>,
 ) -> Result<(), NetworkError> {
+    // The buffers a packet was decoded into are reused by the next one.
+    let mut socket = PooledReader::new(socket);
+    // The writer encodes for the version of the connection it was started with.
+    let connection = Rc::clone(ctx);
+    // Each packet is read whole into the frame and borrows from it.
+    let mut frame = vec![];
+    // A connection may only authenticate with its first packet.
+    let mut leading = true;
     loop {
-        let packet = Packet::deserialize(&mut socket).await?;
+        let packet = tokio::select! {
+            packet = Packet::deserialize_framed(&mut socket, &mut frame, internal.config.max_packet_size) => packet?,
+            _ = internal.shutdown.triggered() => return Ok(()),
+            _ = ctx.closing.triggered() => return Ok(()),
+            _ = idle_deadline(&internal, &ctx) => {
+                internal.emit(DriverEvent::IdleDisconnected(ctx.id));
+                return Ok(());
+            }
+        };
+        internal.metrics.record_buffer_pool(socket.take_stats());
+        ctx.last_active.set(Instant::now());
+        ctx.version.set(packet.version().min(CURRENT_VERSION));
+        connection.version.set(ctx.version.get());
         let packet_id = packet.id();
-        match packet.into_payload() {
-            PacketPayload::Insert { key, value } => {
-                internal.database.insert(key.clone(), (*value).clone()).await?;
-                internal.send(ctx.id, Packet::vreturn(packet_id, &*key, Some(&*value)).to_owned()).await;
-            }
-            PacketPayload::Get { key } => {
-                // let key = &**key;
-                let value = internal.database.get(&*key).await;
-                internal
-                    .send(ctx.id, Packet::vreturn(packet_id, &*key, value.as_deref()).to_owned())
-                    .await;
+        let namespace = packet.namespace().to_string();
+        let trace = packet.trace();
+        let payload = packet.into_payload();
+        let operation = payload.name();
+        let started = Instant::now();
+        internal.metrics.record_packet(operation);
+        let first = std::mem::replace(&mut leading, false);
+
+        if let PacketPayload::Auth { token } = &payload {
+            if !first {
+                // The connection keeps the access it has.
+                internal.send(ctx.id, Packet::auth_result(packet_id, false)).await;
+                continue;
+            }
+            let access = match &internal.config.authenticator {
+                Some(authenticator) => authenticator.authenticate(token),
+                None => Some(Access::ReadWrite)
+            };
+            ctx.access.set(access);
+            internal.send(ctx.id, Packet::auth_result(packet_id, access.is_some())).await;
+            if access.is_none() {
+                return Ok(());
             }
-            PacketPayload::Delete { key } => {
-                internal.database.delete(&*key).await?;
-                internal.send(ctx.id, Packet::get(packet_id, &*key).to_owned()).await;
+            continue;
+        }
+
+        if !ctx.permits(&payload) {
+            internal.send(ctx.id, Packet::auth_result(packet_id, false)).await;
+            if ctx.access.get().is_none() {
+                // The connection did not lead with authentication.
+                return Ok(());
+            }
+            continue;
+        }
+
+        if let Err(e) = check_keys(&internal.config.key_policy, &payload) {
+            let error = Packet::error(packet_id, &e);
+            // Older clients cannot decode the error, they are told the write was refused.
+            let reply = match ctx.version.get() >= error.payload().min_version() {
+                true => error.with_namespace(namespace),
+                false => Packet::auth_result(packet_id, false)
+            };
+            if !packet_id.is_notification() {
+                internal.send(ctx.id, reply).await;
+            }
+            continue;
+        }
+
+        let written: Vec<&Key> = match &payload {
+            PacketPayload::MultiInsert { entries } => entries.iter().map(|(key, _)| key).collect(),
+            payload => written_key(payload).into_iter().collect()
+        };
+        if let Some(retry_after) = written.into_iter().find_map(|key| internal.throttle.admit(&namespace, key, Instant::now()).err()) {
+            internal.metrics.record_throttled();
+            let throttled = PacketPayload::Throttled { retry_after };
+            // Older clients are told the write was refused.
+            let reply = match ctx.version.get() >= throttled.min_version() {
+                true => Packet::new(packet_id, throttled).with_namespace(namespace),
+                false => Packet::auth_result(packet_id, false)
+            };
+            internal.send(ctx.id, reply).await;
+            continue;
+        }
+
+        if let PacketPayload::Resume { session } = payload {
+            let (session, resumed) = resume_client(&internal, ctx, session).await;
+            internal.send(ctx.id, Packet::session(packet_id, session, resumed)).await;
+            continue;
+        }
+
+        if let PacketPayload::AckDelivery { key, sequence } = &payload {
+            if let Some(watch) = ctx.deliveries.get(&(namespace.clone(), (**key).clone())) {
+                watch.acknowledge(*sequence);
+            }
+            continue;
+        }
+
+        if let PacketPayload::Extension { opcode, extension, payload } = &payload {
+            let response = match internal.config.extensions.get(extension) {
+                Some(handler) => {
+                    let response = handler.handle(ExtensionRequest {
+                        client: ctx.id,
+                        access: ctx.access.get().unwrap_or(Access::ReadOnly),
+                        namespace: &namespace,
+                        opcode: *opcode,
+                        payload
+                    });
+                    PacketPayload::extension(response.opcode.max(FIRST_EXTENSION_DISCRIMINATOR), *extension, response.payload)
+                }
+                None => PacketPayload::UnsupportedExtension { extension: *extension }
+            };
+            if !packet_id.is_notification() {
+                internal.send(ctx.id, Packet::new(packet_id, response).with_namespace(namespace.clone())).await;
+            }
+            internal.metrics.record_latency(operation, started.elapsed());
+            continue;
+        }
+
+        let span = tracing::debug_span!("packet", id = packet_id.id(), kind = operation, trace);
+        let payload = payload.to_owned();
+        // Older clients send the packet unframed, so its size is not known.
+        let size = match ctx.version.get() >= FRAMED_VERSION {
+            true => frame.len(),
+            false => 0
+        };
+        let key = internal.config.slow_requests.and_then(|_| addressed_key(&payload).cloned());
+        let policed = match &internal.config.write_policy {
+            Some(policy) => police_writes(&**policy, ctx.id, &namespace, payload),
+            None => Ok(payload)
+        };
+        // The changes the request makes tag the notifications they cause with its trace.
+        let result = match policed {
+            Err(e) => Err(e),
+            Ok(payload) => traced(trace, handle_packet(&internal, ctx, packet_id, namespace.clone(), payload)).instrument(span).await
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to serve a {operation} request: {e}");
+            let error = Packet::error(packet_id, &e);
+            let close = match matches!(e, NetworkError::Protocol(ProtocolError::UnexpectedPacket(..))) {
+                true => {
+                    internal.emit(DriverEvent::ProtocolViolation(ctx.id));
+                    match internal.config.protocol_violation {
+                        ProtocolViolationPolicy::Ignore => continue,
+                        ProtocolViolationPolicy::Reply => false,
+                        ProtocolViolationPolicy::Close => true
+                    }
+                }
+                // Older clients cannot decode the error, they are disconnected.
+                false if ctx.version.get() < error.payload().min_version() => return Err(e),
+                false => false
+            };
+            if !packet_id.is_notification() {
+                internal.send(ctx.id, error.with_namespace(namespace.clone())).await;
+            }
+            if close {
+                // The writer sends what is queued before the connection closes.
+                return Ok(());
+            }
+        }
+        let elapsed = started.elapsed();
+        internal.metrics.record_latency(operation, elapsed);
+        internal.report_slow(SlowRequest { client: ctx.id, operation, namespace, key, duration: elapsed, size });
+    }
+}
+
+/// Checks the keys a write creates against the policy, the keys that are
+/// only read are left alone. No key under [SYSTEM_PREFIX] is written.
+fn check_keys(policy: &KeyPolicy, payload: &PacketPayload<'_>) -> Result<(), NetworkError> {
+    let check = |key: &Key| match is_system_key(key) {
+        true => Err(NetworkError::InvalidKey(format!("The prefix {SYSTEM_PREFIX} is reserved for the server"))),
+        false => policy.check(key.as_str())
+    };
+    if let PacketPayload::Import { records } | PacketPayload::MultiInsert { entries: records } = payload {
+        return records.iter().try_for_each(|(key, _)| check(key));
+    }
+    if let PacketPayload::Rename { from, .. } = payload {
+        if is_system_key(from) {
+            return check(from);
+        }
+    }
+    match written_key(payload) {
+        Some(key) if matches!(payload, PacketPayload::Delete { .. } | PacketPayload::DeleteIf { .. }) && !is_system_key(key) => Ok(()),
+        Some(key) => check(key),
+        None => Ok(())
+    }
+}
+
+/// The key a write changes, for the throttle.
+fn written_key<'p>(payload: &'p PacketPayload<'_>) -> Option<&'p Key> {
+    match payload {
+        PacketPayload::Insert { key, .. }
+        | PacketPayload::InsertAcknowledged { key, .. }
+        | PacketPayload::InsertEphemeral { key, .. }
+        | PacketPayload::GetOrInsert { key, .. }
+        | PacketPayload::InsertIfVersion { key, .. }
+        | PacketPayload::Increment { key, .. }
+        | PacketPayload::Delete { key }
+        | PacketPayload::DeleteIf { key, .. }
+        | PacketPayload::Acquire { key, .. }
+        | PacketPayload::Rename { to: key, .. } => Some(key),
+        PacketPayload::Enqueue { queue: key, .. } | PacketPayload::Publish { topic: key, .. } => Some(key),
+        _ => None
+    }
+}
+
+/// The key a request addresses, for the slow request reports. [None] for
+/// the requests spanning several keys.
+fn addressed_key<'p>(payload: &'p PacketPayload<'_>) -> Option<&'p Key> {
+    match payload {
+        PacketPayload::Get { key }
+        | PacketPayload::GetMeta { key }
+        | PacketPayload::Watch { key, .. }
+        | PacketPayload::WatchSnapshot { key, .. }
+        | PacketPayload::WatchAcknowledged { key, .. }
+        | PacketPayload::Release { key }
+        | PacketPayload::KeyHistory { key, .. }
+        | PacketPayload::History { key, .. }
+        | PacketPayload::GetAt { key, .. }
+        | PacketPayload::Acquire { key, .. }
+        | PacketPayload::ReleaseLock { key }
+        | PacketPayload::Ack { key, .. }
+        | PacketPayload::Nack { key, .. }
+        | PacketPayload::Dequeue { queue: key, .. }
+        | PacketPayload::SubscribeTopic { topic: key }
+        | PacketPayload::UnsubscribeTopic { topic: key } => Some(key),
+        payload => written_key(payload)
+    }
+}
+
+/// Moves the connection over to the detached session, or issues the
+/// connection a new session if that one is gone. Returns the session of
+/// the connection and whether it was resumed.
+async fn resume_client(internal: &Rc<DriverInternal>, ctx: &mut Rc<ClientContext>, session: Option<u64>) -> (u64, bool) {
+    let detached = session.and_then(|f| internal.sessions.resume(f, Instant::now()));
+    let Some((resumed, mut buffered)) = detached else {
+        let session = internal.sessions.issue();
+        ctx.session.set(Some(session));
+        return (session, false);
+    };
+    let Some((_, queue)) = internal.write_queue.remove(&ctx.id) else {
+        // The connection was closed or the driver is shutting down.
+        close_client(internal, &resumed).await;
+        return (resumed.session.get().unwrap_or_default(), false);
+    };
+    // What was queued while the session was detached goes out first, the
+    // queue has the same capacity so it all fits unless the client is
+    // already behind on this connection.
+    while let Ok(outgoing) = buffered.try_recv() {
+        let _ = queue.try_send(outgoing);
+    }
+    internal.write_queue.insert(resumed.id, queue);
+    resumed.access.set(ctx.access.get());
+    resumed.version.set(ctx.version.get());
+    resumed.last_active.set(Instant::now());
+    // The lost connection may have swallowed deliveries that were never acknowledged.
+    for watch in resumed.deliveries.iter() {
+        watch.rewind();
+    }
+    tracing::debug!(client = resumed.id.0, "Resumed a session.");
+
+    // Whatever the connection did before it resumed is dropped.
+    let previous = std::mem::replace(ctx, resumed);
+    close_client(internal, &previous).await;
+    (ctx.session.get().unwrap_or_default(), true)
+}
+
+/// Keeps the context of a client whose connection was lost for the grace
+/// period, returning false if it has no session to keep it under.
+///
+/// The notifications for the client pile up in a fresh queue until it
+/// resumes the session, and the slow consumer policy applies once that is full.
+fn detach_client(internal: &Rc<DriverInternal>, ctx: &Rc<ClientContext>) -> bool {
+    let (Some(session), Some(grace)) = (ctx.session.get(), internal.config.session_grace) else {
+        return false;
+    };
+    if internal.shutdown.is_closing() || ctx.closing.is_closing() {
+        return false;
+    }
+    // Replacing the queue lets the writer of the lost connection exit.
+    let (sender, receiver) = tokio::sync::mpsc::channel(internal.config.queue_capacity);
+    internal.write_queue.insert(ctx.id, sender);
+    internal.sessions.detach(session, (Rc::clone(ctx), receiver), Instant::now() + grace);
+    monoio::spawn(expire_session(Rc::clone(internal), session, grace));
+    true
+}
+
+/// Closes the client of a detached session that was not resumed in time.
+async fn expire_session(internal: Rc<DriverInternal>, session: u64, grace: Duration) {
+    tokio::select! {
+        _ = tokio::time::sleep(grace) => {}
+        _ = internal.shutdown.triggered() => return
+    }
+    if let Some((ctx, _)) = internal.sessions.expire(session, Instant::now()) {
+        tracing::debug!(client = ctx.id.0, "A session expired.");
+        close_client(&internal, &ctx).await;
+    }
+}
+
+/// Serves a single request once it has passed the access checks.
+/// Serves a request, the ones about the records of the default namespace
+/// going to the shard of their key when the store is sharded.
+async fn handle_packet(
+    internal: &Rc<DriverInternal>,
+    ctx: &Rc<ClientContext>,
+    packet_id: PacketId,
+    namespace: String,
+    payload: PacketPayload<'static>,
+) -> Result<(), NetworkError> {
+    let Some(database) = internal.namespaces.get(&namespace) else {
+        internal.send(ctx.id, Packet::new(packet_id, PacketPayload::UnknownNamespace).with_namespace(namespace)).await;
+        return Ok(());
+    };
+    let records = match &internal.shards {
+        Some(shards) if namespace == DEFAULT_NAMESPACE => Records::Sharded(shards),
+        _ => Records::Local(&database)
+    };
+    match payload {
+        PacketPayload::Insert { key, value } => {
+            let version = records.insert(&key, (*value).clone(), Some(ctx.id)).await?;
+            internal.send(ctx.id, Packet::vreturn(packet_id, &key, Some(&*value), version).to_owned().with_namespace(namespace.clone())).await;
+        }
+        PacketPayload::InsertEphemeral { key, value } => {
+            let version = records.insert(&key, (*value).clone(), Some(ctx.id)).await?;
+            ctx.ephemeral.insert((namespace.clone(), (*key).clone()));
+            internal.send(ctx.id, Packet::vreturn(packet_id, &key, Some(&*value), version).to_owned().with_namespace(namespace)).await;
+        }
+        PacketPayload::InsertAcknowledged { key, value, acknowledgement } => {
+            let version = records.insert_acknowledged(&key, (*value).clone(), acknowledgement, Some(ctx.id)).await?;
+            let Acknowledgement::Replicated(followers) = acknowledgement else {
+                internal.send(ctx.id, Packet::vreturn(packet_id, &key, Some(&*value), version).to_owned().with_namespace(namespace)).await;
+                return Ok(());
+            };
+            if let Records::Sharded(..) = records {
+                // The shards have no followers to wait on.
+                let response = match followers {
+                    0 => Packet::vreturn(packet_id, &key, Some(&*value), version).to_owned(),
+                    _ => Packet::new(packet_id, PacketPayload::AcknowledgementTimeout { key, version })
+                };
+                internal.send(ctx.id, response.with_namespace(namespace)).await;
+                return Ok(());
             }
-            PacketPayload::Watch {
-                key,
-                activity,
-                behaviour,
-            } => {
+            // The connection keeps serving requests while the write waits on the followers.
+            monoio::spawn({
+                let internal = Rc::clone(internal);
+                let ctx = Rc::clone(ctx);
+                let guard = internal.shutdown.track();
+                async move {
+                    let _guard = guard;
+                    let replicated = database.wait_replicated(version, followers as usize);
+                    let response = match tokio::time::timeout(internal.config.acknowledgement_timeout, replicated).await {
+                        Ok(()) => Packet::vreturn(packet_id, &key, Some(&*value), version).to_owned(),
+                        Err(_) => Packet::new(packet_id, PacketPayload::AcknowledgementTimeout { key, version })
+                    };
+                    internal.send(ctx.id, response.with_namespace(namespace)).await;
+                }
+            });
+        }
+        PacketPayload::InsertIfVersion { key, value, version } => {
+            let response = match records.insert_if_version(&key, (*value).clone(), version, Some(ctx.id)).await {
+                Ok(version) => Packet::vreturn(packet_id, &key, Some(&*value), version).to_owned(),
+                Err(NetworkError::VersionConflict(version)) => Packet::new(packet_id, PacketPayload::VersionConflict { key: key.clone(), version }),
+                Err(e) => return Err(e)
+            };
+            internal.send(ctx.id, response.with_namespace(namespace)).await;
+        }
+        PacketPayload::GetOrInsert { key, default } => {
+            let (value, version) = records.get_or_insert(&key, default.into_owned(), Some(ctx.id)).await?;
+            internal.send(ctx.id, Packet::vreturn(packet_id, &key, Some(&value), version).to_owned().with_namespace(namespace.clone())).await;
+        }
+        PacketPayload::Get { key } => {
+            let (value, version, meta) = match records.get_with_meta(&key).await? {
+                Some((value, version, meta)) => (Some(Cow::Owned(value)), version, meta),
+                None => (None, 0, None)
+            };
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::Return { key, value, version, meta }).with_namespace(namespace)).await;
+        }
+        PacketPayload::Increment { key, delta } => {
+            let (value, version) = records.increment(&key, delta, Some(ctx.id)).await?;
+            internal.send(ctx.id, Packet::vreturn(packet_id, &key, Some(&value), version).to_owned().with_namespace(namespace)).await;
+        }
+        PacketPayload::Delete { key } => {
+            records.delete(&key).await?;
+            internal.send(ctx.id, Packet::get(packet_id, &key).to_owned().with_namespace(namespace.clone())).await;
+        }
+        PacketPayload::DeleteIf { key, condition } => {
+            let deleted = records.delete_if(&key, condition).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::DeleteIfResult { deleted }).with_namespace(namespace)).await;
+        }
+        PacketPayload::Rename { from, to, overwrite } => {
+            let response = match records.rename(&from, &to, overwrite, Some(ctx.id)).await {
+                Ok(version) => Packet::vreturn(packet_id, &to, None, version.unwrap_or_default()).to_owned(),
+                Err(NetworkError::VersionConflict(version)) => Packet::new(packet_id, PacketPayload::VersionConflict { key: to.clone(), version }),
+                Err(e) => return Err(e)
+            };
+            internal.send(ctx.id, response.with_namespace(namespace.clone())).await;
+        }
+        PacketPayload::Watch {
+            key,
+            activity,
+            behaviour,
+            min_interval
+        } => match records {
+            Records::Local(database) => {
                 let wow = Rc::new(
-                    internal
-                        .database
-                        .subscribe(key.clone(), ctx.id, behaviour, activity)
+                    database
+                        .subscribe(key.clone(), ctx.id, behaviour, activity, internal.config.watcher_limit)
                         .await?,
                 );
-                ctx.watches.insert((*key).clone(), Rc::clone(&wow));
-                
+                ctx.watches.insert((namespace.clone(), (*key).clone()), Rc::clone(&wow));
+            
                 monoio::spawn({
                     let internal = Rc::clone(&internal);
                     let ctx = Rc::clone(&ctx);
                     let key = key.clone();
+                    let namespace = namespace.clone();
+                    let guard = internal.shutdown.track();
+                    let span = tracing::debug_span!("watcher", key = key.as_str());
                     async move {
-                        spawn_subscriber(&*key, wow, internal, ctx).await;
-                    }
+                        let _guard = guard;
+                        spawn_subscriber(&namespace, &key, wow, min_interval, internal, ctx).await;
+                    }.instrument(span)
                 });
-                internal.send(ctx.id, Packet::get(packet_id, &*key).to_owned()).await;
+                internal.send(ctx.id, Packet::get(packet_id, &key).to_owned().with_namespace(namespace.clone())).await;
+            }
+            Records::Sharded(shards) => {
+                let (_, _, changes) = shards.subscribe(&key, ctx.id, behaviour, activity, internal.config.watcher_limit).await?;
+                ctx.shard_watches.insert((*key).clone());
+                spawn_shard_forwarder(internal, ctx, &namespace, &key, changes, behaviour, min_interval);
+                internal.send(ctx.id, Packet::get(packet_id, &key).to_owned().with_namespace(namespace)).await;
             }
-            PacketPayload::Release { key } => {
-                if let Some(..) = ctx.watches.remove(&key) {
-                    internal.database.release(key.clone(), ctx.id).await?;
-                } else {
-                    // Key not present.
+        }
+        PacketPayload::WatchSnapshot { key, behaviour, min_interval } => match records {
+            Records::Local(database) => {
+                let (watcher, value, version) = database
+                    .subscribe_snapshot(&*key, ctx.id, behaviour, internal.config.watcher_limit)
+                    .await;
+                let watcher = Rc::new(watcher);
+                ctx.watches.insert((namespace.clone(), (*key).clone()), Rc::clone(&watcher));
+
+                // Queue the snapshot before the subscriber can queue any notification.
+                internal.send(ctx.id, Packet::snapshot(packet_id, &key, value.as_deref(), version).to_owned().with_namespace(namespace.clone())).await;
+                monoio::spawn({
+                    let internal = Rc::clone(&internal);
+                    let ctx = Rc::clone(&ctx);
+                    let namespace = namespace.clone();
+                    let guard = internal.shutdown.track();
+                    let span = tracing::debug_span!("watcher", key = key.as_str());
+                    async move {
+                        let _guard = guard;
+                        spawn_subscriber(&namespace, &key, watcher, min_interval, internal, ctx).await;
+                    }.instrument(span)
+                });
+            }
+            Records::Sharded(shards) => {
+                let (value, version, changes) = shards
+                    .subscribe(&key, ctx.id, behaviour, WatcherActivity::Lazy, internal.config.watcher_limit)
+                    .await?;
+                ctx.shard_watches.insert((*key).clone());
+                // Queue the snapshot before the forwarder can queue any notification.
+                internal.send(ctx.id, Packet::snapshot(packet_id, &key, value.as_ref(), version).to_owned().with_namespace(namespace.clone())).await;
+                spawn_shard_forwarder(internal, ctx, &namespace, &key, changes, behaviour, min_interval);
+            }
+        }
+        PacketPayload::WatchAcknowledged { key, after } => {
+            // The history of a sharded key is kept on the thread of its shard.
+            let Records::Local(database) = records else {
+                return Err(NetworkError::Unsupported("acknowledged watches on a sharded store".to_string()));
+            };
+            let (watcher, value, version) = database
+                .subscribe_snapshot(&*key, ctx.id, WatcherBehaviour::Eager, internal.config.watcher_limit)
+                .await;
+            let watcher = Rc::new(watcher);
+            let watched = (namespace.clone(), (*key).clone());
+            ctx.watches.insert(watched.clone(), Rc::clone(&watcher));
+            // A fresh watch starts from the snapshot, a resumed one from what the client acknowledged.
+            let (watch, response) = match after {
+                0 => (AcknowledgedWatch::new(version), Packet::snapshot(packet_id, &key, value.as_deref(), version).to_owned()),
+                after => (AcknowledgedWatch::new(after), Packet::get(packet_id, &key).to_owned())
+            };
+            let watch = Rc::new(watch);
+            ctx.deliveries.insert(watched, Rc::clone(&watch));
+
+            // Queue the response before the delivery can queue any change.
+            internal.send(ctx.id, response.with_namespace(namespace.clone())).await;
+            monoio::spawn({
+                let internal = Rc::clone(&internal);
+                let ctx = Rc::clone(&ctx);
+                let guard = internal.shutdown.track();
+                let span = tracing::debug_span!("delivery", key = key.as_str());
+                async move {
+                    let _guard = guard;
+                    spawn_delivery(&namespace, &key, watcher, watch, internal, ctx).await;
+                }.instrument(span)
+            });
+        }
+        PacketPayload::Release { key } => {
+            match records {
+                Records::Local(database) => {
+                    if ctx.watches.remove(&(namespace.clone(), (*key).clone())).is_some() {
+                        database.release(key.clone(), ctx.id).await?;
+                    }
+                }
+                Records::Sharded(shards) => {
+                    if ctx.shard_watches.remove(&*key).is_some() {
+                        shards.release(&key, ctx.id).await?;
+                    }
                 }
-                internal.send(ctx.id, Packet::get(packet_id, &*key).to_owned()).await;
             }
-            _ => unimplemented!(),
+            internal.send(ctx.id, Packet::get(packet_id, &key).to_owned().with_namespace(namespace.clone())).await;
+        }
+        PacketPayload::Enqueue { queue, value } => {
+            let (item, version) = records.enqueue(queue.as_str(), (*value).clone()).await?;
+            internal.send(ctx.id, Packet::vreturn(packet_id, &item, Some(&*value), version).to_owned().with_namespace(namespace.clone())).await;
+            wake_consumers(internal, &namespace, &queue, item);
+        }
+        PacketPayload::Dequeue { queue, visibility } => {
+            let item = records.dequeue(queue.as_str(), visibility).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::Leased { item }).with_namespace(namespace)).await;
+        }
+        PacketPayload::Ack { key, lease } => {
+            let accepted = records.ack(&key, lease).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::LeaseResult { accepted }).with_namespace(namespace)).await;
+        }
+        PacketPayload::Nack { key, lease } => {
+            let accepted = records.nack(&key, lease).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::LeaseResult { accepted }).with_namespace(namespace)).await;
+        }
+        PacketPayload::Acquire { key, ttl } => {
+            let token = records.acquire(&key, ctx.id, ttl).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::LockResult { token }).with_namespace(namespace)).await;
+        }
+        PacketPayload::ReleaseLock { key } => {
+            let accepted = records.release_lock(&key, ctx.id).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::LeaseResult { accepted }).with_namespace(namespace)).await;
+        }
+        PacketPayload::Publish { topic, value } => {
+            let receivers = internal.topics.publish(&namespace, &topic, value.into_owned());
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::Published { receivers: receivers as u64 }).with_namespace(namespace)).await;
+        }
+        PacketPayload::SubscribeTopic { topic } => {
+            let watcher = Rc::new(internal.topics.subscribe(&namespace, &topic, ctx.id, internal.config.watcher_limit));
+            ctx.topics.insert((namespace.clone(), (*topic).clone()), Rc::clone(&watcher));
+            internal.send(ctx.id, Packet::get(packet_id, &topic).to_owned().with_namespace(namespace.clone())).await;
+            monoio::spawn({
+                let internal = Rc::clone(&internal);
+                let ctx = Rc::clone(&ctx);
+                let guard = internal.shutdown.track();
+                let span = tracing::debug_span!("topic", topic = topic.as_str());
+                async move {
+                    let _guard = guard;
+                    spawn_topic_subscriber(&namespace, &topic, watcher, internal, ctx).await;
+                }.instrument(span)
+            });
+        }
+        PacketPayload::UnsubscribeTopic { topic } => {
+            if ctx.topics.remove(&(namespace.clone(), (*topic).clone())).is_some() {
+                internal.topics.unsubscribe(&namespace, &topic, ctx.id);
+            }
+            internal.send(ctx.id, Packet::get(packet_id, &topic).to_owned().with_namespace(namespace)).await;
+        }
+        PacketPayload::CheckManifest => {
+            let drift = match &internal.config.manifest {
+                Some(manifest) => records.check_manifest(manifest).await?,
+                None => vec![]
+            };
+            internal.send(ctx.id, Packet::manifest_report(packet_id, drift)).await;
+        }
+        PacketPayload::Ping => {
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::Pong)).await;
+        }
+        PacketPayload::CompactionHistory => {
+            let runs = records.compactions().await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::CompactionReport { runs })).await;
+        }
+        PacketPayload::KeyHistory { key, from, to } => {
+            let diff = records.key_diff(&key, from, to).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::KeyDiff { diff }).with_namespace(namespace)).await;
+        }
+        PacketPayload::History { key, limit } => {
+            let entries = records.history(&key, limit as usize).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::HistoryReport { entries }).with_namespace(namespace)).await;
+        }
+        PacketPayload::GetAt { key, at } => {
+            let value = records.value_at(&key, at).await?;
+            let payload = PacketPayload::ValueAt { available: value.is_some(), value: value.flatten() };
+            internal.send(ctx.id, Packet::new(packet_id, payload).with_namespace(namespace)).await;
+        }
+        PacketPayload::GetMeta { key } => match records {
+            Records::Local(database) => {
+                let meta = database.meta(&key).await;
+                let value = database.get(&*key).await.map(|f| Cow::Owned((*f).clone()));
+                internal.send(ctx.id, Packet::new(packet_id, PacketPayload::KeyMeta { key, value, meta }).with_namespace(namespace)).await;
+            }
+            Records::Sharded(..) => {
+                let record = records.get_with_meta(&key).await?;
+                let meta = KeyMeta {
+                    exists: record.is_some(),
+                    durability: internal.config.durability.class_of(&key),
+                    record: record.as_ref().and_then(|(_, _, meta)| *meta)
+                };
+                let value = record.map(|(value, _, _)| Cow::Owned(value));
+                internal.send(ctx.id, Packet::new(packet_id, PacketPayload::KeyMeta { key, value, meta }).with_namespace(namespace)).await;
+            }
+        }
+        PacketPayload::Archive { before, delete } => {
+            let records = records.archive(before, delete).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::Archived { records }).with_namespace(namespace)).await;
+        }
+        PacketPayload::Import { records: loaded } => {
+            let count = records.bulk_load(loaded, Some(ctx.id)).await? as u64;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::Imported { count }).with_namespace(namespace)).await;
+        }
+        PacketPayload::MultiInsert { entries } => {
+            let versions = records.insert_all(entries, Some(ctx.id)).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::MultiInserted { versions }).with_namespace(namespace)).await;
+        }
+        PacketPayload::ListKeys { cursor, limit } => {
+            let (keys, cursor) = records.keys(cursor.as_deref(), limit as usize).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::KeyPage { keys, cursor }).with_namespace(namespace)).await;
+        }
+        PacketPayload::FindKeys { filter, cursor, limit } => {
+            let (keys, cursor) = records.find_keys(&filter.compile()?, cursor.as_deref(), limit as usize).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::KeyPage { keys, cursor }).with_namespace(namespace)).await;
+        }
+        PacketPayload::Export { cursor, limit } => {
+            let (records, cursor) = records.export(cursor.as_deref(), limit as usize).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::ExportPage { records, cursor }).with_namespace(namespace)).await;
+        }
+        PacketPayload::ListChildren { key, cursor, limit } => {
+            let (children, cursor) = records.children(&key, cursor.as_deref(), limit as usize).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::Children { children, cursor }).with_namespace(namespace)).await;
+        }
+        PacketPayload::ListWatchers { key } => {
+            let watched = records.watched_keys(key.as_deref()).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::WatcherReport { watched }).with_namespace(namespace)).await;
+        }
+        PacketPayload::SnapshotGet { keys } => {
+            let values = records.snapshot_get(&keys).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::SnapshotValues { values }).with_namespace(namespace)).await;
+        }
+        PacketPayload::RegisterSchema { prefix, schema } => {
+            let previous = records.register_schema(&prefix, schema).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::SchemaRegistered { previous }).with_namespace(namespace)).await;
+        }
+        PacketPayload::ListSchemas => {
+            let schemas = records.schemas().await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::Schemas { schemas }).with_namespace(namespace)).await;
+        }
+        PacketPayload::GetStats => {
+            let stats = records.stats().await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::Stats { stats }).with_namespace(namespace)).await;
+        }
+        PacketPayload::NextSequence { name } => {
+            let value = records.next_sequence(&name).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::Sequence { value }).with_namespace(namespace)).await;
         }
+        PacketPayload::QueryByIndex { field, value } => {
+            let keys = records.query_index(&field, &value).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::IndexMatches { keys }).with_namespace(namespace)).await;
+        }
+        PacketPayload::CreateNamespace { name } => {
+            let changed = match internal.namespaces.create(&name).await {
+                Ok(changed) => changed,
+                Err(NetworkError::InvalidNamespace(reason)) => {
+                    tracing::debug!("Refused to create namespace {name}: {reason}");
+                    false
+                }
+                Err(e) => return Err(e)
+            };
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::NamespaceResult { changed })).await;
+        }
+        PacketPayload::DropNamespace { name } => {
+            let changed = internal.namespaces.drop_namespace(&name).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::NamespaceResult { changed })).await;
+        }
+        PacketPayload::Promote { promotion, dry_run } => {
+            match internal.namespaces.promote(&promotion, dry_run).await {
+                Ok(report) => {
+                    if report.id.is_some() {
+                        tracing::info!(source = %promotion.source, target = %promotion.target, "Promoted {} keys.", report.added.len() + report.changed.len() + report.removed.len());
+                    }
+                    internal.send(ctx.id, Packet::new(packet_id, PacketPayload::PromotionResult { report: Some(report) })).await;
+                }
+                Err(NetworkError::UnknownNamespace) => {
+                    internal.send(ctx.id, Packet::new(packet_id, PacketPayload::UnknownNamespace)).await;
+                }
+                Err(e) => return Err(e)
+            }
+        }
+        PacketPayload::RollbackPromotion { id } => {
+            let report = internal.namespaces.rollback_promotion(id).await?;
+            internal.send(ctx.id, Packet::new(packet_id, PacketPayload::PromotionResult { report })).await;
+        }
+        other => return Err(ProtocolError::UnexpectedPacket(other.name()).into()),
+    }
+    Ok(())
+}
+
+/// Tells the consumers waiting on a queue that an item arrived, by publishing
+/// its key to the [queue_topic] of the queue, which clients cannot publish to.
+fn wake_consumers(internal: &DriverInternal, namespace: &str, queue: &Key, item: Key) {
+    internal.topics.publish(namespace, &queue_topic(queue), Value::String(item.as_str().into()));
+}
+
+/// Resolves once the client has been idle for longer than the timeout.
+async fn idle_deadline(internal: &DriverInternal, ctx: &ClientContext) {
+    match internal.config.idle_timeout {
+        Some(timeout) => tokio::time::sleep_until((ctx.last_active.get() + timeout).into()).await,
+        None => pending().await
     }
 }
 
+/// Closes a client, the writer stops once the queued packets are flushed
+/// and the subscriptions of the client are released.
+async fn close_client(internal: &DriverInternal, ctx: &ClientContext) {
+    if ctx.closing.is_closing() {
+        return;
+    }
+    ctx.closing.trigger();
+    internal.write_queue.remove(&ctx.id);
+    internal.system.set_connections(internal.write_queue.len());
+    internal.slots.notify_one();
+    if let Some(session) = ctx.session.get() {
+        // The session cannot be resumed once its context is gone.
+        internal.sessions.forget(session);
+    }
+
+    let keys: Vec<(String, Key)> = ctx.watches.iter().map(|f| f.key().clone()).collect();
+    ctx.watches.clear();
+    for (namespace, key) in keys {
+        if let Some(database) = internal.namespaces.get(&namespace) {
+            let _ = database.release(key, ctx.id).await;
+        }
+    }
+
+    let topics: Vec<(String, Key)> = ctx.topics.iter().map(|f| f.key().clone()).collect();
+    ctx.topics.clear();
+    for (namespace, topic) in topics {
+        internal.topics.unsubscribe(&namespace, &topic, ctx.id);
+    }
+
+    for (_, database) in internal.namespaces.all() {
+        database.release_locks(ctx.id).await;
+    }
+
+    let ephemeral: Vec<(String, Key)> = ctx.ephemeral.iter().map(|f| f.key().clone()).collect();
+    ctx.ephemeral.clear();
+    for (namespace, key) in ephemeral {
+        let deleted = match (&internal.shards, internal.namespaces.get(&namespace)) {
+            (Some(shards), _) if namespace == DEFAULT_NAMESPACE => shards.delete_written_by(&key, ctx.id).await,
+            (_, Some(database)) => database.delete_written_by(&key, ctx.id).await,
+            // The namespace was dropped along with the key.
+            (_, None) => Ok(false)
+        };
+        if let Err(e) = deleted {
+            tracing::warn!(key = key.as_str(), "Could not delete an ephemeral key: {e}");
+        }
+    }
+
+    if let Some(shards) = &internal.shards {
+        let keys: Vec<Key> = ctx.shard_watches.iter().map(|f| f.key().clone()).collect();
+        ctx.shard_watches.clear();
+        for key in keys {
+            let _ = shards.release(&key, ctx.id).await;
+        }
+        let _ = shards.release_locks(ctx.id).await;
+    }
+}
+
+/// Resolves once a watch of the client has been sent nothing for the
+/// heartbeat interval, never if heartbeats are off or the client is too old
+/// to tell them from a delete.
+async fn heartbeat_due(internal: &DriverInternal, ctx: &ClientContext) {
+    match internal.config.watch_heartbeat.filter(|_| ctx.version.get() >= 32) {
+        Some(interval) => monoio::time::sleep(interval).await,
+        None => pending().await
+    }
+}
+
+/// Tells the client its watch of the key is alive. A heartbeat the full
+/// queue drops is not missed, the next notification or heartbeat follows.
+async fn send_heartbeat(internal: &DriverInternal, ctx: &ClientContext, namespace: &str, key: &Key) -> Delivery {
+    let packet = Packet::new(PacketId::zero(), PacketPayload::heartbeat(key))
+        .to_owned()
+        .with_namespace(namespace.to_string());
+    internal.notify(ctx, packet.into()).await
+}
+
 /// Handles watchng for a certain key.
 async fn spawn_subscriber(
+    namespace: &str,
     key: &Key,
     watcher: Rc<Watcher<WatchClient>>,
+    min_interval: Option<Duration>,
     internal: Rc<DriverInternal>,
     ctx: Rc<ClientContext>,
 ) {
+    let mut health = SubscriptionHealth::new(internal.config.poison);
     loop {
-        let val = watcher.wait().await;
+        let first = tokio::select! {
+            first = watcher.wait() => first,
+            _ = heartbeat_due(&internal, &ctx) => {
+                if watcher.is_killed() {
+                    break;
+                }
+                if let Delivery::Closed = send_heartbeat(&internal, &ctx, namespace, key).await {
+                    return;
+                }
+                continue;
+            }
+        };
+        let mut batch = vec![first];
+        // Everything an ordered watcher queued during a burst goes out
+        // together, flagged so the client knows more of the batch follows.
+        batch.extend(watcher.drain());
+        let missed = watcher.take_missed();
+        let mut failed = false;
+        if missed > 0 {
+            // Tell the client before the changes that survived the overflow.
+            let closed = watcher.is_killed();
+            let packet = Packet::new(PacketId::zero(), PacketPayload::WatchOverflow { key: Cow::Owned(key.clone()), missed, closed })
+                .with_namespace(namespace.to_string());
+            match internal.notify(&ctx, packet.into()).await {
+                Delivery::Queued => {}
+                Delivery::Dropped => failed = true,
+                Delivery::Closed => return
+            }
+        }
         if watcher.is_killed() {
             // Break this and die.
             break;
         }
-        internal
-            .send(ctx.id, Packet::notify(PacketId::zero(), key, val.as_deref(), false).to_owned())
-            .await;
+        tracing::trace!(batch = batch.len(), "Notifying the watcher.");
+        let last = batch.len() - 1;
+        let database = internal.namespaces.get(namespace);
+        for (i, val) in batch.into_iter().enumerate() {
+            let trace = database.as_ref().and_then(|f| f.trace_of(key, val.as_ref()));
+            let packet = match internal.encoder.encode(namespace, key, val.as_ref(), i < last, ctx.version.get(), trace).await {
+                Ok(Some(packet)) => packet,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!("Could not encode a notification: {e}");
+                    failed = true;
+                    continue;
+                }
+            };
+            match internal.notify(&ctx, packet).await {
+                Delivery::Queued => {}
+                Delivery::Dropped => failed = true,
+                Delivery::Closed => {
+                    tracing::debug!("Dropped the watcher of a slow consumer.");
+                    return;
+                }
+            }
+        }
+        if let Some(reason) = health.record(failed, missed > 0, Instant::now()) {
+            internal.isolate(&ctx, namespace, key, reason);
+            // The client may have watched the key again since, that watcher stays.
+            let ours = ctx.watches
+                .remove_if(&(namespace.to_string(), key.clone()), |_, f| Rc::ptr_eq(f, &watcher))
+                .is_some();
+            if let Some(database) = internal.namespaces.get(namespace).filter(|_| ours) {
+                let _ = database.release(key, ctx.id).await;
+            }
+            return;
+        }
+        if let Some(interval) = min_interval {
+            // The changes made meanwhile wait in the watcher, where an eager
+            // one keeps the latest and an ordered one queues them all.
+            monoio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// Sends the changes of a key watched with acknowledged delivery, read from
+/// its history whenever the key changes or the session is resumed.
+///
+/// A change the full queue of the client drops is sent again with the next
+/// one, the client tells apart the ones it already got by their sequence.
+async fn spawn_delivery(
+    namespace: &str,
+    key: &Key,
+    watcher: Rc<Watcher<WatchClient>>,
+    watch: Rc<AcknowledgedWatch>,
+    internal: Rc<DriverInternal>,
+    ctx: Rc<ClientContext>,
+) {
+    while !watcher.is_killed() {
+        let Some(database) = internal.namespaces.get(namespace) else {
+            break;
+        };
+        let deliveries = match watch.pending(database.history(), key) {
+            Pending::Changes(changes) => changes.into_iter().map(|f| (f.value, f.version, false)).collect(),
+            Pending::Gap => vec![(database.get_versioned(key).map(|(value, _)| value), database.version(), true)]
+        };
+        for (value, sequence, gap) in deliveries {
+            if sequence <= watch.sent() {
+                continue;
+            }
+            let value = value.map(|f| Cow::Owned((*f).clone()));
+            let packet = Packet::new(PacketId::zero(), PacketPayload::Delivery { key: Cow::Owned(key.clone()), value, sequence, gap })
+                .with_namespace(namespace.to_string());
+            match internal.notify(&ctx, packet.into()).await {
+                Delivery::Queued => watch.mark_sent(sequence),
+                Delivery::Dropped => break,
+                Delivery::Closed => return
+            }
+        }
+        tokio::select! {
+            _ = watcher.wait() => {}
+            _ = watch.rewound() => {}
+        }
     }
+    ctx.deliveries.remove_if(&(namespace.to_string(), key.clone()), |_, f| Rc::ptr_eq(f, &watch));
 }
 
-#[cfg(test)]
-mod tests {
-    use std::{net::Ipv4Addr, rc::Rc, sync::Arc};
+/// Pushes the messages published to a topic to the client until it unsubscribes.
+///
+/// A message that does not fit the queue of the client is lost, topics
+/// make no promise of delivery.
+async fn spawn_topic_subscriber(
+    namespace: &str,
+    topic: &Key,
+    watcher: Rc<Watcher<WatchClient>>,
+    internal: Rc<DriverInternal>,
+    ctx: Rc<ClientContext>,
+) {
+    loop {
+        let mut batch = vec![watcher.wait().await];
+        batch.extend(watcher.drain());
+        if watcher.is_killed() {
+            break;
+        }
+        for value in batch.into_iter().flatten() {
+            let packet = Packet::new(PacketId::zero(), PacketPayload::topic_message(topic, &value))
+                .to_owned()
+                .with_namespace(namespace.to_string());
+            if let Delivery::Closed = internal.notify(&ctx, packet.into()).await {
+                return;
+            }
+        }
+    }
+}
 
-    use overseer::{
-        access::{WatcherActivity, WatcherBehaviour},
-        error::NetworkError,
-        models::{Key, Value}, network::{Packet, PacketId, PacketPayload}
-    };
-    use monoio::{
-        net::TcpStream,
-    };
-    use tokio::sync::Barrier;
-
-    use crate::net::Driver;
-
-    // #[monoio::test]
-    // pub async fn test_client_subscription() {
-    //     let td = tempfile::tempdir().unwrap();
-    //     let server = Driver::start("127.0.0.1:0", td.path(), "db").await.unwrap();
-
-    //     let staging = Rc::new(Barrier::new(2));
-    //     let staging2 = Rc::new(Barrier::new(2));
-
-    //     let handle = monoio::spawn({
-    //         let port = server.port();
-    //         let staging = Rc::clone(&staging);
-    //         let staging2 = Rc::clone(&staging2);
-    //         async move {
-    //             let mut connect = TcpStream::connect((Ipv4Addr::new(127, 0, 0, 1), port)).await?;
-
-    //             // Configure a lazy watch on the brokers key.
-    //             Packet::watch(PacketId::zero(), Key::from_str("brokers"), WatcherActivity::Lazy, WatcherBehaviour::Ordered)
-    //                 .write(&mut connect)
-    //                 .await?;
-    //             // Let us wait until this gets inserted.
-    //             if let PacketPayload::Get { key, .. } = Packet::read(&mut connect).await?.payload() {
-    //                 assert_eq!(key.as_str(), "brokers");
-    //                 // assert_eq!(value.as_ref().unwrap().as_integer().unwrap(), 145);
-    //             } else {
-    //                 panic!("Expected notify, received other type.");
-    //             }
-
-    //             monoio::spawn(async move {
-    //                 staging.wait().await;
-    //             });
-
-    //             // Let us wait until this gets inserted.
-    //             if let PacketPayload::Notify { key, value, .. } = Packet::read(&mut connect).await?.payload() {
-    //                 assert_eq!(key.as_str(), "brokers");
-    //                 assert_eq!(value.as_ref().unwrap().as_integer().unwrap(), 145);
-    //             } else {
-    //                 panic!("Expected notify, received other type.");
-    //             }
-
-    //             // Let us wait until this gets updated.
-    //             if let PacketPayload::Notify { key, value, .. } = Packet::read(&mut connect).await?.payload() {
-    //                 assert_eq!(key.as_str(), "brokers");
-    //                 assert_eq!(value.as_ref().unwrap().as_integer().unwrap(), 28);
-    //             } else {
-    //                 panic!("Expected notify, received other type.");
-    //             }
-
-    //             // Let us wait until this gets inserted.
-    //             if let PacketPayload::Notify { key, value, .. } = Packet::read(&mut connect).await?.payload() {
-    //                 assert_eq!(key.as_str(), "brokers");
-    //                 assert_eq!(*value, None);
-    //             } else {
-    //                 panic!("Expected notify, received other type.");
-    //             }
-
-    //             //
-
-    //             Packet::release(PacketId::zero(), Key::from_str("brokers")).write(&mut connect).await?;
-    //             matches!(Packet::read(&mut connect).await?.payload(), PacketPayload::Get { .. });
-
-    //             staging2.wait().await;
-
-    //             Ok::<(), NetworkError>(())
-    //         }
-    //     });
-
-    //     let handle2 = monoio::spawn({
-    //         let port = server.port();
-    //         let staging = Arc::clone(&staging);
-    //         async move {
-    //             let mut connect = TcpStream::connect((Ipv4Addr::new(127, 0, 0, 1), port)).await?;
-
-    //             staging.wait().await;
-
-    //             // Configure a lazy watch on the brokers key.
-    //             Packet::insert(PacketId::zero(), Key::from_str("brokers"), Value::Integer(145)).write(&mut connect).await?;
-    //             Packet::insert(PacketId::zero(), Key::from_str("brokers"), Value::Integer(28)).write(&mut connect).await?;
-    //             Packet::delete(PacketId::zero(), Key::from_str("brokers")).write(&mut connect).await?;
-
-    //             staging2.wait().await;
-
-    //             Packet::insert(PacketId::zero(), Key::from_str("brokers"), Value::Integer(13)).write(&mut connect).await?;
-
-    //             Ok::<(), NetworkError>(())
-    //         }
-    //     });
-
-    //     handle.await.unwrap().unwrap();
-    //     handle2.await.unwrap().unwrap();
-    // }
-
-    // #[monoio::test]
-    // pub async fn test_client_basic() {
-    //     let td = tempfile::tempdir().unwrap();
-    //     let server = Driver::start("127.0.0.1:0", td.path(), "db").await.unwrap();
-
-    //     let handle = monoio::spawn({
-    //         let port = server.port();
-    //         async move {
-    //             let mut connect = TcpStream::connect((Ipv4Addr::new(127, 0, 0, 1), port)).await?;
-
-    //             Packet::insert(PacketId::zero(), Key::from_str("hello"), Value::Integer(62)).write(&mut connect).await?;
-    //             // Packet::Insert {
-    //             //     key: Key::from_str("hello"),
-    //             //     value: Value::Integer(62),
-    //             // }
-    //             // .write(&mut connect)
-    //             // .await?;
-
-    //             match Packet::read(&mut connect).await?.payload() {
-    //                 PacketPayload::Return { .. } => {},
-    //                 packet => { panic!("Expected a return packet but received packet {:?}", packet) }
-    //             }
-                
-
-    //             // matches!(packet, Packet::Get { .. });
-
-    //             // Packet::Get {
-    //             //     key: Key::from_str("hello"),
-    //             // }
-    //             // .write(&mut connect)
-    //             // .await?;
-
-    //             Packet::get(PacketId::zero(), Key::from_str("hello")).write(&mut connect).await?;
-
-    //             if let PacketPayload::Return { key, value } = Packet::read(&mut connect).await?.payload() {
-    //                 assert_eq!(key.as_str(), "hello");
-    //                 assert_eq!(value.as_ref().unwrap().as_integer().unwrap(), 62);
-    //             } else {
-    //                 panic!("Incorrect packet type.");
-    //             }
-
-    //             // Try deleting a key.
-    //             Packet::delete(PacketId::zero(), Key::from_str("hello"))
-    //                 .write(&mut connect)
-    //                 .await?;
-    //             match Packet::read(&mut connect).await?.payload() {
-    //                 PacketPayload::Get { .. } => {},
-    //                 packet => { panic!("Expected a get packet but received packet {:?}", packet) }
-    //             }
-               
-
-    //             // Get the key back, should be deleted.
-    //             Packet::get(PacketId::zero(), Key::from_str("hello"))
-    //                 .write(&mut connect)
-    //                 .await?;
-    //             if let PacketPayload::Return { key, value } = Packet::read(&mut connect).await?.payload() {
-    //                 assert_eq!(key.as_str(), "hello");
-    //                 assert_eq!(*value, None);
-    //             } else {
-    //                 panic!("Incorrect packet type.");
-    //             }
-
-    //             Ok::<(), NetworkError>(())
-    //         }
-    //     });
-
-    //     handle.await.unwrap().unwrap();
-    // }
+/// Forwards the changes a shard sends for a key to the client, the way
+/// [spawn_subscriber] does for the watchers of the driver thread.
+fn spawn_shard_forwarder(
+    internal: &Rc<DriverInternal>,
+    ctx: &Rc<ClientContext>,
+    namespace: &str,
+    key: &Key,
+    mut changes: UnboundedReceiver<ShardChanges>,
+    behaviour: WatcherBehaviour,
+    min_interval: Option<Duration>,
+) {
+    let internal = Rc::clone(internal);
+    let ctx = Rc::clone(ctx);
+    let namespace = namespace.to_string();
+    let key = key.clone();
+    let guard = internal.shutdown.track();
+    let span = tracing::debug_span!("watcher", key = key.as_str());
+    monoio::spawn(async move {
+        let _guard = guard;
+        let mut health = SubscriptionHealth::new(internal.config.poison);
+        loop {
+            let mut batch = tokio::select! {
+                batch = changes.recv() => match batch {
+                    Some(batch) => batch,
+                    None => break
+                },
+                _ = heartbeat_due(&internal, &ctx) => {
+                    if let Delivery::Closed = send_heartbeat(&internal, &ctx, &namespace, &key).await {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            if min_interval.is_some() {
+                // Everything the shard sent while the forwarder waited out the interval.
+                while let Ok(later) = changes.try_recv() {
+                    batch.absorb(later, behaviour);
+                }
+            }
+            let mut failed = false;
+            if batch.missed > 0 {
+                let packet = Packet::new(PacketId::zero(), PacketPayload::WatchOverflow { key: Cow::Owned(key.clone()), missed: batch.missed, closed: batch.closed })
+                    .with_namespace(namespace.clone());
+                match internal.notify(&ctx, packet.into()).await {
+                    Delivery::Queued => {}
+                    Delivery::Dropped => failed = true,
+                    Delivery::Closed => return
+                }
+            }
+            if batch.closed {
+                return;
+            }
+            let last = batch.values.len().saturating_sub(1);
+            for (i, val) in batch.values.into_iter().enumerate() {
+                let val = val.map(Rc::new);
+                let trace = batch.traces.get(i).copied().flatten();
+                let packet = match internal.encoder.encode(&namespace, &key, val.as_ref(), i < last, ctx.version.get(), trace).await {
+                    Ok(Some(packet)) => packet,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        tracing::warn!("Could not encode a notification: {e}");
+                        failed = true;
+                        continue;
+                    }
+                };
+                match internal.notify(&ctx, packet).await {
+                    Delivery::Queued => {}
+                    Delivery::Dropped => failed = true,
+                    Delivery::Closed => {
+                        tracing::debug!("Dropped the watcher of a slow consumer.");
+                        return;
+                    }
+                }
+            }
+            if let Some(reason) = health.record(failed, batch.missed > 0, Instant::now()) {
+                internal.isolate(&ctx, &namespace, &key, reason);
+                if ctx.shard_watches.remove(&key).is_some() {
+                    if let Some(shards) = &internal.shards {
+                        let _ = shards.release(&key, ctx.id).await;
+                    }
+                }
+                return;
+            }
+            if let Some(interval) = min_interval {
+                monoio::time::sleep(interval).await;
+            }
+        }
+    }.instrument(span));
 }
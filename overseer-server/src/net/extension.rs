@@ -0,0 +1,62 @@
+use super::{Access, ClientId};
+
+
+/// A packet of a protocol extension sent by a client, see
+/// [overseer::network::PacketPayload::Extension].
+pub struct ExtensionRequest<'a> {
+    pub client: ClientId,
+    pub access: Access,
+    pub namespace: &'a str,
+    pub opcode: u8,
+    pub payload: &'a [u8]
+}
+
+/// The answer to an [ExtensionRequest], the opcode is raised into the
+/// range reserved for extensions if it is below it.
+pub struct ExtensionResponse {
+    pub opcode: u8,
+    pub payload: Vec<u8>
+}
+
+/// Serves the packets of one protocol extension, registered under its id
+/// with [super::DriverConfig::with_extension].
+///
+/// The extension decides what the access of the client permits, the driver
+/// only requires the client to be authenticated. The response is not sent
+/// to a client that sent the request as a notification.
+pub trait ExtensionHandler {
+    fn handle(&self, request: ExtensionRequest<'_>) -> ExtensionResponse;
+}
+
+impl<F> ExtensionHandler for F
+where 
+    F: Fn(ExtensionRequest<'_>) -> ExtensionResponse
+{
+    fn handle(&self, request: ExtensionRequest<'_>) -> ExtensionResponse {
+        (self)(request)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::net::{Access, ClientId};
+
+    use super::{ExtensionHandler, ExtensionRequest, ExtensionResponse};
+
+
+    #[test]
+    pub fn test_closure_extension_handler() {
+        let echo = |request: ExtensionRequest<'_>| ExtensionResponse {
+            opcode: request.opcode,
+            payload: match request.access.can_write() {
+                true => request.payload.to_vec(),
+                false => vec![]
+            }
+        };
+        let request = |access| ExtensionRequest { client: ClientId::from_id(1), access, namespace: "default", opcode: 230, payload: &[1, 2] };
+        let response = echo.handle(request(Access::ReadWrite));
+        assert_eq!((response.opcode, response.payload), (230, vec![1, 2]));
+        assert!(echo.handle(request(Access::ReadOnly)).payload.is_empty());
+    }
+}
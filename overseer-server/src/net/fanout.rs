@@ -0,0 +1,154 @@
+use std::{cell::{Cell, RefCell}, collections::HashMap, rc::{Rc, Weak}};
+
+use overseer::{error::NetworkError, models::{Key, Value}, network::{OverseerSerde, Packet, PacketId}};
+
+
+/// Values at least this many bytes long have their notifications encoded
+/// once and shared by every subscriber, smaller ones are cheaper to encode
+/// per connection than to look up.
+pub const SHARED_NOTIFY_THRESHOLD: usize = 1024;
+
+/// A packet waiting in the queue of a client.
+pub enum Outgoing {
+    Packet(Packet<'static>),
+    /// A notification already encoded for the version of the client.
+    Encoded(Rc<Vec<u8>>)
+}
+
+impl From<Packet<'static>> for Outgoing {
+    fn from(packet: Packet<'static>) -> Self {
+        Self::Packet(packet)
+    }
+}
+
+/// How much work the shared encodings saved.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct FanoutStats {
+    /// Notifications that were encoded to be shared.
+    pub encoded: u64,
+    /// Notifications that reused an earlier encoding.
+    pub shared: u64,
+    /// The bytes that did not have to be encoded again.
+    pub bytes_saved: u64
+}
+
+/// An encoding of the latest value of a key.
+struct Encoding {
+    /// The value the encoding is for, compared by identity.
+    value: Weak<Value>,
+    /// The trace the notification carries.
+    trace: Option<u64>,
+    bytes: Rc<Vec<u8>>
+}
+
+/// Encodes the notifications of large values once per change.
+///
+/// The memory backend hands every watcher of a key the same [Rc] of the new
+/// value, so an encoding is reused for as long as it is handed the same value.
+/// Encodings are kept per namespace, key, batching flag and protocol version,
+/// and forgotten once the value they were made for is gone.
+#[derive(Default)]
+pub struct NotifyEncoder {
+    encodings: RefCell<HashMap<(String, Key, bool, u8), Encoding>>,
+    encoded: Cell<u64>,
+    shared: Cell<u64>,
+    bytes_saved: Cell<u64>
+}
+
+impl NotifyEncoder {
+    /// Prepares the notification of a change for a client on the version.
+    ///
+    /// Returns [None] if the client cannot decode the notification.
+    pub async fn encode(
+        &self,
+        namespace: &str,
+        key: &Key,
+        value: Option<&Rc<Value>>,
+        more: bool,
+        version: u8,
+        trace: Option<u64>
+    ) -> Result<Option<Outgoing>, NetworkError> {
+        let packet = Packet::notify(PacketId::zero(), key, value.map(|f| &**f), more)
+            .to_owned()
+            .with_namespace(namespace.to_string())
+            .with_trace(trace);
+        let Some(value) = value.filter(|f| value_size(f) >= SHARED_NOTIFY_THRESHOLD) else {
+            return Ok(Some(Outgoing::Packet(packet)));
+        };
+
+        let slot = (namespace.to_string(), key.clone(), more, version);
+        if let Some(encoding) = self.encodings.borrow().get(&slot) {
+            if Weak::ptr_eq(&encoding.value, &Rc::downgrade(value)) && encoding.trace == trace {
+                self.shared.set(self.shared.get() + 1);
+                self.bytes_saved.set(self.bytes_saved.get() + encoding.bytes.len() as u64);
+                return Ok(Some(Outgoing::Encoded(Rc::clone(&encoding.bytes))));
+            }
+        }
+
+        let Some(packet) = packet.downgrade(version)? else {
+            return Ok(None);
+        };
+        let mut bytes = vec![];
+        packet.serialize(&mut bytes).await?;
+        let bytes = Rc::new(bytes);
+        self.encoded.set(self.encoded.get() + 1);
+
+        let mut encodings = self.encodings.borrow_mut();
+        encodings.retain(|_, f| f.value.strong_count() > 0);
+        encodings.insert(slot, Encoding { value: Rc::downgrade(value), trace, bytes: Rc::clone(&bytes) });
+        Ok(Some(Outgoing::Encoded(bytes)))
+    }
+    pub fn stats(&self) -> FanoutStats {
+        FanoutStats {
+            encoded: self.encoded.get(),
+            shared: self.shared.get(),
+            bytes_saved: self.bytes_saved.get()
+        }
+    }
+}
+
+/// Roughly how many bytes the value takes on the wire.
+fn value_size(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.len(),
+        Value::Integer(..) => 8,
+        Value::Map(fields) => fields.iter().map(|(name, value)| name.len() + value_size(value)).sum(),
+        Value::Json(json) => json.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use overseer::{models::{Key, Value}, network::{OverseerSerde, Packet, PacketPayload, CURRENT_VERSION, DEFAULT_NAMESPACE}};
+
+    use super::{NotifyEncoder, Outgoing, SHARED_NOTIFY_THRESHOLD};
+
+    #[monoio::test]
+    pub async fn test_shared_notify_encoding() {
+        let encoder = NotifyEncoder::default();
+        let key = Key::from_str("hello");
+        let large = Rc::new(Value::String("x".repeat(SHARED_NOTIFY_THRESHOLD).into()));
+
+        let Some(Outgoing::Encoded(first)) = encoder.encode(DEFAULT_NAMESPACE, &key, Some(&large), false, CURRENT_VERSION, None).await.unwrap() else {
+            panic!("Expected a shared encoding.");
+        };
+        let Some(Outgoing::Encoded(second)) = encoder.encode(DEFAULT_NAMESPACE, &key, Some(&large), false, CURRENT_VERSION, None).await.unwrap() else {
+            panic!("Expected a shared encoding.");
+        };
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(encoder.stats().encoded, 1);
+        assert_eq!(encoder.stats().shared, 1);
+        assert_eq!(encoder.stats().bytes_saved, first.len() as u64);
+
+        // The shared bytes decode to the notification.
+        let packet = Packet::deserialize(&mut first.as_slice()).await.unwrap();
+        assert!(matches!(packet.payload(), PacketPayload::Notify { value: Some(..), .. }));
+
+        // Small values are left to the writer of each client.
+        let small = Rc::new(Value::Integer(3));
+        let outgoing = encoder.encode(DEFAULT_NAMESPACE, &key, Some(&small), false, CURRENT_VERSION, None).await.unwrap();
+        assert!(matches!(outgoing, Some(Outgoing::Packet(..))));
+    }
+}
@@ -0,0 +1,37 @@
+use std::path::Path;
+
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+
+/// The socket the [super::Driver] accepts connections on.
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener)
+}
+
+impl Listener {
+    pub async fn tcp<A>(addr: A) -> std::io::Result<Self>
+    where 
+        A: tokio::net::ToSocketAddrs
+    {
+        Ok(Self::Tcp(TcpListener::bind(addr).await?))
+    }
+    #[cfg(unix)]
+    pub fn unix<P>(path: P) -> std::io::Result<Self>
+    where 
+        P: AsRef<Path>
+    {
+        Ok(Self::Unix(UnixListener::bind(path)?))
+    }
+    /// The port of a TCP listener.
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            Self::Tcp(listener) => Some(listener.local_addr().ok()?.port()),
+            #[cfg(unix)]
+            Self::Unix(..) => None
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::{cell::{Cell, RefCell}, collections::BTreeMap, fmt::Write, time::Duration};
+
+use overseer::network::BufferPoolStats;
+
+use crate::database::{PlacementStats, StorageSnapshot};
+
+use super::FanoutStats;
+
+
+/// The upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 9] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+
+/// A latency histogram with the cumulative layout Prometheus expects.
+#[derive(Default, Clone)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// How many clients the driver serves, see [super::Driver::connection_stats].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ConnectionStats {
+    /// The connections served along with the detached sessions.
+    pub current: usize,
+    /// The most that were ever served at once.
+    pub peak: usize,
+    /// The connections waiting for others to close.
+    pub queued: usize,
+    /// The connections closed for being over the limit.
+    pub rejected: u64
+}
+
+/// Counters collected by the driver as it serves requests.
+#[derive(Default)]
+pub struct DriverMetrics {
+    /// Packets received, keyed by packet name.
+    packets: RefCell<BTreeMap<&'static str, u64>>,
+    /// Time taken to handle each kind of request.
+    latency: RefCell<BTreeMap<&'static str, Histogram>>,
+    /// Subscriptions killed for being broken.
+    isolated: Cell<u64>,
+    /// Writes dropped for coming faster than their key allows.
+    throttled: Cell<u64>,
+    /// How often the connections decoded into a buffer they read into before.
+    buffer_pool: Cell<BufferPoolStats>,
+    peak_connections: Cell<usize>,
+    queued_connections: Cell<usize>,
+    rejected_connections: Cell<u64>
+}
+
+impl DriverMetrics {
+    pub fn record_packet(&self, name: &'static str) {
+        *self.packets.borrow_mut().entry(name).or_default() += 1;
+    }
+    pub fn record_latency(&self, name: &'static str, elapsed: Duration) {
+        self.latency.borrow_mut().entry(name).or_default().observe(elapsed);
+    }
+    pub fn record_isolation(&self) {
+        self.isolated.set(self.isolated.get() + 1);
+    }
+    pub fn record_throttled(&self) {
+        self.throttled.set(self.throttled.get() + 1);
+    }
+    /// How many writes were dropped by the throttle.
+    pub fn throttled(&self) -> u64 {
+        self.throttled.get()
+    }
+    pub fn record_buffer_pool(&self, stats: BufferPoolStats) {
+        let total = self.buffer_pool.get();
+        self.buffer_pool.set(BufferPoolStats { hits: total.hits + stats.hits, misses: total.misses + stats.misses });
+    }
+    /// Raises the peak to the connections served now.
+    pub fn record_connections(&self, current: usize) {
+        self.peak_connections.set(self.peak_connections.get().max(current));
+    }
+    pub fn record_rejection(&self) {
+        self.rejected_connections.set(self.rejected_connections.get() + 1);
+    }
+    /// Counts a connection into or out of the accept queue.
+    pub fn record_queued(&self, queued: bool) {
+        let count = self.queued_connections.get();
+        self.queued_connections.set(if queued { count + 1 } else { count - 1 });
+    }
+    /// The connection counts, given how many are served now.
+    pub fn connections(&self, current: usize) -> ConnectionStats {
+        ConnectionStats {
+            current,
+            peak: self.peak_connections.get().max(current),
+            queued: self.queued_connections.get(),
+            rejected: self.rejected_connections.get()
+        }
+    }
+    /// How often the receive buffers were reused across every connection.
+    pub fn buffer_pool(&self) -> BufferPoolStats {
+        self.buffer_pool.get()
+    }
+    /// How many subscriptions were killed for being broken.
+    pub fn isolated(&self) -> u64 {
+        self.isolated.get()
+    }
+    /// How many packets of a kind were received.
+    pub fn packets(&self, name: &str) -> u64 {
+        self.packets.borrow().get(name).copied().unwrap_or_default()
+    }
+    /// Renders the metrics in the Prometheus text format.
+    pub fn render(
+        &self,
+        watchers: usize,
+        connections: ConnectionStats,
+        storage: StorageSnapshot,
+        placement: PlacementStats,
+        fanout: FanoutStats
+    ) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# HELP overseer_packets_total Packets received by type.");
+        let _ = writeln!(out, "# TYPE overseer_packets_total counter");
+        for (name, count) in self.packets.borrow().iter() {
+            let _ = writeln!(out, "overseer_packets_total{{type=\"{name}\"}} {count}");
+        }
+
+        let _ = writeln!(out, "# HELP overseer_request_duration_seconds Time taken to handle a request.");
+        let _ = writeln!(out, "# TYPE overseer_request_duration_seconds histogram");
+        for (name, histogram) in self.latency.borrow().iter() {
+            for (bucket, bound) in histogram.buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(out, "overseer_request_duration_seconds_bucket{{op=\"{name}\",le=\"{bound}\"}} {bucket}");
+            }
+            let _ = writeln!(out, "overseer_request_duration_seconds_bucket{{op=\"{name}\",le=\"+Inf\"}} {}", histogram.count);
+            let _ = writeln!(out, "overseer_request_duration_seconds_sum{{op=\"{name}\"}} {}", histogram.sum);
+            let _ = writeln!(out, "overseer_request_duration_seconds_count{{op=\"{name}\"}} {}", histogram.count);
+        }
+
+        let _ = writeln!(out, "# HELP overseer_watchers Active watchers.");
+        let _ = writeln!(out, "# TYPE overseer_watchers gauge");
+        let _ = writeln!(out, "overseer_watchers {watchers}");
+
+        let _ = writeln!(out, "# HELP overseer_isolated_watchers_total Subscriptions killed for being broken.");
+        let _ = writeln!(out, "# TYPE overseer_isolated_watchers_total counter");
+        let _ = writeln!(out, "overseer_isolated_watchers_total {}", self.isolated.get());
+
+        let _ = writeln!(out, "# HELP overseer_throttled_writes_total Writes dropped for coming faster than their key allows.");
+        let _ = writeln!(out, "# TYPE overseer_throttled_writes_total counter");
+        let _ = writeln!(out, "overseer_throttled_writes_total {}", self.throttled.get());
+
+        let buffer_pool = self.buffer_pool.get();
+        let _ = writeln!(out, "# HELP overseer_receive_buffer_pool_hit_rate Ratio of packet reads served from a pooled buffer.");
+        let _ = writeln!(out, "# TYPE overseer_receive_buffer_pool_hit_rate gauge");
+        let _ = writeln!(out, "overseer_receive_buffer_pool_hit_rate {}", buffer_pool.hit_rate());
+
+        let _ = writeln!(out, "# HELP overseer_receive_buffer_allocations_total Packet reads that had to allocate a buffer.");
+        let _ = writeln!(out, "# TYPE overseer_receive_buffer_allocations_total counter");
+        let _ = writeln!(out, "overseer_receive_buffer_allocations_total {}", buffer_pool.misses);
+
+        let _ = writeln!(out, "# HELP overseer_storage_dirty Changes not yet flushed to disk.");
+        let _ = writeln!(out, "# TYPE overseer_storage_dirty gauge");
+        let _ = writeln!(out, "overseer_storage_dirty {}", storage.dirty);
+
+        let _ = writeln!(out, "# HELP overseer_storage_flushes_total Flushes of the store to disk.");
+        let _ = writeln!(out, "# TYPE overseer_storage_flushes_total counter");
+        let _ = writeln!(out, "overseer_storage_flushes_total {}", storage.flushes);
+
+        let _ = writeln!(out, "# HELP overseer_storage_flush_latency_seconds Mean time taken to flush the store.");
+        let _ = writeln!(out, "# TYPE overseer_storage_flush_latency_seconds gauge");
+        let _ = writeln!(out, "overseer_storage_flush_latency_seconds {}", storage.mean_flush_latency().as_secs_f64());
+
+        let _ = writeln!(out, "# HELP overseer_keys_by_tier Sampled keys in each storage tier.");
+        let _ = writeln!(out, "# TYPE overseer_keys_by_tier gauge");
+        let _ = writeln!(out, "overseer_keys_by_tier{{tier=\"hot\"}} {}", placement.hot);
+        let _ = writeln!(out, "overseer_keys_by_tier{{tier=\"warm\"}} {}", placement.warm);
+        let _ = writeln!(out, "overseer_keys_by_tier{{tier=\"cold\"}} {}", placement.cold);
+
+        let _ = writeln!(out, "# HELP overseer_shared_notifications_total Notifications that reused a shared encoding.");
+        let _ = writeln!(out, "# TYPE overseer_shared_notifications_total counter");
+        let _ = writeln!(out, "overseer_shared_notifications_total {}", fanout.shared);
+
+        let _ = writeln!(out, "# HELP overseer_shared_notification_bytes_total Bytes that did not have to be encoded again.");
+        let _ = writeln!(out, "# TYPE overseer_shared_notification_bytes_total counter");
+        let _ = writeln!(out, "overseer_shared_notification_bytes_total {}", fanout.bytes_saved);
+
+        let _ = writeln!(out, "# HELP overseer_connections Connected clients.");
+        let _ = writeln!(out, "# TYPE overseer_connections gauge");
+        let _ = writeln!(out, "overseer_connections {}", connections.current);
+
+        let _ = writeln!(out, "# HELP overseer_connections_peak The most clients connected at once.");
+        let _ = writeln!(out, "# TYPE overseer_connections_peak gauge");
+        let _ = writeln!(out, "overseer_connections_peak {}", connections.peak);
+
+        let _ = writeln!(out, "# HELP overseer_connections_queued Connections waiting for a slot.");
+        let _ = writeln!(out, "# TYPE overseer_connections_queued gauge");
+        let _ = writeln!(out, "overseer_connections_queued {}", connections.queued);
+
+        let _ = writeln!(out, "# HELP overseer_connections_rejected_total Connections closed for being over the limit.");
+        let _ = writeln!(out, "# TYPE overseer_connections_rejected_total counter");
+        let _ = writeln!(out, "overseer_connections_rejected_total {}", connections.rejected);
+        out
+    }
+}
+
+/// Serves `/metrics` over plain HTTP.
+#[cfg(feature = "metrics")]
+pub(crate) mod http {
+    use std::future::Future;
+
+    use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}};
+
+    /// Answers a single request with the body produced by `render`, which
+    /// is only awaited for `/metrics`.
+    pub async fn serve<F>(mut socket: TcpStream, render: F) -> std::io::Result<()>
+    where 
+        F: Future<Output = String>
+    {
+        let mut buffer = vec![0u8; 1024];
+        let read = socket.read(&mut buffer).await?;
+        let request = String::from_utf8_lossy(&buffer[..read]);
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+        let response = if request.starts_with("GET ") && path == "/metrics" {
+            let body = render.await;
+            format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())
+        } else {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        };
+        socket.write_all(response.as_bytes()).await?;
+        socket.shutdown().await
+    }
+
+    pub async fn bind<A>(addr: A) -> std::io::Result<TcpListener>
+    where 
+        A: tokio::net::ToSocketAddrs
+    {
+        TcpListener::bind(addr).await
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use overseer::network::BufferPoolStats;
+
+    use crate::{database::{PlacementStats, StorageSnapshot}, net::FanoutStats};
+
+    use super::DriverMetrics;
+
+
+    #[test]
+    pub fn test_render_metrics() {
+        let metrics = DriverMetrics::default();
+        metrics.record_packet("get");
+        metrics.record_packet("get");
+        metrics.record_latency("get", Duration::from_micros(700));
+        metrics.record_isolation();
+        metrics.record_throttled();
+        metrics.record_buffer_pool(BufferPoolStats { hits: 3, misses: 1 });
+        metrics.record_buffer_pool(BufferPoolStats { hits: 5, misses: 1 });
+
+        let storage = StorageSnapshot { dirty: 2, flushes: 4, flush_time: Duration::from_millis(8) };
+        let placement = PlacementStats { hot: 2, ..Default::default() };
+        let fanout = FanoutStats { shared: 4, bytes_saved: 4096, ..Default::default() };
+        metrics.record_connections(4);
+        metrics.record_rejection();
+        metrics.record_queued(true);
+        let text = metrics.render(3, metrics.connections(1), storage, placement, fanout);
+        assert_eq!(metrics.packets("get"), 2);
+        assert!(text.contains("overseer_packets_total{type=\"get\"} 2"));
+        assert!(text.contains("overseer_request_duration_seconds_bucket{op=\"get\",le=\"0.0005\"} 0"));
+        assert!(text.contains("overseer_request_duration_seconds_bucket{op=\"get\",le=\"0.001\"} 1"));
+        assert!(text.contains("overseer_watchers 3"));
+        assert!(text.contains("overseer_isolated_watchers_total 1"));
+        assert!(text.contains("overseer_throttled_writes_total 1"));
+        assert!(text.contains("overseer_receive_buffer_pool_hit_rate 0.8"));
+        assert!(text.contains("overseer_receive_buffer_allocations_total 2"));
+        assert!(text.contains("overseer_storage_dirty 2"));
+        assert!(text.contains("overseer_storage_flushes_total 4"));
+        assert!(text.contains("overseer_storage_flush_latency_seconds 0.002"));
+        assert!(text.contains("overseer_keys_by_tier{tier=\"hot\"} 2"));
+        assert!(text.contains("overseer_shared_notification_bytes_total 4096"));
+        assert!(text.contains("overseer_connections 1"));
+        assert!(text.contains("overseer_connections_peak 4"));
+        assert!(text.contains("overseer_connections_queued 1"));
+        assert!(text.contains("overseer_connections_rejected_total 1"));
+    }
+}
@@ -1,3 +1,28 @@
 
 mod driver;
+mod auth;
+mod config;
+mod listener;
+mod shutdown;
+mod metrics;
+mod fanout;
+mod poison;
+mod delivery;
+mod session;
+mod extension;
+mod throttle;
+mod slow;
+mod validation;
+#[cfg(feature = "tls")]
+mod tls;
 pub use crate::net::driver::*;
+pub use crate::net::auth::*;
+pub use crate::net::config::*;
+pub use crate::net::extension::*;
+pub use crate::net::metrics::{ConnectionStats, DriverMetrics};
+pub use crate::net::fanout::{FanoutStats, SHARED_NOTIFY_THRESHOLD};
+pub use crate::net::poison::PoisonPolicy;
+pub use crate::net::session::DEFAULT_SESSION_GRACE;
+pub use crate::net::throttle::ThrottlePolicy;
+pub use crate::net::slow::{SlowRequest, SlowRequestHook, SlowRequestPolicy};
+pub use crate::net::validation::{WritePolicy, WriteRequest, WriteVerdict};
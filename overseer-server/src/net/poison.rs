@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use overseer::access::IsolationReason;
+
+
+/// When the driver judges a subscription to be broken and kills it, so one
+/// consumer that cannot take its notifications does not hold up a hot key.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PoisonPolicy {
+    /// How many batches in a row may fail to be delivered.
+    pub max_failures: u32,
+    /// How long the queue of a subscription may stay full.
+    pub max_saturation: Duration
+}
+
+impl Default for PoisonPolicy {
+    fn default() -> Self {
+        Self {
+            max_failures: 16,
+            max_saturation: Duration::from_secs(30)
+        }
+    }
+}
+
+/// Tracks how the deliveries of a single subscription went.
+pub(crate) struct SubscriptionHealth {
+    policy: Option<PoisonPolicy>,
+    /// The batches in a row that failed to be delivered.
+    failures: u32,
+    /// Since when every batch found the queue full.
+    saturated_since: Option<Instant>
+}
+
+impl SubscriptionHealth {
+    pub fn new(policy: Option<PoisonPolicy>) -> Self {
+        Self {
+            policy,
+            failures: 0,
+            saturated_since: None
+        }
+    }
+    /// Records how a batch went, a batch is saturated if changes were lost
+    /// to a full queue and failed if a notification of it was not queued.
+    ///
+    /// Returns why the subscription should be isolated, if it should.
+    pub fn record(&mut self, failed: bool, saturated: bool, now: Instant) -> Option<IsolationReason> {
+        let policy = self.policy?;
+
+        self.failures = if failed { self.failures + 1 } else { 0 };
+        if !saturated {
+            self.saturated_since = None;
+        } else if self.saturated_since.is_none() {
+            self.saturated_since = Some(now);
+        }
+
+        if self.failures >= policy.max_failures {
+            Some(IsolationReason::DeliveryFailed)
+        } else if self.saturated_since.is_some_and(|since| now - since >= policy.max_saturation) {
+            Some(IsolationReason::Saturated)
+        } else {
+            None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use overseer::access::IsolationReason;
+
+    use super::{PoisonPolicy, SubscriptionHealth};
+
+    #[test]
+    pub fn test_subscription_health() {
+        let policy = PoisonPolicy { max_failures: 3, max_saturation: Duration::from_secs(10) };
+        let start = Instant::now();
+
+        let mut health = SubscriptionHealth::new(Some(policy));
+        assert_eq!(health.record(true, false, start), None);
+        assert_eq!(health.record(true, false, start), None);
+        // A delivered batch resets the count.
+        assert_eq!(health.record(false, false, start), None);
+        assert_eq!(health.record(true, false, start), None);
+        assert_eq!(health.record(true, false, start), None);
+        assert_eq!(health.record(true, false, start), Some(IsolationReason::DeliveryFailed));
+
+        let mut health = SubscriptionHealth::new(Some(policy));
+        assert_eq!(health.record(false, true, start), None);
+        assert_eq!(health.record(false, true, start + Duration::from_secs(9)), None);
+        assert_eq!(health.record(false, true, start + Duration::from_secs(10)), Some(IsolationReason::Saturated));
+
+        let mut health = SubscriptionHealth::new(None);
+        for _ in 0..10 {
+            assert_eq!(health.record(true, true, start + Duration::from_secs(60)), None);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::{cell::{Cell, RefCell}, collections::{hash_map::RandomState, HashMap}, hash::{BuildHasher, Hasher}, time::{Duration, Instant}};
+
+
+/// How long the session of a lost connection waits to be resumed.
+pub const DEFAULT_SESSION_GRACE: Duration = Duration::from_secs(10);
+
+/// A session whose connection was lost.
+struct Detached<T> {
+    state: T,
+    until: Instant
+}
+
+/// The sessions whose connections were lost, by the id the client resumes
+/// them with, until the grace period of each runs out.
+///
+/// The id is all a client needs to take over a session, so ids are drawn
+/// from a hash keyed afresh by every process rather than counted.
+pub(crate) struct Sessions<T> {
+    detached: RefCell<HashMap<u64, Detached<T>>>,
+    keys: RandomState,
+    issued: Cell<u64>
+}
+
+impl<T> Default for Sessions<T> {
+    fn default() -> Self {
+        Self { detached: RefCell::default(), keys: RandomState::new(), issued: Cell::new(0) }
+    }
+}
+
+impl<T> Sessions<T> {
+    /// A fresh session id, never zero.
+    pub fn issue(&self) -> u64 {
+        let issued = self.issued.get() + 1;
+        self.issued.set(issued);
+        let mut hasher = self.keys.build_hasher();
+        hasher.write_u64(issued);
+        hasher.finish().max(1)
+    }
+    /// Keeps the state of the session until the deadline.
+    pub fn detach(&self, id: u64, state: T, until: Instant) {
+        self.detached.borrow_mut().insert(id, Detached { state, until });
+    }
+    /// Takes the state of the session back, [None] if it is unknown or expired.
+    pub fn resume(&self, id: u64, now: Instant) -> Option<T> {
+        let mut detached = self.detached.borrow_mut();
+        match detached.get(&id) {
+            Some(session) if session.until > now => detached.remove(&id).map(|f| f.state),
+            _ => None
+        }
+    }
+    /// Takes the state of the session if its grace period ran out, it was
+    /// resumed and maybe detached again since otherwise.
+    pub fn expire(&self, id: u64, now: Instant) -> Option<T> {
+        let mut detached = self.detached.borrow_mut();
+        match detached.get(&id) {
+            Some(session) if session.until <= now => detached.remove(&id).map(|f| f.state),
+            _ => None
+        }
+    }
+    /// Drops the session whatever its deadline.
+    pub fn forget(&self, id: u64) -> Option<T> {
+        self.detached.borrow_mut().remove(&id).map(|f| f.state)
+    }
+    /// How many sessions are waiting to be resumed.
+    pub fn len(&self) -> usize {
+        self.detached.borrow().len()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, time::{Duration, Instant}};
+
+    use super::Sessions;
+
+    #[test]
+    pub fn test_sessions() {
+        let sessions = Sessions::default();
+        let ids: HashSet<u64> = (0..1000).map(|_| sessions.issue()).collect();
+        assert_eq!(ids.len(), 1000);
+        assert!(!ids.contains(&0));
+
+        let now = Instant::now();
+        let (first, second) = (sessions.issue(), sessions.issue());
+        sessions.detach(first, "first", now + Duration::from_secs(10));
+        sessions.detach(second, "second", now + Duration::from_secs(10));
+        assert_eq!(sessions.len(), 2);
+
+        // A session can be resumed once, and only inside its grace period.
+        assert_eq!(sessions.resume(first, now + Duration::from_secs(11)), None);
+        assert_eq!(sessions.resume(first, now), Some("first"));
+        assert_eq!(sessions.resume(first, now), None);
+
+        // A session that was resumed and detached again is not expired early.
+        sessions.detach(first, "first", now + Duration::from_secs(20));
+        assert_eq!(sessions.expire(first, now + Duration::from_secs(10)), None);
+        assert_eq!(sessions.expire(second, now + Duration::from_secs(10)), Some("second"));
+        assert_eq!(sessions.forget(first), Some("first"));
+        assert_eq!(sessions.len(), 0);
+    }
+}
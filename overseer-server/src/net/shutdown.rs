@@ -0,0 +1,93 @@
+use std::{cell::Cell, rc::Rc};
+
+use tokio::sync::Notify;
+
+
+/// Coordinates stopping the driver and waiting for the client
+/// tasks to exit.
+#[derive(Default)]
+pub(crate) struct Shutdown {
+    /// Set once shutdown has been requested.
+    closing: Cell<bool>,
+    /// Wakes tasks waiting for shutdown.
+    signal: Notify,
+    /// The number of client tasks that are still running.
+    tasks: Cell<usize>,
+    /// Wakes the shutdown once the last task exits.
+    drained: Notify
+}
+
+impl Shutdown {
+    pub fn is_closing(&self) -> bool {
+        self.closing.get()
+    }
+    /// Requests shutdown, waking everything waiting on [Shutdown::triggered].
+    pub fn trigger(&self) {
+        self.closing.set(true);
+        self.signal.notify_waiters();
+    }
+    /// Resolves once shutdown has been requested.
+    pub async fn triggered(&self) {
+        // Register before checking the flag so a trigger in between is not lost.
+        let notified = self.signal.notified();
+        if self.is_closing() {
+            return;
+        }
+        notified.await;
+    }
+    /// Registers a running task, it is released when the guard drops.
+    pub fn track(self: &Rc<Self>) -> TaskGuard {
+        self.tasks.set(self.tasks.get() + 1);
+        TaskGuard(Rc::clone(self))
+    }
+    /// Resolves once every tracked task has exited.
+    pub async fn drained(&self) {
+        loop {
+            let notified = self.drained.notified();
+            if self.tasks.get() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Marks a task as running until it is dropped.
+pub(crate) struct TaskGuard(Rc<Shutdown>);
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        let tasks = self.0.tasks.get() - 1;
+        self.0.tasks.set(tasks);
+        if tasks == 0 {
+            self.0.drained.notify_waiters();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::Shutdown;
+
+
+    #[monoio::test]
+    pub async fn test_shutdown_drains_tasks() {
+        let shutdown = Rc::new(Shutdown::default());
+        let guard = shutdown.track();
+
+        monoio::spawn({
+            let shutdown = Rc::clone(&shutdown);
+            async move {
+                shutdown.triggered().await;
+                drop(guard);
+            }
+        });
+
+        shutdown.trigger();
+        shutdown.drained().await;
+        assert!(shutdown.is_closing());
+    }
+}
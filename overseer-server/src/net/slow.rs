@@ -0,0 +1,76 @@
+use std::{rc::Rc, time::Duration};
+
+use overseer::models::Key;
+
+use super::ClientId;
+
+
+/// When a request is reported as slow or large, see
+/// [super::DriverConfig::with_slow_request_policy].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SlowRequestPolicy {
+    /// Requests that take this long or longer to serve are reported.
+    pub latency: Duration,
+    /// Requests whose body takes this many bytes or more are reported.
+    pub size: usize
+}
+
+impl Default for SlowRequestPolicy {
+    fn default() -> Self {
+        Self {
+            latency: Duration::from_millis(100),
+            size: 256 * 1024
+        }
+    }
+}
+
+impl SlowRequestPolicy {
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+    pub fn with_size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+    /// Whether a request that took this long and this many bytes is reported.
+    pub fn exceeded(&self, duration: Duration, size: usize) -> bool {
+        duration >= self.latency || size >= self.size
+    }
+}
+
+/// A request the [SlowRequestPolicy] reports, logged as a warning and
+/// handed to the hook of [super::DriverConfig::with_slow_request_hook].
+#[derive(Clone, PartialEq, Debug)]
+pub struct SlowRequest {
+    pub client: ClientId,
+    /// The name of the packet, see [overseer::network::PacketPayload::name].
+    pub operation: &'static str,
+    pub namespace: String,
+    /// The key the request addressed, [None] for requests spanning several.
+    pub key: Option<Key>,
+    /// How long the request took to serve, from when it was read.
+    pub duration: Duration,
+    /// The bytes of the body of the request, zero if the client speaks a
+    /// version from before the packets were framed.
+    pub size: usize
+}
+
+/// Handed the slow and large requests, see [super::DriverConfig::with_slow_request_hook].
+pub type SlowRequestHook = Rc<dyn Fn(&SlowRequest)>;
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SlowRequestPolicy;
+
+    #[test]
+    pub fn test_slow_request_policy() {
+        let policy = SlowRequestPolicy::default().with_latency(Duration::from_millis(10)).with_size(64);
+        assert!(!policy.exceeded(Duration::from_millis(9), 63));
+        assert!(policy.exceeded(Duration::from_millis(10), 0));
+        assert!(policy.exceeded(Duration::ZERO, 64));
+    }
+}
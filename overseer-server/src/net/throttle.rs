@@ -0,0 +1,150 @@
+use std::{cell::{Cell, RefCell}, collections::HashMap, time::{Duration, Instant}};
+
+use overseer::models::Key;
+
+
+/// Caps how often each key under some prefixes is written, so one runaway
+/// producer does not flood the watchers and the store for everyone else.
+///
+/// Every key gets its own budget, a key may take a burst of a second's
+/// worth of writes and then as many a second as its prefix allows. The
+/// longest prefix a key starts with decides its limit.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ThrottlePolicy {
+    /// The writes a second allowed to each key under a prefix.
+    pub prefixes: Vec<(String, u32)>
+}
+
+impl ThrottlePolicy {
+    /// Limits the keys under a prefix, replacing how the prefix was limited.
+    ///
+    /// # Panics
+    /// If no writes are allowed.
+    pub fn with_prefix<S>(mut self, prefix: S, writes_per_second: u32) -> Self
+    where
+        S: Into<String>
+    {
+        assert!(writes_per_second > 0, "A throttled key has to allow some writes.");
+        let prefix = prefix.into();
+        self.prefixes.retain(|(existing, _)| *existing != prefix);
+        self.prefixes.push((prefix, writes_per_second));
+        self
+    }
+    /// The writes a second allowed to the key, [None] if it is not throttled.
+    pub fn limit_of(&self, key: &Key) -> Option<u32> {
+        self.prefixes.iter()
+            .filter(|(prefix, _)| key.as_str().starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, limit)| *limit)
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When the current one second window started and the writes in it.
+    window: (Instant, u32),
+    /// The writes of the last full window.
+    last_window: u32
+}
+
+/// Meters the writes of the throttled keys by namespace and key.
+pub(crate) struct WriteMeter {
+    policy: ThrottlePolicy,
+    keys: RefCell<HashMap<(String, Key), Bucket>>,
+    /// How many keys were metered after the last sweep of idle ones.
+    swept: Cell<usize>
+}
+
+impl WriteMeter {
+    pub fn new(policy: ThrottlePolicy) -> Self {
+        Self { policy, keys: RefCell::default(), swept: Cell::new(0) }
+    }
+    /// Counts a write of the key, failing with how long to wait if it is
+    /// over its limit. Writes of keys that are not throttled always pass.
+    pub fn admit(&self, namespace: &str, key: &Key, now: Instant) -> Result<(), Duration> {
+        let Some(limit) = self.policy.limit_of(key) else {
+            return Ok(());
+        };
+        let limit = limit as f64;
+        let mut keys = self.keys.borrow_mut();
+        if keys.len() > self.swept.get() * 2 + 1024 {
+            // A full bucket that was not written for a window holds nothing worth keeping.
+            keys.retain(|_, f| now - f.updated < Duration::from_secs(1));
+            self.swept.set(keys.len());
+        }
+        let bucket = keys.entry((namespace.to_string(), key.clone())).or_insert_with(|| Bucket {
+            tokens: limit,
+            updated: now,
+            window: (now, 0),
+            last_window: 0
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit).min(limit);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / limit));
+        }
+        bucket.tokens -= 1.0;
+        let (start, writes) = bucket.window;
+        if now - start >= Duration::from_secs(1) {
+            // A window without writes in between leaves nothing for the last one.
+            bucket.last_window = if now - start < Duration::from_secs(2) { writes } else { 0 };
+            bucket.window = (now, 1);
+        } else {
+            bucket.window.1 += 1;
+        }
+        Ok(())
+    }
+    /// The writes a second the key took lately, counting only the ones that
+    /// were let through. [None] if the key is not metered.
+    pub fn rate(&self, namespace: &str, key: &Key, now: Instant) -> Option<u32> {
+        let keys = self.keys.borrow();
+        let bucket = keys.get(&(namespace.to_string(), key.clone()))?;
+        let (start, writes) = bucket.window;
+        Some(match now - start {
+            current if current < Duration::from_secs(1) => writes.max(bucket.last_window),
+            last if last < Duration::from_secs(2) => writes,
+            _ => 0
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use overseer::models::Key;
+
+    use super::{ThrottlePolicy, WriteMeter};
+
+    #[test]
+    pub fn test_write_meter() {
+        let policy = ThrottlePolicy::default().with_prefix("stats.", 10).with_prefix("stats.rare", 2);
+        assert_eq!(policy.limit_of(&Key::from_str("stats.rare.a")), Some(2));
+        assert_eq!(policy.limit_of(&Key::from_str("app.mode")), None);
+
+        let meter = WriteMeter::new(policy);
+        let (rare, hits, free) = (Key::from_str("stats.rare.a"), Key::from_str("stats.hits"), Key::from_str("app.mode"));
+        let start = Instant::now();
+
+        // A burst of a second's worth goes through, then the key waits.
+        assert!(meter.admit("default", &rare, start).is_ok());
+        assert!(meter.admit("default", &rare, start).is_ok());
+        let wait = meter.admit("default", &rare, start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+        assert!(meter.admit("default", &rare, start + wait).is_ok());
+
+        // Every key and namespace has a budget of its own.
+        assert!(meter.admit("tenant-a", &rare, start).is_ok());
+        assert!((0..10).all(|_| meter.admit("default", &hits, start).is_ok()));
+        assert!(meter.admit("default", &hits, start).is_err());
+        assert!((0..100).all(|_| meter.admit("default", &free, start).is_ok()));
+
+        assert_eq!(meter.rate("default", &hits, start), Some(10));
+        assert_eq!(meter.rate("default", &hits, start + Duration::from_millis(1500)), Some(10));
+        assert_eq!(meter.rate("default", &hits, start + Duration::from_secs(3)), Some(0));
+        assert_eq!(meter.rate("default", &free, start), None);
+    }
+}
@@ -0,0 +1,26 @@
+use std::{path::Path, sync::Arc};
+
+use overseer::error::NetworkError;
+use tokio_rustls::{rustls::{pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer}, ServerConfig}, TlsAcceptor};
+
+
+/// Builds the acceptor used to wrap incoming connections from
+/// a PEM encoded certificate chain and private key.
+pub(crate) fn acceptor<C, K>(cert: C, key: K) -> Result<TlsAcceptor, NetworkError>
+where 
+    C: AsRef<Path>,
+    K: AsRef<Path>
+{
+    let chain = CertificateDer::pem_file_iter(cert.as_ref())
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| NetworkError::TlsConfiguration(format!("Could not read the certificate chain: {e}")))?;
+    let key = PrivateKeyDer::from_pem_file(key.as_ref())
+        .map_err(|e| NetworkError::TlsConfiguration(format!("Could not read the private key: {e}")))?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(|e| NetworkError::TlsConfiguration(e.to_string()))?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
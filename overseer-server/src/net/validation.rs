@@ -0,0 +1,116 @@
+use std::borrow::Cow;
+
+use overseer::{error::NetworkError, models::{Key, Value}, network::PacketPayload};
+
+use super::ClientId;
+
+
+/// A write a [WritePolicy] is asked about.
+pub struct WriteRequest<'a> {
+    pub client: ClientId,
+    pub namespace: &'a str,
+    /// The key written, or the queue for an item enqueued.
+    pub key: &'a Key,
+    /// The value written, [None] for a delete.
+    pub value: Option<&'a Value>
+}
+
+/// What a [WritePolicy] makes of a write.
+#[derive(Clone, PartialEq, Debug)]
+pub enum WriteVerdict {
+    Accept,
+    /// Writes this value in place of the one sent. A delete has no value
+    /// to rewrite, so it is accepted as it is.
+    Rewrite(Value),
+    /// Refuses the write, the client is told why with
+    /// [NetworkError::ValidationFailed].
+    Reject(String)
+}
+
+/// Checks the writes of the clients before they reach the database, to
+/// validate the values against a schema or strip what should not be
+/// stored. Registered with [super::DriverConfig::with_write_policy].
+///
+/// The policy sees the inserts, the deletes, the items enqueued and every
+/// record of an import or a multi-key insert. The writes the server makes itself are not checked.
+pub trait WritePolicy {
+    fn check(&self, request: WriteRequest<'_>) -> WriteVerdict;
+}
+
+impl<F> WritePolicy for F
+where
+    F: Fn(WriteRequest<'_>) -> WriteVerdict
+{
+    fn check(&self, request: WriteRequest<'_>) -> WriteVerdict {
+        (self)(request)
+    }
+}
+
+/// Runs the writes of the payload through the policy, returning the
+/// payload with the values it rewrote.
+pub(crate) fn police_writes(policy: &dyn WritePolicy, client: ClientId, namespace: &str, mut payload: PacketPayload<'static>) -> Result<PacketPayload<'static>, NetworkError> {
+    let check = |key: &Key, value: Option<&Value>| match policy.check(WriteRequest { client, namespace, key, value }) {
+        WriteVerdict::Accept => Ok(None),
+        WriteVerdict::Rewrite(value) => Ok(Some(value)),
+        WriteVerdict::Reject(reason) => Err(NetworkError::ValidationFailed(reason))
+    };
+    let rewrite = |key: &Key, value: &mut Cow<'static, Value>| -> Result<(), NetworkError> {
+        if let Some(rewritten) = check(key, Some(&**value))? {
+            *value = Cow::Owned(rewritten);
+        }
+        Ok(())
+    };
+    match &mut payload {
+        PacketPayload::Insert { key, value }
+        | PacketPayload::InsertAcknowledged { key, value, .. }
+        | PacketPayload::InsertEphemeral { key, value }
+        | PacketPayload::InsertIfVersion { key, value, .. }
+        | PacketPayload::GetOrInsert { key, default: value }
+        | PacketPayload::Enqueue { queue: key, value } => rewrite(key, value)?,
+        PacketPayload::Delete { key } | PacketPayload::DeleteIf { key, .. } => {
+            check(key, None)?;
+        }
+        PacketPayload::Import { records } | PacketPayload::MultiInsert { entries: records } => {
+            for (key, value) in records {
+                if let Some(rewritten) = check(key, Some(value))? {
+                    *value = rewritten;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(payload)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use overseer::{error::NetworkError, models::{Key, Value}, network::PacketPayload};
+
+    use crate::net::ClientId;
+
+    use super::{police_writes, WriteRequest, WriteVerdict};
+
+    #[test]
+    pub fn test_police_writes() {
+        let policy = |request: WriteRequest<'_>| match request.value {
+            Some(Value::Json(..)) => WriteVerdict::Reject("JSON is not allowed".to_string()),
+            Some(Value::String(s)) if s.starts_with("secret") => WriteVerdict::Rewrite(Value::String("***".into())),
+            _ => WriteVerdict::Accept
+        };
+        let police = |payload| police_writes(&policy, ClientId::from_id(1), "default", payload);
+        let key = Key::from_str("app.token");
+
+        let secret = Value::String("secret-1".into());
+        let PacketPayload::Insert { value, .. } = police(PacketPayload::insert(&key, &secret).to_owned()).unwrap() else {
+            panic!("Expected an insert.");
+        };
+        assert_eq!(*value, Value::String("***".into()));
+        let json = Value::Json("{}".into());
+        assert!(matches!(police(PacketPayload::insert(&key, &json).to_owned()), Err(NetworkError::ValidationFailed(..))));
+        assert!(police(PacketPayload::delete(&key).to_owned()).is_ok());
+
+        let rejected = PacketPayload::Import { records: vec![(key.clone(), Value::Integer(1)), (key.clone(), json)] };
+        assert!(police(rejected).is_err());
+    }
+}
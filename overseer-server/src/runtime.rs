@@ -0,0 +1,24 @@
+use std::future::Future;
+
+
+/// Runs the future to completion on the current thread the way the
+/// [crate::net::Driver] expects to be run.
+///
+/// The driver spawns its tasks on monoio but uses tokio sockets and
+/// timers, so a tokio runtime is entered as well and its worker keeps
+/// the tokio reactor turning. The future is built inside the runtimes.
+pub fn block_on<F, Fut>(future: F) -> std::io::Result<Fut::Output>
+where 
+    F: FnOnce() -> Fut,
+    Fut: Future
+{
+    let tokio = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()?;
+    let _context = tokio.enter();
+    let mut monoio = monoio::RuntimeBuilder::<monoio::FusionDriver>::new()
+        .enable_timer()
+        .build()?;
+    Ok(monoio.block_on(future()))
+}
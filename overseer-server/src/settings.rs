@@ -0,0 +1,235 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}, time::Duration};
+
+use overseer::{error::NetworkError, models::Durability};
+use serde::Deserialize;
+
+use crate::{database::{DurabilityPolicy, PAGE_SIZE}, net::DriverConfig};
+
+
+/// The settings of a standalone server, read from a TOML file.
+///
+/// ```toml
+/// bind = "0.0.0.0:7878"
+/// data_dir = "/var/lib/overseer"
+///
+/// [limits]
+/// max_connections = 512
+/// idle_timeout_secs = 60
+///
+/// [durability]
+/// default = "periodic"
+/// interval_ms = 1000
+/// group_window_ms = 2
+/// prefixes = { "billing." = "sync", "telemetry." = "best-effort" }
+///
+/// [tls]
+/// cert = "/etc/overseer/cert.pem"
+/// key = "/etc/overseer/key.pem"
+/// ```
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    /// The TCP address to listen on.
+    pub bind: String,
+    /// Listens on a Unix domain socket instead of TCP.
+    pub unix_socket: Option<PathBuf>,
+    /// Where the database lives, this is created if missing.
+    pub data_dir: PathBuf,
+    /// The name of the database file within the data directory.
+    pub database: String,
+    /// The page size of the store, which is fixed per build.
+    pub page_size: usize,
+    /// How many threads the default namespace is split across.
+    pub shards: usize,
+    /// One of `trace`, `debug`, `info`, `warn` or `error`.
+    pub log_level: String,
+    /// Serves Prometheus metrics on this address.
+    pub metrics: Option<String>,
+    pub limits: Limits,
+    pub durability: DurabilitySettings,
+    pub tls: Option<TlsSettings>
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub max_connections: Option<usize>,
+    pub queue_capacity: usize,
+    pub idle_timeout_secs: Option<u64>
+}
+
+/// The durability classes of the keys, see [DurabilityPolicy].
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DurabilitySettings {
+    pub default: Durability,
+    /// How often the writes that were not synced right away are made durable.
+    pub interval_ms: u64,
+    /// How long the synced and periodic writes wait to share a checkpoint.
+    pub group_window_ms: u64,
+    /// The class of the keys under each prefix.
+    pub prefixes: BTreeMap<String, Durability>
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    /// A PEM encoded certificate chain.
+    pub cert: PathBuf,
+    /// A PEM encoded private key.
+    pub key: PathBuf
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:7878".to_string(),
+            unix_socket: None,
+            data_dir: PathBuf::from("data"),
+            database: "overseer.db".to_string(),
+            page_size: PAGE_SIZE,
+            shards: 1,
+            log_level: "info".to_string(),
+            metrics: None,
+            limits: Limits::default(),
+            durability: DurabilitySettings::default(),
+            tls: None
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_connections: None,
+            queue_capacity: DriverConfig::default().queue_capacity,
+            idle_timeout_secs: None
+        }
+    }
+}
+
+impl Default for DurabilitySettings {
+    fn default() -> Self {
+        let policy = DurabilityPolicy::default();
+        Self {
+            default: policy.default,
+            interval_ms: policy.interval.as_millis() as u64,
+            group_window_ms: policy.group_window.as_millis() as u64,
+            prefixes: BTreeMap::new()
+        }
+    }
+}
+
+impl ServerSettings {
+    /// Reads and validates the settings file.
+    pub fn load<P>(path: P) -> Result<Self, NetworkError>
+    where 
+        P: AsRef<Path>
+    {
+        let text = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| NetworkError::InvalidSettings(format!("Could not read {}: {e}", path.as_ref().display())))?;
+        Self::parse(&text)
+    }
+    pub fn parse(text: &str) -> Result<Self, NetworkError> {
+        let settings: Self = toml::from_str(text).map_err(|e| NetworkError::InvalidSettings(e.to_string()))?;
+        settings.validate()?;
+        Ok(settings)
+    }
+    fn validate(&self) -> Result<(), NetworkError> {
+        if self.page_size != PAGE_SIZE {
+            return Err(NetworkError::InvalidSettings(format!("This build only supports a page size of {PAGE_SIZE}")));
+        }
+        if self.shards == 0 {
+            return Err(NetworkError::InvalidSettings("There has to be at least one shard".to_string()));
+        }
+        if self.durability.interval_ms == 0 {
+            return Err(NetworkError::InvalidSettings("The durability interval must be positive".to_string()));
+        }
+        if self.limits.queue_capacity == 0 {
+            return Err(NetworkError::InvalidSettings("The queue capacity must be positive".to_string()));
+        }
+        if self.tls.is_some() && self.unix_socket.is_some() {
+            return Err(NetworkError::InvalidSettings("TLS is only served over TCP".to_string()));
+        }
+        if self.tls.is_some() && !cfg!(feature = "tls") {
+            return Err(NetworkError::InvalidSettings("This build does not support TLS".to_string()));
+        }
+        if self.metrics.is_some() && !cfg!(feature = "metrics") {
+            return Err(NetworkError::InvalidSettings("This build does not support metrics".to_string()));
+        }
+        if self.unix_socket.is_some() && !cfg!(unix) {
+            return Err(NetworkError::InvalidSettings("Unix sockets are not supported here".to_string()));
+        }
+        self.log_level()?;
+        Ok(())
+    }
+    pub fn log_level(&self) -> Result<tracing::Level, NetworkError> {
+        self.log_level.parse().map_err(|_| NetworkError::InvalidSettings(format!("Unknown log level {}", self.log_level)))
+    }
+    /// The driver configuration described by the limits.
+    pub fn driver_config(&self) -> DriverConfig {
+        let mut config = DriverConfig::default()
+            .with_queue_capacity(self.limits.queue_capacity)
+            .with_shards(self.shards)
+            .with_durability_policy(self.durability_policy());
+        if let Some(max_connections) = self.limits.max_connections {
+            config = config.with_max_connections(max_connections);
+        }
+        if let Some(seconds) = self.limits.idle_timeout_secs {
+            config = config.with_idle_timeout(Duration::from_secs(seconds));
+        }
+        config
+    }
+    /// The durability policy described by the durability classes.
+    pub fn durability_policy(&self) -> DurabilityPolicy {
+        let mut policy = DurabilityPolicy::default()
+            .with_default(self.durability.default)
+            .with_interval(Duration::from_millis(self.durability.interval_ms))
+            .with_group_window(Duration::from_millis(self.durability.group_window_ms));
+        for (prefix, durability) in &self.durability.prefixes {
+            policy = policy.with_class(prefix, *durability);
+        }
+        policy
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use overseer::models::{Durability, Key};
+
+    use super::ServerSettings;
+
+    #[test]
+    pub fn test_parse_settings() {
+        let settings = ServerSettings::parse(r#"
+            bind = "0.0.0.0:9000"
+            data_dir = "/tmp/overseer"
+
+            [limits]
+            max_connections = 12
+        "#).unwrap();
+        assert_eq!(settings.bind, "0.0.0.0:9000");
+        assert_eq!(settings.data_dir, PathBuf::from("/tmp/overseer"));
+        assert_eq!(settings.limits.max_connections, Some(12));
+        assert_eq!(settings.database, "overseer.db");
+
+        assert!(ServerSettings::parse("page_size = 12").is_err());
+        assert!(ServerSettings::parse("unknown = 1").is_err());
+        assert!(ServerSettings::parse("log_level = \"loud\"").is_err());
+        assert!(ServerSettings::parse("shards = 0").is_err());
+
+        let settings = ServerSettings::parse(r#"
+            [durability]
+            default = "best-effort"
+            prefixes = { "billing." = "sync" }
+        "#).unwrap();
+        let policy = settings.durability_policy();
+        assert_eq!(policy.class_of(&Key::from_str("billing.plan")), Durability::Sync);
+        assert_eq!(policy.class_of(&Key::from_str("metrics.cpu")), Durability::BestEffort);
+        assert!(ServerSettings::parse("[durability]\ninterval_ms = 0").is_err());
+        assert!(ServerSettings::parse("[durability]\ndefault = \"never\"").is_err());
+    }
+}
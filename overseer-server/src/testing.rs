@@ -0,0 +1,1079 @@
+//! An ephemeral in-process server for integration tests.
+//!
+//! The driver runs on its own thread with a fresh data directory, and
+//! clients reach it through a proxy that can inject faults.
+
+use std::{net::SocketAddr, path::{Path, PathBuf}, sync::{atomic::{AtomicU64, Ordering}, Arc}, thread::JoinHandle, time::Duration};
+
+use overseer::error::NetworkError;
+use overseer_client::{Client, ClientConfig};
+use tempfile::TempDir;
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}, sync::{oneshot, watch}};
+
+use crate::net::{Access, Driver, DriverConfig};
+
+
+/// Faults shared between the [TestServer] and its proxy.
+#[derive(Default)]
+struct Faults {
+    /// Delay added to every chunk forwarded, in microseconds.
+    latency: AtomicU64,
+    /// Bumped to sever every open connection.
+    generation: watch::Sender<u64>
+}
+
+impl Faults {
+    fn latency(&self) -> Duration {
+        Duration::from_micros(self.latency.load(Ordering::Relaxed))
+    }
+}
+
+/// Configures a [TestServer].
+#[derive(Default)]
+pub struct TestServerBuilder {
+    tokens: Vec<(String, Access)>,
+    max_connections: Option<usize>,
+    queue_capacity: Option<usize>,
+    idle_timeout: Option<Duration>,
+    latency: Option<Duration>,
+    #[cfg(feature = "tls")]
+    tls: Option<(PathBuf, PathBuf)>,
+    #[cfg(feature = "metrics")]
+    metrics: bool,
+    configure: Option<Box<dyn FnOnce(DriverConfig) -> DriverConfig + Send>>
+}
+
+impl TestServerBuilder {
+    /// Accepts a token, once a token is added every client must authenticate.
+    pub fn with_token<S>(mut self, token: S, access: Access) -> Self
+    where 
+        S: Into<String>
+    {
+        self.tokens.push((token.into(), access));
+        self
+    }
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = Some(max_connections);
+        self
+    }
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+    /// Delays every chunk of traffic by this much in both directions.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+    /// Serves TLS with a PEM encoded certificate chain and private key.
+    #[cfg(feature = "tls")]
+    pub fn with_tls<C, K>(mut self, cert: C, key: K) -> Self
+    where 
+        C: AsRef<Path>,
+        K: AsRef<Path>
+    {
+        self.tls = Some((cert.as_ref().to_path_buf(), key.as_ref().to_path_buf()));
+        self
+    }
+    /// Serves the metrics over HTTP as well, see [TestServer::metrics_address].
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self) -> Self {
+        self.metrics = true;
+        self
+    }
+    /// Adjusts the driver configuration for anything the builder does not cover.
+    ///
+    /// The configuration is not [Send], so it is built on the server thread.
+    pub fn with_config<F>(mut self, configure: F) -> Self
+    where 
+        F: FnOnce(DriverConfig) -> DriverConfig + Send + 'static
+    {
+        self.configure = Some(Box::new(configure));
+        self
+    }
+    /// Starts the server on a free port.
+    pub async fn start(self) -> Result<TestServer, NetworkError> {
+        let directory = tempfile::tempdir()?;
+        let faults = Arc::new(Faults::default());
+        if let Some(latency) = self.latency {
+            faults.latency.store(latency.as_micros() as u64, Ordering::Relaxed);
+        }
+
+        let (ready, started) = oneshot::channel();
+        let (stop, stopped) = oneshot::channel();
+        let thread = std::thread::spawn({
+            let path = directory.path().to_path_buf();
+            let faults = Arc::clone(&faults);
+            move || serve(self, path, faults, ready, stopped)
+        });
+        let (address, metrics) = match started.await {
+            Ok(result) => result?,
+            Err(..) => return Err(std::io::Error::other("The test server thread failed to start.").into())
+        };
+
+        Ok(TestServer {
+            address,
+            metrics,
+            faults,
+            stop: Some(stop),
+            thread: Some(thread),
+            directory
+        })
+    }
+    fn config(self) -> DriverConfig {
+        let mut config = DriverConfig::default();
+        if !self.tokens.is_empty() {
+            let tokens = self.tokens;
+            config = config.with_authenticator(move |token: &str| {
+                tokens.iter().find(|(f, _)| f == token).map(|(_, access)| *access)
+            });
+        }
+        if let Some(max_connections) = self.max_connections {
+            config = config.with_max_connections(max_connections);
+        }
+        if let Some(capacity) = self.queue_capacity {
+            config = config.with_queue_capacity(capacity);
+        }
+        if let Some(timeout) = self.idle_timeout {
+            config = config.with_idle_timeout(timeout);
+        }
+        match self.configure {
+            Some(configure) => configure(config),
+            None => config
+        }
+    }
+}
+
+/// Runs the driver and the proxy until the server is stopped.
+fn serve(
+    builder: TestServerBuilder,
+    path: PathBuf,
+    faults: Arc<Faults>,
+    ready: oneshot::Sender<Result<(SocketAddr, Option<SocketAddr>), NetworkError>>,
+    stopped: oneshot::Receiver<()>
+) {
+    let result = crate::runtime::block_on(|| async move {
+        #[cfg(feature = "tls")]
+        let tls = builder.tls.clone();
+        #[cfg(feature = "metrics")]
+        let metrics = builder.metrics;
+        let config = builder.config();
+
+        #[cfg(feature = "tls")]
+        let started = match tls {
+            Some((cert, key)) => Driver::start_tls("127.0.0.1:0", cert, key, &path, "test.db", config).await,
+            None => Driver::start_with_config("127.0.0.1:0", &path, "test.db", config).await
+        };
+        #[cfg(not(feature = "tls"))]
+        let started = Driver::start_with_config("127.0.0.1:0", &path, "test.db", config).await;
+
+        let driver = match started {
+            Ok(driver) => driver,
+            Err(e) => {
+                let _ = ready.send(Err(e));
+                return;
+            }
+        };
+        let listener = match TcpListener::bind("127.0.0.1:0").await.and_then(|f| Ok((f.local_addr()?, f))) {
+            Ok(listener) => listener,
+            Err(e) => {
+                let _ = ready.send(Err(e.into()));
+                return;
+            }
+        };
+        #[cfg(feature = "metrics")]
+        let metrics = match metrics {
+            true => match driver.serve_metrics("127.0.0.1:0").await {
+                Ok(port) => Some(SocketAddr::from(([127, 0, 0, 1], port))),
+                Err(e) => {
+                    let _ = ready.send(Err(e));
+                    return;
+                }
+            },
+            false => None
+        };
+        #[cfg(not(feature = "metrics"))]
+        let metrics = None;
+        let upstream = SocketAddr::from(([127, 0, 0, 1], driver.port()));
+        tokio::runtime::Handle::current().spawn(proxy(listener.1, upstream, faults));
+        let _ = ready.send(Ok((listener.0, metrics)));
+
+        let _ = stopped.await;
+        if let Err(e) = driver.shutdown().await {
+            tracing::warn!("The test server did not shut down cleanly: {e}");
+        }
+    });
+    if let Err(e) = result {
+        // Dropping the ready sender tells the caller it failed.
+        tracing::error!("Could not start the test server runtime: {e}");
+    }
+}
+
+/// Forwards connections to the driver, applying the faults.
+async fn proxy(listener: TcpListener, upstream: SocketAddr, faults: Arc<Faults>) {
+    while let Ok((client, _)) = listener.accept().await {
+        let faults = Arc::clone(&faults);
+        tokio::spawn(async move {
+            let Ok(server) = TcpStream::connect(upstream).await else {
+                return;
+            };
+            let mut severed = faults.generation.subscribe();
+            let (client_read, client_write) = client.into_split();
+            let (server_read, server_write) = server.into_split();
+            tokio::select! {
+                _ = forward(client_read, server_write, &faults) => {},
+                _ = forward(server_read, client_write, &faults) => {},
+                _ = severed.changed() => {}
+            }
+        });
+    }
+}
+
+async fn forward<R, W>(mut read: R, mut write: W, faults: &Faults) -> std::io::Result<()>
+where 
+    R: AsyncReadExt + Unpin,
+    W: AsyncWriteExt + Unpin
+{
+    let mut buffer = vec![0u8; 4096];
+    loop {
+        let size = read.read(&mut buffer).await?;
+        if size == 0 {
+            return Ok(());
+        }
+        let latency = faults.latency();
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        write.write_all(&buffer[..size]).await?;
+    }
+}
+
+/// A server on a free port with its own data directory, which is
+/// shut down and deleted when dropped.
+pub struct TestServer {
+    address: SocketAddr,
+    metrics: Option<SocketAddr>,
+    faults: Arc<Faults>,
+    stop: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    directory: TempDir
+}
+
+impl TestServer {
+    pub fn builder() -> TestServerBuilder {
+        TestServerBuilder::default()
+    }
+    /// Starts a server with the default configuration.
+    pub async fn start() -> Result<Self, NetworkError> {
+        Self::builder().start().await
+    }
+    /// The address clients should connect to.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+    /// Where the metrics are served over HTTP, [None] unless built
+    /// [TestServerBuilder::with_metrics].
+    pub fn metrics_address(&self) -> Option<SocketAddr> {
+        self.metrics
+    }
+    /// The data directory of the server.
+    pub fn path(&self) -> &Path {
+        self.directory.path()
+    }
+    pub async fn client(&self) -> Result<Client, NetworkError> {
+        self.client_with_config(ClientConfig::default()).await
+    }
+    pub async fn client_with_config(&self, config: ClientConfig) -> Result<Client, NetworkError> {
+        Client::with_config(self.address, config).await
+    }
+    /// Changes the delay added to traffic, this applies to open connections too.
+    pub fn set_latency(&self, latency: Duration) {
+        self.faults.latency.store(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+    /// Abruptly closes every open connection, as a network partition would.
+    pub fn sever_connections(&self) {
+        self.faults.generation.send_modify(|f| *f += 1);
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex}, time::Duration};
+
+    use overseer::{access::{WatcherActivity, WatcherBehaviour}, error::{ClientError, ErrorCode, NetworkError}, models::{DeleteCondition, Key, KeyDiff, KeyFilter, KeyPolicy, Revision, Schema, Value, ValueType}, network::{OverseerSerde, Packet, PacketId, PacketPayload, DEFAULT_NAMESPACE}};
+    use overseer_client::{traced, ClientConfig, Interceptor};
+    use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream};
+
+    use crate::{database::{Manifest, Quota, QuotaPolicy}, net::{Access, ConnectionLimitPolicy, DriverEvent, ProtocolViolationPolicy, SlowConsumerPolicy, SlowRequestPolicy, WriteRequest, WriteVerdict}};
+
+    use super::TestServer;
+
+    #[tokio::test]
+    pub async fn test_server_fixture() {
+        let server = TestServer::start().await.unwrap();
+        let client = server.client().await.unwrap();
+
+        let key = Key::from_str("hello");
+        client.insert(&key, Value::Integer(3)).await.unwrap();
+        assert_eq!(client.get(&key).await.unwrap(), Some(Value::Integer(3)));
+
+        server.sever_connections();
+        let other = server.client().await.unwrap();
+        assert_eq!(other.get(&key).await.unwrap(), Some(Value::Integer(3)));
+    }
+
+    #[tokio::test]
+    pub async fn test_connection_limit() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let server = TestServer::builder()
+            .with_max_connections(1)
+            .with_config({
+                let events = Arc::clone(&events);
+                move |f| f.with_connection_limit_policy(ConnectionLimitPolicy::Reject).with_event_hook(move |e| events.lock().unwrap().push(e))
+            })
+            .start()
+            .await
+            .unwrap();
+        let client = server.client().await.unwrap();
+        let key = Key::from_str("hello");
+        client.get(&key).await.unwrap();
+
+        // The connection over the limit is told the server is busy and closed.
+        let mut socket = TcpStream::connect(server.address()).await.unwrap();
+        assert!(matches!(Packet::deserialize(&mut socket).await.unwrap().payload(), PacketPayload::ServerBusy));
+        assert_eq!(socket.read(&mut [0; 16]).await.unwrap(), 0);
+        assert_eq!(*events.lock().unwrap(), vec![DriverEvent::ConnectionRejected]);
+        client.get(&key).await.unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_slow_consumer() {
+        let key = Key::from_str("app.blob");
+        // JSON is sent as it is, so the notifications keep their size.
+        let value = Value::Json(format!("\"{}\"", "x".repeat(1 << 18)));
+        for policy in [SlowConsumerPolicy::Disconnect, SlowConsumerPolicy::DropNotify] {
+            let events = Arc::new(Mutex::new(vec![]));
+            let server = TestServer::builder()
+                .with_queue_capacity(1)
+                .with_config({
+                    let events = Arc::clone(&events);
+                    move |f| f.with_slow_consumer_policy(policy).with_event_hook(move |e| events.lock().unwrap().push(e))
+                })
+                .start()
+                .await
+                .unwrap();
+            let writer = server.client().await.unwrap();
+
+            // The watcher never reads, so its notifications pile up once the socket buffers fill.
+            let mut socket = TcpStream::connect(server.address()).await.unwrap();
+            Packet::watch(PacketId::new(1, 0), &key, WatcherActivity::Lazy, WatcherBehaviour::Ordered).serialize(&mut socket).await.unwrap();
+            assert_eq!(Packet::deserialize(&mut socket).await.unwrap().id(), PacketId::new(1, 0));
+            for _ in 0..1000 {
+                if !events.lock().unwrap().is_empty() {
+                    break;
+                }
+                writer.insert(&key, value.clone()).await.unwrap();
+            }
+            let event = events.lock().unwrap().first().copied();
+
+            match policy {
+                SlowConsumerPolicy::Disconnect => {
+                    assert!(matches!(event, Some(DriverEvent::SlowConsumerDisconnected(..))));
+                    let mut buffer = vec![0; 1 << 16];
+                    while socket.read(&mut buffer).await.unwrap() != 0 {}
+                }
+                SlowConsumerPolicy::DropNotify => {
+                    assert!(matches!(event, Some(DriverEvent::NotificationDropped(..))));
+                    // The watcher is served once it catches up.
+                    Packet::get(PacketId::new(2, 0), &key).serialize(&mut socket).await.unwrap();
+                    while Packet::deserialize(&mut socket).await.unwrap().id() != PacketId::new(2, 0) {}
+                }
+            }
+            writer.get(&key).await.unwrap();
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    pub async fn test_tls() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let (chain, key) = (dir.path().join("cert.pem"), dir.path().join("key.pem"));
+        std::fs::write(&chain, cert.cert.pem()).unwrap();
+        std::fs::write(&key, cert.key_pair.serialize_pem()).unwrap();
+
+        let server = TestServer::builder().with_tls(&chain, &key).start().await.unwrap();
+        let client = overseer_client::Client::new_tls(server.address(), overseer_client::ClientTlsConfig::new("localhost", &chain)).await.unwrap();
+        let key = Key::from_str("hello");
+        client.insert(&key, Value::Integer(3)).await.unwrap();
+        assert_eq!(client.get(&key).await.unwrap(), Some(Value::Integer(3)));
+
+        // A client that does not speak TLS is not served.
+        let plain = server.client().await.unwrap();
+        assert!(plain.get(&key).await.is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_import() {
+        let server = TestServer::start().await.unwrap();
+        let client = server.client().await.unwrap();
+
+        let seed = "# Seeded\napp.mode = dark\napp.port = 8080\n";
+        assert_eq!(client.import_from_reader(seed.as_bytes()).await.unwrap(), 2);
+        assert_eq!(client.get(&Key::from_str("app.port")).await.unwrap(), Some(Value::Integer(8080)));
+
+        let records = (0..3000).map(|f| (Key::from_str(format!("seed.{f}")), Value::Integer(f)));
+        assert_eq!(client.import(records).await.unwrap(), 3000);
+        assert_eq!(client.get(&Key::from_str("seed.2999")).await.unwrap(), Some(Value::Integer(2999)));
+        assert!(client.import_from_reader("no separator".as_bytes()).await.is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_export() {
+        let server = TestServer::start().await.unwrap();
+        let client = server.client().await.unwrap();
+        let records: Vec<_> = (0..600).map(|f| (Key::from_str(format!("export.{f:03}")), Value::Integer(f))).collect();
+        client.import(records.clone()).await.unwrap();
+        client.insert(&Key::from_str("app.mode"), Value::String("dark".into())).await.unwrap();
+
+        // The records come out in order with their metadata, across pages.
+        let (page, cursor) = client.namespace(DEFAULT_NAMESPACE).export_page(None, 2).await.unwrap();
+        assert_eq!(page[0].key, Key::from_str("app.mode"));
+        assert!(page[0].meta.is_some());
+        assert_eq!(cursor, Some(Key::from_str("export.000")));
+        let mut dump = vec![];
+        assert_eq!(client.export(&mut dump).await.unwrap(), 601);
+
+        // And load into another server as they were.
+        let other = TestServer::start().await.unwrap();
+        let copy = other.client().await.unwrap();
+        assert_eq!(copy.import_dump(&mut dump.as_slice()).await.unwrap(), 601);
+        assert_eq!(copy.get(&Key::from_str("export.599")).await.unwrap(), Some(Value::Integer(599)));
+        assert_eq!(copy.get(&Key::from_str("app.mode")).await.unwrap(), Some(Value::String("dark".into())));
+        assert!(copy.import_dump(&mut &dump[..dump.len() - 1]).await.is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_children() {
+        let server = TestServer::start().await.unwrap();
+        let client = server.client().await.unwrap();
+        let records = ["app.mode", "app.kafka", "app.kafka.brokers", "app.kafka-old", "app.db.url", "app.db.pool", "other"]
+            .map(|f| (Key::from_str(f), Value::Integer(1)));
+        client.import(records).await.unwrap();
+
+        let children = client.children(&Key::from_str("app")).await.unwrap();
+        let listed: Vec<_> = children.iter().map(|f| (f.key.as_str(), f.has_value, f.has_children)).collect();
+        assert_eq!(listed, [("app.db", false, true), ("app.kafka", true, true), ("app.kafka-old", true, false), ("app.mode", true, false)]);
+        let top: Vec<_> = client.children(&Key::root()).await.unwrap().into_iter().map(|f| f.key).collect();
+        assert_eq!(top, [Key::from_str("app"), Key::from_str("other")]);
+
+        // Paged, and the children of a child.
+        let namespace = client.namespace(DEFAULT_NAMESPACE);
+        let (page, cursor) = namespace.list_children(&Key::from_str(".app."), None, 2).await.unwrap();
+        assert_eq!((page.len(), cursor.clone()), (2, Some(Key::from_str("app.kafka"))));
+        let (page, cursor) = namespace.list_children(&Key::from_str("app"), cursor.as_ref(), 2).await.unwrap();
+        assert_eq!((page[0].key.as_str(), cursor), ("app.kafka-old", None));
+        assert_eq!(client.children(&Key::from_str("app.kafka")).await.unwrap()[0].key, Key::from_str("app.kafka.brokers"));
+        assert!(client.children(&Key::from_str("app.mode")).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn test_snapshot_get() {
+        let server = TestServer::start().await.unwrap();
+        let client = server.client().await.unwrap();
+        let (host, port, user) = (Key::from_str("db.host"), Key::from_str("db.port"), Key::from_str("db.user"));
+        client.insert(&host, Value::String("primary".into())).await.unwrap();
+        client.insert(&port, Value::Integer(5432)).await.unwrap();
+
+        let values = client.snapshot_get(&[host.clone(), user, port.clone()]).await.unwrap();
+        assert_eq!(values, [Some(Value::String("primary".into())), None, Some(Value::Integer(5432))]);
+        let versioned = client.namespace(DEFAULT_NAMESPACE).snapshot_get_versioned(&[port.clone()]).await.unwrap();
+        assert_eq!(versioned, [client.get_versioned(&port).await.unwrap()]);
+        assert!(client.snapshot_get(&[]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn test_rename() {
+        let server = TestServer::start().await.unwrap();
+        let client = server.client().await.unwrap();
+        let (from, to) = (Key::from_str("app.mode"), Key::from_str("app.theme"));
+        client.insert(&from, Value::Integer(1)).await.unwrap();
+        let version = client.get_versioned(&from).await.unwrap().1;
+        let watched = server.client().await.unwrap();
+        let old = watched.subscribe(&from, WatcherActivity::Lazy, WatcherBehaviour::Ordered).await.unwrap();
+        let new = watched.subscribe(&to, WatcherActivity::Lazy, WatcherBehaviour::Ordered).await.unwrap();
+
+        let (renamed, deleted, moved) = tokio::join!(client.rename(&from, &to, false), old.wait_on_update(), new.wait_on_update());
+        assert_eq!((renamed.unwrap(), deleted, moved), (Some(version), None, Some(Value::Integer(1))));
+        assert_eq!(client.get_versioned(&to).await.unwrap(), (Some(Value::Integer(1)), version));
+        assert_eq!(client.rename(&from, &to, false).await.unwrap(), None);
+
+        client.insert(&from, Value::Integer(2)).await.unwrap();
+        assert!(matches!(client.rename(&from, &to, false).await, Err(NetworkError::VersionConflict(v)) if v == version));
+        assert!(matches!(client.rename(&from, &Key::from_str("$sys.mode"), true).await, Err(NetworkError::InvalidKey(..))));
+    }
+
+    #[tokio::test]
+    pub async fn test_traced_notifications() {
+        for shards in [1, 3] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let client = server.client().await.unwrap();
+            let watched = server.client().await.unwrap();
+            let key = Key::from_str("app.mode");
+            let live = watched.subscribe(&key, WatcherActivity::Lazy, WatcherBehaviour::Ordered).await.unwrap();
+
+            // The notification carries the trace of the write that caused it.
+            let (written, value) = tokio::join!(traced(42, client.insert(&key, Value::Integer(1))), live.wait_on_update());
+            written.unwrap();
+            assert_eq!((value, live.trace()), (Some(Value::Integer(1)), Some(42)));
+            let (written, _) = tokio::join!(client.insert(&key, Value::Integer(2)), live.wait_on_update());
+            written.unwrap();
+            assert_eq!(live.trace(), None);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_sharded_acknowledged_watch() {
+        let server = TestServer::builder().with_config(|f| f.with_shards(2)).start().await.unwrap();
+        let client = server.client().await.unwrap();
+        let key = Key::from_str("app.mode");
+        assert!(matches!(client.subscribe_acknowledged(&key, 0).await, Err(NetworkError::Unsupported(..))));
+
+        // The refusal leaves the connection serving requests.
+        client.insert(&key, Value::Integer(1)).await.unwrap();
+        assert_eq!(client.get(&key).await.unwrap(), Some(Value::Integer(1)));
+    }
+
+    #[tokio::test]
+    pub async fn test_debounced_watch() {
+        struct Notifications(Arc<AtomicUsize>);
+        impl Interceptor for Notifications {
+            fn after_receive(&self, packet: &Packet<'_>) {
+                if matches!(packet.payload(), PacketPayload::Notify { .. }) {
+                    self.0.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let client = server.client().await.unwrap();
+            let notifications = Arc::new(AtomicUsize::new(0));
+            let watched = server.client_with_config(ClientConfig::default().with_interceptor(Notifications(Arc::clone(&notifications)))).await.unwrap();
+            let key = Key::from_str("app.cursor");
+            let watch = watched.watch_debounced(&key, WatcherActivity::Lazy, WatcherBehaviour::Eager, Duration::from_secs(1)).await.unwrap();
+
+            // A burst of writes, coalesced into the first and the latest.
+            for i in 1..=20 {
+                client.insert(&key, Value::Integer(i)).await.unwrap();
+            }
+            // The latest value wins once the interval is up.
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while watch.value().get().await != Some(Value::Integer(20)) {
+                    watch.value().wait_on_update().await;
+                }
+            }).await.unwrap();
+            assert!(notifications.load(Ordering::Relaxed) <= 4);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_watch_heartbeat() {
+        for shards in [1, 2] {
+            let server = TestServer::builder()
+                .with_config(move |f| f.with_shards(shards).with_watch_heartbeat(Duration::from_millis(100)))
+                .start().await.unwrap();
+            let client = server.client().await.unwrap();
+            let key = Key::from_str("app.quiet");
+            client.insert(&key, Value::Integer(1)).await.unwrap();
+            let watch = client.watch(&key, WatcherActivity::Kickback, WatcherBehaviour::Eager).await.unwrap();
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while watch.value().get().await != Some(Value::Integer(1)) {
+                    watch.value().wait_on_update().await;
+                }
+            }).await.unwrap();
+
+            // The key stays quiet, yet the watch is seen alive over and over.
+            let mut seen = vec![];
+            for _ in 0..3 {
+                tokio::time::sleep(Duration::from_millis(250)).await;
+                seen.push(watch.last_seen().expect("The watch should have been sent a heartbeat."));
+            }
+            assert!(seen.windows(2).all(|f| f[0] < f[1]));
+            // Heartbeats do not touch the value.
+            assert!(tokio::time::timeout(Duration::from_millis(250), watch.value().wait_on_update()).await.is_err());
+            assert_eq!(watch.value().get().await, Some(Value::Integer(1)));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_slow_request_hook() {
+        let reported = Arc::new(Mutex::new(vec![]));
+        let server = TestServer::builder()
+            .with_config({
+                let reported = Arc::clone(&reported);
+                move |f| f
+                    .with_slow_request_policy(Some(SlowRequestPolicy::default().with_latency(Duration::MAX).with_size(512)))
+                    .with_slow_request_hook(move |request| reported.lock().unwrap().push((request.operation, request.key.clone())))
+            })
+            .start().await.unwrap();
+        let client = server.client().await.unwrap();
+        let (small, large) = (Key::from_str("app.mode"), Key::from_str("app.blob"));
+        client.insert(&small, Value::Integer(1)).await.unwrap();
+        client.insert(&large, Value::Json(format!("\"{}\"", "a".repeat(1024)))).await.unwrap();
+        // The connection serves its requests in order, so the insert was reported by the time the get is answered.
+        client.get(&small).await.unwrap();
+        assert_eq!(*reported.lock().unwrap(), [("insert", Some(large))]);
+    }
+
+    #[tokio::test]
+    pub async fn test_quota_exceeded() {
+        for shards in [1, 2] {
+            let quotas = QuotaPolicy::default().with_default(Quota::default().with_max_value_size(64));
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards).with_quota_policy(quotas)).start().await.unwrap();
+            let client = server.client().await.unwrap();
+            let key = Key::from_str("app.blob");
+            client.insert(&key, Value::String("a".repeat(64).into())).await.unwrap();
+            let refused = client.insert(&key, Value::String("a".repeat(65).into())).await;
+            assert!(matches!(refused, Err(NetworkError::QuotaExceeded(..))));
+            assert_eq!(client.get(&key).await.unwrap(), Some(Value::String("a".repeat(64).into())));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_write_policy() {
+        let policy = |request: WriteRequest<'_>| match request.value {
+            Some(json @ Value::Json(..)) if json.to_serde::<serde::de::IgnoredAny>().is_err() => WriteVerdict::Reject(format!("{} is not JSON", request.key.as_str())),
+            Some(Value::String(..)) if request.key.as_str().starts_with("secrets.") => WriteVerdict::Rewrite(Value::String("redacted".into())),
+            _ => WriteVerdict::Accept
+        };
+        let server = TestServer::builder().with_config(move |f| f.with_write_policy(policy)).start().await.unwrap();
+        let client = server.client().await.unwrap();
+        let (config, secret) = (Key::from_str("app.config"), Key::from_str("secrets.token"));
+
+        let refused = client.insert(&config, Value::Json("{".into())).await;
+        assert!(matches!(refused, Err(NetworkError::ValidationFailed(reason)) if reason == "app.config is not JSON"));
+        client.insert(&config, Value::Json("{}".into())).await.unwrap();
+        client.insert(&secret, Value::String("hunter2".into())).await.unwrap();
+        assert_eq!(client.get(&secret).await.unwrap(), Some(Value::String("redacted".into())));
+    }
+
+    #[tokio::test]
+    pub async fn test_schemas() {
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let client = server.client().await.unwrap();
+            let (port, user) = (Key::from_str("app.port"), Key::from_str("users.jim"));
+            assert!(client.register_schema("app.port", Some(Schema::integer(Some(1), Some(65535)))).await.unwrap().is_none());
+            client.register_schema("users.", Some(Schema::json(r#"{ "type": "object", "required": ["name"] }"#))).await.unwrap();
+            assert!(matches!(client.register_schema("app.", Some(Schema::string("("))).await, Err(NetworkError::ValidationFailed(..))));
+
+            client.insert(&port, Value::Integer(8080)).await.unwrap();
+            assert!(matches!(client.insert(&port, Value::Integer(0)).await, Err(NetworkError::ValidationFailed(..))));
+            assert!(client.insert(&port, Value::String("8080".into())).await.is_err());
+            assert!(client.insert(&user, Value::Json(r#"{ "age": 3 }"#.into())).await.is_err());
+            client.insert(&user, Value::Json(r#"{ "name": "jim" }"#.into())).await.unwrap();
+            assert_eq!(client.get(&port).await.unwrap(), Some(Value::Integer(8080)));
+            assert_eq!(client.schemas().await.unwrap().len(), 2);
+
+            client.register_schema("app.port", None).await.unwrap();
+            client.insert(&port, Value::Integer(0)).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_multi_insert() {
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let client = server.client().await.unwrap();
+            let keys: Vec<Key> = ["app.host", "app.port", "app.mode", "app.tls"].into_iter().map(Key::from_str).collect();
+            client.register_schema("app.port", Some(Schema::integer(Some(1), Some(65535)))).await.unwrap();
+            let rollout = |port: i64| vec![
+                (keys[0].clone(), Value::String("example.com".into())),
+                (keys[1].clone(), Value::Integer(port)),
+                (keys[2].clone(), Value::String("live".into())),
+                (keys[3].clone(), Value::Integer(1))
+            ];
+
+            // A single refused write keeps the whole rollout out.
+            assert!(matches!(client.insert_all(rollout(0)).await, Err(NetworkError::ValidationFailed(..))));
+            assert!(client.snapshot_get(&keys).await.unwrap().iter().all(Option::is_none));
+
+            let watch = client.watch(&keys[3], WatcherActivity::Lazy, WatcherBehaviour::Eager).await.unwrap();
+            assert_eq!(client.insert_all(rollout(443)).await.unwrap().len(), keys.len());
+            let values = client.snapshot_get(&keys).await.unwrap();
+            assert_eq!(values, rollout(443).into_iter().map(|(_, value)| Some(value)).collect::<Vec<_>>());
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while watch.value().get().await != Some(Value::Integer(1)) {
+                    watch.value().wait_on_update().await;
+                }
+            }).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_delete_if() {
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let client = server.client().await.unwrap();
+            let key = Key::from_str("jobs.lease");
+            let worker = |name: &str| Value::String(name.into());
+
+            let version = client.insert_if_version(&key, &worker("worker-1"), 0).await.unwrap();
+            // The lease was taken over before the cleanup ran.
+            client.insert(&key, worker("worker-2")).await.unwrap();
+            assert!(!client.delete_if(&key, DeleteCondition::Value(worker("worker-1"))).await.unwrap());
+            assert!(!client.delete_if(&key, DeleteCondition::Version(version)).await.unwrap());
+            assert_eq!(client.get(&key).await.unwrap(), Some(worker("worker-2")));
+
+            assert!(client.delete_if(&key, DeleteCondition::Value(worker("worker-2"))).await.unwrap());
+            assert_eq!(client.get(&key).await.unwrap(), None);
+            assert!(!client.delete_if(&key, DeleteCondition::Value(worker("worker-2"))).await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_find_keys() {
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let client = server.client().await.unwrap();
+            for key in ["app.db.port", "app.mode", "app.web.port", "svc.a.port", "svc.b.host"] {
+                client.insert(&Key::from_str(key), Value::Integer(1)).await.unwrap();
+            }
+            let find = |filter: KeyFilter| {
+                let client = &client;
+                async move {
+                    let mut keys = client.find_keys(filter);
+                    let mut found = vec![];
+                    while let Some(key) = keys.next().await? {
+                        found.push(key.as_str().to_string());
+                    }
+                    Ok::<_, NetworkError>(found)
+                }
+            };
+            assert_eq!(find(KeyFilter::glob("app.*.port")).await.unwrap(), vec!["app.db.port", "app.web.port"]);
+            assert_eq!(find(KeyFilter::regex("\\.port$")).await.unwrap(), vec!["app.db.port", "app.web.port", "svc.a.port"]);
+            assert!(find(KeyFilter::glob("nothing.*")).await.unwrap().is_empty());
+            assert!(matches!(find(KeyFilter::regex("(")).await, Err(NetworkError::ValidationFailed(..))));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_db_stats() {
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let client = server.client().await.unwrap();
+            assert_eq!(client.db_stats().await.unwrap().keys, 0);
+            for (key, value) in [("app.mode", "dark"), ("app.theme", "blue"), ("flag", "on")] {
+                client.insert(&Key::from_str(key), Value::String(value.into())).await.unwrap();
+            }
+
+            let stats = client.db_stats().await.unwrap();
+            assert_eq!((stats.keys, stats.value_bytes), (3, 10));
+            assert_eq!(stats.prefixes.into_iter().collect::<Vec<_>>(), vec![("app".to_string(), 2), ("flag".to_string(), 1)]);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_manifest_and_compactions() {
+        for shards in [1, 2] {
+            let manifest = Manifest::new()
+                .require_with_default("app.mode", Value::String("light".into()))
+                .require("app.port", ValueType::Integer);
+            let server = TestServer::builder()
+                .with_config(move |f| f.with_shards(shards).with_manifest(manifest).with_default_creation())
+                .start()
+                .await
+                .unwrap();
+            let client = server.client().await.unwrap();
+
+            // The defaults are created where the keys are served from.
+            assert_eq!(client.get(&Key::from_str("app.mode")).await.unwrap(), Some(Value::String("light".into())));
+            let drift = client.check_manifest().await.unwrap();
+            assert_eq!(drift.into_iter().map(|f| f.key).collect::<Vec<_>>(), vec![Key::from_str("app.port")]);
+            client.insert(&Key::from_str("app.port"), Value::Integer(80)).await.unwrap();
+            assert!(client.check_manifest().await.unwrap().is_empty());
+
+            assert!(client.compaction_history().await.unwrap().is_empty());
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    pub async fn test_metrics_endpoint() {
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).with_metrics().start().await.unwrap();
+            let client = server.client().await.unwrap();
+            let key = Key::from_str("app.mode");
+            client.insert(&key, Value::Integer(1)).await.unwrap();
+            // One in sixteen accesses is sampled by default.
+            for _ in 0..64 {
+                client.get(&key).await.unwrap();
+            }
+
+            let mut socket = TcpStream::connect(server.metrics_address().unwrap()).await.unwrap();
+            socket.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").await.unwrap();
+            let mut response = String::new();
+            socket.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+            // The key is sampled on the shard it lives on.
+            let tiers: usize = response.lines()
+                .filter(|f| f.starts_with("overseer_keys_by_tier"))
+                .map(|f| f.rsplit(' ').next().unwrap().parse::<usize>().unwrap())
+                .sum();
+            assert_eq!(tiers, 1);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_key_diff() {
+        let now = || std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let client = server.client().await.unwrap();
+            let key = Key::from_str("app.mode");
+            client.insert(&key, Value::String("light".into())).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            let before = now();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            client.insert(&key, Value::String("dark".into())).await.unwrap();
+
+            let diff = client.diff(&key, Revision::Timestamp(before), Revision::Timestamp(now())).await.unwrap();
+            assert_eq!(diff, KeyDiff::between(Some(Value::String("light".into())), Some(Value::String("dark".into()))));
+            assert!(matches!(client.diff(&key, Revision::Version(0), Revision::Timestamp(now())).await, Err(NetworkError::Client(ClientError::RevisionUnavailable))));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_next_sequence() {
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let (client, other) = (server.client().await.unwrap(), server.client().await.unwrap());
+            let mut issued = vec![];
+            for _ in 0..40 {
+                let (a, b) = tokio::join!(client.next_sequence("jobs"), other.next_sequence("jobs"));
+                issued.extend([a.unwrap(), b.unwrap()]);
+            }
+            issued.sort();
+            assert_eq!(issued, (1..=80).collect::<Vec<_>>());
+            assert_eq!(client.next_sequence("orders").await.unwrap(), 1);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_get_at() {
+        let now = || std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let client = server.client().await.unwrap();
+            let key = Key::from_str("app.mode");
+            client.insert(&key, Value::String("light".into())).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            let before = now();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            client.insert(&key, Value::String("dark".into())).await.unwrap();
+
+            assert_eq!(client.get_at(&key, Revision::Timestamp(before)).await.unwrap(), Some(Value::String("light".into())));
+            assert_eq!(client.get_at(&key, Revision::Timestamp(now())).await.unwrap(), Some(Value::String("dark".into())));
+            assert_eq!(client.get_at(&Key::from_str("app.size"), Revision::Timestamp(before)).await.unwrap(), None);
+            assert!(matches!(client.get_at(&key, Revision::Version(0)).await, Err(NetworkError::Client(ClientError::RevisionUnavailable))));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_watcher_info() {
+        let server = TestServer::start().await.unwrap();
+        let client = server.client().await.unwrap();
+        let other = server.client().await.unwrap();
+        let (mode, size) = (Key::from_str("app.mode"), Key::from_str("app.size"));
+        assert!(client.watcher_info().await.unwrap().is_empty());
+
+        let first = client.watch(&mode, WatcherActivity::Lazy, WatcherBehaviour::Ordered).await.unwrap();
+        let _second = other.watch(&mode, WatcherActivity::Lazy, WatcherBehaviour::Ordered).await.unwrap();
+        let _third = other.watch(&size, WatcherActivity::Lazy, WatcherBehaviour::Ordered).await.unwrap();
+        let watched: Vec<_> = client.watcher_info().await.unwrap().into_iter().map(|f| (f.key, f.clients)).collect();
+        assert_eq!(watched, [(mode.clone(), 2), (size.clone(), 1)]);
+        assert_eq!(client.watcher_count(&Key::from_str("app")).await.unwrap(), 0);
+
+        // A released watch is no longer counted.
+        first.unsubscribe().await.unwrap();
+        assert_eq!(client.watcher_count(&mode).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_authenticates_once() {
+        let server = TestServer::builder()
+            .with_token("reader", Access::ReadOnly)
+            .with_token("admin", Access::ReadWrite)
+            .start()
+            .await
+            .unwrap();
+        let mut socket = TcpStream::connect(server.address()).await.unwrap();
+        let mut request = async |payload: PacketPayload<'_>| {
+            Packet::new(PacketId::new(9, 0), payload).serialize(&mut socket).await.unwrap();
+            Packet::deserialize(&mut socket).await.unwrap().into_payload()
+        };
+        assert!(matches!(request(PacketPayload::auth("reader")).await, PacketPayload::AuthResult { accepted: true }));
+
+        // It cannot authenticate again to gain more access.
+        assert!(matches!(request(PacketPayload::auth("admin")).await, PacketPayload::AuthResult { accepted: false }));
+        let (topic, value) = (Key::from_str("app.events"), Value::Integer(1));
+        assert!(matches!(request(PacketPayload::publish(&topic, &value)).await, PacketPayload::AuthResult { accepted: false }));
+        assert!(matches!(request(PacketPayload::get(&topic)).await, PacketPayload::Return { value: None, .. }));
+    }
+
+    #[tokio::test]
+    pub async fn test_protocol_violation() {
+        let key = Key::from_str("hello");
+        let violation = || Packet::new(PacketId::new(7, 0), PacketPayload::notify(&key, None, false));
+
+        // The connection is answered with an error and closed.
+        let server = TestServer::start().await.unwrap();
+        let mut socket = TcpStream::connect(server.address()).await.unwrap();
+        violation().serialize(&mut socket).await.unwrap();
+        let error = Packet::deserialize(&mut socket).await.unwrap();
+        assert!(matches!(error.payload(), PacketPayload::Error { code: ErrorCode(122), .. }));
+        assert_eq!(socket.read(&mut [0; 16]).await.unwrap(), 0);
+        server.client().await.unwrap().get(&key).await.unwrap();
+
+        // Or answered and served further.
+        let server = TestServer::builder()
+            .with_config(|f| f.with_protocol_violation_policy(ProtocolViolationPolicy::Reply))
+            .start()
+            .await
+            .unwrap();
+        let mut socket = TcpStream::connect(server.address()).await.unwrap();
+        violation().serialize(&mut socket).await.unwrap();
+        Packet::get(PacketId::new(8, 0), &key).serialize(&mut socket).await.unwrap();
+        assert!(matches!(Packet::deserialize(&mut socket).await.unwrap().payload(), PacketPayload::Error { .. }));
+        assert_eq!(Packet::deserialize(&mut socket).await.unwrap().id(), PacketId::new(8, 0));
+    }
+
+    #[tokio::test]
+    pub async fn test_key_policy() {
+        let server = TestServer::builder()
+            .with_config(|f| f.with_key_policy(KeyPolicy::default().with_max_length(16).with_reserved_prefix("sys.")))
+            .start()
+            .await
+            .unwrap();
+        let client = server.client().await.unwrap();
+        for key in ["a-key-over-16-bytes", "sys.version", "bell\x07"] {
+            let refused = client.insert(&Key::from_str(key), Value::Integer(1)).await;
+            assert!(matches!(refused, Err(NetworkError::InvalidKey(..))), "{key} was not refused");
+        }
+        assert!(matches!(client.import([(Key::from_str("sys.seed"), Value::Integer(1))]).await, Err(NetworkError::InvalidKey(..))));
+
+        // The connection is served further, and reading a key is never refused.
+        client.insert(&Key::from_str("app.mode"), Value::Integer(1)).await.unwrap();
+        assert_eq!(client.get(&Key::from_str("sys.version")).await.unwrap(), None);
+        client.delete(&Key::from_str("sys.version")).await.unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_lock_transitions() {
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let (client, holder) = (server.client().await.unwrap(), server.client().await.unwrap());
+            let key = Key::from_str("leader");
+            let transitions = client.subscribe(&Key::from_str("$sys.lock.leader"), WatcherActivity::Lazy, WatcherBehaviour::Ordered).await.unwrap();
+            let record = client.subscribe(&key, WatcherActivity::Lazy, WatcherBehaviour::Ordered).await.unwrap();
+
+            let (token, taken) = tokio::join!(holder.acquire(&key, Duration::from_secs(30)), transitions.wait_on_update());
+            assert!(token.unwrap().is_some());
+            assert!(matches!(taken, Some(Value::Integer(..))));
+            assert_eq!(client.get(&Key::from_str("$sys.lock.leader")).await.unwrap(), taken);
+            let (released, freed) = tokio::join!(holder.release_lock(&key), transitions.wait_on_update());
+            assert!(released.unwrap());
+            assert_eq!(freed, None);
+
+            // The watchers of the key only hear of its record.
+            let (written, value) = tokio::join!(client.insert(&key, Value::Integer(1)), record.wait_on_update());
+            written.unwrap();
+            assert_eq!(value, Some(Value::Integer(1)));
+
+            // Nor can a client lock or forge the transitions.
+            assert!(matches!(client.insert(&Key::from_str("$sys.lock.leader"), Value::Integer(0)).await, Err(NetworkError::InvalidKey(..))));
+            assert!(matches!(client.acquire(&Key::from_str("$sys.lock.leader"), Duration::from_secs(30)).await, Err(NetworkError::InvalidKey(..))));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_channels() {
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let (subscriber, publisher) = (server.client().await.unwrap(), server.client().await.unwrap());
+            let channel = Key::from_str("deploys");
+            let mut messages = subscriber.subscribe_channel(&channel).await.unwrap();
+
+            let (receivers, message) = tokio::join!(publisher.publish(&channel, &Value::Integer(1)), messages.recv());
+            assert_eq!(receivers.unwrap(), 1);
+            assert_eq!(message, Some(Value::Integer(1)));
+            // The broadcast leaves no record behind.
+            assert_eq!(publisher.get(&channel).await.unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_queue_wakeups() {
+        for shards in [1, 2] {
+            let server = TestServer::builder().with_config(move |f| f.with_shards(shards)).start().await.unwrap();
+            let (consumer, producer) = (server.client().await.unwrap(), server.client().await.unwrap());
+            let queue = Key::from_str("jobs");
+            let mut topic = producer.subscribe_topic(&queue).await.unwrap();
+
+            let (leased, item) = tokio::join!(consumer.dequeue_wait(&queue, Duration::from_secs(30)), async {
+                // Giving the consumer the time to find the queue empty.
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                producer.enqueue(&queue, &Value::Integer(1)).await.unwrap()
+            });
+            assert_eq!(leased.unwrap().key, item);
+            // The wakeups stay off the topic named like the queue.
+            assert!(tokio::time::timeout(Duration::from_millis(100), topic.recv()).await.is_err());
+            assert!(matches!(producer.publish(&Key::from_str("$sys.queue.jobs"), &Value::Integer(0)).await, Err(NetworkError::InvalidKey(..))));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_system_keys() {
+        let server = TestServer::start().await.unwrap();
+        let client = server.client().await.unwrap();
+        let other = server.client().await.unwrap();
+        other.insert(&Key::from_str("app.mode"), Value::Integer(1)).await.unwrap();
+
+        let system = |name: &str| Key::from_str(format!("$sys.{name}"));
+        assert_eq!(client.get(&system("version")).await.unwrap(), Some(Value::String(env!("CARGO_PKG_VERSION").into())));
+        assert_eq!(client.get(&system("connections")).await.unwrap(), Some(Value::Integer(2)));
+        assert_eq!(client.get(&system("storage.records")).await.unwrap(), Some(Value::Integer(1)));
+        // Writes are durable by default, so the insert is already flushed.
+        assert_eq!(client.get(&system("storage.dirty")).await.unwrap(), Some(Value::Integer(0)));
+        assert!(matches!(client.get(&system("storage.flushes")).await.unwrap(), Some(Value::Integer(1..))));
+        assert_eq!(client.get(&system("unknown")).await.unwrap(), None);
+
+        // The keys are not records, they cannot be written or listed.
+        let uptime = Key::from_str("$sys.uptime_ms");
+        assert!(matches!(client.insert(&uptime, Value::Integer(0)).await, Err(NetworkError::InvalidKey(..))));
+        assert!(matches!(client.delete(&uptime).await, Err(NetworkError::InvalidKey(..))));
+        assert_eq!(client.children(&Key::root()).await.unwrap().len(), 1);
+
+        // And their watchers hear of them as they change.
+        let live = client.subscribe(&uptime, WatcherActivity::Kickback, WatcherBehaviour::Ordered).await.unwrap();
+        let Some(Value::Integer(first)) = live.wait_on_update().await else { panic!("No uptime") };
+        let Some(Value::Integer(next)) = live.wait_on_update().await else { panic!("No uptime") };
+        assert!(next > first);
+    }
+}
@@ -0,0 +1,70 @@
+//!
+//! Compares [SmallString] with the `String` it replaced for the work the
+//! server does with most keys and values, run with `cargo +nightly bench -p overseer`.
+
+#![feature(test)]
+
+extern crate test;
+
+use std::{collections::HashMap, hint::black_box};
+
+use overseer::models::{Key, SmallString};
+use test::Bencher;
+
+
+const SHORT: &str = "service.api.replicas";
+const KEYS: usize = 1024;
+
+fn long() -> String {
+    r#"{"service":"api","replicas":3,"region":"eu-west-1","zone":"b"}"#.repeat(4)
+}
+
+#[bench]
+fn make_short_string(b: &mut Bencher) {
+    b.iter(|| black_box(SHORT).to_string());
+}
+
+#[bench]
+fn make_short_small_string(b: &mut Bencher) {
+    b.iter(|| SmallString::from(black_box(SHORT)));
+}
+
+#[bench]
+fn clone_short_string(b: &mut Bencher) {
+    let string = SHORT.to_string();
+    b.iter(|| black_box(&string).clone());
+}
+
+#[bench]
+fn clone_short_small_string(b: &mut Bencher) {
+    let string = SmallString::from(SHORT);
+    b.iter(|| black_box(&string).clone());
+}
+
+#[bench]
+fn clone_long_string(b: &mut Bencher) {
+    let string = long();
+    b.iter(|| black_box(&string).clone());
+}
+
+#[bench]
+fn clone_long_small_string(b: &mut Bencher) {
+    let string = SmallString::from(long());
+    b.iter(|| black_box(&string).clone());
+}
+
+#[bench]
+fn index_string_keys(b: &mut Bencher) {
+    b.iter(|| {
+        let map: HashMap<String, usize> = (0..KEYS).map(|i| (format!("app.{i}"), i)).collect();
+        map.get(black_box("app.512")).copied()
+    });
+}
+
+#[bench]
+fn index_keys(b: &mut Bencher) {
+    b.iter(|| {
+        let map: HashMap<Key, usize> = (0..KEYS).map(|i| (Key::from_str(format!("app.{i}")), i)).collect();
+        map.get(&Key::from_str(black_box("app.512"))).copied()
+    });
+}
@@ -1,4 +1,4 @@
-use crate::error::NetworkError;
+use crate::error::{NetworkError, ProtocolError};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum WatcherBehaviour {
@@ -14,7 +14,7 @@ impl TryFrom<u8> for WatcherBehaviour {
         Ok(match value {
             0 => Self::Ordered,
             1 => Self::Eager,
-            _ => Err(NetworkError::WatcherBehaviourDecodeError)?
+            _ => Err(NetworkError::Protocol(ProtocolError::WatcherBehaviourDecodeError))?
         })
     }
 }
@@ -25,11 +25,40 @@ impl TryFrom<u8> for WatcherActivity {
         Ok(match value {
             0 => Self::Kickback,
             1 => Self::Lazy,
-            _ => Err(NetworkError::WatcherActivityDecodeError)?
+            _ => Err(NetworkError::Protocol(ProtocolError::WatcherActivityDecodeError))?
         })
     }
 }
 
+/// Why the server killed a subscription it judged to be broken.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IsolationReason {
+    /// The notifications of the subscription kept failing to be delivered.
+    DeliveryFailed,
+    /// The queue of the subscription stayed full for too long.
+    Saturated
+}
+
+impl TryFrom<u8> for IsolationReason {
+    type Error = NetworkError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::DeliveryFailed,
+            1 => Self::Saturated,
+            _ => Err(NetworkError::Protocol(ProtocolError::IsolationReasonDecodeError))?
+        })
+    }
+}
+
+impl IsolationReason {
+    pub fn discriminator(&self) -> u8 {
+        match self {
+            Self::DeliveryFailed => 0,
+            Self::Saturated => 1
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum WatcherActivity {
     /// Kicks the initial state back to the watcher immediately.
@@ -0,0 +1,49 @@
+use thiserror::Error;
+
+use super::ErrorCode;
+
+
+/// What went wrong talking to a server, as the client sees it.
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("Could not resolve the address")]
+    SocketError,
+    #[error("Wrong response from server")]
+    WrongResponseFromServer,
+    #[error("Every request id is currently in flight")]
+    RequestIdsExhausted,
+    #[error("The connection closed before a response arrived")]
+    ConnectionClosed,
+    #[error("No server addresses were given")]
+    NoEndpoints,
+    #[error("The server did not answer the health check")]
+    HealthCheckFailed,
+    #[error("The server rejected the request as unauthorized")]
+    Unauthorized,
+    #[error("The revision is older than the history the server keeps")]
+    RevisionUnavailable,
+    #[error("The promotion is unknown or a later one has to be rolled back first")]
+    UnknownPromotion,
+    #[error("The key is written more often than the server allows")]
+    Throttled(std::time::Duration),
+    #[error("The write was applied at version {0} but did not reach the acknowledgement level in time")]
+    AcknowledgementTimeout(u64),
+    #[error("The server does not support extension {0}")]
+    UnsupportedExtension(u32),
+    #[error("The offline queue already holds {0} writes")]
+    OfflineQueueFull(usize),
+    #[error("The server failed the request with error {0}: {1}")]
+    Server(ErrorCode, String)
+}
+
+impl ClientError {
+    /// The code the error is sent with, see [ErrorCode]. Only refusals
+    /// have one of their own, the client is where the rest happen.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Unauthorized => ErrorCode::UNAUTHORIZED,
+            Self::Server(code, _) => *code,
+            _ => ErrorCode::INTERNAL
+        }
+    }
+}
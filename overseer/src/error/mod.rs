@@ -1,6 +1,12 @@
 mod value;
 mod network;
+mod protocol;
+mod storage;
+mod client;
 
 pub use crate::error::value::*;
 
-pub use crate::error::network::*;
\ No newline at end of file
+pub use crate::error::network::*;
+pub use crate::error::protocol::*;
+pub use crate::error::storage::*;
+pub use crate::error::client::*;
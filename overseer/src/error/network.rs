@@ -1,49 +1,244 @@
-use std::array::TryFromSliceError;
-
 use thiserror::Error;
 
+use super::{ClientError, ProtocolError, StorageError, ValueParseError};
+
 
 
+/// Every error of the crates, the ones of the wire, the store and the
+/// client are grouped under their own enums.
 #[derive(Error, Debug)]
 pub enum NetworkError {
-    // #[error("Try from slice fail")]
-    // FailedSliceConversion(#[from] TryFromSliceError),
-    #[error("Illegal read.")]
-    IllegalRead,
-    #[error("This page is freed and cannot be freely acquired.")]
-    PageFreedError,
-    #[error("PAge was out of bounds")]
-    PageOutOfBounds,
-    #[error("Failed to connect to address.")]
-    SocketBindFailure(tokio::io::Error),
-    #[error("Unrecognized packet discriminator")]
-    UnrecognizedPacketTypeDiscriminator(u8),
     #[error("Error reading bytes.")]
     IoError(#[from] tokio::io::Error),
-    #[error("Unrecognized value type discriminator")]
-    UnrecognizedValueTypeDiscriminator(u8),
-    #[error("Failed to read key")]
-    FailedToReadKey,
-    #[error("Failed to read value")]
-    FailedToReadValue,
-    #[error("Invalid watcher activity")]
-    WatcherActivityDecodeError,
-    #[error("Invalid watcher behaviour")]
-    WatcherBehaviourDecodeError,
-    #[error("Could not decode option")]
-    ErrorDecodingOption,
-    #[error("Could not decode boolean")]
-    ErrorDecodingBoolean,
-    #[error("Unknown packet schema")]
-    UnknownPacketSchema(u8),
-    #[error("Could not conver from slice")]
-    TrySliceError(#[from] std::array::TryFromSliceError),
-    #[error("Could not convert from slice")]
-    ParseUtf8Error(#[from] std::str::Utf8Error),
-    #[error("Error converting to socket")]
-    SocketError,
-    #[error("Failed to connect to the socket")]
-    FailedToConnectToSocket,
-    #[error("Wrong response from server")]
-    WrongResponseFromServer
-}
\ No newline at end of file
+    #[error(transparent)]
+    Protocol(#[from] ProtocolError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Client(#[from] ClientError),
+    #[error("Invalid TLS configuration")]
+    TlsConfiguration(String),
+    #[error("Invalid settings")]
+    InvalidSettings(String),
+    #[error("Invalid namespace")]
+    InvalidNamespace(String),
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+    #[error("The namespace does not exist")]
+    UnknownNamespace,
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+    #[error("Validation failed: {0}")]
+    ValidationFailed(String),
+    #[error("Not supported: {0}")]
+    Unsupported(String),
+    #[error("The key is at version {0}, not the one the write expected")]
+    VersionConflict(u64),
+    #[error("Could not convert the value: {0}")]
+    ValueConversion(#[from] ValueParseError)
+}
+
+impl From<std::array::TryFromSliceError> for NetworkError {
+    fn from(value: std::array::TryFromSliceError) -> Self {
+        ProtocolError::from(value).into()
+    }
+}
+
+impl From<std::str::Utf8Error> for NetworkError {
+    fn from(value: std::str::Utf8Error) -> Self {
+        ProtocolError::from(value).into()
+    }
+}
+
+impl NetworkError {
+    /// The code the error is sent with in an error packet.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Protocol(e) => e.code(),
+            Self::Storage(e) => e.code(),
+            Self::Client(e) => e.code(),
+            Self::IoError(..) => ErrorCode(250),
+            Self::InvalidNamespace(..) => ErrorCode(300),
+            Self::UnknownNamespace => ErrorCode(301),
+            Self::VersionConflict(..) => ErrorCode(302),
+            Self::ValueConversion(..) => ErrorCode(303),
+            Self::InvalidKey(..) => ErrorCode(305),
+            Self::QuotaExceeded(..) => ErrorCode(306),
+            Self::ValidationFailed(..) => ErrorCode(307),
+            Self::Unsupported(..) => ErrorCode(308),
+            Self::TlsConfiguration(..) | Self::InvalidSettings(..) => ErrorCode::INTERNAL
+        }
+    }
+    /// The error an error packet names. The ones the code alone describes
+    /// come back as themselves, the others as [ClientError::Server].
+    pub fn from_code(code: ErrorCode, message: &str) -> Self {
+        match code.0 {
+            102 => ProtocolError::FailedToReadKey.into(),
+            103 => ProtocolError::FailedToReadValue.into(),
+            104 => ProtocolError::WatcherActivityDecodeError.into(),
+            105 => ProtocolError::WatcherBehaviourDecodeError.into(),
+            106 => ProtocolError::RevisionDecodeError.into(),
+            107 => ProtocolError::DiffLineDecodeError.into(),
+            108 => ProtocolError::DurabilityDecodeError.into(),
+            109 => ProtocolError::IsolationReasonDecodeError.into(),
+            110 => ProtocolError::AcknowledgementDecodeError.into(),
+            111 => ProtocolError::ErrorDecodingOption.into(),
+            112 => ProtocolError::ErrorDecodingBoolean.into(),
+            118 => ProtocolError::DecompressionFailed.into(),
+            120 => ProtocolError::VarintOverflow.into(),
+            200 => StorageError::IllegalRead.into(),
+            201 => StorageError::PageFreedError.into(),
+            202 => StorageError::PageOutOfBounds.into(),
+            207 => StorageError::ShardStopped.into(),
+            301 => Self::UnknownNamespace,
+            304 => ClientError::Unauthorized.into(),
+            305 => Self::InvalidKey(message.strip_prefix("Invalid key: ").unwrap_or(message).to_string()),
+            306 => Self::QuotaExceeded(message.strip_prefix("Quota exceeded: ").unwrap_or(message).to_string()),
+            307 => Self::ValidationFailed(message.strip_prefix("Validation failed: ").unwrap_or(message).to_string()),
+            308 => Self::Unsupported(message.strip_prefix("Not supported: ").unwrap_or(message).to_string()),
+            _ => ClientError::Server(code, message.to_string()).into()
+        }
+    }
+}
+
+/// The stable number an error is sent with, a code is never given to
+/// another error. The hundreds give its [ErrorClass].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct ErrorCode(pub u16);
+
+impl ErrorCode {
+    /// The request was refused for the access of the connection.
+    pub const UNAUTHORIZED: Self = Self(304);
+    /// Anything without a code of its own.
+    pub const INTERNAL: Self = Self(500);
+
+    pub fn class(&self) -> ErrorClass {
+        match self.0 {
+            100..=199 => ErrorClass::Protocol,
+            200..=299 => ErrorClass::Storage,
+            300..=399 => ErrorClass::Request,
+            _ => ErrorClass::Internal
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// What kind of failure an error code names.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorClass {
+    /// The request could not be decoded.
+    Protocol,
+    /// The store failed to read or apply the request.
+    Storage,
+    /// The request cannot be served as it is.
+    Request,
+    /// Anything else that failed on the server.
+    Internal
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{error::{ClientError, ProtocolError, StorageError, ValueParseError}, models::ValueType};
+
+    use super::{ErrorCode, NetworkError};
+
+
+    #[test]
+    pub fn test_code_round_trip() {
+        let invalid = vec![0xff];
+        // Whether the code alone names the error, so it comes back as itself.
+        let errors: Vec<(NetworkError, bool)> = vec![
+            (std::io::Error::other("reset").into(), false),
+            (ProtocolError::UnrecognizedPacketTypeDiscriminator(9).into(), false),
+            (ProtocolError::UnrecognizedValueTypeDiscriminator(9).into(), false),
+            (ProtocolError::FailedToReadKey.into(), true),
+            (ProtocolError::FailedToReadValue.into(), true),
+            (ProtocolError::WatcherActivityDecodeError.into(), true),
+            (ProtocolError::WatcherBehaviourDecodeError.into(), true),
+            (ProtocolError::RevisionDecodeError.into(), true),
+            (ProtocolError::DiffLineDecodeError.into(), true),
+            (ProtocolError::DurabilityDecodeError.into(), true),
+            (ProtocolError::IsolationReasonDecodeError.into(), true),
+            (ProtocolError::AcknowledgementDecodeError.into(), true),
+            (ProtocolError::ErrorDecodingOption.into(), true),
+            (ProtocolError::ErrorDecodingBoolean.into(), true),
+            (ProtocolError::UnknownPacketSchema(3).into(), false),
+            (ProtocolError::UnrecognizedDiscriminator("durability", 7).into(), false),
+            (<[u8; 2]>::try_from(&[0u8][..]).unwrap_err().into(), false),
+            (std::str::from_utf8(&invalid).unwrap_err().into(), false),
+            (ProtocolError::ValueTypeUnsupported(ValueType::Json, 4).into(), false),
+            (ProtocolError::DecompressionFailed.into(), true),
+            (ProtocolError::PacketTooLarge(10, 5).into(), false),
+            (ProtocolError::VarintOverflow.into(), true),
+            (ProtocolError::LengthTooLarge(10).into(), false),
+            (ProtocolError::UnexpectedPacket("notify").into(), false),
+            (ProtocolError::ValueTooDeep(64).into(), false),
+            (ProtocolError::InflatedTooLarge(1024).into(), false),
+            (StorageError::IllegalRead.into(), true),
+            (StorageError::PageFreedError.into(), true),
+            (StorageError::PageOutOfBounds.into(), true),
+            (StorageError::Page("torn".to_string()).into(), false),
+            (StorageError::InvalidSeed("line 3".to_string()).into(), false),
+            (StorageError::InvalidDump("truncated".to_string()).into(), false),
+            (StorageError::MigrationFailed("v2".to_string()).into(), false),
+            (StorageError::ShardStopped.into(), true),
+            (StorageError::ReplicationGap(4, 6).into(), false),
+            (StorageError::Corrupt("db.sequences".to_string()).into(), false),
+            (ClientError::SocketError.into(), false),
+            (ClientError::WrongResponseFromServer.into(), false),
+            (ClientError::RequestIdsExhausted.into(), false),
+            (ClientError::ConnectionClosed.into(), false),
+            (ClientError::NoEndpoints.into(), false),
+            (ClientError::HealthCheckFailed.into(), false),
+            (ClientError::Unauthorized.into(), true),
+            (ClientError::RevisionUnavailable.into(), false),
+            (ClientError::UnknownPromotion.into(), false),
+            (ClientError::Throttled(Duration::from_secs(1)).into(), false),
+            (ClientError::AcknowledgementTimeout(3).into(), false),
+            (ClientError::UnsupportedExtension(1000).into(), false),
+            (ClientError::OfflineQueueFull(64).into(), false),
+            (ClientError::Server(ErrorCode(399), "refused".to_string()).into(), false),
+            (NetworkError::TlsConfiguration("no key".to_string()), false),
+            (NetworkError::InvalidSettings("port".to_string()), false),
+            (NetworkError::InvalidNamespace("a b".to_string()), false),
+            (NetworkError::InvalidKey("bell".to_string()), true),
+            (NetworkError::UnknownNamespace, true),
+            (NetworkError::QuotaExceeded("keys".to_string()), true),
+            (NetworkError::ValidationFailed("port".to_string()), true),
+            (NetworkError::Unsupported("sharded".to_string()), true),
+            (NetworkError::VersionConflict(4), false),
+            (ValueParseError::Serde("eof".to_string()).into(), false)
+        ];
+        for (error, named) in errors {
+            let message = error.to_string();
+            let decoded = NetworkError::from_code(error.code(), &message);
+            assert_eq!(decoded.code(), error.code(), "{error:?}");
+            if named {
+                assert_eq!(decoded.to_string(), message, "{error:?}");
+            } else {
+                assert!(matches!(&decoded, NetworkError::Client(ClientError::Server(code, m)) if *code == error.code() && *m == message), "{error:?}");
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_client_codes() {
+        assert_eq!(ClientError::Unauthorized.code(), ErrorCode::UNAUTHORIZED);
+        assert_eq!(ClientError::Server(ErrorCode(302), String::new()).code(), ErrorCode(302));
+        assert_eq!(ClientError::ConnectionClosed.code(), ErrorCode::INTERNAL);
+    }
+
+    #[test]
+    pub fn test_unknown_code() {
+        let decoded = NetworkError::from_code(ErrorCode(399), "Something new");
+        assert!(matches!(decoded, NetworkError::Client(ClientError::Server(ErrorCode(399), message)) if message == "Something new"));
+    }
+}
@@ -0,0 +1,94 @@
+use thiserror::Error;
+
+use crate::models::ValueType;
+
+use super::ErrorCode;
+
+
+/// What went wrong encoding or decoding the packets and values on the wire.
+#[derive(Error, Debug)]
+pub enum ProtocolError {
+    #[error("Unrecognized packet discriminator")]
+    UnrecognizedPacketTypeDiscriminator(u8),
+    #[error("Unrecognized value type discriminator")]
+    UnrecognizedValueTypeDiscriminator(u8),
+    #[error("Failed to read key")]
+    FailedToReadKey,
+    #[error("Failed to read value")]
+    FailedToReadValue,
+    #[error("Invalid watcher activity")]
+    WatcherActivityDecodeError,
+    #[error("Invalid watcher behaviour")]
+    WatcherBehaviourDecodeError,
+    #[error("Invalid revision")]
+    RevisionDecodeError,
+    #[error("Invalid diff line")]
+    DiffLineDecodeError,
+    #[error("Invalid durability class")]
+    DurabilityDecodeError,
+    #[error("Invalid isolation reason")]
+    IsolationReasonDecodeError,
+    #[error("Invalid acknowledgement level")]
+    AcknowledgementDecodeError,
+    #[error("Could not decode option")]
+    ErrorDecodingOption,
+    #[error("Could not decode boolean")]
+    ErrorDecodingBoolean,
+    #[error("Unknown packet schema")]
+    UnknownPacketSchema(u8),
+    #[error("Unrecognized discriminator {1} of {0}")]
+    UnrecognizedDiscriminator(&'static str, u8),
+    #[error("Could not convert from slice")]
+    TrySliceError(#[from] std::array::TryFromSliceError),
+    #[error("Could not read a string")]
+    ParseUtf8Error(#[from] std::str::Utf8Error),
+    #[error("Value type is unsupported by the protocol version of the peer")]
+    ValueTypeUnsupported(ValueType, u8),
+    #[error("Could not decompress a payload")]
+    DecompressionFailed,
+    #[error("The packet is larger than the most the reader accepts")]
+    PacketTooLarge(usize, usize),
+    #[error("A varint does not fit the integer it encodes")]
+    VarintOverflow,
+    #[error("A length in the packet is larger than the decoder accepts")]
+    LengthTooLarge(usize),
+    #[error("A {0} packet is not a request the server serves")]
+    UnexpectedPacket(&'static str),
+    #[error("A value is nested deeper than the {0} maps the decoder accepts")]
+    ValueTooDeep(usize),
+    #[error("The values of a packet decompress to more than the {0} bytes the decoder accepts")]
+    InflatedTooLarge(usize)
+}
+
+impl ProtocolError {
+    /// The code the error is sent with, see [ErrorCode].
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode(match self {
+            Self::UnrecognizedPacketTypeDiscriminator(..) => 100,
+            Self::UnrecognizedValueTypeDiscriminator(..) => 101,
+            Self::FailedToReadKey => 102,
+            Self::FailedToReadValue => 103,
+            Self::WatcherActivityDecodeError => 104,
+            Self::WatcherBehaviourDecodeError => 105,
+            Self::RevisionDecodeError => 106,
+            Self::DiffLineDecodeError => 107,
+            Self::DurabilityDecodeError => 108,
+            Self::IsolationReasonDecodeError => 109,
+            Self::AcknowledgementDecodeError => 110,
+            Self::ErrorDecodingOption => 111,
+            Self::ErrorDecodingBoolean => 112,
+            Self::UnknownPacketSchema(..) => 113,
+            Self::UnrecognizedDiscriminator(..) => 114,
+            Self::TrySliceError(..) => 115,
+            Self::ParseUtf8Error(..) => 116,
+            Self::ValueTypeUnsupported(..) => 117,
+            Self::DecompressionFailed => 118,
+            Self::PacketTooLarge(..) => 119,
+            Self::VarintOverflow => 120,
+            Self::LengthTooLarge(..) => 121,
+            Self::UnexpectedPacket(..) => 122,
+            Self::ValueTooDeep(..) => 123,
+            Self::InflatedTooLarge(..) => 124
+        })
+    }
+}
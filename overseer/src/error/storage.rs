@@ -0,0 +1,47 @@
+use thiserror::Error;
+
+use super::ErrorCode;
+
+
+/// What went wrong reading or changing what the server stores.
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Illegal read.")]
+    IllegalRead,
+    #[error("This page is freed and cannot be freely acquired.")]
+    PageFreedError,
+    #[error("Page was out of bounds")]
+    PageOutOfBounds,
+    #[error("A page could not be read or written: {0}")]
+    Page(String),
+    #[error("Invalid seed")]
+    InvalidSeed(String),
+    #[error("Invalid dump: {0}")]
+    InvalidDump(String),
+    #[error("Migration failed")]
+    MigrationFailed(String),
+    #[error("The shard stopped before it answered")]
+    ShardStopped,
+    #[error("A replicated change is missing, expected {0} but received {1}")]
+    ReplicationGap(u64, u64),
+    #[error("A file of the store is corrupt: {0}")]
+    Corrupt(String)
+}
+
+impl StorageError {
+    /// The code the error is sent with, see [ErrorCode].
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode(match self {
+            Self::IllegalRead => 200,
+            Self::PageFreedError => 201,
+            Self::PageOutOfBounds => 202,
+            Self::Page(..) => 203,
+            Self::InvalidSeed(..) => 205,
+            Self::MigrationFailed(..) => 206,
+            Self::ShardStopped => 207,
+            Self::ReplicationGap(..) => 208,
+            Self::InvalidDump(..) => 209,
+            Self::Corrupt(..) => 210
+        })
+    }
+}
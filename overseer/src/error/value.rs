@@ -4,4 +4,6 @@ use thiserror::Error;
 pub enum ValueParseError {
     #[error("incompatible data type erorr")]
     IncorrectType(String),
+    #[error("could not convert the value: {0}")]
+    Serde(String),
 }
\ No newline at end of file
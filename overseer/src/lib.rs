@@ -1,3 +1,6 @@
+// The derives name the crate by its path, which has to resolve in here too.
+extern crate self as overseer;
+
 pub mod models;
 pub mod error;
 pub mod access;
@@ -3,6 +3,8 @@ use std::{fmt::Debug, ops};
 use monoio::buf::{IoBuf, IoBufMut};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
+use crate::error::ProtocolError;
+
 
 // pub trait Bidirectional<O> {
 //     fn forwards(self) -> O;
@@ -114,15 +116,32 @@ impl<const N: usize> IoBufferMut<N> {
 #[async_trait::async_trait(?Send)]
 pub trait LocalReadAsync: Sized {
     async fn read_exact(&mut self, buffer: Vec<u8>) -> std::io::Result<(Vec<u8>, usize)>;
+    /// A zeroed buffer of the length to read into, a pooling reader hands
+    /// out one it got back, see [crate::network::BufferPool].
+    fn buffer(&mut self, length: usize) -> Vec<u8> {
+        vec![0u8; length]
+    }
+    /// Hands back a buffer once its bytes were decoded.
+    fn recycle(&mut self, _buffer: Vec<u8>) {}
+    /// Counts the bytes a value is about to decompress to against what the
+    /// packet being read may hold, see [crate::network::MAX_INFLATION].
+    fn inflate(&mut self, _length: usize) -> Result<(), ProtocolError> {
+        Ok(())
+    }
     async fn read_u8(&mut self) -> std::io::Result<u8> {
-        let single = [0u8; 1];
-        let (single, _) = self.read_exact(single.to_vec()).await?;
+        let single = self.buffer(1);
+        let (single, _) = self.read_exact(single).await?;
         // println!("SINGLE: {:?}", single);
-        Ok(single[0])
+        let byte = single[0];
+        self.recycle(single);
+        Ok(byte)
     }
     async fn read_u32(&mut self) -> std::io::Result<u32> {
-        let (d, _) = self.read_exact(vec![0u8; 4]).await?;
-        Ok(u32::from_be_bytes(d[0..4].try_into().unwrap()))
+        let d = self.buffer(4);
+        let (d, _) = self.read_exact(d).await?;
+        let value = u32::from_be_bytes(d[0..4].try_into().unwrap());
+        self.recycle(d);
+        Ok(value)
     }
 }
 
@@ -138,6 +157,24 @@ pub trait LocalWriteAsync {
         self.write_all(data.to_be_bytes().to_vec()).await?;
         Ok(())
     }
+    /// Whether large values are compressed on their way out, see [super::COMPRESSION_THRESHOLD].
+    fn compresses_values(&self) -> bool {
+        true
+    }
+}
+
+/// Writes through to a writer without compressing any value, for peers
+/// that cannot decompress them.
+pub struct UncompressedWriter<'a, W>(pub &'a mut W);
+
+#[async_trait::async_trait(?Send)]
+impl<W: LocalWriteAsync> LocalWriteAsync for UncompressedWriter<'_, W> {
+    async fn write_all(&mut self, buffer: Vec<u8>) -> std::io::Result<()> {
+        self.0.write_all(buffer).await
+    }
+    fn compresses_values(&self) -> bool {
+        false
+    }
 }
 
 
@@ -0,0 +1,12 @@
+use std::time::Duration;
+
+
+/// The outcome of a single compaction run of the store.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CompactionRun {
+    /// When the run finished, in milliseconds since the unix epoch.
+    pub finished_at: u64,
+    pub duration: Duration,
+    pub bytes_reclaimed: u64,
+    pub pages_moved: u64
+}
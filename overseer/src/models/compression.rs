@@ -0,0 +1,54 @@
+//!
+//! Compression of large values in the LZ4 block format, see [lz4_flex::block].
+
+use crate::error::{NetworkError, ProtocolError};
+
+
+/// Values at least this many bytes long are compressed if that makes them smaller.
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// The most bytes a single byte of an LZ4 block expands to.
+const MAX_RATIO: usize = 255;
+
+
+/// Compresses the data into an LZ4 block.
+pub fn compress_block(data: &[u8]) -> Vec<u8> {
+    lz4_flex::block::compress(data)
+}
+
+/// Decompresses an LZ4 block that holds exactly `length` bytes.
+pub fn decompress_block(block: &[u8], length: usize) -> Result<Vec<u8>, NetworkError> {
+    // The length comes from the peer, so nothing is reserved for more than the block can hold.
+    if length > block.len().saturating_mul(MAX_RATIO) {
+        return Err(NetworkError::Protocol(ProtocolError::DecompressionFailed));
+    }
+    match lz4_flex::block::decompress(block, length) {
+        Ok(output) if output.len() == length => Ok(output),
+        _ => Err(NetworkError::Protocol(ProtocolError::DecompressionFailed))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{compress_block, decompress_block};
+
+    #[test]
+    pub fn test_block_round_trip() {
+        let config = (0..200)
+            .map(|i| format!(r#"{{"service":"api-{i}","replicas":{},"region":"eu-west-1"}}"#, i % 5))
+            .collect::<Vec<_>>()
+            .join(",");
+        for data in [config.as_bytes(), &[b'a'; 5000][..], b"short", b""] {
+            let block = compress_block(data);
+            assert_eq!(decompress_block(&block, data.len()).unwrap(), data);
+        }
+        let block = compress_block(config.as_bytes());
+        assert!(block.len() * 4 < config.len());
+
+        // The length has to match what the block holds.
+        assert!(decompress_block(&block, config.len() - 1).is_err());
+        assert!(decompress_block(&block, config.len() + 1).is_err());
+        assert!(decompress_block(&block[..block.len() / 2], config.len()).is_err());
+    }
+}
@@ -0,0 +1,18 @@
+use super::{Key, ValueType};
+
+
+/// A required key that is missing or holds the wrong type.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyDrift {
+    pub key: Key,
+    /// The type the manifest requires.
+    pub expected: ValueType,
+    /// The type currently stored, [None] if the key is missing.
+    pub found: Option<ValueType>
+}
+
+impl KeyDrift {
+    pub fn is_missing(&self) -> bool {
+        self.found.is_none()
+    }
+}
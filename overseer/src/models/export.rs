@@ -0,0 +1,142 @@
+use crate::{error::{NetworkError, StorageError}, network::{OverseerSerde, OvrInteger, CURRENT_VERSION}};
+
+use super::{Key, LocalReadAsync, LocalWriteAsync, RecordMeta, Value};
+
+
+/// Leads every dump, followed by the [DUMP_FORMAT_VERSION] it was written
+/// with and the protocol version its values are encoded in.
+pub const DUMP_MAGIC: &[u8; 8] = b"OVRDUMP\0";
+
+/// The layout of the dumps written, bumped whenever it changes.
+pub const DUMP_FORMAT_VERSION: u8 = 1;
+
+/// A record as it is exported, with the metadata the server keeps for it.
+///
+/// Records do not expire, so there is no time to live to carry.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ExportedRecord {
+    pub key: Key,
+    pub value: Value,
+    /// [None] if the server does not know when the record was written.
+    pub meta: Option<RecordMeta>
+}
+
+/// Writes a dump of records that another server can import.
+///
+/// Every record leads with a marker and the dump ends with the number of
+/// records in it, so a dump that was cut short is told apart from one
+/// that holds less.
+pub struct DumpWriter<'a, W> {
+    writer: &'a mut W,
+    count: u64
+}
+
+impl<'a, W: LocalWriteAsync> DumpWriter<'a, W> {
+    /// Starts a dump by writing its header.
+    pub async fn new(writer: &'a mut W) -> Result<Self, NetworkError> {
+        writer.write_all(DUMP_MAGIC.to_vec()).await?;
+        writer.write_u8(DUMP_FORMAT_VERSION).await?;
+        writer.write_u8(CURRENT_VERSION).await?;
+        Ok(Self { writer, count: 0 })
+    }
+    pub async fn write(&mut self, record: &ExportedRecord) -> Result<(), NetworkError> {
+        true.serialize(self.writer).await?;
+        record.serialize(self.writer).await?;
+        self.count += 1;
+        Ok(())
+    }
+    /// Ends the dump, returning how many records it holds.
+    pub async fn finish(self) -> Result<u64, NetworkError> {
+        false.serialize(self.writer).await?;
+        OvrInteger::write(self.count, self.writer).await?;
+        Ok(self.count)
+    }
+}
+
+/// Reads the records of a dump written by a [DumpWriter].
+pub struct DumpReader<'a, R> {
+    reader: &'a mut R,
+    count: u64,
+    done: bool
+}
+
+impl<'a, R: LocalReadAsync> DumpReader<'a, R> {
+    /// Opens a dump, failing if its header is not one this reads.
+    pub async fn new(reader: &'a mut R) -> Result<Self, NetworkError> {
+        let (magic, _) = reader.read_exact(vec![0; DUMP_MAGIC.len()]).await?;
+        if magic != DUMP_MAGIC {
+            return Err(invalid("The dump does not start with its header"));
+        }
+        let format = reader.read_u8().await?;
+        if format != DUMP_FORMAT_VERSION {
+            return Err(invalid(&format!("The dump format {format} is not supported")));
+        }
+        let protocol = reader.read_u8().await?;
+        if protocol > CURRENT_VERSION {
+            return Err(invalid(&format!("The dump was written with the newer protocol {protocol}")));
+        }
+        Ok(Self { reader, count: 0, done: false })
+    }
+    /// The next record, [None] once the dump ended.
+    pub async fn next(&mut self) -> Result<Option<ExportedRecord>, NetworkError> {
+        if self.done {
+            return Ok(None);
+        }
+        if bool::deserialize(self.reader).await? {
+            self.count += 1;
+            return Ok(Some(ExportedRecord::deserialize(self.reader).await?));
+        }
+        self.done = true;
+        let count: u64 = OvrInteger::read(self.reader).await?;
+        if count != self.count {
+            return Err(invalid(&format!("The dump holds {count} records but {} were read", self.count)));
+        }
+        Ok(None)
+    }
+}
+
+fn invalid(reason: &str) -> NetworkError {
+    NetworkError::Storage(StorageError::InvalidDump(reason.to_string()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{Key, RecordMeta, Value};
+
+    use super::{DumpReader, DumpWriter, ExportedRecord};
+
+    #[tokio::test]
+    pub async fn test_dump_round_trip() {
+        let records = vec![
+            ExportedRecord { key: Key::from_str("app.mode"), value: Value::String("dark".into()), meta: None },
+            ExportedRecord {
+                key: Key::from_str("app.size"),
+                value: Value::Integer(3),
+                meta: Some(RecordMeta { created_at: 1, modified_at: 2, accessed_at: 3, writer: Some(4) })
+            }
+        ];
+        let mut dump = vec![];
+        let mut writer = DumpWriter::new(&mut dump).await.unwrap();
+        for record in &records {
+            writer.write(record).await.unwrap();
+        }
+        assert_eq!(writer.finish().await.unwrap(), 2);
+
+        let mut bytes = dump.as_slice();
+        let mut reader = DumpReader::new(&mut bytes).await.unwrap();
+        let mut read = vec![];
+        while let Some(record) = reader.next().await.unwrap() {
+            read.push(record);
+        }
+        assert_eq!(read, records);
+
+        // A dump cut short or with another header is refused.
+        let mut cut = &dump[..dump.len() - 1];
+        let mut reader = DumpReader::new(&mut cut).await.unwrap();
+        assert!(reader.next().await.unwrap().is_some());
+        assert!(reader.next().await.unwrap().is_some());
+        assert!(reader.next().await.is_err());
+        assert!(DumpReader::new(&mut &b"OVRSEED\0\x01\x17"[..]).await.is_err());
+    }
+}
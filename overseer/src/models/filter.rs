@@ -0,0 +1,168 @@
+use crate::error::NetworkError;
+
+use super::{Key, Pattern};
+
+
+/// The longest pattern a [KeyFilter] takes.
+pub const MAX_FILTER_LENGTH: usize = 256;
+
+/// How many instructions of the pattern matching a single key may follow,
+/// a key the pattern needs more for fails the listing.
+pub const MAX_FILTER_STEPS: usize = 100_000;
+
+/// Picks the keys of a listing by a glob or a regular expression, the
+/// server matches them so only the keys that match are sent.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum KeyFilter {
+    /// A glob matching the whole key, `*` standing for any characters,
+    /// `?` for a single one and `[...]` for a class such as `[a-z]` or
+    /// `[!0-9]`. A `\` makes the character after it literal.
+    Glob(String),
+    /// A [Pattern], matching anywhere in the key unless it is anchored.
+    Regex(String)
+}
+
+impl KeyFilter {
+    pub fn glob<S: Into<String>>(glob: S) -> Self {
+        Self::Glob(glob.into())
+    }
+    pub fn regex<S: Into<String>>(regex: S) -> Self {
+        Self::Regex(regex.into())
+    }
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Glob(source) | Self::Regex(source) => source
+        }
+    }
+    /// Compiles the filter into the pattern keys are matched with, failing
+    /// with [NetworkError::ValidationFailed] if it is invalid or longer
+    /// than [MAX_FILTER_LENGTH].
+    pub fn compile(&self) -> Result<KeyMatcher, NetworkError> {
+        if self.as_str().len() > MAX_FILTER_LENGTH {
+            return Err(NetworkError::ValidationFailed(format!("The filter is longer than {MAX_FILTER_LENGTH} bytes")));
+        }
+        let pattern = match self {
+            Self::Glob(glob) => Pattern::new(&glob_to_regex(glob)),
+            Self::Regex(regex) => Pattern::new(regex)
+        };
+        Ok(KeyMatcher { pattern: pattern?, prefix: self.prefix() })
+    }
+    /// The literal start of a glob, every key it matches starts with it so
+    /// a listing can skip straight to it. Empty for a regular expression.
+    pub fn prefix(&self) -> String {
+        let Self::Glob(glob) = self else {
+            return String::new();
+        };
+        let mut prefix = String::new();
+        let mut chars = glob.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' | '?' | '[' => break,
+                '\\' => match chars.next() {
+                    Some(c) => prefix.push(c),
+                    None => break
+                },
+                c => prefix.push(c)
+            }
+        }
+        prefix
+    }
+}
+
+/// A compiled [KeyFilter].
+#[derive(Clone, Debug)]
+pub struct KeyMatcher {
+    pattern: Pattern,
+    prefix: String
+}
+
+impl KeyMatcher {
+    /// See [KeyFilter::prefix].
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+    /// Checks if the key matches, failing with [NetworkError::ValidationFailed]
+    /// if this takes more than [MAX_FILTER_STEPS].
+    pub fn is_match(&self, key: &Key) -> Result<bool, NetworkError> {
+        self.pattern.is_match_within(key.as_str(), MAX_FILTER_STEPS)
+            .ok_or_else(|| NetworkError::ValidationFailed(format!("The filter {:?} is too complex to match {}", self.pattern.as_str(), key.as_str())))
+    }
+}
+
+/// Rewrites a glob as the anchored regular expression it stands for.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    let mut in_class = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if !in_class => {
+                while chars.peek() == Some(&'*') {
+                    chars.next();
+                }
+                regex.push_str(".*");
+            }
+            '?' if !in_class => regex.push('.'),
+            '[' if !in_class => {
+                in_class = true;
+                regex.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    regex.push('^');
+                }
+            }
+            ']' if in_class => {
+                in_class = false;
+                regex.push(']');
+            }
+            '-' if in_class => regex.push('-'),
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    push_literal(&mut regex, c);
+                }
+            }
+            c => push_literal(&mut regex, c)
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+fn push_literal(regex: &mut String, c: char) {
+    if "\\.^$|()[]{}*+?".contains(c) {
+        regex.push('\\');
+    }
+    regex.push(c);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::NetworkError, models::Key};
+
+    use super::{KeyFilter, MAX_FILTER_LENGTH};
+
+    #[test]
+    pub fn test_key_filter() {
+        let matches = |filter: KeyFilter, key: &str| filter.compile().unwrap().is_match(&Key::from_str(key)).unwrap();
+        assert!(matches(KeyFilter::glob("app.*.port"), "app.web.port"));
+        assert!(!matches(KeyFilter::glob("app.*.port"), "app.web.port.old"));
+        assert!(matches(KeyFilter::glob("host-?"), "host-1"));
+        assert!(!matches(KeyFilter::glob("host-?"), "host-12"));
+        assert!(matches(KeyFilter::glob("host-[!0-9]"), "host-a"));
+        assert!(matches(KeyFilter::glob("rate[*?]"), "rate*"));
+        assert!(matches(KeyFilter::glob("price$(usd)\\*"), "price$(usd)*"));
+        assert!(matches(KeyFilter::regex("\\.port$"), "app.web.port"));
+        assert!(!matches(KeyFilter::regex("^web"), "app.web"));
+
+        assert_eq!(KeyFilter::glob("app.\\*x*.port").prefix(), "app.*x");
+        assert_eq!(KeyFilter::regex("^app").prefix(), "");
+
+        assert!(matches!(KeyFilter::glob("a".repeat(MAX_FILTER_LENGTH + 1)).compile(), Err(NetworkError::ValidationFailed(..))));
+        assert!(KeyFilter::glob("host-[0-9").compile().is_err());
+        let nested = KeyFilter::regex("^(a*)*b$").compile().unwrap();
+        assert!(!nested.is_match(&Key::from_str("a".repeat(4096))).unwrap());
+        let large = KeyFilter::regex("^(a?){500}$").compile().unwrap();
+        assert!(matches!(large.is_match(&Key::from_str("a".repeat(500))), Err(NetworkError::ValidationFailed(..))));
+    }
+}
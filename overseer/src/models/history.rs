@@ -0,0 +1,142 @@
+use super::Value;
+
+
+/// A point in the history of a key.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Revision {
+    /// The key as of a version of the store.
+    Version(u64),
+    /// The key as of a time, in milliseconds since the unix epoch.
+    Timestamp(u64)
+}
+
+/// A change to a key kept in its history.
+#[derive(Clone, PartialEq, Debug)]
+pub struct HistoryEntry {
+    /// The value after the change, [None] if the key was deleted.
+    pub value: Option<Value>,
+    /// The version of the store the change produced.
+    pub version: u64,
+    /// When the change was made, in milliseconds since the unix epoch.
+    pub at: u64
+}
+
+/// A line of a [KeyDiff].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DiffLine {
+    Kept(String),
+    Removed(String),
+    Added(String)
+}
+
+/// What a key held at two revisions and how it changed in between.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyDiff {
+    pub from: Option<Value>,
+    pub to: Option<Value>,
+    pub lines: Vec<DiffLine>
+}
+
+impl KeyDiff {
+    /// Diffs two values, strings line by line and anything else as a whole.
+    pub fn between(from: Option<Value>, to: Option<Value>) -> Self {
+        let lines = match (&from, &to) {
+            (Some(Value::String(old)), Some(Value::String(new))) => diff_lines(old, new),
+            (old, new) => {
+                let mut lines = vec![];
+                if old != new {
+                    lines.extend(old.as_ref().map(|f| DiffLine::Removed(render(f))));
+                    lines.extend(new.as_ref().map(|f| DiffLine::Added(render(f))));
+                } else if let Some(value) = old {
+                    lines.push(DiffLine::Kept(render(value)));
+                }
+                lines
+            }
+        };
+        Self { from, to, lines }
+    }
+    /// Whether the key held the same value at both revisions.
+    pub fn is_unchanged(&self) -> bool {
+        self.from == self.to
+    }
+}
+
+fn render(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.to_string(),
+        Value::Integer(integer) => integer.to_string(),
+        Value::Map(fields) => {
+            let fields: Vec<String> = fields.iter().map(|(name, value)| format!("{name} = {}", render(value))).collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        Value::Json(json) => json.clone()
+    }
+}
+
+/// Diffs two texts line by line along their longest common subsequence.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // The length of the common subsequence of every pair of suffixes.
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(DiffLine::Kept(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            lines.push(DiffLine::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|f| DiffLine::Removed(f.to_string())));
+    lines.extend(new[j..].iter().map(|f| DiffLine::Added(f.to_string())));
+    lines
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::models::Value;
+
+    use super::{diff_lines, DiffLine, KeyDiff};
+
+    #[test]
+    pub fn test_diff_lines() {
+        let lines = diff_lines("a\nb\nc", "a\nc\nd");
+        assert_eq!(lines, vec![
+            DiffLine::Kept("a".to_string()),
+            DiffLine::Removed("b".to_string()),
+            DiffLine::Kept("c".to_string()),
+            DiffLine::Added("d".to_string())
+        ]);
+    }
+
+    #[test]
+    pub fn test_diff_values() {
+        let diff = KeyDiff::between(Some(Value::Integer(3)), Some(Value::Integer(4)));
+        assert_eq!(diff.lines, vec![DiffLine::Removed("3".to_string()), DiffLine::Added("4".to_string())]);
+
+        let diff = KeyDiff::between(None, Some(Value::String("on".into())));
+        assert_eq!(diff.lines, vec![DiffLine::Added("on".to_string())]);
+
+        let diff = KeyDiff::between(Some(Value::Integer(3)), Some(Value::Integer(3)));
+        assert!(diff.is_unchanged());
+    }
+}
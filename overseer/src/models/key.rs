@@ -1,36 +1,152 @@
 use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::{error::NetworkError};
+use crate::network::OverseerSerde;
 
-use super::{LocalReadAsync, LocalWriteAsync};
+use crate::error::NetworkError;
 
+use super::{KeyPolicy, SmallString};
 
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub struct Key(String);
+
+/// Separates the segments of a hierarchical key, as in `app.kafka.brokers`.
+pub const KEY_SEPARATOR: char = '.';
+
+/// Keys are ordered by their bytes, which is the order they are listed in.
+///
+/// Keys nest by their segments, see [KEY_SEPARATOR]. Empty segments are
+/// skipped, so `app..mode.` names the same place as `app.mode`, and the
+/// empty key is the root every key descends from.
+///
+/// Short keys are kept inline, see [SmallString].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, OverseerSerde)]
+pub struct Key(SmallString);
 
 impl Key {
     pub fn from_owned(key: String) -> Self {
-        Self(key)
+        Self(key.into())
     }
     pub fn from_str<S: AsRef<str>>(key: S) -> Self {
-        Self(key.as_ref().to_string())
+        Self(SmallString::new(key.as_ref()))
+    }
+    /// The key, if the default [KeyPolicy] accepts it.
+    pub fn try_from_str<S: AsRef<str>>(key: S) -> Result<Self, NetworkError> {
+        KeyPolicy::default().key(key)
     }
     pub fn as_str(&self) -> &str {
         &self.0
     }
+    /// The key every other key descends from.
+    pub fn root() -> Self {
+        Self::from_str("")
+    }
+    /// Joins the segments into a key, skipping the empty ones.
+    pub fn from_segments<I, S>(segments: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>
+    {
+        let mut key = String::new();
+        for segment in segments {
+            for segment in segment.as_ref().split(KEY_SEPARATOR).filter(|f| !f.is_empty()) {
+                if !key.is_empty() {
+                    key.push(KEY_SEPARATOR);
+                }
+                key.push_str(segment);
+            }
+        }
+        Self::from_owned(key)
+    }
+    /// The segments of the key from the root down, the empty ones skipped.
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.as_str().split(KEY_SEPARATOR).filter(|f| !f.is_empty())
+    }
+    /// The key with its empty segments dropped.
+    pub fn canonical(&self) -> Self {
+        Self::from_segments(self.segments())
+    }
+    pub fn is_root(&self) -> bool {
+        self.segments().next().is_none()
+    }
+    /// The key of the child, which can name more than one segment.
+    pub fn child<S: AsRef<str>>(&self, name: S) -> Self {
+        Self::from_segments(self.segments().chain([name.as_ref()]))
+    }
+    /// The key one segment up, [None] for the root.
+    pub fn parent(&self) -> Option<Self> {
+        let mut segments: Vec<&str> = self.segments().collect();
+        segments.pop()?;
+        Some(Self::from_segments(segments))
+    }
+    /// The last segment of the key, [None] for the root.
+    pub fn name(&self) -> Option<&str> {
+        self.segments().last()
+    }
+    /// The prefix every key below this one starts with.
+    pub fn child_prefix(&self) -> String {
+        let canonical = self.canonical();
+        match canonical.is_root() {
+            true => String::new(),
+            false => format!("{}{KEY_SEPARATOR}", canonical.as_str())
+        }
+    }
+}
+
+/// A key one segment below another, see [Key::child].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyChild {
+    pub key: Key,
+    /// Whether the key holds a record itself.
+    pub has_value: bool,
+    /// Whether there are keys below this one.
+    pub has_children: bool
+}
+
+/// A key that is watched and by how many clients.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WatchedKey {
+    pub key: Key,
+    pub clients: u64
 }
 
 
 impl Into<Key> for &str {
     fn into(self) -> Key {
-        Key(self.to_string())
+        Key(self.into())
     }
 }
 
 impl Into<Key> for String {
     fn into(self) -> Key {
-        Key(self)
+        Key(self.into())
     }
-}
\ No newline at end of file
+}
+
+impl From<SmallString> for Key {
+    fn from(key: SmallString) -> Self {
+        Key(key)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Key;
+
+    #[test]
+    pub fn test_key_hierarchy() {
+        let kafka = Key::root().child("app").child("kafka");
+        assert_eq!(kafka, Key::from_str("app.kafka"));
+        assert_eq!(kafka.child("brokers.0").segments().collect::<Vec<_>>(), ["app", "kafka", "brokers", "0"]);
+        assert_eq!(kafka.parent(), Some(Key::from_str("app")));
+        assert_eq!(Key::from_str("app").parent(), Some(Key::root()));
+        assert_eq!(Key::root().parent(), None);
+        assert_eq!(kafka.name(), Some("kafka"));
+
+        // Stray separators name the same key.
+        assert_eq!(Key::from_str(".app..kafka.").canonical(), kafka);
+        assert_eq!(Key::from_str("..").child(".app."), Key::from_str("app"));
+        assert!(Key::from_str(".").is_root());
+        assert_eq!(kafka.child_prefix(), "app.kafka.");
+        assert_eq!(Key::root().child_prefix(), "");
+    }
+}
@@ -0,0 +1,115 @@
+use crate::error::NetworkError;
+
+use super::Key;
+
+
+/// The longest key the default [KeyPolicy] accepts, in bytes.
+pub const MAX_KEY_LENGTH: usize = 1024;
+
+/// The characters a [KeyPolicy] lets into keys.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum KeyCharset {
+    /// Every character but the control characters.
+    #[default]
+    Printable,
+    /// ASCII letters, digits and `.`, `-`, `_`, `/`, `:` and `#`.
+    Identifier,
+    /// Every character.
+    Any
+}
+
+impl KeyCharset {
+    pub fn allows(&self, c: char) -> bool {
+        match self {
+            Self::Printable => !c.is_control(),
+            Self::Identifier => c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '/' | ':' | '#'),
+            Self::Any => true
+        }
+    }
+}
+
+/// What a key has to look like to be written.
+///
+/// The server checks the keys of every write it decodes against its
+/// policy and refuses the ones that break it with
+/// [NetworkError::InvalidKey], see [Key::try_from_str] for the check on
+/// the client.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyPolicy {
+    /// The longest key accepted, in bytes.
+    pub max_length: usize,
+    pub charset: KeyCharset,
+    /// The prefixes only the server itself writes under.
+    pub reserved_prefixes: Vec<String>
+}
+
+impl Default for KeyPolicy {
+    fn default() -> Self {
+        Self { max_length: MAX_KEY_LENGTH, charset: KeyCharset::default(), reserved_prefixes: vec![] }
+    }
+}
+
+impl KeyPolicy {
+    /// A policy that accepts every key.
+    pub fn unrestricted() -> Self {
+        Self { max_length: usize::MAX, charset: KeyCharset::Any, reserved_prefixes: vec![] }
+    }
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+    pub fn with_charset(mut self, charset: KeyCharset) -> Self {
+        self.charset = charset;
+        self
+    }
+    /// Refuses the keys starting with the prefix.
+    pub fn with_reserved_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.reserved_prefixes.push(prefix.into());
+        self
+    }
+    /// Checks the key against the policy.
+    pub fn check(&self, key: &str) -> Result<(), NetworkError> {
+        if key.len() > self.max_length {
+            return Err(NetworkError::InvalidKey(format!("The key is {} bytes, the most allowed is {}", key.len(), self.max_length)));
+        }
+        if let Some(c) = key.chars().find(|c| !self.charset.allows(*c)) {
+            return Err(NetworkError::InvalidKey(format!("The key holds the character {c:?}, which is not allowed")));
+        }
+        if let Some(prefix) = self.reserved_prefixes.iter().find(|f| key.starts_with(f.as_str())) {
+            return Err(NetworkError::InvalidKey(format!("The prefix {prefix} is reserved")));
+        }
+        Ok(())
+    }
+    /// The key, if the policy accepts it.
+    pub fn key<S: AsRef<str>>(&self, key: S) -> Result<Key, NetworkError> {
+        self.check(key.as_ref())?;
+        Ok(Key::from_str(key))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::NetworkError, models::Key};
+
+    use super::{KeyCharset, KeyPolicy, MAX_KEY_LENGTH};
+
+    #[test]
+    pub fn test_key_policy() {
+        let policy = KeyPolicy::default();
+        assert_eq!(policy.key("app.mode").unwrap(), Key::from_str("app.mode"));
+        assert!(policy.check("").is_ok());
+        assert!(policy.check("café.ü").is_ok());
+        assert!(matches!(policy.check("app\nmode"), Err(NetworkError::InvalidKey(..))));
+        assert!(policy.check(&"a".repeat(MAX_KEY_LENGTH)).is_ok());
+        assert!(policy.check(&"a".repeat(MAX_KEY_LENGTH + 1)).is_err());
+
+        let policy = KeyPolicy::default().with_charset(KeyCharset::Identifier).with_reserved_prefix("sys.");
+        assert!(policy.check("tenant-a/app:mode#0").is_ok());
+        assert!(policy.check("café").is_err());
+        assert!(policy.check("sys.version").is_err());
+        assert!(policy.check("system").is_ok());
+        assert!(KeyPolicy::unrestricted().check("\0").is_ok());
+        assert!(Key::try_from_str("bell\x07").is_err());
+    }
+}
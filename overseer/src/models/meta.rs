@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+
+use super::Value;
+
+
+/// How soon a write to a key is made durable, the stronger classes pay
+/// for it with a sync to disk.
+///
+/// The classes are ordered from the strongest to the weakest.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Durability {
+    /// The write is synced to disk before it is acknowledged.
+    Sync,
+    /// The write reaches the file before it is acknowledged and is synced
+    /// to disk by the next periodic flush.
+    #[default]
+    Periodic,
+    /// The write is held in memory until the next periodic flush, a crash
+    /// loses what was written since.
+    BestEffort
+}
+
+impl Durability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sync => "sync",
+            Self::Periodic => "periodic",
+            Self::BestEffort => "best-effort"
+        }
+    }
+}
+
+/// How far a write has to get before the server acknowledges it.
+///
+/// A level never makes a write less durable than the class of its key.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Acknowledgement {
+    /// The write is applied in memory and as durable as the class of its
+    /// key asks for, which is what a plain insert waits for.
+    #[default]
+    Applied,
+    /// The write is synced to disk.
+    Synced,
+    /// The write is applied by this many followers.
+    ///
+    /// Experimental, followers only join a server from within its process,
+    /// so over the network any count above zero times out.
+    Replicated(u32)
+}
+
+impl Acknowledgement {
+    /// The durability class the write gets, the stronger of what the level
+    /// asks for and the class of its key.
+    pub fn durability(&self, class: Durability) -> Durability {
+        let asked = match self {
+            Self::Applied => Durability::BestEffort,
+            Self::Synced => Durability::Sync,
+            Self::Replicated(_) => class
+        };
+        asked.min(class)
+    }
+}
+
+/// What the record of a key has to hold for a conditional delete of it
+/// to go ahead.
+#[derive(Clone, PartialEq, Debug)]
+pub enum DeleteCondition {
+    /// The key holds this value.
+    Value(Value),
+    /// The record of the key is at this version.
+    Version(u64)
+}
+
+/// What the server knows about a key besides its value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyMeta {
+    /// Whether the key holds a value.
+    pub exists: bool,
+    /// The durability class writes to the key get.
+    pub durability: Durability,
+    /// Who wrote the record and when, [None] if the key holds nothing or the
+    /// record was written before the store kept track of it.
+    pub record: Option<RecordMeta>
+}
+
+/// When a record was written and by whom.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct RecordMeta {
+    /// When the key was first written, in milliseconds since the unix epoch.
+    pub created_at: u64,
+    /// When the record was last written, in milliseconds since the unix epoch.
+    pub modified_at: u64,
+    /// When the record was last read or written, in milliseconds since the unix epoch.
+    pub accessed_at: u64,
+    /// The id of the connection that last wrote the record, [None] if the
+    /// server wrote it itself, such as for a seed or a replicated change.
+    pub writer: Option<u64>
+}
+
+impl RecordMeta {
+    /// The metadata of a write made at the time, a key that was written
+    /// before keeps when it was created.
+    pub fn stamp(previous: Option<&RecordMeta>, at: u64, writer: Option<u64>) -> Self {
+        Self {
+            created_at: previous.map(|f| f.created_at).unwrap_or(at),
+            modified_at: at,
+            accessed_at: at,
+            writer
+        }
+    }
+}
@@ -1,7 +1,37 @@
 pub mod key;
+pub mod key_policy;
 pub mod value;
 pub mod asynctrait;
+pub mod drift;
+pub mod compaction;
+pub mod history;
+pub mod promotion;
+pub mod meta;
+pub mod compression;
+pub mod queue;
+pub mod small_string;
+pub mod seed;
+pub mod export;
+pub mod pattern;
+pub mod schema;
+pub mod filter;
+pub mod stats;
 
 pub use crate::models::key::*;
+pub use crate::models::key_policy::*;
 pub use crate::models::value::*;
-pub use crate::models::asynctrait::*;
\ No newline at end of file
+pub use crate::models::asynctrait::*;
+pub use crate::models::drift::*;
+pub use crate::models::compaction::*;
+pub use crate::models::history::*;
+pub use crate::models::promotion::*;
+pub use crate::models::meta::*;
+pub use crate::models::compression::*;
+pub use crate::models::queue::*;
+pub use crate::models::small_string::*;
+pub use crate::models::seed::*;
+pub use crate::models::export::*;
+pub use crate::models::pattern::*;
+pub use crate::models::schema::*;
+pub use crate::models::filter::*;
+pub use crate::models::stats::*;
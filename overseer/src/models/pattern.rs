@@ -0,0 +1,476 @@
+use crate::error::NetworkError;
+
+
+/// The longest pattern that compiles, which bounds how deep its groups nest.
+pub const MAX_PATTERN_LENGTH: usize = 1024;
+
+/// The most instructions a pattern compiles to, which bounds what the
+/// repetitions `{m,n}` expand to.
+pub const MAX_PATTERN_SIZE: usize = 10_000;
+
+/// The most parts of a pattern that are compiled, counting every time a
+/// repetition compiles its part again. This bounds parts that compile to
+/// no instructions, which [MAX_PATTERN_SIZE] does not see.
+const MAX_COMPILE_STEPS: usize = 10 * MAX_PATTERN_SIZE;
+
+/// A regular expression, the subset a [super::Schema] checks strings with.
+///
+/// Supports literals, `.`, classes such as `[a-z_]` and `[^0-9]`, the
+/// escapes `\d`, `\w`, `\s` and their negations, the anchors `^` and `$`,
+/// groups with alternatives `(a|b)` and the repetitions `*`, `+`, `?`
+/// and `{m,n}`. A pattern matches anywhere in the string unless it is
+/// anchored.
+///
+/// Strings are matched by following every way the pattern could go at
+/// once, so matching takes time in proportion to the length of the string
+/// and the size of the pattern, and no stack in proportion to either.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Pattern {
+    source: String,
+    program: Vec<Inst>
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class { ranges: Vec<(char, char)>, negated: bool },
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat { node: Box<Node>, min: usize, max: Option<usize> }
+}
+
+impl Node {
+    /// Whether the node compiles to no instructions, so repeating it
+    /// matches nothing more than it does once.
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::Concat(nodes) | Self::Alternate(nodes) => nodes.iter().all(Self::is_empty),
+            Self::Repeat { node, max, .. } => *max == Some(0) || node.is_empty(),
+            _ => false
+        }
+    }
+}
+
+/// A step of a compiled pattern.
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class { ranges: Vec<(char, char)>, negated: bool },
+    Start,
+    End,
+    /// Goes on at both instructions.
+    Split(usize, usize),
+    Jump(usize),
+    Match
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Self, NetworkError> {
+        let invalid = |reason: String| NetworkError::ValidationFailed(format!("The pattern {source:?} is invalid, {reason}"));
+        if source.len() > MAX_PATTERN_LENGTH {
+            return Err(invalid(format!("it is longer than {MAX_PATTERN_LENGTH} bytes")));
+        }
+        let mut parser = Parser { chars: source.chars().collect(), at: 0 };
+        let node = parser.alternation().and_then(|node| match parser.peek() {
+            None => Ok(node),
+            Some(c) => Err(format!("unexpected {c:?}"))
+        }).map_err(invalid)?;
+        let mut compiler = Compiler { program: vec![], steps: 0 };
+        compiler.node(&node).and_then(|_| compiler.emit(Inst::Match)).map_err(invalid)?;
+        Ok(Self { source: source.to_string(), program: compiler.program })
+    }
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+    /// Checks if the pattern matches somewhere in the string.
+    pub fn is_match(&self, string: &str) -> bool {
+        self.is_match_within(string, usize::MAX).unwrap_or_default()
+    }
+    /// Checks if the pattern matches somewhere in the string, giving up
+    /// with [None] after following `steps` instructions. This bounds the
+    /// time a large pattern takes on a long string.
+    pub fn is_match_within(&self, string: &str, steps: usize) -> Option<bool> {
+        let chars: Vec<char> = string.chars().collect();
+        let mut search = Search { program: &self.program, length: chars.len(), steps, stack: vec![] };
+        let (mut current, mut next) = (Threads::new(self.program.len()), Threads::new(self.program.len()));
+        for at in 0..=chars.len() {
+            // A match may start anywhere.
+            if search.follow(&mut current, 0, at)? {
+                return Some(true);
+            }
+            let Some(c) = chars.get(at) else {
+                break;
+            };
+            next.clear();
+            for &pc in &current.pcs {
+                let consumed = match &self.program[pc] {
+                    Inst::Char(expected) => expected == c,
+                    Inst::Any => true,
+                    Inst::Class { ranges, negated } => ranges.iter().any(|(low, high)| (low..=high).contains(&c)) != *negated,
+                    _ => false
+                };
+                if consumed && search.follow(&mut next, pc + 1, at + 1)? {
+                    return Some(true);
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        Some(false)
+    }
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+    /// How many parts were compiled so far, see [MAX_COMPILE_STEPS].
+    steps: usize
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> Result<usize, String> {
+        if self.program.len() >= MAX_PATTERN_SIZE {
+            return Err(format!("it compiles to more than {MAX_PATTERN_SIZE} instructions"));
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+    fn node(&mut self, node: &Node) -> Result<(), String> {
+        self.steps += 1;
+        if self.steps > MAX_COMPILE_STEPS {
+            return Err(format!("it takes more than {MAX_COMPILE_STEPS} steps to compile"));
+        }
+        match node {
+            Node::Char(c) => self.emit(Inst::Char(*c)).map(|_| ()),
+            Node::Any => self.emit(Inst::Any).map(|_| ()),
+            Node::Class { ranges, negated } => self.emit(Inst::Class { ranges: ranges.clone(), negated: *negated }).map(|_| ()),
+            Node::Start => self.emit(Inst::Start).map(|_| ()),
+            Node::End => self.emit(Inst::End).map(|_| ()),
+            Node::Concat(nodes) => nodes.iter().try_for_each(|f| self.node(f)),
+            Node::Alternate(nodes) => {
+                let mut jumps = vec![];
+                for (i, alternative) in nodes.iter().enumerate() {
+                    if i + 1 == nodes.len() {
+                        self.node(alternative)?;
+                        break;
+                    }
+                    let split = self.emit(Inst::Split(0, 0))?;
+                    self.node(alternative)?;
+                    jumps.push(self.emit(Inst::Jump(0))?);
+                    self.program[split] = Inst::Split(split + 1, self.program.len());
+                }
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(self.program.len());
+                }
+                Ok(())
+            }
+            Node::Repeat { node, min, max } => {
+                for _ in 0..*min {
+                    self.node(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.emit(Inst::Split(0, 0))?;
+                        self.node(node)?;
+                        self.emit(Inst::Jump(split))?;
+                        self.program[split] = Inst::Split(split + 1, self.program.len());
+                    }
+                    Some(max) => {
+                        let mut splits = vec![];
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Split(0, 0))?);
+                            self.node(node)?;
+                        }
+                        for split in splits {
+                            self.program[split] = Inst::Split(split + 1, self.program.len());
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The instructions the match is at, each at most once.
+struct Threads {
+    pcs: Vec<usize>,
+    on: Vec<bool>
+}
+
+impl Threads {
+    fn new(size: usize) -> Self {
+        Self { pcs: vec![], on: vec![false; size] }
+    }
+    fn insert(&mut self, pc: usize) -> bool {
+        let inserted = !self.on[pc];
+        if inserted {
+            self.on[pc] = true;
+            self.pcs.push(pc);
+        }
+        inserted
+    }
+    fn clear(&mut self) {
+        for pc in self.pcs.drain(..) {
+            self.on[pc] = false;
+        }
+    }
+}
+
+/// A match of a program against a string of the length, which fails once
+/// it follows more than `steps` instructions.
+struct Search<'p> {
+    program: &'p [Inst],
+    length: usize,
+    steps: usize,
+    stack: Vec<usize>
+}
+
+impl Search<'_> {
+    /// Adds the instruction to the threads at the position, along with every
+    /// one it goes on to without reading a character. Whether one of them
+    /// is the end of the pattern, or [None] once the steps run out.
+    fn follow(&mut self, threads: &mut Threads, pc: usize, at: usize) -> Option<bool> {
+        self.stack.push(pc);
+        while let Some(pc) = self.stack.pop() {
+            self.steps = self.steps.checked_sub(1)?;
+            // An instruction already followed here, as by an empty repetition, adds nothing.
+            if !threads.insert(pc) {
+                continue;
+            }
+            match self.program[pc] {
+                Inst::Split(first, second) => self.stack.extend([second, first]),
+                Inst::Jump(to) => self.stack.push(to),
+                Inst::Start if at == 0 => self.stack.push(pc + 1),
+                Inst::End if at == self.length => self.stack.push(pc + 1),
+                Inst::Match => {
+                    self.stack.clear();
+                    return Some(true);
+                }
+                _ => {}
+            }
+        }
+        Some(false)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+    fn take(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.at += c.is_some() as usize;
+        c
+    }
+    fn eat(&mut self, c: char) -> bool {
+        let eaten = self.peek() == Some(c);
+        self.at += eaten as usize;
+        eaten
+    }
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut alternatives = vec![self.concat()?];
+        while self.eat('|') {
+            alternatives.push(self.concat()?);
+        }
+        Ok(match alternatives.len() {
+            1 => alternatives.remove(0),
+            _ => Node::Alternate(alternatives)
+        })
+    }
+    fn concat(&mut self) -> Result<Node, String> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let mut node = self.atom()?;
+            while let Some((min, max)) = self.quantifier()? {
+                if !node.is_empty() {
+                    node = Node::Repeat { node: Box::new(node), min, max };
+                }
+            }
+            nodes.push(node);
+        }
+        Ok(Node::Concat(nodes))
+    }
+    fn atom(&mut self) -> Result<Node, String> {
+        Ok(match self.take().ok_or("the pattern ends early")? {
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err("only the groups `(?:...)` take a `?`".to_string());
+                }
+                let node = self.alternation()?;
+                if !self.eat(')') {
+                    return Err("a group is not closed".to_string());
+                }
+                node
+            }
+            '[' => self.class()?,
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '\\' => self.escape()?,
+            c @ ('*' | '+' | '?' | '{') => return Err(format!("{c:?} repeats nothing")),
+            c => Node::Char(c)
+        })
+    }
+    fn quantifier(&mut self) -> Result<Option<(usize, Option<usize>)>, String> {
+        let quantifier = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => return self.bounds().map(Some),
+            _ => return Ok(None)
+        };
+        self.at += 1;
+        Ok(Some(quantifier))
+    }
+    /// Reads the repetition `{m}`, `{m,}` or `{m,n}`.
+    fn bounds(&mut self) -> Result<(usize, Option<usize>), String> {
+        self.at += 1;
+        let min = self.number().ok_or("a repetition has no minimum")?;
+        let max = match self.eat(',') {
+            true => self.number(),
+            false => Some(min)
+        };
+        if !self.eat('}') {
+            return Err("a repetition is not closed".to_string());
+        }
+        match max {
+            Some(max) if max < min => Err(format!("the repetition {{{min},{max}}} is backwards")),
+            _ if max.unwrap_or(min) > MAX_PATTERN_SIZE => Err(format!("the repetition repeats more than {MAX_PATTERN_SIZE} times")),
+            max => Ok((min, max))
+        }
+    }
+    fn number(&mut self) -> Option<usize> {
+        let start = self.at;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.at += 1;
+        }
+        self.chars[start..self.at].iter().collect::<String>().parse().ok()
+    }
+    fn escape(&mut self) -> Result<Node, String> {
+        let c = self.take().ok_or("the pattern ends in an escape")?;
+        Ok(match shorthand(c) {
+            Some(ranges) => Node::Class { ranges: ranges.to_vec(), negated: c.is_ascii_uppercase() },
+            None => Node::Char(literal(c))
+        })
+    }
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.eat('^');
+        let mut ranges = vec![];
+        let mut first = true;
+        loop {
+            let c = self.take().ok_or("a class is not closed")?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let low = match c {
+                '\\' => {
+                    let escaped = self.take().ok_or("the pattern ends in an escape")?;
+                    if let Some(shorthand) = shorthand(escaped).filter(|_| escaped.is_ascii_lowercase()) {
+                        ranges.extend_from_slice(shorthand);
+                        continue;
+                    }
+                    literal(escaped)
+                }
+                c => c
+            };
+            if self.peek() == Some('-') && self.chars.get(self.at + 1).is_some_and(|f| *f != ']') {
+                self.at += 1;
+                let high = match self.take() {
+                    Some('\\') => literal(self.take().ok_or("the pattern ends in an escape")?),
+                    high => high.unwrap_or_default()
+                };
+                if high < low {
+                    return Err(format!("the range {low}-{high} is backwards"));
+                }
+                ranges.push((low, high));
+            } else {
+                ranges.push((low, low));
+            }
+        }
+        Ok(Node::Class { ranges, negated })
+    }
+}
+
+/// The characters of `\d`, `\w` and `\s`, the upper case escapes negate them.
+fn shorthand(c: char) -> Option<&'static [(char, char)]> {
+    match c.to_ascii_lowercase() {
+        'd' => Some(&[('0', '9')]),
+        'w' => Some(&[('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')]),
+        's' => Some(&[(' ', ' '), ('\t', '\r')]),
+        _ => None
+    }
+}
+
+fn literal(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        c => c
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Pattern, MAX_PATTERN_LENGTH};
+
+    #[test]
+    pub fn test_pattern() {
+        let matches = |pattern: &str, string: &str| Pattern::new(pattern).unwrap().is_match(string);
+        assert!(matches("^[a-z]+@[a-z]+\\.com$", "jim@example.com"));
+        assert!(!matches("^[a-z]+@[a-z]+\\.com$", "jim@example.org"));
+        assert!(matches("\\d{3}-\\d{4}", "call 555-1234 now"));
+        assert!(!matches("^\\d{3}-\\d{4}$", "555-12345"));
+        assert!(matches("^(red|green|blue)$", "green"));
+        assert!(!matches("^(red|green|blue)$", "greenish"));
+        assert!(matches("^v\\d+(\\.\\d+)?$", "v12.3"));
+        assert!(matches("^[^\\s]*$", "no-spaces"));
+        assert!(!matches("^\\S*$", "a space"));
+        assert!(matches("^(a*)*b$", "aaab"));
+        assert!(matches("^ab{2,}c?$", "abbb"));
+        assert!(matches("", "anything"));
+
+        for invalid in ["(a", "a)", "*a", "[a-", "a{3,1}", "[z-a]", "\\"] {
+            assert!(Pattern::new(invalid).is_err(), "{invalid} should not compile");
+        }
+
+        assert!(Pattern::new(&"a".repeat(MAX_PATTERN_LENGTH + 1)).is_err());
+        assert!(Pattern::new("(a{100}){200}").is_err());
+        assert!(Pattern::new("(){1000000000}").is_err());
+        // Repeating what compiles to nothing compiles to nothing.
+        assert!(Pattern::new("(((){10000}){10000}){10000}").unwrap().is_match("a"));
+        assert!(Pattern::new("^((a{0}){10000}){10000}b$").unwrap().is_match("b"));
+        // Few instructions, but every repetition compiles each of the groups again.
+        assert!(Pattern::new(&format!("{}a{}{{2000}}", "(".repeat(100), ")".repeat(100))).is_err());
+
+        let nested = Pattern::new("^(a*)*b$").unwrap();
+        assert_eq!(nested.is_match_within(&"a".repeat(30), 10_000), Some(false));
+        assert_eq!(nested.is_match_within(&"a".repeat(30), 10), None);
+        assert_eq!(nested.is_match_within("aab", 10_000), Some(true));
+        assert_eq!(nested.is_match_within("aac", 10_000), Some(false));
+    }
+
+    #[test]
+    pub fn test_pattern_long_input() {
+        // Neither the length of the string nor how the pattern nests grows the stack.
+        let long = "a".repeat(100_000);
+        assert!(Pattern::new("^.*$").unwrap().is_match(&long));
+        assert!(!Pattern::new("^(a*)*b$").unwrap().is_match(&long));
+        assert!(Pattern::new("(a|aa)+$").unwrap().is_match(&long));
+        let deep = format!("{}a{}", "(".repeat(400), ")".repeat(400));
+        assert!(Pattern::new(&deep).unwrap().is_match("a"));
+    }
+}
@@ -0,0 +1,40 @@
+use super::Key;
+
+
+/// Copies the keys under a prefix of one namespace to a prefix of another,
+/// `app.` in `staging` to `app.` in `production` for instance.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Promotion {
+    pub source: String,
+    pub source_prefix: String,
+    pub target: String,
+    pub target_prefix: String,
+    /// Deletes the keys under the target prefix that the source does not have.
+    pub prune: bool
+}
+
+impl Promotion {
+    /// Where a key of the source lands in the target, [None] if the key
+    /// is not under the source prefix.
+    pub fn target_key(&self, key: &Key) -> Option<Key> {
+        let rest = key.as_str().strip_prefix(&self.source_prefix)?;
+        Some(Key::from_owned(format!("{}{rest}", self.target_prefix)))
+    }
+}
+
+/// The keys of the target a promotion or rollback changed.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct PromotionReport {
+    /// Identifies an applied promotion for a rollback, [None] for a dry run.
+    pub id: Option<u64>,
+    pub added: Vec<Key>,
+    pub changed: Vec<Key>,
+    pub removed: Vec<Key>
+}
+
+impl PromotionReport {
+    /// Whether the target already matched.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
@@ -0,0 +1,13 @@
+use super::{Key, Value};
+
+
+/// An item of a work queue, hidden from the other consumers until it is
+/// acknowledged or the lease runs out.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LeasedItem {
+    /// The key the item is stored under, which names it when acknowledging.
+    pub key: Key,
+    pub value: Value,
+    /// Identifies the lease, only its holder can acknowledge the item.
+    pub lease: u64
+}
@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+use crate::error::NetworkError;
+
+use super::{Pattern, Value};
+
+
+/// How many instructions matching a string against a pattern of a schema
+/// may follow, a string the pattern needs more for does not conform.
+pub const MAX_SCHEMA_STEPS: usize = 1_000_000;
+
+/// What the values under a key prefix have to look like to be written.
+///
+/// Schemas are registered with the server for a prefix, which refuses the
+/// writes of values that do not conform with [NetworkError::ValidationFailed].
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Schema {
+    /// A string matched by a [Pattern].
+    String { pattern: String },
+    /// An integer within the bounds, which are inclusive.
+    Integer { min: Option<i64>, max: Option<i64> },
+    /// A JSON value or a map conforming to a JSON Schema, of which the
+    /// keywords `type`, `enum`, `const`, `minimum`, `maximum`, `minLength`,
+    /// `maxLength`, `pattern`, `items`, `minItems`, `maxItems`,
+    /// `properties`, `required` and `additionalProperties` are checked.
+    Json { schema: String }
+}
+
+impl Schema {
+    pub fn string<S: Into<String>>(pattern: S) -> Self {
+        Self::String { pattern: pattern.into() }
+    }
+    pub fn integer(min: Option<i64>, max: Option<i64>) -> Self {
+        Self::Integer { min, max }
+    }
+    pub fn json<S: Into<String>>(schema: S) -> Self {
+        Self::Json { schema: schema.into() }
+    }
+    /// Checks that the schema itself is well formed, so that it can
+    /// validate values.
+    pub fn check(&self) -> Result<(), NetworkError> {
+        self.compile().map(|_| ())
+    }
+    /// Compiles the schema into the validator values are checked with,
+    /// failing if it is not well formed. Patterns are limited to
+    /// [super::MAX_PATTERN_LENGTH].
+    pub fn compile(&self) -> Result<SchemaValidator, NetworkError> {
+        let mut patterns = HashMap::new();
+        let json = match self {
+            Self::String { pattern } => {
+                patterns.insert(pattern.clone(), Pattern::new(pattern)?);
+                None
+            }
+            Self::Integer { min: Some(min), max: Some(max) } if min > max => return Err(NetworkError::ValidationFailed(format!("The range {min}..={max} is empty"))),
+            Self::Integer { .. } => None,
+            Self::Json { schema } => {
+                let json = parse_json_schema(schema)?;
+                compile_json_schema(&json, &mut patterns)?;
+                Some(json)
+            }
+        };
+        Ok(SchemaValidator { schema: self.clone(), json, patterns })
+    }
+    /// Checks that the value conforms to the schema, see [SchemaValidator::validate].
+    pub fn validate(&self, value: &Value) -> Result<(), NetworkError> {
+        self.compile()?.validate(value)
+    }
+    /// The kind of value the schema accepts.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::String { .. } => "string",
+            Self::Integer { .. } => "integer",
+            Self::Json { .. } => "json"
+        }
+    }
+}
+
+/// A compiled [Schema], its JSON Schema parsed and its patterns compiled
+/// once rather than for every value.
+#[derive(Clone, Debug)]
+pub struct SchemaValidator {
+    schema: Schema,
+    json: Option<Json>,
+    /// The compiled patterns by their source.
+    patterns: HashMap<String, Pattern>
+}
+
+impl SchemaValidator {
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+    /// Checks that the value conforms to the schema.
+    pub fn validate(&self, value: &Value) -> Result<(), NetworkError> {
+        let failed = |reason: String| NetworkError::ValidationFailed(reason);
+        match (&self.schema, value) {
+            (Schema::String { pattern }, Value::String(string)) => match is_match(&self.patterns, pattern, string).map_err(failed)? {
+                true => Ok(()),
+                false => Err(failed(format!("The string does not match {pattern:?}")))
+            },
+            (Schema::Integer { min, max }, Value::Integer(integer)) => {
+                if let Some(min) = min.filter(|f| integer < f) {
+                    return Err(failed(format!("{integer} is less than the minimum {min}")));
+                }
+                if let Some(max) = max.filter(|f| integer > f) {
+                    return Err(failed(format!("{integer} is more than the maximum {max}")));
+                }
+                Ok(())
+            }
+            (Schema::Json { .. }, Value::Json(..) | Value::Map(..)) => match &self.json {
+                Some(json) => validate_json(json, &self.patterns, &to_json(value), "$").map_err(failed),
+                None => Ok(())
+            },
+            (schema, value) => Err(failed(format!("A {} value where a {} is expected", value.type_name(), schema.type_name())))
+        }
+    }
+}
+
+/// Matches the string against the compiled pattern with the source, within [MAX_SCHEMA_STEPS].
+fn is_match(patterns: &HashMap<String, Pattern>, pattern: &str, string: &str) -> Result<bool, String> {
+    patterns.get(pattern)
+        .and_then(|f| f.is_match_within(string, MAX_SCHEMA_STEPS))
+        .ok_or_else(|| format!("The pattern {pattern:?} is too complex to match the string"))
+}
+
+fn parse_json_schema(schema: &str) -> Result<Json, NetworkError> {
+    serde_json::from_str(schema).map_err(|e| NetworkError::ValidationFailed(format!("The JSON Schema is not JSON, {e}")))
+}
+
+/// Reads a value as JSON, the fields of a map becoming the members of an
+/// object.
+fn to_json(value: &Value) -> Json {
+    match value {
+        Value::String(string) => Json::String(string.to_string()),
+        Value::Integer(integer) => Json::from(*integer),
+        Value::Map(fields) => Json::Object(fields.iter().map(|(name, field)| (name.clone(), to_json(field))).collect()),
+        Value::Json(json) => serde_json::from_str(json).unwrap_or_else(|_| Json::String(json.clone()))
+    }
+}
+
+/// Visits the schema and the schemas nested in it.
+fn visit_schemas<'a>(schema: &'a Json, visit: &mut dyn FnMut(&'a Json) -> Result<(), NetworkError>) -> Result<(), NetworkError> {
+    visit(schema)?;
+    if let Some(properties) = schema.get("properties").and_then(Json::as_object) {
+        for property in properties.values() {
+            visit_schemas(property, visit)?;
+        }
+    }
+    for nested in ["items", "additionalProperties"] {
+        if let Some(nested) = schema.get(nested) {
+            visit_schemas(nested, visit)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the JSON Schema is well formed, compiling the patterns in it.
+fn compile_json_schema(schema: &Json, patterns: &mut HashMap<String, Pattern>) -> Result<(), NetworkError> {
+    visit_schemas(schema, &mut |schema| {
+        if !schema.is_object() && !schema.is_boolean() {
+            return Err(NetworkError::ValidationFailed(format!("The JSON Schema {schema} is neither an object nor a boolean")));
+        }
+        if let Some(pattern) = schema.get("pattern") {
+            let pattern = pattern.as_str().unwrap_or_default();
+            patterns.insert(pattern.to_string(), Pattern::new(pattern)?);
+        }
+        Ok(())
+    })
+}
+
+fn is_type(value: &Json, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => false
+    }
+}
+
+/// Validates the value at the path against a JSON Schema, giving the
+/// reason it does not conform.
+fn validate_json(schema: &Json, patterns: &HashMap<String, Pattern>, value: &Json, path: &str) -> Result<(), String> {
+    let schema = match schema {
+        Json::Bool(true) => return Ok(()),
+        Json::Bool(false) => return Err(format!("{path} is not allowed")),
+        Json::Object(schema) => schema,
+        _ => return Ok(())
+    };
+    let bound = |keyword: &str| schema.get(keyword).and_then(Json::as_f64);
+    let length = |keyword: &str| schema.get(keyword).and_then(Json::as_u64).map(|f| f as usize);
+
+    if let Some(types) = schema.get("type") {
+        let names: Vec<&str> = match types {
+            Json::Array(types) => types.iter().filter_map(Json::as_str).collect(),
+            name => name.as_str().into_iter().collect()
+        };
+        if !names.iter().any(|name| is_type(value, name)) {
+            return Err(format!("{path} is not of type {}", names.join(" or ")));
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Json::as_array).filter(|f| !f.contains(value)) {
+        return Err(format!("{path} is not one of {}", Json::Array(allowed.clone())));
+    }
+    if let Some(constant) = schema.get("const").filter(|f| *f != value) {
+        return Err(format!("{path} is not {constant}"));
+    }
+    if let Some(number) = value.as_f64() {
+        if let Some(minimum) = bound("minimum").filter(|f| number < *f) {
+            return Err(format!("{path} is less than the minimum {minimum}"));
+        }
+        if let Some(maximum) = bound("maximum").filter(|f| number > *f) {
+            return Err(format!("{path} is more than the maximum {maximum}"));
+        }
+    }
+    if let Some(string) = value.as_str() {
+        let chars = string.chars().count();
+        if let Some(min) = length("minLength").filter(|f| chars < *f) {
+            return Err(format!("{path} is shorter than {min} characters"));
+        }
+        if let Some(max) = length("maxLength").filter(|f| chars > *f) {
+            return Err(format!("{path} is longer than {max} characters"));
+        }
+        if let Some(pattern) = schema.get("pattern").and_then(Json::as_str) {
+            if !is_match(patterns, pattern, string)? {
+                return Err(format!("{path} does not match {pattern:?}"));
+            }
+        }
+    }
+    if let Some(items) = value.as_array() {
+        if let Some(min) = length("minItems").filter(|f| items.len() < *f) {
+            return Err(format!("{path} holds fewer than {min} items"));
+        }
+        if let Some(max) = length("maxItems").filter(|f| items.len() > *f) {
+            return Err(format!("{path} holds more than {max} items"));
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                validate_json(item_schema, patterns, item, &format!("{path}[{i}]"))?;
+            }
+        }
+    }
+    if let Some(members) = value.as_object() {
+        let properties = schema.get("properties").and_then(Json::as_object);
+        for required in schema.get("required").and_then(Json::as_array).into_iter().flatten().filter_map(Json::as_str) {
+            if !members.contains_key(required) {
+                return Err(format!("{path}.{required} is required"));
+            }
+        }
+        for (name, member) in members {
+            let member_path = format!("{path}.{name}");
+            match properties.and_then(|f| f.get(name)) {
+                Some(property) => validate_json(property, patterns, member, &member_path)?,
+                None => if let Some(additional) = schema.get("additionalProperties") {
+                    validate_json(additional, patterns, member, &member_path)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{error::NetworkError, models::{Value, MAX_PATTERN_LENGTH}};
+
+    use super::Schema;
+
+    #[test]
+    pub fn test_schema() {
+        let email = Schema::string("^[a-z.]+@[a-z]+\\.[a-z]+$");
+        assert!(email.validate(&Value::String("jim@example.com".into())).is_ok());
+        assert!(matches!(email.validate(&Value::String("jim".into())), Err(NetworkError::ValidationFailed(..))));
+        assert!(email.validate(&Value::Integer(1)).is_err());
+
+        let port = Schema::integer(Some(1), Some(65535));
+        assert!(port.validate(&Value::Integer(8080)).is_ok());
+        assert!(port.validate(&Value::Integer(0)).is_err());
+        assert!(port.validate(&Value::Integer(70000)).is_err());
+
+        let user = Schema::json(r#"{
+            "type": "object",
+            "required": ["name"],
+            "properties": {
+                "name": { "type": "string", "minLength": 1 },
+                "age": { "type": "integer", "minimum": 0 },
+                "tags": { "type": "array", "items": { "enum": ["admin", "guest"] } }
+            },
+            "additionalProperties": false
+        }"#);
+        assert!(user.check().is_ok());
+        assert!(user.validate(&Value::Json(r#"{ "name": "jim", "age": 30, "tags": ["admin"] }"#.into())).is_ok());
+        for invalid in [r#"{ "age": 30 }"#, r#"{ "name": "" }"#, r#"{ "name": "jim", "age": -1 }"#, r#"{ "name": "jim", "tags": ["root"] }"#, r#"{ "name": "jim", "extra": 1 }"#, "[]"] {
+            assert!(user.validate(&Value::Json(invalid.into())).is_err(), "{invalid} should not conform");
+        }
+        // Maps are checked as objects.
+        let mut fields = BTreeMap::new();
+        fields.insert("name".to_string(), Value::String("jim".into()));
+        fields.insert("age".to_string(), Value::Integer(30));
+        assert!(user.validate(&Value::Map(fields)).is_ok());
+
+        assert!(Schema::string("(").check().is_err());
+        assert!(Schema::integer(Some(2), Some(1)).check().is_err());
+        assert!(Schema::json("{ not json").check().is_err());
+        assert!(Schema::json(r#"{ "properties": { "a": { "pattern": "[" } } }"#).check().is_err());
+        assert!(Schema::string("a".repeat(MAX_PATTERN_LENGTH + 1)).check().is_err());
+    }
+
+    #[test]
+    pub fn test_schema_long_values() {
+        // A long string neither overflows the stack nor takes long to check.
+        let anything = Schema::string("^.*$").compile().unwrap();
+        assert!(anything.validate(&Value::String("a".repeat(100_000).into())).is_ok());
+        let nested = Schema::string("^(a*)*b$").compile().unwrap();
+        assert!(nested.validate(&Value::String("a".repeat(24).into())).is_err());
+
+        // A string the pattern takes too many steps for does not conform.
+        let large = Schema::string("^(a?){3000}$").compile().unwrap();
+        assert!(matches!(large.validate(&Value::String("a".repeat(3000).into())), Err(NetworkError::ValidationFailed(..))));
+
+        let json = Schema::json(r#"{ "items": { "pattern": "^[a-z]+$" } }"#).compile().unwrap();
+        assert!(json.validate(&Value::Json(r#"["abc", "def"]"#.into())).is_ok());
+        assert!(json.validate(&Value::Json(r#"["abc", "DEF"]"#.into())).is_err());
+    }
+}
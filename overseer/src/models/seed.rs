@@ -0,0 +1,63 @@
+use crate::error::{NetworkError, StorageError};
+
+use super::{Key, Value};
+
+
+/// Parses a seed file.
+///
+/// Every line is `key = value`, blank lines and lines starting with `#` are
+/// ignored. Quoted values are strings, values that parse as integers are
+/// integers and anything else is taken as a string.
+pub fn parse_seed_file(text: &str) -> Result<Vec<(Key, Value)>, NetworkError> {
+    let mut records = vec![];
+    for (number, line) in text.lines().enumerate() {
+        records.extend(parse_seed_line(number + 1, line)?);
+    }
+    Ok(records)
+}
+
+/// Parses a line of a seed file, [None] if it holds no record. The number
+/// of the line goes into the error.
+pub fn parse_seed_line(number: usize, line: &str) -> Result<Option<(Key, Value)>, NetworkError> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let Some((key, value)) = line.split_once('=') else {
+        return Err(NetworkError::Storage(StorageError::InvalidSeed(format!("Line {number} has no '='"))));
+    };
+    let (key, value) = (key.trim(), value.trim());
+    if key.is_empty() {
+        return Err(NetworkError::Storage(StorageError::InvalidSeed(format!("Line {number} has no key"))));
+    }
+
+    let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        Value::String(value[1..value.len() - 1].into())
+    } else if let Ok(integer) = value.parse() {
+        Value::Integer(integer)
+    } else {
+        Value::String(value.into())
+    };
+    Ok(Some((Key::from_str(key), value)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{Key, Value};
+
+    use super::parse_seed_file;
+
+
+    #[test]
+    pub fn test_parse_seed_file() {
+        let records = parse_seed_file("# Database\ndb.pool = 8\n\ndb.url = postgres://localhost\nport = \"5432\"\n").unwrap();
+        assert_eq!(records, vec![
+            (Key::from_str("db.pool"), Value::Integer(8)),
+            (Key::from_str("db.url"), Value::String("postgres://localhost".into())),
+            (Key::from_str("port"), Value::String("5432".into()))
+        ]);
+        assert!(parse_seed_file("missing separator").is_err());
+        assert!(parse_seed_file(" = value").is_err());
+    }
+}
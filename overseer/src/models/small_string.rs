@@ -0,0 +1,206 @@
+use std::{borrow::Borrow, cmp::Ordering, fmt, hash::{Hash, Hasher}, ops::Deref, sync::Arc};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+
+/// Strings up to this many bytes are kept inline.
+pub const INLINE_CAPACITY: usize = 22;
+
+#[derive(Clone)]
+enum Repr {
+    Inline {
+        len: u8,
+        bytes: [u8; INLINE_CAPACITY]
+    },
+    Shared(Arc<str>)
+}
+
+/// An immutable string that keeps short contents inline and shares long ones.
+///
+/// Most keys and many values are short, so making or cloning one does not
+/// allocate. A longer string is allocated once and clones only count the
+/// reference, which is what the server does whenever it hands a stored
+/// value to a reader or a watcher.
+#[derive(Clone)]
+pub struct SmallString(Repr);
+
+impl SmallString {
+    pub fn new(string: &str) -> Self {
+        match string.len() <= INLINE_CAPACITY {
+            true => {
+                let mut bytes = [0u8; INLINE_CAPACITY];
+                bytes[..string.len()].copy_from_slice(string.as_bytes());
+                Self(Repr::Inline { len: string.len() as u8, bytes })
+            }
+            false => Self(Repr::Shared(Arc::from(string)))
+        }
+    }
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            // SAFETY: the bytes were copied from a `str` up to the length.
+            Repr::Inline { len, bytes } => unsafe { std::str::from_utf8_unchecked(&bytes[..*len as usize]) },
+            Repr::Shared(string) => string
+        }
+    }
+    /// Whether the string is kept inline rather than on the heap.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline { .. })
+    }
+}
+
+impl Default for SmallString {
+    fn default() -> Self {
+        Self(Repr::Inline { len: 0, bytes: [0u8; INLINE_CAPACITY] })
+    }
+}
+
+impl Deref for SmallString {
+    type Target = str;
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for SmallString {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for SmallString {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for SmallString {
+    fn from(string: &str) -> Self {
+        Self::new(string)
+    }
+}
+
+impl From<&String> for SmallString {
+    fn from(string: &String) -> Self {
+        Self::new(string)
+    }
+}
+
+impl From<String> for SmallString {
+    fn from(string: String) -> Self {
+        match string.len() <= INLINE_CAPACITY {
+            true => Self::new(&string),
+            false => Self(Repr::Shared(Arc::from(string)))
+        }
+    }
+}
+
+impl From<SmallString> for String {
+    fn from(string: SmallString) -> Self {
+        string.as_str().to_string()
+    }
+}
+
+impl PartialEq for SmallString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SmallString {}
+
+impl PartialEq<str> for SmallString {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for SmallString {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for SmallString {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialOrd for SmallString {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SmallString {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for SmallString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Hashes like the `str` it borrows as.
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for SmallString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for SmallString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+/// Encoded like a `String`, so the stored records read the same.
+impl Serialize for SmallString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SmallString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(String::deserialize(deserializer)?.into())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{hash_map::DefaultHasher, HashMap};
+    use std::hash::{Hash, Hasher};
+
+    use super::{SmallString, INLINE_CAPACITY};
+
+    #[test]
+    pub fn test_small_string() {
+        let short = SmallString::from("app.mode");
+        assert!(short.is_inline());
+        assert_eq!(short, "app.mode");
+        assert!(SmallString::from("a".repeat(INLINE_CAPACITY)).is_inline());
+
+        let long = SmallString::from("a".repeat(INLINE_CAPACITY + 1));
+        assert!(!long.is_inline());
+        assert_eq!(long.len(), INLINE_CAPACITY + 1);
+        assert!(SmallString::default().is_empty());
+
+        // It orders, hashes and looks up like the string it holds.
+        let later = SmallString::from("b");
+        assert!(later > long);
+        let hash = |f: &dyn Fn(&mut DefaultHasher)| {
+            let mut hasher = DefaultHasher::new();
+            f(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&|h| short.hash(h)), hash(&|h| "app.mode".hash(h)));
+        let map = HashMap::from([(short.clone(), 1)]);
+        assert_eq!(map.get("app.mode"), Some(&1));
+        assert_eq!(format!("{short:?} {long}"), format!("\"app.mode\" {}", "a".repeat(INLINE_CAPACITY + 1)));
+    }
+}
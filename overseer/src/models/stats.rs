@@ -0,0 +1,80 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use super::{Key, KEY_SEPARATOR};
+
+
+/// How many records a database holds and how much room they take.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct DatabaseStats {
+    /// How many keys hold a value.
+    pub keys: u64,
+    /// About how many bytes the values take, see [super::Value::size].
+    pub value_bytes: u64,
+    /// How many keys there are under each first segment of a key, such
+    /// as `app` for `app.mode`. A key of a single segment counts under
+    /// itself.
+    pub prefixes: BTreeMap<String, u64>,
+    /// How many bytes the files of the database take on disk.
+    pub storage_bytes: u64,
+    /// How many changes were made that the files do not hold yet.
+    pub dirty: u64,
+    /// How many times the records were written out to the files.
+    pub flushes: u64,
+    /// How long writing them out took in total.
+    pub flush_time: Duration
+}
+
+impl DatabaseStats {
+    /// Counts a record of the key, whose value takes `bytes`.
+    pub fn record(&mut self, key: &Key, bytes: usize) {
+        self.keys += 1;
+        self.value_bytes += bytes as u64;
+        let prefix = key.as_str().split(KEY_SEPARATOR).find(|f| !f.is_empty()).unwrap_or_default();
+        *self.prefixes.entry(prefix.to_string()).or_default() += 1;
+    }
+    /// Adds the figures of another database, as for the shards of a namespace.
+    pub fn merge(&mut self, other: DatabaseStats) {
+        self.keys += other.keys;
+        self.value_bytes += other.value_bytes;
+        self.storage_bytes += other.storage_bytes;
+        self.dirty += other.dirty;
+        self.flushes += other.flushes;
+        self.flush_time += other.flush_time;
+        for (prefix, count) in other.prefixes {
+            *self.prefixes.entry(prefix).or_default() += count;
+        }
+    }
+    /// The mean time writing the records out takes.
+    pub fn mean_flush_latency(&self) -> Duration {
+        match self.flushes {
+            0 => Duration::ZERO,
+            flushes => self.flush_time / flushes as u32
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::models::Key;
+
+    use super::DatabaseStats;
+
+    #[test]
+    pub fn test_database_stats() {
+        let mut stats = DatabaseStats::default();
+        stats.record(&Key::from_str("app.mode"), 4);
+        stats.record(&Key::from_str("app.port"), 8);
+        stats.record(&Key::from_str("flag"), 1);
+        let mut other = DatabaseStats { storage_bytes: 100, flushes: 2, flush_time: Duration::from_millis(6), ..Default::default() };
+        other.record(&Key::from_str(".app.host"), 3);
+        stats.merge(other);
+
+        assert_eq!((stats.keys, stats.value_bytes, stats.storage_bytes), (4, 16, 100));
+        assert_eq!(stats.prefixes.get("app"), Some(&3));
+        assert_eq!(stats.prefixes.get("flag"), Some(&1));
+        assert_eq!(stats.mean_flush_latency(), Duration::from_millis(3));
+    }
+}
@@ -1,16 +1,70 @@
-use std::borrow::Cow;
+use std::collections::BTreeMap;
 
-use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncRead, AsyncWrite};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::{error::{NetworkError, ValueParseError}};
+use crate::{error::{NetworkError, ProtocolError, ValueParseError}};
 
-use super::{LocalReadAsync, LocalWriteAsync};
+use super::SmallString;
 
-#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub enum Value {
-    String(String),
-    Integer(i64)
+    /// Short strings are kept inline, see [SmallString].
+    String(SmallString),
+    Integer(i64),
+    /// Named fields, each holding a value of its own.
+    Map(BTreeMap<String, Value>),
+    /// Any serde type encoded as JSON, see [Value::from_serde].
+    Json(String)
+}
+
+/// The type of a [Value] without the contents.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Serialize, Deserialize)]
+pub enum ValueType {
+    String,
+    Integer,
+    Map,
+    Json
+}
+
+impl ValueType {
+    /// This matches the discriminator of the [Value] it describes.
+    pub fn discriminator(&self) -> u8 {
+        match self {
+            Self::String => 0,
+            Self::Integer => 1,
+            Self::Map => 2,
+            Self::Json => 3
+        }
+    }
+    /// The oldest protocol version that can decode the type.
+    pub fn min_version(&self) -> u8 {
+        match self {
+            Self::String | Self::Integer => 0,
+            Self::Map => 3,
+            Self::Json => 15
+        }
+    }
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Integer => "integer",
+            Self::Map => "map",
+            Self::Json => "json"
+        }
+    }
+}
+
+impl TryFrom<u8> for ValueType {
+    type Error = NetworkError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::String,
+            1 => Self::Integer,
+            2 => Self::Map,
+            3 => Self::Json,
+            x => Err(NetworkError::Protocol(ProtocolError::UnrecognizedValueTypeDiscriminator(x)))?
+        })
+    }
 }
 
 
@@ -26,7 +80,9 @@ impl Value {
     pub fn discriminator(&self) -> u8 {
         match self {
             Self::String(..) => 0,
-            Self::Integer(..) => 1
+            Self::Integer(..) => 1,
+            Self::Map(..) => 2,
+            Self::Json(..) => 3
         }
     }
     // pub fn decode(discrim: u8, bytes: &[u8]) -> Result<Self, NetworkError> {
@@ -34,13 +90,45 @@ impl Value {
     //     match discrim {
     //         0 => Ok(Self::String(std::str::from_utf8(bytes)?.to_string())),
     //         1 => Ok(Self::Integer(i64::from_le_bytes(bytes.try_into()?))),
-    //         x => Err(NetworkError::UnrecognizedValueTypeDiscriminator(x))
+    //         x => Err(NetworkError::Protocol(ProtocolError::UnrecognizedValueTypeDiscriminator(x)))
     //     }
     // }
     pub fn type_name(&self) -> &'static str {
+        self.value_type().type_name()
+    }
+    pub fn value_type(&self) -> ValueType {
         match self {
-            Self::String(..) => "string",
-            Self::Integer(..) => "integer"
+            Self::String(..) => ValueType::String,
+            Self::Integer(..) => ValueType::Integer,
+            Self::Map(..) => ValueType::Map,
+            Self::Json(..) => ValueType::Json
+        }
+    }
+    /// The first type in the value, looking into maps, that a peer on
+    /// the protocol version cannot decode.
+    pub fn unsupported_type(&self, version: u8) -> Option<ValueType> {
+        let value_type = self.value_type();
+        if value_type.min_version() > version {
+            return Some(value_type);
+        }
+        match self {
+            Self::Map(fields) => fields.values().find_map(|f| f.unsupported_type(version)),
+            _ => None
+        }
+    }
+    /// Encodes the serde value as JSON, so applications store their own
+    /// types without writing them into strings by hand.
+    pub fn from_serde<T: Serialize + ?Sized>(value: &T) -> Result<Self, ValueParseError> {
+        serde_json::to_string(value)
+            .map(Self::Json)
+            .map_err(|e| ValueParseError::Serde(e.to_string()))
+    }
+    /// Decodes a value made by [Value::from_serde].
+    pub fn to_serde<T: DeserializeOwned>(&self) -> Result<T, ValueParseError> {
+        if let Self::Json(json) = self {
+            serde_json::from_str(json).map_err(|e| ValueParseError::Serde(e.to_string()))
+        } else {
+            Err(ValueParseError::IncorrectType(format!("Tried to parse as json but was {}.", self.type_name())))
         }
     }
     pub fn as_string(&self) -> Result<&str, ValueParseError> {
@@ -57,6 +145,31 @@ impl Value {
             Err(ValueParseError::IncorrectType(format!("Tried to parse as integer but was {}.", self.type_name())))
         }
     }
+    pub fn as_map(&self) -> Result<&BTreeMap<String, Value>, ValueParseError> {
+        if let Self::Map(s) = self {
+            Ok(s)
+        } else {
+            Err(ValueParseError::IncorrectType(format!("Tried to parse as map but was {}.", self.type_name())))
+        }
+    }
+    /// About how many bytes the value takes, the contents of strings and
+    /// maps along with the names of the fields.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::String(s) => s.len(),
+            Self::Integer(..) => std::mem::size_of::<i64>(),
+            Self::Map(fields) => fields.iter().map(|(name, value)| name.len() + value.size()).sum(),
+            Self::Json(json) => json.len()
+        }
+    }
+    /// Looks up a field by its path, the names of nested maps are
+    /// separated by dots so `region.zone` is the `zone` of the `region`.
+    pub fn field(&self, path: &str) -> Option<&Value> {
+        path.split('.').try_fold(self, |value, name| match value {
+            Self::Map(fields) => fields.get(name),
+            _ => None
+        })
+    }
     // pub fn as_bytes(&self) -> Vec<u8> {
     //     match self {
     //         Self::Integer(i) => i.to_le_bytes().to_vec(),
@@ -73,21 +186,72 @@ impl Into<Value> for i64 {
 
 impl Into<Value> for &str {
     fn into(self) -> Value {
-        Value::String(self.to_string())
+        Value::String(self.into())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Value;
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{Value, ValueType};
 
 
     #[test]
     pub fn test_parse_value() {
-        let value = Value::String("hello".to_string());
+        let value = Value::String("hello".into());
         assert_eq!(value.as_string().unwrap(), "hello");
 
         let value = Value::Integer(32);
         assert_eq!(value.as_integer().unwrap(), 32);
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_value_field() {
+        let zone = Value::Map(BTreeMap::from([("zone".to_string(), Value::Integer(2))]));
+        let value = Value::Map(BTreeMap::from([
+            ("region".to_string(), zone),
+            ("name".to_string(), Value::String("edge".into()))
+        ]));
+        assert_eq!(value.field("name"), Some(&Value::String("edge".into())));
+        assert_eq!(value.field("region.zone"), Some(&Value::Integer(2)));
+        assert_eq!(value.field("name.zone"), None);
+        assert_eq!(value.field("missing"), None);
+        assert_eq!(Value::Integer(1).field("name"), None);
+        assert_eq!(value.size(), "region".len() + "zone".len() + 8 + "name".len() + "edge".len());
+    }
+
+    #[test]
+    pub fn test_value_type() {
+        assert_eq!(Value::String("hello".into()).value_type(), ValueType::String);
+        assert_eq!(Value::Integer(32).value_type(), ValueType::Integer);
+        assert_eq!(Value::Map(BTreeMap::new()).value_type(), ValueType::Map);
+        assert_eq!(Value::Json("{}".into()).value_type(), ValueType::Json);
+        for value_type in [ValueType::String, ValueType::Integer, ValueType::Map, ValueType::Json] {
+            assert_eq!(ValueType::try_from(value_type.discriminator()).unwrap(), value_type);
+        }
+    }
+
+    #[test]
+    pub fn test_serde_value() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Endpoint {
+            host: String,
+            port: u16,
+            tags: Vec<String>
+        }
+        let endpoint = Endpoint { host: "edge".into(), port: 80, tags: vec!["eu".into()] };
+        let value = Value::from_serde(&endpoint).unwrap();
+        assert_eq!(value, Value::Json(r#"{"host":"edge","port":80,"tags":["eu"]}"#.into()));
+        assert_eq!(value.to_serde::<Endpoint>().unwrap(), endpoint);
+        assert!(value.to_serde::<u16>().is_err());
+        assert!(Value::Integer(80).to_serde::<u16>().is_err());
+
+        let map = Value::Map(BTreeMap::from([("endpoint".to_string(), value)]));
+        assert_eq!(map.unsupported_type(14), Some(ValueType::Json));
+        assert_eq!(map.unsupported_type(15), None);
+        assert_eq!(Value::Map(BTreeMap::new()).unsupported_type(2), Some(ValueType::Map));
+    }
+}
@@ -1,14 +1,23 @@
-use std::{borrow::Cow, io::ErrorKind};
+use std::{borrow::Cow, collections::BTreeMap, io::ErrorKind, time::Duration};
 
 
 
 use crate::{
-    access::{WatcherActivity, WatcherBehaviour},
-    error::NetworkError,
-    models::{Key, LocalReadAsync, LocalWriteAsync, Value},
+    access::{IsolationReason, WatcherActivity, WatcherBehaviour},
+    error::{ErrorCode, NetworkError, ProtocolError},
+    models::{compress_block, decompress_block, Acknowledgement, CompactionRun, DatabaseStats, DeleteCondition, DiffLine, Durability, ExportedRecord, HistoryEntry, Key, KeyChild, KeyDiff, KeyDrift, KeyFilter, KeyMeta, LeasedItem, LocalReadAsync, LocalWriteAsync, Promotion, PromotionReport, RecordMeta, Revision, Schema, SmallString, UncompressedWriter, Value, ValueType, WatchedKey, COMPRESSION_THRESHOLD},
 };
 
-use super::{OvrInteger, Packet, PacketId, PacketPayload, CURRENT_VERSION};
+use super::{read_frame_length, write_frame_length, FrameReader, OvrInteger, Packet, UnframedReader, PacketId, PacketPayload, CURRENT_VERSION, DEFAULT_MAX_PACKET_SIZE, FIRST_EXTENSION_DISCRIMINATOR, FRAMED_VERSION, MIN_VERSION};
+
+
+/// Set on the type of a value whose contents are compressed, see [compress_block].
+const COMPRESSED_FLAG: u8 = 0x80;
+/// A string, the only type that is compressed, with the compression bit set.
+const COMPRESSED_STRING: u8 = COMPRESSED_FLAG;
+/// How many maps a value may be nested in, a deeper one fails with
+/// [ProtocolError::ValueTooDeep] before it can overflow the stack.
+pub const MAX_VALUE_DEPTH: usize = 64;
 
 
 
@@ -18,46 +27,192 @@ use super::{OvrInteger, Packet, PacketId, PacketPayload, CURRENT_VERSION};
 impl OverseerSerde<Packet<'static>> for Packet<'_> {
     type E = NetworkError;
     async fn serialize<W: LocalWriteAsync>(&self, socket: &mut W) -> Result<(), Self::E> {
-        socket.write_u8(CURRENT_VERSION).await?;
+        socket.write_u8(self.version()).await?;
         socket.write_u32(self.id().id()).await?;
         socket.write_u32(self.id().order()).await?;
-        socket.write_u8(self.payload().discriminator()).await?;
-        self.payload().serialize(socket).await?;
-        Ok(())
+        if self.version() >= FRAMED_VERSION {
+            let mut body = vec![];
+            write_body(self, &mut body).await?;
+            write_frame_length(self.version(), body.len(), socket).await?;
+            socket.write_all(body).await?;
+            return Ok(());
+        }
+        write_body(self, socket).await
     }
     async fn deserialize<R: LocalReadAsync>(socket: &mut R) -> Result<Packet<'static>, Self::E> {
-        let version = socket.read_u8().await?;
+        let (version, id) = read_header(socket).await?;
+        if version < FRAMED_VERSION {
+            return read_body(version, id, &mut UnframedReader::new(socket, DEFAULT_MAX_PACKET_SIZE)).await;
+        }
+        // The body is read in one go into a buffer of the reader.
+        let length = read_frame_length(version, socket, DEFAULT_MAX_PACKET_SIZE).await?;
+        let frame = socket.buffer(length);
+        let (frame, _) = socket.read_exact(frame).await?;
+        let packet = read_body(version, id, &mut FrameReader::new(&frame)).await;
+        socket.recycle(frame);
+        packet
+    }
+}
+
+impl<'buf> Packet<'buf> {
+    /// Reads a packet into the frame, which it then borrows its namespace,
+    /// strings and extension payload from instead of copying them. Reuse
+    /// the frame for the next packet so reads stop allocating.
+    ///
+    /// A body longer than the max size fails with [ProtocolError::PacketTooLarge]
+    /// before it is read, [Packet::deserialize] bounds it at [DEFAULT_MAX_PACKET_SIZE].
+    /// Packets of versions before [FRAMED_VERSION] carry no length, they
+    /// are read like [Packet::deserialize] does.
+    pub async fn deserialize_framed<R: LocalReadAsync>(socket: &mut R, frame: &'buf mut Vec<u8>, max_size: usize) -> Result<Packet<'buf>, NetworkError> {
+        let (version, id) = read_header(socket).await?;
+        if version < FRAMED_VERSION {
+            return read_body(version, id, &mut UnframedReader::new(socket, max_size)).await;
+        }
+        let length = read_frame_length(version, socket, max_size).await?;
+        frame.clear();
+        frame.resize(length, 0);
+        let (read, _) = socket.read_exact(std::mem::take(frame)).await?;
+        *frame = read;
+        let frame: &'buf Vec<u8> = frame;
+        let mut reader = FrameReader::bounded(frame, max_size);
+        let namespace = reader.read_str().await?;
+        let trace = read_trace(version, &mut reader).await?;
+        let mut payload = read_borrowed_payload(&mut reader).await?;
+        read_trailer(version, &mut payload, &mut reader).await?;
+        Ok(Packet::new(id, payload).with_version(version).with_namespace(namespace).with_trace(trace))
+    }
+}
+
+/// The version and id of a packet, failing on versions no schema is known for.
+async fn read_header<R: LocalReadAsync>(socket: &mut R) -> Result<(u8, PacketId), NetworkError> {
+    let version = socket.read_u8().await?;
+
+    let id_first = socket.read_u32().await?;
+    let id_second = socket.read_u32().await?;
+
+    // Every supported version shares the same payload schema.
+    if !(MIN_VERSION..=CURRENT_VERSION).contains(&version) {
+        return Err(NetworkError::Protocol(ProtocolError::UnknownPacketSchema(version)));
+    }
+    Ok((version, PacketId::new(id_first, id_second)))
+}
 
-        let id_first = socket.read_u32().await?;
-        let id_second = socket.read_u32().await?;
+/// Writes what follows the header of the packet.
+async fn write_body<W: LocalWriteAsync>(packet: &Packet<'_>, socket: &mut W) -> Result<(), NetworkError> {
+    if packet.version() >= 2 {
+        packet.namespace().serialize(socket).await?;
+    }
+    if packet.version() >= 29 {
+        OvrInteger::write(packet.trace().unwrap_or(0), socket).await?;
+    }
+    socket.write_u8(packet.payload().discriminator()).await?;
+    if packet.version() >= 4 {
+        packet.payload().serialize(socket).await?;
+    } else {
+        packet.payload().serialize(&mut UncompressedWriter(socket)).await?;
+    }
+    // The versions and metadata of records, the interval of watches and the
+    // heartbeat flag trail the payload, so older peers keep their schema.
+    match packet.payload() {
+        PacketPayload::Return { version, meta, .. } if packet.version() >= 6 => {
+            OvrInteger::write(*version, socket).await?;
+            if packet.version() >= 10 {
+                meta.as_ref().serialize(socket).await?;
+            }
+        }
+        PacketPayload::KeyMeta { value, meta, .. } if packet.version() >= 10 => {
+            value.as_deref().serialize(socket).await?;
+            meta.record.as_ref().serialize(socket).await?;
+        }
+        PacketPayload::Watch { min_interval, .. }
+        | PacketPayload::WatchSnapshot { min_interval, .. } if packet.version() >= 31 => {
+            // Zero stands for no interval.
+            OvrInteger::write(min_interval.map(|f| f.as_millis() as u64).unwrap_or(0), socket).await?;
+        }
+        PacketPayload::Notify { heartbeat, .. } if packet.version() >= 32 => heartbeat.serialize(socket).await?,
+        _ => {}
+    }
+    Ok(())
+}
 
+/// Reads what follows the header of a packet.
+async fn read_body<R: LocalReadAsync>(version: u8, id: PacketId, socket: &mut R) -> Result<Packet<'static>, NetworkError> {
+    let namespace = if version >= 2 {
+        Some(<&str>::deserialize(socket).await?)
+    } else {
+        None
+    };
+    let trace = read_trace(version, socket).await?;
+    let mut payload = PacketPayload::deserialize(socket).await?;
+    read_trailer(version, &mut payload, socket).await?;
+    let packet = Packet::new(id, payload).with_version(version).with_trace(trace);
+    Ok(match namespace {
+        Some(namespace) => packet.with_namespace(namespace),
+        None => packet
+    })
+}
 
+/// Reads the trace id that follows the namespace, zero standing for none.
+async fn read_trace<R: LocalReadAsync>(version: u8, socket: &mut R) -> Result<Option<u64>, NetworkError> {
+    if version < 29 {
+        return Ok(None);
+    }
+    let trace: u64 = OvrInteger::read(socket).await?;
+    Ok(Some(trace).filter(|f| *f != 0))
+}
 
-        Ok(Packet::new(
-            PacketId::new(id_first, id_second),
-            match version {
-                0 => PacketPayload::deserialize(socket).await?,
-                x => Err(NetworkError::UnknownPacketSchema(x))?,
+/// Reads the versions and metadata of records, the minimum interval of
+/// watches and the heartbeat flag that trail the payload.
+async fn read_trailer<R: LocalReadAsync>(version: u8, payload: &mut PacketPayload<'_>, socket: &mut R) -> Result<(), NetworkError> {
+    match payload {
+        PacketPayload::Return { version: record, meta, .. } if version >= 6 => {
+            *record = OvrInteger::read(socket).await?;
+            if version >= 10 {
+                *meta = Option::<&RecordMeta>::deserialize(socket).await?;
             }
-        ))
+        }
+        PacketPayload::KeyMeta { value, meta, .. } if version >= 10 => {
+            *value = Option::<&Value>::deserialize(socket).await?.map(Cow::Owned);
+            meta.record = Option::<&RecordMeta>::deserialize(socket).await?;
+        }
+        PacketPayload::Watch { min_interval, .. }
+        | PacketPayload::WatchSnapshot { min_interval, .. } if version >= 31 => {
+            let millis: u64 = OvrInteger::read(socket).await?;
+            *min_interval = Some(Duration::from_millis(millis)).filter(|f| !f.is_zero());
+        }
+        PacketPayload::Notify { heartbeat, .. } if version >= 32 => *heartbeat = bool::deserialize(socket).await?,
+        _ => {}
     }
+    Ok(())
 }
 
+/// Reads a payload out of a frame, borrowing the strings and bytes of the
+/// packets that carry them. Keys and values are read as usual, they keep
+/// short contents inline anyway, see [SmallString].
+async fn read_borrowed_payload<'buf>(reader: &mut FrameReader<'buf>) -> Result<PacketPayload<'buf>, NetworkError> {
+    let discriminator = reader.read_borrowed(1)?[0];
+    Ok(match discriminator {
+        7 => PacketPayload::Auth { token: reader.read_str().await? },
+        18 => PacketPayload::Invalidate { prefix: reader.read_str().await? },
+        19 => PacketPayload::CreateNamespace { name: reader.read_str().await? },
+        20 => PacketPayload::DropNamespace { name: reader.read_str().await? },
+        34 => {
+            let field = reader.read_str().await?;
+            PacketPayload::QueryByIndex { field, value: Cow::Owned(Value::deserialize(reader).await?) }
+        }
+        opcode if opcode >= FIRST_EXTENSION_DISCRIMINATOR => {
+            let extension = OvrInteger::read(reader).await?;
+            PacketPayload::Extension { opcode, extension, payload: reader.read_bytes().await? }
+        }
+        discriminator => read_payload(discriminator, reader).await?
+    })
+}
 
 impl OverseerSerde<PacketPayload<'static>> for PacketPayload<'_> {
     type E = NetworkError;
     async fn deserialize<R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'static>, Self::E> {
         let discrim = socket.read_u8().await?;
-        match discrim {
-            0 => read_set_packet(socket).await,
-            1 => read_get_packet(socket).await,
-            2 => read_watch_packet(socket).await,
-            3 => read_release_packet(socket).await,
-            4 => read_delete_packet(socket).await,
-            5 => read_notify_packet(socket).await,
-            6 => read_getreturn_packet(socket).await,
-            x => Err(NetworkError::UnrecognizedPacketTypeDiscriminator(x)),
-        }
+        read_payload(discrim, socket).await
     }
     async fn serialize<W: LocalWriteAsync>(&self, socket: &mut W) -> Result<(), Self::E> {
         match self {
@@ -68,11 +223,362 @@ impl OverseerSerde<PacketPayload<'static>> for PacketPayload<'_> {
                 key,
                 activity,
                 behaviour,
+                ..
             } => write_watch_packet(key, activity, behaviour, socket).await,
             PacketPayload::Delete { key } => write_delete_packet(key, socket).await,
-            PacketPayload::Notify { key, value, more } => write_notify_packet(key, value.as_deref(), *more, socket).await,
-            PacketPayload::Return { key, value } => write_getreturn_packet(key, value.as_deref(), socket).await,
+            PacketPayload::Notify { key, value, more, .. } => write_notify_packet(key, value.as_deref(), *more, socket).await,
+            PacketPayload::Return { key, value, .. } => write_getreturn_packet(key, value.as_deref(), socket).await,
+            PacketPayload::Auth { token } => write_auth_packet(token, socket).await,
+            PacketPayload::AuthResult { accepted } => Ok(accepted.serialize(socket).await?),
+            PacketPayload::CheckManifest => Ok(()),
+            PacketPayload::ManifestReport { drift } => write_manifest_report_packet(drift, socket).await,
+            PacketPayload::ServerClosing => Ok(()),
+            PacketPayload::ServerBusy => Ok(()),
+            PacketPayload::Throttled { retry_after } => Ok(OvrInteger::write(retry_after.as_millis() as u64, socket).await?),
+            PacketPayload::WatchAcknowledged { key, after } => write_key_sequence_packet(key, *after, socket).await,
+            PacketPayload::Delivery { key, value, sequence, gap } => {
+                write_snapshot_packet(key, value.as_deref(), *sequence, socket).await?;
+                Ok(gap.serialize(socket).await?)
+            }
+            PacketPayload::AckDelivery { key, sequence } => write_key_sequence_packet(key, *sequence, socket).await,
+            PacketPayload::Error { code, message } => {
+                OvrInteger::write(code.0, socket).await?;
+                Ok(message.as_ref().serialize(socket).await?)
+            }
+            PacketPayload::WatchSnapshot { key, behaviour, .. } => write_watch_snapshot_packet(key, behaviour, socket).await,
+            PacketPayload::Snapshot { key, value, version } => write_snapshot_packet(key, value.as_deref(), *version, socket).await,
+            PacketPayload::CompactionHistory => Ok(()),
+            PacketPayload::CompactionReport { runs } => write_compaction_report_packet(runs, socket).await,
+            PacketPayload::Ping | PacketPayload::Pong => Ok(()),
+            PacketPayload::Invalidate { prefix } => Ok(prefix.as_ref().serialize(socket).await?),
+            PacketPayload::CreateNamespace { name } | PacketPayload::DropNamespace { name } => Ok(name.as_ref().serialize(socket).await?),
+            PacketPayload::NamespaceResult { changed } => Ok(changed.serialize(socket).await?),
+            PacketPayload::UnknownNamespace => Ok(()),
+            PacketPayload::ListKeys { cursor, limit } => write_list_keys_packet(cursor.as_deref(), *limit, socket).await,
+            PacketPayload::KeyPage { keys, cursor } => write_key_page_packet(keys, cursor.as_ref(), socket).await,
+            PacketPayload::WatchOverflow { key, missed, closed } => write_watch_overflow_packet(key, *missed, *closed, socket).await,
+            PacketPayload::KeyHistory { key, from, to } => write_key_history_packet(key, from, to, socket).await,
+            PacketPayload::KeyDiff { diff } => Ok(diff.as_ref().serialize(socket).await?),
+            PacketPayload::Promote { promotion, dry_run } => write_promote_packet(promotion, *dry_run, socket).await,
+            PacketPayload::RollbackPromotion { id } => Ok(OvrInteger::write(*id, socket).await?),
+            PacketPayload::PromotionResult { report } => Ok(report.as_ref().serialize(socket).await?),
+            PacketPayload::GetMeta { key } => Ok(key.serialize(socket).await?),
+            PacketPayload::KeyMeta { key, meta, .. } => write_key_meta_packet(key, meta, socket).await,
+            PacketPayload::WatchIsolated { key, reason } => write_watch_isolated_packet(key, reason, socket).await,
+            PacketPayload::QueryByIndex { field, value } => write_query_by_index_packet(field, value, socket).await,
+            PacketPayload::IndexMatches { keys } => write_index_matches_packet(keys.as_deref(), socket).await,
+            PacketPayload::GetOrInsert { key, default } => write_insert_packet(key, default, socket).await,
+            PacketPayload::Publish { topic, value } | PacketPayload::TopicMessage { topic, value } => write_insert_packet(topic, value, socket).await,
+            PacketPayload::Published { receivers } => Ok(OvrInteger::write(*receivers, socket).await?),
+            PacketPayload::SubscribeTopic { topic } | PacketPayload::UnsubscribeTopic { topic } => Ok(topic.serialize(socket).await?),
+            PacketPayload::InsertIfVersion { key, value, version } => write_insert_if_version_packet(key, value, *version, socket).await,
+            PacketPayload::VersionConflict { key, version } => write_version_conflict_packet(key, *version, socket).await,
+            PacketPayload::Enqueue { queue, value } => write_insert_packet(queue, value, socket).await,
+            PacketPayload::Dequeue { queue, visibility } => write_dequeue_packet(queue, *visibility, socket).await,
+            PacketPayload::Leased { item } => Ok(item.as_ref().serialize(socket).await?),
+            // A nack is shaped like an ack.
+            PacketPayload::Ack { key, lease } | PacketPayload::Nack { key, lease } => write_version_conflict_packet(key, *lease, socket).await,
+            PacketPayload::LeaseResult { accepted } => Ok(accepted.serialize(socket).await?),
+            PacketPayload::History { key, limit } => write_version_conflict_packet(key, *limit as u64, socket).await,
+            PacketPayload::HistoryReport { entries } => write_history_report_packet(entries, socket).await,
+            PacketPayload::InsertAcknowledged { key, value, acknowledgement } => {
+                write_insert_packet(key, value, socket).await?;
+                Ok(acknowledgement.serialize(socket).await?)
+            }
+            PacketPayload::AcknowledgementTimeout { key, version } => write_version_conflict_packet(key, *version, socket).await,
+            PacketPayload::Archive { before, delete } => {
+                OvrInteger::write(*before, socket).await?;
+                Ok(delete.serialize(socket).await?)
+            }
+            PacketPayload::Archived { records }
+            | PacketPayload::Import { records }
+            | PacketPayload::MultiInsert { entries: records } => write_records(records, socket).await,
+            PacketPayload::Imported { count } => Ok(OvrInteger::write(*count, socket).await?),
+            PacketPayload::Export { cursor, limit } => write_list_keys_packet(cursor.as_deref(), *limit, socket).await,
+            PacketPayload::ExportPage { records, cursor } => write_export_page_packet(records, cursor.as_ref(), socket).await,
+            PacketPayload::ListChildren { key, cursor, limit } => {
+                key.serialize(socket).await?;
+                write_list_keys_packet(cursor.as_deref(), *limit, socket).await
+            }
+            PacketPayload::Children { children, cursor } => write_children_packet(children, cursor.as_ref(), socket).await,
+            PacketPayload::ListWatchers { key } => Ok(key.as_deref().serialize(socket).await?),
+            PacketPayload::Rename { from, to, overwrite } => {
+                from.serialize(socket).await?;
+                to.serialize(socket).await?;
+                Ok(overwrite.serialize(socket).await?)
+            }
+            PacketPayload::SnapshotGet { keys } => keys.serialize(socket).await,
+            PacketPayload::RegisterSchema { prefix, schema } => {
+                prefix.as_ref().serialize(socket).await?;
+                schema.as_ref().serialize(socket).await
+            }
+            PacketPayload::SchemaRegistered { previous } => previous.as_ref().serialize(socket).await,
+            PacketPayload::ListSchemas => Ok(()),
+            PacketPayload::Schemas { schemas } => {
+                OvrInteger::write(schemas.len(), socket).await?;
+                for (prefix, schema) in schemas {
+                    prefix.serialize(socket).await?;
+                    schema.serialize(socket).await?;
+                }
+                Ok(())
+            }
+            PacketPayload::DeleteIf { key, condition } => {
+                key.serialize(socket).await?;
+                condition.serialize(socket).await
+            }
+            PacketPayload::DeleteIfResult { deleted } => Ok(deleted.serialize(socket).await?),
+            PacketPayload::FindKeys { filter, cursor, limit } => {
+                filter.serialize(socket).await?;
+                write_list_keys_packet(cursor.as_deref(), *limit, socket).await
+            }
+            PacketPayload::GetStats => Ok(()),
+            PacketPayload::Stats { stats } => stats.serialize(socket).await,
+            PacketPayload::NextSequence { name } => Ok(name.as_ref().serialize(socket).await?),
+            PacketPayload::Sequence { value } => Ok(OvrInteger::write(*value, socket).await?),
+            PacketPayload::GetAt { key, at } => {
+                key.serialize(socket).await?;
+                Ok(at.serialize(socket).await?)
+            }
+            PacketPayload::ValueAt { value, available } => {
+                available.serialize(socket).await?;
+                value.as_ref().serialize(socket).await
+            }
+            PacketPayload::MultiInserted { versions } => {
+                OvrInteger::write(versions.len(), socket).await?;
+                for version in versions {
+                    OvrInteger::write(*version, socket).await?;
+                }
+                Ok(())
+            }
+            PacketPayload::SnapshotValues { values } => {
+                OvrInteger::write(values.len(), socket).await?;
+                for (value, version) in values {
+                    value.as_ref().serialize(socket).await?;
+                    OvrInteger::write(*version, socket).await?;
+                }
+                Ok(())
+            }
+            PacketPayload::WatcherReport { watched } => {
+                OvrInteger::write(watched.len(), socket).await?;
+                for entry in watched {
+                    entry.key.serialize(socket).await?;
+                    OvrInteger::write(entry.clients, socket).await?;
+                }
+                Ok(())
+            }
+            // An acquire is shaped like a dequeue.
+            PacketPayload::Acquire { key, ttl } => write_dequeue_packet(key, *ttl, socket).await,
+            PacketPayload::ReleaseLock { key } => Ok(key.serialize(socket).await?),
+            // Tokens start at one, so zero stands for a lock held by another.
+            PacketPayload::LockResult { token } => Ok(OvrInteger::write(token.unwrap_or(0), socket).await?),
+            PacketPayload::InsertEphemeral { key, value } => write_insert_packet(key, value, socket).await,
+            // Session ids are never zero, which stands for asking for a new one.
+            PacketPayload::Resume { session } => Ok(OvrInteger::write(session.unwrap_or(0), socket).await?),
+            PacketPayload::Session { id, resumed } => {
+                OvrInteger::write(*id, socket).await?;
+                Ok(resumed.serialize(socket).await?)
+            }
+            PacketPayload::Extension { extension, payload, .. } => {
+                OvrInteger::write(*extension, socket).await?;
+                OvrInteger::write(payload.len(), socket).await?;
+                Ok(socket.write_all(payload.to_vec()).await?)
+            }
+            PacketPayload::UnsupportedExtension { extension } => Ok(OvrInteger::write(*extension, socket).await?),
+            PacketPayload::Increment { key, delta } => {
+                key.serialize(socket).await?;
+                Ok(OvrInteger::write(*delta, socket).await?)
+            }
+        }
+    }
+}
+
+/// Reads a payload after its discriminator.
+async fn read_payload<R: LocalReadAsync>(discrim: u8, socket: &mut R) -> Result<PacketPayload<'static>, NetworkError> {
+    match discrim {
+        0 => read_set_packet(socket).await,
+        1 => read_get_packet(socket).await,
+        2 => read_watch_packet(socket).await,
+        3 => read_release_packet(socket).await,
+        4 => read_delete_packet(socket).await,
+        5 => read_notify_packet(socket).await,
+        6 => read_getreturn_packet(socket).await,
+        7 => read_auth_packet(socket).await,
+        8 => read_auth_result_packet(socket).await,
+        9 => Ok(PacketPayload::CheckManifest),
+        10 => read_manifest_report_packet(socket).await,
+        11 => Ok(PacketPayload::ServerClosing),
+        12 => read_watch_snapshot_packet(socket).await,
+        13 => read_snapshot_packet(socket).await,
+        14 => Ok(PacketPayload::CompactionHistory),
+        15 => read_compaction_report_packet(socket).await,
+        16 => Ok(PacketPayload::Ping),
+        17 => Ok(PacketPayload::Pong),
+        18 => read_invalidate_packet(socket).await,
+        19 => read_create_namespace_packet(socket).await,
+        20 => read_drop_namespace_packet(socket).await,
+        21 => read_namespace_result_packet(socket).await,
+        22 => Ok(PacketPayload::UnknownNamespace),
+        23 => read_list_keys_packet(socket).await,
+        24 => read_key_page_packet(socket).await,
+        25 => read_watch_overflow_packet(socket).await,
+        26 => read_key_history_packet(socket).await,
+        27 => read_key_diff_packet(socket).await,
+        28 => read_promote_packet(socket).await,
+        29 => Ok(PacketPayload::RollbackPromotion { id: OvrInteger::read(socket).await? }),
+        30 => read_promotion_result_packet(socket).await,
+        31 => Ok(PacketPayload::GetMeta { key: Cow::Owned(Key::deserialize(socket).await?) }),
+        32 => read_key_meta_packet(socket).await,
+        33 => read_watch_isolated_packet(socket).await,
+        34 => read_query_by_index_packet(socket).await,
+        35 => read_index_matches_packet(socket).await,
+        36 => read_get_or_insert_packet(socket).await,
+        37 => read_publish_packet(socket).await,
+        38 => Ok(PacketPayload::Published { receivers: OvrInteger::read(socket).await? }),
+        39 => Ok(PacketPayload::SubscribeTopic { topic: Cow::Owned(Key::deserialize(socket).await?) }),
+        40 => Ok(PacketPayload::UnsubscribeTopic { topic: Cow::Owned(Key::deserialize(socket).await?) }),
+        41 => read_topic_message_packet(socket).await,
+        42 => read_insert_if_version_packet(socket).await,
+        43 => read_version_conflict_packet(socket).await,
+        44 => read_enqueue_packet(socket).await,
+        45 => read_dequeue_packet(socket).await,
+        46 => Ok(PacketPayload::Leased { item: Option::<&LeasedItem>::deserialize(socket).await? }),
+        47 => read_ack_packet(socket, false).await,
+        48 => read_ack_packet(socket, true).await,
+        49 => Ok(PacketPayload::LeaseResult { accepted: bool::deserialize(socket).await? }),
+        50 => read_history_packet(socket).await,
+        51 => read_history_report_packet(socket).await,
+        52 => read_insert_acknowledged_packet(socket).await,
+        53 => {
+            let key = Key::deserialize(socket).await?;
+            let version = OvrInteger::read(socket).await?;
+            Ok(PacketPayload::AcknowledgementTimeout { key: Cow::Owned(key), version })
+        }
+        54 => {
+            let before = OvrInteger::read(socket).await?;
+            let delete = bool::deserialize(socket).await?;
+            Ok(PacketPayload::Archive { before, delete })
+        }
+        55 => Ok(PacketPayload::Archived { records: read_records(socket).await? }),
+        56 => {
+            let key = Key::deserialize(socket).await?;
+            let ttl = Duration::from_millis(OvrInteger::read(socket).await?);
+            Ok(PacketPayload::Acquire { key: Cow::Owned(key), ttl })
         }
+        57 => Ok(PacketPayload::ReleaseLock { key: Cow::Owned(Key::deserialize(socket).await?) }),
+        58 => Ok(PacketPayload::LockResult { token: Some(OvrInteger::read(socket).await?).filter(|f| *f != 0) }),
+        59 => {
+            let key = Key::deserialize(socket).await?;
+            let value = Value::deserialize(socket).await?;
+            Ok(PacketPayload::InsertEphemeral { key: Cow::Owned(key), value: Cow::Owned(value) })
+        }
+        60 => Ok(PacketPayload::Resume { session: Some(OvrInteger::read(socket).await?).filter(|f| *f != 0) }),
+        61 => {
+            let id = OvrInteger::read(socket).await?;
+            let resumed = bool::deserialize(socket).await?;
+            Ok(PacketPayload::Session { id, resumed })
+        }
+        62 => Ok(PacketPayload::UnsupportedExtension { extension: OvrInteger::read(socket).await? }),
+        64 => Ok(PacketPayload::ServerBusy),
+        65 => Ok(PacketPayload::Throttled { retry_after: Duration::from_millis(OvrInteger::read(socket).await?) }),
+        66 => {
+            let (key, after) = read_key_sequence_packet(socket).await?;
+            Ok(PacketPayload::WatchAcknowledged { key: Cow::Owned(key), after })
+        }
+        67 => {
+            let PacketPayload::Snapshot { key, value, version } = read_snapshot_packet(socket).await? else {
+                unreachable!("A snapshot is read as a snapshot.");
+            };
+            Ok(PacketPayload::Delivery { key, value, sequence: version, gap: bool::deserialize(socket).await? })
+        }
+        68 => {
+            let (key, sequence) = read_key_sequence_packet(socket).await?;
+            Ok(PacketPayload::AckDelivery { key: Cow::Owned(key), sequence })
+        }
+        69 => {
+            let code = ErrorCode(OvrInteger::read(socket).await?);
+            Ok(PacketPayload::Error { code, message: Cow::Owned(String::deserialize(socket).await?) })
+        }
+        70 => Ok(PacketPayload::Import { records: read_records(socket).await? }),
+        71 => Ok(PacketPayload::Imported { count: OvrInteger::read(socket).await? }),
+        72 => read_export_packet(socket).await,
+        73 => read_export_page_packet(socket).await,
+        74 => read_list_children_packet(socket).await,
+        75 => read_children_packet(socket).await,
+        76 => Ok(PacketPayload::ListWatchers { key: Option::<&Key>::deserialize(socket).await?.map(Cow::Owned) }),
+        77 => read_watcher_report_packet(socket).await,
+        79 => Ok(PacketPayload::SnapshotGet { keys: Vec::<Key>::deserialize(socket).await? }),
+        80 => {
+            let count: usize = OvrInteger::read(socket).await?;
+            let mut values = Vec::new();
+            for _ in 0..count {
+                let value = Option::<&Value>::deserialize(socket).await?;
+                values.push((value, OvrInteger::read(socket).await?));
+            }
+            Ok(PacketPayload::SnapshotValues { values })
+        }
+        81 => {
+            let prefix = <&str>::deserialize(socket).await?;
+            let schema = Option::<&Schema>::deserialize(socket).await?;
+            Ok(PacketPayload::RegisterSchema { prefix: Cow::Owned(prefix), schema })
+        }
+        82 => Ok(PacketPayload::SchemaRegistered { previous: Option::<&Schema>::deserialize(socket).await? }),
+        83 => Ok(PacketPayload::ListSchemas),
+        84 => {
+            let count: usize = OvrInteger::read(socket).await?;
+            let mut schemas = Vec::new();
+            for _ in 0..count {
+                let prefix = String::deserialize(socket).await?;
+                schemas.push((prefix, Schema::deserialize(socket).await?));
+            }
+            Ok(PacketPayload::Schemas { schemas })
+        }
+        85 => Ok(PacketPayload::MultiInsert { entries: read_records(socket).await? }),
+        86 => {
+            let count: usize = OvrInteger::read(socket).await?;
+            let mut versions = Vec::new();
+            for _ in 0..count {
+                versions.push(OvrInteger::read(socket).await?);
+            }
+            Ok(PacketPayload::MultiInserted { versions })
+        }
+        87 => {
+            let key = Key::deserialize(socket).await?;
+            Ok(PacketPayload::DeleteIf { key: Cow::Owned(key), condition: DeleteCondition::deserialize(socket).await? })
+        }
+        88 => Ok(PacketPayload::DeleteIfResult { deleted: bool::deserialize(socket).await? }),
+        89 => {
+            let filter = KeyFilter::deserialize(socket).await?;
+            let cursor = Option::<&Key>::deserialize(socket).await?;
+            let limit = OvrInteger::read(socket).await?;
+            Ok(PacketPayload::FindKeys { filter, cursor: cursor.map(Cow::Owned), limit })
+        }
+        90 => Ok(PacketPayload::GetStats),
+        91 => Ok(PacketPayload::Stats { stats: DatabaseStats::deserialize(socket).await? }),
+        92 => Ok(PacketPayload::NextSequence { name: Cow::Owned(<&str>::deserialize(socket).await?) }),
+        93 => Ok(PacketPayload::Sequence { value: OvrInteger::read(socket).await? }),
+        94 => Ok(PacketPayload::GetAt { key: Cow::Owned(Key::deserialize(socket).await?), at: Revision::deserialize(socket).await? }),
+        95 => {
+            let available = bool::deserialize(socket).await?;
+            Ok(PacketPayload::ValueAt { value: Option::<&Value>::deserialize(socket).await?, available })
+        }
+        78 => {
+            let from = Key::deserialize(socket).await?;
+            let to = Key::deserialize(socket).await?;
+            let overwrite = bool::deserialize(socket).await?;
+            Ok(PacketPayload::Rename { from: Cow::Owned(from), to: Cow::Owned(to), overwrite })
+        }
+        63 => {
+            let key = Key::deserialize(socket).await?;
+            let delta = OvrInteger::read(socket).await?;
+            Ok(PacketPayload::Increment { key: Cow::Owned(key), delta })
+        }
+        opcode if opcode >= FIRST_EXTENSION_DISCRIMINATOR => {
+            let extension = OvrInteger::read(socket).await?;
+            let length = OvrInteger::read_length(socket).await?;
+            let (payload, _) = socket.read_exact(vec![0u8; length]).await?;
+            Ok(PacketPayload::Extension { opcode, extension, payload: Cow::Owned(payload) })
+        }
+        x => Err(NetworkError::Protocol(ProtocolError::UnrecognizedPacketTypeDiscriminator(x))),
     }
 }
 
@@ -134,7 +640,7 @@ where
 //     Ok(match reader.read_u8().await? {
 //         0 => None,
 //         1 => Some(Value::deserialize(reader).await?),
-//         _ => Err(NetworkError::ErrorDecodingOption)?,
+//         _ => Err(NetworkError::Protocol(ProtocolError::ErrorDecodingOption))?,
 //     })
 // }
 
@@ -151,6 +657,35 @@ async fn write_watch_packet<W: LocalWriteAsync>(
     Ok(())
 }
 
+async fn write_watch_snapshot_packet<W: LocalWriteAsync>(
+    key: &Key,
+    behaviour: &WatcherBehaviour,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    key.serialize(socket).await?;
+    socket.write_u8(behaviour.discriminator()).await?;
+    Ok(())
+}
+
+async fn write_snapshot_packet<'a, W: LocalWriteAsync>(
+    key: &'a Key,
+    value: Option<&'a Value>,
+    version: u64,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    key.serialize(socket).await?;
+    value.serialize(socket).await?;
+    OvrInteger::write(version, socket).await?;
+    Ok(())
+}
+
+/// Writes a key followed by a sequence, for the acknowledged delivery packets.
+async fn write_key_sequence_packet<W: LocalWriteAsync>(key: &Key, sequence: u64, socket: &mut W) -> Result<(), NetworkError> {
+    key.serialize(socket).await?;
+    OvrInteger::write(sequence, socket).await?;
+    Ok(())
+}
+
 async fn write_insert_packet<'a, W: LocalWriteAsync>(
     key: &'a Cow<'a, Key>,
     value: &'a Cow<'a, Value>,
@@ -161,6 +696,38 @@ async fn write_insert_packet<'a, W: LocalWriteAsync>(
     Ok(())
 }
 
+async fn write_insert_if_version_packet<W: LocalWriteAsync>(
+    key: &Key,
+    value: &Value,
+    version: u64,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    key.serialize(socket).await?;
+    value.serialize(socket).await?;
+    OvrInteger::write(version, socket).await?;
+    Ok(())
+}
+
+async fn write_version_conflict_packet<W: LocalWriteAsync>(
+    key: &Key,
+    version: u64,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    key.serialize(socket).await?;
+    OvrInteger::write(version, socket).await?;
+    Ok(())
+}
+
+async fn write_dequeue_packet<W: LocalWriteAsync>(
+    queue: &Key,
+    visibility: Duration,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    queue.serialize(socket).await?;
+    OvrInteger::write(visibility.as_millis() as u64, socket).await?;
+    Ok(())
+}
+
 // pub(crate) async fn write_value<'a, W: LocalWriteAsync>(
 //     value: &'a Value,
 //     socket: &mut W,
@@ -190,6 +757,15 @@ async fn write_value_string<'a, W: LocalWriteAsync>(
 //     Ok(())
 // }
 
+async fn write_value_json<W: LocalWriteAsync>(
+    json: &str,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    socket.write_all(vec![ 3 ]).await?;
+    json.serialize(socket).await?;
+    Ok(())
+}
+
 async fn write_value_signed_integer<W: LocalWriteAsync>(
     value: i64,
     socket: &mut W,
@@ -200,6 +776,37 @@ async fn write_value_signed_integer<W: LocalWriteAsync>(
     Ok(())
 }
 
+/// Writes a string compressed if that makes it smaller, the original
+/// length goes ahead of the block.
+async fn write_value_compressed_string<W: LocalWriteAsync>(
+    value: &str,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    let block = compress_block(value.as_bytes());
+    if block.len() >= value.len() {
+        return write_value_string(value, socket).await;
+    }
+    socket.write_all(vec![ COMPRESSED_STRING ]).await?;
+    OvrInteger::write(value.len(), socket).await?;
+    OvrInteger::write(block.len(), socket).await?;
+    socket.write_all(block).await?;
+    Ok(())
+}
+
+async fn write_value_map<W: LocalWriteAsync>(
+    fields: &BTreeMap<String, Value>,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    socket.write_all([2].to_vec()).await?;
+    OvrInteger::write(fields.len(), socket).await?;
+    for (name, value) in fields {
+        name.as_str().serialize(socket).await?;
+        // Maps nest, so the recursion has to be boxed.
+        Box::pin(value.serialize(socket)).await?;
+    }
+    Ok(())
+}
+
 async fn write_delete_packet<W: LocalWriteAsync>(
     key: &Key,
     socket: &mut W,
@@ -216,6 +823,205 @@ async fn write_get_packet<W: LocalWriteAsync>(
     Ok(())
 }
 
+async fn write_auth_packet<W: LocalWriteAsync>(
+    token: &str,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    token.serialize(socket).await?;
+    Ok(())
+}
+
+async fn write_manifest_report_packet<W: LocalWriteAsync>(
+    drift: &[KeyDrift],
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    OvrInteger::write(drift.len(), socket).await?;
+    for entry in drift {
+        entry.serialize(socket).await?;
+    }
+    Ok(())
+}
+
+async fn write_list_keys_packet<W: LocalWriteAsync>(
+    cursor: Option<&Key>,
+    limit: u32,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    cursor.serialize(socket).await?;
+    OvrInteger::write(limit, socket).await?;
+    Ok(())
+}
+
+async fn write_key_page_packet<W: LocalWriteAsync>(
+    keys: &[Key],
+    cursor: Option<&Key>,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    OvrInteger::write(keys.len(), socket).await?;
+    for key in keys {
+        key.serialize(socket).await?;
+    }
+    cursor.serialize(socket).await?;
+    Ok(())
+}
+
+async fn write_export_page_packet<W: LocalWriteAsync>(
+    records: &[ExportedRecord],
+    cursor: Option<&Key>,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    OvrInteger::write(records.len(), socket).await?;
+    for record in records {
+        record.serialize(socket).await?;
+    }
+    cursor.serialize(socket).await?;
+    Ok(())
+}
+
+async fn write_children_packet<W: LocalWriteAsync>(
+    children: &[KeyChild],
+    cursor: Option<&Key>,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    OvrInteger::write(children.len(), socket).await?;
+    for child in children {
+        child.serialize(socket).await?;
+    }
+    cursor.serialize(socket).await?;
+    Ok(())
+}
+
+/// Writes the records of an archived or import packet.
+async fn write_records<W: LocalWriteAsync>(
+    records: &[(Key, Value)],
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    OvrInteger::write(records.len(), socket).await?;
+    for (key, value) in records {
+        key.serialize(socket).await?;
+        value.serialize(socket).await?;
+    }
+    Ok(())
+}
+
+async fn write_watch_overflow_packet<W: LocalWriteAsync>(
+    key: &Key,
+    missed: u64,
+    closed: bool,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    key.serialize(socket).await?;
+    OvrInteger::write(missed, socket).await?;
+    closed.serialize(socket).await?;
+    Ok(())
+}
+
+async fn write_key_history_packet<W: LocalWriteAsync>(
+    key: &Key,
+    from: &Revision,
+    to: &Revision,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    key.serialize(socket).await?;
+    from.serialize(socket).await?;
+    to.serialize(socket).await?;
+    Ok(())
+}
+
+async fn write_key_meta_packet<W: LocalWriteAsync>(
+    key: &Key,
+    meta: &KeyMeta,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    key.serialize(socket).await?;
+    meta.serialize(socket).await?;
+    Ok(())
+}
+
+async fn write_watch_isolated_packet<W: LocalWriteAsync>(
+    key: &Key,
+    reason: &IsolationReason,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    key.serialize(socket).await?;
+    socket.write_u8(reason.discriminator()).await?;
+    Ok(())
+}
+
+async fn write_query_by_index_packet<W: LocalWriteAsync>(
+    field: &str,
+    value: &Value,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    field.serialize(socket).await?;
+    value.serialize(socket).await?;
+    Ok(())
+}
+
+async fn write_index_matches_packet<W: LocalWriteAsync>(
+    keys: Option<&[Key]>,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    keys.is_some().serialize(socket).await?;
+    if let Some(keys) = keys {
+        write_keys(keys, socket).await?;
+    }
+    Ok(())
+}
+
+async fn write_promote_packet<W: LocalWriteAsync>(
+    promotion: &Promotion,
+    dry_run: bool,
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    promotion.source.as_str().serialize(socket).await?;
+    promotion.source_prefix.as_str().serialize(socket).await?;
+    promotion.target.as_str().serialize(socket).await?;
+    promotion.target_prefix.as_str().serialize(socket).await?;
+    promotion.prune.serialize(socket).await?;
+    dry_run.serialize(socket).await?;
+    Ok(())
+}
+
+async fn write_keys<W: LocalWriteAsync>(keys: &[Key], socket: &mut W) -> Result<(), NetworkError> {
+    OvrInteger::write(keys.len(), socket).await?;
+    for key in keys {
+        key.serialize(socket).await?;
+    }
+    Ok(())
+}
+
+async fn read_keys<R: LocalReadAsync>(socket: &mut R) -> Result<Vec<Key>, NetworkError> {
+    let count: usize = OvrInteger::read(socket).await?;
+    let mut keys = Vec::new();
+    for _ in 0..count {
+        keys.push(Key::deserialize(socket).await?);
+    }
+    Ok(keys)
+}
+
+async fn write_compaction_report_packet<W: LocalWriteAsync>(
+    runs: &[CompactionRun],
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    OvrInteger::write(runs.len(), socket).await?;
+    for run in runs {
+        run.serialize(socket).await?;
+    }
+    Ok(())
+}
+
+async fn write_history_report_packet<W: LocalWriteAsync>(
+    entries: &[HistoryEntry],
+    socket: &mut W,
+) -> Result<(), NetworkError> {
+    OvrInteger::write(entries.len(), socket).await?;
+    for entry in entries {
+        entry.serialize(socket).await?;
+    }
+    Ok(())
+}
+
 async fn write_release_packet<W: LocalWriteAsync>(
     key: &Key,
     socket: &mut W,
@@ -240,19 +1046,19 @@ async fn read_getreturn_packet<'a, R: LocalReadAsync>(
 ) -> Result<PacketPayload<'a>, NetworkError> {
     let key = Key::deserialize(socket).await?;
     let value = Option::<&Value>::deserialize(socket).await?;
-    Ok(PacketPayload::Return { key: Cow::Owned(key), value: value.map(|f| Cow::Owned(f)) })
+    // The version trails the payload, see [Packet::deserialize].
+    Ok(PacketPayload::Return { key: Cow::Owned(key), value: value.map(|f| Cow::Owned(f)), version: 0, meta: None })
 }
 /// Reads a packet of the set type.
 async fn read_notify_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
     let key = Key::deserialize(socket).await?;
     let value = Option::<&Value>::deserialize(socket).await?;
     let more = bool::deserialize(socket).await?;
-    Ok(PacketPayload::Notify { key: Cow::Owned(key), value: value.map(|f| Cow::Owned(f)), more })
+    Ok(PacketPayload::Notify { key: Cow::Owned(key), value: value.map(|f| Cow::Owned(f)), more, heartbeat: false })
 }
 
 /// Reads a packet of the set type.
 async fn read_delete_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
-    println!("Reading delete packet...");
     let key = Key::deserialize(socket).await?;
     Ok(PacketPayload::Delete { key: Cow::Owned(key) })
 }
@@ -276,16 +1082,328 @@ async fn read_set_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<Packet
     Ok(PacketPayload::Insert { key: Cow::Owned(key), value: Cow::Owned(value) })
 }
 
-/// Reads a packet of the set type.
-async fn read_watch_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+/// Reads a packet of the get or insert type.
+async fn read_get_or_insert_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
     let key = Key::deserialize(socket).await?;
-    let activity = WatcherActivity::try_from(socket.read_u8().await?)?;
-    let behaviour = WatcherBehaviour::try_from(socket.read_u8().await?)?;
-    Ok(PacketPayload::Watch {
-        key: Cow::Owned(key),
-        activity,
-        behaviour,
-    })
+    let default = Value::deserialize(socket).await?;
+    Ok(PacketPayload::GetOrInsert { key: Cow::Owned(key), default: Cow::Owned(default) })
+}
+
+/// Reads a packet of the publish type.
+async fn read_publish_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let topic = Key::deserialize(socket).await?;
+    let value = Value::deserialize(socket).await?;
+    Ok(PacketPayload::Publish { topic: Cow::Owned(topic), value: Cow::Owned(value) })
+}
+
+/// Reads a packet of the insert if version type.
+async fn read_insert_if_version_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    let value = Value::deserialize(socket).await?;
+    let version = OvrInteger::read(socket).await?;
+    Ok(PacketPayload::InsertIfVersion { key: Cow::Owned(key), value: Cow::Owned(value), version })
+}
+
+/// Reads a packet of the version conflict type.
+async fn read_version_conflict_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    let version = OvrInteger::read(socket).await?;
+    Ok(PacketPayload::VersionConflict { key: Cow::Owned(key), version })
+}
+
+/// Reads a packet of the enqueue type.
+async fn read_enqueue_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let queue = Key::deserialize(socket).await?;
+    let value = Value::deserialize(socket).await?;
+    Ok(PacketPayload::Enqueue { queue: Cow::Owned(queue), value: Cow::Owned(value) })
+}
+
+/// Reads a packet of the dequeue type.
+async fn read_dequeue_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let queue = Key::deserialize(socket).await?;
+    let visibility = Duration::from_millis(OvrInteger::read(socket).await?);
+    Ok(PacketPayload::Dequeue { queue: Cow::Owned(queue), visibility })
+}
+
+/// Reads a packet of the ack or the nack type.
+async fn read_ack_packet<'a, R: LocalReadAsync>(socket: &mut R, nack: bool) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Cow::Owned(Key::deserialize(socket).await?);
+    let lease = OvrInteger::read(socket).await?;
+    Ok(match nack {
+        false => PacketPayload::Ack { key, lease },
+        true => PacketPayload::Nack { key, lease }
+    })
+}
+
+/// Reads a packet of the topic message type.
+async fn read_topic_message_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let topic = Key::deserialize(socket).await?;
+    let value = Value::deserialize(socket).await?;
+    Ok(PacketPayload::TopicMessage { topic: Cow::Owned(topic), value: Cow::Owned(value) })
+}
+
+/// Reads a packet of the auth type.
+async fn read_auth_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let token = <&str>::deserialize(socket).await?;
+    Ok(PacketPayload::Auth { token: Cow::Owned(token) })
+}
+
+/// Reads a packet of the invalidate type.
+async fn read_invalidate_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let prefix = <&str>::deserialize(socket).await?;
+    Ok(PacketPayload::Invalidate { prefix: Cow::Owned(prefix) })
+}
+
+/// Reads a packet of the create namespace type.
+async fn read_create_namespace_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let name = <&str>::deserialize(socket).await?;
+    Ok(PacketPayload::CreateNamespace { name: Cow::Owned(name) })
+}
+
+/// Reads a packet of the drop namespace type.
+async fn read_drop_namespace_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let name = <&str>::deserialize(socket).await?;
+    Ok(PacketPayload::DropNamespace { name: Cow::Owned(name) })
+}
+
+/// Reads a packet of the namespace result type.
+async fn read_namespace_result_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let changed = bool::deserialize(socket).await?;
+    Ok(PacketPayload::NamespaceResult { changed })
+}
+
+/// Reads a packet of the list keys type.
+async fn read_list_keys_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let cursor = Option::<&Key>::deserialize(socket).await?;
+    let limit = OvrInteger::read(socket).await?;
+    Ok(PacketPayload::ListKeys { cursor: cursor.map(Cow::Owned), limit })
+}
+
+/// Reads a packet of the key page type.
+async fn read_key_page_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let count: usize = OvrInteger::read(socket).await?;
+    let mut keys = Vec::new();
+    for _ in 0..count {
+        keys.push(Key::deserialize(socket).await?);
+    }
+    let cursor = Option::<&Key>::deserialize(socket).await?;
+    Ok(PacketPayload::KeyPage { keys, cursor })
+}
+
+/// Reads a packet of the export type, laid out like a list keys packet.
+async fn read_export_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let cursor = Option::<&Key>::deserialize(socket).await?;
+    let limit = OvrInteger::read(socket).await?;
+    Ok(PacketPayload::Export { cursor: cursor.map(Cow::Owned), limit })
+}
+
+/// Reads a packet of the export page type.
+async fn read_export_page_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let count: usize = OvrInteger::read(socket).await?;
+    let mut records = Vec::new();
+    for _ in 0..count {
+        records.push(ExportedRecord::deserialize(socket).await?);
+    }
+    let cursor = Option::<&Key>::deserialize(socket).await?;
+    Ok(PacketPayload::ExportPage { records, cursor })
+}
+
+/// Reads a packet of the list children type, the key followed by what
+/// a list keys packet holds.
+async fn read_list_children_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    let cursor = Option::<&Key>::deserialize(socket).await?;
+    let limit = OvrInteger::read(socket).await?;
+    Ok(PacketPayload::ListChildren { key: Cow::Owned(key), cursor: cursor.map(Cow::Owned), limit })
+}
+
+/// Reads a packet of the children type.
+async fn read_children_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let count: usize = OvrInteger::read(socket).await?;
+    let mut children = Vec::new();
+    for _ in 0..count {
+        children.push(KeyChild::deserialize(socket).await?);
+    }
+    let cursor = Option::<&Key>::deserialize(socket).await?;
+    Ok(PacketPayload::Children { children, cursor })
+}
+
+/// Reads a packet of the watcher report type.
+async fn read_watcher_report_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let count: usize = OvrInteger::read(socket).await?;
+    let mut watched = Vec::new();
+    for _ in 0..count {
+        watched.push(WatchedKey { key: Key::deserialize(socket).await?, clients: OvrInteger::read(socket).await? });
+    }
+    Ok(PacketPayload::WatcherReport { watched })
+}
+
+/// Reads the records of an archived or import packet.
+async fn read_records<R: LocalReadAsync>(socket: &mut R) -> Result<Vec<(Key, Value)>, NetworkError> {
+    let count: usize = OvrInteger::read(socket).await?;
+    let mut records = Vec::new();
+    for _ in 0..count {
+        records.push((Key::deserialize(socket).await?, Value::deserialize(socket).await?));
+    }
+    Ok(records)
+}
+
+/// Reads a packet of the watch overflow type.
+async fn read_watch_overflow_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    let missed = OvrInteger::read(socket).await?;
+    let closed = bool::deserialize(socket).await?;
+    Ok(PacketPayload::WatchOverflow { key: Cow::Owned(key), missed, closed })
+}
+
+/// Reads a packet of the key history type.
+async fn read_key_history_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    let from = Revision::deserialize(socket).await?;
+    let to = Revision::deserialize(socket).await?;
+    Ok(PacketPayload::KeyHistory { key: Cow::Owned(key), from, to })
+}
+
+/// Reads a packet of the promote type.
+async fn read_promote_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let promotion = Promotion {
+        source: <&str>::deserialize(socket).await?,
+        source_prefix: <&str>::deserialize(socket).await?,
+        target: <&str>::deserialize(socket).await?,
+        target_prefix: <&str>::deserialize(socket).await?,
+        prune: bool::deserialize(socket).await?
+    };
+    let dry_run = bool::deserialize(socket).await?;
+    Ok(PacketPayload::Promote { promotion, dry_run })
+}
+
+/// Reads a packet of the promotion result type.
+async fn read_promotion_result_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let report = Option::<&PromotionReport>::deserialize(socket).await?;
+    Ok(PacketPayload::PromotionResult { report })
+}
+
+/// Reads a packet of the key meta type.
+async fn read_key_meta_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    let meta = KeyMeta::deserialize(socket).await?;
+    // The value and the record metadata trail the payload, see [Packet::deserialize].
+    Ok(PacketPayload::KeyMeta { key: Cow::Owned(key), value: None, meta })
+}
+
+/// Reads a packet of the watch isolated type.
+async fn read_watch_isolated_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    let reason = IsolationReason::try_from(socket.read_u8().await?)?;
+    Ok(PacketPayload::WatchIsolated { key: Cow::Owned(key), reason })
+}
+
+/// Reads a packet of the query by index type.
+async fn read_query_by_index_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let field = <&str>::deserialize(socket).await?;
+    let value = Value::deserialize(socket).await?;
+    Ok(PacketPayload::QueryByIndex { field: Cow::Owned(field), value: Cow::Owned(value) })
+}
+
+/// Reads a packet of the index matches type.
+async fn read_index_matches_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let keys = if bool::deserialize(socket).await? {
+        Some(read_keys(socket).await?)
+    } else {
+        None
+    };
+    Ok(PacketPayload::IndexMatches { keys })
+}
+
+/// Reads a packet of the key diff type.
+async fn read_key_diff_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let diff = Option::<&KeyDiff>::deserialize(socket).await?;
+    Ok(PacketPayload::KeyDiff { diff })
+}
+
+/// Reads a packet of the auth result type.
+async fn read_auth_result_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let accepted = bool::deserialize(socket).await?;
+    Ok(PacketPayload::AuthResult { accepted })
+}
+
+/// Reads a packet of the watch snapshot type.
+async fn read_watch_snapshot_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    let behaviour = WatcherBehaviour::try_from(socket.read_u8().await?)?;
+    Ok(PacketPayload::WatchSnapshot { key: Cow::Owned(key), behaviour, min_interval: None })
+}
+
+/// Reads a packet of the snapshot type.
+async fn read_snapshot_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    let value = Option::<&Value>::deserialize(socket).await?;
+    let version = OvrInteger::read(socket).await?;
+    Ok(PacketPayload::Snapshot { key: Cow::Owned(key), value: value.map(|f| Cow::Owned(f)), version })
+}
+
+/// Reads a key followed by a sequence, for the acknowledged delivery packets.
+async fn read_key_sequence_packet<R: LocalReadAsync>(socket: &mut R) -> Result<(Key, u64), NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    Ok((key, OvrInteger::read(socket).await?))
+}
+
+/// Reads a packet of the compaction report type.
+async fn read_compaction_report_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let count: usize = OvrInteger::read(socket).await?;
+    let mut runs = Vec::new();
+    for _ in 0..count {
+        runs.push(CompactionRun::deserialize(socket).await?);
+    }
+    Ok(PacketPayload::CompactionReport { runs })
+}
+
+/// Reads a packet of the acknowledged insert type.
+async fn read_insert_acknowledged_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    let value = Value::deserialize(socket).await?;
+    let acknowledgement = Acknowledgement::deserialize(socket).await?;
+    Ok(PacketPayload::InsertAcknowledged { key: Cow::Owned(key), value: Cow::Owned(value), acknowledgement })
+}
+
+/// Reads a packet of the history type.
+async fn read_history_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    let limit = OvrInteger::read(socket).await?;
+    Ok(PacketPayload::History { key: Cow::Owned(key), limit })
+}
+
+/// Reads a packet of the history report type.
+async fn read_history_report_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let count: usize = OvrInteger::read(socket).await?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        entries.push(HistoryEntry::deserialize(socket).await?);
+    }
+    Ok(PacketPayload::HistoryReport { entries })
+}
+
+/// Reads a packet of the manifest report type.
+async fn read_manifest_report_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let count: usize = OvrInteger::read(socket).await?;
+    let mut drift = Vec::new();
+    for _ in 0..count {
+        drift.push(KeyDrift::deserialize(socket).await?);
+    }
+    Ok(PacketPayload::ManifestReport { drift })
+}
+
+/// Reads a packet of the set type.
+async fn read_watch_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<PacketPayload<'a>, NetworkError> {
+    let key = Key::deserialize(socket).await?;
+    let activity = WatcherActivity::try_from(socket.read_u8().await?)?;
+    let behaviour = WatcherBehaviour::try_from(socket.read_u8().await?)?;
+    Ok(PacketPayload::Watch {
+        key: Cow::Owned(key),
+        activity,
+        behaviour,
+        min_interval: None
+    })
 }
 
 
@@ -294,176 +1412,1722 @@ async fn read_watch_packet<'a, R: LocalReadAsync>(socket: &mut R) -> Result<Pack
 //     match type_discrim {
 //         0 => Ok(Value::String(<&str>::deserialize(socket).await?)),
 //         1 => decode_value_signed_integer(socket).await,
-//         x => Err(NetworkError::UnrecognizedValueTypeDiscriminator(x)),
+//         x => Err(NetworkError::Protocol(ProtocolError::UnrecognizedValueTypeDiscriminator(x))),
 //     }
 // }
 
+/// Reads a value that is nested in `depth` maps.
+async fn decode_value<R: LocalReadAsync>(reader: &mut R, depth: usize) -> Result<Value, NetworkError> {
+    let type_discrim = reader.read_u8().await?;
+    match type_discrim {
+        0 => Ok(Value::String(SmallString::deserialize(reader).await?)),
+        1 => decode_value_signed_integer(reader).await,
+        2 => decode_value_map(reader, depth).await,
+        3 => Ok(Value::Json(String::deserialize(reader).await?)),
+        COMPRESSED_STRING => decode_value_compressed_string(reader).await,
+        x => Err(NetworkError::Protocol(ProtocolError::UnrecognizedValueTypeDiscriminator(x))),
+    }
+}
+
 async fn decode_value_signed_integer<R: LocalReadAsync>(socket: &mut R) -> Result<Value, NetworkError> {
     let val: i64 = OvrInteger::read(socket).await?;
     Ok(Value::Integer(val))
 }
 
+async fn decode_value_compressed_string<R: LocalReadAsync>(socket: &mut R) -> Result<Value, NetworkError> {
+    let length = OvrInteger::read_length(socket).await?;
+    socket.inflate(length)?;
+    let compressed = OvrInteger::read_length(socket).await?;
+    let block = socket.buffer(compressed);
+    let (block, _) = socket.read_exact(block).await?;
+    let bytes = decompress_block(&block, length);
+    socket.recycle(block);
+    let bytes = bytes?;
+    Ok(Value::String(String::from_utf8(bytes).map_err(|_| NetworkError::Protocol(ProtocolError::DecompressionFailed))?.into()))
+}
+
+/// Reads a map nested in `depth` others.
+async fn decode_value_map<R: LocalReadAsync>(socket: &mut R, depth: usize) -> Result<Value, NetworkError> {
+    if depth >= MAX_VALUE_DEPTH {
+        return Err(NetworkError::Protocol(ProtocolError::ValueTooDeep(MAX_VALUE_DEPTH)));
+    }
+    let count: usize = OvrInteger::read(socket).await?;
+    let mut fields = BTreeMap::new();
+    for _ in 0..count {
+        let name = <&str>::deserialize(socket).await?;
+        fields.insert(name, Box::pin(decode_value(socket, depth + 1)).await?);
+    }
+    Ok(Value::Map(fields))
+}
+
+
+
+// pub(crate) async fn read_key<R>(socket: &mut R) -> Result<Key, NetworkError>
+// where 
+//     R: LocalReadAsync
+// {
+    
+// }
+
+#[allow(async_fn_in_trait)]
+pub trait OverseerSerde<O: Sized>: Sized {
+    type E;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E>;
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<O, Self::E>;
+}
+
+impl OverseerSerde<bool> for bool {
+    type E = std::io::Error;
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<bool, Self::E> {
+        let b = reader.read_u8().await? ;
+        Ok(match b {
+            0 => false,
+            1 => true,
+            _ => Err(std::io::Error::new(ErrorKind::InvalidData, "Could not decode boolean."))?,
+        })
+    }
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        if *self {
+            writer.write_all(vec![  1 ]).await?;
+        } else {
+            writer.write_all(vec![ 0 ]).await?;
+        }
+        Ok(())
+    }
+}
+
+
+impl OverseerSerde<Value> for Value {
+    type E = NetworkError;
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Value, Self::E> {
+        decode_value(reader, 0).await
+    }
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        match &*self {
+            Value::String(s) if writer.compresses_values() && s.len() >= COMPRESSION_THRESHOLD => write_value_compressed_string(&*s, writer).await,
+            Value::String(s) => write_value_string(&*s, writer).await,
+            Value::Integer(s) => write_value_signed_integer(*s, writer).await,
+            Value::Map(fields) => write_value_map(fields, writer).await,
+            Value::Json(json) => write_value_json(json, writer).await,
+        }
+    }
+}
+
+impl<'a, J, O> OverseerSerde<Option<O>> for Option<&'a J>
+where 
+    J: OverseerSerde<O>,
+    O: Sized,
+    <J as OverseerSerde<O>>::E: From<std::io::Error>
+{
+    type E = J::E;
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Option<O>, Self::E> {
+        let flag = reader.read_u8().await?;
+        if flag == 0 {
+            Ok(None)
+        } else if flag == 1 {
+            Ok(Some(J::deserialize(reader).await?))
+        } else {
+            Err(std::io::Error::new(ErrorKind::InvalidData, "Failed decoding option"))?
+        }
+    }
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        match self {
+            None => writer.write_u8(0).await?,
+            Some(i) => {
+                writer.write_u8(1).await?;
+                i.serialize(writer).await?;
+            }
+        }
+        Ok(())
+    }
+    
+}
+
+impl<'a> OverseerSerde<String> for &'a str {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        OvrInteger::write(self.len(), writer).await?;
+        writer.write_all(self.as_bytes().to_vec()).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<String, Self::E> {
+        // Figure out the size of the string.
+        let string_length = OvrInteger::read_length(reader).await?;
+
+        if string_length == 0 {
+            return Ok(String::default());
+        }
+
+        let str_buf = vec![0u8; string_length];
+        let (str_buf, _) = reader.read_exact(str_buf).await?;
+
+        Ok(
+            String::from_utf8(str_buf).map_err(|_| NetworkError::Protocol(ProtocolError::FailedToReadValue))?,
+        )
+    }
+}
+
+impl OverseerSerde<String> for String {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        self.as_str().serialize(writer).await
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<String, Self::E> {
+        <&str>::deserialize(reader).await
+    }
+}
+
+/// Encoded like a `&str`, read into a buffer of the reader that it hands
+/// back once the string is copied out.
+impl OverseerSerde<SmallString> for SmallString {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        self.as_str().serialize(writer).await
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<SmallString, Self::E> {
+        let length = OvrInteger::read_length(reader).await?;
+        if length == 0 {
+            return Ok(SmallString::default());
+        }
+        let buffer = reader.buffer(length);
+        let (buffer, _) = reader.read_exact(buffer).await?;
+        let string = std::str::from_utf8(&buffer).map(SmallString::new).map_err(|_| NetworkError::Protocol(ProtocolError::FailedToReadValue));
+        reader.recycle(buffer);
+        string
+    }
+}
+
+/// Unsigned integers are encoded as varints.
+macro_rules! varint_serde {
+    ($($integer:ty),*) => {
+        $(
+            impl OverseerSerde<$integer> for $integer {
+                type E = NetworkError;
+                async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+                    Ok(OvrInteger::write(*self, writer).await?)
+                }
+                async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<$integer, Self::E> {
+                    Ok(OvrInteger::read(reader).await?)
+                }
+            }
+        )*
+    };
+}
+
+varint_serde!(u16, u32, u64, usize);
+
+/// The length as a varint, then every item.
+impl<T> OverseerSerde<Vec<T>> for Vec<T>
+where
+    T: OverseerSerde<T>,
+    NetworkError: From<T::E>
+{
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        OvrInteger::write(self.len(), writer).await?;
+        for item in self {
+            item.serialize(writer).await?;
+        }
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Vec<T>, Self::E> {
+        let length: usize = OvrInteger::read(reader).await?;
+        let mut items = Vec::with_capacity(length.min(1024));
+        for _ in 0..length {
+            items.push(T::deserialize(reader).await?);
+        }
+        Ok(items)
+    }
+}
+
+impl OverseerSerde<ValueType> for ValueType {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        writer.write_u8(self.discriminator()).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        ValueType::try_from(reader.read_u8().await?)
+    }
+}
+
+impl OverseerSerde<KeyDrift> for KeyDrift {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        self.key.serialize(writer).await?;
+        self.expected.serialize(writer).await?;
+        self.found.as_ref().serialize(writer).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        Ok(KeyDrift {
+            key: Key::deserialize(reader).await?,
+            expected: ValueType::deserialize(reader).await?,
+            found: Option::<&ValueType>::deserialize(reader).await?
+        })
+    }
+}
+
+impl OverseerSerde<Revision> for Revision {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        match self {
+            Self::Version(version) => {
+                writer.write_u8(0).await?;
+                OvrInteger::write(*version, writer).await?;
+            }
+            Self::Timestamp(at) => {
+                writer.write_u8(1).await?;
+                OvrInteger::write(*at, writer).await?;
+            }
+        }
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        Ok(match reader.read_u8().await? {
+            0 => Self::Version(OvrInteger::read(reader).await?),
+            1 => Self::Timestamp(OvrInteger::read(reader).await?),
+            _ => Err(NetworkError::Protocol(ProtocolError::RevisionDecodeError))?
+        })
+    }
+}
+
+impl OverseerSerde<DiffLine> for DiffLine {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        let (discriminator, line) = match self {
+            Self::Kept(line) => (0, line),
+            Self::Removed(line) => (1, line),
+            Self::Added(line) => (2, line)
+        };
+        writer.write_u8(discriminator).await?;
+        line.as_str().serialize(writer).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        let discriminator = reader.read_u8().await?;
+        let line = <&str>::deserialize(reader).await?;
+        Ok(match discriminator {
+            0 => Self::Kept(line),
+            1 => Self::Removed(line),
+            2 => Self::Added(line),
+            _ => Err(NetworkError::Protocol(ProtocolError::DiffLineDecodeError))?
+        })
+    }
+}
+
+impl OverseerSerde<KeyDiff> for KeyDiff {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        self.from.as_ref().serialize(writer).await?;
+        self.to.as_ref().serialize(writer).await?;
+        OvrInteger::write(self.lines.len(), writer).await?;
+        for line in &self.lines {
+            line.serialize(writer).await?;
+        }
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        let from = Option::<&Value>::deserialize(reader).await?;
+        let to = Option::<&Value>::deserialize(reader).await?;
+        let count: usize = OvrInteger::read(reader).await?;
+        let mut lines = Vec::new();
+        for _ in 0..count {
+            lines.push(DiffLine::deserialize(reader).await?);
+        }
+        Ok(KeyDiff { from, to, lines })
+    }
+}
+
+impl OverseerSerde<HistoryEntry> for HistoryEntry {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        self.value.as_ref().serialize(writer).await?;
+        OvrInteger::write(self.version, writer).await?;
+        OvrInteger::write(self.at, writer).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        Ok(HistoryEntry {
+            value: Option::<&Value>::deserialize(reader).await?,
+            version: OvrInteger::read(reader).await?,
+            at: OvrInteger::read(reader).await?
+        })
+    }
+}
+
+impl OverseerSerde<Durability> for Durability {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        writer.write_u8(match self {
+            Self::Sync => 0,
+            Self::Periodic => 1,
+            Self::BestEffort => 2
+        }).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        Ok(match reader.read_u8().await? {
+            0 => Self::Sync,
+            1 => Self::Periodic,
+            2 => Self::BestEffort,
+            _ => Err(NetworkError::Protocol(ProtocolError::DurabilityDecodeError))?
+        })
+    }
+}
+
+impl OverseerSerde<Acknowledgement> for Acknowledgement {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        match self {
+            Self::Applied => writer.write_u8(0).await?,
+            Self::Synced => writer.write_u8(1).await?,
+            Self::Replicated(followers) => {
+                writer.write_u8(2).await?;
+                OvrInteger::write(*followers, writer).await?;
+            }
+        }
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        Ok(match reader.read_u8().await? {
+            0 => Self::Applied,
+            1 => Self::Synced,
+            2 => Self::Replicated(OvrInteger::read(reader).await?),
+            _ => Err(NetworkError::Protocol(ProtocolError::AcknowledgementDecodeError))?
+        })
+    }
+}
+
+impl OverseerSerde<DeleteCondition> for DeleteCondition {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        match self {
+            Self::Value(value) => {
+                writer.write_u8(0).await?;
+                value.serialize(writer).await?;
+            }
+            Self::Version(version) => {
+                writer.write_u8(1).await?;
+                OvrInteger::write(*version, writer).await?;
+            }
+        }
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        Ok(match reader.read_u8().await? {
+            0 => Self::Value(Value::deserialize(reader).await?),
+            1 => Self::Version(OvrInteger::read(reader).await?),
+            x => Err(NetworkError::Protocol(ProtocolError::UnrecognizedDiscriminator("delete condition", x)))?
+        })
+    }
+}
+
+impl OverseerSerde<KeyFilter> for KeyFilter {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        writer.write_u8(match self {
+            Self::Glob(..) => 0,
+            Self::Regex(..) => 1
+        }).await?;
+        self.as_str().serialize(writer).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        let kind = reader.read_u8().await?;
+        let source = <&str>::deserialize(reader).await?;
+        Ok(match kind {
+            0 => Self::Glob(source),
+            1 => Self::Regex(source),
+            x => Err(NetworkError::Protocol(ProtocolError::UnrecognizedDiscriminator("key filter", x)))?
+        })
+    }
+}
+
+impl OverseerSerde<KeyMeta> for KeyMeta {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        self.exists.serialize(writer).await?;
+        self.durability.serialize(writer).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        Ok(KeyMeta {
+            exists: bool::deserialize(reader).await?,
+            durability: Durability::deserialize(reader).await?,
+            record: None
+        })
+    }
+}
+
+impl OverseerSerde<RecordMeta> for RecordMeta {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        OvrInteger::write(self.created_at, writer).await?;
+        OvrInteger::write(self.modified_at, writer).await?;
+        OvrInteger::write(self.accessed_at, writer).await?;
+        self.writer.is_some().serialize(writer).await?;
+        if let Some(id) = self.writer {
+            OvrInteger::write(id, writer).await?;
+        }
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        let created_at = OvrInteger::read(reader).await?;
+        let modified_at = OvrInteger::read(reader).await?;
+        let accessed_at = OvrInteger::read(reader).await?;
+        let writer = match bool::deserialize(reader).await? {
+            true => Some(OvrInteger::read(reader).await?),
+            false => None
+        };
+        Ok(RecordMeta { created_at, modified_at, accessed_at, writer })
+    }
+}
+
+impl OverseerSerde<ExportedRecord> for ExportedRecord {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        self.key.serialize(writer).await?;
+        self.value.serialize(writer).await?;
+        self.meta.as_ref().serialize(writer).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        Ok(ExportedRecord {
+            key: Key::deserialize(reader).await?,
+            value: Value::deserialize(reader).await?,
+            meta: Option::<&RecordMeta>::deserialize(reader).await?
+        })
+    }
+}
+
+impl OverseerSerde<KeyChild> for KeyChild {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        self.key.serialize(writer).await?;
+        self.has_value.serialize(writer).await?;
+        self.has_children.serialize(writer).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        Ok(KeyChild {
+            key: Key::deserialize(reader).await?,
+            has_value: bool::deserialize(reader).await?,
+            has_children: bool::deserialize(reader).await?
+        })
+    }
+}
+
+impl OverseerSerde<LeasedItem> for LeasedItem {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        self.key.serialize(writer).await?;
+        self.value.serialize(writer).await?;
+        OvrInteger::write(self.lease, writer).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        Ok(LeasedItem {
+            key: Key::deserialize(reader).await?,
+            value: Value::deserialize(reader).await?,
+            lease: OvrInteger::read(reader).await?
+        })
+    }
+}
+
+impl OverseerSerde<PromotionReport> for PromotionReport {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        match self.id {
+            Some(id) => {
+                writer.write_u8(1).await?;
+                OvrInteger::write(id, writer).await?;
+            }
+            None => writer.write_u8(0).await?
+        }
+        write_keys(&self.added, writer).await?;
+        write_keys(&self.changed, writer).await?;
+        write_keys(&self.removed, writer).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        let id = match reader.read_u8().await? {
+            0 => None,
+            1 => Some(OvrInteger::read(reader).await?),
+            _ => Err(NetworkError::Protocol(ProtocolError::ErrorDecodingOption))?
+        };
+        Ok(PromotionReport {
+            id,
+            added: read_keys(reader).await?,
+            changed: read_keys(reader).await?,
+            removed: read_keys(reader).await?
+        })
+    }
+}
+
+impl OverseerSerde<CompactionRun> for CompactionRun {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        OvrInteger::write(self.finished_at, writer).await?;
+        OvrInteger::write(self.duration.as_micros() as u64, writer).await?;
+        OvrInteger::write(self.bytes_reclaimed, writer).await?;
+        OvrInteger::write(self.pages_moved, writer).await?;
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        Ok(CompactionRun {
+            finished_at: OvrInteger::read(reader).await?,
+            duration: Duration::from_micros(OvrInteger::read(reader).await?),
+            bytes_reclaimed: OvrInteger::read(reader).await?,
+            pages_moved: OvrInteger::read(reader).await?
+        })
+    }
+}
+
+/// The totals, then the count of each prefix.
+impl OverseerSerde<DatabaseStats> for DatabaseStats {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        OvrInteger::write(self.keys, writer).await?;
+        OvrInteger::write(self.value_bytes, writer).await?;
+        OvrInteger::write(self.storage_bytes, writer).await?;
+        OvrInteger::write(self.dirty, writer).await?;
+        OvrInteger::write(self.flushes, writer).await?;
+        OvrInteger::write(self.flush_time.as_micros() as u64, writer).await?;
+        OvrInteger::write(self.prefixes.len(), writer).await?;
+        for (prefix, count) in &self.prefixes {
+            prefix.as_str().serialize(writer).await?;
+            OvrInteger::write(*count, writer).await?;
+        }
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        let mut stats = DatabaseStats {
+            keys: OvrInteger::read(reader).await?,
+            value_bytes: OvrInteger::read(reader).await?,
+            storage_bytes: OvrInteger::read(reader).await?,
+            dirty: OvrInteger::read(reader).await?,
+            flushes: OvrInteger::read(reader).await?,
+            flush_time: Duration::from_micros(OvrInteger::read(reader).await?),
+            prefixes: BTreeMap::new()
+        };
+        let count: usize = OvrInteger::read(reader).await?;
+        for _ in 0..count {
+            let prefix = <&str>::deserialize(reader).await?;
+            stats.prefixes.insert(prefix, OvrInteger::read(reader).await?);
+        }
+        Ok(stats)
+    }
+}
+
+/// The kind of schema, then what it checks, the bounds of an integer
+/// schema each behind a flag.
+impl OverseerSerde<Schema> for Schema {
+    type E = NetworkError;
+    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
+        match self {
+            Self::String { pattern } => {
+                writer.write_u8(0).await?;
+                pattern.serialize(writer).await?;
+            }
+            Self::Integer { min, max } => {
+                writer.write_u8(1).await?;
+                for bound in [min, max] {
+                    bound.is_some().serialize(writer).await?;
+                    if let Some(bound) = bound {
+                        OvrInteger::write(*bound, writer).await?;
+                    }
+                }
+            }
+            Self::Json { schema } => {
+                writer.write_u8(2).await?;
+                schema.serialize(writer).await?;
+            }
+        }
+        Ok(())
+    }
+    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
+        Ok(match reader.read_u8().await? {
+            0 => Self::String { pattern: String::deserialize(reader).await? },
+            1 => {
+                let mut bounds = [None; 2];
+                for bound in &mut bounds {
+                    if bool::deserialize(reader).await? {
+                        *bound = Some(OvrInteger::read(reader).await?);
+                    }
+                }
+                Self::Integer { min: bounds[0], max: bounds[1] }
+            }
+            2 => Self::Json { schema: String::deserialize(reader).await? },
+            x => Err(NetworkError::Protocol(ProtocolError::UnrecognizedDiscriminator("schema", x)))?
+        })
+    }
+}
+
+// #[async_trait::async_trait]
+// impl OverseerSerde for Key {
+//     type E = NetworkError;
+//     async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), E> {
+//         if let Value::String(inner) = decode_value_string(socket).await? {
+//             Ok(Key::from_str(&inner ))
+//         } else {
+//             Err(NetworkError::Protocol(ProtocolError::FailedToReadKey))
+//         }
+//     }
+//     async fn deserialize<W: LocalReadAsync>(writer: &mut W) -> std::io::Result<Key> {
+//         if let Value::String(inner) = decode_value_string(socket).await? {
+//             Ok(Key::from_str(&inner ))
+//         } else {
+//             Err(NetworkError::Protocol(ProtocolError::FailedToReadKey))
+//         }
+//     }
+// }
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, collections::BTreeMap, io::Cursor, time::Duration};
+
+  
+
+    use crate::{
+        access::{IsolationReason, WatcherActivity, WatcherBehaviour},
+        error::{ClientError, ErrorClass, ErrorCode, NetworkError, ProtocolError, StorageError},
+        models::{Acknowledgement, CompactionRun, DatabaseStats, DeleteCondition, Durability, ExportedRecord, HistoryEntry, Key, KeyChild, WatchedKey, KeyDiff, KeyDrift, KeyFilter, KeyMeta, LeasedItem, LocalWriteAsync, Promotion, PromotionReport, RecordMeta, Revision, Schema, Value, ValueType},
+        network::{OverseerSerde, OvrInteger, PacketId, PacketPayload, DEFAULT_MAX_PACKET_SIZE, FIRST_EXTENSION_DISCRIMINATOR, MAX_INFLATION, MAX_VALUE_DEPTH},
+    };
+
+    use super::Packet;
+
+    // use crate::net::{driver::read_packet, Driver};
+
+    #[tokio::test]
+    pub async fn write_namespace_packet() {
+        let packet = Packet::new(PacketId::new(3, 0), PacketPayload::create_namespace("tenant-a")).with_namespace("tenant-a");
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+
+        let packet = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+        assert_eq!(packet.namespace(), "tenant-a");
+        if let PacketPayload::CreateNamespace { name } = packet.payload() {
+            assert_eq!(name, "tenant-a");
+        } else {
+            panic!("Wrong packet type.");
+        }
+
+        // Clients before version 2 only see the default namespace.
+        let packet = Packet::new(PacketId::zero(), PacketPayload::Ping).with_namespace("tenant-a");
+        assert!(packet.downgrade(1).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn write_key_page_packet() {
+        let cursor = Key::from_str("b");
+        let packet = Packet::new(PacketId::new(4, 0), PacketPayload::list_keys(Some(&cursor), 2));
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::ListKeys { cursor, limit } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(cursor.as_deref(), Some(&Key::from_str("b")));
+            assert_eq!(*limit, 2);
+        } else {
+            panic!("Wrong packet type.");
+        }
+
+        let keys = vec![Key::from_str("c"), Key::from_str("d")];
+        let packet = Packet::new(PacketId::new(4, 0), PacketPayload::KeyPage { keys: keys.clone(), cursor: Some(Key::from_str("d")) });
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::KeyPage { keys: decoded, cursor } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(*decoded, keys);
+            assert_eq!(cursor.as_ref(), Some(&Key::from_str("d")));
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_watch_overflow_packet() {
+        let key = Key::from_str("hello");
+        let packet = Packet::new(PacketId::zero(), PacketPayload::WatchOverflow { key: Cow::Borrowed(&key), missed: 300, closed: true });
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::WatchOverflow { key, missed, closed } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(key.as_str(), "hello");
+            assert_eq!(*missed, 300);
+            assert!(*closed);
+        } else {
+            panic!("Wrong packet type.");
+        }
+
+        // Only clients on the current version are told.
+        let packet = Packet::new(PacketId::zero(), PacketPayload::WatchOverflow { key: Cow::Borrowed(&key), missed: 1, closed: false });
+        assert!(packet.downgrade(1).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn write_key_history_packet() {
+        let key = Key::from_str("app.config");
+        let packet = Packet::new(PacketId::new(4, 0), PacketPayload::KeyHistory { key: Cow::Borrowed(&key), from: Revision::Version(3), to: Revision::Timestamp(1_700_000_000_000) });
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::KeyHistory { key, from, to } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(key.as_str(), "app.config");
+            assert_eq!(*from, Revision::Version(3));
+            assert_eq!(*to, Revision::Timestamp(1_700_000_000_000));
+        } else {
+            panic!("Wrong packet type.");
+        }
+
+        let diff = KeyDiff::between(Some(Value::String("a\nb".into())), Some(Value::String("a\nc".into())));
+        let packet = Packet::new(PacketId::new(4, 0), PacketPayload::KeyDiff { diff: Some(diff.clone()) });
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::KeyDiff { diff: decoded } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(decoded.as_ref(), Some(&diff));
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_promote_packet() {
+        let promotion = Promotion {
+            source: "staging".to_string(),
+            source_prefix: "app.".to_string(),
+            target: "production".to_string(),
+            target_prefix: "app.".to_string(),
+            prune: true
+        };
+        let packet = Packet::new(PacketId::new(4, 0), PacketPayload::Promote { promotion: promotion.clone(), dry_run: true });
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::Promote { promotion: decoded, dry_run } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(*decoded, promotion);
+            assert!(*dry_run);
+        } else {
+            panic!("Wrong packet type.");
+        }
+
+        let report = PromotionReport {
+            id: Some(3),
+            added: vec![Key::from_str("app.a")],
+            changed: vec![],
+            removed: vec![Key::from_str("app.b")]
+        };
+        let packet = Packet::new(PacketId::new(4, 0), PacketPayload::PromotionResult { report: Some(report.clone()) });
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::PromotionResult { report: decoded } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(decoded.as_ref(), Some(&report));
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_key_meta_packet() {
+        let key = Key::from_str("telemetry.cpu");
+        let value = Value::Integer(40);
+        let record = RecordMeta { created_at: 1_700_000_000_000, modified_at: 1_700_000_300_000, accessed_at: 1_700_000_400_000, writer: Some(7) };
+        let meta = KeyMeta { exists: true, durability: Durability::BestEffort, record: Some(record) };
+        let packet = Packet::new(PacketId::new(4, 0), PacketPayload::KeyMeta { key: Cow::Borrowed(&key), value: Some(Cow::Borrowed(&value)), meta });
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::KeyMeta { key: decoded, value: decoded_value, meta: decoded_meta } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(**decoded, key);
+            assert_eq!(decoded_value.as_deref(), Some(&value));
+            assert_eq!(*decoded_meta, meta);
+        } else {
+            panic!("Wrong packet type.");
+        }
+
+        // Older peers only get the key meta itself.
+        let mut buffer = vec![];
+        packet.downgrade(9).unwrap().unwrap().serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::KeyMeta { value: decoded_value, meta: decoded_meta, .. } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert!(decoded_value.is_none());
+            assert_eq!(*decoded_meta, KeyMeta { record: None, ..meta });
+        } else {
+            panic!("Wrong packet type.");
+        }
+
+        // A read carries the record metadata, a write made by the server has no writer.
+        let record = RecordMeta { writer: None, ..record };
+        let packet = Packet::new(PacketId::new(5, 0), PacketPayload::Return { key: Cow::Borrowed(&key), value: Some(Cow::Borrowed(&value)), version: 3, meta: Some(record) });
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::Return { version, meta: decoded_meta, .. } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(*version, 3);
+            assert_eq!(*decoded_meta, Some(record));
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_watch_isolated_packet() {
+        let key = Key::from_str("app.feed");
+        let packet = Packet::new(PacketId::zero(), PacketPayload::WatchIsolated { key: Cow::Borrowed(&key), reason: IsolationReason::Saturated });
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::WatchIsolated { key: decoded, reason } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(**decoded, key);
+            assert_eq!(*reason, IsolationReason::Saturated);
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
+
+    #[tokio::test]
+    pub async fn read_nested_value() {
+        let mut value = Value::Integer(7);
+        for _ in 0..MAX_VALUE_DEPTH {
+            value = Value::Map(BTreeMap::from([("a".to_string(), value)]));
+        }
+        let mut buffer = vec![];
+        value.serialize(&mut buffer).await.unwrap();
+        assert_eq!(Value::deserialize(&mut Cursor::new(buffer)).await.unwrap(), value);
+
+        // A map of one field for every level, far past what the stack holds.
+        let mut buffer = [2, 1, 1, b'a'].repeat(100_000);
+        buffer.extend_from_slice(&[1, 0]);
+        assert!(matches!(
+            Value::deserialize(&mut Cursor::new(buffer)).await,
+            Err(NetworkError::Protocol(ProtocolError::ValueTooDeep(MAX_VALUE_DEPTH)))
+        ));
+    }
+
+    #[tokio::test]
+    pub async fn write_query_by_index_packet() {
+        let value = Value::Map(BTreeMap::from([
+            ("zone".to_string(), Value::Integer(2)),
+            ("labels".to_string(), Value::Map(BTreeMap::from([("tier".to_string(), Value::String("edge".into()))])))
+        ]));
+        let packet = Packet::new(PacketId::new(4, 0), PacketPayload::query_by_index("region", &value));
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::QueryByIndex { field, value: decoded } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(field, "region");
+            assert_eq!(**decoded, value);
+        } else {
+            panic!("Wrong packet type.");
+        }
+
+        for keys in [Some(vec![Key::from_str("host.a"), Key::from_str("host.b")]), None] {
+            let packet = Packet::new(PacketId::new(4, 0), PacketPayload::IndexMatches { keys: keys.clone() });
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            if let PacketPayload::IndexMatches { keys: decoded } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+                assert_eq!(*decoded, keys);
+            } else {
+                panic!("Wrong packet type.");
+            }
+        }
+
+        // Clients before version 3 cannot decode maps.
+        let key = Key::from_str("host.a");
+        let packet = Packet::new(PacketId::zero(), PacketPayload::return_packet(&key, Some(&value), 0));
+        assert!(matches!(packet.downgrade(2), Err(NetworkError::Protocol(ProtocolError::ValueTypeUnsupported(ValueType::Map, 2)))));
+    }
+
+    #[tokio::test]
+    pub async fn write_get_or_insert_packet() {
+        let key = Key::from_str("app.mode");
+        let default = Value::String("a".into());
+        let packet = Packet::new(PacketId::new(4, 0), PacketPayload::get_or_insert(&key, &default));
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::GetOrInsert { key: decoded, default: decoded_default } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(**decoded, key);
+            assert_eq!(**decoded_default, default);
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_record_versions() {
+        let key = Key::from_str("app.mode");
+        let value = Value::String("a".into());
+
+        let mut buffer = vec![];
+        Packet::vreturn(PacketId::new(4, 0), &key, Some(&value), 17).serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::Return { version, .. } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(*version, 17);
+        } else {
+            panic!("Wrong packet type.");
+        }
+
+        // Older peers never see the version.
+        let mut buffer = vec![];
+        let packet = Packet::vreturn(PacketId::new(4, 0), &key, Some(&value), 17).downgrade(5).unwrap().unwrap();
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::Return { version, .. } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(*version, 0);
+        } else {
+            panic!("Wrong packet type.");
+        }
+
+        for payload in [PacketPayload::insert_if_version(&key, &value, 9), PacketPayload::VersionConflict { key: Cow::Borrowed(&key), version: 12 }] {
+            let mut buffer = vec![];
+            Packet::new(PacketId::new(4, 0), payload).serialize(&mut buffer).await.unwrap();
+            match Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+                PacketPayload::InsertIfVersion { key: decoded, value: decoded_value, version } => {
+                    assert_eq!((&**decoded, &**decoded_value, *version), (&key, &value, 9));
+                }
+                PacketPayload::VersionConflict { key: decoded, version } => assert_eq!((&**decoded, *version), (&key, 12)),
+                _ => panic!("Wrong packet type.")
+            }
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_insert_acknowledged_packet() {
+        let key = Key::from_str("app.mode");
+        let value = Value::Integer(3);
+        for acknowledgement in [Acknowledgement::Applied, Acknowledgement::Synced, Acknowledgement::Replicated(2)] {
+            let mut buffer = vec![];
+            Packet::new(PacketId::new(4, 0), PacketPayload::insert_acknowledged(&key, &value, acknowledgement)).serialize(&mut buffer).await.unwrap();
+            if let PacketPayload::InsertAcknowledged { key: decoded, value: decoded_value, acknowledgement: level } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+                assert_eq!((&**decoded, &**decoded_value, *level), (&key, &value, acknowledgement));
+            } else {
+                panic!("Wrong packet type.");
+            }
+        }
+
+        // A level never weakens the class of the key.
+        assert_eq!(Acknowledgement::Applied.durability(Durability::Sync), Durability::Sync);
+        assert_eq!(Acknowledgement::Synced.durability(Durability::Periodic), Durability::Sync);
+        assert_eq!(Acknowledgement::Replicated(1).durability(Durability::Periodic), Durability::Periodic);
+        assert_eq!(Acknowledgement::Applied.durability(Durability::Periodic), Durability::Periodic);
+    }
+
+    #[tokio::test]
+    pub async fn write_history_packets() {
+        let key = Key::from_str("app.mode");
+        let mut buffer = vec![];
+        Packet::new(PacketId::new(4, 0), PacketPayload::history(&key, 10)).serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::History { key: decoded, limit } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!((&**decoded, *limit), (&key, 10));
+        } else {
+            panic!("Wrong packet type.");
+        }
+
+        let entries = vec![
+            HistoryEntry { value: Some(Value::String("a".into())), version: 3, at: 1_700_000_000_000 },
+            HistoryEntry { value: None, version: 5, at: 1_700_000_000_500 }
+        ];
+        let mut buffer = vec![];
+        Packet::new(PacketId::new(4, 0), PacketPayload::HistoryReport { entries: entries.clone() }).serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::HistoryReport { entries: decoded } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(*decoded, entries);
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_archive_packets() {
+        let mut buffer = vec![];
+        Packet::new(PacketId::new(4, 0), PacketPayload::Archive { before: 1_700_000_000_000, delete: true }).serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::Archive { before, delete } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!((*before, *delete), (1_700_000_000_000, true));
+        } else {
+            panic!("Wrong packet type.");
+        }
+
+        let records = vec![
+            (Key::from_str("legacy.mode"), Value::String("a".into())),
+            (Key::from_str("legacy.port"), Value::Integer(80))
+        ];
+        let packet = Packet::new(PacketId::new(4, 0), PacketPayload::Archived { records: records.clone() });
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::Archived { records: decoded } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(*decoded, records);
+        } else {
+            panic!("Wrong packet type.");
+        }
+        assert!(packet.downgrade(9).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn write_lock_packets() {
+        let key = Key::from_str("leader");
+        let packets = [
+            PacketPayload::acquire(&key, Duration::from_secs(15)),
+            PacketPayload::release_lock(&key),
+            PacketPayload::LockResult { token: Some(3) },
+            PacketPayload::LockResult { token: None }
+        ];
+        for payload in packets {
+            let name = payload.name();
+            let mut buffer = vec![];
+            Packet::new(PacketId::new(4, 0), payload).serialize(&mut buffer).await.unwrap();
+            let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+            assert_eq!(decoded.payload().name(), name);
+            match decoded.payload() {
+                PacketPayload::Acquire { key: decoded, ttl } => assert_eq!((&**decoded, *ttl), (&key, Duration::from_secs(15))),
+                PacketPayload::ReleaseLock { key: decoded } => assert_eq!(&**decoded, &key),
+                PacketPayload::LockResult { token } => assert!(token.is_none() || *token == Some(3)),
+                _ => panic!("Wrong packet type.")
+            }
+        }
+        let packet = Packet::new(PacketId::zero(), PacketPayload::LockResult { token: None });
+        assert!(packet.downgrade(10).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn write_insert_ephemeral_packet() {
+        let key = Key::from_str("presence.agent-1");
+        let value = Value::String("10.0.0.7:4000".into());
+        let packet = Packet::new(PacketId::new(4, 0), PacketPayload::insert_ephemeral(&key, &value));
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::InsertEphemeral { key: decoded, value: decoded_value } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!((&**decoded, &**decoded_value), (&key, &value));
+        } else {
+            panic!("Wrong packet type.");
+        }
+        assert!(packet.downgrade(11).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn write_extension_packets() {
+        let packets = [
+            PacketPayload::extension(FIRST_EXTENSION_DISCRIMINATOR, 7, vec![1, 2, 3]),
+            PacketPayload::extension(255, 7, vec![]),
+            PacketPayload::UnsupportedExtension { extension: 7 }
+        ];
+        for payload in packets {
+            let discriminator = payload.discriminator();
+            let mut buffer = vec![];
+            Packet::new(PacketId::new(4, 0), payload).serialize(&mut buffer).await.unwrap();
+            // Whatever the opcode, the packet reads the same way.
+            let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+            assert_eq!(decoded.payload().discriminator(), discriminator);
+            match decoded.payload() {
+                PacketPayload::Extension { opcode: FIRST_EXTENSION_DISCRIMINATOR, extension: 7, payload } => assert_eq!(&**payload, &[1, 2, 3]),
+                PacketPayload::Extension { opcode: 255, extension: 7, payload } => assert!(payload.is_empty()),
+                PacketPayload::UnsupportedExtension { extension } => assert_eq!(*extension, 7),
+                _ => panic!("Wrong packet type.")
+            }
+        }
+        let packet = Packet::new(PacketId::zero(), PacketPayload::extension(230, 1, vec![9]));
+        assert!(packet.downgrade(13).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn write_increment_packet() {
+        let key = Key::from_str("stats.hits");
+        for delta in [1, -7, i64::MIN] {
+            let mut buffer = vec![];
+            Packet::new(PacketId::new(4, 0), PacketPayload::increment(&key, delta)).serialize(&mut buffer).await.unwrap();
+            let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+            let PacketPayload::Increment { key: decoded, delta: decoded_delta } = decoded.payload() else {
+                panic!("Wrong packet type.");
+            };
+            assert_eq!((&**decoded, *decoded_delta), (&key, delta));
+        }
+        let packet = Packet::new(PacketId::zero(), PacketPayload::increment(&key, 1));
+        assert!(packet.downgrade(15).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn write_server_busy_packet() {
+        let mut buffer = vec![];
+        Packet::new(PacketId::zero(), PacketPayload::ServerBusy).serialize(&mut buffer).await.unwrap();
+        let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+        assert!(matches!(decoded.payload(), PacketPayload::ServerBusy));
+        assert!(decoded.id().is_notification());
+        let packet = Packet::new(PacketId::zero(), PacketPayload::ServerBusy);
+        assert!(packet.downgrade(18).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn write_throttled_packet() {
+        let mut buffer = vec![];
+        Packet::new(PacketId::new(4, 0), PacketPayload::Throttled { retry_after: Duration::from_millis(250) }).serialize(&mut buffer).await.unwrap();
+        let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+        assert!(matches!(decoded.payload(), PacketPayload::Throttled { retry_after } if *retry_after == Duration::from_millis(250)));
+        let packet = Packet::new(PacketId::zero(), PacketPayload::Throttled { retry_after: Duration::ZERO });
+        assert!(packet.downgrade(19).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn write_delivery_packets() {
+        let key = Key::from_str("orders.last");
+        let value = Value::Integer(12);
+        let mut buffer = vec![];
+        Packet::new(PacketId::new(4, 0), PacketPayload::WatchAcknowledged { key: Cow::Borrowed(&key), after: u64::MAX }).serialize(&mut buffer).await.unwrap();
+        Packet::new(PacketId::zero(), PacketPayload::Delivery { key: Cow::Borrowed(&key), value: Some(Cow::Borrowed(&value)), sequence: 41, gap: false }).serialize(&mut buffer).await.unwrap();
+        Packet::new(PacketId::zero(), PacketPayload::Delivery { key: Cow::Borrowed(&key), value: None, sequence: 42, gap: true }).serialize(&mut buffer).await.unwrap();
+        Packet::new(PacketId::zero(), PacketPayload::AckDelivery { key: Cow::Borrowed(&key), sequence: 42 }).serialize(&mut buffer).await.unwrap();
+
+        let mut cursor = Cursor::new(buffer);
+        let PacketPayload::WatchAcknowledged { key: watched, after } = Packet::deserialize(&mut cursor).await.unwrap().into_payload() else {
+            panic!("Wrong packet type.");
+        };
+        assert_eq!((&*watched, after), (&key, u64::MAX));
+        let PacketPayload::Delivery { value: delivered, sequence, gap, .. } = Packet::deserialize(&mut cursor).await.unwrap().into_payload() else {
+            panic!("Wrong packet type.");
+        };
+        assert_eq!((delivered.as_deref(), sequence, gap), (Some(&value), 41, false));
+        let PacketPayload::Delivery { value: delivered, sequence, gap, .. } = Packet::deserialize(&mut cursor).await.unwrap().into_payload() else {
+            panic!("Wrong packet type.");
+        };
+        assert_eq!((delivered, sequence, gap), (None, 42, true));
+        let PacketPayload::AckDelivery { sequence, .. } = Packet::deserialize(&mut cursor).await.unwrap().into_payload() else {
+            panic!("Wrong packet type.");
+        };
+        assert_eq!(sequence, 42);
+
+        let packet = Packet::new(PacketId::zero(), PacketPayload::AckDelivery { key: Cow::Borrowed(&key), sequence: 1 });
+        assert!(packet.downgrade(20).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn write_error_packet() {
+        let error = NetworkError::from(StorageError::PageOutOfBounds);
+        let mut buffer = vec![];
+        Packet::error(PacketId::new(9, 0), &error).serialize(&mut buffer).await.unwrap();
+
+        let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+        assert_eq!(decoded.id(), PacketId::new(9, 0));
+        let PacketPayload::Error { code, message } = decoded.into_payload() else {
+            panic!("Wrong packet type.");
+        };
+        assert_eq!((code, code.class(), message.as_ref()), (ErrorCode(202), ErrorClass::Storage, "Page was out of bounds"));
+        assert!(matches!(NetworkError::from_code(code, &message), NetworkError::Storage(StorageError::PageOutOfBounds)));
+
+        // Errors that carry more than their code keep the message.
+        let conflict = NetworkError::VersionConflict(4);
+        assert_eq!(conflict.code().class(), ErrorClass::Request);
+        assert!(matches!(
+            NetworkError::from_code(conflict.code(), &conflict.to_string()),
+            NetworkError::Client(ClientError::Server(ErrorCode(302), message)) if message == conflict.to_string()
+        ));
+        assert!(Packet::error(PacketId::zero(), &error).downgrade(21).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn write_import_packets() {
+        let records = vec![
+            (Key::from_str("seed.a"), Value::Integer(1)),
+            (Key::from_str("seed.b"), Value::String("b".into()))
+        ];
+        let packets = [
+            Packet::new(PacketId::new(3, 0), PacketPayload::Import { records: records.clone() }),
+            Packet::new(PacketId::new(3, 0), PacketPayload::Imported { count: 2 })
+        ];
+        for packet in packets {
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            match Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().into_payload() {
+                PacketPayload::Import { records: decoded } => assert_eq!(decoded, records),
+                PacketPayload::Imported { count } => assert_eq!(count, 2),
+                _ => panic!("Wrong packet type.")
+            }
+            assert!(packet.downgrade(22).unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_multi_insert_packets() {
+        let entries = vec![
+            (Key::from_str("app.host"), Value::String("example.com".into())),
+            (Key::from_str("app.port"), Value::Integer(443))
+        ];
+        let packets = [
+            Packet::new(PacketId::new(3, 0), PacketPayload::MultiInsert { entries: entries.clone() }),
+            Packet::new(PacketId::new(3, 0), PacketPayload::MultiInserted { versions: vec![7, 8] })
+        ];
+        for packet in packets {
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            match Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().into_payload() {
+                PacketPayload::MultiInsert { entries: decoded } => assert_eq!(decoded, entries),
+                PacketPayload::MultiInserted { versions } => assert_eq!(versions, [7, 8]),
+                _ => panic!("Wrong packet type.")
+            }
+            assert!(packet.downgrade(32).unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_delete_if_packets() {
+        let key = Key::from_str("jobs.lease");
+        let conditions = [DeleteCondition::Value(Value::String("worker-1".into())), DeleteCondition::Version(12)];
+        for condition in conditions {
+            let packet = Packet::new(PacketId::new(3, 0), PacketPayload::delete_if(&key, condition.clone()));
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+            assert!(matches!(decoded.payload(), PacketPayload::DeleteIf { key: k, condition: c } if **k == key && *c == condition));
+            assert!(packet.downgrade(33).unwrap().is_none());
+        }
+
+        let packet = Packet::new(PacketId::new(3, 0), PacketPayload::DeleteIfResult { deleted: true });
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+        assert!(matches!(decoded.payload(), PacketPayload::DeleteIfResult { deleted: true }));
+    }
+
+    #[tokio::test]
+    pub async fn write_find_keys_packet() {
+        let cursor = Key::from_str("app.a");
+        for filter in [KeyFilter::glob("app.*.port"), KeyFilter::regex("port$")] {
+            let packet = Packet::new(PacketId::new(3, 0), PacketPayload::find_keys(filter.clone(), Some(&cursor), 50));
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+            assert!(matches!(decoded.payload(), PacketPayload::FindKeys { filter: f, cursor: Some(c), limit: 50 } if *f == filter && **c == cursor));
+            assert!(packet.to_owned().downgrade(34).unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_stats_packets() {
+        let stats = DatabaseStats {
+            keys: 3,
+            value_bytes: 120,
+            prefixes: BTreeMap::from([("app".to_string(), 2), ("flag".to_string(), 1)]),
+            storage_bytes: 4096,
+            dirty: 2,
+            flushes: 7,
+            flush_time: Duration::from_micros(1500)
+        };
+        for payload in [PacketPayload::GetStats, PacketPayload::Stats { stats: stats.clone() }] {
+            let packet = Packet::new(PacketId::new(3, 0), payload);
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            match Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+                PacketPayload::GetStats => {}
+                PacketPayload::Stats { stats: decoded } => assert_eq!(*decoded, stats),
+                payload => panic!("Expected a stats packet, got {payload:?}.")
+            }
+            assert!(packet.downgrade(35).unwrap().is_none());
+        }
+    }
 
+    #[tokio::test]
+    pub async fn write_sequence_packets() {
+        for payload in [PacketPayload::NextSequence { name: Cow::Borrowed("jobs") }, PacketPayload::Sequence { value: 65 }] {
+            let packet = Packet::new(PacketId::new(3, 0), payload);
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            match Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+                PacketPayload::NextSequence { name } => assert_eq!(name, "jobs"),
+                PacketPayload::Sequence { value } => assert_eq!(*value, 65),
+                payload => panic!("Expected a sequence packet, got {payload:?}.")
+            }
+            assert!(packet.downgrade(36).unwrap().is_none());
+        }
+    }
 
-// pub(crate) async fn read_key<R>(socket: &mut R) -> Result<Key, NetworkError>
-// where 
-//     R: LocalReadAsync
-// {
-    
-// }
+    #[tokio::test]
+    pub async fn write_get_at_packets() {
+        let key = Key::from_str("app.mode");
+        let payloads = [
+            PacketPayload::GetAt { key: Cow::Borrowed(&key), at: Revision::Timestamp(1_700_000_000_000) },
+            PacketPayload::ValueAt { value: Some(Value::String("dark".into())), available: true },
+            PacketPayload::ValueAt { value: None, available: false }
+        ];
+        for payload in payloads {
+            let packet = Packet::new(PacketId::new(3, 0), payload);
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            match (packet.payload(), Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload()) {
+                (PacketPayload::GetAt { .. }, PacketPayload::GetAt { key: decoded, at }) => {
+                    assert_eq!(**decoded, key);
+                    assert_eq!(*at, Revision::Timestamp(1_700_000_000_000));
+                }
+                (PacketPayload::ValueAt { value, available }, PacketPayload::ValueAt { value: decoded, available: decoded_available }) => {
+                    assert_eq!((value, available), (decoded, decoded_available));
+                }
+                (_, payload) => panic!("Expected a get at packet, got {payload:?}.")
+            }
+            assert!(packet.downgrade(37).unwrap().is_none());
+        }
+    }
 
-#[allow(async_fn_in_trait)]
-pub trait OverseerSerde<O: Sized>: Sized {
-    type E;
-    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E>;
-    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<O, Self::E>;
-}
+    #[tokio::test]
+    pub async fn write_export_packets() {
+        let records = vec![
+            ExportedRecord { key: Key::from_str("app.mode"), value: Value::String("dark".into()), meta: None },
+            ExportedRecord {
+                key: Key::from_str("app.size"),
+                value: Value::Integer(3),
+                meta: Some(RecordMeta { created_at: 1, modified_at: 2, accessed_at: 3, writer: None })
+            }
+        ];
+        let cursor = Key::from_str("app.mode");
+        let packets = [
+            Packet::new(PacketId::new(3, 0), PacketPayload::export(Some(&cursor), 64)),
+            Packet::new(PacketId::new(3, 0), PacketPayload::ExportPage { records: records.clone(), cursor: Some(Key::from_str("app.size")) })
+        ];
+        for packet in packets {
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            match Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().into_payload() {
+                PacketPayload::Export { cursor: decoded, limit } => assert_eq!((decoded.as_deref(), limit), (Some(&cursor), 64)),
+                PacketPayload::ExportPage { records: decoded, cursor } => {
+                    assert_eq!(decoded, records);
+                    assert_eq!(cursor, Some(Key::from_str("app.size")));
+                }
+                _ => panic!("Wrong packet type.")
+            }
+            assert!(packet.downgrade(23).unwrap().is_none());
+        }
+    }
 
-impl OverseerSerde<bool> for bool {
-    type E = std::io::Error;
-    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<bool, Self::E> {
-        let b = reader.read_u8().await? ;
-        // println!("Acked: {b}");
-        Ok(match b {
-            0 => false,
-            1 => true,
-            _ => Err(std::io::Error::new(ErrorKind::InvalidData, "Could not decode boolean."))?,
-        })
+    #[tokio::test]
+    pub async fn write_children_packets() {
+        let (key, cursor) = (Key::from_str("app"), Key::from_str("app.db"));
+        let children = vec![
+            KeyChild { key: Key::from_str("app.kafka"), has_value: false, has_children: true },
+            KeyChild { key: Key::from_str("app.mode"), has_value: true, has_children: false }
+        ];
+        let packets = [
+            Packet::new(PacketId::new(3, 0), PacketPayload::list_children(&key, Some(&cursor), 16)),
+            Packet::new(PacketId::new(3, 0), PacketPayload::Children { children: children.clone(), cursor: None })
+        ];
+        for packet in packets {
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            match Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().into_payload() {
+                PacketPayload::ListChildren { key: decoded, cursor: after, limit } => {
+                    assert_eq!((&*decoded, after.as_deref(), limit), (&key, Some(&cursor), 16));
+                }
+                PacketPayload::Children { children: decoded, cursor } => assert_eq!((decoded, cursor), (children.clone(), None)),
+                _ => panic!("Wrong packet type.")
+            }
+            assert!(packet.downgrade(24).unwrap().is_none());
+        }
     }
-    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
-        if *self {
-            writer.write_all(vec![  1 ]).await?;
-        } else {
-            writer.write_all(vec![ 0 ]).await?;
+
+    #[tokio::test]
+    pub async fn write_watcher_packets() {
+        let key = Key::from_str("app.mode");
+        let watched = vec![WatchedKey { key: key.clone(), clients: 3 }, WatchedKey { key: Key::from_str("app.size"), clients: 1 }];
+        let packets = [
+            Packet::new(PacketId::new(3, 0), PacketPayload::ListWatchers { key: Some(Cow::Borrowed(&key)) }),
+            Packet::new(PacketId::new(3, 0), PacketPayload::ListWatchers { key: None }),
+            Packet::new(PacketId::new(3, 0), PacketPayload::WatcherReport { watched: watched.clone() })
+        ];
+        for packet in packets {
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            match (packet.payload(), Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().into_payload()) {
+                (PacketPayload::ListWatchers { key: sent }, PacketPayload::ListWatchers { key: decoded }) => assert_eq!(sent, &decoded),
+                (_, PacketPayload::WatcherReport { watched: decoded }) => assert_eq!(decoded, watched),
+                _ => panic!("Wrong packet type.")
+            }
+            assert!(packet.downgrade(25).unwrap().is_none());
         }
-        Ok(())
     }
-}
 
+    #[tokio::test]
+    pub async fn write_rename_packet() {
+        let (from, to) = (Key::from_str("app.mode"), Key::from_str("app.theme"));
+        let packet = Packet::new(PacketId::new(3, 0), PacketPayload::rename(&from, &to, true));
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        if let PacketPayload::Rename { from: a, to: b, overwrite } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!((&**a, &**b, *overwrite), (&from, &to, true));
+        } else {
+            panic!("Wrong packet type.");
+        }
+        assert!(packet.downgrade(26).unwrap().is_none());
+    }
 
-impl OverseerSerde<Value> for Value {
-    type E = NetworkError;
-    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Value, Self::E> {
-        let type_discrim = reader.read_u8().await?;
-        match type_discrim {
-            0 => Ok(Value::String(<&str>::deserialize(reader).await?)),
-            1 => decode_value_signed_integer(reader).await,
-            x => Err(NetworkError::UnrecognizedValueTypeDiscriminator(x)),
+    #[tokio::test]
+    pub async fn write_snapshot_get_packets() {
+        let keys = vec![Key::from_str("app.mode"), Key::from_str("app.size")];
+        let values = vec![(Some(Value::String("dark".into())), 4), (None, 0)];
+        let packets = [
+            Packet::new(PacketId::new(3, 0), PacketPayload::snapshot_get(&keys)),
+            Packet::new(PacketId::new(3, 0), PacketPayload::SnapshotValues { values: values.clone() })
+        ];
+        for packet in packets {
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            match Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().into_payload() {
+                PacketPayload::SnapshotGet { keys: decoded } => assert_eq!(decoded, keys),
+                PacketPayload::SnapshotValues { values: decoded } => assert_eq!(decoded, values),
+                _ => panic!("Wrong packet type.")
+            }
+            assert!(packet.downgrade(27).unwrap().is_none());
         }
     }
-    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
-        match &*self {
-            Value::String(s) => write_value_string(&*s, writer).await,
-            Value::Integer(s) => write_value_signed_integer(*s, writer).await,
+
+    #[tokio::test]
+    pub async fn write_debounced_watch_packets() {
+        let key = Key::from_str("app.cursor");
+        let interval = Some(Duration::from_millis(250));
+        let packets = [
+            Packet::new(PacketId::new(3, 0), PacketPayload::watch(&key, WatcherActivity::Lazy, WatcherBehaviour::Eager).with_min_interval(interval)),
+            Packet::new(PacketId::new(3, 0), PacketPayload::watch_snapshot(&key, WatcherBehaviour::Ordered).with_min_interval(interval))
+        ];
+        for packet in packets {
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            let mut frame = vec![];
+            for decoded in [
+                Packet::deserialize(&mut Cursor::new(buffer.clone())).await.unwrap(),
+                Packet::deserialize_framed(&mut Cursor::new(buffer.clone()), &mut frame, DEFAULT_MAX_PACKET_SIZE).await.unwrap().to_owned()
+            ] {
+                match decoded.payload() {
+                    PacketPayload::Watch { min_interval, .. } | PacketPayload::WatchSnapshot { min_interval, .. } => assert_eq!(*min_interval, interval),
+                    _ => panic!("Wrong packet type.")
+                }
+            }
+
+            // Older peers are sent the watch without the interval.
+            let mut buffer = vec![];
+            packet.downgrade(30).unwrap().unwrap().serialize(&mut buffer).await.unwrap();
+            match Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+                PacketPayload::Watch { min_interval, .. } | PacketPayload::WatchSnapshot { min_interval, .. } => assert_eq!(*min_interval, None),
+                _ => panic!("Wrong packet type.")
+            }
         }
+        let zero = PacketPayload::watch(&key, WatcherActivity::Lazy, WatcherBehaviour::Eager).with_min_interval(Some(Duration::ZERO));
+        assert!(matches!(zero, PacketPayload::Watch { min_interval: None, .. }));
     }
-}
 
-impl<'a, J, O> OverseerSerde<Option<O>> for Option<&'a J>
-where 
-    J: OverseerSerde<O>,
-    O: Sized,
-    <J as OverseerSerde<O>>::E: From<std::io::Error>
-{
-    type E = J::E;
-    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Option<O>, Self::E> {
-        let flag = reader.read_u8().await?;
-        if flag == 0 {
-            Ok(None)
-        } else if flag == 1 {
-            Ok(Some(J::deserialize(reader).await?))
-        } else {
-            Err(std::io::Error::new(ErrorKind::InvalidData, "Failed decoding option"))?
+    #[tokio::test]
+    pub async fn write_heartbeat_packet() {
+        let key = Key::from_str("app.cursor");
+        let packet = Packet::new(PacketId::zero(), PacketPayload::heartbeat(&key));
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        let mut frame = vec![];
+        for decoded in [
+            Packet::deserialize(&mut Cursor::new(buffer.clone())).await.unwrap(),
+            Packet::deserialize_framed(&mut Cursor::new(buffer.clone()), &mut frame, DEFAULT_MAX_PACKET_SIZE).await.unwrap().to_owned()
+        ] {
+            assert!(matches!(decoded.payload(), PacketPayload::Notify { value: None, heartbeat: true, .. }));
         }
+        let notify = Packet::new(PacketId::zero(), PacketPayload::notify(&key, None, false));
+        let mut buffer = vec![];
+        notify.serialize(&mut buffer).await.unwrap();
+        assert!(matches!(Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload(), PacketPayload::Notify { heartbeat: false, .. }));
+
+        // Older peers would read a heartbeat as the key being deleted.
+        assert!(packet.downgrade(31).unwrap().is_none());
+        assert!(notify.downgrade(31).unwrap().is_some());
     }
-    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
-        match self {
-            None => writer.write_u8(0).await?,
-            Some(i) => {
-                writer.write_u8(1).await?;
-                i.serialize(writer).await?;
+
+    #[tokio::test]
+    pub async fn write_schema_packets() {
+        let schema = Schema::integer(Some(-5), None);
+        let schemas = vec![("app.port".to_string(), Schema::integer(Some(1), Some(65535))), ("app.user".to_string(), Schema::json("{}"))];
+        let packets = [
+            Packet::new(PacketId::new(3, 0), PacketPayload::register_schema("app.", Some(Schema::string("^[a-z]+$")))),
+            Packet::new(PacketId::new(3, 0), PacketPayload::SchemaRegistered { previous: Some(schema.clone()) }),
+            Packet::new(PacketId::new(3, 0), PacketPayload::ListSchemas),
+            Packet::new(PacketId::new(3, 0), PacketPayload::Schemas { schemas: schemas.clone() })
+        ];
+        for packet in packets {
+            let mut buffer = vec![];
+            packet.serialize(&mut buffer).await.unwrap();
+            match Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().into_payload() {
+                PacketPayload::RegisterSchema { prefix, schema } => assert_eq!((&*prefix, schema), ("app.", Some(Schema::string("^[a-z]+$")))),
+                PacketPayload::SchemaRegistered { previous } => assert_eq!(previous, Some(schema.clone())),
+                PacketPayload::ListSchemas => {}
+                PacketPayload::Schemas { schemas: decoded } => assert_eq!(decoded, schemas),
+                _ => panic!("Wrong packet type.")
             }
+            assert!(packet.downgrade(29).unwrap().is_none());
         }
-        Ok(())
     }
-    
-}
 
-impl<'a> OverseerSerde<String> for &'a str {
-    type E = NetworkError;
-    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
-        OvrInteger::write(self.len(), writer).await?;
-        writer.write_all(self.as_bytes().to_vec()).await?;
-        Ok(())
+    #[tokio::test]
+    pub async fn write_traced_packets() {
+        let key = Key::from_str("app.mode");
+        let packet = Packet::new(PacketId::new(5, 0), PacketPayload::get(&key)).with_namespace("tenant-a").with_trace(Some(u64::MAX - 3));
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        let decoded = Packet::deserialize(&mut Cursor::new(buffer.clone())).await.unwrap();
+        assert_eq!((decoded.trace(), decoded.namespace()), (Some(u64::MAX - 3), "tenant-a"));
+        let mut frame = vec![];
+        let decoded = Packet::deserialize_framed(&mut Cursor::new(buffer), &mut frame, DEFAULT_MAX_PACKET_SIZE).await.unwrap();
+        assert_eq!(decoded.trace(), Some(u64::MAX - 3));
+
+        // Zero is no trace, and older peers are sent none.
+        assert_eq!(Packet::new(PacketId::new(5, 0), PacketPayload::get(&key)).with_trace(Some(0)).trace(), None);
+        let older = packet.downgrade(28).unwrap().unwrap();
+        assert_eq!(older.trace(), None);
+        let mut buffer = vec![];
+        older.serialize(&mut buffer).await.unwrap();
+        let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+        assert_eq!((decoded.version(), decoded.trace()), (28, None));
+        assert!(matches!(decoded.payload(), PacketPayload::Get { .. }));
     }
-    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<String, Self::E> {
-        // Figure out the size of the string.
-        let string_length: u64 = OvrInteger::read(reader).await?;
 
-        if string_length == 0 {
-            return Ok(String::default());
+    #[tokio::test]
+    pub async fn write_session_packets() {
+        let packets = [
+            PacketPayload::Resume { session: Some(u64::MAX - 7) },
+            PacketPayload::Resume { session: None },
+            PacketPayload::Session { id: u64::MAX - 7, resumed: true }
+        ];
+        for payload in packets {
+            let name = payload.name();
+            let mut buffer = vec![];
+            Packet::new(PacketId::new(4, 0), payload).serialize(&mut buffer).await.unwrap();
+            let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+            assert_eq!(decoded.payload().name(), name);
+            match decoded.payload() {
+                PacketPayload::Resume { session } => assert!(session.is_none() || *session == Some(u64::MAX - 7)),
+                PacketPayload::Session { id, resumed } => assert_eq!((*id, *resumed), (u64::MAX - 7, true)),
+                _ => panic!("Wrong packet type.")
+            }
         }
+        let packet = Packet::session(PacketId::zero(), 9, false);
+        assert!(packet.downgrade(12).unwrap().is_none());
+    }
 
-        let str_buf = vec![0u8; string_length as usize];
-        let (str_buf, _) = reader.read_exact(str_buf).await?;
+    #[tokio::test]
+    pub async fn write_queue_packets() {
+        let queue = Key::from_str("jobs");
+        let item = LeasedItem { key: Key::from_str("jobs.00000000000000000004"), value: Value::Integer(7), lease: 12 };
+        let packets = [
+            PacketPayload::enqueue(&queue, &item.value),
+            PacketPayload::dequeue(&queue, Duration::from_secs(30)),
+            PacketPayload::Leased { item: Some(item.clone()) },
+            PacketPayload::Leased { item: None },
+            PacketPayload::ack(&item.key, item.lease),
+            PacketPayload::nack(&item.key, item.lease),
+            PacketPayload::LeaseResult { accepted: true }
+        ];
+        for payload in packets {
+            let name = payload.name();
+            let mut buffer = vec![];
+            Packet::new(PacketId::new(4, 0), payload).serialize(&mut buffer).await.unwrap();
+            let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+            assert_eq!(decoded.payload().name(), name);
+            match decoded.payload() {
+                PacketPayload::Enqueue { queue: decoded, value } => assert_eq!((&**decoded, &**value), (&queue, &item.value)),
+                PacketPayload::Dequeue { queue: decoded, visibility } => assert_eq!((&**decoded, *visibility), (&queue, Duration::from_secs(30))),
+                PacketPayload::Leased { item: decoded } => assert!(decoded.is_none() || decoded.as_ref() == Some(&item)),
+                PacketPayload::Ack { key, lease } | PacketPayload::Nack { key, lease } => assert_eq!((&**key, *lease), (&item.key, item.lease)),
+                PacketPayload::LeaseResult { accepted } => assert!(*accepted),
+                _ => panic!("Wrong packet type.")
+            }
+        }
 
-        Ok(
-            String::from_utf8(str_buf).map_err(|_| NetworkError::FailedToReadValue)?,
-        )
+        // Clients before version 7 know nothing of queues.
+        let packet = Packet::new(PacketId::zero(), PacketPayload::LeaseResult { accepted: true });
+        assert!(packet.downgrade(6).unwrap().is_none());
     }
-}
 
-impl OverseerSerde<Key> for Key {
-    type E = NetworkError;
-    async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), Self::E> {
-        self.as_str().serialize(writer).await?;
-        Ok(())
-    }
-    async fn deserialize<R: LocalReadAsync>(reader: &mut R) -> Result<Self, Self::E> {
-        Ok(Key::from_owned(<&str>::deserialize(reader).await?))
+    #[tokio::test]
+    pub async fn write_topic_packets() {
+        let topic = Key::from_str("deploy.started");
+        let value = Value::String("api".into());
+        let packets = [
+            PacketPayload::publish(&topic, &value),
+            PacketPayload::Published { receivers: 3 },
+            PacketPayload::subscribe_topic(&topic),
+            PacketPayload::unsubscribe_topic(&topic),
+            PacketPayload::topic_message(&topic, &value)
+        ];
+        for payload in packets {
+            let name = payload.name();
+            let mut buffer = vec![];
+            Packet::new(PacketId::new(4, 0), payload).serialize(&mut buffer).await.unwrap();
+            let decoded = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+            assert_eq!(decoded.payload().name(), name);
+            match decoded.payload() {
+                PacketPayload::Publish { topic: decoded, value: decoded_value }
+                | PacketPayload::TopicMessage { topic: decoded, value: decoded_value } => {
+                    assert_eq!(**decoded, topic);
+                    assert_eq!(**decoded_value, value);
+                }
+                PacketPayload::Published { receivers } => assert_eq!(*receivers, 3),
+                PacketPayload::SubscribeTopic { topic: decoded } | PacketPayload::UnsubscribeTopic { topic: decoded } => assert_eq!(**decoded, topic),
+                _ => panic!("Wrong packet type.")
+            }
+        }
+
+        // Clients before version 5 know nothing of topics.
+        let packet = Packet::new(PacketId::zero(), PacketPayload::topic_message(&topic, &value));
+        assert!(packet.downgrade(4).unwrap().is_none());
     }
-}
 
-// #[async_trait::async_trait]
-// impl OverseerSerde for Key {
-//     type E = NetworkError;
-//     async fn serialize<W: LocalWriteAsync>(&self, writer: &mut W) -> Result<(), E> {
-//         if let Value::String(inner) = decode_value_string(socket).await? {
-//             Ok(Key::from_str(&inner ))
-//         } else {
-//             Err(NetworkError::FailedToReadKey)
-//         }
-//     }
-//     async fn deserialize<W: LocalReadAsync>(writer: &mut W) -> std::io::Result<Key> {
-//         if let Value::String(inner) = decode_value_string(socket).await? {
-//             Ok(Key::from_str(&inner ))
-//         } else {
-//             Err(NetworkError::FailedToReadKey)
-//         }
-//     }
-// }
+    #[tokio::test]
+    pub async fn write_compressed_value() {
+        let key = Key::from_str("app.config");
+        let config = (0..100).map(|i| format!(r#"{{"service":"api-{i}","region":"eu-west-1"}}"#)).collect::<Vec<_>>().join(",");
+        let value = Value::String(config.clone().into());
+
+        let mut buffer = vec![];
+        Packet::vreturn(PacketId::new(4, 0), &key, Some(&value), 0).serialize(&mut buffer).await.unwrap();
+        assert!(buffer.len() * 2 < config.len());
+        if let PacketPayload::Return { value: decoded, .. } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(decoded.as_deref(), Some(&value));
+        } else {
+            panic!("Wrong packet type.");
+        }
 
-#[cfg(test)]
-mod tests {
-    use std::io::{Cursor, Read, Write};
+        // Older peers get the string as it is.
+        let packet = Packet::vreturn(PacketId::new(4, 0), &key, Some(&value), 0).downgrade(3).unwrap().unwrap();
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        assert!(buffer.len() > config.len());
+        if let PacketPayload::Return { value: decoded, .. } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
+            assert_eq!(decoded.as_deref(), Some(&value));
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
 
-  
+    #[tokio::test]
+    pub async fn read_inflated_packet() {
+        // Each value compresses to a sliver of what it decompresses to.
+        let archived = |count: usize| Packet::new(PacketId::new(4, 0), PacketPayload::Archived {
+            records: (0..count).map(|i| (Key::from_str(&format!("app.{i}")), Value::String("a".repeat(100_000).into()))).collect()
+        });
+        let max_size = 64 * 1024;
+
+        let mut buffer = vec![];
+        archived(2).serialize(&mut buffer).await.unwrap();
+        assert!(buffer.len() < 4096);
+        let mut frame = vec![];
+        assert!(Packet::deserialize_framed(&mut Cursor::new(buffer), &mut frame, max_size).await.is_ok());
+
+        let mut buffer = vec![];
+        archived(3).serialize(&mut buffer).await.unwrap();
+        assert!(matches!(
+            Packet::deserialize_framed(&mut Cursor::new(buffer), &mut frame, max_size).await,
+            Err(NetworkError::Protocol(ProtocolError::InflatedTooLarge(limit))) if limit == max_size * MAX_INFLATION
+        ));
+    }
 
-    use crate::{
-        access::{WatcherActivity, WatcherBehaviour},
-        models::{Key, LocalWriteAsync, Value},
-        network::{OverseerSerde, OvrInteger, PacketId, PacketPayload},
-    };
+    #[tokio::test]
+    pub async fn downgrade_packet() {
+        let key = Key::from_str("hello");
+        let value = Value::Integer(3);
 
-    use super::Packet;
+        let packet = Packet::notify(PacketId::zero(), &key, Some(&value), true).downgrade(0).unwrap().unwrap();
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        assert_eq!(buffer[0], 0);
 
-    // use crate::net::{driver::read_packet, Driver};
+        let packet = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap();
+        assert_eq!(packet.version(), 0);
+        assert!(matches!(packet.payload(), PacketPayload::Notify { more: false, .. }));
+
+        // Older clients do not know about invalidation.
+        let packet = Packet::new(PacketId::zero(), PacketPayload::invalidate("hel"));
+        assert!(packet.downgrade(0).unwrap().is_none());
+    }
 
     #[tokio::test]
     pub async fn read_bool_test() {
         let mut cursor = Cursor::new(vec![0, 1]);
         assert_eq!(bool::deserialize(&mut cursor).await.unwrap(), false);
-        println!("REad...");
         assert_eq!(bool::deserialize(&mut cursor).await.unwrap(), true);
     }
 
@@ -525,7 +3189,7 @@ mod tests {
         packet.serialize(&mut cursor).await.unwrap();
         cursor.set_position(0);
 
-        if let PacketPayload::Notify { key, value, more } = Packet::deserialize(&mut cursor).await.unwrap().payload() {
+        if let PacketPayload::Notify { key, value, more, .. } = Packet::deserialize(&mut cursor).await.unwrap().payload() {
             assert_eq!(key.as_str(), "hello");
             assert!(value.is_none());
             assert!(!more);
@@ -586,6 +3250,7 @@ mod tests {
             key,
             activity,
             behaviour,
+            ..
         } = Packet::deserialize(&mut cursor).await.unwrap().payload()
         {
             assert_eq!(key.as_str(), "hello");
@@ -600,7 +3265,7 @@ mod tests {
     pub async fn write_insert_string_packet() {
 
         let key = Key::from_str("hello");
-        let value = Value::String("hello world".to_string());
+        let value = Value::String("hello world".into());
         let packet = Packet::new(PacketId::zero(), PacketPayload::insert(&key, &value));
 
         // Write the packet.
@@ -652,6 +3317,28 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    pub async fn write_auth_packet() {
+        let packet = Packet::auth(PacketId::zero(), "secret");
+
+        // Write the packet.
+        let mut cursor = Cursor::new(vec![]);
+        packet.serialize(&mut cursor).await.unwrap();
+        Packet::auth_result(PacketId::zero(), true).serialize(&mut cursor).await.unwrap();
+        cursor.set_position(0);
+
+        if let PacketPayload::Auth { token } = Packet::deserialize(&mut cursor).await.unwrap().payload() {
+            assert_eq!(token, "secret");
+        } else {
+            panic!("Wrong packet type.");
+        }
+        if let PacketPayload::AuthResult { accepted } = Packet::deserialize(&mut cursor).await.unwrap().payload() {
+            assert!(accepted);
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
+
     #[tokio::test]
     pub async fn read_release_packet() {
         let skey = "hello";
@@ -682,7 +3369,7 @@ mod tests {
         LocalWriteAsync::write_all(&mut buffer, vec![1]).await.unwrap();
         // buffer.extend_from_slice(&vec![1, 1].into_iter().chain(Ov).chain(vec![1]).collect::<Vec<u8>>());
 
-        if let PacketPayload::Notify { key, value, more } =
+        if let PacketPayload::Notify { key, value, more, .. } =
             Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload()
         {
             assert_eq!(**key, Key::from_str(skey));
@@ -707,6 +3394,7 @@ mod tests {
             key,
             activity,
             behaviour,
+            ..
         } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload()
         {
             assert_eq!(**key, Key::from_str(skey));
@@ -724,8 +3412,6 @@ mod tests {
         OvrInteger::write(skey.as_bytes().len(), &mut buffer).await.unwrap();
         buffer.extend_from_slice(skey.as_bytes());
 
-        println!("Hello");
-
         if let PacketPayload::Delete { key } = Packet::deserialize(&mut Cursor::new(buffer)).await.unwrap().payload() {
             assert_eq!(**key, Key::from_str(skey));
         } else {
@@ -789,4 +3475,182 @@ mod tests {
             panic!("Packet did not decode as the proper type.");
         }
     }
+
+    #[tokio::test]
+    pub async fn write_manifest_report_packet() {
+        let drift = vec![
+            KeyDrift { key: Key::from_str("pool.size"), expected: ValueType::Integer, found: None },
+            KeyDrift { key: Key::from_str("db.url"), expected: ValueType::String, found: Some(ValueType::Integer) }
+        ];
+        let packet = Packet::manifest_report(PacketId::zero(), drift.clone());
+
+        // Write the packet.
+        let mut cursor = Cursor::new(vec![]);
+        packet.serialize(&mut cursor).await.unwrap();
+        cursor.set_position(0);
+
+        if let PacketPayload::ManifestReport { drift: decoded } = Packet::deserialize(&mut cursor).await.unwrap().payload() {
+            assert_eq!(*decoded, drift);
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_snapshot_packet() {
+        let key = Key::from_str("hello");
+        let value = Value::Integer(3);
+
+        // Write the packets.
+        let mut cursor = Cursor::new(vec![]);
+        Packet::new(PacketId::zero(), PacketPayload::watch_snapshot(&key, WatcherBehaviour::Ordered)).serialize(&mut cursor).await.unwrap();
+        Packet::snapshot(PacketId::zero(), &key, Some(&value), 300).serialize(&mut cursor).await.unwrap();
+        cursor.set_position(0);
+
+        if let PacketPayload::WatchSnapshot { key, behaviour, .. } = Packet::deserialize(&mut cursor).await.unwrap().payload() {
+            assert_eq!(key.as_str(), "hello");
+            assert_eq!(*behaviour, WatcherBehaviour::Ordered);
+        } else {
+            panic!("Wrong packet type.");
+        }
+        if let PacketPayload::Snapshot { key, value, version } = Packet::deserialize(&mut cursor).await.unwrap().payload() {
+            assert_eq!(key.as_str(), "hello");
+            assert_eq!(value.as_deref(), Some(&Value::Integer(3)));
+            assert_eq!(*version, 300);
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_compaction_report_packet() {
+        let runs = vec![CompactionRun {
+            finished_at: 1_700_000_000_000,
+            duration: Duration::from_micros(1500),
+            bytes_reclaimed: 8192,
+            pages_moved: 3
+        }];
+        let packet = Packet::new(PacketId::zero(), PacketPayload::CompactionReport { runs: runs.clone() });
+
+        // Write the packet.
+        let mut cursor = Cursor::new(vec![]);
+        packet.serialize(&mut cursor).await.unwrap();
+        cursor.set_position(0);
+
+        if let PacketPayload::CompactionReport { runs: decoded } = Packet::deserialize(&mut cursor).await.unwrap().payload() {
+            assert_eq!(*decoded, runs);
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
+
+    #[tokio::test]
+    pub async fn write_invalidate_packet() {
+        let packet = Packet::new(PacketId::zero(), PacketPayload::invalidate("config.kafka."));
+
+        // Write the packet.
+        let mut cursor = Cursor::new(vec![]);
+        packet.serialize(&mut cursor).await.unwrap();
+        cursor.set_position(0);
+
+        if let PacketPayload::Invalidate { prefix } = Packet::deserialize(&mut cursor).await.unwrap().payload() {
+            assert_eq!(prefix, "config.kafka.");
+        } else {
+            panic!("Wrong packet type.");
+        }
+    }
+
+    #[derive(OverseerSerde, Debug, PartialEq)]
+    struct Heartbeat {
+        node: Key,
+        term: u64,
+        leader: Option<Value>,
+        peers: Vec<Key>,
+        zone: String
+    }
+
+    #[derive(OverseerSerde, Debug, PartialEq)]
+    #[repr(u8)]
+    enum Command {
+        Stop,
+        Resize(u32, u32),
+        Beat { heartbeat: Heartbeat },
+        Reset = 9
+    }
+
+    #[tokio::test]
+    pub async fn derive_overseer_serde() {
+        let heartbeat = Heartbeat {
+            node: Key::from_str("nodes.a"),
+            term: 300,
+            leader: Some(Value::Integer(2)),
+            peers: vec![Key::from_str("nodes.b"), Key::from_str("nodes.c")],
+            zone: "eu-west".to_string()
+        };
+        let commands = [Command::Stop, Command::Resize(640, 480), Command::Beat { heartbeat }, Command::Reset];
+        let mut cursor = Cursor::new(vec![]);
+        for command in &commands {
+            command.serialize(&mut cursor).await.unwrap();
+        }
+        cursor.set_position(0);
+        for command in commands {
+            assert_eq!(Command::deserialize(&mut cursor).await.unwrap(), command);
+        }
+
+        // The fields go in order, the integers as varints.
+        let mut bytes = vec![];
+        Command::Resize(300, 1).serialize(&mut bytes).await.unwrap();
+        assert_eq!(bytes, [1, 0xac, 0x02, 1]);
+        let mut bytes = vec![];
+        Command::Reset.serialize(&mut bytes).await.unwrap();
+        assert_eq!(bytes, [9]);
+        assert!(matches!(
+            Command::deserialize(&mut Cursor::new(vec![4])).await,
+            Err(NetworkError::Protocol(ProtocolError::UnrecognizedDiscriminator("Command", 4)))
+        ));
+
+        // A derived key encodes like the string it holds.
+        let (mut derived, mut string) = (vec![], vec![]);
+        Key::from_str("app.mode").serialize(&mut derived).await.unwrap();
+        "app.mode".serialize(&mut string).await.unwrap();
+        assert_eq!(derived, string);
+    }
+
+    #[tokio::test]
+    pub async fn test_framed_packets() {
+        let packet = Packet::new(PacketId::new(9, 2), PacketPayload::auth("secret")).with_namespace("tenant-a");
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        // The header, then the varint length of the body.
+        assert_eq!(buffer[9] as usize, buffer.len() - 10);
+
+        let decoded = Packet::deserialize(&mut Cursor::new(buffer.clone())).await.unwrap();
+        assert_eq!(decoded.namespace(), "tenant-a");
+        assert!(matches!(decoded.payload(), PacketPayload::Auth { token } if token == "secret"));
+
+        // Read into a frame, the token is borrowed from it.
+        let mut frame = vec![];
+        let decoded = Packet::deserialize_framed(&mut Cursor::new(buffer), &mut frame, DEFAULT_MAX_PACKET_SIZE).await.unwrap();
+        assert_eq!(decoded.id(), PacketId::new(9, 2));
+        assert_eq!(decoded.namespace(), "tenant-a");
+        assert!(matches!(decoded.payload(), PacketPayload::Auth { token: Cow::Borrowed("secret") }));
+
+        // A frame over the bound is refused before its body is read.
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        let mut frame = vec![];
+        assert!(matches!(
+            Packet::deserialize_framed(&mut Cursor::new(buffer), &mut frame, 4).await,
+            Err(NetworkError::Protocol(ProtocolError::PacketTooLarge(_, 4)))
+        ));
+
+        // Packets from before framing are read as they were.
+        let packet = packet.downgrade(16).unwrap().unwrap();
+        let mut buffer = vec![];
+        packet.serialize(&mut buffer).await.unwrap();
+        let mut frame = vec![];
+        let decoded = Packet::deserialize_framed(&mut Cursor::new(buffer), &mut frame, DEFAULT_MAX_PACKET_SIZE).await.unwrap();
+        assert_eq!(decoded.version(), 16);
+        assert!(matches!(decoded.payload(), PacketPayload::Auth { token } if token == "secret"));
+    }
 }
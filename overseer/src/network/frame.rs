@@ -0,0 +1,192 @@
+use std::{borrow::Cow, io::ErrorKind};
+
+use crate::{error::{NetworkError, ProtocolError}, models::{LocalReadAsync, LocalWriteAsync}};
+
+use super::OvrInteger;
+
+
+/// The first protocol version whose packets carry the length of their
+/// body after the header, so a reader takes each of them in with one read.
+pub const FRAMED_VERSION: u8 = 17;
+
+/// The first protocol version whose frames lead with a varint length
+/// rather than a `u32`.
+pub const VARINT_FRAME_VERSION: u8 = 18;
+
+/// The longest body of a packet that is read unless a reader asks for
+/// another bound, see [read_frame_length].
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 4 * 1024 * 1024;
+
+/// How many times the max size of a packet its values may decompress to
+/// in total, beyond that it fails with [ProtocolError::InflatedTooLarge].
+pub const MAX_INFLATION: usize = 4;
+
+/// Writes the length of a frame the way the version encodes it.
+pub async fn write_frame_length<W: LocalWriteAsync>(version: u8, length: usize, socket: &mut W) -> Result<(), NetworkError> {
+    if version >= VARINT_FRAME_VERSION {
+        OvrInteger::write(length, socket).await?;
+    } else {
+        socket.write_u32(length as u32).await?;
+    }
+    Ok(())
+}
+
+/// Reads the length of a frame, failing before anything is allocated
+/// if it is longer than the most the reader takes.
+pub async fn read_frame_length<R: LocalReadAsync>(version: u8, socket: &mut R, max_size: usize) -> Result<usize, NetworkError> {
+    let length: usize = if version >= VARINT_FRAME_VERSION {
+        OvrInteger::read(socket).await?
+    } else {
+        socket.read_u32().await? as usize
+    };
+    if length > max_size {
+        return Err(NetworkError::Protocol(ProtocolError::PacketTooLarge(length, max_size)));
+    }
+    Ok(length)
+}
+
+/// Reads the body of a packet that was taken in whole, so the strings and
+/// bytes in it are borrowed rather than copied out.
+pub struct FrameReader<'buf> {
+    frame: &'buf [u8],
+    inflation: Inflation
+}
+
+impl<'buf> FrameReader<'buf> {
+    pub fn new(frame: &'buf [u8]) -> Self {
+        Self::bounded(frame, DEFAULT_MAX_PACKET_SIZE)
+    }
+    /// Reads a frame of a packet that was read with the max size, which
+    /// bounds what its values decompress to, see [MAX_INFLATION].
+    pub fn bounded(frame: &'buf [u8], max_size: usize) -> Self {
+        Self { frame, inflation: Inflation::new(max_size) }
+    }
+    /// How many bytes of the frame are left.
+    pub fn remaining(&self) -> usize {
+        self.frame.len()
+    }
+    /// The next bytes of the frame.
+    pub fn read_borrowed(&mut self, length: usize) -> Result<&'buf [u8], NetworkError> {
+        if length > self.frame.len() {
+            return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+        let (read, rest) = self.frame.split_at(length);
+        self.frame = rest;
+        Ok(read)
+    }
+    /// Bytes after their length, encoded like a `&str`.
+    pub async fn read_bytes(&mut self) -> Result<Cow<'buf, [u8]>, NetworkError> {
+        let length = OvrInteger::read_length(self).await?;
+        Ok(Cow::Borrowed(self.read_borrowed(length)?))
+    }
+    /// A string encoded like a `&str`.
+    pub async fn read_str(&mut self) -> Result<Cow<'buf, str>, NetworkError> {
+        let length = OvrInteger::read_length(self).await?;
+        std::str::from_utf8(self.read_borrowed(length)?)
+            .map(Cow::Borrowed)
+            .map_err(|_| NetworkError::Protocol(ProtocolError::FailedToReadValue))
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl LocalReadAsync for FrameReader<'_> {
+    async fn read_exact(&mut self, mut buffer: Vec<u8>) -> std::io::Result<(Vec<u8>, usize)> {
+        let length = buffer.len();
+        let read = self.read_borrowed(length).map_err(|_| std::io::Error::from(ErrorKind::UnexpectedEof))?;
+        buffer.copy_from_slice(read);
+        Ok((buffer, length))
+    }
+    fn inflate(&mut self, length: usize) -> Result<(), ProtocolError> {
+        self.inflation.add(length)
+    }
+}
+
+/// Reads a packet of a version before [FRAMED_VERSION] straight off the
+/// socket, bounding what its values decompress to like a [FrameReader].
+pub(crate) struct UnframedReader<'r, R> {
+    socket: &'r mut R,
+    inflation: Inflation
+}
+
+impl<'r, R> UnframedReader<'r, R> {
+    pub fn new(socket: &'r mut R, max_size: usize) -> Self {
+        Self { socket, inflation: Inflation::new(max_size) }
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl<R: LocalReadAsync> LocalReadAsync for UnframedReader<'_, R> {
+    async fn read_exact(&mut self, buffer: Vec<u8>) -> std::io::Result<(Vec<u8>, usize)> {
+        self.socket.read_exact(buffer).await
+    }
+    fn buffer(&mut self, length: usize) -> Vec<u8> {
+        self.socket.buffer(length)
+    }
+    fn recycle(&mut self, buffer: Vec<u8>) {
+        self.socket.recycle(buffer);
+    }
+    fn inflate(&mut self, length: usize) -> Result<(), ProtocolError> {
+        self.inflation.add(length)
+    }
+}
+
+/// What the values of a packet decompressed to so far.
+struct Inflation {
+    inflated: usize,
+    limit: usize
+}
+
+impl Inflation {
+    fn new(max_size: usize) -> Self {
+        Self { inflated: 0, limit: max_size.saturating_mul(MAX_INFLATION) }
+    }
+    fn add(&mut self, length: usize) -> Result<(), ProtocolError> {
+        self.inflated = self.inflated.saturating_add(length);
+        if self.inflated > self.limit {
+            return Err(ProtocolError::InflatedTooLarge(self.limit));
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::{error::{NetworkError, ProtocolError}, network::OverseerSerde};
+
+    use super::{read_frame_length, write_frame_length, FrameReader, FRAMED_VERSION, VARINT_FRAME_VERSION};
+
+    #[tokio::test]
+    pub async fn test_frame_reader_borrows() {
+        let mut frame = vec![];
+        "tenant-a".serialize(&mut frame).await.unwrap();
+        frame.extend_from_slice(&[7, 8]);
+
+        let mut reader = FrameReader::new(&frame);
+        assert!(matches!(reader.read_str().await.unwrap(), Cow::Borrowed("tenant-a")));
+        assert_eq!(reader.remaining(), 2);
+        assert!(reader.read_borrowed(3).is_err());
+        assert_eq!(reader.read_borrowed(2).unwrap(), &[7, 8]);
+        assert!(reader.read_str().await.is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_frame_length_bound() {
+        let mut length = vec![];
+        write_frame_length(VARINT_FRAME_VERSION, 4096, &mut length).await.unwrap();
+        assert_eq!(length, [0x80, 0x20]);
+        assert_eq!(read_frame_length(VARINT_FRAME_VERSION, &mut length.as_slice(), 4096).await.unwrap(), 4096);
+        assert!(matches!(
+            read_frame_length(VARINT_FRAME_VERSION, &mut length.as_slice(), 4095).await,
+            Err(NetworkError::Protocol(ProtocolError::PacketTooLarge(4096, 4095)))
+        ));
+
+        // The first framed version leads with a `u32`.
+        let mut length = vec![];
+        write_frame_length(FRAMED_VERSION, 4096, &mut length).await.unwrap();
+        assert_eq!(length, 4096u32.to_be_bytes());
+        assert_eq!(read_frame_length(FRAMED_VERSION, &mut length.as_slice(), 4096).await.unwrap(), 4096);
+    }
+}
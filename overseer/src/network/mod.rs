@@ -4,7 +4,14 @@ pub(crate) mod decoder;
 
 mod packet;
 mod varint;
+mod pool;
+mod frame;
+#[cfg(test)]
+mod properties;
 
 pub use crate::network::packet::*;
 pub use crate::network::varint::*;
-pub use crate::network::decoder::OverseerSerde;
+pub use crate::network::pool::*;
+pub use crate::network::frame::*;
+pub use crate::network::decoder::{OverseerSerde, MAX_VALUE_DEPTH};
+pub use overseer_derive::OverseerSerde;
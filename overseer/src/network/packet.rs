@@ -1,19 +1,88 @@
-use std::borrow::{Borrow, Cow};
+use std::{borrow::Cow, time::Duration};
 
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use crate::{access::{IsolationReason, WatcherActivity, WatcherBehaviour}, error::{ErrorCode, NetworkError, ProtocolError}, models::{Acknowledgement, CompactionRun, HistoryEntry, Key, KeyDiff, KeyDrift, KeyChild, KeyMeta, DatabaseStats, DeleteCondition, ExportedRecord, KeyFilter, LeasedItem, Promotion, PromotionReport, RecordMeta, Revision, Schema, Value, WatchedKey}};
 
-use crate::{access::{WatcherActivity, WatcherBehaviour}, error::NetworkError, models::{Key, LocalReadAsync, Value}};
 
 
+/// The protocol version spoken by this build.
+///
+/// Version 1 adds every packet after `Return` and batched notifications,
+/// version 2 adds the namespace to the header and the namespace packets,
+/// version 3 adds map values and the secondary index packets, version 4
+/// compresses large strings and adds [PacketPayload::GetOrInsert], version 5
+/// adds the topic packets, version 6 adds record versions to
+/// [PacketPayload::Return] and [PacketPayload::InsertIfVersion], version 7
+/// adds the work queue packets, version 8 adds [PacketPayload::History],
+/// version 9 adds [PacketPayload::InsertAcknowledged] and version 10 adds
+/// the record metadata to [PacketPayload::Return] and [PacketPayload::KeyMeta]
+/// along with [PacketPayload::Archive], version 11 adds the lock packets,
+/// version 12 adds [PacketPayload::InsertEphemeral], version 13 adds the
+/// session packets, version 14 adds the extension packets, version 15
+/// adds [Value::Json], version 16 adds [PacketPayload::Increment] and
+/// version 17 frames the packets, see [super::FRAMED_VERSION], version 18
+/// encodes the length of the frames as a varint, version 19 adds
+/// [PacketPayload::ServerBusy], version 20 adds [PacketPayload::Throttled]
+/// and version 21 adds the acknowledged delivery packets and version 22
+/// adds [PacketPayload::Error], version 23 adds [PacketPayload::Import],
+/// version 24 adds [PacketPayload::Export], version 25 adds
+/// [PacketPayload::ListChildren], version 26 adds
+/// [PacketPayload::ListWatchers], version 27 adds [PacketPayload::Rename],
+/// version 28 adds [PacketPayload::SnapshotGet], version 29 adds the
+/// trace id to the header, see [Packet::trace], version 30 adds the
+/// schema packets, version 31 adds the minimum interval of the watches,
+/// version 32 adds the heartbeats of the watches, version 33 adds
+/// [PacketPayload::MultiInsert], version 34 adds [PacketPayload::DeleteIf],
+/// version 35 adds [PacketPayload::FindKeys], version 36 adds
+/// [PacketPayload::GetStats], version 37 adds [PacketPayload::NextSequence]
+/// and version 38 adds [PacketPayload::GetAt].
+pub const CURRENT_VERSION: u8 = 38;
+/// The namespace of packets that do not name one, and the only
+/// namespace older clients can reach.
+pub const DEFAULT_NAMESPACE: &str = "default";
+/// The prefix of the topics the server announces the items enqueued on a
+/// queue on, `$sys.queue.jobs` for the queue `jobs`. Only the server
+/// publishes there, see [queue_topic].
+pub const QUEUE_TOPIC_PREFIX: &str = "$sys.queue.";
+/// The oldest protocol version that is still served.
+pub const MIN_VERSION: u8 = 0;
+/// The discriminators from this one up are reserved for protocol extensions
+/// and never assigned upstream. Every packet in the range is framed alike,
+/// so a peer that does not know the extension can still read past it.
+pub const FIRST_EXTENSION_DISCRIMINATOR: u8 = 224;
 
-pub const CURRENT_VERSION: u8 = 0;
+/// The topic the items enqueued on the queue are announced on, see [QUEUE_TOPIC_PREFIX].
+pub fn queue_topic(queue: &Key) -> Key {
+    Key::from_owned(format!("{QUEUE_TOPIC_PREFIX}{}", queue.as_str()))
+}
 
 #[derive(Debug)]
 pub struct Packet<'a> {
+    version: u8,
     id: PacketId,
+    namespace: Cow<'a, str>,
+    trace: Option<u64>,
     payload: PacketPayload<'a>
 }
 
+/// The request id carried by packets the server pushes on its own
+/// (notifications), these never answer a request.
+pub const NOTIFICATION_ID: u32 = 0;
+/// The first request id a client may allocate.
+pub const FIRST_REQUEST_ID: u32 = 1;
+/// The last request id a client may allocate. Everything above
+/// this is reserved for future control traffic.
+pub const LAST_REQUEST_ID: u32 = u32::MAX - 0xFF;
+
+/// Identifies a packet on a connection.
+///
+/// The first component is the request id. The client allocates these from
+/// [FIRST_REQUEST_ID] to [LAST_REQUEST_ID] and the server copies the id of a
+/// request into its response. Once the client reaches [LAST_REQUEST_ID] it wraps
+/// back around to [FIRST_REQUEST_ID], skipping any id that is still in flight,
+/// so an id is never shared by two outstanding requests. The id [NOTIFICATION_ID]
+/// is used for server pushed packets.
+///
+/// The second component is the order of a packet within a request.
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub struct PacketId(u32, u32);
 
@@ -30,6 +99,14 @@ impl PacketId {
     pub fn order(&self) -> u32 {
         self.1
     }
+    /// Checks if this packet was pushed by the server instead of answering a request.
+    pub fn is_notification(&self) -> bool {
+        self.0 == NOTIFICATION_ID
+    }
+    /// Checks if the id lies in the range clients may allocate from.
+    pub fn is_request(&self) -> bool {
+        (FIRST_REQUEST_ID..=LAST_REQUEST_ID).contains(&self.0)
+    }
 }
 
 
@@ -37,16 +114,70 @@ impl PacketId {
 impl<'a> Packet<'a> {
     pub fn new(id: PacketId, payload: PacketPayload<'a>) -> Self {
         Self {
+            version: CURRENT_VERSION,
             id,
+            namespace: Cow::Borrowed(DEFAULT_NAMESPACE),
+            trace: None,
             payload
         }
     }
+    /// Moves the packet into a namespace.
+    pub fn with_namespace<N>(mut self, namespace: N) -> Self
+    where 
+        N: Into<Cow<'a, str>>
+    {
+        self.namespace = namespace.into();
+        self
+    }
+    /// The key space the packet addresses.
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+    /// Tags the packet with the id of the trace it belongs to, zero being
+    /// read as no trace.
+    pub fn with_trace(mut self, trace: Option<u64>) -> Self {
+        self.trace = trace.filter(|f| *f != 0);
+        self
+    }
+    /// The id of the trace the packet belongs to, so the notifications a
+    /// write causes can be tied to the request that made it.
+    pub fn trace(&self) -> Option<u64> {
+        self.trace
+    }
+    /// Tags the packet in place, see [Packet::with_trace].
+    pub fn set_trace(&mut self, trace: Option<u64>) {
+        self.trace = trace.filter(|f| *f != 0);
+    }
+    /// The protocol version the packet is encoded with.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+    pub(crate) fn with_version(mut self, version: u8) -> Self {
+        self.version = version;
+        self
+    }
+    /// Rewrites the packet for a peer speaking an older protocol version.
+    ///
+    /// Packets the peer would not understand are suppressed by returning
+    /// [None], and values the peer cannot decode are an error.
+    pub fn downgrade(self, version: u8) -> Result<Option<Self>, NetworkError> {
+        let Self { id, namespace, trace, payload, .. } = self;
+        if version < 2 && namespace != DEFAULT_NAMESPACE {
+            return Ok(None);
+        }
+        // Older peers have no room for the trace in the header, it is left out.
+        let trace = trace.filter(|_| version >= 29);
+        Ok(payload.downgrade(version)?.map(|payload| Self { version, id, namespace, trace, payload }))
+    }
     pub fn id(&self) -> PacketId {
         self.id
     }
     pub fn payload(&self) -> &PacketPayload<'a> {
         &self.payload
     }
+    pub fn payload_mut(&mut self) -> &mut PacketPayload<'a> {
+        &mut self.payload
+    }
     
     pub fn into_payload(self) -> PacketPayload<'a> {
         self.payload
@@ -54,31 +185,19 @@ impl<'a> Packet<'a> {
 
     pub fn get(id: PacketId, key: &'a Key) -> Self
     {
-        Self {
-            id,
-            payload: PacketPayload::get(key)
-        }
+        Self::new(id, PacketPayload::get(key))
     }
     pub fn delete(id: PacketId, key: &'a Key) -> Self
     {
-        Self {
-            id,
-            payload: PacketPayload::delete(key)
-        }
+        Self::new(id, PacketPayload::delete(key))
     }
     pub fn insert(id: PacketId, key: &'a Key, value: &'a Value) -> Self
     {
-        Self {
-            id,
-            payload: PacketPayload::insert(key, value)
-        }
+        Self::new(id, PacketPayload::insert(key, value))
     }
     pub fn release(id: PacketId, key: &'a Key) -> Self
     {
-        Self {
-            id,
-            payload: PacketPayload::release(key)
-        }
+        Self::new(id, PacketPayload::release(key))
     }
     pub fn watch(
         id: PacketId,
@@ -87,21 +206,16 @@ impl<'a> Packet<'a> {
         behaviour: WatcherBehaviour
     ) -> Self
     {
-        Self {
-            id,
-            payload: PacketPayload::watch(key, activity, behaviour)
-        }
+        Self::new(id, PacketPayload::watch(key, activity, behaviour))
     }
     pub fn vreturn(
         id: PacketId,
         key: &'a Key,
-        value: Option<&'a Value>
+        value: Option<&'a Value>,
+        version: u64
     ) -> Self
     {
-        Self {
-            id,
-            payload: PacketPayload::return_packet(key, value)
-        }
+        Self::new(id, PacketPayload::return_packet(key, value, version))
     }
     pub fn notify(
         id: PacketId,
@@ -110,14 +224,39 @@ impl<'a> Packet<'a> {
         is_more: bool
     ) -> Self
     {
-        Self {
-            id,
-            payload: PacketPayload::notify(key, value, is_more)
-        }
+        Self::new(id, PacketPayload::notify(key, value, is_more))
+    }
+    pub fn snapshot(
+        id: PacketId,
+        key: &'a Key,
+        value: Option<&'a Value>,
+        version: u64
+    ) -> Self
+    {
+        Self::new(id, PacketPayload::snapshot(key, value, version))
+    }
+    pub fn auth(id: PacketId, token: &'a str) -> Self {
+        Self::new(id, PacketPayload::auth(token))
+    }
+    pub fn auth_result(id: PacketId, accepted: bool) -> Self {
+        Self::new(id, PacketPayload::AuthResult { accepted })
+    }
+    pub fn session(id: PacketId, session: u64, resumed: bool) -> Self {
+        Self::new(id, PacketPayload::Session { id: session, resumed })
+    }
+    /// Reports the error a request failed with.
+    pub fn error(id: PacketId, error: &NetworkError) -> Self {
+        Self::new(id, PacketPayload::Error { code: error.code(), message: Cow::Owned(error.to_string()) })
+    }
+    pub fn manifest_report(id: PacketId, drift: Vec<KeyDrift>) -> Self {
+        Self::new(id, PacketPayload::ManifestReport { drift })
     }
     pub fn to_owned(self) -> Packet<'static> {
         Packet {
+            version: self.version,
             id: self.id,
+            namespace: Cow::Owned(self.namespace.into_owned()),
+            trace: self.trace,
             payload: self.payload.to_owned()
         }
     }
@@ -142,7 +281,10 @@ pub enum PacketPayload<'a> {
     Watch {
         key: Cow<'a, Key>,
         activity: WatcherActivity,
-        behaviour: WatcherBehaviour
+        behaviour: WatcherBehaviour,
+        /// The least time between two notifications, the changes made in
+        /// between are coalesced, see [PacketPayload::with_min_interval].
+        min_interval: Option<Duration>
     },
     Release {
         key: Cow<'a, Key>
@@ -153,38 +295,654 @@ pub enum PacketPayload<'a> {
     Notify {
         key: Cow<'a, Key>,
         value: Option<Cow<'a, Value>>,
-        more: bool
+        more: bool,
+        /// Tells that the watch is alive without bringing a change, the value
+        /// is empty, see [PacketPayload::heartbeat]. Peers before version 32
+        /// are not sent heartbeats.
+        heartbeat: bool
     },
+    /// The value of a key along with the version of the store that wrote
+    /// it, which is zero if the key holds nothing. Peers before version 6
+    /// do not send the version. A read also carries who wrote the record and
+    /// when, which peers before version 10 do not send.
     Return {
         key: Cow<'a, Key>,
-        value: Option<Cow<'a, Value>>
+        value: Option<Cow<'a, Value>>,
+        version: u64,
+        meta: Option<RecordMeta>
+    },
+    /// Authenticates the connection, this must be the first packet
+    /// sent when the server requires authentication.
+    Auth {
+        token: Cow<'a, str>
+    },
+    /// The answer to an [PacketPayload::Auth] packet, also sent in response
+    /// to any request the connection is not permitted to make.
+    AuthResult {
+        accepted: bool
+    },
+    /// Asks the server to check the store against its manifest.
+    CheckManifest,
+    /// The required keys that are missing or hold the wrong type.
+    ManifestReport {
+        drift: Vec<KeyDrift>
+    },
+    /// Pushed to every client when the server shuts down, no more
+    /// packets follow it on the connection.
+    ServerClosing,
+    /// Subscribes to a key and returns the current value in the same step.
+    WatchSnapshot {
+        key: Cow<'a, Key>,
+        behaviour: WatcherBehaviour,
+        /// See [PacketPayload::Watch].
+        min_interval: Option<Duration>
+    },
+    /// The answer to a [PacketPayload::WatchSnapshot]. Every notification
+    /// that follows for the key comes from a change after `version`.
+    Snapshot {
+        key: Cow<'a, Key>,
+        value: Option<Cow<'a, Value>>,
+        version: u64
+    },
+    /// Asks for the recent compaction runs of the store.
+    CompactionHistory,
+    /// The recent compaction runs, oldest first.
+    CompactionReport {
+        runs: Vec<CompactionRun>
+    },
+    /// Keeps an idle connection alive, answered by a [PacketPayload::Pong].
+    Ping,
+    Pong,
+    /// Pushed by the server when keys under the prefix changed out of band,
+    /// clients should drop what they cached for them and fetch again.
+    Invalidate {
+        prefix: Cow<'a, str>
+    },
+    /// Creates an empty namespace, answered by a [PacketPayload::NamespaceResult].
+    CreateNamespace {
+        name: Cow<'a, str>
+    },
+    /// Drops a namespace with every key in it.
+    DropNamespace {
+        name: Cow<'a, str>
+    },
+    /// Whether a namespace was created or dropped.
+    NamespaceResult {
+        changed: bool
+    },
+    /// The answer to a request addressing a namespace that does not exist.
+    UnknownNamespace,
+    /// Asks for up to `limit` keys in order, starting after the cursor.
+    ListKeys {
+        cursor: Option<Cow<'a, Key>>,
+        limit: u32
+    },
+    /// A page of keys, the cursor continues the listing and is [None]
+    /// once there are no keys left.
+    KeyPage {
+        keys: Vec<Key>,
+        cursor: Option<Key>
+    },
+    /// Pushed by the server when a watcher could not queue `missed` changes
+    /// of the key, the watch is gone if it was `closed`.
+    WatchOverflow {
+        key: Cow<'a, Key>,
+        missed: u64,
+        closed: bool
+    },
+    /// Asks what a key held at two revisions, answered by a [PacketPayload::KeyDiff].
+    KeyHistory {
+        key: Cow<'a, Key>,
+        from: Revision,
+        to: Revision
+    },
+    /// The values at both revisions and their diff, [None] if a revision
+    /// is older than the history the server keeps.
+    KeyDiff {
+        diff: Option<KeyDiff>
+    },
+    /// Promotes one key space into another in a single step, or only reports
+    /// what would change if it is a dry run. Answered by a [PacketPayload::PromotionResult].
+    Promote {
+        promotion: Promotion,
+        dry_run: bool
+    },
+    /// Restores what the target held before a promotion.
+    RollbackPromotion {
+        id: u64
+    },
+    /// What a promotion or rollback changed, [None] if there was nothing to roll back.
+    PromotionResult {
+        report: Option<PromotionReport>
+    },
+    /// Asks what the server knows about a key, answered by a [PacketPayload::KeyMeta].
+    GetMeta {
+        key: Cow<'a, Key>
+    },
+    /// The metadata of a key along with its value. Peers before version 10
+    /// send neither the value nor the record metadata.
+    KeyMeta {
+        key: Cow<'a, Key>,
+        value: Option<Cow<'a, Value>>,
+        meta: KeyMeta
+    },
+    /// Pushed by the server when it killed the watch of a key because the
+    /// subscription was broken, the client should not expect more changes.
+    WatchIsolated {
+        key: Cow<'a, Key>,
+        reason: IsolationReason
+    },
+    /// Asks for the keys whose map value holds `value` at the field path,
+    /// answered by a [PacketPayload::IndexMatches].
+    QueryByIndex {
+        field: Cow<'a, str>,
+        value: Cow<'a, Value>
+    },
+    /// The matching keys in order, [None] if no index is declared on the field.
+    IndexMatches {
+        keys: Option<Vec<Key>>
+    },
+    /// Inserts the default if the key holds nothing, answered by a
+    /// [PacketPayload::Return] with the value the key holds afterwards.
+    GetOrInsert {
+        key: Cow<'a, Key>,
+        default: Cow<'a, Value>
+    },
+    /// Hands a message to the subscribers of a topic without storing it,
+    /// answered by a [PacketPayload::Published].
+    ///
+    /// Topics are the pub/sub channels of the server, the topic is the channel.
+    #[doc(alias = "PublishChannel")]
+    Publish {
+        topic: Cow<'a, Key>,
+        value: Cow<'a, Value>
+    },
+    /// How many subscribers the message was queued for.
+    Published {
+        receivers: u64
+    },
+    /// Subscribes to the messages of a topic, answered by a [PacketPayload::Get].
+    #[doc(alias = "SubscribeChannel")]
+    SubscribeTopic {
+        topic: Cow<'a, Key>
+    },
+    /// Stops the messages of a topic, answered by a [PacketPayload::Get].
+    UnsubscribeTopic {
+        topic: Cow<'a, Key>
+    },
+    /// Pushed by the server with a message published to a topic the client subscribed to.
+    TopicMessage {
+        topic: Cow<'a, Key>,
+        value: Cow<'a, Value>
+    },
+    /// Inserts the value only if the key is still at the version, zero for a
+    /// key that holds nothing. Answered by a [PacketPayload::Return] with the
+    /// new version, or a [PacketPayload::VersionConflict] if the key moved on.
+    InsertIfVersion {
+        key: Cow<'a, Key>,
+        value: Cow<'a, Value>,
+        version: u64
+    },
+    /// The version the key is at, which the conditional write did not expect.
+    VersionConflict {
+        key: Cow<'a, Key>,
+        version: u64
+    },
+    /// Appends an item to the work queue under the prefix, answered by a
+    /// [PacketPayload::Return] with the key the item is stored under.
+    Enqueue {
+        queue: Cow<'a, Key>,
+        value: Cow<'a, Value>
+    },
+    /// Leases the oldest visible item of a queue for the visibility timeout,
+    /// answered by a [PacketPayload::Leased].
+    Dequeue {
+        queue: Cow<'a, Key>,
+        visibility: Duration
+    },
+    /// The leased item, [None] if the queue has no visible item.
+    Leased {
+        item: Option<LeasedItem>
+    },
+    /// Removes a leased item from its queue, answered by a [PacketPayload::LeaseResult].
+    Ack {
+        key: Cow<'a, Key>,
+        lease: u64
+    },
+    /// Gives up a lease so the item is visible again right away, answered
+    /// by a [PacketPayload::LeaseResult].
+    Nack {
+        key: Cow<'a, Key>,
+        lease: u64
+    },
+    /// Whether the lease was still held, an expired lease may have been handed on.
+    LeaseResult {
+        accepted: bool
+    },
+    /// Asks for the last changes to a key the server kept, every kept change
+    /// if the limit is zero. Answered by a [PacketPayload::HistoryReport].
+    History {
+        key: Cow<'a, Key>,
+        limit: u32
+    },
+    /// The changes to the key, oldest first.
+    HistoryReport {
+        entries: Vec<HistoryEntry>
+    },
+    /// Inserts a value and answers with a [PacketPayload::Return] once the
+    /// write reached the level, or with a [PacketPayload::AcknowledgementTimeout].
+    InsertAcknowledged {
+        key: Cow<'a, Key>,
+        value: Cow<'a, Value>,
+        acknowledgement: Acknowledgement
+    },
+    /// The write was applied at the version but did not reach its level in time.
+    AcknowledgementTimeout {
+        key: Cow<'a, Key>,
+        version: u64
+    },
+    /// Lists every key neither read nor written since the time, in
+    /// milliseconds since the unix epoch, and deletes them if `delete` is
+    /// set. Answered by [PacketPayload::Archived].
+    Archive {
+        before: u64,
+        delete: bool
+    },
+    /// The keys an [PacketPayload::Archive] found, in order.
+    Archived {
+        records: Vec<(Key, Value)>
+    },
+    /// Takes the lock on a key for the time to live, or extends it if the
+    /// connection holds it already. Answered by a [PacketPayload::LockResult].
+    ///
+    /// The lock is released when the connection closes, and its watchers
+    /// are told the id of each new holder and nothing once it is free.
+    Acquire {
+        key: Cow<'a, Key>,
+        ttl: Duration
+    },
+    /// Releases a lock the connection holds, answered by a [PacketPayload::LeaseResult].
+    ReleaseLock {
+        key: Cow<'a, Key>
+    },
+    /// The token of the lock, which grows with every acquisition, [None]
+    /// if another connection holds it.
+    LockResult {
+        token: Option<u64>
+    },
+    /// Inserts a value the server deletes once the connection closes, unless
+    /// another connection wrote the key since. Answered by a [PacketPayload::Return].
+    InsertEphemeral {
+        key: Cow<'a, Key>,
+        value: Cow<'a, Value>
+    },
+    /// Reattaches the connection to a session that lost its own connection,
+    /// or asks for a session if there is none to resume. Answered by a
+    /// [PacketPayload::Session].
+    ///
+    /// A resumed session keeps its watches, topics, locks and ephemeral keys,
+    /// and the notifications that arrived in between are delivered first.
+    Resume {
+        session: Option<u64>
+    },
+    /// The session of the connection and whether it is the one that was
+    /// resumed, if it is a new one the client has to watch its keys again.
+    Session {
+        id: u64,
+        resumed: bool
+    },
+    /// A packet of a protocol extension, whose opcode is one of the reserved
+    /// discriminators, see [FIRST_EXTENSION_DISCRIMINATOR]. The extension id
+    /// tells apart the forks that picked the same opcode and the payload is
+    /// up to the extension.
+    ///
+    /// A server without a handler for the extension answers with a
+    /// [PacketPayload::UnsupportedExtension].
+    Extension {
+        opcode: u8,
+        extension: u32,
+        payload: Cow<'a, [u8]>
+    },
+    /// The server has no handler for the extension.
+    UnsupportedExtension {
+        extension: u32
+    },
+    /// Adds to the integer the key holds, a key holding nothing counting as
+    /// zero. Answered by a [PacketPayload::Return] with the value after, a
+    /// key holding anything else is returned untouched.
+    Increment {
+        key: Cow<'a, Key>,
+        delta: i64
+    },
+    /// Sent to a connection the server turned away for serving as many as
+    /// it takes, it closes the connection right after.
+    ServerBusy,
+    /// Answers a write of a key that is written more often than the server
+    /// allows, the write was dropped. Writing it again may succeed after the delay.
+    Throttled {
+        retry_after: Duration
+    },
+    /// Watches a key with acknowledged delivery, every change after the
+    /// sequence is sent as a [PacketPayload::Delivery] until the client
+    /// acknowledges it. A fresh watch passes zero and is answered by a
+    /// [PacketPayload::Snapshot] of the key, one picking up where another
+    /// connection left off passes the last sequence it acknowledged and is
+    /// answered by a [PacketPayload::Get] once the changes since are queued.
+    WatchAcknowledged {
+        key: Cow<'a, Key>,
+        after: u64
+    },
+    /// A change of a key watched with acknowledged delivery, the sequence is
+    /// the version of the store the change produced.
+    ///
+    /// A gap means the history no longer held the changes before this one,
+    /// the value is what the key holds now rather than the next change.
+    Delivery {
+        key: Cow<'a, Key>,
+        value: Option<Cow<'a, Value>>,
+        sequence: u64,
+        gap: bool
+    },
+    /// Acknowledges the deliveries of a key up to the sequence, it is sent
+    /// as a notification and not answered.
+    AckDelivery {
+        key: Cow<'a, Key>,
+        sequence: u64
+    },
+    /// Answers a request the server failed to serve with the code of the
+    /// error, see [ErrorCode], the connection stays open. Older clients
+    /// are disconnected instead.
+    Error {
+        code: ErrorCode,
+        message: Cow<'a, str>
+    },
+    /// Loads a chunk of records in bulk to seed the store, the watchers
+    /// are not told. Answered by [PacketPayload::Imported].
+    Import {
+        records: Vec<(Key, Value)>
+    },
+    /// How many records an [PacketPayload::Import] loaded.
+    Imported {
+        count: u64
+    },
+    /// Asks for up to `limit` records in order along with their metadata,
+    /// starting after the cursor. Answered by [PacketPayload::ExportPage].
+    Export {
+        cursor: Option<Cow<'a, Key>>,
+        limit: u32
+    },
+    /// A page of exported records, the cursor continues the export and is
+    /// [None] once there are no records left.
+    ExportPage {
+        records: Vec<ExportedRecord>,
+        cursor: Option<Key>
+    },
+    /// Asks for up to `limit` of the keys one segment below the key in
+    /// order, starting after the cursor, see [Key::child]. Answered by
+    /// [PacketPayload::Children].
+    ListChildren {
+        key: Cow<'a, Key>,
+        cursor: Option<Cow<'a, Key>>,
+        limit: u32
+    },
+    /// A page of children, the cursor continues the listing and is [None]
+    /// once there are no children left.
+    Children {
+        children: Vec<KeyChild>,
+        cursor: Option<Key>
+    },
+    /// Asks which keys are watched and by how many clients, every key if
+    /// none is named. Answered by [PacketPayload::WatcherReport].
+    ListWatchers {
+        key: Option<Cow<'a, Key>>
+    },
+    /// The watched keys in order, a key nobody watches is left out.
+    WatcherReport {
+        watched: Vec<WatchedKey>
+    },
+    /// Moves the record of a key to another in one step, keeping its
+    /// version and metadata. Answered by a [PacketPayload::Return] of the
+    /// target with the version of the moved record, zero if the key held
+    /// nothing, or a [PacketPayload::VersionConflict] if the target holds a
+    /// record and `overwrite` is not set.
+    Rename {
+        from: Cow<'a, Key>,
+        to: Cow<'a, Key>,
+        overwrite: bool
+    },
+    /// Reads the keys at a single point in time, so no write lands between
+    /// two of them. Answered by a [PacketPayload::SnapshotValues].
+    SnapshotGet {
+        keys: Vec<Key>
+    },
+    /// The value of each key of a [PacketPayload::SnapshotGet] in order,
+    /// with the version of its record, which is zero if it holds nothing.
+    SnapshotValues {
+        values: Vec<(Option<Value>, u64)>
+    },
+    /// Registers the schema the values written under the prefix must
+    /// conform to, replacing the one it had, or removes it if there is no
+    /// schema. Answered by a [PacketPayload::SchemaRegistered].
+    RegisterSchema {
+        prefix: Cow<'a, str>,
+        schema: Option<Schema>
+    },
+    /// The schema the prefix had before a [PacketPayload::RegisterSchema].
+    SchemaRegistered {
+        previous: Option<Schema>
+    },
+    /// Lists the schemas registered in the namespace. Answered by a
+    /// [PacketPayload::Schemas].
+    ListSchemas,
+    /// The registered schemas by their prefix, in order.
+    Schemas {
+        schemas: Vec<(String, Schema)>
+    },
+    /// Inserts the values under their keys all at once, no reader or
+    /// watcher sees some of the writes without the others and none is made
+    /// if one is refused. Answered by a [PacketPayload::MultiInserted].
+    MultiInsert {
+        entries: Vec<(Key, Value)>
+    },
+    /// The version of each record a [PacketPayload::MultiInsert] wrote, in order.
+    MultiInserted {
+        versions: Vec<u64>
+    },
+    /// Deletes the key only if its record meets the condition, checked and
+    /// deleted in one step. Answered by a [PacketPayload::DeleteIfResult].
+    DeleteIf {
+        key: Cow<'a, Key>,
+        condition: DeleteCondition
+    },
+    /// Whether a [PacketPayload::DeleteIf] deleted the key.
+    DeleteIfResult {
+        deleted: bool
+    },
+    /// Lists the keys matching the filter like [PacketPayload::ListKeys],
+    /// answered by a [PacketPayload::KeyPage]. The server looks at a
+    /// bounded number of keys per page, so a page may hold fewer keys than
+    /// the limit, or none, and still have a cursor.
+    FindKeys {
+        filter: KeyFilter,
+        cursor: Option<Cow<'a, Key>>,
+        limit: u32
+    },
+    /// Asks how many records the namespace holds and how much room they
+    /// take. Answered by a [PacketPayload::Stats].
+    GetStats,
+    /// The figures of the database serving the namespace.
+    Stats {
+        stats: DatabaseStats
+    },
+    /// Takes the next number of the named sequence, which is larger than
+    /// every number it handed out before, across restarts too. Answered by
+    /// a [PacketPayload::Sequence].
+    NextSequence {
+        name: Cow<'a, str>
+    },
+    /// The number a [PacketPayload::NextSequence] took.
+    Sequence {
+        value: u64
+    },
+    /// Asks what a key held at a revision, answered by a [PacketPayload::ValueAt].
+    GetAt {
+        key: Cow<'a, Key>,
+        at: Revision
+    },
+    /// The value a key held at the revision, [None] if it had none. Not
+    /// `available` if the revision is older than the history the server keeps.
+    ValueAt {
+        value: Option<Value>,
+        available: bool
     }
 }
 
 
 impl<'a> PacketPayload<'a> {
     pub fn notify(key: &'a Key, value: Option<&'a Value>, more: bool) -> Self {
-        Self::Notify { key: Cow::Borrowed(key), value: value.map(|f| Cow::Borrowed(f)), more }
+        Self::Notify { key: Cow::Borrowed(key), value: value.map(|f| Cow::Borrowed(f)), more, heartbeat: false }
 
     }
-    pub fn return_packet(key: &'a Key, value: Option<&'a Value>) -> Self {
-        Self::Return { key: Cow::Borrowed(key), value: value.map(|f| Cow::Borrowed(f)) }
+    /// The notification a watch of the key is sent when it has been quiet
+    /// for a while, so the client knows it is still alive.
+    pub fn heartbeat(key: &'a Key) -> Self {
+        Self::Notify { key: Cow::Borrowed(key), value: None, more: false, heartbeat: true }
+    }
+    pub fn return_packet(key: &'a Key, value: Option<&'a Value>, version: u64) -> Self {
+        Self::Return { key: Cow::Borrowed(key), value: value.map(|f| Cow::Borrowed(f)), version, meta: None }
     }
     pub fn release(key: &'a Key) -> Self {
         Self::Release { key: Cow::Borrowed(key) }
     }
     pub fn watch(key: &'a Key, activity: WatcherActivity, behaviour: WatcherBehaviour) -> Self {
-        Self::Watch { key: Cow::Borrowed(key), activity, behaviour }
+        Self::Watch { key: Cow::Borrowed(key), activity, behaviour, min_interval: None }
+    }
+    /// Sets the least time between two notifications of a watch, the
+    /// server coalescing the changes made in between. An eager watch is
+    /// sent the latest value, an ordered one every change in a batch.
+    ///
+    /// Peers before version 31 are sent the watch without it.
+    pub fn with_min_interval(mut self, interval: Option<Duration>) -> Self {
+        if let Self::Watch { min_interval, .. } | Self::WatchSnapshot { min_interval, .. } = &mut self {
+            *min_interval = interval.filter(|f| !f.is_zero());
+        }
+        self
     }
     pub fn insert(key: &'a Key, value: &'a Value) -> Self {
         Self::Insert { key: Cow::Borrowed(key), value: Cow::Borrowed(value) }
     }
+    pub fn insert_ephemeral(key: &'a Key, value: &'a Value) -> Self {
+        Self::InsertEphemeral { key: Cow::Borrowed(key), value: Cow::Borrowed(value) }
+    }
+    pub fn insert_if_version(key: &'a Key, value: &'a Value, version: u64) -> Self {
+        Self::InsertIfVersion { key: Cow::Borrowed(key), value: Cow::Borrowed(value), version }
+    }
+    pub fn insert_acknowledged(key: &'a Key, value: &'a Value, acknowledgement: Acknowledgement) -> Self {
+        Self::InsertAcknowledged { key: Cow::Borrowed(key), value: Cow::Borrowed(value), acknowledgement }
+    }
+    pub fn history(key: &'a Key, limit: u32) -> Self {
+        Self::History { key: Cow::Borrowed(key), limit }
+    }
+    pub fn enqueue(queue: &'a Key, value: &'a Value) -> Self {
+        Self::Enqueue { queue: Cow::Borrowed(queue), value: Cow::Borrowed(value) }
+    }
+    pub fn dequeue(queue: &'a Key, visibility: Duration) -> Self {
+        Self::Dequeue { queue: Cow::Borrowed(queue), visibility }
+    }
+    pub fn ack(key: &'a Key, lease: u64) -> Self {
+        Self::Ack { key: Cow::Borrowed(key), lease }
+    }
+    pub fn acquire(key: &'a Key, ttl: Duration) -> Self {
+        Self::Acquire { key: Cow::Borrowed(key), ttl }
+    }
+    pub fn release_lock(key: &'a Key) -> Self {
+        Self::ReleaseLock { key: Cow::Borrowed(key) }
+    }
+    pub fn increment(key: &'a Key, delta: i64) -> Self {
+        Self::Increment { key: Cow::Borrowed(key), delta }
+    }
+    /// # Panics
+    /// If the opcode is not one of the discriminators reserved for extensions.
+    pub fn extension(opcode: u8, extension: u32, payload: Vec<u8>) -> Self {
+        assert!(opcode >= FIRST_EXTENSION_DISCRIMINATOR, "Extension opcodes start at {FIRST_EXTENSION_DISCRIMINATOR}.");
+        Self::Extension { opcode, extension, payload: Cow::Owned(payload) }
+    }
+    pub fn nack(key: &'a Key, lease: u64) -> Self {
+        Self::Nack { key: Cow::Borrowed(key), lease }
+    }
+    pub fn get_or_insert(key: &'a Key, default: &'a Value) -> Self {
+        Self::GetOrInsert { key: Cow::Borrowed(key), default: Cow::Borrowed(default) }
+    }
+    pub fn publish(topic: &'a Key, value: &'a Value) -> Self {
+        Self::Publish { topic: Cow::Borrowed(topic), value: Cow::Borrowed(value) }
+    }
+    pub fn subscribe_topic(topic: &'a Key) -> Self {
+        Self::SubscribeTopic { topic: Cow::Borrowed(topic) }
+    }
+    pub fn unsubscribe_topic(topic: &'a Key) -> Self {
+        Self::UnsubscribeTopic { topic: Cow::Borrowed(topic) }
+    }
+    /// A [PacketPayload::SubscribeTopic], the topic being the channel.
+    pub fn subscribe_channel(channel: &'a Key) -> Self {
+        Self::subscribe_topic(channel)
+    }
+    pub fn topic_message(topic: &'a Key, value: &'a Value) -> Self {
+        Self::TopicMessage { topic: Cow::Borrowed(topic), value: Cow::Borrowed(value) }
+    }
     pub fn delete(key: &'a Key) -> Self {
         Self::Delete { key: Cow::Borrowed(key) }
     }
+    pub fn delete_if(key: &'a Key, condition: DeleteCondition) -> Self {
+        Self::DeleteIf { key: Cow::Borrowed(key), condition }
+    }
     pub fn get(key: &'a Key) -> Self {
         Self::Get { key: Cow::Borrowed(key) }
     }
+    pub fn watch_snapshot(key: &'a Key, behaviour: WatcherBehaviour) -> Self {
+        Self::WatchSnapshot { key: Cow::Borrowed(key), behaviour, min_interval: None }
+    }
+    pub fn snapshot(key: &'a Key, value: Option<&'a Value>, version: u64) -> Self {
+        Self::Snapshot { key: Cow::Borrowed(key), value: value.map(|f| Cow::Borrowed(f)), version }
+    }
+    pub fn invalidate(prefix: &'a str) -> Self {
+        Self::Invalidate { prefix: Cow::Borrowed(prefix) }
+    }
+    pub fn create_namespace(name: &'a str) -> Self {
+        Self::CreateNamespace { name: Cow::Borrowed(name) }
+    }
+    pub fn drop_namespace(name: &'a str) -> Self {
+        Self::DropNamespace { name: Cow::Borrowed(name) }
+    }
+    pub fn list_keys(cursor: Option<&'a Key>, limit: u32) -> Self {
+        Self::ListKeys { cursor: cursor.map(Cow::Borrowed), limit }
+    }
+    pub fn find_keys(filter: KeyFilter, cursor: Option<&'a Key>, limit: u32) -> Self {
+        Self::FindKeys { filter, cursor: cursor.map(Cow::Borrowed), limit }
+    }
+    pub fn export(cursor: Option<&'a Key>, limit: u32) -> Self {
+        Self::Export { cursor: cursor.map(Cow::Borrowed), limit }
+    }
+    pub fn list_children(key: &'a Key, cursor: Option<&'a Key>, limit: u32) -> Self {
+        Self::ListChildren { key: Cow::Borrowed(key), cursor: cursor.map(Cow::Borrowed), limit }
+    }
+    pub fn list_watchers(key: Option<&'a Key>) -> Self {
+        Self::ListWatchers { key: key.map(Cow::Borrowed) }
+    }
+    pub fn rename(from: &'a Key, to: &'a Key, overwrite: bool) -> Self {
+        Self::Rename { from: Cow::Borrowed(from), to: Cow::Borrowed(to), overwrite }
+    }
+    pub fn snapshot_get(keys: &[Key]) -> Self {
+        Self::SnapshotGet { keys: keys.to_vec() }
+    }
+    pub fn register_schema(prefix: &'a str, schema: Option<Schema>) -> Self {
+        Self::RegisterSchema { prefix: Cow::Borrowed(prefix), schema }
+    }
+    pub fn query_by_index(field: &'a str, value: &'a Value) -> Self {
+        Self::QueryByIndex { field: Cow::Borrowed(field), value: Cow::Borrowed(value) }
+    }
+    pub fn auth(token: &'a str) -> Self {
+        Self::Auth { token: Cow::Borrowed(token) }
+    }
     pub fn discriminator(&self) -> u8 {
         match self {
             Self::Insert { .. } => 0,
@@ -193,7 +951,280 @@ impl<'a> PacketPayload<'a> {
             Self::Release { .. } => 3,
             Self::Delete { .. } => 4,
             Self::Notify { .. } => 5,
-            Self::Return { .. } => 6
+            Self::Return { .. } => 6,
+            Self::Auth { .. } => 7,
+            Self::AuthResult { .. } => 8,
+            Self::CheckManifest => 9,
+            Self::ManifestReport { .. } => 10,
+            Self::ServerClosing => 11,
+            Self::WatchSnapshot { .. } => 12,
+            Self::Snapshot { .. } => 13,
+            Self::CompactionHistory => 14,
+            Self::CompactionReport { .. } => 15,
+            Self::Ping => 16,
+            Self::Pong => 17,
+            Self::Invalidate { .. } => 18,
+            Self::CreateNamespace { .. } => 19,
+            Self::DropNamespace { .. } => 20,
+            Self::NamespaceResult { .. } => 21,
+            Self::UnknownNamespace => 22,
+            Self::ListKeys { .. } => 23,
+            Self::KeyPage { .. } => 24,
+            Self::WatchOverflow { .. } => 25,
+            Self::KeyHistory { .. } => 26,
+            Self::KeyDiff { .. } => 27,
+            Self::Promote { .. } => 28,
+            Self::RollbackPromotion { .. } => 29,
+            Self::PromotionResult { .. } => 30,
+            Self::GetMeta { .. } => 31,
+            Self::KeyMeta { .. } => 32,
+            Self::WatchIsolated { .. } => 33,
+            Self::QueryByIndex { .. } => 34,
+            Self::IndexMatches { .. } => 35,
+            Self::GetOrInsert { .. } => 36,
+            Self::Publish { .. } => 37,
+            Self::Published { .. } => 38,
+            Self::SubscribeTopic { .. } => 39,
+            Self::UnsubscribeTopic { .. } => 40,
+            Self::TopicMessage { .. } => 41,
+            Self::InsertIfVersion { .. } => 42,
+            Self::VersionConflict { .. } => 43,
+            Self::Enqueue { .. } => 44,
+            Self::Dequeue { .. } => 45,
+            Self::Leased { .. } => 46,
+            Self::Ack { .. } => 47,
+            Self::Nack { .. } => 48,
+            Self::LeaseResult { .. } => 49,
+            Self::History { .. } => 50,
+            Self::HistoryReport { .. } => 51,
+            Self::InsertAcknowledged { .. } => 52,
+            Self::AcknowledgementTimeout { .. } => 53,
+            Self::Archive { .. } => 54,
+            Self::Archived { .. } => 55,
+            Self::Acquire { .. } => 56,
+            Self::ReleaseLock { .. } => 57,
+            Self::LockResult { .. } => 58,
+            Self::InsertEphemeral { .. } => 59,
+            Self::Resume { .. } => 60,
+            Self::Session { .. } => 61,
+            Self::UnsupportedExtension { .. } => 62,
+            Self::Increment { .. } => 63,
+            Self::ServerBusy => 64,
+            Self::Throttled { .. } => 65,
+            Self::WatchAcknowledged { .. } => 66,
+            Self::Delivery { .. } => 67,
+            Self::AckDelivery { .. } => 68,
+            Self::Error { .. } => 69,
+            Self::Import { .. } => 70,
+            Self::Imported { .. } => 71,
+            Self::Export { .. } => 72,
+            Self::ExportPage { .. } => 73,
+            Self::ListChildren { .. } => 74,
+            Self::Children { .. } => 75,
+            Self::ListWatchers { .. } => 76,
+            Self::WatcherReport { .. } => 77,
+            Self::Rename { .. } => 78,
+            Self::SnapshotGet { .. } => 79,
+            Self::SnapshotValues { .. } => 80,
+            Self::RegisterSchema { .. } => 81,
+            Self::SchemaRegistered { .. } => 82,
+            Self::ListSchemas => 83,
+            Self::Schemas { .. } => 84,
+            Self::MultiInsert { .. } => 85,
+            Self::MultiInserted { .. } => 86,
+            Self::DeleteIf { .. } => 87,
+            Self::DeleteIfResult { .. } => 88,
+            Self::FindKeys { .. } => 89,
+            Self::GetStats => 90,
+            Self::Stats { .. } => 91,
+            Self::NextSequence { .. } => 92,
+            Self::Sequence { .. } => 93,
+            Self::GetAt { .. } => 94,
+            Self::ValueAt { .. } => 95,
+            Self::Extension { opcode, .. } => (*opcode).max(FIRST_EXTENSION_DISCRIMINATOR)
+        }
+    }
+    /// The oldest protocol version that knows the packet.
+    pub fn min_version(&self) -> u8 {
+        if let Self::Notify { heartbeat: true, .. } = self {
+            // An older peer would take the heartbeat for a delete.
+            return 32;
+        }
+        match self.discriminator() {
+            0..=6 => 0,
+            7..=18 => 1,
+            19..=33 => 2,
+            34..=35 => 3,
+            36 => 4,
+            37..=41 => 5,
+            42..=43 => 6,
+            44..=49 => 7,
+            50..=51 => 8,
+            52..=53 => 9,
+            54..=55 => 10,
+            56..=58 => 11,
+            59 => 12,
+            60..=61 => 13,
+            63 => 16,
+            64 => 19,
+            65 => 20,
+            66..=68 => 21,
+            69 => 22,
+            70 | 71 => 23,
+            72 | 73 => 24,
+            74 | 75 => 25,
+            76 | 77 => 26,
+            78 => 27,
+            79 | 80 => 28,
+            81..=84 => 30,
+            85 | 86 => 33,
+            87 | 88 => 34,
+            89 => 35,
+            90 | 91 => 36,
+            92 | 93 => 37,
+            94 | 95 => 38,
+            _ => 14
+        }
+    }
+    /// Rewrites the payload for a peer speaking an older protocol version,
+    /// see [Packet::downgrade].
+    pub fn downgrade(self, version: u8) -> Result<Option<Self>, NetworkError> {
+        if version >= CURRENT_VERSION {
+            return Ok(Some(self));
+        }
+        if self.min_version() > version {
+            return Ok(None);
+        }
+        let values = match &self {
+            Self::Insert { value, .. }
+            | Self::GetOrInsert { default: value, .. }
+            | Self::Publish { value, .. }
+            | Self::TopicMessage { value, .. }
+            | Self::InsertIfVersion { value, .. }
+            | Self::Enqueue { value, .. }
+            | Self::InsertAcknowledged { value, .. }
+            | Self::InsertEphemeral { value, .. } => vec![&**value],
+            Self::Leased { item } => item.iter().map(|f| &f.value).collect(),
+            Self::HistoryReport { entries } => entries.iter().filter_map(|f| f.value.as_ref()).collect(),
+            Self::Notify { value, .. }
+            | Self::Return { value, .. }
+            | Self::Snapshot { value, .. }
+            | Self::Delivery { value, .. } => value.as_deref().into_iter().collect(),
+            Self::KeyDiff { diff: Some(diff) } => diff.from.iter().chain(diff.to.iter()).collect(),
+            Self::ValueAt { value, .. } => value.iter().collect(),
+            _ => vec![]
+        };
+        for value in values {
+            if let Some(value_type) = value.unsupported_type(version) {
+                return Err(NetworkError::Protocol(ProtocolError::ValueTypeUnsupported(value_type, version)));
+            }
+        }
+        Ok(Some(match self {
+            // Older peers cannot batch notifications.
+            Self::Notify { key, value, heartbeat, .. } => Self::Notify { key, value, more: false, heartbeat },
+            payload => payload
+        }))
+    }
+    /// A stable name for the kind of packet, for logs and metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Insert { .. } => "insert",
+            Self::Get { .. } => "get",
+            Self::Watch { .. } => "watch",
+            Self::Release { .. } => "release",
+            Self::Delete { .. } => "delete",
+            Self::Notify { .. } => "notify",
+            Self::Return { .. } => "return",
+            Self::Auth { .. } => "auth",
+            Self::AuthResult { .. } => "auth_result",
+            Self::CheckManifest => "check_manifest",
+            Self::ManifestReport { .. } => "manifest_report",
+            Self::ServerClosing => "server_closing",
+            Self::ServerBusy => "server_busy",
+            Self::Throttled { .. } => "throttled",
+            Self::WatchAcknowledged { .. } => "watch_acknowledged",
+            Self::Delivery { .. } => "delivery",
+            Self::AckDelivery { .. } => "ack_delivery",
+            Self::Error { .. } => "error",
+            Self::Import { .. } => "import",
+            Self::Imported { .. } => "imported",
+            Self::Export { .. } => "export",
+            Self::ExportPage { .. } => "export_page",
+            Self::ListChildren { .. } => "list_children",
+            Self::Children { .. } => "children",
+            Self::ListWatchers { .. } => "list_watchers",
+            Self::WatcherReport { .. } => "watcher_report",
+            Self::Rename { .. } => "rename",
+            Self::SnapshotGet { .. } => "snapshot_get",
+            Self::SnapshotValues { .. } => "snapshot_values",
+            Self::RegisterSchema { .. } => "register_schema",
+            Self::SchemaRegistered { .. } => "schema_registered",
+            Self::ListSchemas => "list_schemas",
+            Self::Schemas { .. } => "schemas",
+            Self::MultiInsert { .. } => "multi_insert",
+            Self::MultiInserted { .. } => "multi_inserted",
+            Self::DeleteIf { .. } => "delete_if",
+            Self::DeleteIfResult { .. } => "delete_if_result",
+            Self::FindKeys { .. } => "find_keys",
+            Self::GetStats => "get_stats",
+            Self::Stats { .. } => "stats",
+            Self::NextSequence { .. } => "next_sequence",
+            Self::Sequence { .. } => "sequence",
+            Self::GetAt { .. } => "get_at",
+            Self::ValueAt { .. } => "value_at",
+            Self::WatchSnapshot { .. } => "watch_snapshot",
+            Self::Snapshot { .. } => "snapshot",
+            Self::CompactionHistory => "compaction_history",
+            Self::CompactionReport { .. } => "compaction_report",
+            Self::Ping => "ping",
+            Self::Pong => "pong",
+            Self::Invalidate { .. } => "invalidate",
+            Self::CreateNamespace { .. } => "create_namespace",
+            Self::DropNamespace { .. } => "drop_namespace",
+            Self::NamespaceResult { .. } => "namespace_result",
+            Self::UnknownNamespace => "unknown_namespace",
+            Self::ListKeys { .. } => "list_keys",
+            Self::KeyPage { .. } => "key_page",
+            Self::WatchOverflow { .. } => "watch_overflow",
+            Self::KeyHistory { .. } => "key_history",
+            Self::KeyDiff { .. } => "key_diff",
+            Self::Promote { .. } => "promote",
+            Self::RollbackPromotion { .. } => "rollback_promotion",
+            Self::PromotionResult { .. } => "promotion_result",
+            Self::GetMeta { .. } => "get_meta",
+            Self::KeyMeta { .. } => "key_meta",
+            Self::WatchIsolated { .. } => "watch_isolated",
+            Self::QueryByIndex { .. } => "query_by_index",
+            Self::IndexMatches { .. } => "index_matches",
+            Self::GetOrInsert { .. } => "get_or_insert",
+            Self::Publish { .. } => "publish",
+            Self::Published { .. } => "published",
+            Self::SubscribeTopic { .. } => "subscribe_topic",
+            Self::UnsubscribeTopic { .. } => "unsubscribe_topic",
+            Self::TopicMessage { .. } => "topic_message",
+            Self::InsertIfVersion { .. } => "insert_if_version",
+            Self::VersionConflict { .. } => "version_conflict",
+            Self::Enqueue { .. } => "enqueue",
+            Self::Dequeue { .. } => "dequeue",
+            Self::Leased { .. } => "leased",
+            Self::Ack { .. } => "ack",
+            Self::Nack { .. } => "nack",
+            Self::LeaseResult { .. } => "lease_result",
+            Self::History { .. } => "history",
+            Self::HistoryReport { .. } => "history_report",
+            Self::InsertAcknowledged { .. } => "insert_acknowledged",
+            Self::AcknowledgementTimeout { .. } => "acknowledgement_timeout",
+            Self::Archive { .. } => "archive",
+            Self::Archived { .. } => "archived",
+            Self::Acquire { .. } => "acquire",
+            Self::ReleaseLock { .. } => "release_lock",
+            Self::LockResult { .. } => "lock_result",
+            Self::InsertEphemeral { .. } => "insert_ephemeral",
+            Self::Resume { .. } => "resume",
+            Self::Session { .. } => "session",
+            Self::Extension { .. } => "extension",
+            Self::UnsupportedExtension { .. } => "unsupported_extension",
+            Self::Increment { .. } => "increment"
         }
     }
     pub fn to_owned(self) -> PacketPayload<'static> {
@@ -224,10 +1255,100 @@ fn own_packet_payload(payload: PacketPayload<'_>) -> PacketPayload<'static> {
         PacketPayload::Delete { key } => PacketPayload::Delete { key: Cow::Owned(key.into_owned()) },
         PacketPayload::Get { key } => PacketPayload::Get { key: Cow::Owned(key.into_owned()) },
         PacketPayload::Insert { key, value } => PacketPayload::Insert { key: Cow::Owned(key.into_owned()), value: Cow::Owned(value.into_owned()) },
-        PacketPayload::Notify { key, value, more } => PacketPayload::Notify { key: Cow::Owned(key.into_owned()), value: own_value_cow(value), more },
+        PacketPayload::Notify { key, value, more, heartbeat } => PacketPayload::Notify { key: Cow::Owned(key.into_owned()), value: own_value_cow(value), more, heartbeat },
         PacketPayload::Release { key } => PacketPayload::Release { key: Cow::Owned(key.into_owned()) },
-        PacketPayload::Watch { key, activity, behaviour } => PacketPayload::Watch { key: Cow::Owned(key.into_owned()), activity, behaviour },
-        PacketPayload::Return { key, value } => PacketPayload::Return { key: Cow::Owned(key.into_owned()), value: own_value_cow(value) },
+        PacketPayload::Watch { key, activity, behaviour, min_interval } => PacketPayload::Watch { key: Cow::Owned(key.into_owned()), activity, behaviour, min_interval },
+        PacketPayload::Return { key, value, version, meta } => PacketPayload::Return { key: Cow::Owned(key.into_owned()), value: own_value_cow(value), version, meta },
+        PacketPayload::Auth { token } => PacketPayload::Auth { token: Cow::Owned(token.into_owned()) },
+        PacketPayload::AuthResult { accepted } => PacketPayload::AuthResult { accepted },
+        PacketPayload::CheckManifest => PacketPayload::CheckManifest,
+        PacketPayload::ManifestReport { drift } => PacketPayload::ManifestReport { drift },
+        PacketPayload::ServerClosing => PacketPayload::ServerClosing,
+        PacketPayload::ServerBusy => PacketPayload::ServerBusy,
+        PacketPayload::Throttled { retry_after } => PacketPayload::Throttled { retry_after },
+        PacketPayload::WatchAcknowledged { key, after } => PacketPayload::WatchAcknowledged { key: Cow::Owned(key.into_owned()), after },
+        PacketPayload::Delivery { key, value, sequence, gap } => PacketPayload::Delivery { key: Cow::Owned(key.into_owned()), value: own_value_cow(value), sequence, gap },
+        PacketPayload::AckDelivery { key, sequence } => PacketPayload::AckDelivery { key: Cow::Owned(key.into_owned()), sequence },
+        PacketPayload::Error { code, message } => PacketPayload::Error { code, message: Cow::Owned(message.into_owned()) },
+        PacketPayload::Import { records } => PacketPayload::Import { records },
+        PacketPayload::Imported { count } => PacketPayload::Imported { count },
+        PacketPayload::Export { cursor, limit } => PacketPayload::Export { cursor: cursor.map(|f| Cow::Owned(f.into_owned())), limit },
+        PacketPayload::ExportPage { records, cursor } => PacketPayload::ExportPage { records, cursor },
+        PacketPayload::ListChildren { key, cursor, limit } => PacketPayload::ListChildren { key: Cow::Owned(key.into_owned()), cursor: cursor.map(|f| Cow::Owned(f.into_owned())), limit },
+        PacketPayload::Children { children, cursor } => PacketPayload::Children { children, cursor },
+        PacketPayload::ListWatchers { key } => PacketPayload::ListWatchers { key: key.map(|f| Cow::Owned(f.into_owned())) },
+        PacketPayload::WatcherReport { watched } => PacketPayload::WatcherReport { watched },
+        PacketPayload::Rename { from, to, overwrite } => PacketPayload::Rename { from: Cow::Owned(from.into_owned()), to: Cow::Owned(to.into_owned()), overwrite },
+        PacketPayload::SnapshotGet { keys } => PacketPayload::SnapshotGet { keys },
+        PacketPayload::SnapshotValues { values } => PacketPayload::SnapshotValues { values },
+        PacketPayload::RegisterSchema { prefix, schema } => PacketPayload::RegisterSchema { prefix: Cow::Owned(prefix.into_owned()), schema },
+        PacketPayload::SchemaRegistered { previous } => PacketPayload::SchemaRegistered { previous },
+        PacketPayload::ListSchemas => PacketPayload::ListSchemas,
+        PacketPayload::Schemas { schemas } => PacketPayload::Schemas { schemas },
+        PacketPayload::MultiInsert { entries } => PacketPayload::MultiInsert { entries },
+        PacketPayload::MultiInserted { versions } => PacketPayload::MultiInserted { versions },
+        PacketPayload::DeleteIf { key, condition } => PacketPayload::DeleteIf { key: Cow::Owned(key.into_owned()), condition },
+        PacketPayload::DeleteIfResult { deleted } => PacketPayload::DeleteIfResult { deleted },
+        PacketPayload::FindKeys { filter, cursor, limit } => PacketPayload::FindKeys { filter, cursor: cursor.map(|f| Cow::Owned(f.into_owned())), limit },
+        PacketPayload::GetStats => PacketPayload::GetStats,
+        PacketPayload::Stats { stats } => PacketPayload::Stats { stats },
+        PacketPayload::NextSequence { name } => PacketPayload::NextSequence { name: Cow::Owned(name.into_owned()) },
+        PacketPayload::Sequence { value } => PacketPayload::Sequence { value },
+        PacketPayload::GetAt { key, at } => PacketPayload::GetAt { key: Cow::Owned(key.into_owned()), at },
+        PacketPayload::ValueAt { value, available } => PacketPayload::ValueAt { value, available },
+        PacketPayload::WatchSnapshot { key, behaviour, min_interval } => PacketPayload::WatchSnapshot { key: Cow::Owned(key.into_owned()), behaviour, min_interval },
+        PacketPayload::Snapshot { key, value, version } => PacketPayload::Snapshot { key: Cow::Owned(key.into_owned()), value: own_value_cow(value), version },
+        PacketPayload::CompactionHistory => PacketPayload::CompactionHistory,
+        PacketPayload::CompactionReport { runs } => PacketPayload::CompactionReport { runs },
+        PacketPayload::Ping => PacketPayload::Ping,
+        PacketPayload::Pong => PacketPayload::Pong,
+        PacketPayload::Invalidate { prefix } => PacketPayload::Invalidate { prefix: Cow::Owned(prefix.into_owned()) },
+        PacketPayload::CreateNamespace { name } => PacketPayload::CreateNamespace { name: Cow::Owned(name.into_owned()) },
+        PacketPayload::DropNamespace { name } => PacketPayload::DropNamespace { name: Cow::Owned(name.into_owned()) },
+        PacketPayload::NamespaceResult { changed } => PacketPayload::NamespaceResult { changed },
+        PacketPayload::UnknownNamespace => PacketPayload::UnknownNamespace,
+        PacketPayload::ListKeys { cursor, limit } => PacketPayload::ListKeys { cursor: cursor.map(|f| Cow::Owned(f.into_owned())), limit },
+        PacketPayload::KeyPage { keys, cursor } => PacketPayload::KeyPage { keys, cursor },
+        PacketPayload::WatchOverflow { key, missed, closed } => PacketPayload::WatchOverflow { key: Cow::Owned(key.into_owned()), missed, closed },
+        PacketPayload::KeyHistory { key, from, to } => PacketPayload::KeyHistory { key: Cow::Owned(key.into_owned()), from, to },
+        PacketPayload::KeyDiff { diff } => PacketPayload::KeyDiff { diff },
+        PacketPayload::Promote { promotion, dry_run } => PacketPayload::Promote { promotion, dry_run },
+        PacketPayload::RollbackPromotion { id } => PacketPayload::RollbackPromotion { id },
+        PacketPayload::PromotionResult { report } => PacketPayload::PromotionResult { report },
+        PacketPayload::GetMeta { key } => PacketPayload::GetMeta { key: Cow::Owned(key.into_owned()) },
+        PacketPayload::KeyMeta { key, value, meta } => PacketPayload::KeyMeta { key: Cow::Owned(key.into_owned()), value: own_value_cow(value), meta },
+        PacketPayload::WatchIsolated { key, reason } => PacketPayload::WatchIsolated { key: Cow::Owned(key.into_owned()), reason },
+        PacketPayload::QueryByIndex { field, value } => PacketPayload::QueryByIndex { field: Cow::Owned(field.into_owned()), value: Cow::Owned(value.into_owned()) },
+        PacketPayload::IndexMatches { keys } => PacketPayload::IndexMatches { keys },
+        PacketPayload::GetOrInsert { key, default } => PacketPayload::GetOrInsert { key: Cow::Owned(key.into_owned()), default: Cow::Owned(default.into_owned()) },
+        PacketPayload::Publish { topic, value } => PacketPayload::Publish { topic: Cow::Owned(topic.into_owned()), value: Cow::Owned(value.into_owned()) },
+        PacketPayload::Published { receivers } => PacketPayload::Published { receivers },
+        PacketPayload::SubscribeTopic { topic } => PacketPayload::SubscribeTopic { topic: Cow::Owned(topic.into_owned()) },
+        PacketPayload::UnsubscribeTopic { topic } => PacketPayload::UnsubscribeTopic { topic: Cow::Owned(topic.into_owned()) },
+        PacketPayload::TopicMessage { topic, value } => PacketPayload::TopicMessage { topic: Cow::Owned(topic.into_owned()), value: Cow::Owned(value.into_owned()) },
+        PacketPayload::InsertIfVersion { key, value, version } => PacketPayload::InsertIfVersion { key: Cow::Owned(key.into_owned()), value: Cow::Owned(value.into_owned()), version },
+        PacketPayload::VersionConflict { key, version } => PacketPayload::VersionConflict { key: Cow::Owned(key.into_owned()), version },
+        PacketPayload::Enqueue { queue, value } => PacketPayload::Enqueue { queue: Cow::Owned(queue.into_owned()), value: Cow::Owned(value.into_owned()) },
+        PacketPayload::Dequeue { queue, visibility } => PacketPayload::Dequeue { queue: Cow::Owned(queue.into_owned()), visibility },
+        PacketPayload::Leased { item } => PacketPayload::Leased { item },
+        PacketPayload::Ack { key, lease } => PacketPayload::Ack { key: Cow::Owned(key.into_owned()), lease },
+        PacketPayload::Nack { key, lease } => PacketPayload::Nack { key: Cow::Owned(key.into_owned()), lease },
+        PacketPayload::LeaseResult { accepted } => PacketPayload::LeaseResult { accepted },
+        PacketPayload::History { key, limit } => PacketPayload::History { key: Cow::Owned(key.into_owned()), limit },
+        PacketPayload::HistoryReport { entries } => PacketPayload::HistoryReport { entries },
+        PacketPayload::InsertAcknowledged { key, value, acknowledgement } => PacketPayload::InsertAcknowledged { key: Cow::Owned(key.into_owned()), value: Cow::Owned(value.into_owned()), acknowledgement },
+        PacketPayload::AcknowledgementTimeout { key, version } => PacketPayload::AcknowledgementTimeout { key: Cow::Owned(key.into_owned()), version },
+        PacketPayload::Archive { before, delete } => PacketPayload::Archive { before, delete },
+        PacketPayload::Archived { records } => PacketPayload::Archived { records },
+        PacketPayload::Acquire { key, ttl } => PacketPayload::Acquire { key: Cow::Owned(key.into_owned()), ttl },
+        PacketPayload::ReleaseLock { key } => PacketPayload::ReleaseLock { key: Cow::Owned(key.into_owned()) },
+        PacketPayload::LockResult { token } => PacketPayload::LockResult { token },
+        PacketPayload::InsertEphemeral { key, value } => PacketPayload::InsertEphemeral { key: Cow::Owned(key.into_owned()), value: Cow::Owned(value.into_owned()) },
+        PacketPayload::Resume { session } => PacketPayload::Resume { session },
+        PacketPayload::Session { id, resumed } => PacketPayload::Session { id, resumed },
+        PacketPayload::Extension { opcode, extension, payload } => PacketPayload::Extension { opcode, extension, payload: Cow::Owned(payload.into_owned()) },
+        PacketPayload::UnsupportedExtension { extension } => PacketPayload::UnsupportedExtension { extension },
+        PacketPayload::Increment { key, delta } => PacketPayload::Increment { key: Cow::Owned(key.into_owned()), delta },
 
     }
 }
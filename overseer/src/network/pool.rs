@@ -0,0 +1,151 @@
+use crate::{error::ProtocolError, models::LocalReadAsync};
+
+
+/// The most buffers a pool keeps at once.
+const POOLED_BUFFERS: usize = 16;
+/// The smallest buffer a pool allocates, so it suits the next short read too.
+const MIN_BUFFER: usize = 64;
+/// Larger buffers are dropped rather than kept, so a single large value
+/// does not pin its memory for as long as the connection lives.
+pub const MAX_POOLED_BUFFER: usize = 64 * 1024;
+
+/// How often the buffers of a pool were reused.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct BufferPoolStats {
+    pub hits: u64,
+    pub misses: u64
+}
+
+impl BufferPoolStats {
+    /// The share of buffers that were reused, zero before the first.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// The buffers a connection reads into, handed back once their bytes
+/// were decoded so the next packet reuses them.
+#[derive(Default)]
+pub struct BufferPool {
+    free: Vec<Vec<u8>>,
+    stats: BufferPoolStats
+}
+
+impl BufferPool {
+    /// A zeroed buffer of the length, reusing a pooled one large enough.
+    pub fn take(&mut self, length: usize) -> Vec<u8> {
+        let mut buffer = match self.free.iter().position(|f| f.capacity() >= length) {
+            Some(index) => {
+                self.stats.hits += 1;
+                self.free.swap_remove(index)
+            }
+            None => {
+                self.stats.misses += 1;
+                Vec::with_capacity(length.max(MIN_BUFFER))
+            }
+        };
+        buffer.clear();
+        buffer.resize(length, 0);
+        buffer
+    }
+    /// Keeps the buffer for a later read, unless it is too large or the pool is full.
+    pub fn recycle(&mut self, buffer: Vec<u8>) {
+        if buffer.capacity() <= MAX_POOLED_BUFFER && self.free.len() < POOLED_BUFFERS {
+            self.free.push(buffer);
+        }
+    }
+    /// The hits and misses since the last time they were taken.
+    pub fn take_stats(&mut self) -> BufferPoolStats {
+        std::mem::take(&mut self.stats)
+    }
+}
+
+/// Reads through to a reader, decoding into the buffers of a [BufferPool].
+pub struct PooledReader<R> {
+    inner: R,
+    pool: BufferPool
+}
+
+impl<R> PooledReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, pool: BufferPool::default() }
+    }
+    /// The reuse of the buffers since the last time it was taken.
+    pub fn take_stats(&mut self) -> BufferPoolStats {
+        self.pool.take_stats()
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl<R: LocalReadAsync> LocalReadAsync for PooledReader<R> {
+    async fn read_exact(&mut self, buffer: Vec<u8>) -> std::io::Result<(Vec<u8>, usize)> {
+        self.inner.read_exact(buffer).await
+    }
+    fn buffer(&mut self, length: usize) -> Vec<u8> {
+        self.pool.take(length)
+    }
+    fn recycle(&mut self, buffer: Vec<u8>) {
+        self.pool.recycle(buffer);
+    }
+    fn inflate(&mut self, length: usize) -> Result<(), ProtocolError> {
+        self.inner.inflate(length)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{models::{Key, Value}, network::{OverseerSerde, Packet, PacketId, PacketPayload}};
+
+    use super::{BufferPool, PooledReader, MAX_POOLED_BUFFER};
+
+    #[test]
+    pub fn test_buffer_pool() {
+        let mut pool = BufferPool::default();
+        let buffer = pool.take(8);
+        assert_eq!(buffer, vec![0u8; 8]);
+        pool.recycle(buffer);
+
+        // The buffer comes back zeroed, whatever was read into it.
+        let mut buffer = pool.take(16);
+        buffer.fill(7);
+        pool.recycle(buffer);
+        assert_eq!(pool.take(4), vec![0u8; 4]);
+        pool.recycle(vec![0u8; MAX_POOLED_BUFFER + 1]);
+        pool.take(1024);
+
+        let stats = pool.take_stats();
+        assert_eq!((stats.hits, stats.misses), (2, 2));
+        assert_eq!(stats.hit_rate(), 0.5);
+        assert_eq!(pool.take_stats().hit_rate(), 0.0);
+    }
+
+    #[tokio::test]
+    pub async fn test_pooled_reader_decodes_packets() {
+        let key = Key::from_str("app.mode");
+        let value = Value::String("on".into());
+        let mut buffer = vec![];
+        for _ in 0..4 {
+            Packet::insert(PacketId::zero(), &key, &value).serialize(&mut buffer).await.unwrap();
+        }
+
+        let mut reader = PooledReader::new(Cursor::new(buffer));
+        for _ in 0..4 {
+            let packet = Packet::deserialize(&mut reader).await.unwrap();
+            let PacketPayload::Insert { key: decoded, value: decoded_value } = packet.payload() else {
+                panic!("Wrong packet type.");
+            };
+            assert_eq!((&**decoded, &**decoded_value), (&key, &value));
+        }
+        // Each packet reads into the buffers the one before it handed back.
+        let stats = reader.take_stats();
+        assert!(stats.misses <= 2 && stats.hits > stats.misses * 10, "{stats:?}");
+    }
+}
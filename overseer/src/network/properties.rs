@@ -0,0 +1,148 @@
+//! Round trips of random packets, values, keys and integers, and decodes of
+//! random and damaged bytes, which have to fail with an error rather than panic.
+
+use std::{collections::BTreeMap, io::Cursor, time::Duration};
+
+use fastrand::Rng;
+
+use crate::{
+    error::{NetworkError, ProtocolError},
+    models::{Key, Value},
+    network::{OverseerSerde, OvrInteger, Packet, PacketId, PacketPayload, CURRENT_VERSION, MAX_FIELD_LENGTH, MIN_VERSION},
+};
+
+/// How many cases each property is checked on, the seeds are fixed so a
+/// failure reproduces.
+const CASES: u64 = 500;
+
+fn string(rng: &mut Rng) -> String {
+    // Mostly short, now and then long enough to be compressed.
+    let length = if rng.u8(..) < 16 { rng.usize(200..2000) } else { rng.usize(..24) };
+    match rng.bool() {
+        true => (0..length).map(|_| rng.alphanumeric()).collect(),
+        false => (0..length).map(|_| rng.char(..)).collect()
+    }
+}
+
+fn key(rng: &mut Rng) -> Key {
+    Key::from_str(string(rng))
+}
+
+fn value(rng: &mut Rng, depth: usize) -> Value {
+    match rng.u8(..if depth == 0 { 3 } else { 4 }) {
+        0 => Value::String(string(rng).into()),
+        1 => Value::Integer(rng.i64(..)),
+        2 => Value::Json(format!("{{\"n\":{}}}", rng.i32(..))),
+        _ => Value::Map((0..rng.usize(..4)).map(|_| (string(rng), value(rng, depth - 1))).collect::<BTreeMap<_, _>>())
+    }
+}
+
+async fn encode<T: OverseerSerde<U>, U>(item: &T) -> Vec<u8>
+where
+    T::E: std::fmt::Debug
+{
+    let mut buffer = vec![];
+    item.serialize(&mut buffer).await.unwrap();
+    buffer
+}
+
+/// A packet of one of the shapes that carry keys, values and strings.
+fn packet<'a>(rng: &mut Rng, key: &'a Key, value: &'a Value, text: &'a str) -> Packet<'a> {
+    let payload = match rng.u8(..12) {
+        0 => PacketPayload::get(key),
+        1 => PacketPayload::insert(key, value),
+        2 => PacketPayload::delete(key),
+        3 => PacketPayload::increment(key, rng.i64(..)),
+        4 => PacketPayload::insert_if_version(key, value, rng.u64(..)),
+        5 => PacketPayload::return_packet(key, Some(value), rng.u64(..)),
+        6 => PacketPayload::publish(key, value),
+        7 => PacketPayload::dequeue(key, Duration::from_millis(rng.u32(..) as u64)),
+        8 => PacketPayload::auth(text),
+        9 => PacketPayload::invalidate(text),
+        10 => PacketPayload::query_by_index(text, value),
+        _ => PacketPayload::extension(rng.u8(224..), rng.u32(..), text.as_bytes().to_vec())
+    };
+    Packet::new(PacketId::new(rng.u32(..), rng.u32(..)), payload).with_namespace(text).with_trace(rng.bool().then(|| rng.u64(..)))
+}
+
+#[tokio::test]
+pub async fn test_integer_round_trips() {
+    let mut rng = Rng::with_seed(1);
+    for _ in 0..CASES {
+        let (unsigned, signed) = (rng.u64(..) >> rng.u32(..64), rng.i64(..) >> rng.u32(..64));
+        assert_eq!(OvrInteger::read::<u64, _>(&mut encode(&unsigned).await.as_slice()).await.unwrap(), unsigned);
+        let mut buffer = vec![];
+        OvrInteger::write(signed, &mut buffer).await.unwrap();
+        assert_eq!(OvrInteger::read::<i64, _>(&mut buffer.as_slice()).await.unwrap(), signed);
+
+        // An integer that does not fit the one read is refused, not truncated.
+        let read = OvrInteger::read::<u32, _>(&mut encode(&unsigned).await.as_slice()).await;
+        match u32::try_from(unsigned) {
+            Ok(fits) => assert_eq!(read.unwrap(), fits),
+            Err(_) => assert!(matches!(read, Err(NetworkError::Protocol(ProtocolError::VarintOverflow))))
+        }
+    }
+
+    // Continuation bytes past the width of the integer.
+    assert!(matches!(OvrInteger::read::<u64, _>(&mut [0xff; 11].as_slice()).await, Err(NetworkError::Protocol(ProtocolError::VarintOverflow))));
+    assert!(matches!(OvrInteger::read::<u16, _>(&mut [0x80, 0x80, 0x80, 0x01].as_slice()).await, Err(NetworkError::Protocol(ProtocolError::VarintOverflow))));
+    // A length over the bound is refused before it is allocated.
+    let mut buffer = vec![];
+    OvrInteger::write(MAX_FIELD_LENGTH + 1, &mut buffer).await.unwrap();
+    assert!(matches!(String::deserialize(&mut buffer.as_slice()).await, Err(NetworkError::Protocol(ProtocolError::LengthTooLarge(..)))));
+}
+
+#[tokio::test]
+pub async fn test_value_and_key_round_trips() {
+    let mut rng = Rng::with_seed(2);
+    for _ in 0..CASES {
+        let key = key(&mut rng);
+        assert_eq!(Key::deserialize(&mut encode(&key).await.as_slice()).await.unwrap(), key);
+        let value = value(&mut rng, 2);
+        assert_eq!(Value::deserialize(&mut encode(&value).await.as_slice()).await.unwrap(), value);
+    }
+}
+
+#[tokio::test]
+pub async fn test_packet_round_trips() {
+    let mut rng = Rng::with_seed(3);
+    for _ in 0..CASES {
+        let (key, value, text) = (key(&mut rng), value(&mut rng, 2), string(&mut rng));
+        let packet = packet(&mut rng, &key, &value, &text);
+        let version = rng.u8(MIN_VERSION..=CURRENT_VERSION);
+        let Ok(Some(packet)) = packet.downgrade(version) else {
+            continue;
+        };
+        // Decoding and encoding again gives back the same bytes.
+        let bytes = encode(&packet).await;
+        let decoded = Packet::deserialize(&mut Cursor::new(bytes.clone())).await.unwrap();
+        assert_eq!(decoded.version(), packet.version());
+        assert_eq!(encode(&decoded).await, bytes);
+    }
+}
+
+#[tokio::test]
+pub async fn test_damaged_packets_fail_cleanly() {
+    let mut rng = Rng::with_seed(4);
+    for _ in 0..CASES {
+        // Random bytes behind a header that is mostly valid.
+        let mut bytes: Vec<u8> = (0..rng.usize(..64)).map(|_| rng.u8(..)).collect();
+        if let Some(version) = bytes.first_mut() {
+            *version = rng.u8(MIN_VERSION..=CURRENT_VERSION + 1);
+        }
+        let _ = Packet::deserialize(&mut Cursor::new(bytes)).await;
+        let _ = Value::deserialize(&mut (0..rng.usize(..32)).map(|_| rng.u8(..)).collect::<Vec<_>>().as_slice()).await;
+
+        // A valid packet cut short or with a byte flipped.
+        let (key, value, text) = (key(&mut rng), value(&mut rng, 2), string(&mut rng));
+        let mut bytes = encode(&packet(&mut rng, &key, &value, &text)).await;
+        match rng.bool() {
+            true => bytes.truncate(rng.usize(..bytes.len())),
+            false => {
+                let at = rng.usize(..bytes.len());
+                bytes[at] ^= 1 << rng.u32(..8);
+            }
+        }
+        let _ = Packet::deserialize(&mut Cursor::new(bytes)).await;
+    }
+}
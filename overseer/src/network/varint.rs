@@ -1,10 +1,25 @@
-use std::io;
-
 use integer_encoding::VarInt;
 
-use crate::models::{LocalReadAsync, LocalWriteAsync};
+use crate::{error::{NetworkError, ProtocolError}, models::{LocalReadAsync, LocalWriteAsync}};
+
+
+
+/// The longest string or bytes a decoder reads, whatever the bound of the frame.
+pub const MAX_FIELD_LENGTH: usize = 64 * 1024 * 1024;
 
+/// The most bytes a variable integer takes, those of a 64 bit one.
+pub const MAX_VARINT_LENGTH: usize = 10;
 
+/// Maps a signed integer to an unsigned one so small magnitudes take few
+/// bytes either side of zero, 0, -1, 1, -2 become 0, 1, 2, 3.
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Undoes [zigzag_encode].
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
 
 pub struct OvrInteger;
 
@@ -18,38 +33,110 @@ impl OvrInteger {
         Ok(())
 
     }
-    /// Reads a variable integer.
-    pub async fn read<VI, R>(reader: &mut R) -> std::io::Result<VI>
+    /// Reads a variable integer, failing with [ProtocolError::VarintOverflow]
+    /// on one that runs over the bytes the integer takes or does not fit it.
+    pub async fn read<VI, R>(reader: &mut R) -> Result<VI, NetworkError>
     where 
         VI: VarInt,
         R: LocalReadAsync
     {
-        let mut buffer: Vec<u8> = vec![];
+        let width = Self::width::<VI>();
+        let mut buffer = [0u8; MAX_VARINT_LENGTH];
+        let mut length = 0;
         loop {
             let byte = reader.read_u8().await?;
+            *buffer.get_mut(length).filter(|_| length < width).ok_or(NetworkError::Protocol(ProtocolError::VarintOverflow))? = byte;
+            length += 1;
             if byte & 0x80 == 0 {
-                buffer.push(byte);
                 break;
-            } else {
-                buffer.push(byte);
             }
         }
-        Ok(VI::decode_var(&buffer).ok_or_else(|| std::io::Error::new(io::ErrorKind::InvalidData, "Failed to decode"))?.0)
-
+        Self::decode_checked(&buffer[..length])
+    }
+    /// Reads a variable integer from the start of a slice, the way
+    /// [OvrInteger::read] does.
+    pub fn read_slice<VI: VarInt>(slice: &[u8]) -> Result<VI, NetworkError> {
+        let width = Self::width::<VI>();
+        let length = slice.iter()
+            .take(width)
+            .position(|byte| byte & 0x80 == 0)
+            .ok_or(if slice.len() < width { NetworkError::Protocol(ProtocolError::FailedToReadValue) } else { NetworkError::Protocol(ProtocolError::VarintOverflow) })?;
+        Self::decode_checked(&slice[..=length])
+    }
+    /// The bytes the integer is encoded in.
+    pub fn to_bytes<VI: VarInt>(data: VI) -> Vec<u8> {
+        data.encode_var_vec()
+    }
+    /// How many bytes the integer is encoded in.
+    pub fn required_space<VI: VarInt>(data: VI) -> usize {
+        data.required_space()
+    }
+    pub async fn write_u16<W: LocalWriteAsync>(data: u16, writer: &mut W) -> std::io::Result<()> {
+        Self::write(data, writer).await
+    }
+    pub async fn read_u16<R: LocalReadAsync>(reader: &mut R) -> Result<u16, NetworkError> {
+        Self::read(reader).await
+    }
+    pub async fn write_u32<W: LocalWriteAsync>(data: u32, writer: &mut W) -> std::io::Result<()> {
+        Self::write(data, writer).await
+    }
+    pub async fn read_u32<R: LocalReadAsync>(reader: &mut R) -> Result<u32, NetworkError> {
+        Self::read(reader).await
+    }
+    /// Writes a signed integer zig-zag encoded, so -1 takes one byte rather
+    /// than ten.
+    pub async fn write_i64<W: LocalWriteAsync>(data: i64, writer: &mut W) -> std::io::Result<()> {
+        Self::write(zigzag_encode(data), writer).await
+    }
+    pub async fn read_i64<R: LocalReadAsync>(reader: &mut R) -> Result<i64, NetworkError> {
+        Ok(zigzag_decode(Self::read(reader).await?))
+    }
+    pub async fn write_i32<W: LocalWriteAsync>(data: i32, writer: &mut W) -> std::io::Result<()> {
+        Self::write_i64(data as i64, writer).await
+    }
+    pub async fn read_i32<R: LocalReadAsync>(reader: &mut R) -> Result<i32, NetworkError> {
+        // The zig-zag encoding of an i32 fits a u32, a larger one did not come from one.
+        let value: u32 = Self::read(reader).await?;
+        Ok(zigzag_decode(value as u64) as i32)
+    }
+    /// The most bytes an integer of the type is encoded in.
+    fn width<VI>() -> usize {
+        (std::mem::size_of::<VI>() * 8).div_ceil(7).min(MAX_VARINT_LENGTH)
+    }
+    fn decode_checked<VI: VarInt>(bytes: &[u8]) -> Result<VI, NetworkError> {
+        let (value, _) = VI::decode_var(bytes).ok_or(NetworkError::Protocol(ProtocolError::VarintOverflow))?;
+        // Decoding truncates to the integer, only a value that fits encodes back the same.
+        let mut encoded = [0u8; MAX_VARINT_LENGTH];
+        let written = value.encode_var(&mut encoded);
+        if encoded[..written] != *bytes {
+            return Err(NetworkError::Protocol(ProtocolError::VarintOverflow));
+        }
+        Ok(value)
+    }
+    /// Reads the length of a string or bytes, failing with
+    /// [ProtocolError::LengthTooLarge] before anything is allocated for it
+    /// if it is longer than [MAX_FIELD_LENGTH].
+    pub async fn read_length<R: LocalReadAsync>(reader: &mut R) -> Result<usize, NetworkError> {
+        let length: usize = Self::read(reader).await?;
+        if length > MAX_FIELD_LENGTH {
+            return Err(NetworkError::Protocol(ProtocolError::LengthTooLarge(length)));
+        }
+        Ok(length)
     }
 }
 
 
 
 
-
 #[cfg(test)]
 mod tests {
     use std::{io::Cursor, u16};
 
     use integer_encoding::{FixedInt, VarInt, VarIntAsyncReader};
 
-    use crate::network::OvrInteger;
+    use crate::{error::{NetworkError, ProtocolError}, network::OvrInteger};
+
+    use super::{zigzag_decode, zigzag_encode};
 
    
 
@@ -72,4 +159,35 @@ mod tests {
         cursor.set_position(0);
         assert_eq!(OvrInteger::read::<i64, _>(&mut cursor).await.unwrap(), 7393);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    pub async fn test_zigzag_and_helpers() {
+        for (value, encoded) in [(0i64, 0u64), (-1, 1), (1, 2), (-2, 3), (i64::MAX, u64::MAX - 1), (i64::MIN, u64::MAX)] {
+            assert_eq!(zigzag_encode(value), encoded);
+            assert_eq!(zigzag_decode(encoded), value);
+        }
+
+        // The explicit encoding is the one the generic write uses for signed types.
+        let (mut explicit, mut generic) = (vec![], vec![]);
+        OvrInteger::write_i64(-7393, &mut explicit).await.unwrap();
+        OvrInteger::write(-7393i64, &mut generic).await.unwrap();
+        assert_eq!(explicit, generic);
+        assert_eq!(OvrInteger::read_i64(&mut explicit.as_slice()).await.unwrap(), -7393);
+
+        let mut buffer = vec![];
+        OvrInteger::write_i32(i32::MIN, &mut buffer).await.unwrap();
+        OvrInteger::write_u16(u16::MAX, &mut buffer).await.unwrap();
+        OvrInteger::write_u32(70000, &mut buffer).await.unwrap();
+        let mut reader = buffer.as_slice();
+        assert_eq!(OvrInteger::read_i32(&mut reader).await.unwrap(), i32::MIN);
+        assert_eq!(OvrInteger::read_u16(&mut reader).await.unwrap(), u16::MAX);
+        assert_eq!(OvrInteger::read_u32(&mut reader).await.unwrap(), 70000);
+        assert!(matches!(OvrInteger::read_u16(&mut OvrInteger::to_bytes(70000u32).as_slice()).await, Err(NetworkError::Protocol(ProtocolError::VarintOverflow))));
+
+        // Slices are held to the same bounds as readers.
+        assert_eq!(OvrInteger::read_slice::<usize>(&[0x80, 0x20, 0xff]).unwrap(), 4096);
+        assert_eq!(OvrInteger::required_space(4096usize), 2);
+        assert!(matches!(OvrInteger::read_slice::<u64>(&[0xff; 12]), Err(NetworkError::Protocol(ProtocolError::VarintOverflow))));
+        assert!(matches!(OvrInteger::read_slice::<u64>(&[0xff; 3]), Err(NetworkError::Protocol(ProtocolError::FailedToReadValue))));
+    }
+}